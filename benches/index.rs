@@ -0,0 +1,81 @@
+// `cargo bench --bench index`: one service out of a 300-entry config, read through the index
+// as status and enable do, against the full parse every command made before it; then the same
+// for a whole `svc logs` run, start-up included, against the settings form, which the index
+// leaves to the full parse
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+const ENTRIES: usize = 300;
+const ROUNDS: u32 = 50;
+const RUNS: u32 = 20;
+
+fn average(rounds: u32, mut run: impl FnMut()) -> Duration {
+    let started = Instant::now();
+    for _ in 0..rounds {
+        run();
+    }
+    started.elapsed() / rounds
+}
+
+fn entries(dir: &Path, indent: &str) -> String {
+    let log = dir.join("app.log");
+    (0..ENTRIES)
+        .map(|at| {
+            format!(
+                "{indent}- name: service-{at}\n{indent}  type: Executable\n{indent}  path: C:\\apps\\{at}\\app.exe\n\
+                 {indent}  args: [--listen, '127.0.0.1:{}', --data, 'D:\\data\\{at}']\n{indent}  restart: always\n\
+                 {indent}  log: '{}'\n\n",
+                8000 + at,
+                log.display()
+            )
+        })
+        .collect()
+}
+
+// `svc logs` of the last service, as a user would run it
+fn logs(dir: &Path, config: &Path, name: &str) {
+    let output = Command::new(env!("CARGO_BIN_EXE_svc"))
+        .args(["logs", name, "1"])
+        .env("SVC_CONFIG", config)
+        .env("LOCALAPPDATA", dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+fn main() {
+    let dir = std::env::temp_dir().join(format!("svc-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    // The index is cached in %LOCALAPPDATA%\svc, kept out of the real one here
+    std::env::set_var("LOCALAPPDATA", &dir);
+    fs::write(dir.join("app.log"), "started\n").unwrap();
+    let path = dir.join("services.yaml");
+    fs::write(&path, entries(&dir, "")).unwrap();
+    let settings = dir.join("settings.yaml");
+    fs::write(&settings, format!("startup_stagger: 5s\nservices:\n{}", entries(&dir, "  "))).unwrap();
+    let name = format!("service-{}", ENTRIES - 1);
+    let report = |what: &str, indexed: Duration, full: Duration| {
+        println!(
+            "{} of {} services: {:?} through the index, {:?} parsing them all ({:.0}x)",
+            what,
+            ENTRIES,
+            indexed,
+            full,
+            full.as_secs_f64() / indexed.as_secs_f64()
+        )
+    };
+
+    let config = path.to_str().unwrap();
+    // The first lookup builds the index, as the first command after an edit does
+    svc::find_document(config, &name).unwrap();
+    let indexed = average(ROUNDS, || assert_eq!(svc::find_document(config, &name).unwrap().services.len(), 1));
+    let full = average(ROUNDS, || assert_eq!(svc::load_document(config).unwrap().services.len(), ENTRIES));
+    report("one", indexed, full);
+
+    let indexed = average(RUNS, || logs(&dir, &path, &name));
+    let full = average(RUNS, || logs(&dir, &settings, &name));
+    report("svc logs, one", indexed, full);
+    fs::remove_dir_all(&dir).unwrap();
+}
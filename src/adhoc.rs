@@ -0,0 +1,120 @@
+use crate::edit;
+use crate::state::{self, state_dir};
+use crate::{cli, load_document, mutex, run_service, RunOverride, Service, ServiceType, SvcError};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::path::PathBuf;
+
+const REGISTRY_FILE: &str = "adhoc.yaml";
+
+// A service started with `svc run-adhoc`, kept out of services.yaml until it is promoted
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdhocEntry {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub service_type: ServiceType,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub work_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interpreter: Option<String>,
+    // Send stdout and stderr to a log in the state directory
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub log: bool,
+}
+
+impl AdhocEntry {
+    // The entry as it would appear in services.yaml
+    fn to_value(&self) -> Result<Value, SvcError> {
+        let mut value = serde_yaml::to_value(self)?;
+        if let Value::Mapping(mapping) = &mut value {
+            mapping.remove("log");
+        }
+        Ok(value)
+    }
+
+    fn to_service(&self) -> Result<Service<'static>, SvcError> {
+        let mut service: Service = serde_yaml::from_value(self.to_value()?)?;
+        service.adhoc = true;
+        if self.log {
+            service.log_file = Some(log_path(&self.name)?);
+        }
+        Ok(service)
+    }
+}
+
+fn registry_path() -> Result<PathBuf, SvcError> {
+    Ok(state_dir()?.join(REGISTRY_FILE))
+}
+
+pub fn log_path(name: &str) -> Result<PathBuf, SvcError> {
+    Ok(state_dir()?.join(format!("adhoc-{}.log", name)))
+}
+
+fn entries() -> Result<Vec<AdhocEntry>, SvcError> {
+    let parse = |content: &str| match content.trim() {
+        "" => Ok(Vec::new()),
+        _ => serde_yaml::from_str(content).map_err(|err| err.to_string()),
+    };
+    Ok(state::read(&registry_path()?, parse)?.unwrap_or_default())
+}
+
+fn save(entries: &[AdhocEntry]) -> Result<(), SvcError> {
+    state::replace(&registry_path()?, &serde_yaml::to_string(entries)?)
+}
+
+// Adhoc services as the rest of svc sees them, marked so they can be told apart
+pub fn services() -> Result<Vec<Service<'static>>, SvcError> {
+    entries()?.iter().map(AdhocEntry::to_service).collect()
+}
+
+fn not_found(entries: &[AdhocEntry], name: &str) -> SvcError {
+    SvcError::ServiceNotFound {
+        name: name.to_string(),
+        suggestion: cli::suggest(name, entries.iter().map(|entry| entry.name.as_str())).map(String::from),
+    }
+}
+
+pub fn run_adhoc(config_path: &str, entry: AdhocEntry) -> Result<(), SvcError> {
+    let config = load_document(config_path)?;
+    if config.services.iter().any(|s| s.name == entry.name.as_str() && !s.adhoc) {
+        return Err(SvcError::ServiceNameTaken(entry.name));
+    }
+    let service = entry.to_service()?;
+    {
+        let _lock = mutex::lock("adhoc", "registry")?;
+        let mut entries = entries()?;
+        entries.retain(|existing| existing.name != entry.name);
+        entries.push(entry);
+        save(&entries)?;
+    }
+    run_service(&service, &RunOverride::default())
+}
+
+pub fn promote(config_path: &str, name: &str) -> Result<(), SvcError> {
+    let _lock = mutex::lock("adhoc", "registry")?;
+    let mut entries = entries()?;
+    let at = entries
+        .iter()
+        .position(|entry| entry.name == name)
+        .ok_or_else(|| not_found(&entries, name))?;
+    edit::append_service(std::path::Path::new(config_path), entries[at].to_value()?)?;
+    entries.remove(at);
+    save(&entries)?;
+    println!("Service {} promoted into services.yaml.", name.cyan());
+    Ok(())
+}
+
+pub fn forget(name: &str) -> Result<(), SvcError> {
+    let _lock = mutex::lock("adhoc", "registry")?;
+    let mut entries = entries()?;
+    let at = entries
+        .iter()
+        .position(|entry| entry.name == name)
+        .ok_or_else(|| not_found(&entries, name))?;
+    entries.remove(at);
+    save(&entries)?;
+    println!("Forgot adhoc service {}; processes it started keep running.", name.cyan());
+    Ok(())
+}
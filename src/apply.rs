@@ -0,0 +1,169 @@
+use crate::backends::{self, Backend};
+use crate::probe::{self, Capability};
+use crate::rollback::Batch;
+use crate::startup::{self, Lookup};
+use crate::{mutex, pathcmp, progress, Config, Service, Settings, StartupState, SvcError};
+use colored::Colorize;
+
+enum Change {
+    Enable,
+    Disable,
+    // One of our values whose data or name is out of date
+    Repair(String),
+    Unchanged,
+    // Another program's value under the service's bare name, never touched
+    Foreign(String),
+}
+
+fn plan(service: &Service, desired: StartupState, expected: &str) -> Result<Change, SvcError> {
+    Ok(match (desired, startup::lookup(service)?) {
+        (StartupState::Enabled, Lookup::Found(entry)) if entry.is_legacy() => {
+            Change::Repair(format!("move {} to {}", entry.value, startup::value_name(&service.name)))
+        }
+        // A bare path spelled differently, e.g. in another case or as 8.3 names, is still up to date
+        (StartupState::Enabled, Lookup::Found(entry))
+            if entry.data != expected && !pathcmp::same_file(&entry.data, expected) =>
+        {
+            Change::Repair(format!("data was {}", entry.data))
+        }
+        (StartupState::Enabled, Lookup::Found(_)) => Change::Unchanged,
+        (StartupState::Enabled, Lookup::Missing) => Change::Enable,
+        (StartupState::Disabled, Lookup::Found(_)) => Change::Disable,
+        (StartupState::Disabled, Lookup::Missing) => Change::Unchanged,
+        (_, Lookup::Foreign(data)) => Change::Foreign(data),
+    })
+}
+
+// Write the change to the Run key; Foreign enables ours next to the other program's value
+fn execute(service: &Service, change: &Change, expected: &str) -> Result<(), SvcError> {
+    let value = startup::value_name(&service.name);
+    match change {
+        Change::Enable | Change::Foreign(_) => startup::set_value(&value, expected),
+        Change::Disable => match startup::find(service)? {
+            Some(entry) => startup::delete_value(&entry.value),
+            None => Ok(()),
+        },
+        Change::Repair(_) => {
+            let stale = startup::find(service)?;
+            startup::set_value(&value, expected)?;
+            match stale.filter(|entry| entry.value != value) {
+                Some(entry) => startup::delete_value(&entry.value),
+                None => Ok(()),
+            }
+        }
+        Change::Unchanged => Ok(()),
+    }
+}
+
+fn describe(change: &Change) -> String {
+    match change {
+        Change::Enable | Change::Foreign(_) => "enable".to_string(),
+        Change::Disable => "disable".to_string(),
+        Change::Repair(why) => format!("repair ({})", why),
+        Change::Unchanged => "unchanged".to_string(),
+    }
+}
+
+// The start-up change of one service with a `startup` field, made unless dry_run; None when
+// its Run value is already right
+pub fn reconcile(service: &Service, settings: &Settings, dry_run: bool) -> Result<Option<String>, SvcError> {
+    let Some(desired) = service.startup else {
+        return Ok(None);
+    };
+    let _lock = mutex::lock("startup", &service.name)?;
+    let expected = startup::run_data(service, settings)?;
+    let change = match plan(service, desired, &expected)? {
+        Change::Foreign(_) if desired == StartupState::Disabled => Change::Unchanged,
+        change => change,
+    };
+    if matches!(change, Change::Unchanged) {
+        return Ok(None);
+    }
+    if !dry_run {
+        probe::require(Capability::StartupWrite)?;
+        execute(service, &change, &expected)?;
+    }
+    Ok(Some(describe(&change)))
+}
+
+// Make the registry match what the config declares; services without `startup` are left alone.
+// Every change is planned before the first is made, and one that fails doesn't stop the rest
+pub fn apply_startup(config: &Config, dry_run: bool, rollback_on_failure: bool) -> Result<(), SvcError> {
+    if !dry_run {
+        probe::require(Capability::StartupWrite)?;
+    }
+    // Held until the end, so what was planned is still what gets changed
+    let mut locks = Vec::new();
+    let mut planned = Vec::new();
+    let mut unchanged = 0;
+    for service in &config.services {
+        let Some(desired) = service.startup else {
+            continue;
+        };
+        locks.push(mutex::lock("startup", &service.name)?);
+        let expected = startup::run_data(service, &config.settings)?;
+        let name = service.name.cyan();
+        // Only the Run key is this command's; the others are shown so overlaps don't go unnoticed
+        for other in backends::query(service)?.iter().filter(|entry| entry.backend != Backend::RunKey) {
+            println!("  {} {} also starts through {}: {}", "!".yellow(), name, other.backend, other.command_line());
+        }
+
+        let change = plan(service, desired, &expected)?;
+        match &change {
+            Change::Unchanged => {
+                unchanged += 1;
+                continue;
+            }
+            Change::Foreign(data) => {
+                println!("  {} {} (a value svc didn't write runs {})", "!".yellow(), name, data);
+                if desired == StartupState::Disabled {
+                    unchanged += 1;
+                    continue;
+                }
+                // Ours lives next to it under the namespaced name
+                println!("  {} {} {}", "+".green(), name, describe(&change));
+            }
+            Change::Enable => println!("  {} {} {}", "+".green(), name, describe(&change)),
+            Change::Disable => println!("  {} {} {}", "-".red(), name, describe(&change)),
+            Change::Repair(_) => println!("  {} {} {}", "~".yellow(), name, describe(&change)),
+        }
+        planned.push((service, change, expected));
+    }
+    if dry_run {
+        println!("Would apply: {} changed, {} unchanged.", planned.len(), unchanged);
+        return Ok(());
+    }
+
+    let services: Vec<&Service> = planned.iter().map(|(service, _, _)| *service).collect();
+    let batch = match rollback_on_failure {
+        true => Some(Batch::capture(&services, &config.settings)?),
+        false => None,
+    };
+    let (mut changed, mut failed) = (Vec::new(), Vec::new());
+    for (service, change, expected) in &planned {
+        let result = execute(service, change, expected);
+        let event = serde_json::json!({ "action": describe(change), "ok": result.is_ok() });
+        progress::emit("applied", Some(&service.name), event);
+        match result {
+            Ok(()) => changed.push(service.name.as_ref()),
+            Err(err) => {
+                println!("  {} {} {}: {}", "x".red(), service.name.cyan(), describe(change), err);
+                failed.push(service.name.to_string());
+            }
+        }
+    }
+    let failures = match failed.len() {
+        0 => String::new(),
+        count => format!(", {} failed", count),
+    };
+    println!("Applied: {} changed, {} unchanged{}.", changed.len(), unchanged, failures);
+    if failed.is_empty() {
+        return Ok(());
+    }
+    let Some(batch) = batch else {
+        return Err(SvcError::StartupApplyIncomplete { failed });
+    };
+    let unreverted = batch.roll_back(&changed);
+    batch.print_end_state();
+    Err(SvcError::RolledBack { failed, unreverted })
+}
@@ -0,0 +1,350 @@
+use crate::backends::{self, Backend};
+use crate::format::write_atomic;
+use crate::state::{self, state_dir};
+use crate::{adhoc, cancel, effects, confirm_protected, edit, firewall, get_pids, history, load_config, load_document, logs};
+use crate::{locate, metrics, not_found, pathcmp, scratch, startup, workspace, Service, SvcError};
+use colored::Colorize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+
+// Something svc made for a service that outlives its config entry
+pub enum Artifact {
+    // A file or directory in svc's state or scratch directories
+    Path(PathBuf),
+    // Records of history.jsonl
+    History(usize),
+    // The Run value `svc enable` wrote
+    StartupValue(String),
+    // The rule `svc firewall apply` wrote
+    FirewallRule,
+    // Written for the service but outside svc's own directories, so only ever listed
+    Outside(PathBuf),
+}
+
+impl Artifact {
+    fn describe(&self, name: &str) -> String {
+        match self {
+            Artifact::Path(path) if path.is_dir() => format!("{:<16}{}", "directory", path.display()),
+            Artifact::Path(path) => format!("{:<16}{}", "file", path.display()),
+            Artifact::History(records) => format!("{:<16}{} record(s)", "history", records),
+            Artifact::StartupValue(value) => format!("{:<16}{}", "start-up entry", value),
+            Artifact::FirewallRule => format!("{:<16}{}", "firewall rule", firewall::rule_name(name)),
+            Artifact::Outside(path) => format!("{:<16}{} (kept, outside svc's directories)", "log", path.display()),
+        }
+    }
+
+    fn delete(&self, name: &str) -> Result<(), SvcError> {
+        match self {
+            Artifact::Path(path) => {
+                // The last line of defence for "nothing outside svc's own directories"
+                if !owned(path)? {
+                    return Err(SvcError::InvalidCommand(format!(
+                        "deleting {}, which is outside svc's directories",
+                        path.display()
+                    )));
+                }
+                effects::delete(path)
+            }
+            Artifact::History(_) => forget_history(name),
+            Artifact::StartupValue(value) => startup::delete_value(value),
+            Artifact::FirewallRule => firewall::delete(name),
+            Artifact::Outside(_) => Ok(()),
+        }
+    }
+}
+
+// Scratch directories, unless %TEMP%\svc is the state directory itself (no LOCALAPPDATA),
+// where every subdirectory would look like a service's
+pub fn scratch_root() -> Result<Option<PathBuf>, SvcError> {
+    let root = scratch::root();
+    let state = state_dir()?;
+    Ok((!pathcmp::same_file(&root.to_string_lossy(), &state.to_string_lossy())).then_some(root))
+}
+
+pub fn owned(path: &Path) -> Result<bool, SvcError> {
+    let path = path.to_string_lossy();
+    let mut roots = vec![state_dir()?];
+    roots.extend(scratch_root()?);
+    Ok(roots.iter().any(|root| pathcmp::is_within(&path, &root.to_string_lossy())))
+}
+
+// The service each history.jsonl line is about, in file order
+fn history_lines() -> Result<Vec<(String, Option<String>)>, SvcError> {
+    let content = match fs::read_to_string(history::history_path()?) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    Ok(content
+        .lines()
+        .map(|line| {
+            let service = serde_json::from_str::<serde_json::Value>(line)
+                .ok()
+                .and_then(|record| record.get("service")?.as_str().map(String::from));
+            (line.to_string(), service)
+        })
+        .collect())
+}
+
+fn forget_history(name: &str) -> Result<(), SvcError> {
+    let _lock = state::lock(&history::history_path()?)?;
+    let kept: Vec<String> = history_lines()?
+        .into_iter()
+        .filter(|(_, service)| service.as_deref() != Some(name))
+        .map(|(line, _)| line + "\n")
+        .collect();
+    write_atomic(&history::history_path()?, &kept.concat())
+}
+
+// What svc keeps under the service's name in its own directories and the registry
+fn of_name(name: &str) -> Result<Vec<Artifact>, SvcError> {
+    let state = state_dir()?;
+    let mut paths = vec![
+        state::service_path(name)?,
+        // Where svc kept them before each service had its own directory
+        state.join(format!("{}.restart.pid", name)),
+        state.join(format!("{}.runas.pid", name)),
+        metrics::metrics_dir()?.join(format!("{}.jsonl", name)),
+        adhoc::log_path(name)?,
+    ];
+    if scratch_root()?.is_some() {
+        paths.push(scratch::service_dir(name));
+    }
+    let mut artifacts: Vec<Artifact> = paths.into_iter().filter(|path| path.exists()).map(Artifact::Path).collect();
+
+    let records = history_lines()?
+        .iter()
+        .filter(|(_, service)| service.as_deref() == Some(name))
+        .count();
+    if records > 0 {
+        artifacts.push(Artifact::History(records));
+    }
+    let value = startup::value_name(name);
+    if startup::query_value(&value)?.is_some() {
+        artifacts.push(Artifact::StartupValue(value));
+    }
+    // Without netsh there is no rule to be found either
+    if firewall::has_rule(name).unwrap_or(false) {
+        artifacts.push(Artifact::FirewallRule);
+    }
+    Ok(artifacts)
+}
+
+// As of_name, plus a start-up entry under the bare name and the service's log file
+fn of_service(service: &Service) -> Result<Vec<Artifact>, SvcError> {
+    let mut artifacts = of_name(&service.name)?;
+    if let Some(entry) = startup::find(service)?.filter(|entry| entry.is_legacy()) {
+        artifacts.push(Artifact::StartupValue(entry.value));
+    }
+    if let Some(log) = logs::path(service).filter(|log| log.exists()) {
+        if !owned(&log)? {
+            artifacts.push(Artifact::Outside(log));
+        }
+    }
+    Ok(artifacts)
+}
+
+fn print_artifacts(name: &str, artifacts: &[Artifact]) {
+    for artifact in artifacts {
+        println!("  {}", artifact.describe(name));
+    }
+}
+
+// Deletes everything it can, warning about the rest; returns how many went and the first failure
+fn delete_all(name: &str, artifacts: &[Artifact]) -> (usize, Option<SvcError>) {
+    let mut deleted = 0;
+    let mut first_error = None;
+    for artifact in artifacts.iter().filter(|artifact| !matches!(artifact, Artifact::Outside(_))) {
+        match artifact.delete(name) {
+            Ok(()) => deleted += 1,
+            Err(err) => {
+                eprintln!(
+                    "{} could not delete {}: {}",
+                    "warning:".yellow().bold(),
+                    artifact.describe(name),
+                    err
+                );
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    (deleted, first_error)
+}
+
+pub struct RemoveOptions {
+    pub purge: bool,
+    pub dry_run: bool,
+    // Go ahead while it runs or has a start-up entry
+    pub force: bool,
+}
+
+// Drop a service from the config (or the adhoc registry) and say what it leaves behind
+pub fn remove(config_path: &str, name: &str, options: &RemoveOptions) -> Result<(), SvcError> {
+    let config = load_document(config_path)?;
+    let service = config
+        .services
+        .iter()
+        .find(|s| s.name == name)
+        .ok_or_else(|| not_found(&config.services, name))?;
+    let dependents: Vec<&str> = config
+        .services
+        .iter()
+        .filter(|s| s.depends_on.iter().any(|d| d == name))
+        .map(|s| s.name.as_ref())
+        .collect();
+    if service.global {
+        return Err(SvcError::InvalidCommand(format!(
+            "remove {}, which the workspace extends from the global services.yaml",
+            name
+        )));
+    }
+    if !dependents.is_empty() {
+        return Err(SvcError::InvalidCommand(format!(
+            "remove {}, which {} depend on",
+            name,
+            dependents.join(", ")
+        )));
+    }
+    // Its processes would be left without anything to find or stop them by, and its
+    // start-up entry pointing at a name nothing defines
+    if !options.force {
+        if !get_pids(service)?.is_empty() {
+            return Err(SvcError::ServiceIsRunning);
+        }
+        if backends::query(service)?.iter().any(|entry| entry.backend != Backend::Scm) {
+            return Err(SvcError::ServiceIsEnabled);
+        }
+    }
+    let artifacts = of_service(service)?;
+
+    if options.dry_run {
+        let from = if service.adhoc { "the adhoc registry" } else { "services.yaml" };
+        println!("Would remove {} from {}.", name.cyan(), from);
+        if !artifacts.is_empty() {
+            println!("{}", if options.purge { "Would delete:" } else { "Would leave behind:" });
+            print_artifacts(name, &artifacts);
+        }
+        return Ok(());
+    }
+
+    confirm_protected(service, options.force)?;
+    if service.adhoc {
+        adhoc::forget(name)?;
+    } else {
+        edit::remove_service(Path::new(config_path), name)?;
+    }
+    if artifacts.is_empty() {
+        return Ok(());
+    }
+    if options.purge {
+        println!("Deleting:");
+        print_artifacts(name, &artifacts);
+        if let (_, Some(err)) = delete_all(name, &artifacts) {
+            return Err(err);
+        }
+    } else {
+        println!("Left behind, for 'svc gc' to clean up:");
+        print_artifacts(name, &artifacts);
+    }
+    Ok(())
+}
+
+pub struct GcOptions {
+    pub dry_run: bool,
+    pub yes: bool,
+}
+
+// Names that have something in svc's directories or a namespaced Run value
+fn names_with_artifacts() -> Result<BTreeSet<String>, SvcError> {
+    let mut names = BTreeSet::new();
+    let file_names = |dir: &Path| -> Vec<String> {
+        fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .collect()
+    };
+    for file in file_names(&state_dir()?) {
+        let name = file
+            .strip_suffix(".restart.pid")
+            .or_else(|| file.strip_suffix(".runas.pid"))
+            .or_else(|| file.strip_prefix("adhoc-")?.strip_suffix(".log"));
+        names.extend(name.map(String::from));
+    }
+    names.extend(state::service_dirs()?);
+    for file in file_names(&metrics::metrics_dir()?) {
+        names.extend(file.strip_suffix(".jsonl").map(String::from));
+    }
+    if let Some(root) = scratch_root()? {
+        names.extend(file_names(&root).into_iter().filter(|name| root.join(name).is_dir()));
+    }
+    names.extend(history_lines()?.into_iter().filter_map(|(_, service)| service));
+    names.extend(startup::svc_values()?);
+    names.remove("");
+    Ok(names)
+}
+
+fn confirm() -> Result<bool, SvcError> {
+    eprint!("Delete them? [y/N] ");
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    cancel::check()?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+// Artifacts of names no config source defines any more
+pub fn gc(config_path: &str, options: &GcOptions) -> Result<(), SvcError> {
+    // A config that doesn't load can't say what is orphaned, so nothing is. The global
+    // config and every other config svc was used with count, wherever gc runs
+    let mut configured: BTreeSet<String> = load_config(config_path)?.iter().map(|s| s.name.to_string()).collect();
+    let mut others: Vec<PathBuf> = workspace::known()?;
+    others.push(PathBuf::from(locate::global_path()?));
+    for path in others.iter().filter(|path| path.is_file()) {
+        configured.extend(load_config(&path.to_string_lossy())?.iter().map(|s| s.name.to_string()));
+    }
+    let mut orphans = Vec::new();
+    for name in names_with_artifacts()?.difference(&configured) {
+        let artifacts = of_name(name)?;
+        if !artifacts.is_empty() {
+            orphans.push((name.clone(), artifacts));
+        }
+    }
+    if orphans.is_empty() {
+        println!("Nothing to clean up.");
+        return Ok(());
+    }
+
+    for (name, artifacts) in &orphans {
+        println!("{} (no longer configured):", name.cyan());
+        print_artifacts(name, artifacts);
+    }
+    if options.dry_run {
+        return Ok(());
+    }
+    if !options.yes {
+        if !std::io::stdin().is_terminal() {
+            println!("Delete them with 'svc gc --yes'.");
+            return Ok(());
+        }
+        if !confirm()? {
+            return Ok(());
+        }
+    }
+
+    let mut deleted = 0;
+    let mut first_error = None;
+    for (name, artifacts) in &orphans {
+        let (count, error) = delete_all(name, artifacts);
+        deleted += count;
+        if let Some(err) = error {
+            first_error.get_or_insert(err);
+        }
+    }
+    println!("Deleted {} artifacts of {} services.", deleted, orphans.len());
+    match first_error {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,458 @@
+use crate::backends::{self, Backend};
+use crate::pe::{self, Arch};
+use crate::probe::{self, Capability};
+use crate::startup::{self, MACHINE_RUN_KEY, RUN_KEY};
+use crate::{load_document, mutex, output, pathcmp, Config, Service, SvcError};
+use colored::Colorize;
+use serde::Serialize;
+use std::path::Path;
+
+// The Run keys as the audit reads and repairs them
+pub trait Registry {
+    fn value_names(&self, key: &str) -> Result<Vec<String>, SvcError>;
+    fn query(&self, key: &str, name: &str) -> Result<Option<String>, SvcError>;
+    fn set(&mut self, key: &str, name: &str, data: &str) -> Result<(), SvcError>;
+    fn delete(&mut self, key: &str, name: &str) -> Result<(), SvcError>;
+}
+
+pub struct SystemRegistry;
+
+impl Registry for SystemRegistry {
+    fn value_names(&self, key: &str) -> Result<Vec<String>, SvcError> {
+        startup::value_names_in(key)
+    }
+
+    fn query(&self, key: &str, name: &str) -> Result<Option<String>, SvcError> {
+        startup::query_value_in(key, name)
+    }
+
+    fn set(&mut self, key: &str, name: &str, data: &str) -> Result<(), SvcError> {
+        startup::set_value_in(key, name, data)
+    }
+
+    fn delete(&mut self, key: &str, name: &str) -> Result<(), SvcError> {
+        startup::delete_value_in(key, name)
+    }
+}
+
+const RUN_KEYS: [(Backend, &str); 2] = [(Backend::RunKey, RUN_KEY), (Backend::MachineRunKey, MACHINE_RUN_KEY)];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Class {
+    Ok,
+    // A program path with spaces but no quotes; Windows tries C:\Program.exe first
+    Unquoted,
+    // Starts something other than what services.yaml says
+    StaleTarget,
+    // Starts another build of the program, say the x86 one where the config names the x64 one
+    ArchMismatch,
+    // Another program's value under the service's bare name
+    ForeignCollision,
+}
+
+impl Class {
+    fn name(self) -> &'static str {
+        match self {
+            Class::Ok => "ok",
+            Class::Unquoted => "unquoted",
+            Class::StaleTarget => "stale-target",
+            Class::ArchMismatch => "arch-mismatch",
+            Class::ForeignCollision => "foreign-collision",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Fix {
+    Fixed,
+    Failed,
+    // Ambiguous, so explained rather than rewritten
+    LeftAlone,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Audited {
+    pub service: String,
+    pub backend: Backend,
+    // The Run value it lives under; other backends have one entry per service
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    pub command_line: String,
+    pub class: Class,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    pub repairable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<Fix>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn unquoted(line: &str) -> bool {
+    let line = line.trim_start();
+    !line.starts_with('"')
+        && line.to_ascii_lowercase().find(".exe").is_some_and(|end| line[..end].contains(char::is_whitespace))
+}
+
+// How a stored command line stands against the config, judged by the same parse `svc status`
+// uses; `arch` reads the machine of a binary, None when it can't tell
+pub fn classify(service: &Service, backend: Backend, line: &str, arch: &dyn Fn(&str) -> Option<Arch>) -> (Class, String) {
+    if unquoted(line) {
+        return (Class::Unquoted, "the program path has spaces but no quotes".to_string());
+    }
+    let entry = backends::parse_entry(service, backend, line);
+    if entry.matches_config {
+        // A `svc run --startup` line names svc itself, which may have moved since
+        if !pathcmp::same_file(&entry.target, &service.path) && !Path::new(&entry.target).exists() {
+            return (Class::StaleTarget, format!("{} no longer exists", entry.target));
+        }
+        return (Class::Ok, String::new());
+    }
+    if let (Some(found), Some(configured)) = (arch(&entry.target), arch(&service.path)) {
+        if found != configured {
+            return (
+                Class::ArchMismatch,
+                format!("runs the {} build {}; services.yaml names the {} build", found, entry.target, configured),
+            );
+        }
+    }
+    (
+        Class::StaleTarget,
+        format!("runs {}, services.yaml says {}", entry.command_line(), startup::command_line(service)),
+    )
+}
+
+fn audited(service: &str, backend: Backend, value: Option<&str>, line: &str, class: Class, note: String) -> Audited {
+    Audited {
+        service: service.to_string(),
+        backend,
+        value: value.map(String::from),
+        command_line: line.to_string(),
+        class,
+        note: (!note.is_empty()).then_some(note),
+        repairable: matches!(class, Class::Unquoted | Class::StaleTarget),
+        fix: None,
+        error: None,
+    }
+}
+
+// Every svc value in both Run keys, plus bare-name values of configured services
+pub fn scan_registry(
+    config: &Config,
+    registry: &dyn Registry,
+    arch: &dyn Fn(&str) -> Option<Arch>,
+) -> Result<Vec<Audited>, SvcError> {
+    let mut found = Vec::new();
+    for (backend, key) in RUN_KEYS {
+        for name in registry.value_names(key)? {
+            let Some(data) = registry.query(key, &name)? else {
+                continue;
+            };
+            let namespaced = startup::service_of(&name);
+            let service = config.services.iter().find(|service| service.name == namespaced.unwrap_or(&name));
+            let mut entry = match (service, namespaced) {
+                // Some other program's value
+                (None, None) => continue,
+                (None, Some(orphan)) => audited(
+                    orphan,
+                    backend,
+                    Some(&name),
+                    &data,
+                    Class::StaleTarget,
+                    format!("no service named {} is configured; remove it if it is no longer wanted", orphan),
+                ),
+                (Some(service), None) if !startup::written_by_svc(service, &data) => audited(
+                    &name,
+                    backend,
+                    Some(&name),
+                    &data,
+                    Class::ForeignCollision,
+                    "another program's value; svc never touches it".to_string(),
+                ),
+                (Some(service), _) => {
+                    let (class, note) = classify(service, backend, &data, arch);
+                    audited(&service.name, backend, Some(&name), &data, class, note)
+                }
+            };
+            // Nothing to rewrite an orphan to
+            if service.is_none() {
+                entry.repairable = false;
+            }
+            found.push(entry);
+        }
+    }
+    Ok(found)
+}
+
+// Logon tasks and Startup folder shortcuts of configured services
+fn scan_backends(config: &Config, arch: &dyn Fn(&str) -> Option<Arch>) -> Result<Vec<Audited>, SvcError> {
+    let mut found = Vec::new();
+    for service in &config.services {
+        for backend in [Backend::ScheduledTask, Backend::StartupFolder] {
+            if let Some(entry) = backends::find(service, backend)? {
+                let line = entry.command_line();
+                let (class, note) = classify(service, backend, &line, arch);
+                found.push(audited(&service.name, backend, None, &line, class, note));
+            }
+        }
+    }
+    Ok(found)
+}
+
+// Rewrite an entry to what `svc enable` would write now; a legacy value moves to the namespaced name
+pub fn repair(config: &Config, entry: &Audited, registry: &mut dyn Registry) -> Result<(), SvcError> {
+    let service = config
+        .services
+        .iter()
+        .find(|service| service.name == entry.service)
+        .ok_or_else(|| SvcError::ServiceNotFound {
+            name: entry.service.clone(),
+            suggestion: None,
+        })?;
+    let _lock = mutex::lock("startup", &service.name)?;
+    let data = startup::run_data(service, &config.settings)?;
+    match RUN_KEYS.iter().find(|(backend, _)| *backend == entry.backend) {
+        Some((_, key)) => {
+            let value = startup::value_name(&service.name);
+            registry.set(key, &value, &data)?;
+            match entry.value.as_ref().filter(|old| **old != value) {
+                Some(old) => registry.delete(key, old),
+                None => Ok(()),
+            }
+        }
+        None => {
+            backends::disable(service, entry.backend)?;
+            backends::enable(service, entry.backend, &data)
+        }
+    }
+}
+
+pub fn repair_all(config: &Config, entries: &mut [Audited], registry: &mut dyn Registry) {
+    for entry in entries.iter_mut().filter(|entry| entry.class != Class::Ok) {
+        if !entry.repairable {
+            entry.fix = Some(Fix::LeftAlone);
+            continue;
+        }
+        match repair(config, entry, registry) {
+            Ok(()) => entry.fix = Some(Fix::Fixed),
+            Err(err) => {
+                entry.fix = Some(Fix::Failed);
+                entry.error = Some(err.to_string());
+            }
+        }
+    }
+}
+
+fn print_report(entries: &[Audited], fix: bool) -> Result<(), SvcError> {
+    if output::options().json {
+        let report = serde_json::json!({ "entries": entries });
+        println!("{}", serde_json::to_string_pretty(&report).map_err(std::io::Error::other)?);
+        return Ok(());
+    }
+    if entries.is_empty() {
+        println!("No start-up entries.");
+        return Ok(());
+    }
+    let width = entries.iter().map(|entry| output::width(&entry.service)).max().unwrap_or(0);
+    for entry in entries {
+        let class = format!("{:<17}", entry.class.name());
+        let class = match entry.class {
+            Class::Ok => class.green().to_string(),
+            _ if entry.repairable => class.yellow().to_string(),
+            _ => class.red().to_string(),
+        };
+        let fix = match entry.fix {
+            Some(Fix::Fixed) => format!("  {}", "fixed".green()),
+            Some(Fix::Failed) => format!("  {}: {}", "failed".red().bold(), entry.error.as_deref().unwrap_or_default()),
+            Some(Fix::LeftAlone) => format!("  {}", "left alone".yellow()),
+            None => String::new(),
+        };
+        let note = entry.note.as_deref().map(|note| format!("  {}", note)).unwrap_or_default();
+        println!("  {}  {:<15}  {}{}{}", output::pad(&entry.service, width), entry.backend, class, note, fix);
+    }
+    let problems = entries.iter().filter(|entry| entry.class != Class::Ok).count();
+    let repairable = entries.iter().filter(|entry| entry.class != Class::Ok && entry.repairable).count();
+    if problems > 0 && !fix {
+        println!(
+            "{} of {} entries need attention; --fix rewrites the {} repairable.",
+            problems,
+            entries.len(),
+            repairable
+        );
+    }
+    Ok(())
+}
+
+// `svc audit-startup`: classify every start-up entry svc manages and, with fix, repair what can be
+pub fn run(config_path: &str, fix: bool) -> Result<(), SvcError> {
+    let config = load_document(config_path)?;
+    let arch = |path: &str| pe::inspect(Path::new(path)).map(|info| info.arch);
+    let mut registry = SystemRegistry;
+    let mut entries = scan_registry(&config, &registry, &arch)?;
+    entries.extend(scan_backends(&config, &arch)?);
+    if fix {
+        if entries.iter().any(|entry| entry.backend == Backend::RunKey && entry.repairable) {
+            probe::require(Capability::StartupWrite)?;
+        }
+        repair_all(&config, &mut entries, &mut registry);
+    }
+    print_report(&entries, fix)?;
+    let left = entries
+        .iter()
+        .filter(|entry| entry.class != Class::Ok && entry.fix != Some(Fix::Fixed))
+        .count();
+    if left > 0 {
+        return Err(SvcError::StartupAuditFindings { left });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_config;
+    use std::collections::HashMap;
+
+    // Run keys in memory, keyed by (key, value name)
+    #[derive(Default)]
+    struct FakeRegistry {
+        values: HashMap<(String, String), String>,
+    }
+
+    impl FakeRegistry {
+        fn with(values: &[(&str, &str, &str)]) -> Self {
+            FakeRegistry {
+                values: values
+                    .iter()
+                    .map(|(key, name, data)| ((key.to_string(), name.to_string()), data.to_string()))
+                    .collect(),
+            }
+        }
+
+        fn get(&self, key: &str, name: &str) -> Option<&str> {
+            self.values.get(&(key.to_string(), name.to_string())).map(String::as_str)
+        }
+    }
+
+    impl Registry for FakeRegistry {
+        fn value_names(&self, key: &str) -> Result<Vec<String>, SvcError> {
+            let mut names: Vec<String> =
+                self.values.keys().filter(|(k, _)| k == key).map(|(_, name)| name.clone()).collect();
+            names.sort();
+            Ok(names)
+        }
+
+        fn query(&self, key: &str, name: &str) -> Result<Option<String>, SvcError> {
+            Ok(self.get(key, name).map(String::from))
+        }
+
+        fn set(&mut self, key: &str, name: &str, data: &str) -> Result<(), SvcError> {
+            self.values.insert((key.to_string(), name.to_string()), data.to_string());
+            Ok(())
+        }
+
+        fn delete(&mut self, key: &str, name: &str) -> Result<(), SvcError> {
+            self.values.remove(&(key.to_string(), name.to_string()));
+            Ok(())
+        }
+    }
+
+    const CONFIG: &str = r#"
+services:
+  - name: api
+    type: Executable
+    path: 'C:\Program Files\Api\api.exe'
+    args: ['--port', '8080']
+  - name: OneDrive
+    type: Executable
+    path: 'C:\tools\sync.exe'
+"#;
+
+    fn no_arch(_: &str) -> Option<Arch> {
+        None
+    }
+
+    fn scan(registry: &FakeRegistry) -> Vec<Audited> {
+        scan_registry(&parse_config(CONFIG).unwrap(), registry, &no_arch).unwrap()
+    }
+
+    fn classes(entries: &[Audited]) -> Vec<(&str, Class)> {
+        entries.iter().map(|entry| (entry.service.as_str(), entry.class)).collect()
+    }
+
+    #[test]
+    fn an_up_to_date_value_is_ok() {
+        let registry = FakeRegistry::with(&[(RUN_KEY, "svc:api", r#""C:\Program Files\Api\api.exe" --port 8080"#)]);
+        assert_eq!(classes(&scan(&registry)), [("api", Class::Ok)]);
+    }
+
+    #[test]
+    fn finds_unquoted_and_stale_values_in_both_keys() {
+        let registry = FakeRegistry::with(&[
+            (RUN_KEY, "svc:api", r#"C:\Program Files\Api\api.exe --port 8080"#),
+            (MACHINE_RUN_KEY, "svc:api", r#""C:\Program Files\Api\old.exe" --port 8080"#),
+        ]);
+        let entries = scan(&registry);
+        assert_eq!(classes(&entries), [("api", Class::Unquoted), ("api", Class::StaleTarget)]);
+        assert_eq!(entries[1].backend, Backend::MachineRunKey);
+        assert!(entries.iter().all(|entry| entry.repairable));
+    }
+
+    #[test]
+    fn another_programs_value_under_the_bare_name_is_a_collision() {
+        let registry = FakeRegistry::with(&[
+            (RUN_KEY, "OneDrive", r#""C:\Program Files\Microsoft OneDrive\OneDrive.exe" /background"#),
+            (RUN_KEY, "Teams", r#""C:\Teams\Teams.exe""#),
+        ]);
+        let entries = scan(&registry);
+        assert_eq!(classes(&entries), [("OneDrive", Class::ForeignCollision)]);
+        assert!(!entries[0].repairable);
+    }
+
+    #[test]
+    fn a_value_for_an_unconfigured_service_is_not_repairable() {
+        let registry = FakeRegistry::with(&[(RUN_KEY, "svc:gone", r#""C:\gone\gone.exe""#)]);
+        let entries = scan(&registry);
+        assert_eq!(classes(&entries), [("gone", Class::StaleTarget)]);
+        assert!(!entries[0].repairable);
+    }
+
+    #[test]
+    fn another_build_of_the_program_is_an_arch_mismatch() {
+        let registry = FakeRegistry::with(&[(RUN_KEY, "svc:OneDrive", r#""C:\tools\x86\sync.exe""#)]);
+        let arch = |path: &str| Some(if path.contains("x86") { Arch::X86 } else { Arch::X64 });
+        let entries = scan_registry(&parse_config(CONFIG).unwrap(), &registry, &arch).unwrap();
+        assert_eq!(classes(&entries), [("OneDrive", Class::ArchMismatch)]);
+        assert!(!entries[0].repairable);
+    }
+
+    #[test]
+    fn fix_rewrites_repairable_values_and_leaves_the_rest() {
+        let config = parse_config(CONFIG).unwrap();
+        let foreign = r#""C:\Program Files\Microsoft OneDrive\OneDrive.exe""#;
+        let mut registry = FakeRegistry::with(&[
+            (RUN_KEY, "api", r#"C:\Program Files\Api\api.exe"#),
+            (RUN_KEY, "OneDrive", foreign),
+            (MACHINE_RUN_KEY, "svc:OneDrive", r#""C:\old\sync.exe""#),
+        ]);
+        let mut entries = scan_registry(&config, &registry, &no_arch).unwrap();
+        repair_all(&config, &mut entries, &mut registry);
+
+        let fixes: Vec<_> = entries.iter().map(|entry| (entry.service.as_str(), entry.fix)).collect();
+        assert_eq!(
+            fixes,
+            [("OneDrive", Some(Fix::LeftAlone)), ("api", Some(Fix::Fixed)), ("OneDrive", Some(Fix::Fixed))]
+        );
+        // The legacy bare-name value moved to the namespaced one
+        assert_eq!(registry.get(RUN_KEY, "api"), None);
+        assert_eq!(registry.get(RUN_KEY, "svc:api"), Some(r#""C:\Program Files\Api\api.exe" --port 8080"#));
+        assert_eq!(registry.get(RUN_KEY, "OneDrive"), Some(foreign));
+        assert_eq!(registry.get(MACHINE_RUN_KEY, "svc:OneDrive"), Some(r#""C:\tools\sync.exe""#));
+        // Only the collision is left
+        let again = scan_registry(&config, &registry, &no_arch).unwrap();
+        let left: Vec<_> = classes(&again).into_iter().filter(|(_, class)| *class != Class::Ok).collect();
+        assert_eq!(left, [("OneDrive", Class::ForeignCollision)]);
+    }
+}
@@ -0,0 +1,336 @@
+use crate::flavor::{self, Feature};
+use crate::probe::{self, Capability};
+use crate::runas::ps_quote;
+use crate::startup::{self, MACHINE_RUN_KEY};
+use crate::{capture, effects, elevation, output, pathcmp, work_dir, Service, SvcError};
+use clap::ValueEnum;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+// Task Scheduler folder svc's logon tasks go in, so they can't clash with anyone else's
+const TASK_FOLDER: &str = r"\svc\";
+
+// A mechanism Windows starts programs at logon or boot through
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// The per-user Run key
+    #[default]
+    RunKey,
+    /// The machine-wide Run key, for every user; writing it needs administrator rights
+    MachineRunKey,
+    /// A logon task in Task Scheduler's \svc\ folder
+    ScheduledTask,
+    /// A shortcut in the user's Startup folder
+    StartupFolder,
+    /// A Windows service of the same name; only reported, svc never registers one
+    Scm,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 5] = [
+        Backend::RunKey,
+        Backend::MachineRunKey,
+        Backend::ScheduledTask,
+        Backend::StartupFolder,
+        Backend::Scm,
+    ];
+
+    // Whether svc writes entries there, rather than only reporting them
+    pub fn writable(self) -> bool {
+        self != Backend::Scm
+    }
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Backend::RunKey => "run-key",
+            Backend::MachineRunKey => "machine-run-key",
+            Backend::ScheduledTask => "scheduled-task",
+            Backend::StartupFolder => "startup-folder",
+            Backend::Scm => "scm",
+        })
+    }
+}
+
+// One mechanism that starts the service, and what it starts
+#[derive(Debug, Clone, Serialize)]
+pub struct StartupEntry {
+    pub backend: Backend,
+    pub target: String,
+    pub args: Vec<String>,
+    // Starts the configured binary with the configured args, directly or through `svc run --startup`
+    pub matches_config: bool,
+}
+
+impl StartupEntry {
+    fn new(service: &Service, backend: Backend, target: String, args: Vec<String>) -> Self {
+        let direct = pathcmp::same_file(&target, &service.path)
+            && args.len() == service.args.len()
+            && args.iter().zip(&service.args).all(|(arg, configured)| arg == configured.as_ref());
+        let run = match args.as_slice() {
+            [flag, _, run @ ..] if flag == "--config" => run,
+            run => run,
+        };
+        let through_svc = run == ["run", "--startup", service.name.as_ref()];
+        StartupEntry {
+            backend,
+            target,
+            args,
+            matches_config: direct || through_svc,
+        }
+    }
+
+    // The target and args as one command line, quoted as Windows splits them
+    pub fn command_line(&self) -> String {
+        match self.args.as_slice() {
+            [] => format!("\"{}\"", self.target),
+            args => format!("\"{}\" {}", self.target, join_args(args)),
+        }
+    }
+}
+
+fn join_args(args: &[String]) -> String {
+    args.iter().map(|arg| elevation::quote(arg)).collect::<Vec<_>>().join(" ")
+}
+
+// Program and arguments of a command line. Run values written before services had args hold
+// a bare path, spaces and all, so a line that names the service's binary as a whole is kept whole
+fn split_command_line(service: &Service, line: &str) -> (String, Vec<String>) {
+    let line = line.trim();
+    if !line.starts_with('"') && (Path::new(line).is_file() || pathcmp::same_file(line, &service.path)) {
+        return (line.to_string(), Vec::new());
+    }
+    let mut words = split_args(line).into_iter();
+    (words.next().unwrap_or_default(), words.collect())
+}
+
+// Words split on unquoted whitespace, with \" for a literal quote
+pub fn split_args(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let (mut quoted, mut started) = (false, false);
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'"') => {
+                word.push('"');
+                chars.next();
+            }
+            '"' => {
+                quoted = !quoted;
+                started = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if started || !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                started = false;
+            }
+            c => word.push(c),
+        }
+    }
+    if started || !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn task_name(service: &str) -> String {
+    format!("{}{}", TASK_FOLDER, service)
+}
+
+fn shortcut_path(service: &str) -> Result<PathBuf, SvcError> {
+    let appdata = std::env::var_os("APPDATA")
+        .ok_or_else(|| SvcError::InvalidCommand("startup-folder without %APPDATA%".to_string()))?;
+    Ok(PathBuf::from(appdata)
+        .join(r"Microsoft\Windows\Start Menu\Programs\Startup")
+        .join(format!("svc - {}.lnk", service)))
+}
+
+// Output of a helper that may write UTF-16, as schtasks does for XML
+fn decode(bytes: &[u8]) -> String {
+    match bytes {
+        [0xFF, 0xFE, rest @ ..] => {
+            let units: Vec<u16> = rest.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(bytes).to_string(),
+    }
+}
+
+// stdout of a helper that succeeded; a missing helper or a failure means there is nothing to report
+fn helper_output(command: &mut Command) -> Option<String> {
+    let output = capture::output(command.stdin(Stdio::null()).stderr(Stdio::null())).ok()?;
+    output.status.success().then(|| decode(&output.stdout))
+}
+
+fn xml_element(xml: &str, tag: &str) -> Option<String> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(
+        xml[start..end]
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&amp;", "&"),
+    )
+}
+
+// The entry a stored command line makes, judged against the config as `svc status` does
+pub fn parse_entry(service: &Service, backend: Backend, line: &str) -> StartupEntry {
+    let (target, args) = split_command_line(service, line);
+    StartupEntry::new(service, backend, target, args)
+}
+
+// The entry of one backend, if the service has one there
+pub fn find(service: &Service, backend: Backend) -> Result<Option<StartupEntry>, SvcError> {
+    let entry = |line: &str| parse_entry(service, backend, line);
+    Ok(match backend {
+        Backend::RunKey => startup::find(service)?.map(|found| entry(&found.data)),
+        Backend::MachineRunKey => startup::query_value_in(MACHINE_RUN_KEY, &startup::value_name(&service.name))?
+            .map(|data| entry(&data)),
+        Backend::ScheduledTask => {
+            helper_output(Command::new("schtasks").args(["/Query", "/TN", &task_name(&service.name), "/XML", "ONE"]))
+                .and_then(|xml| {
+                    let args = split_args(&xml_element(&xml, "Arguments").unwrap_or_default());
+                    Some(StartupEntry::new(service, backend, xml_element(&xml, "Command")?, args))
+                })
+        }
+        // Nothing there to find where no shell runs the folder
+        Backend::StartupFolder if flavor::missing(Feature::StartupFolder).is_some() => None,
+        Backend::StartupFolder => {
+            let Ok(shortcut) = shortcut_path(&service.name) else {
+                return Ok(None);
+            };
+            if !shortcut.exists() {
+                return Ok(None);
+            }
+            let script = format!(
+                "$s = (New-Object -ComObject WScript.Shell).CreateShortcut({}); $s.TargetPath; $s.Arguments",
+                ps_quote(&shortcut.to_string_lossy())
+            );
+            helper_output(Command::new("powershell").args(["-NoProfile", "-NonInteractive", "-Command", &script])).map(
+                |output| {
+                    let mut lines = output.lines();
+                    let target = lines.next().unwrap_or_default().trim().to_string();
+                    StartupEntry::new(service, backend, target, split_args(lines.next().unwrap_or_default()))
+                },
+            )
+        }
+        // Lines look like "        BINARY_PATH_NAME   : C:\app\server.exe"
+        Backend::Scm => helper_output(Command::new("sc").args(["qc", &service.name])).and_then(|output| {
+            output.lines().find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                (key.trim() == "BINARY_PATH_NAME").then(|| entry(value))
+            })
+        }),
+    })
+}
+
+// Every mechanism that starts the service, whichever svc would use itself; the Run keys are
+// skipped where they can't be read, which callers point out through probe::missing
+pub fn query(service: &Service) -> Result<Vec<StartupEntry>, SvcError> {
+    let unreadable = probe::missing(Capability::StartupRead).is_some();
+    let mut entries = Vec::new();
+    for backend in Backend::ALL {
+        if unreadable && matches!(backend, Backend::RunKey | Backend::MachineRunKey) {
+            continue;
+        }
+        entries.extend(find(service, backend)?);
+    }
+    Ok(entries)
+}
+
+// A bare-name Run value from an older svc is moved to the namespaced one instead
+fn enable_run_key(service: &Service, data: &str) -> Result<(), SvcError> {
+    let legacy = match startup::find(service)? {
+        Some(entry) if entry.is_legacy() => Some(entry),
+        Some(_) => return Err(SvcError::ServiceIsEnabled),
+        None => None,
+    };
+    let value = startup::value_name(&service.name);
+    startup::set_value(&value, data)?;
+    if let Some(entry) = legacy {
+        startup::delete_value(&entry.value)?;
+        if output::options().verbose {
+            eprintln!("Moved start-up entry {} to {}.", entry.value.cyan(), value.cyan());
+        }
+    }
+    Ok(())
+}
+
+fn disable_run_key(service: &Service) -> Result<(), SvcError> {
+    startup::delete_value(&startup::lookup(service)?.removable(service)?.value)
+}
+
+// Have the backend start `data` at logon
+pub fn enable(service: &Service, backend: Backend, data: &str) -> Result<(), SvcError> {
+    let not_yet = || match find(service, backend)? {
+        Some(_) => Err(SvcError::ServiceIsEnabled),
+        None => Ok(()),
+    };
+    match backend {
+        Backend::RunKey => enable_run_key(service, data),
+        Backend::MachineRunKey => {
+            not_yet()?;
+            startup::set_value_in(MACHINE_RUN_KEY, &startup::value_name(&service.name), data)
+        }
+        Backend::ScheduledTask => {
+            not_yet()?;
+            capture::run_helper(
+                Command::new("schtasks")
+                    .args(["/Create", "/TN", &task_name(&service.name), "/TR", data, "/SC", "ONLOGON", "/F"])
+                    .args(service.elevated.then_some(["/RL", "HIGHEST"]).into_iter().flatten()),
+            )
+        }
+        Backend::StartupFolder => {
+            flavor::require(Feature::StartupFolder)?;
+            not_yet()?;
+            let (target, args) = split_command_line(service, data);
+            let script = format!(
+                "$s = (New-Object -ComObject WScript.Shell).CreateShortcut({}); $s.TargetPath = {}; $s.Arguments = {}; $s.WorkingDirectory = {}; $s.Save()",
+                ps_quote(&shortcut_path(&service.name)?.to_string_lossy()),
+                ps_quote(&target),
+                ps_quote(&join_args(&args)),
+                ps_quote(work_dir(service))
+            );
+            capture::run_helper(Command::new("powershell").args(["-NoProfile", "-NonInteractive", "-Command", &script]))
+        }
+        Backend::Scm => Err(scm_unsupported()),
+    }
+}
+
+pub fn disable(service: &Service, backend: Backend) -> Result<(), SvcError> {
+    let present = || match find(service, backend)? {
+        Some(_) => Ok(()),
+        None => Err(SvcError::ServiceIsDisabled),
+    };
+    match backend {
+        Backend::RunKey => disable_run_key(service),
+        Backend::MachineRunKey => {
+            present()?;
+            startup::delete_value_in(MACHINE_RUN_KEY, &startup::value_name(&service.name))
+        }
+        Backend::ScheduledTask => {
+            present()?;
+            capture::run_helper(Command::new("schtasks").args(["/Delete", "/TN", &task_name(&service.name), "/F"]))
+        }
+        Backend::StartupFolder => {
+            flavor::require(Feature::StartupFolder)?;
+            present()?;
+            effects::delete(&shortcut_path(&service.name)?)
+        }
+        Backend::Scm => Err(scm_unsupported()),
+    }
+}
+
+// A plain program doesn't answer the service control manager, which kills it after 30 seconds
+fn scm_unsupported() -> SvcError {
+    SvcError::InvalidCommand("--backend scm; svc only reports Windows services, it doesn't register them".to_string())
+}
@@ -0,0 +1,188 @@
+use crate::format::{lock_config, write_atomic};
+use crate::output::timestamp;
+use crate::{enable_service, history, parse_config, read_config, secrets, startup, state, SvcError, VERSION};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+const MANIFEST: &str = "manifest.json";
+const CONFIG: &str = "services.yaml";
+const STARTUP: &str = "startup.json";
+const SECRETS: &str = "secrets.json";
+const HISTORY: &str = "history.jsonl";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    format: u32,
+    created: String,
+    svc_version: String,
+}
+
+// Secret keys run_as refers to, and whether the store had them; values never leave the machine
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SecretRefs {
+    keys: BTreeMap<String, bool>,
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, SvcError> {
+    Ok(serde_json::to_string_pretty(value).map_err(std::io::Error::other)?)
+}
+
+pub fn backup(config_path: &str, bundle: &Path) -> Result<(), SvcError> {
+    let content = read_config(Path::new(config_path))?;
+    let config = parse_config(&content)?;
+
+    // The registry is the record of what is actually enabled, not the config
+    let mut entries = BTreeMap::new();
+    for service in &config.services {
+        if let Some(entry) = startup::find(service)? {
+            entries.insert(service.name.to_string(), entry.data);
+        }
+    }
+    let mut refs = SecretRefs::default();
+    for run_as in config.services.iter().filter_map(|s| s.run_as.as_ref()) {
+        let key = run_as.password_secret.to_string();
+        let stored = secrets::read_blob(&key).is_ok();
+        refs.keys.insert(key, stored);
+    }
+    let history = fs::read_to_string(history::history_path()?).unwrap_or_default();
+
+    let manifest = Manifest {
+        format: 1,
+        created: timestamp(),
+        svc_version: VERSION.to_string(),
+    };
+    let files: [(&str, String); 5] = [
+        (MANIFEST, to_json(&manifest)?),
+        (CONFIG, content),
+        (STARTUP, to_json(&entries)?),
+        (SECRETS, to_json(&refs)?),
+        (HISTORY, history),
+    ];
+
+    let mut zip = ZipWriter::new(File::create(bundle)?);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, content) in &files {
+        zip.start_file(*name, options)?;
+        zip.write_all(content.as_bytes())?;
+    }
+    zip.finish()?.sync_all()?;
+
+    println!(
+        "Backed up {} services, {} start-up entries and {} secret references to {}.",
+        config.services.len(),
+        entries.len(),
+        refs.keys.len(),
+        bundle.display().to_string().cyan()
+    );
+    Ok(())
+}
+
+fn read_member(archive: &mut ZipArchive<File>, name: &str) -> Result<String, SvcError> {
+    let mut content = String::new();
+    archive.by_name(name)?.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+fn parse_member<T: for<'de> Deserialize<'de>>(archive: &mut ZipArchive<File>, name: &str) -> Result<T, SvcError> {
+    Ok(serde_json::from_str(&read_member(archive, name)?).map_err(std::io::Error::other)?)
+}
+
+// Rebuild the config, history and start-up entries from a bundle; running it twice
+// changes nothing the second time
+pub fn restore(config_path: &str, bundle: &Path, dry_run: bool) -> Result<(), SvcError> {
+    let mut archive = ZipArchive::new(File::open(bundle)?)?;
+    let manifest: Manifest = parse_member(&mut archive, MANIFEST)?;
+    let content = read_member(&mut archive, CONFIG)?;
+    let entries: BTreeMap<String, String> = parse_member(&mut archive, STARTUP)?;
+    let refs: SecretRefs = parse_member(&mut archive, SECRETS)?;
+    let bundled_history = read_member(&mut archive, HISTORY)?;
+
+    // Refuse a bundle whose config this svc can't load before touching anything
+    let config = parse_config(&content)?;
+    let mode = if dry_run { "would" } else { "done" };
+    println!(
+        "Restoring a backup taken {} by svc {}{}.",
+        manifest.created,
+        manifest.svc_version,
+        if dry_run { " (dry run)" } else { "" }
+    );
+
+    let mut problems: Vec<String> = Vec::new();
+    let step = |done: bool, what: String| {
+        let marker = if done { mode.green() } else { "skipped".yellow() };
+        println!("  {:7} {}", marker, what);
+    };
+
+    let config_path = Path::new(config_path);
+    if fs::read_to_string(config_path).is_ok_and(|current| current == content) {
+        step(false, format!("config {} (already up to date)", config_path.display()));
+    } else {
+        if !dry_run {
+            let _lock = lock_config(config_path)?;
+            write_atomic(config_path, &content)?;
+        }
+        step(true, format!("config {}", config_path.display()));
+    }
+
+    // Only records the local history doesn't already have are added
+    let history_path = history::history_path()?;
+    let local_history = fs::read_to_string(&history_path).unwrap_or_default();
+    let known: std::collections::HashSet<&str> = local_history.lines().collect();
+    let missing: Vec<&str> = bundled_history.lines().filter(|line| !known.contains(line)).collect();
+    if missing.is_empty() {
+        step(false, "history (nothing new)".to_string());
+    } else {
+        if !dry_run {
+            let _lock = state::lock(&history_path)?;
+            let mut file = fs::OpenOptions::new().create(true).append(true).open(&history_path)?;
+            for line in &missing {
+                writeln!(file, "{}", line)?;
+            }
+        }
+        step(true, format!("history ({} records)", missing.len()));
+    }
+
+    for name in entries.keys() {
+        let Some(service) = config.services.iter().find(|s| s.name == name.as_str()) else {
+            problems.push(format!("start-up entry {} has no service in the bundled config", name));
+            continue;
+        };
+        if !Path::new(service.path.as_ref()).exists() {
+            problems.push(format!("{}: {} does not exist on this machine", name, service.path));
+            continue;
+        }
+        if startup::find(service)?.is_some() {
+            step(false, format!("start-up entry {} (already enabled)", name));
+            continue;
+        }
+        if dry_run {
+            step(true, format!("start-up entry {}", name));
+            continue;
+        }
+        match enable_service(service, &config.settings, None) {
+            Ok(()) => step(true, format!("start-up entry {}", name)),
+            Err(err) => problems.push(format!("enable {}: {}", name, err)),
+        }
+    }
+
+    for (key, stored) in &refs.keys {
+        if secrets::read_blob(key).is_err() {
+            let note = if *stored { "store it again" } else { "it was missing at backup time too; store it" };
+            problems.push(format!("secret {} is not in the store, {} with 'svc secret set {}'", key, note, key));
+        }
+    }
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+    for problem in &problems {
+        eprintln!("  {} {}", "not restored:".red(), problem);
+    }
+    Err(SvcError::RestoreIncomplete(problems.len()))
+}
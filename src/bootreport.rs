@@ -0,0 +1,258 @@
+use crate::clock::Stamp;
+use crate::output::{self, format_time};
+use crate::state::state_dir;
+use crate::{effects, SvcError};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// One <boot time>.jsonl per boot under this directory of the state dir
+const REPORT_DIR: &str = "startup";
+// Uptime and the wall clock are read apart, so runners of one boot can disagree by a second
+const BOOT_SLACK: u64 = 5;
+const KEEP_BOOTS: usize = 10;
+// Entries flagged in the report
+const SLOWEST: usize = 3;
+
+// How one `svc run --startup` went; times are Unix milliseconds
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StartupRecord {
+    pub service: String,
+    pub runner_started: u64,
+    // The startup_order × startup_stagger delay it slept through
+    pub delay_ms: u64,
+    pub woke: u64,
+    // None when the service didn't start
+    pub spawned: Option<u64>,
+    // Services have no way to declare readiness yet, so this stays empty
+    #[serde(default)]
+    pub ready: Option<u64>,
+    pub success: bool,
+    // From woke to spawned by the monotonic clock, which a clock change in between doesn't skew
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spawn_ms: Option<u64>,
+}
+
+impl StartupRecord {
+    // Records written before spawn_ms was kept fall back to the wall-clock times
+    fn spawn_latency(&self) -> Option<u64> {
+        let spawned = self.spawned?;
+        Some(self.spawn_ms.unwrap_or_else(|| spawned.saturating_sub(self.woke)))
+    }
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+// Unix seconds at which Windows booted
+#[cfg(windows)]
+fn boot_time() -> Option<u64> {
+    use windows_sys::Win32::System::SystemInformation::GetTickCount64;
+
+    let uptime = unsafe { GetTickCount64() } / 1000;
+    Some((millis(SystemTime::now()) / 1000).saturating_sub(uptime))
+}
+
+#[cfg(not(windows))]
+fn boot_time() -> Option<u64> {
+    None
+}
+
+fn report_dir() -> Result<PathBuf, SvcError> {
+    let dir = state_dir()?.join(REPORT_DIR);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+// Boot times with a file, oldest first
+fn boots() -> Result<Vec<u64>, SvcError> {
+    let mut boots: Vec<u64> = fs::read_dir(report_dir()?)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".jsonl")?.parse().ok())
+        .collect();
+    boots.sort_unstable();
+    Ok(boots)
+}
+
+// The file of the boot svc runs in, reusing one recorded a few seconds off
+fn current_boot(boots: &[u64]) -> Option<u64> {
+    let boot = boot_time()?;
+    Some(boots.iter().copied().find(|&known| known.abs_diff(boot) <= BOOT_SLACK).unwrap_or(boot))
+}
+
+fn boot_path(boot: u64) -> Result<PathBuf, SvcError> {
+    Ok(report_dir()?.join(format!("{}.jsonl", boot)))
+}
+
+fn write(record: &StartupRecord) -> Result<(), SvcError> {
+    let known = boots()?;
+    let Some(boot) = current_boot(&known) else {
+        return Ok(());
+    };
+    if !known.contains(&boot) {
+        for old in known.iter().rev().skip(KEEP_BOOTS - 1) {
+            let _ = fs::remove_file(boot_path(*old)?);
+        }
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(boot_path(boot)?)?;
+    writeln!(file, "{}", serde_json::to_string(record).map_err(std::io::Error::other)?)?;
+    Ok(())
+}
+
+// Called by `svc run --startup` once the service is started, or failed to; the report
+// is only an aid, so a state directory that can't be written doesn't fail the start
+pub fn record(service: &str, started: Stamp, delay: Option<Duration>, woke: Stamp, success: bool) {
+    if effects::dry_run() {
+        return;
+    }
+    let spawned = Stamp::now();
+    let record = StartupRecord {
+        service: service.to_string(),
+        runner_started: millis(started.wall),
+        delay_ms: delay.map_or(0, |delay| delay.as_millis() as u64),
+        woke: millis(woke.wall),
+        spawned: success.then(|| millis(spawned.wall)),
+        ready: None,
+        success,
+        spawn_ms: success.then(|| spawned.since(&woke).as_millis() as u64),
+    };
+    let _ = write(&record);
+}
+
+fn read(boot: u64) -> Result<Vec<StartupRecord>, SvcError> {
+    let content = fs::read_to_string(boot_path(boot)?)?;
+    // A line cut short by a power loss is skipped rather than failing the report
+    let mut records: Vec<StartupRecord> =
+        content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect();
+    records.sort_by_key(|record| record.spawned.unwrap_or(record.woke));
+    Ok(records)
+}
+
+fn format_millis(ms: u64) -> String {
+    if ms < 1000 {
+        format!("{}ms", ms)
+    } else {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    }
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    boot: String,
+    records: &'a [StartupRecord],
+}
+
+// The start-up sequence of this boot, or of the latest one recorded
+pub fn report() -> Result<(), SvcError> {
+    let known = boots()?;
+    let boot = current_boot(&known).filter(|boot| known.contains(boot)).or(known.last().copied());
+    let Some(boot) = boot else {
+        println!("No start-up runs recorded yet.");
+        println!(
+            "{} only entries that go through 'svc run --startup' are recorded, which needs startup_stagger",
+            "hint:".cyan().bold()
+        );
+        return Ok(());
+    };
+    let records = read(boot)?;
+    let booted = format_time(UNIX_EPOCH + Duration::from_secs(boot));
+
+    if output::options().json {
+        let report = Report { boot: booted, records: &records };
+        println!("{}", serde_json::to_string_pretty(&report).map_err(std::io::Error::other)?);
+        return Ok(());
+    }
+
+    // Offsets count from the first runner, the closest svc gets to the logon itself
+    let first = records.iter().map(|record| record.runner_started).min().unwrap_or(0);
+    println!(
+        "Boot at {}, first start-up runner {} later",
+        booted,
+        format_millis(first.saturating_sub(boot * 1000))
+    );
+
+    let mut latencies: Vec<u64> = records.iter().filter_map(StartupRecord::spawn_latency).collect();
+    latencies.sort_unstable_by(|a, b| b.cmp(a));
+    let threshold = latencies.get(SLOWEST - 1).or(latencies.last()).copied();
+
+    let dash = || "-".to_string();
+    let rows: Vec<[String; 6]> = records
+        .iter()
+        .map(|record| {
+            let note = match record.spawn_latency() {
+                _ if !record.success => "failed",
+                // Nothing stands out among a handful of entries
+                Some(latency) if records.len() > SLOWEST && Some(latency) >= threshold => "slowest",
+                _ => "",
+            };
+            [
+                record.service.clone(),
+                record.spawned.map_or_else(dash, |spawned| format!("+{}", format_millis(spawned.saturating_sub(first)))),
+                format_millis(record.delay_ms),
+                record.spawn_latency().map_or_else(dash, format_millis),
+                record.ready.map_or_else(dash, format_millis),
+                note.to_string(),
+            ]
+        })
+        .collect();
+
+    let header = ["SERVICE", "STARTED", "DELAY", "SPAWN", "READY", ""];
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(output::width(cell));
+        }
+    }
+    let line = |cells: [&str; 6]| {
+        cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| output::pad(cell, width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    println!("{}", line(header).bold());
+    for row in &rows {
+        let text = line(row.each_ref().map(String::as_str));
+        match row[5].as_str() {
+            "failed" => println!("{}", text.red()),
+            "slowest" => println!("{}", text.yellow()),
+            _ => println!("{}", text),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(woke: u64, spawned: u64, spawn_ms: Option<u64>) -> StartupRecord {
+        StartupRecord {
+            service: "api".to_string(),
+            runner_started: woke,
+            delay_ms: 0,
+            woke,
+            spawned: Some(spawned),
+            ready: None,
+            success: true,
+            spawn_ms,
+        }
+    }
+
+    #[test]
+    fn spawn_latency_survives_the_clock_being_set_back() {
+        // The clock went back an hour between waking and spawning
+        let woke = 1_774_747_800_000;
+        assert_eq!(record(woke, woke - 3_600_000 + 120, Some(120)).spawn_latency(), Some(120));
+        // A record from before spawn_ms was kept clamps the same jump to 0
+        assert_eq!(record(woke, woke - 3_600_000 + 120, None).spawn_latency(), Some(0));
+        assert_eq!(record(woke, woke + 80, None).spawn_latency(), Some(80));
+    }
+}
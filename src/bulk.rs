@@ -0,0 +1,365 @@
+use crate::checkpoint::{self, Progress, Tracker};
+use crate::cli::{BulkArgs, Cmd, OrderKey, Selection, SessionFilter, StdinFormat};
+use crate::deps::State;
+use crate::order::{self, Order};
+use crate::rollback::Batch;
+use crate::{cancel, dispatch, get_status, list, load_config, load_document, not_found, output, show_status, warnings};
+use crate::{ports, Service, ServiceStatus, SvcError};
+use colored::Colorize;
+use std::io::{IsTerminal, Read};
+
+// Service names piped in: one per line with blank lines and #-comments skipped, or a JSON array
+fn read_names(format: StdinFormat) -> Result<Vec<String>, SvcError> {
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input)?;
+    Ok(match format {
+        StdinFormat::Lines => input
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .collect(),
+        StdinFormat::Json => serde_json::from_str(&input).map_err(std::io::Error::other)?,
+    })
+}
+
+// The same command for another service
+fn with_name(command: &Cmd, name: String) -> Cmd {
+    let bulk = BulkArgs::default();
+    let name = Some(name);
+    match command {
+        Cmd::Run {
+            at,
+            startup,
+            terminal,
+            extra,
+            force,
+            no_deps,
+            lock_timeout,
+            capture,
+            ..
+        } => Cmd::Run {
+            name,
+            at: at.clone(),
+            at_dir: None,
+            force: *force,
+            no_deps: *no_deps,
+            lock_timeout: lock_timeout.clone(),
+            capture: capture.clone(),
+            startup: *startup,
+            terminal: *terminal,
+            extra: extra.clone(),
+            bulk,
+        },
+        Cmd::Enable { backend, .. } => Cmd::Enable {
+            name,
+            backend: *backend,
+            rollback_on_failure: false,
+            bulk,
+        },
+        Cmd::Disable { backend, .. } => Cmd::Disable {
+            name,
+            backend: *backend,
+            rollback_on_failure: false,
+            bulk,
+        },
+        Cmd::Status {
+            trend, no_deps, session, ..
+        } => Cmd::Status {
+            name,
+            pid: None,
+            trend: *trend,
+            no_deps: *no_deps,
+            session: *session,
+            sort: None,
+            bulk,
+        },
+        Cmd::Kill {
+            force, session, uptime, ..
+        } => Cmd::Kill {
+            name,
+            pid: None,
+            force: *force,
+            session: *session,
+            uptime: uptime.clone(),
+            bulk,
+            stop_all: Default::default(),
+        },
+        Cmd::Stop {
+            force, session, uptime, ..
+        } => Cmd::Stop {
+            name,
+            force: *force,
+            session: *session,
+            uptime: uptime.clone(),
+            bulk,
+        },
+        _ => unreachable!("only commands with BulkArgs are run in bulk"),
+    }
+}
+
+// In JSON mode one array of the statuses, rather than a document per name
+fn status_json(
+    config_path: &str,
+    names: &[String],
+    no_deps: bool,
+    session: SessionFilter,
+    sort: Option<OrderKey>,
+) -> Result<(), SvcError> {
+    let config = load_document(config_path)?;
+    let selected = names.iter().map(|name| config.service(name)).collect::<Result<Vec<_>, _>>()?;
+    let mut reports = list::reports(&config.services, &selected, !no_deps)?;
+    Order::of(&config.services).arrange(&mut reports, sort, |report| report.name, |report| report.state);
+    for report in &mut reports {
+        report.retain_session(session);
+    }
+    println!("{}", serde_json::to_string_pretty(&reports).map_err(std::io::Error::other)?);
+    Ok(())
+}
+
+// Statuses gathered in parallel, then printed one service after another
+fn status_text(
+    config_path: &str,
+    names: &[String],
+    trend: bool,
+    no_deps: bool,
+    session: SessionFilter,
+    sort: Option<OrderKey>,
+) -> Result<usize, SvcError> {
+    let config = load_document(config_path)?;
+    let selected = names.iter().map(|name| config.service(name)).collect::<Result<Vec<_>, _>>()?;
+    // Printed grouped after the last status, not repeated after each one
+    let _warnings = warnings::collect();
+    let statuses = order::par_map(&selected, |service| get_status(service));
+    let mut gathered: Vec<_> = selected.iter().copied().zip(statuses).collect();
+    // A status that couldn't be read sorts with the services that are down
+    let state = |(_, status): &(&Service, Result<ServiceStatus, SvcError>)| match status {
+        Ok(status) if !status.pids.is_empty() => State::Running,
+        _ => State::Down,
+    };
+    Order::of(&config.services).arrange(&mut gathered, sort, |(service, _)| &service.name, state);
+    let (selected, statuses): (Vec<&Service>, Vec<_>) = gathered.into_iter().unzip();
+    let known: Vec<(&str, &[u64])> = selected
+        .iter()
+        .zip(&statuses)
+        .filter_map(|(service, status)| Some((service.name.as_ref(), status.as_ref().ok()?.pids.as_slice())))
+        .collect();
+    let conflicts = ports::conflicts(&config.services, &known);
+    let mut failed = 0;
+    for (service, status) in selected.iter().zip(statuses) {
+        let conflicts = conflicts.get(service.name.as_ref()).map(Vec::as_slice).unwrap_or_default();
+        let shown = status.and_then(|status| show_status(&config, service, status, conflicts, trend, no_deps, session));
+        if let Err(err) = shown {
+            output::report_service_error(&service.name, &err);
+            failed += 1;
+        }
+    }
+    Ok(failed)
+}
+
+// The names a selection stands for; tags and --all go by the config's order
+fn select(services: &[Service], selection: &Selection) -> Result<Vec<String>, SvcError> {
+    let names: Vec<String> = match selection {
+        Selection::Stdin(format) => return read_names(*format),
+        Selection::Names(names) => return Ok(names.clone()),
+        Selection::Tags(tags) => services
+            .iter()
+            .filter(|service| service.tags.iter().any(|tag| tags.iter().any(|wanted| wanted == tag)))
+            .map(|service| service.name.to_string())
+            .collect(),
+        Selection::All => services.iter().map(|service| service.name.to_string()).collect(),
+    };
+    match selection {
+        Selection::Tags(tags) if names.is_empty() => Err(SvcError::NoTaggedServices(tags.join(", "))),
+        _ => Ok(names),
+    }
+}
+
+// How the attempts came out, past those that succeeded
+#[derive(Default)]
+struct Tally {
+    failed: usize,
+    // Of those failed, the ones that weren't running
+    not_running: usize,
+    // Left alone by --if-uptime-gt or --if-uptime-lt, which isn't a failure
+    skipped: usize,
+    not_attempted: usize,
+}
+
+// Reports each result as it comes; None is a name Ctrl+C kept from being attempted
+fn tally<'a>(results: impl Iterator<Item = (&'a String, Option<Result<(), SvcError>>)>) -> Tally {
+    let mut tally = Tally::default();
+    for (name, result) in results {
+        match result {
+            Some(Ok(())) => {}
+            Some(Err(err @ SvcError::ConditionNotMet { .. })) => {
+                output::report_skipped(&err);
+                tally.skipped += 1;
+            }
+            Some(Err(err)) => {
+                output::report_service_error(name, &err);
+                tally.failed += 1;
+                if matches!(err, SvcError::ServiceIsNotRunning) {
+                    tally.not_running += 1;
+                }
+            }
+            None => tally.not_attempted += 1,
+        }
+    }
+    tally
+}
+
+// Enable or disable with --rollback-on-failure: every attempt is made, and when any fails the
+// services that succeeded get back the entries captured before the first of them changed
+fn with_rollback(
+    config_path: &str,
+    names: &[String],
+    tracker: Option<&Tracker>,
+    attempt: impl Fn(&String) -> Option<Result<(), SvcError>>,
+) -> Result<Tally, SvcError> {
+    let config = load_document(config_path)?;
+    let selected = names.iter().map(|name| config.service(name)).collect::<Result<Vec<_>, _>>()?;
+    let batch = Batch::capture(&selected, &config.settings)?;
+    let (mut changed, mut failed) = (Vec::new(), Vec::new());
+    let counts = tally(names.iter().map(|name| {
+        let result = attempt(name);
+        match &result {
+            Some(Ok(())) => changed.push(name.as_str()),
+            Some(Err(_)) => failed.push(name.clone()),
+            None => {}
+        }
+        (name, result)
+    }));
+    if failed.is_empty() {
+        return Ok(counts);
+    }
+    let unreverted = batch.roll_back(&changed);
+    batch.print_end_state();
+    // Nothing is left to resume: the rolled-back ones count as failed with the rest
+    if let Some(tracker) = tracker {
+        for name in changed.iter().filter(|name| !unreverted.iter().any(|kept| kept == *name)) {
+            tracker.done(name, Progress::Failed, Some("rolled back".to_string()));
+        }
+    }
+    Err(SvcError::RolledBack { failed, unreverted })
+}
+
+// Run the command once per selected name, carrying on past failures; after Ctrl+C what is
+// under way gets a short grace and nothing new starts
+pub fn run(config_path: &str, command: Cmd, selection: Selection) -> Result<(), SvcError> {
+    let config = load_config(config_path)?;
+    ports::warn_duplicates(&config);
+    let names = select(&config, &selection)?;
+    let (known, unknown): (Vec<String>, Vec<String>) =
+        names.into_iter().partition(|name| config.iter().any(|service| service.name == name.as_str()));
+    // A bulk kill or stop leaves protected services running without --force, rather than ask
+    // about each; resuming leaves them alone too
+    let (protected, known): (Vec<String>, Vec<String>) = match &command {
+        Cmd::Kill { force: false, .. } | Cmd::Stop { force: false, .. } => known
+            .into_iter()
+            .partition(|name| config.iter().any(|service| service.name == name.as_str() && service.protected)),
+        _ => (Vec::new(), known),
+    };
+    for name in &protected {
+        output::report_skipped(&SvcError::ServiceIsProtected(name.clone()));
+    }
+    let json = output::options().json;
+
+    // Only what changes something is worth resuming
+    let tracker = command.mutation().map(|_| Tracker::begin(config_path, &known));
+    let attempt = |name: &String| {
+        if cancel::requested() {
+            return None;
+        }
+        let result = cancel::in_flight(|| dispatch(config_path, with_name(&command, name.clone())));
+        if let Some(tracker) = &tracker {
+            match &result {
+                // A skipped service was dealt with as asked, so resuming leaves it alone
+                Ok(()) | Err(SvcError::ConditionNotMet { .. }) => tracker.done(name, Progress::Succeeded, None),
+                Err(err) => tracker.done(name, Progress::Failed, Some(err.to_string())),
+            }
+        }
+        Some(result)
+    };
+    let tally = match &command {
+        Cmd::Status {
+            no_deps, session, sort, ..
+        } if json => {
+            status_json(config_path, &known, *no_deps, *session, *sort)?;
+            Tally::default()
+        }
+        Cmd::Status {
+            trend,
+            no_deps,
+            session,
+            sort,
+            ..
+        } => Tally {
+            failed: status_text(config_path, &known, *trend, *no_deps, *session, *sort)?,
+            ..Tally::default()
+        },
+        // In parallel unless a loose match may ask at the console, which only works one service
+        // at a time
+        Cmd::Kill { force, .. } | Cmd::Stop { force, .. } if *force || !std::io::stdin().is_terminal() => {
+            let results = order::par_map(&known, attempt);
+            tally(known.iter().zip(results))
+        }
+        Cmd::Enable {
+            rollback_on_failure: true,
+            ..
+        }
+        | Cmd::Disable {
+            rollback_on_failure: true,
+            ..
+        } => {
+            // The whole batch is refused before any change rather than rolled back for a typo
+            if let Some(name) = unknown.first() {
+                return Err(not_found(&config, name));
+            }
+            with_rollback(config_path, &known, tracker.as_ref(), attempt)?
+        }
+        // Runs in order, since one may start what a later one depends on
+        _ => tally(known.iter().map(|name| (name, attempt(name)))),
+    };
+
+    let total = known.len() + protected.len() + unknown.len();
+    // Stdout holds only the JSON documents; the final error carries the counts
+    if !json {
+        let count = |count: usize, what: &str| match count {
+            0 => String::new(),
+            count => format!(", {} {}", count, what),
+        };
+        let not_running = match tally.not_running {
+            0 => String::new(),
+            count => format!(" ({} not running)", count),
+        };
+        println!(
+            "{} of {} succeeded{}{}, {} failed{}, {} unknown{}.",
+            known.len() - tally.failed - tally.skipped - tally.not_attempted,
+            total,
+            count(protected.len(), "protected, skipped"),
+            count(tally.skipped, "skipped by condition"),
+            tally.failed,
+            not_running,
+            unknown.len(),
+            count(tally.not_attempted, "not attempted")
+        );
+    }
+    if json {
+        for name in &unknown {
+            output::report_service_error(name, &not_found(&config, name));
+        }
+    } else if !unknown.is_empty() {
+        eprintln!("{} unknown services: {}", "error:".red().bold(), unknown.join(", "));
+    }
+    if tally.not_attempted > 0 {
+        return Err(checkpoint::interrupted(tally.not_attempted, total));
+    }
+    if tally.failed + unknown.len() > 0 {
+        return Err(SvcError::BulkFailed {
+            failed: tally.failed + unknown.len(),
+            total,
+        });
+    }
+    Ok(())
+}
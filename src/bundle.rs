@@ -0,0 +1,137 @@
+use crate::effects::{self, Effect};
+use crate::format::to_canonical;
+use crate::locate::CONFIG_FILE;
+use crate::process::expand_env;
+use crate::{edit, find_service, ServiceType, SvcError};
+use colored::Colorize;
+use serde_yaml::{Mapping, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const RUN_SCRIPT: &str = "run.cmd";
+
+// Entry fields that only make sense on this machine, dropped from the bundled entry
+const LEFT_OUT: [(&str, &str); 4] = [
+    ("work_at", "it runs in the folder of its binary instead"),
+    ("depends_on", "the services it needs aren't bundled"),
+    ("startup", "whether it starts at logon is up to the machine it lands on"),
+    ("run_as", "the account and its secret belong to this machine"),
+];
+
+fn copy_file(from: &Path, to: &Path) -> Result<(), SvcError> {
+    let effect = Effect::WriteFile {
+        path: to.display().to_string(),
+    };
+    effects::perform(effect, || {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(from, to)?;
+        Ok(())
+    })
+    .map(drop)
+}
+
+// Everything under `from` to the same place under `to`; the number of files copied
+fn copy_dir(from: &Path, to: &Path) -> Result<usize, SvcError> {
+    let mut copied = 0;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copied += copy_dir(&entry.path(), &target)?;
+        } else {
+            copy_file(&entry.path(), &target)?;
+            copied += 1;
+        }
+    }
+    Ok(copied)
+}
+
+// Runs the service when double-clicked, or any svc command against the bundled config
+fn run_script(name: &str, exe: &str) -> String {
+    [
+        "@echo off".to_string(),
+        format!("if \"%~1\"==\"\" (\"%~dp0{}\" --config \"%~dp0{}\" run {}", exe, CONFIG_FILE, name),
+        format!(") else \"%~dp0{}\" --config \"%~dp0{}\" %*", exe, CONFIG_FILE),
+        String::new(),
+    ]
+    .join("\r\n")
+}
+
+pub fn bundle(config_path: &str, name: &str, out: &Path, whole_dir: bool) -> Result<(), SvcError> {
+    let service = find_service(config_path, name)?;
+    let refuse = |reason: String| SvcError::BundleRefused {
+        service: name.to_string(),
+        reason,
+    };
+    let mut document = edit::resolved_document(Path::new(config_path))?;
+    let Some(Value::Mapping(mut entry)) = edit::entry_mut(&mut document, name).map(std::mem::take) else {
+        return Err(refuse("it isn't an entry of the config file".to_string()));
+    };
+    if fs::read_dir(out).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(refuse(format!("{} already has files in it", out.display())));
+    }
+    let source = std::path::absolute(expand_env(&service.path))?;
+    let Some(file) = source.file_name().filter(|_| source.is_file()).map(PathBuf::from) else {
+        return Err(refuse(format!("its path {} is not a file", source.display())));
+    };
+
+    let (bundled, copied) = match source.parent().filter(|_| whole_dir) {
+        Some(dir) => {
+            // A binary at the root of a drive has no directory name to keep
+            let folder = dir.file_name().map_or_else(|| PathBuf::from(name), PathBuf::from);
+            let copied = copy_dir(dir, &out.join(&folder))?;
+            (folder.join(&file), copied)
+        }
+        None => {
+            copy_file(&source, &out.join(&file))?;
+            (file, 1)
+        }
+    };
+    // svc itself, so the folder needs nothing installed
+    let exe = std::env::current_exe()?;
+    let exe_name = exe.file_name().map_or_else(|| "svc.exe".to_string(), |exe| exe.to_string_lossy().to_string());
+    copy_file(&exe, &out.join(&exe_name))?;
+
+    let mut notes = Vec::new();
+    entry.insert("path".into(), Value::String(bundled.display().to_string()));
+    for (field, why) in LEFT_OUT {
+        if entry.remove(field).is_some() {
+            notes.push(format!("{} is left out: {}", field, why));
+        }
+    }
+    let mut bundled_document = Mapping::new();
+    bundled_document.insert("paths_relative_to".into(), "config".into());
+    if let Some(shell) = &service.shell {
+        let setting = document.get("shells").and_then(|shells| shells.get(shell.as_ref())).cloned();
+        if let Some(setting) = setting {
+            let mut shells = Mapping::new();
+            shells.insert(shell.to_string().into(), setting);
+            bundled_document.insert("shells".into(), Value::Mapping(shells));
+        }
+    }
+    bundled_document.insert("services".into(), Value::Sequence(vec![Value::Mapping(entry)]));
+    effects::write(&out.join(CONFIG_FILE), &to_canonical(&Value::Mapping(bundled_document))?)?;
+    effects::write(&out.join(RUN_SCRIPT), &run_script(name, &exe_name))?;
+
+    if matches!(service.service_type, ServiceType::Util) {
+        notes.push(format!(
+            "{} isn't bundled: the machine it lands on needs it on PATH; check with '{} which {}' there",
+            service.interpreter, RUN_SCRIPT, name
+        ));
+    }
+    println!(
+        "Bundled {} into {}: {} file(s) of the service, {}, {} and {}.",
+        name.cyan(),
+        out.display(),
+        copied,
+        exe_name,
+        CONFIG_FILE,
+        RUN_SCRIPT
+    );
+    for note in notes {
+        println!("  {} {}", "!".yellow(), note);
+    }
+    Ok(())
+}
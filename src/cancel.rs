@@ -0,0 +1,279 @@
+use crate::SvcError;
+use std::cell::Cell;
+use std::io::{IsTerminal, Write};
+use std::process::{Child, ExitStatus};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Exit code of a command stopped by Ctrl+C, following the 128 + SIGINT convention
+pub const EXIT_CODE: u8 = 130;
+
+// How often blocking waits look at the flag, bounding how long Ctrl+C takes to land
+const POLL: Duration = Duration::from_millis(50);
+
+// How long work already under way when Ctrl+C lands gets to finish, inside `in_flight`
+pub const IN_FLIGHT_GRACE: Duration = Duration::from_secs(3);
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+static CANCELLED_AT: OnceLock<Instant> = OnceLock::new();
+
+thread_local! {
+    static IN_FLIGHT: Cell<bool> = const { Cell::new(false) };
+}
+
+// Helpers svc waits on, such as PowerShell, which Ctrl+C ends rather than waiting them out;
+// one without a console of its own never sees Ctrl+C itself
+struct Helper {
+    id: u64,
+    // Spawned by work in flight, so ended only once its grace has passed
+    in_flight: bool,
+    // A handle of svc's own, so the PID can't be reused while it is tracked
+    #[cfg(windows)]
+    handle: usize,
+    #[cfg(not(windows))]
+    pid: u32,
+}
+
+static HELPERS: Mutex<Vec<Helper>> = Mutex::new(Vec::new());
+static NEXT_HELPER: AtomicU64 = AtomicU64::new(0);
+
+// Console modes when the handler was installed; a helper or utility ended by Ctrl+C can
+// leave echo or line input off
+static CONSOLE_MODES: OnceLock<Vec<(usize, u32)>> = OnceLock::new();
+
+// Ctrl+C and Ctrl+Break only raise the flag and end the helpers being waited on; long-running
+// loops notice it and unwind through their normal error path so locks and state files get
+// cleaned up
+pub fn install() {
+    let _ = CONSOLE_MODES.set(console_modes());
+    let _ = ctrlc::set_handler(|| {
+        let at = *CANCELLED_AT.get_or_init(Instant::now);
+        CANCELLED.store(true, Ordering::SeqCst);
+        end_helpers(false);
+        thread::spawn(move || {
+            thread::sleep(IN_FLIGHT_GRACE.saturating_sub(at.elapsed()));
+            end_helpers(true);
+        });
+    });
+}
+
+// Before the interrupted command reports: the console modes back as they were, and no color
+// left on from a line cut short
+pub fn restore_terminal() {
+    for &(handle, mode) in CONSOLE_MODES.get().into_iter().flatten() {
+        set_console_mode(handle, mode);
+    }
+    if colored::control::SHOULD_COLORIZE.should_colorize() {
+        if std::io::stdout().is_terminal() {
+            let _ = std::io::stdout().write_all(b"\x1b[0m");
+        }
+        if std::io::stderr().is_terminal() {
+            let _ = std::io::stderr().write_all(b"\x1b[0m");
+        }
+    }
+}
+
+#[cfg(windows)]
+fn console_modes() -> Vec<(usize, u32)> {
+    use windows_sys::Win32::System::Console::{
+        GetConsoleMode, GetStdHandle, STD_ERROR_HANDLE, STD_INPUT_HANDLE, STD_OUTPUT_HANDLE,
+    };
+    [STD_INPUT_HANDLE, STD_OUTPUT_HANDLE, STD_ERROR_HANDLE]
+        .into_iter()
+        .filter_map(|which| {
+            let handle = unsafe { GetStdHandle(which) };
+            let mut mode = 0;
+            // Redirected, so there is no mode to keep
+            (unsafe { GetConsoleMode(handle, &mut mode) } != 0).then_some((handle as usize, mode))
+        })
+        .collect()
+}
+
+#[cfg(windows)]
+fn set_console_mode(handle: usize, mode: u32) {
+    use windows_sys::Win32::System::Console::SetConsoleMode;
+    unsafe { SetConsoleMode(handle as _, mode) };
+}
+
+// Off Windows the terminal's own line discipline isn't svc's to change
+#[cfg(not(windows))]
+fn console_modes() -> Vec<(usize, u32)> {
+    Vec::new()
+}
+
+#[cfg(not(windows))]
+fn set_console_mode(_handle: usize, _mode: u32) {}
+
+fn helpers() -> MutexGuard<'static, Vec<Helper>> {
+    HELPERS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Tracks a helper while it is waited on; dropping it stops tracking
+pub struct Tracked(u64);
+
+pub fn track(child: &Child) -> Tracked {
+    let helper = Helper {
+        id: NEXT_HELPER.fetch_add(1, Ordering::Relaxed),
+        in_flight: IN_FLIGHT.get(),
+        #[cfg(windows)]
+        handle: duplicate(child),
+        #[cfg(not(windows))]
+        pid: child.id(),
+    };
+    let id = helper.id;
+    // Checked under the lock the handler ends helpers under, so one spawned just as Ctrl+C
+    // landed is ended either here or there
+    let mut helpers = helpers();
+    if is_cancelled() {
+        terminate(&helper);
+    }
+    helpers.push(helper);
+    Tracked(id)
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        let mut helpers = helpers();
+        if let Some(at) = helpers.iter().position(|helper| helper.id == self.0) {
+            close(helpers.swap_remove(at));
+        }
+    }
+}
+
+fn end_helpers(in_flight: bool) {
+    for helper in helpers().iter().filter(|helper| helper.in_flight == in_flight) {
+        terminate(helper);
+    }
+}
+
+#[cfg(windows)]
+fn duplicate(child: &Child) -> usize {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Foundation::{DuplicateHandle, DUPLICATE_SAME_ACCESS};
+    use windows_sys::Win32::System::Threading::GetCurrentProcess;
+
+    let mut handle = std::ptr::null_mut();
+    unsafe {
+        let process = GetCurrentProcess();
+        DuplicateHandle(process, child.as_raw_handle(), process, &mut handle, 0, 0, DUPLICATE_SAME_ACCESS);
+    }
+    handle as usize
+}
+
+#[cfg(windows)]
+fn terminate(helper: &Helper) {
+    use windows_sys::Win32::System::Threading::TerminateProcess;
+    if helper.handle != 0 {
+        unsafe { TerminateProcess(helper.handle as _, u32::from(EXIT_CODE)) };
+    }
+}
+
+#[cfg(windows)]
+fn close(helper: Helper) {
+    use windows_sys::Win32::Foundation::CloseHandle;
+    if helper.handle != 0 {
+        unsafe { CloseHandle(helper.handle as _) };
+    }
+}
+
+// Without a process handle to hold, kill is told the PID
+#[cfg(not(windows))]
+fn terminate(helper: &Helper) {
+    use std::process::{Command, Stdio};
+    let _ = Command::new("kill").arg(helper.pid.to_string()).stdout(Stdio::null()).stderr(Stdio::null()).status();
+}
+
+#[cfg(not(windows))]
+fn close(_helper: Helper) {}
+
+// Whether Ctrl+C was pressed at all; what dispatches work stops handing it out on this
+pub fn requested() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+// Whether the current work should stop now; in flight, only once its grace has passed too
+pub fn is_cancelled() -> bool {
+    requested()
+        && (!IN_FLIGHT.get() || CANCELLED_AT.get().is_none_or(|at| at.elapsed() >= IN_FLIGHT_GRACE))
+}
+
+// Work a bulk operation already started, which Ctrl+C lets finish for up to IN_FLIGHT_GRACE
+pub fn in_flight<T>(work: impl FnOnce() -> T) -> T {
+    let outer = IN_FLIGHT.replace(true);
+    let result = work();
+    IN_FLIGHT.set(outer);
+    result
+}
+
+pub fn check() -> Result<(), SvcError> {
+    if is_cancelled() {
+        Err(SvcError::Interrupted)
+    } else {
+        Ok(())
+    }
+}
+
+// A sleep that ends early, with an error, once Ctrl+C is pressed
+pub fn sleep(duration: Duration) -> Result<(), SvcError> {
+    let started = Instant::now();
+    loop {
+        check()?;
+        let left = duration.saturating_sub(started.elapsed());
+        if left.is_zero() {
+            return Ok(());
+        }
+        thread::sleep(left.min(POLL));
+    }
+}
+
+// Wait for a child svc spawned, killing it rather than leaving it orphaned on Ctrl+C
+pub fn wait(child: &mut Child) -> Result<ExitStatus, SvcError> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if is_cancelled() {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(SvcError::Interrupted);
+        }
+        thread::sleep(POLL);
+    }
+}
+
+// Spawns sleep, which Windows lacks
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn tracked_pid(pid: u32) -> Option<bool> {
+        helpers().iter().find(|helper| helper.pid == pid).map(|helper| helper.in_flight)
+    }
+
+    // Only the helpers of this test are ended, as others may be running helpers of their own
+    #[test]
+    fn helpers_are_tracked_while_waited_on_with_whether_they_are_in_flight() {
+        let mut helper = Command::new("sleep").arg("30").spawn().unwrap();
+        let mut flying = Command::new("sleep").arg("30").spawn().unwrap();
+        let guard = track(&helper);
+        let flying_guard = in_flight(|| track(&flying));
+        assert_eq!(tracked_pid(helper.id()), Some(false));
+        assert_eq!(tracked_pid(flying.id()), Some(true));
+
+        let started = Instant::now();
+        for pid in [helper.id(), flying.id()] {
+            let helpers = helpers();
+            terminate(helpers.iter().find(|helper| helper.pid == pid).unwrap());
+        }
+        assert!(!helper.wait().unwrap().success());
+        assert!(!flying.wait().unwrap().success());
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        drop((guard, flying_guard));
+        assert_eq!(tracked_pid(helper.id()), None);
+        assert_eq!(tracked_pid(flying.id()), None);
+    }
+}
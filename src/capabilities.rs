@@ -0,0 +1,167 @@
+use crate::backends::Backend;
+use crate::cli::Cli;
+use crate::serve::PROTOCOL_VERSION;
+use crate::{RunAs, Service, Settings, SvcError, VERSION};
+use clap::{Arg, ArgAction, Command, CommandFactory};
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::Serialize;
+
+// Bumped when a config that loads today would stop loading or change meaning
+pub const CONFIG_SCHEMA: u32 = 2;
+
+#[derive(Serialize)]
+struct Capabilities {
+    version: &'static str,
+    platform: Platform,
+    commands: Vec<CommandInfo>,
+    global_flags: Vec<FlagInfo>,
+    config: ConfigInfo,
+    startup_backends: &'static [&'static str],
+    pipe_protocol: u32,
+}
+
+#[derive(Serialize)]
+struct Platform {
+    os: &'static str,
+    arch: &'static str,
+}
+
+#[derive(Serialize)]
+struct CommandInfo {
+    name: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<String>,
+    flags: Vec<FlagInfo>,
+    positionals: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    subcommands: Vec<CommandInfo>,
+}
+
+#[derive(Serialize)]
+struct FlagInfo {
+    name: String,
+    takes_value: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    values: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ConfigInfo {
+    schema: u32,
+    settings: &'static [&'static str],
+    service: &'static [&'static str],
+    run_as: &'static [&'static str],
+}
+
+fn flag(arg: &Arg) -> Option<FlagInfo> {
+    let name = arg.get_long()?;
+    let takes_value = matches!(arg.get_action(), ArgAction::Set | ArgAction::Append);
+    // Switches report true/false as possible values, which says nothing useful
+    let values = if takes_value {
+        arg.get_possible_values()
+            .iter()
+            .filter(|value| !value.is_hide_set())
+            .map(|value| value.get_name().to_string())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    Some(FlagInfo {
+        name: format!("--{}", name),
+        takes_value,
+        values,
+    })
+}
+
+fn describe(command: &Command) -> CommandInfo {
+    let own = |arg: &&Arg| !arg.is_global_set() && !matches!(arg.get_id().as_str(), "help" | "version");
+    CommandInfo {
+        name: command.get_name().to_string(),
+        aliases: command.get_all_aliases().map(String::from).collect(),
+        flags: command.get_arguments().filter(own).filter_map(flag).collect(),
+        positionals: command.get_positionals().map(|arg| arg.get_id().to_string()).collect(),
+        subcommands: command.get_subcommands().map(describe).collect(),
+    }
+}
+
+// Field and variant names as serde sees them, captured from the derive instead of listed by hand
+#[derive(Default)]
+struct Capture {
+    fields: Option<&'static [&'static str]>,
+    variants: Option<&'static [&'static str]>,
+}
+
+impl<'de> Deserializer<'de> for &mut Capture {
+    type Error = de::value::Error;
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.fields = Some(fields);
+        Err(de::Error::custom("fields captured"))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.variants = Some(variants);
+        Err(de::Error::custom("variants captured"))
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map identifier ignored_any
+    }
+}
+
+fn capture<'de, T: Deserialize<'de>>() -> Capture {
+    let mut capture = Capture::default();
+    let _ = T::deserialize(&mut capture);
+    capture
+}
+
+pub fn fields<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    capture::<T>().fields.unwrap_or_default()
+}
+
+pub fn variants<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    capture::<T>().variants.unwrap_or_default()
+}
+
+pub fn print() -> Result<(), SvcError> {
+    let command = Cli::command();
+    let capabilities = Capabilities {
+        version: VERSION,
+        platform: Platform {
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+        },
+        commands: command.get_subcommands().map(describe).collect(),
+        global_flags: command
+            .get_arguments()
+            .filter(|arg| arg.is_global_set())
+            .filter_map(flag)
+            .collect(),
+        config: ConfigInfo {
+            schema: CONFIG_SCHEMA,
+            settings: fields::<Settings>(),
+            service: fields::<Service>(),
+            run_as: fields::<RunAs>(),
+        },
+        startup_backends: variants::<Backend>(),
+        pipe_protocol: PROTOCOL_VERSION,
+    };
+    println!("{}", serde_json::to_string_pretty(&capabilities).map_err(std::io::Error::other)?);
+    Ok(())
+}
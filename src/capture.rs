@@ -0,0 +1,109 @@
+use crate::encoding::{self, Encoding};
+use crate::effects::{self, Effect};
+use crate::{cancel, debugbundle, SvcError};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, Command, Output, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+// Only the tail of a child's output is kept, so chatty scripts can't grow memory unbounded
+pub const TAIL_LINES: usize = 100;
+
+// Lines are decoded with the service's encoding, so a tail never fails on a stray byte
+pub fn tail_lines(reader: impl Read + Send + 'static, encoding: Option<Encoding>) -> JoinHandle<VecDeque<String>> {
+    tee_lines(reader, encoding, Vec::new())
+}
+
+// As tail_lines, copying each line to every one of `copies` as it arrives; with an encoding
+// they get it as UTF-8, without one as the child wrote it
+pub fn tee_lines(
+    reader: impl Read + Send + 'static,
+    encoding: Option<Encoding>,
+    mut copies: Vec<Box<dyn Write + Send>>,
+) -> JoinHandle<VecDeque<String>> {
+    thread::spawn(move || {
+        let mut tail = VecDeque::with_capacity(TAIL_LINES);
+        let mut reader = BufReader::new(reader);
+        let mut buf = Vec::new();
+
+        while let Ok(n) = reader.read_until(b'\n', &mut buf) {
+            if n == 0 {
+                break;
+            }
+            let line = encoding::decode(encoding, &buf);
+            let bytes = if encoding.is_some() { line.as_bytes() } else { &buf };
+            for copy in &mut copies {
+                let _ = copy.write_all(bytes).and_then(|()| copy.flush());
+            }
+            if tail.len() == TAIL_LINES {
+                tail.pop_front();
+            }
+            tail.push_back(line.trim_end().to_string());
+            buf.clear();
+        }
+        tail
+    })
+}
+
+// Run a helper such as reg or taskkill to completion; a failure carries what it printed,
+// stderr first, with its first non-blank line as the reason
+pub fn run_helper(command: &mut Command) -> Result<(), SvcError> {
+    effects::perform(Effect::run_helper(command), || helper_stdout(command).map(drop)).map(drop)
+}
+
+// As run_helper, returning what the helper printed to stdout
+// Runs a helper to its end, as Command::output, noting it for --debug-bundle. Its stderr is
+// captured even where the caller had it discarded, since left unset it would be inherited
+pub fn output(command: &mut Command) -> std::io::Result<Output> {
+    let started = Instant::now();
+    let output = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(wait_output);
+    debugbundle::command(command, &output, started.elapsed());
+    output
+}
+
+// Child::wait_with_output for a helper, which Ctrl+C ends meanwhile
+pub fn wait_output(child: Child) -> std::io::Result<Output> {
+    let _tracked = cancel::track(&child);
+    child.wait_with_output()
+}
+
+pub fn helper_stdout(command: &mut Command) -> Result<String, SvcError> {
+    let output = output(command.stdin(Stdio::null()))?;
+    if output.status.success() {
+        return Ok(String::from_utf8_lossy(&output.stdout).to_string());
+    }
+
+    let lines: Vec<String> = [&output.stderr, &output.stdout]
+        .iter()
+        .flat_map(|bytes| {
+            String::from_utf8_lossy(bytes)
+                .lines()
+                .map(str::trim_end)
+                .filter(|line| !line.trim().is_empty())
+                .map(String::from)
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    let mut name = command.get_program().to_string_lossy().to_string();
+    if let Some(action) = command.get_args().next() {
+        name = format!("{} {}", name, action.to_string_lossy());
+    }
+    Err(SvcError::HelperFailed {
+        command: name,
+        reason: lines.first().map_or_else(|| output.status.to_string(), |line| line.trim().to_string()),
+        output: lines,
+    })
+}
+
+pub fn join_tail(handle: Option<JoinHandle<VecDeque<String>>>) -> Vec<String> {
+    handle
+        .and_then(|handle| handle.join().ok())
+        .map(Vec::from)
+        .unwrap_or_default()
+}
@@ -0,0 +1,199 @@
+use crate::deps::{self, State};
+use crate::{not_found, order, outdated, procinfo, ready, running_pids, RestartPolicy, Service, SvcError};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Ok,
+    Degraded,
+    Down,
+}
+
+impl Severity {
+    fn exit_code(self) -> u8 {
+        match self {
+            Severity::Ok => 0,
+            Severity::Degraded => 1,
+            Severity::Down => 2,
+        }
+    }
+
+    fn nagios_label(self) -> &'static str {
+        match self {
+            Severity::Ok => "OK",
+            Severity::Degraded => "WARNING",
+            Severity::Down => "CRITICAL",
+        }
+    }
+}
+
+pub struct CheckOptions {
+    pub nagios: bool,
+    pub with_deps: bool,
+    // A running service whose binary changed on disk since it started counts as degraded
+    pub outdated: bool,
+}
+
+struct CheckResult<'a> {
+    service: &'a Service<'a>,
+    pids: usize,
+    severity: Severity,
+    // Why a running service is degraded
+    reason: Option<String>,
+}
+
+// Services that are expected to be up: supervised ones, autostart ones, and any named explicitly
+pub fn expected<'a>(config: &'a [Service<'a>], names: &[String]) -> Result<Vec<&'a Service<'a>>, SvcError> {
+    for name in names {
+        if !config.iter().any(|s| s.name == name.as_str()) {
+            return Err(not_found(config, name));
+        }
+    }
+
+    Ok(config
+        .iter()
+        .filter(|s| {
+            s.restart == RestartPolicy::Always
+                || s.autostart
+                || names.iter().any(|name| s.name == name.as_str())
+        })
+        .collect())
+}
+
+// Why a running service whose dependencies are up still isn't well: its binary changed when
+// --outdated asks, or its ready_pattern or health_check doesn't pass
+fn health(service: &Service, pids: &[u64], options: &CheckOptions) -> Option<String> {
+    if options.outdated {
+        if let Some(reason) = outdated::reason(service, pids) {
+            return Some(reason);
+        }
+    }
+    ready::healthy(service).err().map(|reason| format!("unhealthy: {}", reason))
+}
+
+// Returns the process exit code: 0 when everything is up, 1 when a running service is
+// unhealthy, has a dependency down or couldn't be queried, 2 when anything is down
+pub fn check(config: &[Service], names: &[String], options: &CheckOptions) -> Result<u8, SvcError> {
+    let services = expected(config, names)?;
+    let mut needed = services.clone();
+    if options.with_deps {
+        for service in &services {
+            for dependency in deps::closure(config, service) {
+                if !needed.iter().any(|s| s.name == dependency.name) {
+                    needed.push(dependency);
+                }
+            }
+        }
+    }
+
+    // Only processes are looked at, so a slow start-up backend can't hold the check up
+    let images = procinfo::Images::take();
+    let pids = order::par_map(&needed, |service| running_pids(service, &images));
+    let running: HashMap<&str, bool> = needed
+        .iter()
+        .zip(&pids)
+        .map(|(service, pids)| (service.name.as_ref(), pids.as_ref().is_ok_and(|pids| !pids.is_empty())))
+        .collect();
+    let with_pids: Vec<(&Service, &Result<Vec<u64>, SvcError>)> = services.iter().copied().zip(&pids).collect();
+    let results: Vec<CheckResult> = order::par_map(&with_pids, |&(service, pids)| {
+        let pids = match pids {
+            Ok(pids) => pids,
+            Err(err) => {
+                return CheckResult {
+                    service,
+                    pids: 0,
+                    severity: Severity::Degraded,
+                    reason: Some(format!("couldn't be queried: {}", err)),
+                }
+            }
+        };
+        let dependencies = if options.with_deps { deps::evaluate(config, &running, service) } else { Vec::new() };
+        let (severity, reason) = match deps::own_state(!pids.is_empty(), &dependencies) {
+            State::Down => (Severity::Down, None),
+            State::Degraded => (Severity::Degraded, deps::reason(&dependencies)),
+            State::Running => match health(service, pids, options) {
+                Some(reason) => (Severity::Degraded, Some(reason)),
+                None => (Severity::Ok, None),
+            },
+        };
+        CheckResult {
+            service,
+            pids: pids.len(),
+            severity,
+            reason,
+        }
+    });
+
+    let worst = results
+        .iter()
+        .map(|r| r.severity)
+        .max()
+        .unwrap_or(Severity::Ok);
+    let down: Vec<&str> = results
+        .iter()
+        .filter(|r| r.severity == Severity::Down)
+        .map(|r| r.service.name.as_ref())
+        .collect();
+    let degraded: Vec<&CheckResult> = results.iter().filter(|r| r.severity == Severity::Degraded).collect();
+
+    if options.nagios {
+        let mut details = if down.is_empty() {
+            format!("{} of {} services running", results.len(), results.len())
+        } else {
+            format!("{} of {} services down: {}", down.len(), results.len(), down.join(", "))
+        };
+        if !degraded.is_empty() {
+            let names: Vec<&str> = degraded.iter().map(|r| r.service.name.as_ref()).collect();
+            details.push_str(&format!(", {} degraded: {}", degraded.len(), names.join(", ")));
+        }
+        let perfdata: Vec<String> = results
+            .iter()
+            .map(|r| format!("'{}_pids'={};;;0", r.service.name, r.pids))
+            .collect();
+        println!("{} - {} | {}", worst.nagios_label(), details, perfdata.join(" "));
+    } else {
+        for name in &down {
+            println!("{}: down", name);
+        }
+        for result in &degraded {
+            println!("{}: degraded ({})", result.service.name, result.reason.as_deref().unwrap_or("dependency down"));
+        }
+        if degraded.is_empty() {
+            println!("{} checked, {} down", results.len(), down.len());
+        } else {
+            println!("{} checked, {} down, {} degraded", results.len(), down.len(), degraded.len());
+        }
+    }
+
+    Ok(worst.exit_code())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_config;
+    use std::net::TcpListener;
+
+    const OPTIONS: CheckOptions = CheckOptions {
+        nagios: false,
+        with_deps: false,
+        outdated: false,
+    };
+
+    #[test]
+    fn a_running_service_whose_health_check_fails_is_unhealthy() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let config = parse_config(&format!(
+            "- name: api\n  path: /opt/api/api.exe\n  type: Executable\n  health_check: tcp://127.0.0.1:{}\n",
+            port
+        ))
+        .unwrap();
+        let api = &config.services[0];
+        assert_eq!(health(api, &[42], &OPTIONS), None);
+
+        drop(listener);
+        let reason = health(api, &[42], &OPTIONS).unwrap();
+        assert!(reason.starts_with("unhealthy: 127.0.0.1:"), "{}", reason);
+    }
+}
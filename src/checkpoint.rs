@@ -0,0 +1,128 @@
+use crate::output::timestamp;
+use crate::state::{self, state_dir};
+use crate::{cli, effects, progress, SvcError};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+const CHECKPOINT_FILE: &str = "last-bulk.json";
+
+// The command line `svc resume-last` is running again, recorded in place of its own
+static RESUMING: OnceLock<Vec<String>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Progress {
+    NotAttempted,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub service: String,
+    pub progress: Progress,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// Where the last bulk operation got to
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    // The command line after `svc`, with aliases resolved
+    pub args: Vec<String>,
+    pub config: String,
+    pub started: String,
+    pub services: Vec<Entry>,
+}
+
+impl Checkpoint {
+    // What `svc resume-last` runs again: the failed and the not attempted, in their order
+    pub fn remaining(&self) -> Vec<String> {
+        self.services
+            .iter()
+            .filter(|entry| entry.progress != Progress::Succeeded)
+            .map(|entry| entry.service.clone())
+            .collect()
+    }
+
+    pub fn command_line(&self) -> String {
+        format!("svc {}", self.args.join(" "))
+    }
+}
+
+pub fn path() -> Result<PathBuf, SvcError> {
+    Ok(state_dir()?.join(CHECKPOINT_FILE))
+}
+
+pub fn last() -> Result<Option<Checkpoint>, SvcError> {
+    state::read_json(&path()?)
+}
+
+pub fn resuming(args: Vec<String>) {
+    let _ = RESUMING.set(args);
+}
+
+fn save(checkpoint: &Checkpoint) -> Result<(), SvcError> {
+    // A dry run leaves the last real operation to resume
+    if effects::dry_run() {
+        return Ok(());
+    }
+    let content = serde_json::to_string_pretty(checkpoint).map_err(std::io::Error::other)?;
+    state::replace(&path()?, &content)
+}
+
+// The end of a bulk operation Ctrl+C cut short, once its summary is out
+pub fn interrupted(not_attempted: usize, total: usize) -> SvcError {
+    eprintln!(
+        "Interrupted with {} of {} services not attempted; 'svc resume-last' runs them and any that failed.",
+        not_attempted, total
+    );
+    SvcError::Interrupted
+}
+
+// A bulk operation's checkpoint, rewritten as each service finishes so Ctrl+C or a crash
+// still leaves a record of which were done
+pub struct Tracker(Mutex<Checkpoint>);
+
+impl Tracker {
+    pub fn begin(config_path: &str, names: &[String]) -> Tracker {
+        let checkpoint = Checkpoint {
+            args: RESUMING.get().cloned().unwrap_or_else(cli::args),
+            config: config_path.to_string(),
+            started: timestamp(),
+            services: names
+                .iter()
+                .map(|name| Entry {
+                    service: name.clone(),
+                    progress: Progress::NotAttempted,
+                    error: None,
+                })
+                .collect(),
+        };
+        // Only resuming depends on it, so the operation goes ahead without
+        if let Err(err) = save(&checkpoint) {
+            eprintln!("{} could not save a checkpoint: {}", "warning:".yellow().bold(), err);
+        }
+        Tracker(Mutex::new(checkpoint))
+    }
+
+    pub fn done(&self, service: &str, progress: Progress, error: Option<String>) {
+        let Ok(mut checkpoint) = self.0.lock() else {
+            return;
+        };
+        if let Some(entry) = checkpoint.services.iter_mut().find(|entry| entry.service == service) {
+            entry.progress = progress;
+            entry.error = error;
+        }
+        let _ = save(&checkpoint);
+        let done = checkpoint.services.iter().filter(|entry| entry.progress != Progress::NotAttempted).count();
+        let total = checkpoint.services.len();
+        progress::emit(
+            "item",
+            Some(service),
+            serde_json::json!({ "status": progress, "done": done, "total": total }),
+        );
+    }
+}
@@ -0,0 +1,1464 @@
+use crate::backends::Backend;
+use crate::probe::Capability;
+use crate::units::{ByteSize, HumanDuration};
+use crate::{effects, output, SvcError, VERSION};
+use clap::error::{ContextKind, ContextValue, ErrorKind};
+use crate::priority::Priority;
+use clap::{ArgGroup, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+// `command_aliases` from the config, kept for the help output
+static ALIASES: OnceLock<BTreeMap<String, String>> = OnceLock::new();
+// The command line after the program name, aliases resolved
+static ARGS: OnceLock<Vec<String>> = OnceLock::new();
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "svc",
+    version = VERSION,
+    about = "A simple service & util manager for Windows",
+    long_about = "SVC by EFL, MIT License\nhttps://github.com/EFLKumo/svc\n\nA simple service & util manager for Windows.",
+    disable_help_subcommand = true
+)]
+pub struct Cli {
+    /// Suppress hints and other secondary output, and a utility's stdout on `svc run`
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+    /// Print status, list and errors as JSON
+    #[arg(long, global = true)]
+    pub json: bool,
+    /// Print extra detail, such as the output of successful utilities
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+    /// Print without colors, as when NO_COLOR is set
+    #[arg(long, global = true)]
+    pub no_color: bool,
+    /// Print colors and unicode markers even on a console that seems not to support them
+    #[arg(long, global = true, conflicts_with = "no_color")]
+    pub force_color: bool,
+    /// Apply the hosts section for this computer name instead of the local one
+    #[arg(long, global = true, value_name = "NAME")]
+    pub host: Option<String>,
+    /// Relaunch elevated without asking when a command needs administrator rights
+    #[arg(long, global = true, conflicts_with = "no_elevate")]
+    pub elevate: bool,
+    /// Fail instead of offering to relaunch elevated
+    #[arg(long, global = true)]
+    pub no_elevate: bool,
+    /// Fail at the first error instead of retrying ones that are usually transient,
+    /// such as WMI right after boot
+    #[arg(long, global = true)]
+    pub no_retry: bool,
+    /// Refuse every command that would change services, start-up entries or the config;
+    /// also set by SVC_READ_ONLY
+    #[arg(long, global = true)]
+    pub read_only: bool,
+    /// Run only the command itself, without svc's saved state, history, notifications,
+    /// enrichers, metrics or config cache, for when one of them is broken; also set by SVC_SAFE
+    #[arg(long, global = true)]
+    pub safe: bool,
+    /// Leave out service entries that don't parse or validate, and say so after the command,
+    /// instead of failing; also `lenient: true` in the config
+    #[arg(long, global = true)]
+    pub lenient: bool,
+    /// Also write a zip for bug reports: the config with secrets redacted, capabilities,
+    /// every helper run and its output, the process snapshot, timings and the result
+    #[arg(long, global = true, value_name = "PATH")]
+    pub debug_bundle: Option<PathBuf>,
+    /// Change nothing: list what the command would write, delete, set, start or stop instead,
+    /// or with --json print it as {"dry_run", "effects"}
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+    /// With --json, also print a line of JSON as stops, restarts, waits, bulk commands and applies
+    /// progress, each with a "seq" number, and {"event": "result"} as the last line
+    #[arg(long, global = true, requires = "json")]
+    pub progress_events: bool,
+    /// Skip the enrichers setting's programs, for a faster status and list
+    #[arg(long, global = true)]
+    pub no_enrichers: bool,
+    /// Read services from this file instead of searching for one; also set by SVC_CONFIG
+    #[arg(long, global = true, value_name = "PATH", overrides_with = "config")]
+    pub config: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Cmd,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Cmd {
+    /// Start a service, or run a utility and wait for it
+    Run {
+        #[arg(required_unless_present_any = ["stdin", "tag", "all"])]
+        name: Option<String>,
+        /// Legacy working-directory override: `at <dir>`
+        #[arg(value_names = ["at", "dir"], num_args = 2)]
+        at: Option<Vec<String>>,
+        /// Run in this working directory instead of the service's work_dir
+        #[arg(long = "at", value_name = "DIR", conflicts_with_all = ["at", "startup"])]
+        at_dir: Option<String>,
+        /// Wait out the service's start-up stagger first (used by start-up entries)
+        #[arg(long, conflicts_with = "at")]
+        startup: bool,
+        /// Open it in Windows Terminal, with the service's `terminal_profile` if set
+        #[arg(long, conflicts_with = "startup")]
+        terminal: bool,
+        /// Start it even though it is already running, or is also a Windows service
+        #[arg(long, conflicts_with = "startup")]
+        force: bool,
+        /// Start only this service, not the depends_on services that aren't running
+        #[arg(long, conflicts_with = "startup")]
+        no_deps: bool,
+        /// Wait this long for the service's `lock` file instead of failing when it is held
+        #[arg(long, value_name = "DURATION", conflicts_with = "terminal")]
+        lock_timeout: Option<HumanDuration>,
+        /// Util only: also append its stdout to this file
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["terminal", "startup"])]
+        capture: Option<PathBuf>,
+        /// Arguments after `--`, appended to the service's `args` for this run
+        #[arg(last = true, conflicts_with = "startup", value_name = "ARGS")]
+        extra: Vec<String>,
+        #[command(flatten)]
+        bulk: BulkArgs,
+    },
+    /// Add a start-up entry for a service
+    Enable {
+        #[arg(required_unless_present_any = ["stdin", "tag", "all"])]
+        name: Option<String>,
+        /// Where to register it, instead of the service's or the settings' startup_backend
+        #[arg(long, value_enum)]
+        backend: Option<Backend>,
+        /// When one service fails, put back the start-up entries of those this run already changed
+        #[arg(long)]
+        rollback_on_failure: bool,
+        #[command(flatten)]
+        bulk: BulkArgs,
+    },
+    /// Remove the start-up entry of a service
+    Disable {
+        #[arg(required_unless_present_any = ["stdin", "tag", "all"])]
+        name: Option<String>,
+        /// Where to remove it from, instead of every backend svc registered it on
+        #[arg(long, value_enum)]
+        backend: Option<Backend>,
+        /// When one service fails, put back the start-up entries of those this run already changed
+        #[arg(long)]
+        rollback_on_failure: bool,
+        #[command(flatten)]
+        bulk: BulkArgs,
+    },
+    /// Show PIDs and start-up state of a service
+    Status {
+        #[arg(required_unless_present_any = ["stdin", "tag", "all", "pid"])]
+        name: Option<String>,
+        /// Show the configured service this process belongs to
+        #[arg(long, conflicts_with_all = ["name", "stdin", "tag", "all"])]
+        pid: Option<u64>,
+        /// Add memory, CPU and restart trends over the last day, sampled by `svc watch`
+        #[arg(long)]
+        trend: bool,
+        /// Report the service's own processes only, ignoring its depends_on
+        #[arg(long)]
+        no_deps: bool,
+        /// Report processes of this logon session only, or of all of them
+        #[arg(long, value_enum, default_value_t)]
+        session: SessionFilter,
+        /// With several services, order them by this instead of the config order
+        #[arg(long, value_enum)]
+        sort: Option<OrderKey>,
+        #[command(flatten)]
+        bulk: BulkArgs,
+    },
+    /// Show every configured service with its status and resource usage
+    List {
+        /// Order by this column instead of the config order
+        #[arg(long, value_enum)]
+        sort: Option<SortKey>,
+        /// Which config's services to show, instead of those the active config uses
+        #[arg(long, value_enum)]
+        scope: Option<Scope>,
+        /// Only services whose binary changed on disk since they started
+        #[arg(long)]
+        outdated: bool,
+    },
+    /// Kill every process running the service's executable
+    Kill {
+        #[arg(required_unless_present_any = ["stdin", "tag", "all", "pid"])]
+        name: Option<String>,
+        /// Kill only this process, provided it belongs to a configured service
+        #[arg(long, conflicts_with_all = ["name", "stdin", "tag", "all"])]
+        pid: Option<u64>,
+        /// Skip the confirmations for a protected service and for loosely matched processes, and
+        /// kill one that is also a Windows service
+        #[arg(long)]
+        force: bool,
+        /// Kill only the instance in this logon session, or in all of them
+        #[arg(long, value_enum, default_value_t)]
+        session: SessionFilter,
+        #[command(flatten)]
+        uptime: UptimeArgs,
+        #[command(flatten)]
+        bulk: BulkArgs,
+        /// With --all: dependents first, one at a time, within a deadline
+        #[command(flatten)]
+        stop_all: StopAllArgs,
+    },
+    /// Ask a service to exit, killing it only after its stop_timeout
+    Stop {
+        #[arg(required_unless_present_any = ["stdin", "tag", "all"])]
+        name: Option<String>,
+        /// Skip the confirmations for a protected service and for loosely matched processes
+        #[arg(long)]
+        force: bool,
+        /// Stop only the instance in this logon session, or in all of them
+        #[arg(long, value_enum, default_value_t)]
+        session: SessionFilter,
+        #[command(flatten)]
+        uptime: UptimeArgs,
+        #[command(flatten)]
+        bulk: BulkArgs,
+    },
+    /// Stop every configured service, dependents first, within one deadline
+    Down {
+        /// Skip the confirmations for protected services and loosely matched processes
+        #[arg(long)]
+        force: bool,
+        /// Stop only the instances in this logon session, or in all of them
+        #[arg(long, value_enum, default_value_t)]
+        session: SessionFilter,
+        #[command(flatten)]
+        stop_all: StopAllArgs,
+    },
+    /// Run the last bulk operation again for the services it didn't reach or that failed
+    ResumeLast,
+    /// Kill a service and start it again
+    Restart {
+        #[arg(required_unless_present = "outdated")]
+        name: Option<String>,
+        /// Restart every running service whose binary changed on disk since it started,
+        /// dependencies first, each once the one before is ready
+        #[arg(long, conflicts_with_all = ["name", "if_uptime_gt", "if_uptime_lt"])]
+        outdated: bool,
+        /// Skip the confirmations for a protected service and for loosely matched processes
+        #[arg(long)]
+        force: bool,
+        /// Wait until its CPU usage stays low before restarting
+        #[arg(long)]
+        when_idle: bool,
+        /// Restart right away, overriding --when-idle
+        #[arg(long)]
+        now: bool,
+        /// CPU share in percent of the whole machine below which it counts as idle
+        #[arg(long, default_value_t = 5.0, value_name = "PERCENT")]
+        idle_cpu: f64,
+        /// How long it has to stay below --idle-cpu
+        #[arg(long, default_value = "30s")]
+        idle_for: HumanDuration,
+        /// Give up waiting for idle after this long
+        #[arg(long, default_value = "1h")]
+        max_wait: HumanDuration,
+        /// How long the killed processes may take to exit before the restart fails
+        #[arg(long, default_value = "10s")]
+        timeout: HumanDuration,
+        /// Start the new instance and wait for its ready_pattern or health_check before stopping the old one
+        #[arg(long, conflicts_with_all = ["if_uptime_gt", "if_uptime_lt"])]
+        overlap: bool,
+        #[command(flatten)]
+        uptime: UptimeArgs,
+    },
+    /// Change priority or CPU affinity of a running service without restarting it
+    #[command(group(ArgGroup::new("change").required(true).multiple(true).args(["priority", "affinity"])))]
+    Adjust {
+        name: String,
+        #[arg(long, value_enum)]
+        priority: Option<Priority>,
+        /// CPU numbers the processes may run on, e.g. 0,1
+        #[arg(long, value_delimiter = ',')]
+        affinity: Option<Vec<u32>>,
+        /// Also write the values to services.yaml, so later starts use them
+        #[arg(long)]
+        persist: bool,
+    },
+    /// Relaunch services whenever they exit
+    Watch(WatchArgs),
+    /// Answer status queries and subscriptions on the \\.\pipe\svc named pipe
+    Serve {
+        /// Time between status polls shared by all clients
+        #[arg(long, default_value = "2s")]
+        interval: HumanDuration,
+        /// Subscriptions served at once; more are turned away
+        #[arg(long, default_value_t = 16)]
+        max_subscribers: usize,
+        /// Clients connected at once, subscribers included; more wait until one disconnects
+        #[arg(long, default_value_t = 64)]
+        max_connections: usize,
+        /// Time between memory and CPU samples shown by `svc status --trend`
+        #[arg(long, default_value = "5m")]
+        sample_interval: HumanDuration,
+    },
+    /// Check that every expected service is running
+    Check {
+        /// Format the summary for Nagios-style monitoring
+        #[arg(long)]
+        nagios: bool,
+        /// Count a running service as up even when its dependencies are down
+        #[arg(long)]
+        no_deps: bool,
+        /// Count a running service whose binary changed on disk since it started as degraded
+        #[arg(long)]
+        outdated: bool,
+        /// Extra services to check besides `restart: always` and `autostart: true` ones
+        names: Vec<String>,
+    },
+    /// Print the end of a service's log file
+    Logs {
+        name: String,
+        /// Lines to print
+        #[arg(default_value_t = 50)]
+        lines: usize,
+        /// Keep printing what the service writes, until Ctrl+C
+        #[arg(short, long)]
+        follow: bool,
+    },
+    /// Print the command line `svc run` would start for a service
+    Which { name: String },
+    /// Print the env the config passes a service, or what a running process actually has
+    Env {
+        name: String,
+        /// Read the environment block of the running process
+        #[arg(long)]
+        live: bool,
+        /// Read this one of the service's processes instead of the first
+        #[arg(long, requires = "live")]
+        pid: Option<u64>,
+        /// Compare each configured variable with the running process's
+        #[arg(long, requires = "live")]
+        diff_config: bool,
+        /// What a logon launch gets: the variables enable captured, then the configured env
+        #[arg(long, conflicts_with = "live")]
+        startup: bool,
+    },
+    /// Show what svc keeps on disk for each service, against its disk_budget
+    Du {
+        /// Services to show; every configured one without names
+        names: Vec<String>,
+        /// Prune services over their budget first, as svc does after it writes for them
+        #[arg(long)]
+        enforce: bool,
+    },
+    /// Print one line of up, down and stopped counts, for shell prompts
+    Summary {
+        /// Also count services without a start-up entry, which reads the registry
+        #[arg(long)]
+        with_startup: bool,
+        /// Template with {up}, {down}, {stopped}, {disabled} and {total}
+        #[arg(long)]
+        format: Option<String>,
+    },
+    /// Rewrite services.yaml in canonical form
+    Fmt {
+        /// Only report whether the file would change
+        #[arg(long)]
+        check: bool,
+        /// Rewrite a config that has comments, which the canonical form can't keep
+        #[arg(long, conflicts_with = "check")]
+        drop_comments: bool,
+    },
+    /// Start a binary that isn't in the config, tracked in the adhoc registry
+    RunAdhoc {
+        #[arg(long)]
+        path: String,
+        /// Defaults to the file name without extension
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long)]
+        work_at: Option<String>,
+        /// Run it as a Util, waiting for it to finish
+        #[arg(long)]
+        util: bool,
+        #[arg(long, requires = "util")]
+        interpreter: Option<String>,
+        /// Write stdout and stderr to a log in the state directory
+        #[arg(long)]
+        log: bool,
+    },
+    /// Move an adhoc service into services.yaml
+    Promote { name: String },
+    /// Append a service to the config, checked to load before it is written
+    Add {
+        name: String,
+        /// The executable, or the script a Util runs
+        #[arg(long)]
+        path: String,
+        #[arg(long = "type", value_enum, default_value_t)]
+        service_type: ServiceKind,
+        /// What runs the script, default python
+        #[arg(long)]
+        interpreter: Option<String>,
+        /// Working directory, defaults to the directory of the path
+        #[arg(long, value_name = "DIR")]
+        at: Option<String>,
+    },
+    /// Change one field of a service's entry, leaving the rest of the file as is
+    Set {
+        name: String,
+        field: String,
+        /// Read as YAML, e.g. true, 10s or [a, b]; null removes the field
+        value: String,
+    },
+    /// Drop a stopped service from the config, listing what svc keeps for it
+    Remove {
+        name: String,
+        /// Also delete its state files, history, start-up entry and firewall rule
+        #[arg(long)]
+        purge: bool,
+        /// Remove it even while it runs or starts at logon
+        #[arg(long)]
+        force: bool,
+    },
+    /// Delete what svc keeps for services no config defines any more
+    Gc {
+        /// Delete without asking
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// Clear what a crashed svc left behind: PID files of processes that are gone, abandoned locks
+    Recover,
+    /// Drop an adhoc service from the registry, leaving its processes alone
+    Forget { name: String },
+    /// Rename a service, moving its start-up entry along with it
+    Rename { old: String, new: String },
+    /// Find a moved binary by its file name and point the service at it
+    Relocate {
+        name: String,
+        /// Directory to search instead of the usual install roots; may be repeated
+        #[arg(long)]
+        search: Vec<PathBuf>,
+        /// Take the n-th candidate without asking
+        #[arg(long)]
+        pick: Option<usize>,
+        /// Restart the service on the new binary if it is running
+        #[arg(long)]
+        restart: bool,
+    },
+    /// Bundle the config, start-up entries, secret references and history into a zip
+    Backup { file: PathBuf },
+    /// Re-create the state saved by `svc backup`
+    Restore { file: PathBuf },
+    /// Copy a service, svc and a services.yaml of just its entry into a folder that runs anywhere
+    Bundle {
+        name: String,
+        /// The folder to create; it must not exist or must be empty
+        #[arg(long, value_name = "DIR")]
+        out: PathBuf,
+        /// Copy the whole directory of its binary or script, not just the file
+        #[arg(long)]
+        whole_dir: bool,
+    },
+    /// Print a script that starts a service without svc or the registry, for another autostart
+    ExportLauncher {
+        name: String,
+        #[arg(long, value_enum, default_value_t)]
+        style: LauncherStyle,
+        /// Write it to this file instead of printing it
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+    /// Enable and disable start-up entries to match each service's `startup` field
+    ApplyStartup {
+        /// When one change fails, put back those already made
+        #[arg(long)]
+        rollback_on_failure: bool,
+    },
+    /// Classify every start-up entry svc manages: ok, unquoted, stale-target, arch-mismatch or foreign-collision
+    AuditStartup {
+        /// Rewrite unquoted and stale entries; ambiguous ones are left alone with an explanation
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Show what this account lets svc do: read and write the Run key, list and open processes
+    Doctor,
+    /// List every error code svc reports, grouped, with what it means
+    Errors,
+    /// Converge the config, start-up entries and running services on a desired-state document
+    Apply {
+        /// The JSON document
+        #[arg(short, long, value_name = "FILE", required_unless_present = "stdin", conflicts_with = "stdin")]
+        file: Option<PathBuf>,
+        /// Read the document from stdin
+        #[arg(long)]
+        stdin: bool,
+        /// Remove services the document doesn't list from the config
+        #[arg(long)]
+        prune: bool,
+        /// Stop and remove protected services without asking
+        #[arg(long)]
+        force: bool,
+    },
+    /// Load the config and check the binaries and directories it points to
+    Validate {
+        /// Only check the config itself, without touching the filesystem
+        #[arg(long)]
+        offline: bool,
+        /// How long one path may take before it is reported as unknown
+        #[arg(long, default_value = "5s")]
+        timeout: HumanDuration,
+        /// Also verify Authenticode signatures of the binaries
+        #[arg(long)]
+        signatures: bool,
+    },
+    /// Print the config in canonical form
+    Export {
+        /// As this machine sees it: templates expanded and hosts overrides applied
+        #[arg(long)]
+        resolve: bool,
+    },
+    /// Print a JSON Schema of services.yaml for editors and the YAML language server
+    Schema,
+    /// Create a commented starter config in %APPDATA%\\svc, or at --config
+    Init,
+    /// Show how this boot's start-up entries went: delays, spawn latency and readiness
+    StartupReport,
+    /// Maintain the config file itself
+    #[command(subcommand)]
+    Config(ConfigCmd),
+    /// Manage the DPAPI secret store
+    #[command(subcommand)]
+    Secret(SecretCmd),
+    /// Stop running services gracefully when Windows logs off or shuts down
+    #[command(subcommand)]
+    ShutdownHook(ShutdownHookCmd),
+    /// Keep the inbound Windows Firewall rule a service's `firewall` block asks for
+    #[command(subcommand)]
+    Firewall(FirewallCmd),
+    /// Print the version; with --json, everything this build supports
+    Version,
+    /// Show help for a command or a topic (config-file, units, exit-codes, startup, desired-state)
+    Help { topic: Option<String> },
+}
+
+#[derive(Debug, Args)]
+pub struct WatchArgs {
+    /// Keep watching in a detached process without a console window
+    #[arg(long, conflicts_with_all = ["stop", "status"])]
+    pub background: bool,
+    /// Stop the background watcher
+    #[arg(long, conflicts_with = "status")]
+    pub stop: bool,
+    /// Report whether a background watcher is running
+    #[arg(long)]
+    pub status: bool,
+    /// Kill services that are removed from the config while watching [default: kill_removed in the config]
+    #[arg(long)]
+    pub kill_removed: bool,
+    /// Leave services removed from the config running, whatever kill_removed says
+    #[arg(long, conflicts_with = "kill_removed")]
+    pub no_kill_removed: bool,
+    /// Kill the relaunched services when watching is interrupted with Ctrl+C
+    #[arg(long, conflicts_with = "background")]
+    pub kill_on_exit: bool,
+    /// Poll at this fixed interval instead of adapting between --min-interval and --max-interval
+    #[arg(long, conflicts_with_all = ["min_interval", "max_interval"])]
+    pub interval: Option<HumanDuration>,
+    /// Time between polls for a while after a service starts, stops or the config changes
+    #[arg(long, default_value = "1s")]
+    pub min_interval: HumanDuration,
+    /// Time between polls once nothing has changed for a while
+    #[arg(long, default_value = "30s")]
+    pub max_interval: HumanDuration,
+    /// Rotate the background watcher's log file at this size
+    #[arg(long, default_value = "10M")]
+    pub log_max_size: ByteSize,
+    /// Time between memory and CPU samples shown by `svc status --trend`
+    #[arg(long, default_value = "5m")]
+    pub sample_interval: HumanDuration,
+    #[arg(long, hide = true)]
+    pub background_child: bool,
+    /// Services to watch; defaults to every service with `restart: always` or `idle_stop`
+    pub names: Vec<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCmd {
+    /// Inspect or resolve a config write that was interrupted
+    Recover {
+        /// Which version to keep; without it both are shown
+        #[arg(long, value_enum)]
+        keep: Option<RecoverChoice>,
+    },
+    /// Move the services.yaml next to svc.exe to %APPDATA%\svc, where svc looks first
+    Migrate,
+    /// Compare the per-user config with the one next to svc.exe, entry by entry
+    Diff,
+}
+
+// A service's type as `svc add --type` spells it
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum ServiceKind {
+    /// Started in the background
+    #[default]
+    Executable,
+    /// A script run by its interpreter and waited on
+    Util,
+}
+
+// What `svc export-launcher` writes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LauncherStyle {
+    /// A batch file; it starts the service minimized, as its own window can't be hidden
+    #[default]
+    Cmd,
+    /// A PowerShell script starting the service with its window hidden
+    Ps1,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum RecoverChoice {
+    /// The config file as it is now
+    Current,
+    /// The temp file the interrupted write left behind
+    Pending,
+}
+
+// Several services at once instead of the one named on the command line
+#[derive(Debug, Clone, Default, Args)]
+pub struct BulkArgs {
+    /// Act on each service named on standard input
+    #[arg(long, conflicts_with_all = ["name", "tag", "all"])]
+    pub stdin: bool,
+    /// How the names on standard input are written
+    #[arg(long, value_enum, default_value_t, requires = "stdin")]
+    pub stdin_format: StdinFormat,
+    /// Act on each service with this tag; repeat it for services with any of several
+    #[arg(long, value_name = "TAG", conflicts_with_all = ["name", "all"])]
+    pub tag: Vec<String>,
+    /// Act on every configured service
+    #[arg(long, conflicts_with = "name")]
+    pub all: bool,
+}
+
+// How `svc down` and `svc kill --all` go through the services
+#[derive(Debug, Clone, Default, Args)]
+pub struct StopAllArgs {
+    /// Time for stopping all of them; a stop_signal's grace is cut short to fit [default: 30s]
+    #[arg(long)]
+    pub deadline: Option<HumanDuration>,
+    /// Leave the remaining services alone once one does not stop
+    #[arg(long)]
+    pub halt_on_failure: bool,
+    /// Terminate whatever still runs at the deadline instead of reporting it
+    #[arg(long)]
+    pub force_at_deadline: bool,
+}
+
+impl StopAllArgs {
+    pub fn given(&self) -> bool {
+        self.deadline.is_some() || self.halt_on_failure || self.force_at_deadline
+    }
+}
+
+// Which of a service's processes `svc kill`, `svc stop` and `svc restart` act on, by how long
+// each has been up; the others are left alone and reported as skipped
+#[derive(Debug, Clone, Default, Args)]
+pub struct UptimeArgs {
+    /// Act only on processes up for longer than this, e.g. 1h
+    #[arg(long, value_name = "DURATION")]
+    pub if_uptime_gt: Option<HumanDuration>,
+    /// Act only on processes up for less than this, e.g. 5m to catch one that's crash-looping
+    #[arg(long, value_name = "DURATION")]
+    pub if_uptime_lt: Option<HumanDuration>,
+}
+
+impl UptimeArgs {
+    pub fn given(&self) -> bool {
+        self.if_uptime_gt.is_some() || self.if_uptime_lt.is_some()
+    }
+}
+
+// Which services a bulk command acts on
+#[derive(Debug, Clone)]
+pub enum Selection {
+    Stdin(StdinFormat),
+    Tags(Vec<String>),
+    All,
+    // What `svc resume-last` picked up from a checkpoint
+    Names(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum StdinFormat {
+    /// One name per line; blank lines and #-comments are skipped
+    #[default]
+    Lines,
+    /// A JSON array of names
+    Json,
+}
+
+impl Cmd {
+    // Set when the command should run once per name piped in
+    pub fn selection(&self) -> Option<Selection> {
+        let (Cmd::Run { bulk, .. }
+        | Cmd::Enable { bulk, .. }
+        | Cmd::Disable { bulk, .. }
+        | Cmd::Status { bulk, .. }
+        | Cmd::Kill { bulk, .. }
+        | Cmd::Stop { bulk, .. }) = self
+        else {
+            return None;
+        };
+        if bulk.stdin {
+            Some(Selection::Stdin(bulk.stdin_format))
+        } else if !bulk.tag.is_empty() {
+            Some(Selection::Tags(bulk.tag.clone()))
+        } else {
+            bulk.all.then_some(Selection::All)
+        }
+    }
+
+    // What the command can't do without, checked before it changes anything; start-up writes
+    // are checked where the backend is known
+    pub fn capabilities(&self) -> Vec<Capability> {
+        match self {
+            Cmd::Stop { .. } | Cmd::Kill { .. } | Cmd::Down { .. } | Cmd::Restart { .. } => {
+                vec![Capability::ProcessList, Capability::ProcessOpen]
+            }
+            Cmd::Apply { .. } if !effects::dry_run() => vec![Capability::ProcessList, Capability::ProcessOpen],
+            Cmd::Watch(args) if args.stop || args.status => Vec::new(),
+            Cmd::Run { .. } | Cmd::Status { .. } | Cmd::List { .. } | Cmd::Watch(_) | Cmd::Apply { .. } => {
+                vec![Capability::ProcessList]
+            }
+            Cmd::AuditStartup { .. } => vec![Capability::StartupRead],
+            _ => Vec::new(),
+        }
+    }
+
+    // The name of a command that changes something, for refusing it in read-only mode
+    pub fn mutation(&self) -> Option<&'static str> {
+        Some(match self {
+            Cmd::Run { .. } => "run",
+            Cmd::Enable { .. } => "enable",
+            Cmd::Disable { .. } => "disable",
+            Cmd::Kill { .. } => "kill",
+            Cmd::Stop { .. } => "stop",
+            Cmd::Down { .. } => "down",
+            Cmd::ResumeLast => "resume-last",
+            Cmd::Restart { .. } => "restart",
+            Cmd::Adjust { .. } => "adjust",
+            Cmd::Watch(args) if !args.status => "watch",
+            Cmd::Fmt { check: false, .. } => "fmt",
+            Cmd::Init => "init",
+            Cmd::RunAdhoc { .. } => "run-adhoc",
+            Cmd::Promote { .. } => "promote",
+            Cmd::Add { .. } => "add",
+            Cmd::Set { .. } => "set",
+            Cmd::Remove { .. } => "remove",
+            Cmd::Gc { .. } => "gc",
+            Cmd::Recover => "recover",
+            Cmd::Forget { .. } => "forget",
+            Cmd::Rename { .. } => "rename",
+            Cmd::Relocate { .. } => "relocate",
+            Cmd::Restore { .. } => "restore",
+            Cmd::Bundle { .. } => "bundle",
+            Cmd::ExportLauncher { out: Some(_), .. } => "export-launcher",
+            Cmd::ApplyStartup { .. } => "apply-startup",
+            Cmd::AuditStartup { fix: true } => "audit-startup",
+            Cmd::Apply { .. } => "apply",
+            Cmd::Config(ConfigCmd::Recover { keep: Some(_) }) => "config recover",
+            Cmd::Config(ConfigCmd::Migrate) => "config migrate",
+            Cmd::Du { enforce: true, .. } => "du --enforce",
+            Cmd::Secret(SecretCmd::Set { .. }) => "secret set",
+            Cmd::Secret(SecretCmd::Remove { .. }) => "secret remove",
+            Cmd::ShutdownHook(ShutdownHookCmd::Install) => "shutdown-hook install",
+            Cmd::ShutdownHook(ShutdownHookCmd::Uninstall) => "shutdown-hook uninstall",
+            Cmd::Firewall(FirewallCmd::Apply { .. }) => "firewall apply",
+            _ => return None,
+        })
+    }
+}
+
+// Which logon sessions' processes count, on machines with several sessions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SessionFilter {
+    /// Only processes in the session svc runs in
+    Current,
+    /// Processes in any session
+    #[default]
+    All,
+}
+
+// Where `svc list` takes services from when a workspace's .svc.yaml is active
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Scope {
+    /// Only the services the .svc.yaml defines itself
+    Workspace,
+    /// The services of the global services.yaml, in %APPDATA%\\svc or next to svc.exe
+    Global,
+    /// The workspace's services and every global one
+    All,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SortKey {
+    Name,
+    /// Working set, largest first
+    Memory,
+    /// CPU usage, busiest first
+    Cpu,
+    /// Longest running first
+    Uptime,
+    /// Most processes first
+    Pids,
+    /// Running first, then degraded, then down
+    State,
+}
+
+// How output about several services is ordered when not in the config's order
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum OrderKey {
+    Name,
+    /// Running first, then degraded, then down
+    State,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ShutdownHookCmd {
+    /// Let `svc watch` and `svc serve` hold the session end while they stop services
+    Install,
+    /// Remove the hook; running watch and serve processes stop acting on it at once
+    Uninstall,
+    /// Show whether the hook is installed and which process holds it
+    Status,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum FirewallCmd {
+    /// Create or replace the rule "svc: <name>" for the service's executable
+    Apply {
+        name: String,
+        /// Delete the rule instead
+        #[arg(long)]
+        remove: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SecretCmd {
+    /// Store a secret read from stdin
+    Set { key: String },
+    /// Delete a stored secret
+    Remove { key: String },
+}
+
+// Invocation examples per subcommand, rendered into each command's long help
+pub const EXAMPLES: &[(&str, &str, &str)] = &[
+    ("run", "svc run MyServer", "start a service in the background"),
+    ("run", "svc run js", "run a utility and wait for it to finish"),
+    ("run", r#"svc run js --capture js.log && echo done"#, "tee its stdout to js.log, going on only if it exits with 0"),
+    ("run", r#"svc run MyTool at "D:\""#, "run with a different working directory"),
+    ("run", r#"svc run MyTool --at "D:\""#, "the same, spelled as a flag"),
+    ("run", r#"svc run MyServer at logs --force"#, "start a second instance in .\\logs"),
+    ("run", "svc run repl --terminal", "open an interactive utility in Windows Terminal"),
+    ("run", "svc run MyServer -- --port 8081", "append arguments to the configured args for this run"),
+    ("run", "svc run MyServer --no-deps", "start it without starting its depends_on services first"),
+    ("run", "svc run --tag work", "start every service tagged work, reporting each one"),
+    ("run", "svc run nightly --lock-timeout 5m", "wait for another holder of its lock file instead of failing"),
+    ("enable", "svc enable MyServer", "start MyServer at logon"),
+    ("enable", "svc enable MyServer --backend scheduled-task", "start it through a logon task instead"),
+    ("enable", "svc enable --tag work --rollback-on-failure", "enable them all, or none if one fails"),
+    ("disable", "svc disable MyServer", "stop starting MyServer at logon"),
+    ("disable", "svc disable MyServer --backend startup-folder", "remove its Startup folder shortcut"),
+    ("status", "svc status MyServer", "show PIDs and start-up state"),
+    ("status", "svc --json status MyServer", "report errors as JSON"),
+    ("status", "svc status --trend MyServer", "include memory and restarts over the last day"),
+    ("status", "svc status --all", "show the status of every configured service"),
+    ("status", "svc status --all --sort state", "running services first, each group in config order"),
+    ("status", "svc status --pid 4312", "find the configured service a process belongs to"),
+    ("status", "svc --host LAPTOP status MyServer", "show MyServer as the hosts section sets it up on LAPTOP"),
+    ("status", "svc status --session current MyServer", "only processes in this logon session, e.g. over RDP"),
+    ("status", "svc status --no-deps MyServer", "ignore depends_on, report only MyServer's processes"),
+    ("list", "svc list", "show all services in config order"),
+    ("list", "svc list --sort memory", "largest working set first"),
+    ("list", "svc --json list", "print the status of every service as JSON"),
+    ("list", "svc list --scope all", "inside a workspace, show its services and every global one"),
+    ("list", "svc list --outdated", "only services whose binary changed on disk since they started"),
+    ("list", r"svc --config D:\dotfiles\services.yaml list", "use this config instead of searching for one"),
+    ("kill", "svc kill MyServer", "kill every process of MyServer"),
+    ("kill", "svc kill --force MyDatabase", "kill a protected service without confirming"),
+    ("stop", "svc stop MyServer", "close MyServer's windows or raise its stop_signal, kill it after stop_timeout"),
+    ("stop", "svc --json --progress-events stop MyServer", "a JSON line per phase of the stop, then the result"),
+    ("stop", "type names.txt | svc stop --stdin", "stop each service named on standard input"),
+    ("kill", "type names.txt | svc kill --stdin", "kill each service named on standard input"),
+    ("kill", "svc kill --tag work", "kill every service tagged work"),
+    ("kill", "svc kill --pid 4312", "kill one process, if it belongs to a configured service"),
+    ("kill", "svc kill --elevate MyServer", "relaunch elevated through UAC if MyServer runs elevated"),
+    ("kill", "svc kill --tag work --if-uptime-lt 5m", "kill only the ones that keep crashing and coming back"),
+    ("env", "svc env MyServer --live", "print the environment MyServer's process actually has"),
+    ("env", "svc env MyServer --live --diff-config", "configured variables that differ in the running process"),
+    ("env", "svc env MyServer --startup", "the variables a logon launch passes MyServer"),
+    ("kill", "svc kill --all --deadline 1m", "kill everything dependents first, as svc down does"),
+    ("down", "svc down", "stop every service, dependents first, reporting any that would not stop"),
+    ("down", "svc down --deadline 10s --force-at-deadline", "terminate whatever is left after ten seconds"),
+    ("down", "svc --json down --halt-on-failure", "stop at the first stuck service, report as JSON"),
+    ("resume-last", "svc resume-last", "finish an interrupted svc kill --all: only what it didn't reach or failed"),
+    ("restart", "svc restart MyServer", "kill MyServer and start it again"),
+    ("restart", "svc restart MyServer --when-idle --idle-for 2m", "wait for two quiet minutes first"),
+    ("restart", "svc restart MyServer --overlap", "start the new instance and wait until it is ready, then stop the old"),
+    ("restart", "svc restart --outdated", "restart whatever still runs a binary that was replaced on disk"),
+    ("restart", "svc restart MyServer --if-uptime-gt 1h", "restart it only if it has been up for more than an hour"),
+    ("restart", "svc --debug-bundle report.zip restart MyServer", "collect what happened for a bug report, secrets redacted"),
+    ("status", "svc --read-only status MyServer", "inspect on a shared machine; run, kill and the like are refused"),
+    ("status", "svc --safe status MyServer", "skip svc's own state, history and notifications when one is broken"),
+    ("status", "svc --lenient status --all", "leave out broken service entries instead of failing"),
+    ("watch", "svc watch MyServer MyTool", "restart both whenever they exit"),
+    ("watch", "svc watch --background", "watch `restart: always` and `idle_stop` services without a console"),
+    ("watch", "svc watch --kill-on-exit MyServer", "relaunch MyServer until Ctrl+C, then kill it"),
+    ("watch", "svc watch --stop", "stop the background watcher"),
+    ("watch", "svc watch --interval 30s MyServer", "poll every 30 seconds, however busy it gets"),
+    ("watch", "svc watch --min-interval 2s --max-interval 1m", "poll every 2s after a change, slowing to once a minute"),
+    ("serve", "svc serve", "serve status over the named pipe for dashboards"),
+    ("version", "svc --json version", "list commands, flags and config fields this build supports"),
+    ("doctor", "svc doctor", "show what this account and this edition of Windows can do, and what needs it"),
+    ("errors", "svc --json errors", "every error code with its group and description, for scripts"),
+    ("export", "svc export --resolve", "print the services as this machine runs them, templates expanded"),
+    ("schema", "svc schema > services.schema.json", "JSON Schema for validating services.yaml in an editor"),
+    ("startup-report", "svc startup-report", "which start-up entries of this boot were slowest to come up"),
+    ("apply-startup", "svc apply-startup --dry-run", "list the start-up entries that would change"),
+    ("apply-startup", "svc apply-startup", "enable and disable entries to match the config"),
+    ("apply-startup", "svc apply-startup --rollback-on-failure", "undo the changes made when one fails"),
+    ("audit-startup", "svc audit-startup", "classify every start-up entry svc manages"),
+    ("audit-startup", "svc audit-startup --fix", "rewrite unquoted and stale entries"),
+    ("apply", "svc apply -f desired.json --dry-run", "show what the desired-state document would change"),
+    (
+        "apply",
+        "curl -s https://fleet/desired.json | svc --json apply --stdin --prune",
+        "converge on a fetched document, unlisted services removed",
+    ),
+    ("adjust", "svc adjust MyServer --priority below_normal", "lower the priority of every running process"),
+    ("adjust", "svc adjust MyServer --affinity 0,1 --persist", "pin to CPUs 0 and 1, now and on later starts"),
+    ("validate", "svc validate", "check the config and every path it points to"),
+    ("validate", "svc validate --offline", "check the config alone, unknown fields included, e.g. before deploying it"),
+    ("validate", "svc validate --signatures --timeout 10s", "also verify signatures; slow shares report unknown"),
+    ("check", "svc check", "exit 0 if everything expected is running"),
+    ("check", "svc check --nagios MyTool", "Nagios-style summary including MyTool"),
+    ("check", "svc check --no-deps", "don't count services with a dependency down as degraded"),
+    ("check", "svc check --outdated", "also count services running a replaced binary as degraded"),
+    ("logs", "svc logs MyServer", "the last 50 lines MyServer wrote to its log"),
+    ("logs", "svc logs MyServer 200 -f", "the last 200 lines, then follow"),
+    ("which", "svc which backup", "show the full command line, shell and flags included"),
+    ("which", "svc --host BUILD-01 which backup", "the same as BUILD-01's shells and hosts entries set it up"),
+    ("du", "svc du", "what svc keeps for each service, against its disk_budget"),
+    ("du", "svc du MyServer --enforce --verbose", "prune MyServer to its budget and list what is left"),
+    ("summary", "svc summary", "counts for a prompt, exit code 1 when something is down"),
+    ("summary", r#"svc summary --format "{up}/{total}""#, "just the counts, in your own layout"),
+    ("fmt", "svc fmt", "rewrite services.yaml in canonical form"),
+    ("fmt", "svc fmt --check", "fail if services.yaml isn't canonical"),
+    ("fmt", "svc fmt --drop-comments", "rewrite it even though its comments go"),
+    ("rename", "svc rename MyServer MyWebServer", "rename a service"),
+    ("rename", "svc --dry-run rename MyServer MyWebServer", "list what renaming would write and move, changing nothing"),
+    ("add", r"svc add MyServer --path D:\server.exe", "append an Executable to the config"),
+    ("add", r"svc add backup --type util --path D:\backup.py --at D:\data", "append a Util that runs in D:\\data"),
+    ("set", "svc set MyServer restart always", "change one field, keeping everything else in the file"),
+    ("set", "svc set MyServer depends_on [MyDatabase]", "values are YAML; `null` removes the field"),
+    ("remove", "svc remove MyServer --dry-run", "show what removing a service would leave behind"),
+    ("remove", "svc remove MyServer --purge", "remove a service along with everything svc kept for it"),
+    ("remove", "svc remove MyServer --force", "remove it even though it is running or enabled"),
+    ("gc", "svc gc --dry-run", "list what is kept for services that no longer exist"),
+    ("gc", "svc gc --yes", "delete it without asking"),
+    ("recover", "svc recover", "clear the PID files and locks of an svc that crashed"),
+    ("run-adhoc", r"svc run-adhoc --path C:\tools\thing.exe --work-at C:\tools --log", "try a tool without adding it to the config"),
+    ("promote", "svc promote thing", "keep an adhoc service by moving it into services.yaml"),
+    ("forget", "svc forget thing", "drop an adhoc service from the registry"),
+    ("shutdown-hook", "svc shutdown-hook install", "stop services gracefully on logoff and shutdown"),
+    ("shutdown-hook", "svc shutdown-hook status", "show whether a watch or serve process holds the hook"),
+    ("relocate", "svc relocate MyServer", "list binaries named like MyServer's elsewhere and pick one"),
+    ("relocate", "svc relocate MyServer --search D:\\apps --pick 1 --restart", "switch to the newest one and restart"),
+    ("backup", r"svc backup D:\svc-backup.zip", "save everything svc manages"),
+    ("restore", r"svc restore --dry-run D:\svc-backup.zip", "show what a restore would change"),
+    ("bundle", r"svc bundle MyServer --out D:\handover", "a folder that runs MyServer wherever it is copied"),
+    ("bundle", r"svc bundle MyServer --out D:\handover --whole-dir", "bring the files next to its binary too"),
+    ("export-launcher", r"svc export-launcher MyServer --out D:\autostart\MyServer.cmd", "start it at logon without the registry"),
+    ("export-launcher", "svc export-launcher MyServer --style ps1", "a PowerShell script that hides its window"),
+    ("config", "svc config recover", "compare the config with an interrupted write"),
+    ("config", "svc config recover --keep pending", "finish the interrupted write"),
+    ("config", "svc config migrate", r"move the services.yaml next to svc.exe to %APPDATA%\svc"),
+    ("config", "svc config diff", "compare the per-user config with the one next to svc.exe"),
+    ("secret", "svc secret set svcaccount-password", "store a secret for run_as"),
+    ("firewall", "svc firewall apply MyServer", "allow MyServer's inbound ports through Windows Firewall"),
+    ("firewall", "svc firewall apply MyServer --remove", "delete the rule again"),
+    ("help", "svc help config-file", "describe the config file format"),
+    ("init", "svc init", "create a commented starter config in %APPDATA%\\svc"),
+];
+
+const TOPICS: &[(&str, &str, &str)] = &[
+    (
+        "config-file",
+        "config file format",
+        "svc reads the file --config or SVC_CONFIG names; otherwise a workspace's\n\
+         .svc.yaml, then services.yaml in the current directory, in %APPDATA%\\svc\n\
+         and next to svc.exe, whichever is found first. 'svc init' creates a\n\
+         commented starter, and 'svc config migrate' moves one next to svc.exe\n\
+         to %APPDATA%\\svc. It is a list of services:\n\n\
+         - name: MyServer        # unique name used on the command line\n\
+         \x20 type: Executable     # Executable or Util\n\
+         \x20 path: D:\\server.exe\n\
+         \x20 work_at: D:\\dir      # optional, defaults to the file's directory\n\
+         \x20 interpreter: python  # Util only, default \"python\"\n\
+         \x20 shell: pwsh          # Util only, an entry of shells instead of interpreter\n\
+         \x20 args: [-c, frpc.toml] # optional, passed after the path or script\n\
+         \x20 log: true            # optional, Executable only, output to logs\\<name>.log\n\
+         \x20                      # next to the exe; or a path, see `svc logs`\n\
+         \x20 env:                 # optional, set for the process; %VAR% and ${VAR} expand\n\
+         \x20   RUST_LOG: debug\n\
+         \x20   DATA_DIR: '%APPDATA%\\app'\n\
+         \x20 restart: always      # optional, relaunched by `svc watch`\n\
+         \x20 restart_delay: 10s   # optional, wait before relaunching, doubled while it\n\
+         \x20                       # keeps exiting within 10 minutes (up to 5m)\n\
+         \x20 restart_backoff:     # optional, backs off relaunches in place of doubling,\n\
+         \x20   attempts: 5        # the fields of retry; then `svc watch` leaves it down\n\
+         \x20 max_restarts_per_hour: 20  # optional, then `svc watch` leaves it down\n\
+         \x20 autostart: true      # optional, expected to run by `svc check`\n\
+         \x20 capture_stdout: true # optional, Util only\n\
+         \x20 encoding: utf8       # optional, utf8, oem or a code page; how output is decoded\n\
+         \x20 chcp: true           # optional, Util under cmd with utf8: chcp 65001 first\n\
+         \x20 temp_work_dir: true  # optional, Util only, fresh %TEMP%\\svc directory per run\n\
+         \x20 retry:               # optional, Util only, run again when it exits with an error\n\
+         \x20   attempts: 3        # tries after the first, default 3\n\
+         \x20   delay: 2s          # before the first retry, default 1s\n\
+         \x20   multiplier: 2      # each wait this many times the last, default 2\n\
+         \x20   max_delay: 30s     # optional, no wait longer than this\n\
+         \x20   jitter: true       # optional, shorten each wait at random by up to half\n\
+         \x20 protected: true      # optional, kill asks for confirmation\n\
+         \x20 label: My Server     # optional, display name in status, list and watch logs\n\
+         \x20 tags: [work]         # optional, selected together by --tag work\n\
+         \x20 startup_order: 1     # optional, position in the start-up stagger\n\
+         \x20 startup_backend: scheduled-task  # optional, over the setting\n\
+         \x20 elevated: true       # optional, scheduled-task only: highest privileges\n\
+         \x20 logon_delay: 30s     # optional, start-up entry waits this long\n\
+         \x20 startup_env_allowlist: [SDK_ROOT] # optional, captured by enable for logon\n\
+         \x20 disk_budget: 200M   # optional, most svc keeps for it; see `svc du`\n\
+         \x20 terminal_profile: PowerShell # optional, profile used by `run --terminal`\n\
+         \x20 depends_on: [MyDatabase] # optional, started first by run; degraded while down\n\
+         \x20 steps:              # optional, run in order before the service itself\n\
+         \x20   - type: Util       # each with path, type, interpreter and args\n\
+         \x20     path: migrate.py\n\
+         \x20     continue_on_error: true # optional, a failure doesn't stop the run\n\
+         \x20 per_session: true    # optional, each logon session may run its own instance\n\
+         \x20 priority: below_normal # optional, idle .. realtime, set when it starts\n\
+         \x20 affinity: [0, 1]     # optional, CPUs it may run on\n\
+         \x20 startup: enabled     # optional, enabled or disabled, see `svc apply-startup`\n\
+         \x20 stop_signal:         # optional, ask it to exit before kill terminates it\n\
+         \x20   file: stop.flag    # created on kill; or `event: Local\\myapp-stop`\n\
+         \x20 stop_grace: 10s      # optional, how long kill waits on stop_signal\n\
+         \x20 stop_timeout: 30s    # optional, how long `svc stop` waits, default 10s\n\
+         \x20 ready_pattern: Listening on  # optional, log text meaning up, for restart --overlap\n\
+         \x20 health_check: http://127.0.0.1:8080/health  # optional, or tcp://host:port\n\
+         \x20 port: 8080           # optional, TCP port it listens on, checked by validate and status\n\
+         \x20 ready_timeout: 1m    # optional, how long --overlap waits for ready, default 30s\n\
+         \x20 idle_stop:           # optional, `svc watch` stops it once idle this long\n\
+         \x20   after: 30m\n\
+         \x20   metric: cpu        # cpu (cpu_below, default 5%) or no_window_focus\n\
+         \x20 lock: D:\\locks\\job.lock # optional, held while it runs; see --lock-timeout\n\
+         \x20 firewall:            # optional, Executable only, see `svc firewall apply`\n\
+         \x20   inbound_ports: [8443] # every port when left out\n\
+         \x20   protocol: tcp      # tcp or udp, default tcp\n\
+         \x20 run_as:              # optional, Executable only\n\
+         \x20   user: .\\account\n\
+         \x20   password_secret: key-in-secret-store\n\n\
+         Settings need the mapping form, with the list under `services`:\n\n\
+         startup_stagger: 5s     # delay start-up entries by startup_order × this\n\
+         history_output_lines: 20 # output kept per failed history record, default 100\n\
+         transient_retry:        # registry and WMI queries that fail in passing, default\n\
+         \x20 attempts: 4          # 2 retries from 250ms; the fields of a Util's retry\n\
+         lenient: true           # skip broken service entries instead of failing, as --lenient\n\
+         disk_budget: 1G         # disk_budget of services without their own\n\
+         startup_backend: run-key # where enable and disable go without --backend\n\
+         shells:                 # command lines Utils name with `shell`, script appended\n\
+         \x20 pwsh: C:\\Program Files\\PowerShell\\7\\pwsh.exe -NoProfile -File\n\
+         \x20 py:                   # or per machine, by computer name\n\
+         \x20   command: python -u\n\
+         \x20   hosts:\n\
+         \x20     BUILD-01: D:\\py311\\python.exe -u\n\
+         command_aliases:        # extra command names, listed in `svc help`\n\
+         \x20 up: run\n\
+         notify:                 # sinks for start, stop, restart, crash_loop, enable,\n\
+         \x20 - kind: webhook        # disable and failure events\n\
+         \x20   url: https://hooks.example.com/svc\n\
+         \x20   events: [crash_loop, failure] # every event when left out\n\
+         \x20 - kind: toast          # also event_log (source) and email (smtp_server,\n\
+         \x20                        # port, from, to, user, password_secret, tls)\n\
+         enrichers:              # programs given each service as JSON on stdin; the JSON\n\
+         \x20 - command: D:\\tools\\deployinfo.exe # object they print shows under Extra\n\
+         \x20   args: [--json]\n\
+         \x20   timeout: 1s          # default 2s; a slow or failing one is only a warning\n\
+         templates:              # service bodies with {{var}} placeholders\n\
+         \x20 bot:\n\
+         \x20   type: Executable\n\
+         \x20   path: D:\\bots\\bot.exe\n\
+         \x20   args: [--token, '{{token}}', --port, '{{port}}']\n\
+         hosts:                  # per-machine overrides, by computer name\n\
+         \x20 LAPTOP:\n\
+         \x20   MyServer:\n\
+         \x20     path: E:\\server.exe\n\
+         services:\n\
+         \x20 - name: MyServer\n\
+         \x20   ...\n\
+         \x20 - name: bot1        # an instance: bot with vars filled in, {{name}} is\n\
+         \x20   instances_of: bot   # its own name; other fields override the template\n\
+         \x20   vars: {token: a.txt, port: 8001}\n\n\
+         An empty or comment-only file defines no services.\n\n\
+         A .svc.yaml in the current directory or above (up to your profile directory)\n\
+         is used instead, so a project can define its own services; it may pull\n\
+         services of the global file in by name with `extends: [MyDatabase]`.\n\
+         Set SVC_NO_WORKSPACE=1 to ignore it.",
+    ),
+    (
+        "units",
+        "durations and sizes",
+        "Durations take a unit: 500ms, 30s, 5m, 2h, 1d (fractions like 1.5h work).\n\
+         Sizes use binary units: 512B, 64K, 512M, 1.5G, 1T.\n\
+         Bare numbers are rejected because the unit would be a guess.",
+    ),
+    (
+        "exit-codes",
+        "exit codes",
+        "0  success\n\
+         1  the command failed; the error is printed on stderr, or `svc check`\n\
+         \x20  found a running service with a dependency down\n\
+         N  `svc run` of a utility that exited with code N (1 to 255)\n\
+         2  invalid usage, or `svc check` found a service down\n\
+         130  interrupted with Ctrl+C",
+    ),
+    (
+        "startup",
+        "startup backends",
+        "`svc enable` writes a value named svc:<name> under\n\
+         HKCU\\SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Run, so the\n\
+         executable starts at logon. `svc disable` deletes that value.\n\
+         Values older versions wrote under the bare name are still recognized\n\
+         and moved on the next enable; a bare value svc didn't write, such as\n\
+         another program's entry with the same name, is never touched.\n\
+         With `startup_stagger` set, the value runs `svc run --startup <name>`\n\
+         instead, which waits startup_order × startup_stagger before starting\n\
+         the service; services without an order start after all others.\n\
+         Each such run is recorded per boot, and `svc startup-report` lists\n\
+         when every entry started, its delay and how long the spawn took.\n\n\
+         --backend (or the startup_backend of the service or the settings) picks\n\
+         another mechanism; enabling on one removes the entry svc wrote on any\n\
+         other, and `svc disable` without --backend removes them all:\n\
+         \x20 run-key          the per-user Run key above, the default\n\
+         \x20 machine-run-key  the same under HKLM, for every user; needs admin\n\
+         \x20 scheduled-task   a logon task named \\svc\\<name> in Task Scheduler\n\
+         \x20 startup-folder   a \"svc - <name>\" shortcut in the Startup folder\n\
+         \x20 scm              a Windows service of the same name, only reported\n\
+         `elevated: true` makes the scheduled task run with highest privileges,\n\
+         and `logon_delay: 30s` has the entry wait that long through\n\
+         `svc run --startup`, on top of any stagger.\n\
+         `svc status` lists every backend that starts the service and flags an\n\
+         entry that runs something other than the config says; `svc validate`\n\
+         and `svc apply-startup` warn about a service started more than once.",
+    ),
+    (
+        "desired-state",
+        "desired-state documents",
+        "`svc apply -f desired.json` (or --stdin) takes a JSON document listing\n\
+         services as they should be:\n\n\
+         {\"services\": [\n\
+         \x20 {\"name\": \"api\", \"type\": \"Executable\", \"path\": \"D:\\\\api\\\\api.exe\",\n\
+         \x20  \"startup\": \"enabled\", \"running\": true}\n\
+         ]}\n\n\
+         Each entry is a services.yaml entry and replaces the configured one as a\n\
+         whole; `running` is not written to the config. svc stops the services\n\
+         that should not run, writes the config in one go (with --prune also\n\
+         removing services the document doesn't list, after stopping them and\n\
+         deleting their start-up entries), reconciles the `startup` fields like\n\
+         `svc apply-startup` and starts what should run but doesn't.\n\
+         A running service whose entry changed is not restarted.\n\n\
+         Every change is reported as planned (--dry-run), applied, failed or\n\
+         skipped; with --json as {\"dry_run\", \"changes\", \"unchanged\"}. A service\n\
+         that didn't stop keeps its entry, and nothing after a failed config write\n\
+         is tried, so applying the same document again converges.",
+    ),
+];
+
+fn examples_for(command: &str) -> Option<String> {
+    let lines: Vec<String> = EXAMPLES
+        .iter()
+        .filter(|(name, _, _)| *name == command)
+        .map(|(_, line, about)| format!("  {}\n      {}", line, about))
+        .collect();
+    (!lines.is_empty()).then(|| format!("Examples:\n{}", lines.join("\n")))
+}
+
+pub fn is_command(name: &str) -> bool {
+    Cli::command().get_subcommands().any(|sub| sub.get_name() == name)
+}
+
+fn aliases_help(aliases: &BTreeMap<String, String>) -> Option<String> {
+    let width = aliases.keys().map(String::len).max()?;
+    let lines: Vec<String> = aliases
+        .iter()
+        .map(|(alias, target)| format!("  {:width$}  {}", alias, target, width = width))
+        .collect();
+    Some(format!("Aliases (from services.yaml):\n{}", lines.join("\n")))
+}
+
+pub fn command() -> clap::Command {
+    let mut command = Cli::command();
+    if let Some(help) = ALIASES.get().and_then(aliases_help) {
+        command = command.after_help(help);
+    }
+    let names: Vec<String> = command
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_string())
+        .collect();
+    for name in names {
+        if let Some(examples) = examples_for(&name) {
+            command = command.mut_subcommand(name, |sub| sub.after_long_help(examples));
+        }
+    }
+    command
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = (previous + usize::from(ca != cb)).min(row[j] + 1).min(current + 1);
+            previous = current;
+        }
+    }
+    row[b.len()]
+}
+
+// Closest candidate within a couple of typos, for did-you-mean hints
+pub fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, candidate)| distance <= 2 && distance < name.len().max(candidate.len()))
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+// Where the command is among the arguments after the program name: the first word that is
+// neither a flag nor the value of a global flag taking one, as s.yaml in `svc --config s.yaml ls`
+fn command_at(command: &clap::Command, args: &[String]) -> Option<usize> {
+    let valued: Vec<String> = command
+        .get_arguments()
+        .filter(|arg| arg.get_action().takes_values())
+        .flat_map(|arg| {
+            let long = arg.get_long().map(|long| format!("--{}", long));
+            long.into_iter().chain(arg.get_short().map(|short| format!("-{}", short)))
+        })
+        .collect();
+    let mut at = 0;
+    while let Some(arg) = args.get(at) {
+        if arg == "--" {
+            return None;
+        } else if valued.contains(arg) {
+            at += 2;
+        } else if arg.starts_with('-') {
+            at += 1;
+        } else {
+            return Some(at);
+        }
+    }
+    None
+}
+
+// Replace an alias in the command position with what it stands for
+fn resolve(args: &mut Vec<String>, aliases: &BTreeMap<String, String>) -> Option<(String, String)> {
+    let at = command_at(&Cli::command(), &args[1..])? + 1;
+    let target = aliases.get(&args[at])?;
+    let alias = args.splice(at..=at, target.split_whitespace().map(String::from)).next()?;
+    Some((alias, target.clone()))
+}
+
+// Reported like any other svc error, so --json and --quiet apply even though parsing failed
+fn unknown_command(args: &[String], name: &str, exit_code: i32) -> ! {
+    let has = |flags: &[&str]| args.iter().take_while(|arg| *arg != "--").any(|arg| flags.contains(&arg.as_str()));
+    output::init(output::OutputOptions {
+        quiet: has(&["-q", "--quiet"]),
+        json: has(&["--json"]),
+        verbose: false,
+        no_color: has(&["--no-color"]),
+        force_color: has(&["--force-color"]),
+    });
+
+    let aliases = ALIASES.get().cloned().unwrap_or_default();
+    let commands: Vec<String> = Cli::command().get_subcommands().map(|sub| sub.get_name().to_string()).collect();
+    let candidates = commands.iter().chain(aliases.keys()).map(String::as_str);
+    let suggestion = suggest(name, candidates).map(|near| match aliases.get(near) {
+        Some(target) => format!("{} (alias for {})", near, target),
+        None => near.to_string(),
+    });
+    output::report_error(&SvcError::UnknownCommand {
+        name: name.to_string(),
+        suggestion,
+    });
+    std::process::exit(exit_code)
+}
+
+// --lenient ahead of parsing the arguments, as the config that holds the aliases is loaded first
+pub fn lenient_flag() -> bool {
+    std::env::args().skip(1).take_while(|arg| arg != "--").any(|arg| arg == "--lenient")
+}
+
+// `help`, `version` and `init` work without a config, as do --help and --version, so main
+// doesn't read one for them; a config that is broken or slow to load can't get in their way
+pub fn needs_config() -> bool {
+    let command = Cli::command();
+    let args: Vec<String> = std::env::args().skip(1).take_while(|arg| arg != "--").collect();
+    let at = command_at(&command, &args);
+    let asks = args.iter().any(|flag| matches!(flag.as_str(), "--help" | "-h" | "--version" | "-V"));
+    match at.map(|at| args[at].as_str()) {
+        Some("help" | "version" | "init") => false,
+        // An alias is only known from the config
+        Some(word) => !(asks && command.find_subcommand(word).is_some()),
+        None => !asks,
+    }
+}
+
+// The last value of a global flag as given, for the ones needed before the arguments are parsed
+fn early_flag(flag: &str) -> Option<String> {
+    let mut found = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        if arg == flag {
+            found = args.next();
+        } else if let Some(value) = arg.strip_prefix(flag).and_then(|rest| rest.strip_prefix('=')) {
+            found = Some(value.to_string());
+        }
+    }
+    found
+}
+
+// --config, since aliases come from the config
+pub fn config_flag() -> Option<String> {
+    early_flag("--config")
+}
+
+// --host, so the config is read as that machine's from the start
+pub fn host_flag() -> Option<String> {
+    early_flag("--host")
+}
+
+// Parse the command line, returning the alias that was resolved, if any
+pub fn parse(aliases: BTreeMap<String, String>) -> (Cli, Option<(String, String)>) {
+    let mut args: Vec<String> = std::env::args_os()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let resolved = resolve(&mut args, &aliases);
+    let _ = ALIASES.set(aliases);
+    let _ = ARGS.set(args.iter().skip(1).cloned().collect());
+
+    let matches = command().try_get_matches_from(&args).unwrap_or_else(|err| {
+        if err.kind() == ErrorKind::InvalidSubcommand {
+            if let Some(ContextValue::String(name)) = err.get(ContextKind::InvalidSubcommand) {
+                unknown_command(&args, name, err.exit_code());
+            }
+        }
+        err.exit()
+    });
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    // `--at <dir>` is the legacy `at <dir>` spelled as a flag
+    if let Cmd::Run { at, at_dir, .. } = &mut cli.command {
+        if let Some(dir) = at_dir.take() {
+            *at = Some(vec!["at".to_string(), dir]);
+        }
+    }
+    (cli, resolved)
+}
+
+pub fn args() -> Vec<String> {
+    ARGS.get().cloned().unwrap_or_default()
+}
+
+// A command line recorded earlier, as `svc resume-last` runs it again
+pub fn parse_args(args: &[String]) -> Result<Cli, clap::Error> {
+    let matches = command().try_get_matches_from(std::iter::once("svc").chain(args.iter().map(String::as_str)))?;
+    Cli::from_arg_matches(&matches)
+}
+
+pub fn print_help(topic: Option<&str>) -> Result<(), SvcError> {
+    let mut command = command();
+    command.build();
+    let Some(topic) = topic else {
+        command.print_long_help()?;
+        return Ok(());
+    };
+
+    if let Some(sub) = command.find_subcommand_mut(topic) {
+        sub.print_long_help()?;
+        return Ok(());
+    }
+    if let Some((_, title, text)) = TOPICS.iter().find(|(name, _, _)| *name == topic) {
+        println!("{}\n\n{}", title, text);
+        return Ok(());
+    }
+
+    let topics: Vec<&str> = TOPICS.iter().map(|(name, _, _)| *name).collect();
+    Err(SvcError::UnknownHelpTopic {
+        topic: topic.to_string(),
+        available: format!("{} or any command name", topics.join(", ")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The svc part of an example as cmd would hand it over: the one command of a pipe or
+    // `&&` chain that is svc, before any redirect, split on spaces outside double quotes
+    fn words(example: &str) -> Vec<String> {
+        let mut commands = example.split("&&").flat_map(|chain| chain.split('|')).map(str::trim);
+        let line = commands.find(|command| command.starts_with("svc ")).unwrap_or(example);
+        let line = line.split('>').next().unwrap().trim();
+        let (mut words, mut word, mut quoted) = (Vec::new(), String::new(), false);
+        for c in line.chars() {
+            match c {
+                '"' => quoted = !quoted,
+                ' ' if !quoted => words.extend((!word.is_empty()).then(|| std::mem::take(&mut word))),
+                c => word.push(c),
+            }
+        }
+        words.extend((!word.is_empty()).then_some(word));
+        words
+    }
+
+    #[test]
+    fn every_example_parses_as_the_command_it_is_listed_under() {
+        for (name, example, _) in EXAMPLES {
+            let words = words(example);
+            assert_eq!(words[0], "svc", "{}", example);
+            if let Err(err) = Cli::try_parse_from(&words) {
+                panic!("{}: {}", example, err);
+            }
+            let matches = command().try_get_matches_from(&words).unwrap();
+            assert_eq!(matches.subcommand_name(), Some(*name), "{}", example);
+        }
+    }
+
+    #[test]
+    fn an_alias_is_found_after_a_global_flag_and_its_value() {
+        let aliases = BTreeMap::from([("ls".to_string(), "list --all".to_string())]);
+        let words = |line: &str| line.split(' ').map(String::from).collect::<Vec<_>>();
+
+        let mut args = words("svc --config s.yaml ls");
+        assert_eq!(resolve(&mut args, &aliases), Some(("ls".to_string(), "list --all".to_string())));
+        assert_eq!(args, words("svc --config s.yaml list --all"));
+
+        // A value is never the command, even when it is spelled like an alias
+        let mut args = words("svc --config ls status");
+        assert_eq!(resolve(&mut args, &aliases), None);
+        assert_eq!(args, words("svc --config ls status"));
+    }
+}
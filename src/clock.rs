@@ -0,0 +1,163 @@
+use crate::warnings;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// The wall clock says when something happened, and jumps when it is set by hand, synced or a
+// VM resumes; how long something took comes from the monotonic clock, which never goes back
+
+// A moment as both clocks saw it, for what is recorded with a time and measured as a duration
+#[derive(Debug, Clone, Copy)]
+pub struct Stamp {
+    pub wall: SystemTime,
+    mono: Instant,
+}
+
+impl Stamp {
+    pub fn now() -> Stamp {
+        Stamp {
+            wall: SystemTime::now(),
+            mono: Instant::now(),
+        }
+    }
+
+    // From `earlier` to this moment by the monotonic clock, however the wall clock moved between
+    pub fn since(&self, earlier: &Stamp) -> Duration {
+        self.mono.saturating_duration_since(earlier.mono)
+    }
+}
+
+// Seconds since the Unix epoch; 0 for a clock set before it
+pub fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs())
+}
+
+// Seconds from `started` to `now`, or how far `started` is ahead of `now`
+fn elapsed(started: u64, now: u64) -> Result<u64, u64> {
+    now.checked_sub(started).ok_or_else(|| started - now)
+}
+
+// How long a process created at `started` has been up at `now`, both Unix seconds. A start the
+// clock puts in the future means it was set back since; that is warned about and taken as 0
+pub fn uptime(pid: u64, started: u64, now: u64) -> u64 {
+    elapsed(started, now).unwrap_or_else(|ahead| {
+        warnings::warn(
+            "clock_skew",
+            &format!("PID {}", pid),
+            format!("was created {}s after the current time; the clock was set back, so its uptime is taken as 0", ahead),
+        );
+        0
+    })
+}
+
+// "YYYY-MM-DD HH:MM:SS" of Unix seconds, without pulling in a date crate
+fn civil(secs: i64) -> String {
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+
+    // Civil-from-days conversion (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+// UTC wall-clock time, for names that sort by when they were made
+pub fn utc(time: SystemTime) -> String {
+    civil(unix_secs(time) as i64)
+}
+
+// Seconds local time is ahead of UTC at `secs`, by the time zone's rules for that date, so a time
+// from before a DST change keeps the offset it had
+#[cfg(windows)]
+fn utc_offset(secs: u64) -> i64 {
+    use windows_sys::Win32::Foundation::{FILETIME, SYSTEMTIME};
+    use windows_sys::Win32::System::Time::{FileTimeToSystemTime, SystemTimeToFileTime, SystemTimeToTzSpecificLocalTimeEx};
+
+    // FILETIME counts 100ns ticks from 1601, 11644473600 seconds before the Unix epoch
+    let ticks = (secs + 11_644_473_600) * 10_000_000;
+    let file = FILETIME {
+        dwLowDateTime: ticks as u32,
+        dwHighDateTime: (ticks >> 32) as u32,
+    };
+    let mut universal = SYSTEMTIME::default();
+    let mut local = SYSTEMTIME::default();
+    let mut local_file = FILETIME::default();
+    let converted = unsafe {
+        FileTimeToSystemTime(&file, &mut universal) != 0
+            && SystemTimeToTzSpecificLocalTimeEx(std::ptr::null(), &universal, &mut local) != 0
+            && SystemTimeToFileTime(&local, &mut local_file) != 0
+    };
+    if !converted {
+        return 0;
+    }
+    let local_ticks = (u64::from(local_file.dwHighDateTime) << 32) | u64::from(local_file.dwLowDateTime);
+    (local_ticks as i64 - ticks as i64) / 10_000_000
+}
+
+#[cfg(not(windows))]
+fn utc_offset(_secs: u64) -> i64 {
+    0
+}
+
+fn with_offset(secs: u64, offset: i64) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let minutes = offset.abs() / 60;
+    format!("{} {}{:02}:{:02}", civil(secs as i64 + offset), sign, minutes / 60, minutes % 60)
+}
+
+// Local wall-clock time with its UTC offset, "2026-03-29 03:30:00 +02:00", so times from
+// either side of a DST change or from another machine still compare
+pub fn local(time: SystemTime) -> String {
+    let secs = unix_secs(time);
+    with_offset(secs, utc_offset(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_clock_set_back_gives_no_negative_uptime() {
+        assert_eq!(elapsed(1_000, 1_600), Ok(600));
+        // Set back an hour ten minutes after the process started
+        assert_eq!(elapsed(4_000, 4_000 + 600 - 3_600), Err(3_000));
+        assert_eq!(uptime(42, 1_000, 400), 0);
+    }
+
+    #[test]
+    fn durations_come_from_the_monotonic_clock() {
+        let earlier = Stamp::now();
+        // The wall clock jumping back a day doesn't reach the measured duration
+        let later = Stamp {
+            wall: earlier.wall - Duration::from_secs(86_400),
+            mono: earlier.mono + Duration::from_millis(250),
+        };
+        assert_eq!(later.since(&earlier), Duration::from_millis(250));
+        assert_eq!(earlier.since(&later), Duration::ZERO);
+    }
+
+    #[test]
+    fn local_times_carry_their_offset() {
+        // An hour either side of 01:30 UTC on the morning Central Europe moves to summer time read
+        // 01:30 and 04:30 local; the offsets show they are two hours apart, not three
+        let secs = 1_774_747_800;
+        assert_eq!(civil(secs as i64), "2026-03-29 01:30:00");
+        assert_eq!(with_offset(secs - 3_600, 3_600), "2026-03-29 01:30:00 +01:00");
+        assert_eq!(with_offset(secs + 3_600, 7_200), "2026-03-29 04:30:00 +02:00");
+        assert_eq!(with_offset(secs, -(3 * 3_600 + 1_800)), "2026-03-28 22:00:00 -03:30");
+        assert_eq!(utc(UNIX_EPOCH), "1970-01-01 00:00:00");
+    }
+}
@@ -0,0 +1,879 @@
+use crate::backends::Backend;
+use crate::cli::Scope;
+use crate::lenient::{self, Skipped};
+use crate::{adhoc, cli, deps, encoding, enrich, firewall, hosts, idlestop, index, locate, logs, notify, pathcmp, priority};
+use crate::{procinfo, retry, shells, steps};
+use crate::{stopsignal, templates, units, workspace, SvcError};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// YAML config file structure, use serde for (de)serializing
+// New config keys add fields in minor versions, here and in Settings and Config
+#[derive(Debug, Deserialize)]
+#[non_exhaustive]
+pub struct Service<'a> {
+    pub name: Cow<'a, str>,
+    pub path: Cow<'a, str>,
+    #[serde(rename = "type")]
+    pub service_type: ServiceType,
+    #[serde(default = "default_interpreter")]
+    pub interpreter: Cow<'a, str>,
+    // Util only: an entry of the `shells` setting, used instead of interpreter
+    pub shell: Option<Cow<'a, str>>,
+    // Flags of that shell, passed before the script
+    #[serde(skip)]
+    pub interpreter_args: Vec<String>,
+    #[serde(default = "default_work_at")]
+    pub work_at: Cow<'a, str>,
+    // Passed after the path, or after the script for a Util
+    #[serde(default)]
+    pub args: Vec<Cow<'a, str>>,
+    // Executable only: stdout and stderr appended to this file, or to logs\<name>.log
+    // next to the executable for `log: true`
+    pub log: Option<logs::LogSetting<'a>>,
+    // Set for the process on top of svc's own environment; values may use %VAR% or ${VAR}
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    #[serde(default, deserialize_with = "units::restart_delay")]
+    pub restart_delay: Option<units::HumanDuration>,
+    // How `svc watch` backs off a service that keeps exiting, in place of doubling restart_delay
+    pub restart_backoff: Option<retry::RetryPolicy>,
+    // Relaunches by `svc watch` within an hour after which it leaves the service down
+    pub max_restarts_per_hour: Option<u32>,
+    #[serde(default)]
+    pub autostart: bool,
+    #[serde(default)]
+    pub capture_stdout: bool,
+    // How its captured output and log are decoded; utf8 also asks Python to write UTF-8
+    pub encoding: Option<encoding::Encoding>,
+    // Util under cmd with encoding utf8: switch the console to code page 65001 first
+    #[serde(default)]
+    pub chcp: bool,
+    // Util only: run again when it exits with an error
+    pub retry: Option<retry::RetryPolicy>,
+    // Util only: run in a fresh directory under %TEMP% instead of work_at
+    #[serde(default)]
+    pub temp_work_dir: bool,
+    #[serde(default)]
+    pub protected: bool,
+    pub label: Option<Cow<'a, str>>,
+    // Groups `--tag` selects the service by, e.g. [work]
+    #[serde(default)]
+    pub tags: Vec<Cow<'a, str>>,
+    pub startup_order: Option<u32>,
+    pub terminal_profile: Option<Cow<'a, str>>,
+    #[serde(default)]
+    pub depends_on: Vec<Cow<'a, str>>,
+    // Commands run in order before the service itself, each waited on
+    #[serde(default)]
+    pub steps: Vec<steps::Step<'a>>,
+    // Start-up state `svc apply-startup` reconciles the registry to; unmanaged when unset
+    pub startup: Option<StartupState>,
+    // Where enable registers this service without --backend, over the startup_backend setting
+    pub startup_backend: Option<Backend>,
+    // scheduled-task only: the logon task runs with highest privileges
+    #[serde(default)]
+    pub elevated: bool,
+    // Waited by `svc run --startup` on top of the stagger, whichever backend starts it
+    #[serde(default, deserialize_with = "units::logon_delay")]
+    pub logon_delay: Option<units::HumanDuration>,
+    // Most svc keeps for it in its own directories; the oldest of what can go is pruned over it
+    #[serde(default, deserialize_with = "units::disk_budget")]
+    pub disk_budget: Option<units::ByteSize>,
+    // Variables captured at enable and set again when `svc run --startup` launches it at logon
+    #[serde(default, deserialize_with = "crate::startupenv::allowlist")]
+    pub startup_env_allowlist: Vec<String>,
+    // Applied to the process right after it starts
+    pub priority: Option<priority::Priority>,
+    // CPU numbers the process may run on, e.g. [0, 1]
+    pub affinity: Option<Vec<u32>>,
+    // Let each logon session run its own instance
+    #[serde(default)]
+    pub per_session: bool,
+    // `{file: ...}` or `{event: ...}`, rather than the tagged form serde_yaml defaults to
+    #[serde(default, deserialize_with = "serde_yaml::with::singleton_map::deserialize")]
+    pub stop_signal: Option<stopsignal::StopSignal<'a>>,
+    // How long kill waits for stop_signal to take effect before terminating
+    #[serde(default, deserialize_with = "units::stop_grace")]
+    pub stop_grace: Option<units::HumanDuration>,
+    // How long `svc stop` waits for the processes to exit before terminating them
+    #[serde(default, deserialize_with = "units::stop_timeout")]
+    pub stop_timeout: Option<units::HumanDuration>,
+    // Executable only: text in its log that means a fresh instance is up, for `restart --overlap`
+    pub ready_pattern: Option<Cow<'a, str>>,
+    // http://host:port/path answering 2xx or 3xx, or tcp://host:port accepting connections
+    pub health_check: Option<Cow<'a, str>>,
+    // TCP port it listens on; no two services may declare the same one
+    pub port: Option<u16>,
+    // How long `restart --overlap` waits for the new instance to be ready
+    #[serde(default, deserialize_with = "units::ready_timeout")]
+    pub ready_timeout: Option<units::HumanDuration>,
+    // `svc watch` stops it the way `svc stop` does once it has been idle this long
+    pub idle_stop: Option<idlestop::IdleStop>,
+    pub run_as: Option<RunAs<'a>>,
+    // File held exclusively while the steps and the service run, shared with other schedulers;
+    // relative to the working directory, and may use %VAR% or ${VAR}
+    pub lock: Option<Cow<'a, str>>,
+    // Inbound rule `svc firewall apply` creates for the executable
+    pub firewall: Option<firewall::Firewall>,
+    // Started with `svc run-adhoc` and kept in the adhoc registry, not services.yaml
+    #[serde(skip)]
+    pub adhoc: bool,
+    // Where stdout and stderr of an Executable go, for adhoc runs with --log
+    #[serde(skip)]
+    pub log_file: Option<PathBuf>,
+    // Defined in the global services.yaml while a workspace config is active
+    #[serde(skip)]
+    pub global: bool,
+}
+
+// Launch under another account; the password comes from the secret store, never the config
+#[derive(Debug, Deserialize)]
+pub struct RunAs<'a> {
+    pub user: Cow<'a, str>,
+    pub password_secret: Cow<'a, str>,
+}
+
+impl Service<'_> {
+    // Human-facing name: the label when one is configured, keeping the real name visible
+    pub fn title(&self) -> String {
+        match &self.label {
+            Some(label) => format!("{} ({})", label, self.name),
+            None => self.name.to_string(),
+        }
+    }
+}
+
+pub fn default_interpreter() -> Cow<'static, str> {
+    Cow::Borrowed("python")
+}
+
+fn default_work_at() -> Cow<'static, str> {
+    Cow::Borrowed("")
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum ServiceType {
+    Executable,
+    Util,
+}
+
+// What a relative path or work_at is taken from
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PathsRelativeTo {
+    #[default]
+    CurrentDir,
+    // The config file's directory, so the two can move together, as a `svc bundle` does
+    Config,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StartupState {
+    Enabled,
+    Disabled,
+}
+
+// Whether `svc watch` relaunches the service when it exits
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    Always,
+}
+
+impl Display for ServiceType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            ServiceType::Executable => "Executable",
+            ServiceType::Util => "Utility",
+        };
+        write!(f, "{}", str)
+    }
+}
+
+// Top-level settings, available when the config is a mapping with a `services` list
+#[derive(Debug, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Settings {
+    #[serde(default, deserialize_with = "units::startup_stagger")]
+    pub startup_stagger: Option<units::HumanDuration>,
+    // Extra command names, e.g. `up: run`, resolved before the arguments are parsed
+    #[serde(default)]
+    pub command_aliases: BTreeMap<String, String>,
+    // Per-machine field overrides: computer name -> service name -> fields
+    #[serde(default)]
+    pub hosts: BTreeMap<String, BTreeMap<String, serde_yaml::Mapping>>,
+    // Output lines a failed history record keeps, newest last
+    pub history_output_lines: Option<usize>,
+    // How registry and WMI queries are retried when they fail in a way that passes
+    pub transient_retry: Option<retry::RetryPolicy>,
+    // disk_budget of services without their own
+    #[serde(default, deserialize_with = "units::disk_budget")]
+    pub disk_budget: Option<units::ByteSize>,
+    // Where run, kill, enable, disable, restart and watch report what happened
+    #[serde(default)]
+    pub notify: Vec<notify::SinkConfig>,
+    // Programs that add facts of their own to status and list
+    #[serde(default)]
+    pub enrichers: Vec<enrich::Enricher>,
+    // What enable and disable use without --backend
+    #[serde(default)]
+    pub startup_backend: Backend,
+    // In a workspace's .svc.yaml, services of the global services.yaml it uses by name
+    #[serde(default)]
+    pub extends: Vec<String>,
+    // Logical shell names Utils refer to with `shell`, e.g. `pwsh: pwsh.exe -NoProfile -File`
+    #[serde(default)]
+    pub shells: BTreeMap<String, shells::ShellSetting>,
+    #[serde(default)]
+    pub paths_relative_to: PathsRelativeTo,
+    // Leave out service entries that don't parse or validate instead of failing, as --lenient does
+    #[serde(default)]
+    pub lenient: bool,
+    // What watch does without --kill-removed or --no-kill-removed
+    #[serde(default)]
+    pub kill_removed: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Config<'a> {
+    #[serde(flatten)]
+    pub settings: Settings,
+    #[serde(default)]
+    pub services: Vec<Service<'a>>,
+    // Entries lenient loading left out, each with why
+    #[serde(skip)]
+    pub skipped: Vec<Skipped>,
+}
+
+impl<'a> Config<'a> {
+    pub fn service(&self, name: &str) -> Result<&Service<'a>, SvcError> {
+        self.services.iter().find(|s| s.name == name).ok_or_else(|| {
+            match self.skipped.iter().find(|entry| entry.service == name) {
+                Some(entry) => entry.error(),
+                None => not_found(&self.services, name),
+            }
+        })
+    }
+}
+
+// An unknown name in an empty config gets pointed at how to add services instead; one that
+// lenient loading left out gets what was wrong with its entry
+pub fn not_found(services: &[Service], name: &str) -> SvcError {
+    if let Some(err) = lenient::skipped(name) {
+        return err;
+    }
+    if services.is_empty() {
+        SvcError::NoServicesConfigured(name.to_string())
+    } else {
+        SvcError::ServiceNotFound {
+            name: name.to_string(),
+            suggestion: cli::suggest(name, services.iter().map(|s| s.name.as_ref())).map(String::from),
+        }
+    }
+}
+
+// The config is either a bare list of services or a mapping with settings and `services`;
+// an empty or comment-only file is a config without services
+pub fn parse_config(content: &str) -> Result<Config<'static>, SvcError> {
+    parse(content, false)
+}
+
+// Lenient when asked to, by --lenient or by the document's own `lenient: true`
+fn parse(content: &str, lenient: bool) -> Result<Config<'static>, SvcError> {
+    let shape: serde_yaml::Value = serde_yaml::from_str(content)?;
+    let lenient = lenient || lenient::on() || shape.get("lenient").and_then(serde_yaml::Value::as_bool) == Some(true);
+    let listed = match &shape {
+        serde_yaml::Value::Mapping(mapping) => mapping.get("services").is_none_or(serde_yaml::Value::is_sequence),
+        serde_yaml::Value::Sequence(_) => true,
+        _ => false,
+    };
+    // A document that isn't a list or mapping of entries fails the strict way either way
+    if lenient && listed {
+        return parse_lenient(shape);
+    }
+    let mut config = if shape.is_null() {
+        Config::default()
+    } else if shape.is_mapping() {
+        let mut shape = shape;
+        templates::expand(&mut shape)?;
+        hosts::apply(&mut shape)?;
+        serde_yaml::from_value(shape.clone()).map_err(|err| invalid_entry(&shape, err))?
+    } else {
+        // Only to explain an instance here, as the bare list form has no templates
+        templates::expand(&mut shape.clone())?;
+        Config {
+            settings: Settings::default(),
+            services: serde_yaml::from_str(content).map_err(|err| invalid_entry(&shape, err))?,
+            skipped: Vec::new(),
+        }
+    };
+    shells::resolve(&mut config)?;
+    Ok(config)
+}
+
+// The name of an entry, or its position when it has none
+fn entry_label(at: usize, entry: &serde_yaml::Value) -> String {
+    match entry.get("name").and_then(serde_yaml::Value::as_str) {
+        Some(name) => name.to_string(),
+        None => format!("#{}", at + 1),
+    }
+}
+
+// Each entry on its own, so one that doesn't expand, parse or resolve its shell is skipped
+// rather than failing the rest; settings are still all or nothing
+fn parse_lenient(mut shape: serde_yaml::Value) -> Result<Config<'static>, SvcError> {
+    use serde_yaml::Value;
+    let entries = match &mut shape {
+        Value::Sequence(entries) => std::mem::take(entries),
+        Value::Mapping(mapping) => match mapping.remove("services") {
+            Some(Value::Sequence(entries)) => entries,
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    };
+    let mut skipped = Vec::new();
+    let mut skip = |at: usize, service: String, err: SvcError| {
+        let entry = Skipped {
+            service,
+            reason: err.to_string(),
+            file: String::new(),
+        };
+        skipped.push((at, entry))
+    };
+
+    // Templates are expanded one entry at a time, labeled by where the entry was written
+    let templates = shape.get("templates").cloned();
+    let mut labels = Vec::new();
+    let mut expanded = Vec::new();
+    for (at, entry) in entries.into_iter().enumerate() {
+        let label = entry_label(at, &entry);
+        let mut single = serde_yaml::Mapping::new();
+        if let Some(templates) = &templates {
+            single.insert("templates".into(), templates.clone());
+        }
+        single.insert("services".into(), Value::Sequence(vec![entry]));
+        let mut single = Value::Mapping(single);
+        match templates::expand(&mut single) {
+            Ok(()) => {
+                labels.push((at, label));
+                expanded.extend(single.get_mut("services").and_then(Value::as_sequence_mut).and_then(Vec::pop));
+            }
+            Err(err) => skip(at, label, err),
+        }
+    }
+
+    let (mut config, entries) = match shape {
+        Value::Mapping(mut mapping) => {
+            mapping.insert("services".into(), Value::Sequence(expanded));
+            let mut shape = Value::Mapping(mapping);
+            hosts::apply(&mut shape)?;
+            let entries = match shape.as_mapping_mut().and_then(|mapping| mapping.remove("services")) {
+                Some(Value::Sequence(entries)) => entries,
+                _ => Vec::new(),
+            };
+            (serde_yaml::from_value::<Config>(shape)?, entries)
+        }
+        _ => (Config::default(), expanded),
+    };
+    for ((at, label), entry) in labels.into_iter().zip(entries) {
+        let parsed = serde_yaml::from_value::<Service>(entry).map_err(SvcError::from).and_then(|mut service| {
+            shells::resolve_service(&config.settings.shells, &mut service)?;
+            Ok(service)
+        });
+        match parsed {
+            Ok(service) => config.services.push(service),
+            Err(err) => skip(at, label, err),
+        }
+    }
+    // In the order they were written, like every other list of services
+    skipped.sort_by_key(|(at, _)| *at);
+    config.skipped = skipped.into_iter().map(|(_, entry)| entry).collect();
+    Ok(config)
+}
+
+// serde only says where a parse failed, so name the service entry it failed in when it was one
+fn invalid_entry(document: &serde_yaml::Value, err: serde_yaml::Error) -> SvcError {
+    let entries = match document {
+        serde_yaml::Value::Mapping(mapping) => mapping.get("services"),
+        other => Some(other),
+    };
+    for (at, entry) in entries.and_then(serde_yaml::Value::as_sequence).into_iter().flatten().enumerate() {
+        if let Err(reason) = serde_yaml::from_value::<Service>(entry.clone()) {
+            let service = entry_label(at, entry);
+            let line = err.location().map(|at| format!(" (line {})", at.line())).unwrap_or_default();
+            return SvcError::InvalidEntry {
+                service,
+                reason: format!("{}{}", reason, line),
+            };
+        }
+    }
+    err.into()
+}
+
+// Why the config can't be read, for the cases that get their own explanation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigAccessError {
+    Directory { suggested: String },
+    // A sharing violation: another program opened it without letting others read
+    Locked,
+    PermissionDenied { user: Option<String>, owner: Option<String> },
+    // Whitespace or quotes a shell left around the path
+    OddPath { received: String },
+}
+
+impl ConfigAccessError {
+    // Stable name for the JSON error details
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ConfigAccessError::Directory { .. } => "directory",
+            ConfigAccessError::Locked => "locked",
+            ConfigAccessError::PermissionDenied { .. } => "permission_denied",
+            ConfigAccessError::OddPath { .. } => "odd_path",
+        }
+    }
+}
+
+impl Display for ConfigAccessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigAccessError::Directory { suggested } => write!(f, "it is a directory, did you mean {}?", suggested),
+            ConfigAccessError::Locked => write!(f, "another program has it open and locked (a sharing violation)"),
+            ConfigAccessError::PermissionDenied { user, owner } => {
+                write!(f, "access denied to {}", user.as_deref().unwrap_or("this account"))?;
+                match owner {
+                    Some(owner) => write!(f, ", the file belongs to {}", owner),
+                    None => Ok(()),
+                }
+            }
+            ConfigAccessError::OddPath { received } => {
+                write!(f, "no such file, and the path received was {} with stray whitespace or quotes", received)
+            }
+        }
+    }
+}
+
+// ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+const SHARING_CODES: [i32; 2] = [32, 33];
+
+fn access_problem(path: &Path, err: &std::io::Error) -> Option<ConfigAccessError> {
+    // Windows reports reading a directory as access denied, so this comes first
+    if path.is_dir() {
+        let workspace = path.join(workspace::WORKSPACE_FILE);
+        let file = if workspace.is_file() { workspace } else { path.join(locate::CONFIG_FILE) };
+        return Some(ConfigAccessError::Directory {
+            suggested: file.display().to_string(),
+        });
+    }
+    if err.raw_os_error().is_some_and(|code| SHARING_CODES.contains(&code)) {
+        return Some(ConfigAccessError::Locked);
+    }
+    match err.kind() {
+        std::io::ErrorKind::PermissionDenied => Some(ConfigAccessError::PermissionDenied {
+            user: procinfo::owner(u64::from(std::process::id())).or_else(|| std::env::var("USERNAME").ok()),
+            owner: procinfo::file_owner(path),
+        }),
+        std::io::ErrorKind::NotFound => {
+            let text = path.to_string_lossy();
+            let trimmed = text.trim_matches(|c: char| c.is_whitespace() || c == '"' || c == '\'');
+            // Debug form, so the stray characters are visible
+            (trimmed != text).then(|| ConfigAccessError::OddPath {
+                received: format!("{:?}", text),
+            })
+        }
+        _ => None,
+    }
+}
+
+// A missing file gets its own error, so a first run explains where the config goes; so do
+// a directory, a locked file, denied access and a mangled path
+pub fn read_config(path: &Path) -> Result<String, SvcError> {
+    fs::read_to_string(path).map_err(|err| match access_problem(path, &err) {
+        Some(problem) => SvcError::ConfigAccess {
+            path: path.display().to_string(),
+            problem,
+        },
+        None if err.kind() == std::io::ErrorKind::NotFound => SvcError::ConfigMissing(path.display().to_string()),
+        None => err.into(),
+    })
+}
+
+// The global services a workspace config extends, marked as global; when lenient, one whose
+// entry the global file can't load is skipped like an entry of the workspace's own
+type Extended = (Vec<Service<'static>>, Vec<Skipped>);
+
+fn extended_services(path: &str, names: &[String], lenient: bool) -> Result<Extended, SvcError> {
+    if names.is_empty() {
+        return Ok(Extended::default());
+    }
+    let global = locate::global_path()?;
+    let invalid = |name: &str, reason: &str| SvcError::InvalidExtends {
+        name: name.to_string(),
+        reason: reason.to_string(),
+    };
+    if pathcmp::same_file(path, &global) {
+        return Err(invalid(&names[0], "only a workspace's .svc.yaml extends the global config"));
+    }
+    let mut config = parse(&read_config(Path::new(&global))?, lenient)?;
+    for entry in &mut config.skipped {
+        entry.file = global.clone();
+    }
+    let mut extended = Vec::new();
+    for name in names {
+        match config.services.iter().position(|s| s.name == name.as_str()) {
+            Some(at) => {
+                let mut service = config.services.swap_remove(at);
+                service.global = true;
+                extended.push(service);
+            }
+            None if config.skipped.iter().any(|entry| entry.service == *name) => {}
+            None => return Err(invalid(name, "the global services.yaml has no such service")),
+        }
+    }
+    let skipped = config.skipped.into_iter().filter(|entry| names.contains(&entry.service)).collect();
+    Ok((extended, skipped))
+}
+
+// When lenient, a service whose depends_on doesn't hold is left out, and then any that
+// depended on it, until the rest check out
+fn check_dependencies(config: &mut Config, lenient: bool) -> Result<(), SvcError> {
+    loop {
+        let err = match deps::validate(&config.services) {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+        let at = match &err {
+            SvcError::InvalidDependency { service, .. } => {
+                config.services.iter().position(|s| s.name == service.as_str())
+            }
+            _ => None,
+        };
+        match at {
+            Some(at) if lenient => {
+                let service = config.services.remove(at);
+                config.skipped.push(Skipped {
+                    service: service.name.to_string(),
+                    reason: err.to_string(),
+                    file: String::new(),
+                });
+            }
+            _ => return Err(err),
+        }
+    }
+}
+
+// A relative path or work_at made absolute from the config's directory; one that starts
+// with a variable is left for expand_env
+fn anchor_paths(services: &mut [Service], config_path: &str) {
+    let Some(dir) = std::path::absolute(config_path).ok().and_then(|path| path.parent().map(Path::to_path_buf)) else {
+        return;
+    };
+    let anchor = |path: &str| {
+        let relative = !path.is_empty() && Path::new(path).is_relative() && !path.starts_with(['%', '$']);
+        relative.then(|| Cow::Owned(dir.join(path).display().to_string()))
+    };
+    for service in services {
+        if let Some(path) = anchor(&service.path) {
+            service.path = path;
+        }
+        if let Some(work_at) = anchor(&service.work_at) {
+            service.work_at = work_at;
+        }
+    }
+}
+
+pub fn load_document(path: &str) -> Result<Config<'static>, SvcError> {
+    let mut config = parse_config(&read_config(Path::new(path))?)?;
+    if config.settings.paths_relative_to == PathsRelativeTo::Config {
+        anchor_paths(&mut config.services, path);
+    }
+    let lenient = lenient::on() || config.settings.lenient;
+    let (extended, skipped) = extended_services(path, &config.settings.extends, lenient)?;
+    for entry in &mut config.skipped {
+        entry.file = path.to_string();
+    }
+    config.services.extend(extended);
+    config.skipped.extend(skipped);
+    // A config entry of the same name wins over an adhoc one
+    let adhoc: Vec<Service> = adhoc::services()?
+        .into_iter()
+        .filter(|service| !config.services.iter().any(|s| s.name == service.name))
+        .collect();
+    config.services.extend(adhoc);
+
+    let mut seen = HashSet::new();
+    let mut at = 0;
+    while at < config.services.len() {
+        if seen.insert(config.services[at].name.to_string()) {
+            at += 1;
+            continue;
+        }
+        let err = SvcError::DuplicateService(config.services[at].name.to_string());
+        if !lenient {
+            return Err(err);
+        }
+        // The first entry of the name is the one kept
+        let duplicate = config.services.remove(at);
+        config.skipped.push(Skipped {
+            service: duplicate.name.to_string(),
+            reason: err.to_string(),
+            file: path.to_string(),
+        });
+    }
+    if let Some(alias) = config.settings.command_aliases.keys().find(|alias| cli::is_command(alias)) {
+        return Err(SvcError::AliasShadowsCommand(alias.clone()));
+    }
+    check_dependencies(&mut config, lenient)?;
+    for entry in config.skipped.iter_mut().filter(|entry| entry.file.is_empty()) {
+        entry.file = path.to_string();
+    }
+    hosts::validate(&config)?;
+    lenient::record(&config.skipped);
+    Ok(config)
+}
+
+// What main reads before every command, aliases and the like: only the top level of a mapping,
+// leaving its services unexpanded and unparsed, and nothing of a bare list, which has no settings
+pub fn load_settings(path: &str) -> Result<Settings, SvcError> {
+    parse_settings(&read_config(Path::new(path))?)
+}
+
+fn parse_settings(content: &str) -> Result<Settings, SvcError> {
+    let mut lines = content.lines().map(str::trim_end);
+    let first = lines.find(|line| !line.trim_start().is_empty() && !line.trim_start().starts_with('#'));
+    if first.is_none_or(|line| line.starts_with("- ") || line == "-") {
+        return Ok(Settings::default());
+    }
+    let serde_yaml::Value::Mapping(mut mapping) = serde_yaml::from_str(content)? else {
+        return Ok(Settings::default());
+    };
+    mapping.remove("services");
+    let settings: Settings = serde_yaml::from_value(serde_yaml::Value::Mapping(mapping))?;
+    if let Some(alias) = settings.command_aliases.keys().find(|alias| cli::is_command(alias)) {
+        return Err(SvcError::AliasShadowsCommand(alias.clone()));
+    }
+    Ok(settings)
+}
+
+pub fn load_config(path: &str) -> Result<Vec<Service<'static>>, SvcError> {
+    Ok(load_document(path)?.services)
+}
+
+// What `svc list --scope` shows; outside a workspace every service is global
+pub fn scoped_services(config_path: &str, scope: Option<Scope>) -> Result<Vec<Service<'static>>, SvcError> {
+    let mut services = load_config(config_path)?;
+    let (Some(scope), Some(_)) = (scope, workspace::active()) else {
+        return match scope {
+            Some(Scope::Workspace) => Err(SvcError::InvalidCommand(format!(
+                "--scope workspace without a {} here or above",
+                workspace::WORKSPACE_FILE
+            ))),
+            _ => Ok(services),
+        };
+    };
+    let global = || -> Result<Vec<Service<'static>>, SvcError> {
+        let mut services = load_config(&locate::global_path()?)?;
+        for service in &mut services {
+            service.global = !service.adhoc;
+        }
+        Ok(services)
+    };
+    match scope {
+        Scope::Workspace => services.retain(|s| !s.global),
+        Scope::Global => services = global()?,
+        // A workspace service wins over a global one of the same name
+        Scope::All => {
+            for service in global()? {
+                if !services.iter().any(|s| s.name == service.name) {
+                    services.push(service);
+                }
+            }
+        }
+    }
+    Ok(services)
+}
+
+// Logon delay of a svc-mediated start: startup_order × startup_stagger, with services that
+// have no order going after all that do, plus the service's own logon_delay
+pub fn startup_delay(config: &Config, service: &Service) -> Option<Duration> {
+    let own = service.logon_delay.as_ref().map(units::HumanDuration::get);
+    let Some(stagger) = config.settings.startup_stagger.as_ref().map(units::HumanDuration::get) else {
+        return own;
+    };
+    let order = service.startup_order.unwrap_or_else(|| {
+        config
+            .services
+            .iter()
+            .filter_map(|s| s.startup_order)
+            .max()
+            .map_or(0, |max| max + 1)
+    });
+    Some(stagger * order + own.unwrap_or_default())
+}
+
+// Single-service commands go through the index and only parse their own entry
+pub fn find_service(path: &str, name: &str) -> Result<Service<'static>, SvcError> {
+    match index::load_service(Path::new(path), name) {
+        Ok(Some(service)) => return Ok(service),
+        // An entry that doesn't parse is explained by the full load, which names what is wrong with it,
+        // and skipped by it when lenient; a name the file lacks may be an adhoc or extended service
+        Ok(None) | Err(SvcError::YamlError(_) | SvcError::ServiceNotFound { .. }) => {}
+        Err(err) => return Err(err),
+    }
+
+    let mut config = load_config(path)?;
+    match config.iter().position(|s| s.name == name) {
+        Some(at) => Ok(config.swap_remove(at)),
+        None => Err(not_found(&config, name)),
+    }
+}
+
+// What status, enable and disable read of the config: through the index, the one entry under
+// the default settings a bare list has, when it needs none of the others; else the full load
+pub fn find_document(path: &str, name: &str) -> Result<Config<'static>, SvcError> {
+    match index::load_standalone(Path::new(path), name) {
+        Ok(Some(service)) => {
+            return Ok(Config {
+                services: vec![service],
+                ..Config::default()
+            })
+        }
+        Ok(None) | Err(SvcError::YamlError(_) | SvcError::ServiceNotFound { .. }) => {}
+        Err(err) => return Err(err),
+    }
+    load_document(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_fill_in_optional_fields() {
+        let config = parse_config("- name: api\n  path: C:\\api\\api.exe\n  type: Executable\n").unwrap();
+        let service = &config.services[0];
+        assert_eq!(service.interpreter, "python");
+        assert_eq!(service.work_at, "");
+        assert!(service.args.is_empty());
+        assert!(!service.protected);
+        assert!(service.startup.is_none());
+    }
+
+    #[test]
+    fn settings_form_and_bare_list_parse_alike() {
+        let bare = parse_config("- name: job\n  path: job.py\n  type: Util\n").unwrap();
+        let mapping = parse_config("services:\n  - name: job\n    path: job.py\n    type: Util\n").unwrap();
+        assert_eq!(bare.services[0].name, mapping.services[0].name);
+        assert!(matches!(mapping.services[0].service_type, ServiceType::Util));
+    }
+
+    #[test]
+    fn empty_config_has_no_services() {
+        assert!(parse_config("").unwrap().services.is_empty());
+        assert!(matches!(not_found(&[], "api"), SvcError::NoServicesConfigured(name) if name == "api"));
+    }
+
+    #[test]
+    fn entry_without_type_names_the_service() {
+        let err = parse_config("- name: api\n  path: api.exe\n").unwrap_err();
+        assert!(matches!(err, SvcError::InvalidEntry { service, .. } if service == "api"));
+    }
+
+    #[test]
+    fn unnamed_entry_is_reported_by_position() {
+        let err = parse_config("- name: ok\n  path: a.exe\n  type: Executable\n- path: b.exe\n").unwrap_err();
+        assert!(matches!(err, SvcError::InvalidEntry { service, .. } if service == "#2"));
+    }
+
+    #[test]
+    fn unknown_service_suggests_a_close_name() {
+        let config = parse_config("- name: backend\n  path: b.exe\n  type: Executable\n").unwrap();
+        match config.service("backnd") {
+            Err(SvcError::ServiceNotFound { name, suggestion }) => {
+                assert_eq!(name, "backnd");
+                assert_eq!(suggestion.as_deref(), Some("backend"));
+            }
+            other => panic!("unexpected {:?}", other.map(|service| service.name.to_string())),
+        }
+    }
+
+    #[test]
+    fn lenient_parsing_skips_broken_entries_and_keeps_the_rest() {
+        let config = parse(
+            "templates:
+  bot: { type: Executable, path: 'bot-{{port}}.exe' }
+shells:
+  pwsh: pwsh -File
+services:
+  - { name: api, path: api.exe, type: Executable }
+  - { name: notype, path: b.exe }
+  - { name: job, path: job.ps1, type: Util, shell: pwsh }
+  - { name: oddshell, path: o.ps1, type: Util, shell: zsh }
+  - { path: unnamed.exe, type: Sometimes }
+  - { name: bot1, instances_of: bot, vars: { port: 8001 } }
+  - { name: bot2, instances_of: robot }
+",
+            true,
+        )
+        .unwrap();
+        let names: Vec<&str> = config.services.iter().map(|s| s.name.as_ref()).collect();
+        assert_eq!(names, ["api", "job", "bot1"]);
+        let skipped: Vec<&str> = config.skipped.iter().map(|entry| entry.service.as_str()).collect();
+        assert_eq!(skipped, ["notype", "oddshell", "#5", "bot2"]);
+        assert_eq!(config.services[1].interpreter, "pwsh");
+
+        match config.service("notype") {
+            Err(SvcError::InvalidEntry { service, reason }) => {
+                assert_eq!(service, "notype");
+                assert!(reason.contains("type"), "{}", reason);
+            }
+            other => panic!("unexpected {:?}", other.map(|service| service.name.to_string())),
+        }
+        assert!(matches!(config.service("nope"), Err(SvcError::ServiceNotFound { .. })));
+    }
+
+    #[test]
+    fn lenient_is_also_a_setting_and_strict_is_the_default() {
+        let broken = "services:\n  - { name: api, path: a.exe, type: Executable }\n  - { name: b, path: b.exe }\n";
+        assert!(matches!(parse_config(broken), Err(SvcError::InvalidEntry { .. })));
+        let config = parse_config(&format!("lenient: true\n{}", broken)).unwrap();
+        assert_eq!(config.services.len(), 1);
+        assert_eq!(config.skipped.len(), 1);
+    }
+
+    #[test]
+    fn settings_are_read_without_the_services() {
+        // An entry that wouldn't parse, or a template it names that doesn't exist, is no matter here
+        let config = "startup_stagger: 5s\ncommand_aliases: { up: run }\nservices:\n  - { name: b, template: none }\n";
+        let settings = parse_settings(config).unwrap();
+        assert_eq!(settings.command_aliases["up"], "run");
+        assert!(settings.startup_stagger.is_some());
+        assert!(parse_settings("# services\n- { name: b }\n").unwrap().command_aliases.is_empty());
+        assert!(parse_settings("").unwrap().command_aliases.is_empty());
+        let shadowing = "command_aliases: { status: list }\nservices: []\n";
+        assert!(matches!(parse_settings(shadowing), Err(SvcError::AliasShadowsCommand(_))));
+    }
+
+    #[test]
+    fn watch_keeps_removed_services_running_unless_the_config_says_otherwise() {
+        assert!(!parse_config("services: []\n").unwrap().settings.kill_removed);
+        assert!(parse_config("kill_removed: true\nservices: []\n").unwrap().settings.kill_removed);
+    }
+
+    #[test]
+    fn title_keeps_the_name_next_to_the_label() {
+        let config = parse_config("- name: api\n  path: a.exe\n  type: Executable\n  label: Public API\n").unwrap();
+        assert_eq!(config.services[0].title(), "Public API (api)");
+    }
+}
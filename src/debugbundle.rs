@@ -0,0 +1,274 @@
+use crate::output::{error_object, timestamp};
+use crate::probe::{self, Capability};
+use crate::{config_path, read_config, SvcError, VERSION};
+use colored::Colorize;
+use serde::Serialize;
+use serde_yaml::Value;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+const REDACTED: &str = "<redacted>";
+// Shorter values such as MODE=1 are only redacted in the config, or every 1 would go
+const MIN_REDACTED_LEN: usize = 4;
+
+// A helper svc ran, as it ended
+#[derive(Serialize)]
+struct Executed {
+    program: String,
+    args: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    stdout: String,
+    stderr: String,
+    millis: u128,
+}
+
+#[derive(Serialize)]
+struct Process {
+    pid: u64,
+    name: String,
+    path: String,
+}
+
+#[derive(Serialize)]
+struct Phase {
+    name: String,
+    millis: u128,
+}
+
+// What this invocation did, kept only with --debug-bundle
+#[derive(Default)]
+struct Session {
+    path: PathBuf,
+    commands: Vec<Executed>,
+    snapshot: Vec<Process>,
+    phases: Vec<Phase>,
+    // Secret values read this invocation, redacted wherever they show up
+    secrets: Vec<String>,
+}
+
+static SESSION: OnceLock<Mutex<Session>> = OnceLock::new();
+
+pub fn enable(path: PathBuf) {
+    let _ = SESSION.set(Mutex::new(Session {
+        path,
+        ..Session::default()
+    }));
+}
+
+fn with(record: impl FnOnce(&mut Session)) {
+    if let Some(Ok(mut session)) = SESSION.get().map(Mutex::lock) {
+        record(&mut session);
+    }
+}
+
+pub fn command(command: &Command, output: &std::io::Result<Output>, took: Duration) {
+    with(|session| {
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).to_string();
+        session.commands.push(Executed {
+            program: command.get_program().to_string_lossy().to_string(),
+            args: command.get_args().map(|arg| arg.to_string_lossy().to_string()).collect(),
+            exit_code: output.as_ref().ok().and_then(|output| output.status.code()),
+            error: output.as_ref().err().map(ToString::to_string),
+            stdout: output.as_ref().map_or_else(|_| String::new(), |output| text(&output.stdout)),
+            stderr: output.as_ref().map_or_else(|_| String::new(), |output| text(&output.stderr)),
+            millis: took.as_millis(),
+        })
+    });
+}
+
+pub fn enabled() -> bool {
+    SESSION.get().is_some()
+}
+
+// PID, file name and image path of every process; the latest snapshot replaces the one before
+pub fn snapshot(processes: Vec<(u64, String, String)>) {
+    with(|session| {
+        session.snapshot = processes
+            .into_iter()
+            .map(|(pid, name, path)| Process { pid, name, path })
+            .collect()
+    });
+}
+
+pub fn phase(name: &str, started: Instant) {
+    with(|session| {
+        session.phases.push(Phase {
+            name: name.to_string(),
+            millis: started.elapsed().as_millis(),
+        })
+    });
+}
+
+pub fn sensitive(value: &str) {
+    with(|session| session.secrets.push(value.to_string()));
+}
+
+// Env values, secret references and webhook URLs anywhere in the document, replaced in place
+// and returned so they can be redacted from everything else too
+fn redact_document(value: &mut Value, found: &mut Vec<String>) {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping.iter_mut() {
+                match key.as_str() {
+                    Some("env") => {
+                        if let Value::Mapping(env) = value {
+                            env.iter_mut().for_each(|(_, value)| redact_scalar(value, found));
+                        }
+                    }
+                    Some("password_secret" | "url") => redact_scalar(value, found),
+                    _ => redact_document(value, found),
+                }
+            }
+        }
+        Value::Sequence(items) => items.iter_mut().for_each(|item| redact_document(item, found)),
+        Value::Tagged(tagged) => redact_document(&mut tagged.value, found),
+        _ => {}
+    }
+}
+
+fn redact_scalar(value: &mut Value, found: &mut Vec<String>) {
+    let text = match value {
+        Value::String(text) => text.clone(),
+        Value::Number(number) => number.to_string(),
+        _ => return,
+    };
+    if !text.is_empty() {
+        found.push(text);
+    }
+    *value = Value::String(REDACTED.to_string());
+}
+
+// The config with its sensitive values redacted, and those values; a config that doesn't
+// parse can't be told apart from its secrets, so only the parse error goes in
+fn redact_config(content: &str) -> (String, Vec<String>) {
+    let mut found = Vec::new();
+    match serde_yaml::from_str::<Value>(content) {
+        Ok(mut document) => {
+            redact_document(&mut document, &mut found);
+            (serde_yaml::to_string(&document).unwrap_or_default(), found)
+        }
+        Err(err) => (format!("# the config doesn't parse: {}\n", err), found),
+    }
+}
+
+// Longest first, so a value containing another is still replaced whole
+fn redact(text: &str, sensitive: &[String]) -> String {
+    let mut values: Vec<&String> = sensitive.iter().filter(|value| value.len() >= MIN_REDACTED_LEN).collect();
+    values.sort_by_key(|value| std::cmp::Reverse(value.len()));
+    values.iter().fold(text.to_string(), |text, value| text.replace(value.as_str(), REDACTED))
+}
+
+fn to_json<T: Serialize + ?Sized>(value: &T) -> Result<String, SvcError> {
+    Ok(serde_json::to_string_pretty(value).map_err(std::io::Error::other)?)
+}
+
+fn write(session: &Session, capabilities: &[serde_json::Value], result: &Result<(), SvcError>) -> Result<(), SvcError> {
+    let (config, mut sensitive) = match config_path() {
+        Ok(path) => match read_config(Path::new(&path)) {
+            Ok(content) => redact_config(&content),
+            Err(err) => (format!("# {} can't be read: {}\n", path, err), Vec::new()),
+        },
+        Err(err) => (format!("# no config: {}\n", err), Vec::new()),
+    };
+    sensitive.extend(session.secrets.iter().cloned());
+
+    let manifest = serde_json::json!({
+        "svc_version": VERSION,
+        "created": timestamp(),
+        "args": std::env::args().collect::<Vec<_>>(),
+    });
+    let result = match result {
+        Ok(()) => serde_json::json!({ "ok": true }),
+        Err(err) => serde_json::json!({ "ok": false, "error": error_object(err) }),
+    };
+    let files: [(&str, String); 7] = [
+        ("manifest.json", to_json(&manifest)?),
+        ("config.yaml", config),
+        ("capabilities.json", to_json(capabilities)?),
+        ("commands.json", to_json(&session.commands)?),
+        ("processes.json", to_json(&session.snapshot)?),
+        ("phases.json", to_json(&session.phases)?),
+        ("result.json", to_json(&result)?),
+    ];
+
+    let mut zip = ZipWriter::new(File::create(&session.path)?);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, content) in &files {
+        zip.start_file(*name, options)?;
+        zip.write_all(redact(content, &sensitive).as_bytes())?;
+    }
+    zip.finish()?.sync_all()?;
+    Ok(())
+}
+
+// Once the command is done, however it went
+pub fn finish(result: &Result<(), SvcError>) {
+    let Some(lock) = SESSION.get() else {
+        return;
+    };
+    // Probing runs helpers of its own, which are recorded too
+    let capabilities: Vec<_> = Capability::ALL
+        .iter()
+        .map(|&capability| serde_json::json!({ "capability": capability, "missing": probe::missing(capability) }))
+        .collect();
+    // Out of the lock, as writing may run helpers that would record themselves
+    let Ok(session) = lock.lock().map(|mut session| std::mem::take(&mut *session)) else {
+        return;
+    };
+    match write(&session, &capabilities, result) {
+        Ok(()) => eprintln!("Debug bundle written to {}.", session.path.display().to_string().cyan()),
+        Err(err) => eprintln!("{} could not write the debug bundle: {}", "warning:".yellow().bold(), err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+notify:
+  - kind: webhook
+    url: https://hooks.example.com/abc
+services:
+  - name: api
+    path: C:\\api\\api.exe
+    type: Executable
+    env:
+      API_TOKEN: hunter2-token
+    run_as:
+      user: svc
+      password_secret: api-password
+";
+
+    #[test]
+    fn redacts_env_values_secret_references_and_webhooks() {
+        let (redacted, sensitive) = redact_config(CONFIG);
+        for value in ["hunter2-token", "api-password", "https://hooks.example.com/abc"] {
+            assert!(!redacted.contains(value), "{} is in\n{}", value, redacted);
+            assert!(sensitive.iter().any(|found| found == value));
+        }
+        assert!(redacted.contains("C:\\api\\api.exe"));
+
+        // The same value anywhere else in the bundle, such as a helper's command line
+        let command = "powershell -Command Invoke-RestMethod -Uri https://hooks.example.com/abc -Body hunter2-token";
+        assert_eq!(
+            redact(command, &sensitive),
+            "powershell -Command Invoke-RestMethod -Uri <redacted> -Body <redacted>"
+        );
+    }
+
+    #[test]
+    fn a_config_that_does_not_parse_is_left_out() {
+        let (redacted, _) = redact_config("- name: api\n  env: [API_TOKEN: hunter2\n");
+        assert!(!redacted.contains("hunter2"));
+    }
+}
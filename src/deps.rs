@@ -0,0 +1,226 @@
+use crate::{cancel, effects, get_pids, load_config, progress, run_service, RunOverride, Service, ServiceType, SvcError};
+use colored::Colorize;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+// How long `svc run` waits for each dependency it started to show a process
+const START_TIMEOUT: Duration = Duration::from_secs(30);
+const START_POLL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Running,
+    Down,
+    // Running, but something it depends on is not
+    Degraded,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Dependency<'a> {
+    pub name: &'a str,
+    pub state: State,
+}
+
+fn invalid(service: &Service, reason: String) -> SvcError {
+    SvcError::InvalidDependency {
+        service: service.name.to_string(),
+        reason,
+    }
+}
+
+// Checked at load: every dependency exists and runs as a process, and there are no cycles
+pub fn validate<'a>(services: &'a [Service<'a>]) -> Result<(), SvcError> {
+    let by_name: HashMap<&str, &Service> = services.iter().map(|s| (s.name.as_ref(), s)).collect();
+    for service in services {
+        for dependency in &service.depends_on {
+            match by_name.get(dependency.as_ref()) {
+                None => return Err(invalid(service, format!("{} is not defined", dependency))),
+                Some(target) if matches!(target.service_type, ServiceType::Util) => {
+                    return Err(invalid(service, format!("{} is a utility and never stays running", dependency)))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    // Depth-first walk; a service met again while still on the path closes a cycle
+    fn visit<'a>(
+        name: &'a str,
+        by_name: &HashMap<&'a str, &'a Service<'a>>,
+        path: &mut Vec<&'a str>,
+        done: &mut HashSet<&'a str>,
+    ) -> Result<(), SvcError> {
+        if done.contains(name) {
+            return Ok(());
+        }
+        if let Some(start) = path.iter().position(|&n| n == name) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(name);
+            return Err(invalid(by_name[name], format!("dependency cycle {}", cycle.join(" -> "))));
+        }
+        path.push(name);
+        for dependency in &by_name[name].depends_on {
+            visit(dependency, by_name, path, done)?;
+        }
+        path.pop();
+        done.insert(name);
+        Ok(())
+    }
+
+    let mut done = HashSet::new();
+    for service in services {
+        visit(&service.name, &by_name, &mut Vec::new(), &mut done)?;
+    }
+    Ok(())
+}
+
+// Everything a service needs, directly or through other dependencies
+pub fn closure<'a>(services: &'a [Service<'a>], service: &Service) -> Vec<&'a Service<'a>> {
+    let mut found: Vec<&'a Service<'a>> = Vec::new();
+    let mut pending: Vec<&str> = service.depends_on.iter().map(|d| d.as_ref()).collect();
+    while let Some(name) = pending.pop() {
+        if found.iter().any(|s| s.name == name) {
+            continue;
+        }
+        if let Some(dependency) = services.iter().find(|s| s.name == name) {
+            pending.extend(dependency.depends_on.iter().map(|d| d.as_ref()));
+            found.push(dependency);
+        }
+    }
+    found
+}
+
+// The closure in start order: every service after everything it depends on
+fn start_order<'a>(services: &'a [Service<'a>], service: &Service) -> Vec<&'a Service<'a>> {
+    fn visit<'a>(services: &'a [Service<'a>], name: &str, order: &mut Vec<&'a Service<'a>>, seen: &mut HashSet<String>) {
+        // Cycles are rejected at load; the seen set only keeps this from looping regardless
+        if !seen.insert(name.to_string()) {
+            return;
+        }
+        let Some(service) = services.iter().find(|s| s.name == name) else {
+            return;
+        };
+        for dependency in &service.depends_on {
+            visit(services, dependency, order, seen);
+        }
+        order.push(service);
+    }
+
+    let mut order = Vec::new();
+    let mut seen = HashSet::from([service.name.to_string()]);
+    for dependency in &service.depends_on {
+        visit(services, dependency, &mut order, &mut seen);
+    }
+    order
+}
+
+fn wait_running(service: &Service, dependent: &Service) -> Result<(), SvcError> {
+    let deadline = Instant::now() + START_TIMEOUT;
+    let mut phase = progress::Phase::begin("waiting", &dependent.name, "dependency");
+    while get_pids(service)?.is_empty() {
+        if Instant::now() >= deadline {
+            return Err(SvcError::DependencyTimeout {
+                service: dependent.name.to_string(),
+                dependency: service.name.to_string(),
+                waited: format!("{}s", START_TIMEOUT.as_secs_f64()),
+            });
+        }
+        cancel::sleep(START_POLL)?;
+        phase.tick();
+    }
+    Ok(())
+}
+
+// Before `svc run`: start whatever the service depends on that isn't running, deepest
+// first, each once the ones it needs have processes
+pub fn start_dependencies(config_path: &str, service: &Service) -> Result<(), SvcError> {
+    if service.depends_on.is_empty() {
+        return Ok(());
+    }
+    let services = load_config(config_path)?;
+    for dependency in start_order(&services, service) {
+        if !get_pids(dependency)?.is_empty() {
+            continue;
+        }
+        println!("Starting {}, which {} depends on.", dependency.name.cyan(), service.name.cyan());
+        run_service(dependency, &RunOverride::default())?;
+        // A dry run started nothing to wait for
+        if !effects::dry_run() {
+            wait_running(dependency, service)?;
+        }
+    }
+    Ok(())
+}
+
+// State of each direct dependency, given which services have processes
+pub fn evaluate<'a>(services: &'a [Service<'a>], running: &HashMap<&str, bool>, service: &Service) -> Vec<Dependency<'a>> {
+    fn state(services: &[Service], running: &HashMap<&str, bool>, name: &str, seen: &mut HashSet<String>) -> State {
+        if !running.get(name).copied().unwrap_or(false) {
+            return State::Down;
+        }
+        // Cycles are rejected at load; the seen set only keeps this from looping regardless
+        if !seen.insert(name.to_string()) {
+            return State::Running;
+        }
+        let Some(service) = services.iter().find(|s| s.name == name) else {
+            return State::Running;
+        };
+        let healthy = service
+            .depends_on
+            .iter()
+            .all(|d| state(services, running, d, seen) == State::Running);
+        if healthy {
+            State::Running
+        } else {
+            State::Degraded
+        }
+    }
+
+    service
+        .depends_on
+        .iter()
+        .filter_map(|name| services.iter().find(|s| s.name == *name))
+        .map(|dependency| Dependency {
+            name: &dependency.name,
+            state: state(services, running, &dependency.name, &mut HashSet::new()),
+        })
+        .collect()
+}
+
+// Own state of a service, degraded when it runs on top of a dependency that doesn't
+pub fn own_state(running: bool, dependencies: &[Dependency]) -> State {
+    if !running {
+        State::Down
+    } else if dependencies.iter().any(|d| d.state != State::Running) {
+        State::Degraded
+    } else {
+        State::Running
+    }
+}
+
+// Why a service counts as degraded, e.g. "dependency db not running"
+pub fn reason(dependencies: &[Dependency]) -> Option<String> {
+    dependencies.iter().find_map(|d| match d.state {
+        State::Running => None,
+        State::Down => Some(format!("dependency {} not running", d.name)),
+        State::Degraded => Some(format!("dependency {} degraded", d.name)),
+    })
+}
+
+// Dependencies of one service, from a single status pass over everything it needs
+pub fn states<'a>(services: &'a [Service<'a>], service: &Service) -> Result<Vec<Dependency<'a>>, SvcError> {
+    let needed = closure(services, service);
+    let statuses = needed
+        .par_iter()
+        .map(|s| get_pids(s))
+        .collect::<Result<Vec<_>, SvcError>>()?;
+    let running: HashMap<&str, bool> = needed
+        .iter()
+        .zip(&statuses)
+        .map(|(s, pids)| (s.name.as_ref(), !pids.is_empty()))
+        .collect();
+    Ok(evaluate(services, &running, service))
+}
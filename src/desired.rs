@@ -0,0 +1,399 @@
+use crate::cli::SessionFilter;
+use crate::process::{self, RunOutcome, RunOverride};
+use crate::history::{self, HistoryRecord};
+use crate::probe::{self, Capability};
+use crate::{apply, confirm_protected, edit, get_pids, load_document, output, progress, startup, stop_service};
+use crate::{Config, ServiceType, SvcError};
+use colored::Colorize;
+use serde::Serialize;
+use serde_json::json;
+use serde_yaml::Value;
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+// Where the desired-state document comes from
+pub enum Source {
+    File(PathBuf),
+    Stdin,
+}
+
+pub struct ApplyOptions {
+    pub dry_run: bool,
+    // Remove config entries the document doesn't list
+    pub prune: bool,
+    // Stop and remove protected services without asking
+    pub force: bool,
+}
+
+// One service of the document: its services.yaml entry, and whether it should be running
+struct Desired {
+    name: String,
+    entry: Value,
+    running: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Action {
+    Add,
+    Update,
+    Remove,
+    Startup,
+    Start,
+    Stop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    // Found by a dry run
+    Planned,
+    Applied,
+    Failed,
+    // Not tried, because something it depends on failed
+    Skipped,
+}
+
+#[derive(Serialize)]
+struct Change {
+    service: String,
+    action: Action,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Change {
+    fn new(service: &str, action: Action, detail: Option<String>) -> Self {
+        Change {
+            service: service.to_string(),
+            action,
+            detail,
+            status: Status::Planned,
+            error: None,
+        }
+    }
+
+    // Carry out the change unless this is a dry run or `skip` says why it can't be tried;
+    // what it returns replaces the detail
+    fn run(&mut self, dry_run: bool, skip: Option<&str>, apply: impl FnOnce() -> Result<Option<String>, SvcError>) {
+        if let Some(why) = skip {
+            self.status = Status::Skipped;
+            self.error = Some(why.to_string());
+            return;
+        }
+        if dry_run {
+            return;
+        }
+        progress::emit("applying", Some(&self.service), json!({ "action": self.action }));
+        match apply() {
+            Ok(detail) => {
+                self.status = Status::Applied;
+                if detail.is_some() {
+                    self.detail = detail;
+                }
+            }
+            Err(err) => self.fail(&err),
+        }
+        progress::emit("applied", Some(&self.service), json!({ "action": self.action, "status": self.status }));
+    }
+
+    fn fail(&mut self, err: &SvcError) {
+        self.status = Status::Failed;
+        self.error = Some(err.to_string());
+    }
+
+    fn pending(&self) -> bool {
+        self.status == Status::Planned
+    }
+}
+
+fn invalid(reason: impl Into<String>) -> SvcError {
+    SvcError::InvalidDesiredState(reason.into())
+}
+
+fn read(source: &Source) -> Result<String, SvcError> {
+    Ok(match source {
+        Source::File(path) => std::fs::read_to_string(path)?,
+        Source::Stdin => {
+            let mut content = String::new();
+            std::io::stdin().read_to_string(&mut content)?;
+            content
+        }
+    })
+}
+
+// `{"services": [...]}`, each entry as in services.yaml plus an optional `running`
+fn parse(content: &str) -> Result<Vec<Desired>, SvcError> {
+    let document: serde_json::Value = serde_json::from_str(content).map_err(|err| invalid(err.to_string()))?;
+    let entries = document
+        .get("services")
+        .and_then(serde_json::Value::as_array)
+        .ok_or_else(|| invalid("it has no services list"))?;
+
+    let mut names = HashSet::new();
+    let mut desired = Vec::with_capacity(entries.len());
+    for (at, entry) in entries.iter().enumerate() {
+        let mut entry = entry.clone();
+        let object = entry
+            .as_object_mut()
+            .ok_or_else(|| invalid(format!("service #{} is not an object", at + 1)))?;
+        let running = match object.remove("running") {
+            None => None,
+            Some(serde_json::Value::Bool(running)) => Some(running),
+            Some(_) => return Err(invalid(format!("running of service #{} is not true or false", at + 1))),
+        };
+        let name = object
+            .get("name")
+            .and_then(serde_json::Value::as_str)
+            .ok_or_else(|| invalid(format!("service #{} has no name", at + 1)))?
+            .to_string();
+        if !names.insert(name.clone()) {
+            return Err(invalid(format!("{} is listed twice", name)));
+        }
+        desired.push(Desired {
+            name,
+            entry: serde_yaml::to_value(&entry)?,
+            running,
+        });
+    }
+    Ok(desired)
+}
+
+fn entry_name(entry: &Value) -> Option<&str> {
+    entry.get("name").and_then(Value::as_str)
+}
+
+// Top-level fields whose values differ, those the new entry drops included
+fn changed_fields(old: &Value, new: &Value) -> Vec<String> {
+    let (Some(old), Some(new)) = (old.as_mapping(), new.as_mapping()) else {
+        return Vec::new();
+    };
+    let keys = new.keys().chain(old.keys().filter(|key| !new.contains_key(*key)));
+    keys.filter(|key| old.get(*key) != new.get(*key))
+        .filter_map(|key| key.as_str().map(String::from))
+        .collect()
+}
+
+// The config changes: every listed service added or updated, and with prune the unlisted removed
+fn diff_entries(raw: &[Value], desired: &[Desired], prune: bool) -> (Vec<Change>, Vec<String>) {
+    let mut changes = Vec::new();
+    let mut unchanged = Vec::new();
+    for service in desired {
+        match raw.iter().find(|entry| entry_name(entry) == Some(&service.name)) {
+            None => changes.push(Change::new(&service.name, Action::Add, None)),
+            Some(existing) if *existing == service.entry => unchanged.push(service.name.clone()),
+            Some(existing) => {
+                let fields = changed_fields(existing, &service.entry).join(", ");
+                changes.push(Change::new(&service.name, Action::Update, Some(fields)));
+            }
+        }
+    }
+    if prune {
+        for name in raw.iter().filter_map(entry_name) {
+            if !desired.iter().any(|service| service.name == name) {
+                changes.push(Change::new(name, Action::Remove, None));
+            }
+        }
+    }
+    (changes, unchanged)
+}
+
+// The config as the document leaves it, checked before anything changes
+fn check(path: &Path, desired: &[Desired], entries: &[Change]) -> Result<Config<'static>, SvcError> {
+    let set: Vec<Value> = desired
+        .iter()
+        .filter(|service| entries.iter().any(|change| change.service == service.name))
+        .map(|service| service.entry.clone())
+        .collect();
+    let removed: Vec<&str> = entries
+        .iter()
+        .filter(|change| change.action == Action::Remove)
+        .map(|change| change.service.as_str())
+        .collect();
+    let planned = edit::replace_entries(path, &set, &removed, true)?;
+    for service in &planned.services {
+        if let Some(dependency) = service.depends_on.iter().find(|name| removed.contains(&name.as_ref())) {
+            return Err(invalid(format!("{} depends on {}, which it removes", service.name, dependency)));
+        }
+    }
+    for service in desired.iter().filter(|service| service.running.is_some()) {
+        if matches!(planned.service(&service.name)?.service_type, ServiceType::Util) {
+            return Err(invalid(format!("{} is a Util, which has no running state", service.name)));
+        }
+    }
+    Ok(planned)
+}
+
+// The config changes in one write. A service that didn't stop keeps its entry, so svc can
+// still find and stop it; false when the write failed
+fn write_entries(path: &Path, current: &Config, desired: &[Desired], entries: &mut [Change], stops: &[Change]) -> bool {
+    for change in entries.iter_mut().filter(|change| change.action == Action::Remove) {
+        if stops.iter().any(|stop| stop.service == change.service && stop.status == Status::Failed) {
+            change.status = Status::Skipped;
+            change.error = Some("it is still running".to_string());
+        }
+    }
+    // A removed service's start-up entries would start a name nothing defines any more
+    for change in entries.iter_mut().filter(|change| change.action == Action::Remove && change.pending()) {
+        let Ok(service) = current.service(&change.service) else {
+            continue;
+        };
+        match startup::disable(service, None) {
+            Ok(_) | Err(SvcError::ServiceIsDisabled) => {}
+            Err(err) => change.fail(&err),
+        }
+    }
+
+    let set: Vec<Value> = desired
+        .iter()
+        .filter(|service| entries.iter().any(|change| change.service == service.name && change.pending()))
+        .map(|service| service.entry.clone())
+        .collect();
+    let removed: Vec<String> = entries
+        .iter()
+        .filter(|change| change.action == Action::Remove && change.pending())
+        .map(|change| change.service.clone())
+        .collect();
+    if set.is_empty() && removed.is_empty() {
+        return true;
+    }
+    let removed: Vec<&str> = removed.iter().map(String::as_str).collect();
+    let result = edit::replace_entries(path, &set, &removed, false).map(drop);
+    for change in entries.iter_mut().filter(|change| change.pending()) {
+        match &result {
+            Ok(()) => change.status = Status::Applied,
+            Err(err) => change.fail(err),
+        }
+    }
+    result.is_ok()
+}
+
+// A history record for each change that was tried
+fn record(changes: &[Change]) {
+    for change in changes.iter().filter(|change| matches!(change.status, Status::Applied | Status::Failed)) {
+        let mut entry = HistoryRecord::new(&change.service, "apply", change.status == Status::Applied);
+        entry.output.extend(change.error.clone());
+        let _ = history::record(&entry);
+    }
+}
+
+fn print_report(changes: &[Change], unchanged: &[String], dry_run: bool) -> Result<(), SvcError> {
+    if output::options().json {
+        let report = serde_json::json!({ "dry_run": dry_run, "changes": changes, "unchanged": unchanged });
+        println!("{}", serde_json::to_string_pretty(&report).map_err(std::io::Error::other)?);
+        return Ok(());
+    }
+    for change in changes {
+        let marker = match change.action {
+            Action::Add | Action::Start => "+".green(),
+            Action::Remove | Action::Stop => "-".red(),
+            Action::Update | Action::Startup => "~".yellow(),
+        };
+        let action = serde_json::to_value(change.action).ok();
+        let action = action.as_ref().and_then(serde_json::Value::as_str).unwrap_or_default();
+        let detail = change.detail.as_ref().map(|detail| format!(" ({})", detail)).unwrap_or_default();
+        let line = format!("  {} {} {}{}", marker, change.service.cyan(), action, detail);
+        match (change.status, &change.error) {
+            (Status::Failed, Some(error)) => println!("{}: {}", line, error.red()),
+            (Status::Skipped, Some(why)) => println!("{} {}", line, format!("skipped, {}", why).dimmed()),
+            _ => println!("{}", line),
+        }
+    }
+    println!(
+        "{} {} changed, {} unchanged.",
+        if dry_run { "Would apply:" } else { "Applied:" },
+        changes.iter().filter(|change| change.status != Status::Failed && change.status != Status::Skipped).count(),
+        unchanged.len()
+    );
+    Ok(())
+}
+
+// `svc apply`: converge the config, the start-up entries and the running services on a
+// desired-state document. Each change is reported as applied, failed or skipped, so applying
+// the same document again picks up where this left off
+pub fn apply(config_path: &str, source: &Source, options: &ApplyOptions) -> Result<(), SvcError> {
+    let desired = parse(&read(source)?)?;
+    let path = Path::new(config_path);
+    let current = load_document(config_path)?;
+    let (mut entries, unchanged) = diff_entries(&edit::raw_entries(path)?, &desired, options.prune);
+    // Checked in full before anything changes, so a bad document applies nothing
+    let planned = check(path, &desired, &entries)?;
+    // Start-up entries are reconciled last, after the config is written, so they are checked now
+    let reconciles = desired.iter().filter_map(|service| planned.service(&service.name).ok()).any(|s| s.startup.is_some());
+    if reconciles && !options.dry_run {
+        probe::require(Capability::StartupWrite)?;
+    }
+
+    // Stopped by the definitions they were started with, before their entries change
+    let stopping = desired.iter().filter(|service| service.running == Some(false)).map(|service| &service.name);
+    let removed = entries.iter().filter(|change| change.action == Action::Remove).map(|change| &change.service);
+    let mut changes = Vec::new();
+    for name in removed.chain(stopping) {
+        let service = current.service(name).or_else(|_| planned.service(name))?;
+        if get_pids(service)?.is_empty() {
+            continue;
+        }
+        let mut change = Change::new(name, Action::Stop, None);
+        change.run(options.dry_run, None, || {
+            confirm_protected(service, options.force)?;
+            stop_service(service, SessionFilter::All, options.force).map(|()| None)
+        });
+        changes.push(change);
+    }
+
+    let written = options.dry_run || write_entries(path, &current, &desired, &mut entries, &changes);
+    changes.append(&mut entries);
+    let config = match written && !options.dry_run {
+        true => load_document(config_path).unwrap_or(planned),
+        false => planned,
+    };
+    let skip = (!written).then_some("the config was not written");
+
+    for service in desired.iter().filter_map(|service| config.service(&service.name).ok()) {
+        match apply::reconcile(service, &config.settings, true) {
+            Ok(None) => {}
+            Ok(Some(detail)) => {
+                let mut change = Change::new(&service.name, Action::Startup, Some(detail));
+                change.run(options.dry_run, skip, || apply::reconcile(service, &config.settings, false));
+                changes.push(change);
+            }
+            Err(err) => {
+                let mut change = Change::new(&service.name, Action::Startup, None);
+                change.fail(&err);
+                changes.push(change);
+            }
+        }
+    }
+    for name in desired.iter().filter(|service| service.running == Some(true)).map(|service| &service.name) {
+        let service = config.service(name)?;
+        if !get_pids(service)?.is_empty() {
+            continue;
+        }
+        let mut change = Change::new(name, Action::Start, None);
+        change.run(options.dry_run, skip, || {
+            Ok(match process::start(&process::System, service, &RunOverride::default())? {
+                RunOutcome::Started { pid, .. } => Some(format!("PID {}", pid)),
+                RunOutcome::Finished { .. } => None,
+            })
+        });
+        changes.push(change);
+    }
+
+    record(&changes);
+    print_report(&changes, &unchanged, options.dry_run)?;
+    let mut failed: Vec<String> = Vec::new();
+    for change in changes.iter().filter(|change| matches!(change.status, Status::Failed | Status::Skipped)) {
+        if !failed.contains(&change.service) {
+            failed.push(change.service.clone());
+        }
+    }
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(SvcError::ApplyIncomplete { failed })
+    }
+}
@@ -0,0 +1,302 @@
+use crate::artifacts::{owned, scratch_root};
+use crate::history::{self, HistoryRecord};
+use crate::state::{self, prev_path};
+use crate::units::{format_bytes, ByteSize};
+use crate::{adhoc, effects, logfile, logs, metrics, output, scratch, warnings, Service, SvcError};
+use colored::Colorize;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+// `disk_budget: 200M` caps what svc keeps for a service in its own directories: the state
+// directory, its scratch directories and any log svc's directories hold. Over it, the oldest of
+// what can go is pruned, metrics ring first, then rotated logs, then the directories kept from
+// failed runs; the live log, the newest failed run and the rest of the state are never pruned.
+// A log configured elsewhere isn't svc's, so it neither counts nor goes. Nor do history records:
+// history.jsonl is every service's, and each pruning is recorded in it
+
+// The `disk_budget` setting, for services without their own
+static DEFAULT: OnceLock<ByteSize> = OnceLock::new();
+
+pub fn set_default(budget: ByteSize) {
+    let _ = DEFAULT.set(budget);
+}
+
+pub fn of(service: &Service) -> Option<u64> {
+    service.disk_budget.as_ref().or(DEFAULT.get()).map(ByteSize::bytes)
+}
+
+// What an artifact is, in the order pruning takes them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Metrics,
+    RotatedLog,
+    // A scratch directory kept for inspection after a failed run
+    FailedRun,
+    // Never pruned
+    Log,
+    State,
+}
+
+impl Kind {
+    fn prunable(self) -> bool {
+        matches!(self, Kind::Metrics | Kind::RotatedLog | Kind::FailedRun)
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Kind::Metrics => "metrics",
+            Kind::RotatedLog => "rotated log",
+            Kind::FailedRun => "failed run",
+            Kind::Log => "log",
+            Kind::State => "state",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Artifact {
+    pub kind: Kind,
+    pub path: PathBuf,
+    pub bytes: u64,
+    #[serde(skip)]
+    modified: SystemTime,
+}
+
+// A file's size, or everything under a directory
+fn size(path: &Path) -> u64 {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::read_dir(path)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| size(&entry.path()))
+            .sum(),
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    }
+}
+
+fn artifact(kind: Kind, path: PathBuf) -> Artifact {
+    let modified = fs::metadata(&path).and_then(|metadata| metadata.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+    Artifact {
+        kind,
+        bytes: size(&path),
+        path,
+        modified,
+    }
+}
+
+// A log and its `.1` generation, when they are inside svc's directories
+fn log_artifacts(log: &Path, artifacts: &mut Vec<Artifact>) -> Result<(), SvcError> {
+    for (kind, path) in [(Kind::Log, log.to_path_buf()), (Kind::RotatedLog, logfile::rotated_path(log))] {
+        if path.is_file() && owned(&path)? {
+            artifacts.push(artifact(kind, path));
+        }
+    }
+    Ok(())
+}
+
+// Everything of the service's that svc keeps and the budget counts
+pub fn artifacts(service: &Service) -> Result<Vec<Artifact>, SvcError> {
+    let mut artifacts = Vec::new();
+    let dir = state::service_path(&service.name)?;
+    // The ring and the previous version `replace` keeps of it go together, or reading it would
+    // fall back to the older one
+    let ring = dir.join("metrics.jsonl");
+    for entry in fs::read_dir(&dir).into_iter().flatten().filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if path == prev_path(&ring) {
+            continue;
+        }
+        let kind = if path == ring { Kind::Metrics } else { Kind::State };
+        let mut found = artifact(kind, path);
+        if kind == Kind::Metrics {
+            found.bytes += size(&prev_path(&ring));
+        }
+        artifacts.push(found);
+    }
+    // Samples left where they were kept before each service had its own directory
+    let legacy = metrics::metrics_dir()?.join(format!("{}.jsonl", service.name));
+    if legacy.is_file() {
+        artifacts.push(artifact(Kind::Metrics, legacy));
+    }
+    log_artifacts(&adhoc::log_path(&service.name)?, &mut artifacts)?;
+    if let Some(log) = logs::path(service) {
+        if !artifacts.iter().any(|artifact| artifact.path == log) {
+            log_artifacts(&log, &mut artifacts)?;
+        }
+    }
+    if scratch_root()?.is_some() {
+        let runs = fs::read_dir(scratch::service_dir(&service.name)).into_iter().flatten();
+        for entry in runs.filter_map(|entry| entry.ok()) {
+            if entry.path().is_dir() {
+                artifacts.push(artifact(Kind::FailedRun, entry.path()));
+            }
+        }
+    }
+    Ok(artifacts)
+}
+
+// What to prune to get within `budget`, in order: by kind, then oldest first. The newest failed
+// run stays, as the one most likely still being looked into
+fn plan(artifacts: &[Artifact], budget: u64) -> Vec<&Artifact> {
+    let mut used: u64 = artifacts.iter().map(|artifact| artifact.bytes).sum();
+    let newest_run = artifacts
+        .iter()
+        .filter(|artifact| artifact.kind == Kind::FailedRun)
+        .max_by_key(|artifact| (artifact.modified, artifact.path.clone()));
+    let mut candidates: Vec<&Artifact> = artifacts
+        .iter()
+        .filter(|artifact| artifact.kind.prunable())
+        .filter(|artifact| !newest_run.is_some_and(|newest| std::ptr::eq(*artifact, newest)))
+        .collect();
+    candidates.sort_by_key(|artifact| (artifact.kind, artifact.modified, artifact.path.clone()));
+    let mut pruned = Vec::new();
+    for candidate in candidates {
+        if used <= budget {
+            break;
+        }
+        used -= candidate.bytes;
+        pruned.push(candidate);
+    }
+    pruned
+}
+
+fn prune(service: &Service, budget: u64) -> Result<(), SvcError> {
+    let artifacts = artifacts(service)?;
+    for artifact in plan(&artifacts, budget) {
+        let path = &artifact.path;
+        // Checked again right before deleting, as artifacts::remove does
+        if !owned(path)? {
+            return Err(SvcError::InvalidCommand(format!(
+                "pruning {}, which is outside svc's directories",
+                path.display()
+            )));
+        }
+        effects::delete(path)?;
+        if artifact.kind == Kind::Metrics && prev_path(path).exists() {
+            effects::delete(&prev_path(path))?;
+        }
+        eprintln!(
+            "Pruned {} ({}) of {}, over its disk budget of {}.",
+            path.display(),
+            format_bytes(artifact.bytes),
+            service.name.cyan(),
+            format_bytes(budget)
+        );
+        let mut record = HistoryRecord::new(&service.name, "prune", true);
+        record.output = vec![format!("{} ({})", path.display(), format_bytes(artifact.bytes))];
+        history::record(&record)?;
+    }
+    Ok(())
+}
+
+// After svc wrote something for the service; never fails what it wrote for, only warns
+pub fn enforce(service: &Service) {
+    let Some(budget) = of(service) else {
+        return;
+    };
+    if let Err(err) = prune(service, budget) {
+        warnings::warn("disk_budget", &service.name, format!("failed to keep to the disk budget: {}", err));
+    }
+}
+
+#[derive(Serialize)]
+struct Usage<'a> {
+    service: &'a str,
+    bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    budget: Option<u64>,
+    over: bool,
+    artifacts: Vec<Artifact>,
+}
+
+// `svc du`: each service's usage against its budget; with `enforce`, pruned to it first
+pub fn report(services: &[&Service], enforce: bool) -> Result<(), SvcError> {
+    let mut usages = Vec::new();
+    for service in services {
+        let budget = of(service);
+        if let Some(budget) = budget.filter(|_| enforce) {
+            prune(service, budget)?;
+        }
+        let artifacts = artifacts(service)?;
+        let bytes = artifacts.iter().map(|artifact| artifact.bytes).sum();
+        usages.push(Usage {
+            service: &service.name,
+            bytes,
+            budget,
+            over: budget.is_some_and(|budget| bytes > budget),
+            artifacts,
+        });
+    }
+
+    if output::options().json {
+        println!("{}", serde_json::to_string_pretty(&usages).map_err(std::io::Error::other)?);
+        return Ok(());
+    }
+    let width = usages.iter().map(|usage| output::width(usage.service)).max().unwrap_or(0);
+    for usage in &usages {
+        let name = output::pad(usage.service, width);
+        let used = format!("{:>7}", format_bytes(usage.bytes));
+        match usage.budget {
+            Some(budget) if usage.over => {
+                println!("{}  {} of {}  {}", name.yellow(), used, format_bytes(budget), "over".yellow().bold())
+            }
+            Some(budget) => println!("{}  {} of {}", name, used, format_bytes(budget)),
+            None => println!("{}  {}", name, used),
+        }
+        if output::options().verbose {
+            for artifact in &usage.artifacts {
+                let kind = artifact.kind.label();
+                println!("  {:>7}  {:<12}{}", format_bytes(artifact.bytes), kind, artifact.path.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(kind: Kind, path: &str, bytes: u64, age: u64) -> Artifact {
+        Artifact {
+            kind,
+            path: PathBuf::from(path),
+            bytes,
+            modified: SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000 - age),
+        }
+    }
+
+    fn pruned(artifacts: &[Artifact], budget: u64) -> Vec<&str> {
+        plan(artifacts, budget).iter().map(|artifact| artifact.path.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn pruning_goes_metrics_then_rotated_logs_then_old_failed_runs() {
+        let artifacts = [
+            at(Kind::FailedRun, "scratch/run-3", 100, 10),
+            at(Kind::FailedRun, "scratch/run-1", 100, 300),
+            at(Kind::FailedRun, "scratch/run-2", 100, 200),
+            at(Kind::RotatedLog, "adhoc-api.log.1", 100, 50),
+            at(Kind::Log, "adhoc-api.log", 500, 0),
+            at(Kind::Metrics, "metrics.jsonl", 100, 1),
+            at(Kind::Metrics, "metrics/api.jsonl", 100, 5),
+            at(Kind::State, "restart.pid", 100, 1000),
+        ];
+        // 1200 in all; within budget nothing goes
+        assert!(pruned(&artifacts, 1200).is_empty());
+        assert_eq!(pruned(&artifacts, 1100), ["metrics/api.jsonl"]);
+        assert_eq!(pruned(&artifacts, 950), ["metrics/api.jsonl", "metrics.jsonl", "adhoc-api.log.1"]);
+        // However small the budget, the live log, the newest failed run and the rest of the state stay
+        assert_eq!(
+            pruned(&artifacts, 0),
+            ["metrics/api.jsonl", "metrics.jsonl", "adhoc-api.log.1", "scratch/run-1", "scratch/run-2"]
+        );
+    }
+}
@@ -0,0 +1,369 @@
+use crate::capabilities::fields;
+use crate::cli::{self, RecoverChoice};
+use crate::priority::Priority;
+use crate::effects::{self, Effect};
+use crate::format::{lock_config, temp_path, to_canonical, write_config};
+use crate::{hosts, not_found, parse_config, read_config, startup, templates, Config, Service, ServiceType, SvcError};
+use colored::Colorize;
+use serde_yaml::{Mapping, Value};
+use std::fs;
+use std::path::Path;
+
+fn read_document(config_path: &Path) -> Result<Value, SvcError> {
+    Ok(serde_yaml::from_str(&read_config(config_path)?)?)
+}
+
+// The raw service entries, out of either config shape
+fn entries_mut(document: &mut Value) -> Option<&mut Vec<Value>> {
+    let services = match document {
+        Value::Mapping(mapping) => mapping.get_mut("services")?,
+        other => other,
+    };
+    services.as_sequence_mut()
+}
+
+// Mutable access to the raw entry of a service, keeping fields svc doesn't know about
+pub fn entry_mut<'a>(document: &'a mut Value, name: &str) -> Option<&'a mut Value> {
+    entries_mut(document)?
+        .iter_mut()
+        .find(|entry| entry.get("name").and_then(Value::as_str) == Some(name))
+}
+
+// Write priority and affinity into a service's entry, leaving the rest of it as is
+pub fn set_scheduling(config_path: &Path, name: &str, priority: Option<Priority>, affinity: Option<&[u32]>) -> Result<(), SvcError> {
+    let _lock = lock_config(config_path)?;
+    let mut document = read_document(config_path)?;
+    let entry = entry_mut(&mut document, name).ok_or_else(|| SvcError::ServiceNotFound {
+        name: name.to_string(),
+        suggestion: None,
+    })?;
+    if let Some(priority) = priority {
+        entry["priority"] = Value::String(priority.to_string());
+    }
+    if let Some(affinity) = affinity {
+        entry["affinity"] = Value::Sequence(affinity.iter().map(|&cpu| Value::from(cpu)).collect());
+    }
+    write_config(config_path, &document)?;
+
+    println!("Saved to the config of service {}.", name.cyan());
+    Ok(())
+}
+
+// Point a service at a new binary, leaving the rest of its entry as is
+pub fn set_path(config_path: &Path, name: &str, path: &str) -> Result<(), SvcError> {
+    let _lock = lock_config(config_path)?;
+    let mut document = read_document(config_path)?;
+    let entry = entry_mut(&mut document, name).ok_or_else(|| SvcError::ServiceNotFound {
+        name: name.to_string(),
+        suggestion: None,
+    })?;
+    entry["path"] = Value::String(path.to_string());
+    write_config(config_path, &document)?;
+
+    println!("Service {} now points at {}.", name.cyan(), path);
+    Ok(())
+}
+
+// Add an entry at the end of the service list, creating the list in an empty config
+fn push_entry(document: &mut Value, entry: Value) -> Result<(), SvcError> {
+    if document.is_null() {
+        *document = Value::Sequence(Vec::new());
+    }
+    if let Value::Mapping(mapping) = document {
+        mapping
+            .entry(Value::from("services"))
+            .or_insert_with(|| Value::Sequence(Vec::new()));
+    }
+    entries_mut(document)
+        .ok_or_else(|| SvcError::InvalidCommand("services in services.yaml is not a list".to_string()))?
+        .push(entry);
+    Ok(())
+}
+
+pub fn append_service(config_path: &Path, entry: Value) -> Result<(), SvcError> {
+    let _lock = lock_config(config_path)?;
+    let mut document = read_document(config_path)?;
+    push_entry(&mut document, entry)?;
+    write_config(config_path, &document)
+}
+
+// Write the document only if it still loads as a whole, so an edit can't leave a config
+// that every later command fails on
+fn write_checked(config_path: &Path, document: &Value) -> Result<(), SvcError> {
+    parse_config(&serde_yaml::to_string(document)?)?;
+    write_config(config_path, document)
+}
+
+// What `svc add` is given on the command line
+pub struct NewService {
+    pub name: String,
+    pub service_type: ServiceType,
+    pub path: String,
+    pub interpreter: Option<String>,
+    pub work_at: Option<String>,
+}
+
+// Paths as given on the command line are relative to the current directory, not to the config
+fn absolute(path: &str) -> String {
+    std::path::absolute(path).map_or_else(|_| path.to_string(), |path| path.display().to_string())
+}
+
+pub fn add(config_path: &Path, config: &[Service], new: NewService) -> Result<(), SvcError> {
+    if config.iter().any(|s| s.name == new.name.as_str()) {
+        return Err(SvcError::ServiceNameTaken(new.name));
+    }
+    if new.interpreter.is_some() && !matches!(new.service_type, ServiceType::Util) {
+        return Err(SvcError::InvalidCommand("add, --interpreter is only for --type util".to_string()));
+    }
+    let path = absolute(&new.path);
+    if !Path::new(&path).exists() {
+        eprintln!("{} {} does not exist (yet)", "warning:".yellow().bold(), path);
+    }
+    let mut entry = Mapping::new();
+    entry.insert("name".into(), new.name.clone().into());
+    entry.insert("type".into(), serde_yaml::to_value(&new.service_type)?);
+    entry.insert("path".into(), path.into());
+    if let Some(interpreter) = new.interpreter {
+        entry.insert("interpreter".into(), interpreter.into());
+    }
+    if let Some(work_at) = new.work_at {
+        entry.insert("work_at".into(), absolute(&work_at).into());
+    }
+
+    let _lock = lock_config(config_path)?;
+    let mut document = read_document(config_path)?;
+    push_entry(&mut document, Value::Mapping(entry))?;
+    write_checked(config_path, &document)?;
+
+    println!("Service {} added.", new.name.cyan());
+    Ok(())
+}
+
+// `svc set`: one field of one entry, with the value read as YAML; null drops the field
+pub fn set_field(config_path: &Path, config: &[Service], name: &str, field: &str, value: &str) -> Result<(), SvcError> {
+    if !config.iter().any(|s| s.name == name) {
+        return Err(not_found(config, name));
+    }
+    if field == "name" {
+        return Err(SvcError::InvalidCommand("set name; use 'svc rename' instead".to_string()));
+    }
+    let known = fields::<Service>();
+    if !known.contains(&field) {
+        let suggestion = cli::suggest(field, known.iter().copied())
+            .map(|known| format!(", did you mean {}?", known))
+            .unwrap_or_default();
+        return Err(SvcError::InvalidCommand(format!("set, {} is not a service field{}", field, suggestion)));
+    }
+    let value: Value = serde_yaml::from_str(value)?;
+
+    let _lock = lock_config(config_path)?;
+    let mut document = read_document(config_path)?;
+    let entry = entry_mut(&mut document, name)
+        .and_then(Value::as_mapping_mut)
+        .ok_or_else(|| SvcError::InvalidCommand(format!("set on {}, which is not in {}", name, config_path.display())))?;
+    let removed = value.is_null();
+    if removed {
+        entry.remove(field);
+    } else {
+        entry.insert(field.into(), value);
+    }
+    // Reported with the field, rather than a position in a document that was never on disk;
+    // an instance of a template is only complete once expanded, which write_checked does
+    if !entry.contains_key("instances_of") {
+        serde_yaml::from_value::<Service>(Value::Mapping(entry.clone()))
+            .map_err(|err| SvcError::InvalidCommand(format!("set {} of {}: {}", field, name, err)))?;
+    }
+    write_checked(config_path, &document)?;
+
+    if removed {
+        println!("Removed {} from service {}.", field, name.cyan());
+    } else {
+        println!("Set {} of service {}.", field, name.cyan());
+    }
+    Ok(())
+}
+
+pub fn rename(config_path: &Path, config: &[Service], old: &str, new: &str) -> Result<(), SvcError> {
+    let Some(service) = config.iter().find(|s| s.name == old) else {
+        return Err(not_found(config, old));
+    };
+    if config.iter().any(|s| s.name == new) {
+        return Err(SvcError::ServiceNameTaken(new.to_string()));
+    }
+
+    let _lock = lock_config(config_path)?;
+    let mut document = read_document(config_path)?;
+    let entry = entry_mut(&mut document, old).ok_or_else(|| not_found(config, old))?;
+    entry["name"] = Value::String(new.to_string());
+    // Services that depend on the old name follow it
+    for entry in entries_mut(&mut document).into_iter().flatten() {
+        let Some(depends_on) = entry.get_mut("depends_on").and_then(Value::as_sequence_mut) else {
+            continue;
+        };
+        for dependency in depends_on.iter_mut().filter(|d| d.as_str() == Some(old)) {
+            *dependency = Value::String(new.to_string());
+        }
+    }
+    // So do their per-host overrides
+    if let Some(Value::Mapping(hosts)) = document.get_mut("hosts") {
+        for (_, overrides) in hosts.iter_mut() {
+            if let Some(fields) = overrides.as_mapping_mut().and_then(|o| o.remove(old)) {
+                overrides[new] = fields;
+            }
+        }
+    }
+    write_config(config_path, &document)?;
+
+    // Move the startup entry so the old name doesn't linger in the registry
+    if let Some(entry) = startup::find(service)? {
+        let data = match entry.data.strip_suffix(&format!("\"{}\"", old)) {
+            Some(command) if command.ends_with(" run --startup ") => format!("{}\"{}\"", command, new),
+            _ => entry.data,
+        };
+        startup::set_value(&startup::value_name(new), &data)?;
+        startup::delete_value(&entry.value)?;
+        println!("Start-up entry moved from {} to {}.", old.cyan(), new.cyan());
+    }
+
+    println!("Service {} renamed to {}.", old.cyan(), new.cyan());
+    Ok(())
+}
+
+// Drop a service's entry and its per-host overrides; dependents are the caller's to check
+pub fn remove_service(config_path: &Path, name: &str) -> Result<(), SvcError> {
+    let _lock = lock_config(config_path)?;
+    let mut document = read_document(config_path)?;
+    let entries = entries_mut(&mut document).ok_or_else(|| SvcError::ServiceNotFound {
+        name: name.to_string(),
+        suggestion: None,
+    })?;
+    let before = entries.len();
+    entries.retain(|entry| entry.get("name").and_then(Value::as_str) != Some(name));
+    if entries.len() == before {
+        return Err(SvcError::ServiceNotFound {
+            name: name.to_string(),
+            suggestion: None,
+        });
+    }
+    drop_host_overrides(&mut document, name);
+    write_config(config_path, &document)?;
+
+    println!("Service {} removed from services.yaml.", name.cyan());
+    Ok(())
+}
+
+fn drop_host_overrides(document: &mut Value, name: &str) {
+    if let Some(Value::Mapping(hosts)) = document.get_mut("hosts") {
+        for (_, overrides) in hosts.iter_mut() {
+            if let Some(overrides) = overrides.as_mapping_mut() {
+                overrides.remove(name);
+            }
+        }
+    }
+}
+
+// The service entries of the config file itself, as written
+pub fn raw_entries(config_path: &Path) -> Result<Vec<Value>, SvcError> {
+    let mut document = read_document(config_path)?;
+    Ok(entries_mut(&mut document).map(std::mem::take).unwrap_or_default())
+}
+
+// Entries replaced by name or appended, and others dropped with their per-host overrides, all
+// in one checked write; the config as it loads afterwards. Nothing is written on a dry run
+pub fn replace_entries(
+    config_path: &Path,
+    set: &[Value],
+    remove: &[&str],
+    dry_run: bool,
+) -> Result<Config<'static>, SvcError> {
+    let _lock = lock_config(config_path)?;
+    let mut document = read_document(config_path)?;
+    for entry in set {
+        let name = entry.get("name").and_then(Value::as_str).unwrap_or_default();
+        match entry_mut(&mut document, name) {
+            Some(existing) => *existing = entry.clone(),
+            None => push_entry(&mut document, entry.clone())?,
+        }
+    }
+    for &name in remove {
+        if let Some(entries) = entries_mut(&mut document) {
+            entries.retain(|entry| entry.get("name").and_then(Value::as_str) != Some(name));
+        }
+        drop_host_overrides(&mut document, name);
+    }
+    let config = parse_config(&serde_yaml::to_string(&document)?)?;
+    if !dry_run {
+        write_config(config_path, &document)?;
+    }
+    Ok(config)
+}
+
+// The services this machine runs, with templates expanded and its hosts overrides applied
+fn resolve(document: &mut Value) -> Result<(), SvcError> {
+    templates::expand(document)?;
+    hosts::apply(document)?;
+    if let Value::Mapping(mapping) = document {
+        mapping.remove("templates");
+        mapping.remove("hosts");
+    }
+    Ok(())
+}
+
+// `svc export`: the config in canonical form, or resolved as this machine sees it
+pub fn export(config_path: &Path, resolve_it: bool) -> Result<(), SvcError> {
+    let mut document = read_document(config_path)?;
+    if resolve_it {
+        resolve(&mut document)?;
+    }
+    print!("{}", to_canonical(&document)?);
+    Ok(())
+}
+
+// The config file as this machine sees it
+pub fn resolved_document(config_path: &Path) -> Result<Value, SvcError> {
+    let mut document = read_document(config_path)?;
+    resolve(&mut document)?;
+    Ok(document)
+}
+
+fn describe(path: &Path) -> String {
+    let Ok(content) = fs::read_to_string(path) else {
+        return "unreadable".red().to_string();
+    };
+    match parse_config(&content) {
+        Ok(config) => format!("{} bytes, {} services", content.len(), config.services.len()),
+        Err(err) => format!("{} bytes, {}", content.len(), format!("invalid: {}", err).red()),
+    }
+}
+
+// Resolve the temp file left behind by an interrupted config write
+pub fn recover(config_path: &Path, choice: Option<RecoverChoice>) -> Result<(), SvcError> {
+    let temp = temp_path(config_path);
+    if !temp.exists() {
+        println!("Nothing to recover, {} is intact.", config_path.display().to_string().cyan());
+        return Ok(());
+    }
+
+    let _lock = lock_config(config_path)?;
+    match choice {
+        None => {
+            println!("Current: {} ({})", config_path.display().to_string().cyan(), describe(config_path));
+            println!("Pending: {} ({})", temp.display().to_string().cyan(), describe(&temp));
+            println!("Keep one with 'svc config recover --keep current' or '--keep pending'.");
+        }
+        Some(RecoverChoice::Current) => {
+            effects::delete(&temp)?;
+            println!("Discarded {}.", temp.display().to_string().cyan());
+        }
+        Some(RecoverChoice::Pending) => {
+            // Never promote a half-written file over a good one
+            parse_config(&fs::read_to_string(&temp)?)?;
+            let effect = Effect::WriteFile {
+                path: config_path.display().to_string(),
+            };
+            effects::perform(effect, || Ok(fs::rename(&temp, config_path)?))?;
+            println!("Restored {} from {}.", config_path.display().to_string().cyan(), temp.display());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use crate::{elevation, output, SvcError};
+use colored::Colorize;
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+// Set by --dry-run: every effect is recorded instead of made
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+static PLANNED: Mutex<Vec<Effect>> = Mutex::new(Vec::new());
+
+// A change svc makes to the machine, declared where it's made so a dry run can list it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "effect", rename_all = "snake_case")]
+pub enum Effect {
+    WriteFile { path: String },
+    DeleteFile { path: String },
+    SetRegistryValue { key: String, name: String, data: String },
+    DeleteRegistryValue { key: String, name: String },
+    RunHelper { command: String },
+    Start { service: String },
+    Stop { service: String, pids: Vec<u64> },
+    Terminate { service: String, pids: Vec<u64> },
+    Adjust { service: String, pids: Vec<u64> },
+}
+
+impl Effect {
+    pub fn run_helper(command: &Command) -> Effect {
+        let line: Vec<String> = std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| elevation::quote(&arg.to_string_lossy()))
+            .collect();
+        Effect::RunHelper { command: line.join(" ") }
+    }
+}
+
+fn pid_list(pids: &[u64]) -> String {
+    let pids: Vec<String> = pids.iter().map(u64::to_string).collect();
+    format!("PID {}", pids.join(", "))
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Effect::WriteFile { path } => write!(f, "write {}", path),
+            Effect::DeleteFile { path } => write!(f, "delete {}", path),
+            Effect::SetRegistryValue { key, name, data } => write!(f, "set {}\\{} to {}", key, name, data),
+            Effect::DeleteRegistryValue { key, name } => write!(f, "delete {}\\{}", key, name),
+            Effect::RunHelper { command } => write!(f, "run {}", command),
+            Effect::Start { service } => write!(f, "start {}", service),
+            Effect::Stop { service, pids } => write!(f, "stop {} ({})", service, pid_list(pids)),
+            Effect::Terminate { service, pids } => write!(f, "terminate {} ({})", service, pid_list(pids)),
+            Effect::Adjust { service, pids } => write!(f, "adjust the scheduling of {} ({})", service, pid_list(pids)),
+        }
+    }
+}
+
+pub fn enable_dry_run() {
+    DRY_RUN.store(true, Ordering::Relaxed);
+}
+
+pub fn dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+// Make the effect, or on a dry run only record it; None when it was only recorded
+pub fn perform<T>(effect: Effect, make: impl FnOnce() -> Result<T, SvcError>) -> Result<Option<T>, SvcError> {
+    if !dry_run() {
+        return make().map(Some);
+    }
+    if let Ok(mut planned) = PLANNED.lock() {
+        planned.push(effect);
+    }
+    Ok(None)
+}
+
+// fs::write as an effect
+pub fn write(path: &Path, content: &str) -> Result<(), SvcError> {
+    let effect = Effect::WriteFile {
+        path: path.display().to_string(),
+    };
+    perform(effect, || Ok(fs::write(path, content)?)).map(drop)
+}
+
+// A file, or a directory with everything in it
+pub fn delete(path: &Path) -> Result<(), SvcError> {
+    let effect = Effect::DeleteFile {
+        path: path.display().to_string(),
+    };
+    perform(effect, || {
+        match path.is_dir() {
+            true => fs::remove_dir_all(path)?,
+            false => fs::remove_file(path)?,
+        }
+        Ok(())
+    })
+    .map(drop)
+}
+
+pub fn planned() -> Vec<Effect> {
+    PLANNED.lock().map(|planned| planned.clone()).unwrap_or_default()
+}
+
+// Once a dry run is done: what it would have changed, as "would ..." lines or, with --json,
+// as {"dry_run", "effects"}; nothing for a command that changed nothing or printed its own plan
+pub fn print_plan() -> Result<(), SvcError> {
+    let planned = planned();
+    if !dry_run() || planned.is_empty() {
+        return Ok(());
+    }
+    if output::options().json {
+        let plan = serde_json::json!({ "dry_run": true, "effects": planned });
+        println!("{}", serde_json::to_string_pretty(&plan).map_err(std::io::Error::other)?);
+        return Ok(());
+    }
+    println!("{}", "Dry run, nothing was changed. svc would:".yellow());
+    for effect in &planned {
+        println!("  {}", effect);
+    }
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use crate::SvcError;
+use colored::Colorize;
+use std::io::{IsTerminal, Write};
+
+// What `--elevate` / `--no-elevate` ask for when a command needs administrator rights
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Elevate {
+    Ask,
+    Always,
+    Never,
+}
+
+// Rights svc wants on another process
+#[derive(Debug, Clone, Copy)]
+pub enum Access {
+    Terminate,
+    SetInformation,
+    // Reading its memory, as `svc env --live` does
+    ReadMemory,
+}
+
+#[cfg(windows)]
+pub fn is_elevated() -> bool {
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::Security::{GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY};
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    let mut token: HANDLE = std::ptr::null_mut();
+    if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) } == 0 {
+        return false;
+    }
+    let mut elevation = TOKEN_ELEVATION { TokenIsElevated: 0 };
+    let mut size = 0;
+    let ok = unsafe {
+        GetTokenInformation(
+            token,
+            TokenElevation,
+            (&mut elevation as *mut TOKEN_ELEVATION).cast(),
+            std::mem::size_of::<TOKEN_ELEVATION>() as u32,
+            &mut size,
+        )
+    } != 0;
+    unsafe { CloseHandle(token) };
+    ok && elevation.TokenIsElevated != 0
+}
+
+#[cfg(not(windows))]
+pub fn is_elevated() -> bool {
+    false
+}
+
+// True only when opening the process fails for lack of rights; gone or odd processes
+// are left for the command itself to report
+#[cfg(windows)]
+pub fn denied(pid: u64, access: Access) -> bool {
+    use windows_sys::Win32::Foundation::{CloseHandle, GetLastError, ERROR_ACCESS_DENIED};
+    use windows_sys::Win32::System::Threading::{OpenProcess, PROCESS_SET_INFORMATION, PROCESS_TERMINATE};
+    use windows_sys::Win32::System::Threading::{PROCESS_QUERY_INFORMATION, PROCESS_VM_READ};
+
+    let rights = match access {
+        Access::Terminate => PROCESS_TERMINATE,
+        Access::SetInformation => PROCESS_SET_INFORMATION,
+        Access::ReadMemory => PROCESS_QUERY_INFORMATION | PROCESS_VM_READ,
+    };
+    let process = unsafe { OpenProcess(rights, 0, pid as u32) };
+    if process.is_null() {
+        return unsafe { GetLastError() } == ERROR_ACCESS_DENIED;
+    }
+    unsafe { CloseHandle(process) };
+    false
+}
+
+#[cfg(not(windows))]
+pub fn denied(_pid: u64, _access: Access) -> bool {
+    false
+}
+
+// Quote one argument the way CommandLineToArgvW splits it again
+pub fn quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        // Backslashes only escape when a quote follows them
+        let escapes = if c == '"' { backslashes * 2 + 1 } else { backslashes };
+        quoted.push_str(&"\\".repeat(escapes));
+        quoted.push(c);
+        backslashes = 0;
+    }
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+// Run this same svc invocation elevated through the UAC prompt and wait for its exit code.
+// The child is the same svc.exe started in the same directory, and is told the config with
+// --config when searching wouldn't find it again, as with SVC_CONFIG, which it doesn't inherit
+#[cfg(windows)]
+fn relaunch() -> Result<i32, SvcError> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{GetExitCodeProcess, WaitForSingleObject, INFINITE};
+    use windows_sys::Win32::UI::Shell::{ShellExecuteExW, SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW};
+    use windows_sys::Win32::UI::WindowsAndMessaging::SW_SHOWNORMAL;
+
+    let wide = |text: &std::ffi::OsStr| text.encode_wide().chain(Some(0)).collect::<Vec<u16>>();
+    let exe = wide(std::env::current_exe()?.as_os_str());
+    let args: Vec<String> = crate::locate::carried_args()
+        .into_iter()
+        .chain(std::env::args().skip(1))
+        .map(|arg| quote(&arg))
+        .collect();
+    let params = wide(args.join(" ").as_ref());
+    let verb = wide("runas".as_ref());
+    let dir = wide(std::env::current_dir()?.as_os_str());
+
+    let mut info: SHELLEXECUTEINFOW = unsafe { std::mem::zeroed() };
+    info.cbSize = std::mem::size_of::<SHELLEXECUTEINFOW>() as u32;
+    info.fMask = SEE_MASK_NOCLOSEPROCESS;
+    info.lpVerb = verb.as_ptr();
+    info.lpFile = exe.as_ptr();
+    info.lpParameters = params.as_ptr();
+    info.lpDirectory = dir.as_ptr();
+    info.nShow = SW_SHOWNORMAL;
+    // Fails with ERROR_CANCELLED when the prompt is declined
+    if unsafe { ShellExecuteExW(&mut info) } == 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let mut code = 1;
+    unsafe {
+        WaitForSingleObject(info.hProcess, INFINITE);
+        GetExitCodeProcess(info.hProcess, &mut code);
+        CloseHandle(info.hProcess);
+    }
+    Ok(code as i32)
+}
+
+#[cfg(not(windows))]
+fn relaunch() -> Result<i32, SvcError> {
+    Err(std::io::Error::from(std::io::ErrorKind::Unsupported).into())
+}
+
+// Decide what to do about a command that needs rights svc lacks: the elevated child's exit
+// code when it was relaunched, ElevationRequired when it may not or can't ask.
+// Without a console nobody would see the UAC prompt, so it fails fast there
+pub fn ensure(action: String, mode: Elevate) -> Result<i32, SvcError> {
+    let interactive = std::io::stdin().is_terminal() && std::io::stderr().is_terminal();
+    let relaunch_now = match mode {
+        Elevate::Always => true,
+        Elevate::Never => false,
+        Elevate::Ask if !interactive => false,
+        Elevate::Ask => {
+            eprint!("{} needs administrator rights. Relaunch elevated? [y/N] ", action.cyan());
+            std::io::stderr().flush()?;
+            let mut answer = String::new();
+            std::io::stdin().read_line(&mut answer)?;
+            crate::cancel::check()?;
+            matches!(answer.trim(), "y" | "Y" | "yes")
+        }
+    };
+    if !relaunch_now {
+        return Err(SvcError::ElevationRequired(action));
+    }
+    relaunch()
+}
@@ -0,0 +1,118 @@
+use crate::{Service, ServiceType};
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use std::fmt::Display;
+use std::path::Path;
+
+// UTF-8 as a Windows code page
+const CP_UTF8: u32 = 65001;
+
+// How svc decodes what a service prints: `utf8`, `oem` for the console's OEM code page, or
+// a code page number such as 1252
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Oem,
+    CodePage(u32),
+}
+
+impl<'de> Deserialize<'de> for Encoding {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(u32),
+            Name(String),
+        }
+        let code_page = |number: u32| if number == CP_UTF8 { Encoding::Utf8 } else { Encoding::CodePage(number) };
+        match Raw::deserialize(deserializer)? {
+            Raw::Number(number) => Ok(code_page(number)),
+            Raw::Name(name) => match name.trim().to_ascii_lowercase().as_str() {
+                "utf8" | "utf-8" => Ok(Encoding::Utf8),
+                "oem" => Ok(Encoding::Oem),
+                other => other.parse().map(code_page).map_err(|_| {
+                    de::Error::custom(format!("unknown encoding {}, expected utf8, oem or a code page number", name))
+                }),
+            },
+        }
+    }
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Encoding::Utf8 => write!(f, "utf8"),
+            Encoding::Oem => match oem_code_page() {
+                Some(code_page) => write!(f, "oem (code page {})", code_page),
+                None => write!(f, "oem"),
+            },
+            Encoding::CodePage(code_page) => write!(f, "code page {}", code_page),
+        }
+    }
+}
+
+impl Encoding {
+    // Text of the bytes; what doesn't decode becomes U+FFFD rather than ending the stream
+    pub fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 => String::from_utf8_lossy(bytes).to_string(),
+            Encoding::Oem => decode_code_page(oem_code_page().unwrap_or(CP_UTF8), bytes),
+            Encoding::CodePage(code_page) => decode_code_page(code_page, bytes),
+        }
+    }
+}
+
+// Text of what a service printed, taken as UTF-8 unless it sets an encoding
+pub fn decode(encoding: Option<Encoding>, bytes: &[u8]) -> String {
+    encoding.unwrap_or(Encoding::Utf8).decode(bytes)
+}
+
+// What a chcp Util under cmd runs ahead of its script; cmd /c takes the rest of its command
+// line as one command, so the redirection and && are cmd's own
+pub fn chcp_prefix(service: &Service) -> &'static [&'static str] {
+    let under_cmd = Path::new(service.interpreter.as_ref())
+        .file_stem()
+        .is_some_and(|stem| stem.eq_ignore_ascii_case("cmd"));
+    let util = matches!(service.service_type, ServiceType::Util);
+    match service.chcp && util && under_cmd && service.encoding == Some(Encoding::Utf8) {
+        true => &["chcp", "65001", ">nul", "&&"],
+        false => &[],
+    }
+}
+
+#[cfg(windows)]
+fn oem_code_page() -> Option<u32> {
+    Some(unsafe { windows_sys::Win32::Globalization::GetOEMCP() })
+}
+
+#[cfg(not(windows))]
+fn oem_code_page() -> Option<u32> {
+    None
+}
+
+// Without MB_ERR_INVALID_CHARS, bytes that don't map come out as the default character
+#[cfg(windows)]
+fn decode_code_page(code_page: u32, bytes: &[u8]) -> String {
+    use windows_sys::Win32::Globalization::MultiByteToWideChar;
+
+    if bytes.is_empty() {
+        return String::new();
+    }
+    let Ok(length) = i32::try_from(bytes.len()) else {
+        return String::from_utf8_lossy(bytes).to_string();
+    };
+    let needed = unsafe { MultiByteToWideChar(code_page, 0, bytes.as_ptr(), length, std::ptr::null_mut(), 0) };
+    if needed <= 0 {
+        // A code page Windows doesn't have
+        return String::from_utf8_lossy(bytes).to_string();
+    }
+    let mut wide = vec![0u16; needed as usize];
+    let written = unsafe { MultiByteToWideChar(code_page, 0, bytes.as_ptr(), length, wide.as_mut_ptr(), needed) };
+    String::from_utf16_lossy(&wide[..written.max(0) as usize])
+}
+
+// There are no code page tables off Windows
+#[cfg(not(windows))]
+fn decode_code_page(_code_page: u32, bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).to_string()
+}
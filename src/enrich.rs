@@ -0,0 +1,124 @@
+use crate::units::HumanDuration;
+use crate::{warnings, Service};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{mpsc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+// Past this much stdout an enricher's answer is dropped rather than parsed
+const MAX_OUTPUT: usize = 64 * 1024;
+const POLL: Duration = Duration::from_millis(10);
+
+// One `enrichers` entry of the settings: a program given each service as JSON on stdin,
+// whose JSON object on stdout adds to what status and list show
+#[derive(Debug, Deserialize)]
+pub struct Enricher {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub timeout: Option<HumanDuration>,
+}
+
+impl Enricher {
+    fn label(&self) -> String {
+        Path::new(&self.command)
+            .file_stem()
+            .map_or_else(|| self.command.clone(), |stem| stem.to_string_lossy().into_owned())
+    }
+}
+
+// Set once the config is read, unless --no-enrichers or safe mode leaves them out
+static ENRICHERS: OnceLock<Vec<Enricher>> = OnceLock::new();
+
+pub fn init(enrichers: Vec<Enricher>) {
+    let _ = ENRICHERS.set(enrichers);
+}
+
+// What an enricher printed, or why it's left out
+fn ask(enricher: &Enricher, input: Vec<u8>) -> Result<Map<String, Value>, String> {
+    let timeout = enricher.timeout.as_ref().map_or(DEFAULT_TIMEOUT, HumanDuration::get);
+    let mut child = Command::new(&enricher.command)
+        .args(&enricher.args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| format!("could not start: {}", err))?;
+    // Both ends in threads, so one that neither reads nor writes can't hold up status
+    if let Some(mut stdin) = child.stdin.take() {
+        thread::spawn(move || stdin.write_all(&input));
+    }
+    let (sender, receiver) = mpsc::channel();
+    if let Some(stdout) = child.stdout.take() {
+        thread::spawn(move || {
+            let mut bytes = Vec::new();
+            let _ = stdout.take(MAX_OUTPUT as u64 + 1).read_to_end(&mut bytes);
+            let _ = sender.send(bytes);
+        });
+    }
+    let started = Instant::now();
+    let bytes = receiver.recv_timeout(timeout).unwrap_or_default();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if bytes.len() <= MAX_OUTPUT && started.elapsed() < timeout => thread::sleep(POLL),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                if bytes.len() > MAX_OUTPUT {
+                    return Err(format!("printed more than {} KiB", MAX_OUTPUT / 1024));
+                }
+                return Err(format!("did not answer within {}s", timeout.as_secs_f64()));
+            }
+        }
+    };
+    if bytes.len() > MAX_OUTPUT {
+        return Err(format!("printed more than {} KiB", MAX_OUTPUT / 1024));
+    }
+    if !status.success() {
+        return Err(format!("exited with {}", status));
+    }
+    match serde_json::from_slice(&bytes) {
+        Ok(Value::Object(extra)) => Ok(extra),
+        Ok(_) => Err("printed JSON that is not an object".to_string()),
+        Err(err) => Err(format!("printed something other than JSON: {}", err)),
+    }
+}
+
+// Every enricher's facts about the service, a later one's keys replacing an earlier one's;
+// one that fails is only a warning
+pub fn extra(service: &Service, pids: &[u64]) -> Map<String, Value> {
+    let mut extra = Map::new();
+    let Some(enrichers) = ENRICHERS.get().filter(|enrichers| !enrichers.is_empty()) else {
+        return extra;
+    };
+    let input = serde_json::json!({
+        "name": service.name,
+        "type": service.service_type,
+        "path": service.path,
+        "tags": service.tags,
+        "label": service.label,
+        "pids": pids,
+    });
+    for enricher in enrichers {
+        match ask(enricher, input.to_string().into_bytes()) {
+            Ok(facts) => extra.extend(facts),
+            Err(reason) => warnings::warn("enricher", &service.name, format!("enricher {} {}", enricher.label(), reason)),
+        }
+    }
+    extra
+}
+
+// A fact as status prints it: strings bare, anything else as compact JSON
+pub fn display(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
@@ -0,0 +1,417 @@
+use crate::{output, SvcError};
+use colored::Colorize;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+// One stable code of SvcError::code, as `svc errors` lists it
+#[derive(Debug, Serialize)]
+pub struct ErrorCode {
+    pub code: &'static str,
+    pub group: &'static str,
+    pub description: &'static str,
+}
+
+const fn entry(group: &'static str, code: &'static str, description: &'static str) -> ErrorCode {
+    ErrorCode {
+        code,
+        group,
+        description,
+    }
+}
+
+// Every code svc reports, grouped; scripts match on these, so codes are added but never
+// renamed or reused
+pub const CODES: &[ErrorCode] = &[
+    entry("service", "already_running", "the service is already running"),
+    entry("service", "not_running", "the service is not running"),
+    entry("service", "already_enabled", "the service already has a start-up entry"),
+    entry("service", "already_disabled", "the service has no start-up entry"),
+    entry("service", "service_not_found", "no service of that name, or no services at all"),
+    entry("service", "service_name_taken", "an adhoc service would reuse a configured name"),
+    entry("service", "service_protected", "a protected service needs confirmation or --force"),
+    entry("service", "service_busy", "another svc invocation is working on the service"),
+    entry("service", "no_tagged_services", "no service has the tag"),
+    entry("service", "interpreter_not_found", "the interpreter of a utility is not installed"),
+    entry("service", "work_dir_invalid", "the working directory is missing or not a directory"),
+    entry("service", "util_failed", "a utility exited unsuccessfully"),
+    entry("service", "step_failed", "a step of a multi-step service failed"),
+    entry("service", "bulk_failed", "some services of a bulk command failed"),
+    entry("service", "apply_incomplete", "apply could not converge every service"),
+    entry("service", "dependency_timeout", "a dependency did not start in time"),
+    entry("service", "idle_timeout", "the service did not go idle before --max-wait"),
+    entry("service", "switchover_failed", "the new instance of an overlapping restart was not ready"),
+    entry("service", "not_ready", "a service restarted by --outdated was not ready in time"),
+    entry("service", "condition_not_met", "no process of the service meets --if-uptime-gt or --if-uptime-lt; it is skipped"),
+    entry("service", "bundle_refused", "the service can't be bundled, or the folder already has files"),
+    entry("service", "log_file_failed", "the log file can't be opened"),
+    entry("service", "lock_held", "the lock file of the service is held"),
+    entry("service", "scm_managed", "the binary is also a Windows service, which sc controls instead"),
+    entry("service", "secret_not_found", "a secret the service needs is not stored"),
+    entry("service", "invalid_secret_key", "a secret key would name a file outside the secret store"),
+    entry("service", "logon_failed", "starting as another user failed to log on"),
+    entry("process", "process_not_found", "no process has the PID"),
+    entry("process", "process_not_managed", "the PID belongs to no configured service"),
+    entry("process", "still_running", "processes survived being killed"),
+    entry("process", "stop_incomplete", "some services did not stop"),
+    entry("process", "loose_match", "processes matched only loosely were spared"),
+    entry("process", "kill_refused", "processes that belong to Windows were spared"),
+    entry("process", "adjust_failed", "some processes could not be adjusted"),
+    entry("process", "affinity_out_of_range", "an affinity names a CPU past the mask"),
+    entry("permission", "elevation_required", "the command needs administrator rights"),
+    entry("permission", "capability_missing", "this account can't do what the command needs"),
+    entry("permission", "feature_unavailable", "this edition of Windows lacks what the command needs"),
+    entry("permission", "kill_denied", "terminating a process of another user was denied"),
+    entry("permission", "environment_denied", "the environment of a process can't be read"),
+    entry("startup", "foreign_startup_entry", "a start-up entry of that name was not created by svc"),
+    entry("startup", "startup_write_mismatch", "a start-up entry reads back differently than written"),
+    entry("startup", "startup_audit_findings", "audit-startup left entries that need attention"),
+    entry("startup", "startup_apply_incomplete", "apply-startup could not make every change"),
+    entry("startup", "rolled_back", "a batch start-up change failed and the ones before it were undone"),
+    entry("config", "config_missing", "no config file where svc looked"),
+    entry("config", "config_access", "the config is a directory, locked, denied or a mangled path"),
+    entry("config", "config_parse", "the config is not valid YAML for svc"),
+    entry("config", "config_locked", "another process is modifying the config"),
+    entry("config", "config_not_formatted", "the config is not in canonical form"),
+    entry("config", "fmt_drops_comments", "svc fmt would drop the config's comments"),
+    entry("config", "validation_failed", "svc validate found errors"),
+    entry("config", "duplicate_service", "a service name is defined more than once"),
+    entry("config", "invalid_entry", "a field of a service is invalid"),
+    entry("config", "invalid_template", "a template instance is invalid"),
+    entry("config", "invalid_dependency", "depends_on names an unknown service or a cycle"),
+    entry("config", "invalid_extends", "an extends entry can't be resolved"),
+    entry("config", "invalid_host_override", "the hosts section is invalid"),
+    entry("config", "unknown_shell", "a service uses a shell that is not defined"),
+    entry("config", "alias_shadows_command", "a command alias hides a built-in command"),
+    entry("config", "invalid_desired_state", "the desired-state document is invalid"),
+    entry("command", "invalid_command", "the command line is invalid"),
+    entry("command", "unknown_command", "no command of that name"),
+    entry("command", "unknown_help_topic", "no help topic of that name"),
+    entry("command", "nothing_to_watch", "watch was given no services"),
+    entry("command", "watcher_running", "a background watcher is already running"),
+    entry("command", "watcher_not_running", "no background watcher is running"),
+    entry("command", "read_only_mode", "the command changes something and svc is read-only"),
+    entry("command", "interrupted", "Ctrl+C ended the command"),
+    entry("state", "corrupt_state", "a file in svc's state directory can't be parsed"),
+    entry("state", "no_checkpoint", "svc resume-last found no bulk operation to pick up"),
+    entry("state", "cannot_read_pid", "a PID file can't be read"),
+    entry("state", "invalid_pid", "a PID file doesn't hold a PID"),
+    entry("state", "restore_incomplete", "some items of a backup could not be restored"),
+    entry("state", "archive", "a backup archive is unreadable"),
+    entry("system", "io", "a file or system call failed"),
+    entry("system", "helper_failed", "a Windows tool svc runs failed"),
+    entry("system", "invalid_utf8", "output was not valid UTF-8"),
+];
+
+pub fn group(code: &str) -> &'static str {
+    CODES.iter().find(|entry| entry.code == code).map_or("system", |entry| entry.group)
+}
+
+// The service, path and PID an error is about, for scripts that branch on more than the code
+pub fn details(err: &SvcError) -> Map<String, Value> {
+    let (service, path, pid): (Option<&str>, Option<&str>, Option<u64>) = match err {
+        SvcError::ServiceNotFound { name, .. } => (Some(name), None, None),
+        SvcError::NoServicesConfigured(service)
+        | SvcError::DuplicateService(service)
+        | SvcError::ServiceNameTaken(service)
+        | SvcError::ServiceIsProtected(service)
+        | SvcError::ServiceBusy(service) => (Some(service), None, None),
+        SvcError::InterpreterNotFound { service, .. }
+        | SvcError::LooseMatch { service, .. }
+        | SvcError::KillRefused { service, .. }
+        | SvcError::StillRunning { service, .. }
+        | SvcError::IdleTimeout { service, .. }
+        | SvcError::SwitchoverFailed { service, .. }
+        | SvcError::NotReady { service, .. }
+        | SvcError::ConditionNotMet { service, .. }
+        | SvcError::BundleRefused { service, .. }
+        | SvcError::InvalidDependency { service, .. }
+        | SvcError::DependencyTimeout { service, .. }
+        | SvcError::InvalidEntry { service, .. }
+        | SvcError::ScmManaged { service, .. }
+        | SvcError::UnknownShell { service, .. } => (Some(service), None, None),
+        SvcError::StepFailed { service, path, .. } | SvcError::WorkDirInvalid { service, path, .. } => {
+            (Some(service), Some(path), None)
+        }
+        SvcError::LockHeld { service, path, pid } => (Some(service), Some(path), *pid),
+        SvcError::UtilFailed { path, .. }
+        | SvcError::LogFileFailed { path, .. }
+        | SvcError::CorruptState { path, .. }
+        | SvcError::ConfigAccess { path, .. }
+        | SvcError::ConfigMissing(path) => (None, Some(path), None),
+        SvcError::WatcherIsRunning(pid) | SvcError::ProcessNotFound(pid) | SvcError::ConfigLocked(pid) => {
+            (None, None, Some(*pid))
+        }
+        SvcError::ProcessNotManaged { pid, .. }
+        | SvcError::KillDenied { pid, .. }
+        | SvcError::EnvironmentDenied { pid, .. } => (None, None, Some(*pid)),
+        _ => (None, None, None),
+    };
+    let mut details = Map::new();
+    if let SvcError::ConfigAccess { problem, .. } = err {
+        details.insert("problem".to_string(), problem.kind().into());
+    }
+    if let Some(service) = service {
+        details.insert("service".to_string(), service.into());
+    }
+    if let Some(path) = path {
+        details.insert("path".to_string(), path.into());
+    }
+    if let Some(pid) = pid {
+        details.insert("pid".to_string(), pid.into());
+    }
+    details
+}
+
+// `svc errors`
+pub fn list() -> Result<(), SvcError> {
+    if output::options().json {
+        println!("{}", serde_json::to_string_pretty(CODES).map_err(std::io::Error::other)?);
+        return Ok(());
+    }
+    let width = CODES.iter().map(|entry| entry.code.len()).max().unwrap_or(0);
+    let mut group = "";
+    for entry in CODES {
+        if entry.group != group {
+            group = entry.group;
+            println!("{}", group.bold());
+        }
+        println!("  {}  {}", format!("{:<width$}", entry.code, width = width).cyan(), entry.description);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigAccessError;
+    use crate::flavor::Feature;
+    use crate::probe::Capability;
+    use std::collections::HashSet;
+
+    fn text() -> String {
+        "x".to_string()
+    }
+
+    // One of each variant
+    fn samples() -> Vec<SvcError> {
+        vec![
+            SvcError::ServiceIsRunning,
+            SvcError::ServiceIsNotRunning,
+            SvcError::ServiceIsDisabled,
+            SvcError::ServiceIsEnabled,
+            SvcError::ServiceNotFound { name: text(), suggestion: None },
+            SvcError::NoServicesConfigured(text()),
+            SvcError::DuplicateService(text()),
+            SvcError::ServiceNameTaken(text()),
+            SvcError::ServiceIsProtected(text()),
+            SvcError::InvalidCommand(text()),
+            SvcError::UnknownCommand { name: text(), suggestion: None },
+            SvcError::ConfigMissing(text()),
+            SvcError::ConfigAccess { path: text(), problem: ConfigAccessError::Locked },
+            SvcError::UnknownHelpTopic { topic: text(), available: text() },
+            SvcError::NothingToWatch,
+            SvcError::WatcherIsRunning(1),
+            SvcError::WatcherIsNotRunning,
+            SvcError::UtilFailed { path: text(), status: text(), code: None, output: Vec::new() },
+            SvcError::InterpreterNotFound { service: text(), interpreter: text() },
+            SvcError::SecretNotFound(text()),
+            SvcError::InvalidSecretKey(text()),
+            SvcError::LogonFailed { user: text(), reason: text() },
+            SvcError::StepFailed { service: text(), step: 1, path: text(), reason: text() },
+            SvcError::BulkFailed { failed: 1, total: 2 },
+            SvcError::StopIncomplete { stuck: Vec::new() },
+            SvcError::InvalidDesiredState(text()),
+            SvcError::ApplyIncomplete { failed: Vec::new() },
+            SvcError::CapabilityMissing { capability: Capability::StartupRead, reason: text() },
+            SvcError::FeatureUnavailable { feature: Feature::Toasts, platform: "x", constraint: "x" },
+            SvcError::SwitchoverFailed { service: text(), reason: text() },
+            SvcError::NotReady { service: text(), reason: text() },
+            SvcError::ConditionNotMet { service: text(), condition: text() },
+            SvcError::BundleRefused { service: text(), reason: text() },
+            SvcError::StartupAuditFindings { left: 1 },
+            SvcError::StartupApplyIncomplete { failed: Vec::new() },
+            SvcError::RolledBack { failed: Vec::new(), unreverted: Vec::new() },
+            SvcError::NoTaggedServices(text()),
+            SvcError::ProcessNotFound(1),
+            SvcError::ProcessNotManaged { pid: 1, image: None },
+            SvcError::HelperFailed { command: text(), reason: text(), output: Vec::new() },
+            SvcError::ElevationRequired(text()),
+            SvcError::WorkDirInvalid { service: text(), path: text(), reason: text() },
+            SvcError::LooseMatch { service: text(), pids: Vec::new() },
+            SvcError::KillRefused { service: text(), pids: Vec::new() },
+            SvcError::LogFileFailed { path: text(), reason: text() },
+            SvcError::StillRunning { service: text(), pids: Vec::new(), waited: text() },
+            SvcError::IdleTimeout { service: text(), waited: text() },
+            SvcError::ReadOnlyMode("run"),
+            SvcError::ValidationFailed(1),
+            SvcError::AdjustFailed { failed: 1, total: 2 },
+            SvcError::AffinityOutOfRange(64),
+            SvcError::KillDenied {
+                pid: 1,
+                owner: None,
+                reason: text(),
+                fix: "",
+            },
+            SvcError::EnvironmentDenied { pid: 1, owner: None },
+            SvcError::ConfigLocked(1),
+            SvcError::ConfigNotFormatted,
+            SvcError::FmtDropsComments(1),
+            SvcError::Interrupted,
+            SvcError::ServiceBusy(text()),
+            SvcError::RestoreIncomplete(1),
+            SvcError::AliasShadowsCommand(text()),
+            SvcError::InvalidHostOverride { host: text(), reason: text() },
+            SvcError::InvalidDependency { service: text(), reason: text() },
+            SvcError::DependencyTimeout { service: text(), dependency: text(), waited: text() },
+            SvcError::InvalidExtends { name: text(), reason: text() },
+            SvcError::ForeignStartupEntry { name: text(), data: text() },
+            SvcError::StartupWriteMismatch { name: text(), expected: text(), found: text() },
+            SvcError::InvalidTemplate { template: text(), instance: text(), reason: text() },
+            SvcError::InvalidEntry { service: text(), reason: text() },
+            SvcError::UnknownShell { service: text(), shell: text(), defined: Vec::new() },
+            SvcError::CorruptState { path: text(), reason: text() },
+            SvcError::NoCheckpoint,
+            SvcError::ScmManaged { service: text(), scm_name: text(), state: text() },
+            SvcError::LockHeld { service: text(), path: text(), pid: None },
+            SvcError::IoError(std::io::Error::other("x")),
+            SvcError::YamlError(serde_yaml::from_str::<u8>("x").unwrap_err()),
+            SvcError::ArchiveError(zip::result::ZipError::FileNotFound),
+            SvcError::CannotReadPID,
+            SvcError::FailedToParsePID,
+            SvcError::FailedToConvertUtf8(String::from_utf8(vec![0xff]).unwrap_err()),
+        ]
+    }
+
+    // No wildcard on purpose: a new variant doesn't compile here until it has a sample
+    fn variant(err: &SvcError) -> &'static str {
+        match err {
+            SvcError::ServiceIsRunning => "ServiceIsRunning",
+            SvcError::ServiceIsNotRunning => "ServiceIsNotRunning",
+            SvcError::ServiceIsDisabled => "ServiceIsDisabled",
+            SvcError::ServiceIsEnabled => "ServiceIsEnabled",
+            SvcError::ServiceNotFound { .. } => "ServiceNotFound",
+            SvcError::NoServicesConfigured(_) => "NoServicesConfigured",
+            SvcError::DuplicateService(_) => "DuplicateService",
+            SvcError::ServiceNameTaken(_) => "ServiceNameTaken",
+            SvcError::ServiceIsProtected(_) => "ServiceIsProtected",
+            SvcError::InvalidCommand(_) => "InvalidCommand",
+            SvcError::UnknownCommand { .. } => "UnknownCommand",
+            SvcError::ConfigMissing(_) => "ConfigMissing",
+            SvcError::ConfigAccess { .. } => "ConfigAccess",
+            SvcError::UnknownHelpTopic { .. } => "UnknownHelpTopic",
+            SvcError::NothingToWatch => "NothingToWatch",
+            SvcError::WatcherIsRunning(_) => "WatcherIsRunning",
+            SvcError::WatcherIsNotRunning => "WatcherIsNotRunning",
+            SvcError::UtilFailed { .. } => "UtilFailed",
+            SvcError::InterpreterNotFound { .. } => "InterpreterNotFound",
+            SvcError::SecretNotFound(_) => "SecretNotFound",
+            SvcError::InvalidSecretKey(_) => "InvalidSecretKey",
+            SvcError::LogonFailed { .. } => "LogonFailed",
+            SvcError::StepFailed { .. } => "StepFailed",
+            SvcError::BulkFailed { .. } => "BulkFailed",
+            SvcError::StopIncomplete { .. } => "StopIncomplete",
+            SvcError::InvalidDesiredState(_) => "InvalidDesiredState",
+            SvcError::ApplyIncomplete { .. } => "ApplyIncomplete",
+            SvcError::CapabilityMissing { .. } => "CapabilityMissing",
+            SvcError::FeatureUnavailable { .. } => "FeatureUnavailable",
+            SvcError::SwitchoverFailed { .. } => "SwitchoverFailed",
+            SvcError::NotReady { .. } => "NotReady",
+            SvcError::ConditionNotMet { .. } => "ConditionNotMet",
+            SvcError::BundleRefused { .. } => "BundleRefused",
+            SvcError::StartupAuditFindings { .. } => "StartupAuditFindings",
+            SvcError::StartupApplyIncomplete { .. } => "StartupApplyIncomplete",
+            SvcError::RolledBack { .. } => "RolledBack",
+            SvcError::NoTaggedServices(_) => "NoTaggedServices",
+            SvcError::ProcessNotFound(_) => "ProcessNotFound",
+            SvcError::ProcessNotManaged { .. } => "ProcessNotManaged",
+            SvcError::HelperFailed { .. } => "HelperFailed",
+            SvcError::ElevationRequired(_) => "ElevationRequired",
+            SvcError::WorkDirInvalid { .. } => "WorkDirInvalid",
+            SvcError::LooseMatch { .. } => "LooseMatch",
+            SvcError::KillRefused { .. } => "KillRefused",
+            SvcError::LogFileFailed { .. } => "LogFileFailed",
+            SvcError::StillRunning { .. } => "StillRunning",
+            SvcError::IdleTimeout { .. } => "IdleTimeout",
+            SvcError::ReadOnlyMode(_) => "ReadOnlyMode",
+            SvcError::ValidationFailed(_) => "ValidationFailed",
+            SvcError::AdjustFailed { .. } => "AdjustFailed",
+            SvcError::AffinityOutOfRange(_) => "AffinityOutOfRange",
+            SvcError::KillDenied { .. } => "KillDenied",
+            SvcError::EnvironmentDenied { .. } => "EnvironmentDenied",
+            SvcError::ConfigLocked(_) => "ConfigLocked",
+            SvcError::ConfigNotFormatted => "ConfigNotFormatted",
+            SvcError::FmtDropsComments(_) => "FmtDropsComments",
+            SvcError::Interrupted => "Interrupted",
+            SvcError::ServiceBusy(_) => "ServiceBusy",
+            SvcError::RestoreIncomplete(_) => "RestoreIncomplete",
+            SvcError::AliasShadowsCommand(_) => "AliasShadowsCommand",
+            SvcError::InvalidHostOverride { .. } => "InvalidHostOverride",
+            SvcError::InvalidDependency { .. } => "InvalidDependency",
+            SvcError::DependencyTimeout { .. } => "DependencyTimeout",
+            SvcError::InvalidExtends { .. } => "InvalidExtends",
+            SvcError::ForeignStartupEntry { .. } => "ForeignStartupEntry",
+            SvcError::StartupWriteMismatch { .. } => "StartupWriteMismatch",
+            SvcError::InvalidTemplate { .. } => "InvalidTemplate",
+            SvcError::InvalidEntry { .. } => "InvalidEntry",
+            SvcError::UnknownShell { .. } => "UnknownShell",
+            SvcError::CorruptState { .. } => "CorruptState",
+            SvcError::NoCheckpoint => "NoCheckpoint",
+            SvcError::ScmManaged { .. } => "ScmManaged",
+            SvcError::LockHeld { .. } => "LockHeld",
+            SvcError::IoError(_) => "IoError",
+            SvcError::YamlError(_) => "YamlError",
+            SvcError::ArchiveError(_) => "ArchiveError",
+            SvcError::CannotReadPID => "CannotReadPID",
+            SvcError::FailedToParsePID => "FailedToParsePID",
+            SvcError::FailedToConvertUtf8(_) => "FailedToConvertUtf8",
+        }
+    }
+
+    // Variants that report the same condition under one code
+    const SHARED: [(&str, &str); 1] = [("ServiceNotFound", "NoServicesConfigured")];
+
+    #[test]
+    fn every_variant_has_a_listed_code() {
+        let samples = samples();
+        let variants: HashSet<_> = samples.iter().map(variant).collect();
+        assert_eq!(variants.len(), samples.len(), "a variant has two samples");
+        for err in &samples {
+            assert!(
+                CODES.iter().any(|entry| entry.code == err.code()),
+                "{} reports {}, which CODES doesn't list",
+                variant(err),
+                err.code()
+            );
+        }
+        for entry in CODES {
+            assert!(samples.iter().any(|err| err.code() == entry.code), "nothing reports {}", entry.code);
+        }
+    }
+
+    #[test]
+    fn codes_never_collide() {
+        let listed: HashSet<_> = CODES.iter().map(|entry| entry.code).collect();
+        assert_eq!(listed.len(), CODES.len(), "CODES lists a code twice");
+
+        let samples = samples();
+        for (at, err) in samples.iter().enumerate() {
+            for other in &samples[at + 1..] {
+                let pair = (variant(err), variant(other));
+                if err.code() == other.code() && !SHARED.contains(&pair) {
+                    panic!("{} and {} both report {}", pair.0, pair.1, err.code());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn details_name_what_the_error_is_about() {
+        let err = SvcError::LockHeld { service: "api".to_string(), path: "api.lock".to_string(), pid: Some(7) };
+        assert_eq!(
+            Value::Object(details(&err)),
+            serde_json::json!({ "service": "api", "path": "api.lock", "pid": 7 })
+        );
+        assert!(details(&SvcError::Interrupted).is_empty());
+        assert_eq!(group(SvcError::Interrupted.code()), "command");
+    }
+}
@@ -0,0 +1,89 @@
+use crate::SvcError;
+use std::path::Path;
+use std::time::Duration;
+
+#[cfg(windows)]
+const POLL: Duration = Duration::from_millis(250);
+
+// A service's `lock` file, open without write sharing for as long as the run holds it.
+// Windows closes the handle when the holder exits or crashes, so a leftover file with an
+// old PID in it never keeps the next run out
+pub struct FileLock {
+    #[cfg(windows)]
+    _file: std::fs::File,
+}
+
+// The PID the holder wrote, when the holder is an svc that is still running; an external
+// scheduler that opened the file without sharing can't be read at all
+#[cfg(windows)]
+fn holder(path: &Path) -> Option<u64> {
+    use std::io::Read;
+    use std::os::windows::fs::OpenOptionsExt;
+    use windows_sys::Win32::Storage::FileSystem::{FILE_SHARE_READ, FILE_SHARE_WRITE};
+
+    let mut content = String::new();
+    std::fs::OpenOptions::new()
+        .read(true)
+        .share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE)
+        .open(path)
+        .ok()?
+        .read_to_string(&mut content)
+        .ok()?;
+    let pid = content.trim().parse().ok()?;
+    crate::is_pid_alive(pid).unwrap_or(false).then_some(pid)
+}
+
+// Takes the service's lock file, waiting up to `timeout` for another holder to let go;
+// without one, a held lock fails right away
+#[cfg(windows)]
+pub fn acquire(service: &str, path: &Path, timeout: Option<Duration>) -> Result<FileLock, SvcError> {
+    use crate::{cancel, progress};
+    use std::io::Write;
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::time::Instant;
+    use windows_sys::Win32::Storage::FileSystem::FILE_SHARE_READ;
+
+    // ERROR_SHARING_VIOLATION: someone else has it open for writing
+    const SHARING_VIOLATION: i32 = 32;
+
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    let deadline = Instant::now() + timeout.unwrap_or_default();
+    // Announced once it turns out to be held
+    let mut waiting: Option<progress::Phase> = None;
+    loop {
+        let opened = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .share_mode(FILE_SHARE_READ)
+            .open(path);
+        match opened {
+            Ok(mut file) => {
+                // Only a hint for whoever finds it held, so failing to write it doesn't matter
+                let _ = file.set_len(0).and_then(|()| writeln!(file, "{}", std::process::id()));
+                return Ok(FileLock { _file: file });
+            }
+            Err(err) if err.raw_os_error() == Some(SHARING_VIOLATION) => {
+                if Instant::now() >= deadline {
+                    return Err(SvcError::LockHeld {
+                        service: service.to_string(),
+                        path: path.display().to_string(),
+                        pid: holder(path),
+                    });
+                }
+                cancel::sleep(POLL)?;
+                waiting.get_or_insert_with(|| progress::Phase::begin("waiting", service, "lock")).tick();
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+// Sharing modes are a Windows facility; elsewhere runs simply don't exclude each other
+#[cfg(not(windows))]
+pub fn acquire(_service: &str, _path: &Path, _timeout: Option<Duration>) -> Result<FileLock, SvcError> {
+    Ok(FileLock {})
+}
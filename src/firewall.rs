@@ -0,0 +1,168 @@
+use crate::{capture, pathcmp, Service, ServiceType, SvcError};
+use colored::Colorize;
+use serde::Deserialize;
+use std::fmt::Display;
+use std::process::Command;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Protocol {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+impl Display for Protocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Protocol::Tcp => "TCP",
+            Protocol::Udp => "UDP",
+        })
+    }
+}
+
+// Inbound rule `svc firewall apply` keeps for the service's executable
+#[derive(Debug, Deserialize)]
+pub struct Firewall {
+    // Every port when empty
+    #[serde(default)]
+    pub inbound_ports: Vec<u16>,
+    #[serde(default)]
+    pub protocol: Protocol,
+}
+
+impl Firewall {
+    // As netsh writes LocalPort
+    fn ports(&self) -> String {
+        match self.inbound_ports.as_slice() {
+            [] => "Any".to_string(),
+            ports => ports.iter().map(u16::to_string).collect::<Vec<_>>().join(","),
+        }
+    }
+}
+
+pub fn rule_name(service: &str) -> String {
+    format!("svc: {}", service)
+}
+
+// The parts of an existing rule svc compares against the config
+struct Rule {
+    program: String,
+    ports: String,
+    protocol: String,
+}
+
+fn netsh() -> Command {
+    let mut command = Command::new("netsh");
+    command.args(["advfirewall", "firewall"]);
+    command
+}
+
+// netsh exits non-zero with "No rules match the specified criteria." for a missing rule
+fn query(service: &str) -> Result<Option<Rule>, SvcError> {
+    let output = capture::output(netsh().args(["show", "rule", &format!("name={}", rule_name(service)), "verbose"]))?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    // Lines look like "Program:          C:\app\server.exe"
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let field = |key: &str| {
+        stdout.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            (name.trim() == key).then(|| value.trim().to_string())
+        })
+    };
+    Ok(Some(Rule {
+        program: field("Program").unwrap_or_default(),
+        ports: field("LocalPort").unwrap_or_default(),
+        protocol: field("Protocol").unwrap_or_default(),
+    }))
+}
+
+// Whether svc has a rule for the service, configured or not
+pub fn has_rule(service: &str) -> Result<bool, SvcError> {
+    Ok(query(service)?.is_some())
+}
+
+pub fn delete(service: &str) -> Result<(), SvcError> {
+    capture::run_helper(netsh().args(["delete", "rule", &format!("name={}", rule_name(service))]))
+}
+
+fn firewall<'a>(service: &'a Service) -> Result<&'a Firewall, SvcError> {
+    let firewall = service
+        .firewall
+        .as_ref()
+        .ok_or_else(|| SvcError::InvalidCommand(format!("firewall apply for {}, which has no firewall block", service.name)))?;
+    // A rule on the interpreter would open the port to every script it runs
+    if let ServiceType::Util = service.service_type {
+        return Err(SvcError::InvalidCommand("firewall apply for a Util service".to_string()));
+    }
+    Ok(firewall)
+}
+
+// Replace the service's rule with one matching the config, or only delete it
+pub fn apply(service: &Service, remove: bool) -> Result<(), SvcError> {
+    let name = rule_name(&service.name);
+    let existing = query(&service.name)?;
+    if remove {
+        if existing.is_none() {
+            println!("No firewall rule {} to remove.", name.cyan());
+            return Ok(());
+        }
+        delete(&service.name)?;
+        println!("Firewall rule {} removed.", name.cyan());
+        return Ok(());
+    }
+
+    let firewall = firewall(service)?;
+    if existing.is_some() {
+        delete(&service.name)?;
+    }
+    capture::run_helper(netsh().args([
+        "add",
+        "rule",
+        &format!("name={}", name),
+        "dir=in",
+        "action=allow",
+        &format!("program={}", service.path),
+        &format!("protocol={}", firewall.protocol),
+        &format!("localport={}", firewall.ports()),
+        "enable=yes",
+    ]))?;
+    println!(
+        "Firewall rule {} allows inbound {} {} to {}.",
+        name.cyan(),
+        firewall.protocol,
+        firewall.ports(),
+        service.path
+    );
+    Ok(())
+}
+
+// What is wrong with the rule of a service that declares one; Err when netsh can't be asked
+pub fn problem(service: &Service) -> Result<Option<String>, SvcError> {
+    let Some(firewall) = &service.firewall else {
+        return Ok(None);
+    };
+    let fix = format!("run 'svc firewall apply {}'", service.name);
+    let Some(rule) = query(&service.name)? else {
+        return Ok(Some(format!("firewall rule '{}' is missing; {}", rule_name(&service.name), fix)));
+    };
+    Ok(if !pathcmp::same_file(&rule.program, &service.path) {
+        Some(format!("firewall rule points at {}, not {}; {}", rule.program, service.path, fix))
+    } else if !rule.ports.eq_ignore_ascii_case(&firewall.ports())
+        || !rule.protocol.eq_ignore_ascii_case(&firewall.protocol.to_string())
+    {
+        Some(format!(
+            "firewall rule allows {} {}, the config asks for {} {}; {}",
+            rule.protocol,
+            rule.ports,
+            firewall.protocol,
+            firewall.ports(),
+            fix
+        ))
+    } else {
+        None
+    })
+}
@@ -0,0 +1,227 @@
+use crate::{startup, SvcError};
+use serde::Serialize;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+const CURRENT_VERSION: &str = r"HKLM\SOFTWARE\Microsoft\Windows NT\CurrentVersion";
+
+// The edition of Windows svc runs on, by the InstallationType setup records
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Flavor {
+    Client,
+    Server,
+    ServerCore,
+    NanoServer,
+    // Not recorded or not readable; nothing is held back then
+    Unknown,
+}
+
+impl Flavor {
+    fn from_installation_type(value: &str) -> Flavor {
+        match value.trim() {
+            "Client" => Flavor::Client,
+            "Server" => Flavor::Server,
+            "Server Core" => Flavor::ServerCore,
+            "Nano Server" => Flavor::NanoServer,
+            _ => Flavor::Unknown,
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            Flavor::Client => "Windows",
+            Flavor::Server => "Windows Server",
+            Flavor::ServerCore => "Windows Server Core",
+            Flavor::NanoServer => "Nano Server",
+            Flavor::Unknown => "an edition of Windows svc couldn't tell",
+        }
+    }
+}
+
+// Something svc uses that some editions leave out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Feature {
+    Toasts,
+    StartupFolder,
+    Wmi,
+    WindowsTerminal,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 4] = [Feature::Toasts, Feature::StartupFolder, Feature::Wmi, Feature::WindowsTerminal];
+
+    fn needed_by(self) -> &'static str {
+        match self {
+            Feature::Toasts => "the toast notification sink",
+            Feature::StartupFolder => "--backend startup-folder",
+            Feature::Wmi => "finding terminal_profile services",
+            Feature::WindowsTerminal => "run --terminal",
+        }
+    }
+
+    // What svc does instead where the feature is missing; None when the command fails
+    fn fallback(self) -> Option<&'static str> {
+        match self {
+            Feature::Toasts => Some("toasts are skipped, the other sinks still notify"),
+            Feature::StartupFolder => None,
+            Feature::Wmi => Some("processes are found by executable and by what svc launched"),
+            Feature::WindowsTerminal => Some("the service opens in a new console window"),
+        }
+    }
+}
+
+impl Feature {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Feature::Toasts => "Toast notifications",
+            Feature::StartupFolder => "The Startup folder",
+            Feature::Wmi => "WMI",
+            Feature::WindowsTerminal => "Windows Terminal",
+        }
+    }
+}
+
+impl Display for Feature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Feature::Toasts => "toasts",
+            Feature::StartupFolder => "startup-folder",
+            Feature::Wmi => "wmi",
+            Feature::WindowsTerminal => "windows-terminal",
+        })
+    }
+}
+
+// Why the flavor lacks the feature, or None when it has it
+fn constraint(flavor: Flavor, feature: Feature) -> Option<&'static str> {
+    match (flavor, feature) {
+        (Flavor::ServerCore | Flavor::NanoServer, Feature::Toasts) => {
+            Some("there is no notification center to show them")
+        }
+        (Flavor::ServerCore | Flavor::NanoServer, Feature::StartupFolder) => {
+            Some("no Explorer shell runs the Startup folder at logon")
+        }
+        (Flavor::ServerCore | Flavor::NanoServer, Feature::WindowsTerminal) => {
+            Some("there is no desktop to install it on")
+        }
+        (Flavor::NanoServer, Feature::Wmi) => Some("there is no Windows PowerShell to query it with"),
+        _ => None,
+    }
+}
+
+// Read once per invocation, and only when something asks
+pub fn current() -> Flavor {
+    static FLAVOR: OnceLock<Flavor> = OnceLock::new();
+    *FLAVOR.get_or_init(|| match startup::query_value_in(CURRENT_VERSION, "InstallationType") {
+        Ok(Some(value)) => Flavor::from_installation_type(&value),
+        _ => Flavor::Unknown,
+    })
+}
+
+// Why this machine lacks the feature, for callers that do without it
+pub fn missing(feature: Feature) -> Option<&'static str> {
+    constraint(current(), feature)
+}
+
+// Refuse up front rather than fail inside the helper that needs the feature
+pub fn require(feature: Feature) -> Result<(), SvcError> {
+    check(current(), feature)
+}
+
+fn check(flavor: Flavor, feature: Feature) -> Result<(), SvcError> {
+    match constraint(flavor, feature) {
+        Some(constraint) => Err(SvcError::FeatureUnavailable {
+            feature,
+            platform: flavor.describe(),
+            constraint,
+        }),
+        None => Ok(()),
+    }
+}
+
+#[derive(Serialize)]
+pub struct Availability {
+    pub feature: Feature,
+    pub available: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub constraint: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<&'static str>,
+    pub needed_by: &'static str,
+}
+
+impl Availability {
+    // "ok", "degraded" when svc does without it, "missing" when what needs it fails
+    pub fn state(&self) -> &'static str {
+        match (self.constraint, self.fallback) {
+            (None, _) => "ok",
+            (Some(_), Some(_)) => "degraded",
+            (Some(_), None) => "missing",
+        }
+    }
+}
+
+fn matrix(flavor: Flavor) -> Vec<Availability> {
+    Feature::ALL
+        .iter()
+        .map(|&feature| {
+            let constraint = constraint(flavor, feature);
+            Availability {
+                feature,
+                available: constraint.is_none(),
+                constraint,
+                fallback: constraint.and(feature.fallback()),
+                needed_by: feature.needed_by(),
+            }
+        })
+        .collect()
+}
+
+// For `svc doctor`: the flavor and what each feature comes to on it
+pub fn report() -> (Flavor, Vec<Availability>) {
+    let flavor = current();
+    (flavor, matrix(flavor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn installation_types_map_to_flavors() {
+        assert_eq!(Flavor::from_installation_type("Client"), Flavor::Client);
+        assert_eq!(Flavor::from_installation_type("Server Core"), Flavor::ServerCore);
+        assert_eq!(Flavor::from_installation_type("Nano Server"), Flavor::NanoServer);
+        assert_eq!(Flavor::from_installation_type("Something New"), Flavor::Unknown);
+    }
+
+    #[test]
+    fn desktops_and_unknown_editions_have_everything() {
+        for flavor in [Flavor::Client, Flavor::Server, Flavor::Unknown] {
+            assert!(matrix(flavor).iter().all(|availability| availability.available), "{:?}", flavor);
+        }
+    }
+
+    #[test]
+    fn server_core_degrades_what_it_can_and_refuses_the_rest() {
+        let core = matrix(Flavor::ServerCore);
+        let state = |feature| core.iter().find(|row| row.feature == feature).unwrap().state();
+        assert_eq!(state(Feature::Toasts), "degraded");
+        assert_eq!(state(Feature::WindowsTerminal), "degraded");
+        assert_eq!(state(Feature::Wmi), "ok");
+        assert_eq!(state(Feature::StartupFolder), "missing");
+
+        let err = check(Flavor::ServerCore, Feature::StartupFolder).unwrap_err();
+        assert_eq!(err.code(), "feature_unavailable");
+        assert!(err.to_string().contains("Windows Server Core"), "{}", err);
+        assert!(check(Flavor::ServerCore, Feature::Wmi).is_ok());
+    }
+
+    #[test]
+    fn nano_server_has_no_wmi_either() {
+        assert!(check(Flavor::NanoServer, Feature::Wmi).is_err());
+        assert!(matrix(Flavor::NanoServer).iter().all(|availability| !availability.available));
+    }
+}
@@ -0,0 +1,253 @@
+use crate::effects::{self, Effect};
+use crate::process::is_svc_alive;
+use crate::{cancel, yamledit, SvcError};
+use serde_yaml::{Mapping, Value};
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const LOCK_WAIT: Duration = Duration::from_secs(5);
+
+// Fields that always lead a service entry, in this order; the rest follow alphabetically
+const LEADING_FIELDS: [&str; 3] = ["name", "type", "path"];
+
+fn key_rank(key: &Value) -> (usize, String) {
+    let key = key.as_str().unwrap_or_default();
+    let rank = LEADING_FIELDS
+        .iter()
+        .position(|&field| field == key)
+        .unwrap_or(LEADING_FIELDS.len());
+    (rank, key.to_string())
+}
+
+fn sort_mapping(mapping: &Mapping, top_level: bool) -> Mapping {
+    let mut entries: Vec<(&Value, &Value)> = mapping.iter().collect();
+    if top_level {
+        entries.sort_by_key(|(key, _)| key_rank(key));
+    } else {
+        entries.sort_by_key(|(key, _)| key.as_str().unwrap_or_default().to_string());
+    }
+
+    entries
+        .into_iter()
+        .map(|(key, value)| (key.clone(), canonical_value(value, false)))
+        .collect()
+}
+
+fn canonical_value(value: &Value, service_level: bool) -> Value {
+    match value {
+        Value::Mapping(mapping) => Value::Mapping(sort_mapping(mapping, service_level)),
+        Value::Sequence(items) => {
+            Value::Sequence(items.iter().map(|item| canonical_value(item, false)).collect())
+        }
+        other => other.clone(),
+    }
+}
+
+fn canonical_services(services: &Value) -> Value {
+    match services {
+        Value::Sequence(services) => Value::Sequence(
+            services
+                .iter()
+                .map(|service| canonical_value(service, true))
+                .collect(),
+        ),
+        other => canonical_value(other, false),
+    }
+}
+
+// Canonical form of a parsed config: services keep their declared order,
+// fields within each service are ordered deterministically. In the mapping
+// form, settings come first alphabetically and the service list last.
+pub fn to_canonical(config: &Value) -> Result<String, SvcError> {
+    let canonical = match config {
+        Value::Mapping(mapping) => {
+            let mut entries: Vec<(&Value, &Value)> = mapping.iter().collect();
+            entries.sort_by_key(|(key, _)| {
+                let key = key.as_str().unwrap_or_default();
+                (key == "services", key.to_string())
+            });
+            Value::Mapping(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| match key.as_str() {
+                        Some("services") => (key.clone(), canonical_services(value)),
+                        _ => (key.clone(), canonical_value(value, false)),
+                    })
+                    .collect(),
+            )
+        }
+        other => canonical_services(other),
+    };
+    Ok(serde_yaml::to_string(&canonical)?)
+}
+
+// Comments in YAML text, whole-line and trailing ones alike: a # at the start of a line or
+// after whitespace, outside a quoted scalar. Quotes only open one at its start, so the
+// apostrophe of `it's` doesn't
+fn has_comment(line: &str) -> bool {
+    let (mut quote, mut previous, mut escaped) = (None, ' ', false);
+    for c in line.chars() {
+        match quote {
+            None if c == '#' && previous.is_whitespace() => return true,
+            None if matches!(c, '"' | '\'') && (previous.is_whitespace() || "[{,:-".contains(previous)) => {
+                quote = Some(c)
+            }
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(open) if c == open => quote = None,
+            _ => {}
+        }
+        previous = c;
+    }
+    false
+}
+
+pub fn comments(content: &str) -> usize {
+    content.lines().filter(|line| has_comment(line)).count()
+}
+
+pub fn canonicalize(content: &str) -> Result<String, SvcError> {
+    to_canonical(&serde_yaml::from_str::<Value>(content)?)
+}
+
+pub fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// Leftover of a write that was interrupted before the rename
+pub fn temp_path(path: &Path) -> PathBuf {
+    sibling(path, ".tmp")
+}
+
+// Held for the duration of a read-modify-write of the config; removed on drop
+pub struct ConfigLock {
+    path: PathBuf,
+}
+
+impl Drop for ConfigLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub fn lock_path(config_path: &Path) -> PathBuf {
+    sibling(config_path, ".lock")
+}
+
+pub fn lock_config(config_path: &Path) -> Result<ConfigLock, SvcError> {
+    let path = lock_path(config_path);
+    let started = Instant::now();
+
+    loop {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                write!(file, "{}", std::process::id())?;
+                return Ok(ConfigLock { path });
+            }
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                let owner = fs::read_to_string(&path)
+                    .ok()
+                    .and_then(|pid| pid.trim().parse::<u64>().ok());
+                match owner {
+                    // The writer died mid-mutation, its lock is stale
+                    Some(pid) if !is_svc_alive(pid)? => break_stale(&path, owner),
+                    Some(pid) if started.elapsed() >= LOCK_WAIT => {
+                        return Err(SvcError::ConfigLocked(pid));
+                    }
+                    None if started.elapsed() >= LOCK_WAIT => break_stale(&path, owner),
+                    _ => cancel::sleep(Duration::from_millis(100))?,
+                }
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+// Two waiters can find a lock stale at once, and the second to remove it would remove the lock
+// the first just took. So it is renamed aside first, and only dropped when what was taken aside
+// still names the `owner` found dead; a live one goes back, unless another has taken its place
+fn break_stale(path: &Path, owner: Option<u64>) {
+    let aside = sibling(path, &format!(".stale-{}", std::process::id()));
+    if fs::rename(path, &aside).is_err() {
+        return;
+    }
+    let held = fs::read_to_string(&aside).ok().and_then(|pid| pid.trim().parse::<u64>().ok());
+    if held != owner {
+        // A hard link never replaces a file, unlike a rename
+        let _ = fs::hard_link(&aside, path);
+    }
+    let _ = fs::remove_file(&aside);
+}
+
+// Replace the file in one step so an interrupted write can't truncate it
+pub fn write_atomic(path: &Path, content: &str) -> Result<(), SvcError> {
+    let effect = Effect::WriteFile {
+        path: path.display().to_string(),
+    };
+    effects::perform(effect, || {
+        let temp = temp_path(path);
+        let mut file = File::create(&temp)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&temp, path)?;
+        Ok(())
+    })
+    .map(drop)
+}
+
+// Every command that changes the config goes through here. The change is made to the file's
+// text where it can be, so comments, blank lines and untouched entries stay as written; a new
+// file, or a change those edits can't make, gets the canonical form
+pub fn write_config(path: &Path, config: &Value) -> Result<(), SvcError> {
+    let current = fs::read_to_string(path).ok();
+    let content = match current.as_deref().and_then(|current| yamledit::edit(current, config)) {
+        Some(content) => content,
+        None => to_canonical(config)?,
+    };
+    if current.is_some_and(|current| current == content) {
+        return Ok(());
+    }
+    write_atomic(path, &content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn breaking_a_stale_lock_leaves_one_taken_since_in_place() {
+        let dir = std::env::temp_dir().join(format!("svc-lock-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = lock_path(&dir.join("services.yaml"));
+        fs::write(&path, "4000000").unwrap();
+        break_stale(&path, Some(4_000_000));
+        assert!(!path.exists());
+
+        // Another waiter broke it first and holds it now, under its own PID
+        fs::write(&path, "4100").unwrap();
+        break_stale(&path, Some(4_000_000));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "4100");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn trailing_comments_count_as_well_as_whole_lines() {
+        let content = "# services for the lab
+services:
+  - name: api   # the web API
+    path: 'D:\\api #2\\api.exe'
+    args: [\"--tag=#1\", x]  # two of them
+    title: it's #3 of them
+    url: http://host/#anchor
+    note: \"a \\\" # still quoted\"
+";
+        assert_eq!(comments(content), 4);
+        assert_eq!(comments("services: []\n"), 0);
+    }
+}
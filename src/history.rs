@@ -0,0 +1,86 @@
+use crate::output::timestamp;
+use crate::state::{self, state_dir};
+use crate::{effects, safe, SvcError};
+use serde::{Serialize, Serializer};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+const HISTORY_FILE: &str = "history.jsonl";
+// Output lines kept per failed record unless `history_output_lines` says otherwise
+pub const DEFAULT_OUTPUT_LINES: usize = 100;
+
+static OUTPUT_LINES: OnceLock<usize> = OnceLock::new();
+
+pub fn set_output_lines(lines: usize) {
+    let _ = OUTPUT_LINES.set(lines);
+}
+
+// One line of history.jsonl in the state directory
+#[derive(Debug, Serialize)]
+pub struct HistoryRecord<'a> {
+    pub time: String,
+    pub service: &'a str,
+    pub action: &'a str,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Vec::is_empty", serialize_with = "output_tail")]
+    pub output: Vec<String>,
+    // Scratch directory of a temp_work_dir run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scratch: Option<String>,
+    // Per-step outcome of a service with steps
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<StepRecord>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StepRecord {
+    pub path: String,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+}
+
+// The tail says the most about a failure
+fn output_tail<S: Serializer>(output: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+    let limit = *OUTPUT_LINES.get().unwrap_or(&DEFAULT_OUTPUT_LINES);
+    output[output.len().saturating_sub(limit)..].serialize(serializer)
+}
+
+impl<'a> HistoryRecord<'a> {
+    pub fn new(service: &'a str, action: &'a str, success: bool) -> Self {
+        HistoryRecord {
+            time: timestamp(),
+            service,
+            action,
+            success,
+            exit_code: None,
+            output: Vec::new(),
+            scratch: None,
+            steps: Vec::new(),
+        }
+    }
+}
+
+pub fn history_path() -> Result<PathBuf, SvcError> {
+    Ok(state_dir()?.join(HISTORY_FILE))
+}
+
+pub fn record(entry: &HistoryRecord) -> Result<(), SvcError> {
+    if safe::on() || effects::dry_run() {
+        return Ok(());
+    }
+    state::append(&history_path()?, &serde_json::to_string(entry).map_err(std::io::Error::other)?)
+}
+
+// Outcome of a mutating command; output is only kept for failures, to keep records small
+pub fn record_result(service: &str, action: &str, result: &Result<(), SvcError>) {
+    let mut entry = HistoryRecord::new(service, action, result.is_ok());
+    entry.output = match result {
+        Ok(()) => Vec::new(),
+        Err(SvcError::HelperFailed { output, .. }) => output.clone(),
+        Err(err) => vec![err.to_string()],
+    };
+    let _ = record(&entry);
+}
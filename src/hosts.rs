@@ -0,0 +1,91 @@
+use crate::{Config, SvcError};
+use serde_yaml::{Mapping, Value};
+use std::sync::OnceLock;
+
+static HOST: OnceLock<String> = OnceLock::new();
+
+// Pretend to be another machine, for trying out a hosts section
+pub fn set_override(host: String) {
+    let _ = HOST.set(host);
+}
+
+// Computer name the hosts section is matched against
+pub fn current() -> Option<String> {
+    HOST.get()
+        .cloned()
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+}
+
+// Computer names are case-insensitive on Windows
+pub fn matches(key: &str, host: &str) -> bool {
+    key.eq_ignore_ascii_case(host)
+}
+
+// Merge the current machine's overrides into the service entries, before they're deserialized
+pub fn apply(document: &mut Value) -> Result<(), SvcError> {
+    let Some(host) = current() else {
+        return Ok(());
+    };
+    let Some(Value::Mapping(hosts)) = document.get("hosts") else {
+        return Ok(());
+    };
+    let Some((_, Value::Mapping(overrides))) = hosts.iter().find(|(key, _)| key.as_str().is_some_and(|key| matches(key, &host)))
+    else {
+        return Ok(());
+    };
+    let overrides = overrides.clone();
+
+    let Some(services) = document.get_mut("services").and_then(Value::as_sequence_mut) else {
+        return Ok(());
+    };
+    for (name, fields) in &overrides {
+        let Value::Mapping(fields) = fields else {
+            return Err(SvcError::InvalidHostOverride {
+                host: host.clone(),
+                reason: format!("the entry for {} must be a mapping of fields", name.as_str().unwrap_or("?")),
+            });
+        };
+        if fields.contains_key("name") {
+            return Err(SvcError::InvalidHostOverride {
+                host: host.clone(),
+                reason: "a service's name can't be overridden".to_string(),
+            });
+        }
+        let entry = services
+            .iter_mut()
+            .filter_map(Value::as_mapping_mut)
+            .find(|entry| entry.get("name") == Some(name));
+        if let Some(entry) = entry {
+            for (field, value) in fields {
+                entry.insert(field.clone(), value.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+// Checked at load for every host, not only this one, so a typo shows up on any machine
+pub fn validate(config: &Config) -> Result<(), SvcError> {
+    for (host, overrides) in &config.settings.hosts {
+        // An override of an entry lenient loading left out has nothing to apply to, which is no error
+        let defined = |name: &str| {
+            config.services.iter().any(|s| s.name == name) || config.skipped.iter().any(|entry| entry.service == name)
+        };
+        if let Some(name) = overrides.keys().find(|name| !defined(name)) {
+            return Err(SvcError::InvalidHostOverride {
+                host: host.clone(),
+                reason: format!("service {} is not defined", name),
+            });
+        }
+    }
+    Ok(())
+}
+
+// Host and fields whose overrides were applied to a service on this machine
+pub fn applied<'a>(config: &'a Config, service: &str) -> Option<(&'a str, Vec<&'a str>)> {
+    let host = current()?;
+    let (key, overrides) = config.settings.hosts.iter().find(|(key, _)| matches(key, &host))?;
+    let fields: &Mapping = overrides.get(service)?;
+    Some((key, fields.keys().filter_map(Value::as_str).collect()))
+}
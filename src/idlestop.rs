@@ -0,0 +1,116 @@
+use crate::units::HumanDuration;
+use crate::Service;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::time::Instant;
+
+// Percent of the whole machine below which a service counts as idle, as for `restart --when-idle`
+const DEFAULT_CPU_BELOW: f64 = 5.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdleMetric {
+    // CPU use between two metrics samples of the watcher
+    #[default]
+    Cpu,
+    // None of its processes owns the foreground window
+    NoWindowFocus,
+}
+
+// When `svc watch` stops a service nobody is using; `svc run` starts it again as usual
+#[derive(Debug, Deserialize)]
+pub struct IdleStop {
+    pub after: HumanDuration,
+    #[serde(default)]
+    pub metric: IdleMetric,
+    // Cpu only: percent of the whole machine, default 5
+    pub cpu_below: Option<f64>,
+}
+
+impl Display for IdleStop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.metric {
+            IdleMetric::Cpu => write!(f, "below {}% CPU for {}", self.cpu_below.unwrap_or(DEFAULT_CPU_BELOW), self.after),
+            IdleMetric::NoWindowFocus => write!(f, "without window focus for {}", self.after),
+        }
+    }
+}
+
+// The process owning the foreground window, if there is one
+#[cfg(windows)]
+fn foreground_pid() -> Option<u64> {
+    use windows_sys::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowThreadProcessId};
+
+    let window = unsafe { GetForegroundWindow() };
+    if window.is_null() {
+        return None;
+    }
+    let mut pid = 0u32;
+    unsafe { GetWindowThreadProcessId(window, &mut pid) };
+    (pid != 0).then_some(u64::from(pid))
+}
+
+#[cfg(not(windows))]
+fn foreground_pid() -> Option<u64> {
+    None
+}
+
+struct Observed {
+    pids: Vec<u64>,
+    cpu_ms: u64,
+    at: Instant,
+    idle_since: Option<Instant>,
+}
+
+// Idleness of every idle_stop service across the watcher's sampling rounds. A round that
+// can't tell, e.g. a failed snapshot or a process svc may not read, counts as busy
+#[derive(Default)]
+pub struct Tracker {
+    observed: HashMap<String, Observed>,
+}
+
+impl Tracker {
+    pub fn forget(&mut self, name: &str) {
+        self.observed.remove(name);
+    }
+
+    // Record one round; true once the service has been idle for its `after`
+    pub fn observe(&mut self, service: &Service, pids: &[u64], cpu_ms: Option<u64>) -> bool {
+        let Some(idle_stop) = &service.idle_stop else {
+            return false;
+        };
+        let now = Instant::now();
+        let mut pids = pids.to_vec();
+        pids.sort_unstable();
+        let previous = self.observed.remove(service.name.as_ref());
+        let idle = match idle_stop.metric {
+            IdleMetric::NoWindowFocus => foreground_pid().map(|pid| !pids.contains(&pid)),
+            // Only comparable with a previous round over the same processes
+            IdleMetric::Cpu => match (&previous, cpu_ms) {
+                (Some(previous), Some(cpu_ms)) if previous.pids == pids && cpu_ms >= previous.cpu_ms => {
+                    let elapsed = now.duration_since(previous.at).as_millis() as f64;
+                    let cores = std::thread::available_parallelism().map_or(1, |n| n.get()) as f64;
+                    let percent = (cpu_ms - previous.cpu_ms) as f64 / elapsed.max(1.0) / cores * 100.0;
+                    Some(percent < idle_stop.cpu_below.unwrap_or(DEFAULT_CPU_BELOW))
+                }
+                _ => None,
+            },
+        };
+        // The first idle round only starts the clock, as the time before it is unknown
+        let idle_since = match idle {
+            Some(true) => Some(previous.and_then(|previous| previous.idle_since).unwrap_or(now)),
+            _ => None,
+        };
+        self.observed.insert(
+            service.name.to_string(),
+            Observed {
+                pids,
+                cpu_ms: cpu_ms.unwrap_or(0),
+                at: now,
+                idle_since,
+            },
+        );
+        idle_since.is_some_and(|since| now.duration_since(since) >= idle_stop.after.get())
+    }
+}
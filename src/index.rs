@@ -0,0 +1,202 @@
+use crate::state::state_dir;
+use crate::{cli, read_config, Service, SvcError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+const INDEX_FILE: &str = "index.json";
+
+// Byte ranges of each top-level entry, so one service can be parsed without the rest
+#[derive(Debug, Serialize, Deserialize)]
+struct ConfigIndex {
+    path: String,
+    modified: u128,
+    len: u64,
+    entries: HashMap<String, (usize, usize)>,
+    // Whether any entry sets a port, as status then checks every service for conflicts
+    ports: bool,
+}
+
+#[derive(Deserialize)]
+struct NameOnly {
+    name: String,
+    #[serde(default)]
+    port: Option<serde_yaml::Value>,
+}
+
+fn file_stamp(path: &Path) -> Result<(u128, u64), SvcError> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    Ok((modified, metadata.len()))
+}
+
+// Split a block-style list into its top-level "- " items
+fn entry_ranges(content: &str) -> Option<Vec<(usize, usize)>> {
+    let mut starts = Vec::new();
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        if line.starts_with("- ") || line.trim_end() == "-" {
+            starts.push(offset);
+        } else if !is_blank_or_comment(line) && (starts.is_empty() || !line.starts_with(' ')) {
+            // Not a plain list of entries, leave it to the full parser
+            return None;
+        }
+        offset += line.len();
+    }
+
+    let mut ranges: Vec<(usize, usize)> = starts.windows(2).map(|w| (w[0], w[1])).collect();
+    if let Some(&last) = starts.last() {
+        ranges.push((last, content.len()));
+    }
+    Some(ranges)
+}
+
+fn is_blank_or_comment(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+fn build(path: &Path, content: &str) -> Result<Option<ConfigIndex>, SvcError> {
+    let Some(ranges) = entry_ranges(content) else {
+        return Ok(None);
+    };
+
+    let mut entries = HashMap::with_capacity(ranges.len());
+    let mut ports = false;
+    for (start, end) in ranges {
+        let names: Vec<NameOnly> = serde_yaml::from_str(&content[start..end])?;
+        for entry in names {
+            ports |= entry.port.is_some();
+            if entries.insert(entry.name, (start, end)).is_some() {
+                // Duplicates are reported by the full parse
+                return Ok(None);
+            }
+        }
+    }
+
+    let (modified, len) = file_stamp(path)?;
+    Ok(Some(ConfigIndex {
+        path: path.display().to_string(),
+        modified,
+        len,
+        entries,
+        ports,
+    }))
+}
+
+fn cached(path: &Path) -> Option<ConfigIndex> {
+    let content = fs::read_to_string(state_dir().ok()?.join(INDEX_FILE)).ok()?;
+    let index: ConfigIndex = serde_json::from_str(&content).ok()?;
+    let (modified, len) = file_stamp(path).ok()?;
+
+    // Any change to the file invalidates the whole index
+    (index.path == path.display().to_string() && index.modified == modified && index.len == len)
+        .then_some(index)
+}
+
+fn save(index: &ConfigIndex) {
+    if let (Ok(dir), Ok(json)) = (state_dir(), serde_json::to_string(index)) {
+        let _ = fs::write(dir.join(INDEX_FILE), json);
+    }
+}
+
+// Parse only the entry for `name`; None means the caller should fall back to a full parse
+pub fn load_service(path: &Path, name: &str) -> Result<Option<Service<'static>>, SvcError> {
+    Ok(load(path, name)?.map(|(_, service)| service))
+}
+
+// The entry for `name` when nothing about it needs the other entries: no depends_on, and no
+// port anywhere in the file
+pub fn load_standalone(path: &Path, name: &str) -> Result<Option<Service<'static>>, SvcError> {
+    Ok(load(path, name)?.filter(|(ports, service)| !ports && service.depends_on.is_empty()).map(|(_, service)| service))
+}
+
+fn load(path: &Path, name: &str) -> Result<Option<(bool, Service<'static>)>, SvcError> {
+    let content = read_config(path)?;
+    let index = match cached(path) {
+        Some(index) => index,
+        None => match build(path, &content)? {
+            Some(index) => {
+                save(&index);
+                index
+            }
+            None => return Ok(None),
+        },
+    };
+
+    // An empty config takes the full load, which explains that nothing is configured
+    if index.entries.is_empty() {
+        return Ok(None);
+    }
+    let Some(&(start, end)) = index.entries.get(name) else {
+        return Err(SvcError::ServiceNotFound {
+            name: name.to_string(),
+            suggestion: cli::suggest(name, index.entries.keys().map(String::as_str)).map(String::from),
+        });
+    };
+    let Some(chunk) = content.get(start..end) else {
+        return Ok(None);
+    };
+    let services: Vec<Service> = serde_yaml::from_str(chunk)?;
+    Ok(services.into_iter().find(|s| s.name == name).map(|service| (index.ports, service)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "# services\n- name: api\n  path: a.exe\n  type: Executable\n\n- name: job\n  path: j.py\n  type: Util\n";
+
+    #[test]
+    fn ranges_cover_each_entry() {
+        let ranges = entry_ranges(CONFIG).unwrap();
+        assert_eq!(ranges.len(), 2);
+        assert!(CONFIG[ranges[0].0..ranges[0].1].contains("name: api"));
+        assert!(CONFIG[ranges[1].0..ranges[1].1].contains("name: job"));
+        assert_eq!(ranges[1].1, CONFIG.len());
+    }
+
+    #[test]
+    fn settings_form_is_left_to_the_full_parse() {
+        assert!(entry_ranges("settings:\n  notify: true\nservices:\n  - name: api\n").is_none());
+    }
+
+    #[test]
+    fn names_map_to_their_entries() {
+        let path = std::env::temp_dir().join(format!("svc-index-test-{}.yaml", std::process::id()));
+        fs::write(&path, CONFIG).unwrap();
+        let index = build(&path, CONFIG).unwrap().unwrap();
+        let (start, end) = index.entries["job"];
+        let services: Vec<Service> = serde_yaml::from_str(&CONFIG[start..end]).unwrap();
+        assert_eq!(services[0].name, "job");
+        assert!(!index.entries.contains_key("missing"));
+
+        let duplicated = format!("{}- name: api\n  path: b.exe\n  type: Executable\n", CONFIG);
+        assert!(build(&path, &duplicated).unwrap().is_none());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn an_entry_stands_alone_without_dependencies_or_ports() {
+        let path = std::env::temp_dir().join(format!("svc-index-alone-test-{}.yaml", std::process::id()));
+        let depending = format!("{}- name: web\n  path: w.exe\n  type: Executable\n  depends_on: [api]\n", CONFIG);
+        fs::write(&path, &depending).unwrap();
+        assert_eq!(load_standalone(&path, "api").unwrap().unwrap().name, "api");
+        assert!(load_standalone(&path, "web").unwrap().is_none());
+        assert_eq!(load_service(&path, "web").unwrap().unwrap().name, "web");
+
+        // Another entry's port can clash with what this one listens on
+        let ported = format!("{}- name: db\n  path: d.exe\n  type: Executable\n  port: 5432\n", CONFIG);
+        fs::write(&path, &ported).unwrap();
+        assert!(!build(&path, CONFIG).unwrap().unwrap().ports);
+        assert!(load_standalone(&path, "api").unwrap().is_none());
+        assert_eq!(load_service(&path, "api").unwrap().unwrap().name, "api");
+        let _ = fs::remove_file(&path);
+    }
+}
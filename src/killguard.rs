@@ -0,0 +1,109 @@
+use crate::history::{self, HistoryRecord};
+use crate::{cancel, pathcmp, procinfo, runas, spawned, state, Service, SvcError};
+use colored::Colorize;
+use std::io::IsTerminal;
+
+// How a PID came to be attributed to the service
+enum Match {
+    // Its image is the configured path itself, or svc recorded it at launch
+    Exact,
+    // Found by substring, command line or window title; could be anything
+    Loose(Option<String>),
+    Refused(String),
+}
+
+fn system32() -> String {
+    let root = std::env::var("SystemRoot").unwrap_or_else(|_| r"C:\Windows".to_string());
+    format!(r"{}\System32", root.trim_end_matches('\\'))
+}
+
+fn classify(service: &Service, pid: u64, launched: &[u64]) -> Match {
+    if launched.contains(&pid) {
+        return Match::Exact;
+    }
+    let image = procinfo::image_path(pid);
+    if let Some(image) = &image {
+        let system = system32();
+        if pathcmp::is_within(image, &system) && !pathcmp::is_within(&service.path, &system) {
+            return Match::Refused(format!("{} is part of Windows and {} does not live there", image, service.path));
+        }
+        // A terminal profile's instances are told apart by command line, whatever the image
+        if service.terminal_profile.is_none() && pathcmp::same_file(image, &service.path) {
+            return Match::Exact;
+        }
+    }
+    Match::Loose(image)
+}
+
+fn refuse(service: &Service, pid: u64, reason: &str) {
+    eprintln!(
+        "{} not killing PID {} of {}: {}",
+        "refused:".red().bold(),
+        pid,
+        service.name.cyan(),
+        reason
+    );
+    let mut entry = HistoryRecord::new(&service.name, "kill-refused", false);
+    entry.output = vec![format!("PID {}: {}", pid, reason)];
+    let _ = history::record(&entry);
+}
+
+fn confirm(service: &Service, loose: &[(u64, Option<String>)]) -> Result<bool, SvcError> {
+    eprintln!(
+        "These processes matched {} only loosely, not by its exact path {}:",
+        service.name.cyan(),
+        service.path
+    );
+    for (pid, image) in loose {
+        eprintln!("  PID {}  {}", pid.to_string().yellow(), image.as_deref().unwrap_or("(path unknown)"));
+        if let Some(line) = procinfo::command_line(*pid) {
+            eprintln!("    {}", line.dimmed());
+        }
+    }
+    eprint!("Kill them? [y/N] ");
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    cancel::check()?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+// The PIDs it is safe to terminate: Windows' own processes are never killed for a service
+// that doesn't live there, and loose matches need a yes or force
+pub fn screen(service: &Service, pids: Vec<u64>, force: bool) -> Result<Vec<u64>, SvcError> {
+    let mut launched = spawned::live_pids(&service.name)?;
+    if service.run_as.is_some() {
+        launched.extend(state::read_pid_file(&runas::pid_file(&service.name))?);
+    }
+
+    let mut safe = Vec::new();
+    let mut loose = Vec::new();
+    let mut refused = Vec::new();
+    for pid in pids {
+        match classify(service, pid, &launched) {
+            Match::Exact => safe.push(pid),
+            Match::Loose(image) => loose.push((pid, image)),
+            Match::Refused(reason) => {
+                refuse(service, pid, &reason);
+                refused.push(pid);
+            }
+        }
+    }
+
+    if !loose.is_empty() {
+        let approved = force || (std::io::stdin().is_terminal() && confirm(service, &loose)?);
+        if !approved {
+            return Err(SvcError::LooseMatch {
+                service: service.name.to_string(),
+                pids: loose.into_iter().map(|(pid, _)| pid).collect(),
+            });
+        }
+        safe.extend(loose.into_iter().map(|(pid, _)| pid));
+    }
+    if safe.is_empty() && !refused.is_empty() {
+        return Err(SvcError::KillRefused {
+            service: service.name.to_string(),
+            pids: refused,
+        });
+    }
+    Ok(safe)
+}
@@ -0,0 +1,201 @@
+use crate::cli::LauncherStyle;
+use crate::elevation::quote;
+use crate::process::{env_vars, expand_env, work_dir};
+use crate::runas::ps_quote;
+use crate::{effects, invocation, load_document, Service, SvcError};
+use colored::Colorize;
+use std::path::Path;
+
+const HEADER: &str = "generated by 'svc export-launcher'; regenerate it rather than editing it";
+
+// Fields a launcher script can't carry out, and what that means for the service
+fn left_out(service: &Service) -> Vec<&'static str> {
+    [
+        (service.run_as.is_some(), "run_as is left out: it runs as whoever the launcher runs as"),
+        (service.log.is_some(), "log is left out: its output isn't written to a file"),
+        (!service.steps.is_empty(), "steps are left out: only the service itself is started"),
+        (!service.depends_on.is_empty(), "depends_on is left out: what it needs must be started some other way"),
+        (service.lock.is_some(), "lock is left out: nothing holds its lock file"),
+        (service.priority.is_some() || service.affinity.is_some(), "priority and affinity are left out"),
+        (service.logon_delay.is_some(), "logon_delay is left out: it starts as soon as the launcher runs"),
+        (
+            !service.startup_env_allowlist.is_empty(),
+            "startup_env_allowlist is left out: it gets the launcher's environment",
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(applies, note)| applies.then_some(note))
+    .collect()
+}
+
+// What the script starts, with everything svc would expand already expanded
+struct Launch {
+    program: String,
+    args: Vec<String>,
+    work_at: String,
+    env: Vec<(String, String)>,
+}
+
+fn launch(service: &Service) -> Result<Launch, SvcError> {
+    let (program, args) = invocation(service);
+    let mut program = expand_env(program);
+    // A bare program name is still looked up on PATH
+    if program.contains(['\\', '/']) {
+        program = std::path::absolute(&program)?.display().to_string();
+    }
+    let work_at = match work_dir(service) {
+        "" => String::new(),
+        dir => std::path::absolute(expand_env(dir))?.display().to_string(),
+    };
+    Ok(Launch {
+        program,
+        args: args.into_iter().map(String::from).collect(),
+        work_at,
+        env: env_vars(service),
+    })
+}
+
+// % is doubled everywhere in a batch file; outside double quotes, the characters cmd acts on
+// take a ^ so they reach the program as written
+fn cmd_escape(line: &str) -> String {
+    let mut escaped = String::with_capacity(line.len());
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '%' => escaped.push('%'),
+            '^' | '&' | '|' | '<' | '>' | '(' | ')' if !quoted => escaped.push('^'),
+            _ => {}
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// The console window of a batch file can't be hidden, so the service starts minimized
+fn cmd_script(name: &str, launch: &Launch) -> Result<String, SvcError> {
+    let values = std::iter::once(&launch.program)
+        .chain(&launch.args)
+        .chain(std::iter::once(&launch.work_at))
+        .chain(launch.env.iter().flat_map(|(key, value)| [key, value]));
+    for value in values {
+        if value.contains(['\r', '\n']) {
+            return Err(SvcError::InvalidCommand(format!(
+                "export-launcher {} --style cmd: {:?} has a line break, which a batch file can't hold; use --style ps1",
+                name, value
+            )));
+        }
+    }
+    let mut lines = vec!["@echo off".to_string(), cmd_escape(&format!("rem {}: {}", name, HEADER))];
+    let command_line: Vec<String> = std::iter::once(&launch.program).chain(&launch.args).map(|arg| quote(arg)).collect();
+    let body: Vec<String> = launch
+        .env
+        .iter()
+        .map(|(key, value)| format!("set \"{}={}\"", key, value))
+        .chain((!launch.work_at.is_empty()).then(|| format!("cd /d \"{}\"", launch.work_at)))
+        .chain(std::iter::once(format!("start \"\" /min {}", command_line.join(" "))))
+        .collect();
+    // cmd reads each line in the console's code page
+    if !body.iter().all(|line| line.is_ascii()) {
+        lines.push("chcp 65001 >nul".to_string());
+    }
+    lines.push("setlocal DisableDelayedExpansion".to_string());
+    lines.extend(body.iter().map(|line| cmd_escape(line)));
+    lines.push(String::new());
+    Ok(lines.join("\r\n"))
+}
+
+fn ps1_script(name: &str, launch: &Launch) -> String {
+    let mut lines = vec![
+        format!("# {}: {}", name, HEADER),
+        "# Start it with: powershell -NoProfile -ExecutionPolicy Bypass -WindowStyle Hidden -File <this file>".to_string(),
+    ];
+    for (key, value) in &launch.env {
+        lines.push(format!("[Environment]::SetEnvironmentVariable({}, {})", ps_quote(key), ps_quote(value)));
+    }
+    lines.push("$launch = @{".to_string());
+    lines.push(format!("    FilePath = {}", ps_quote(&launch.program)));
+    // One string, quoted as the program splits it; Windows PowerShell joins an array without quoting
+    if !launch.args.is_empty() {
+        let args: Vec<String> = launch.args.iter().map(|arg| quote(arg)).collect();
+        lines.push(format!("    ArgumentList = {}", ps_quote(&args.join(" "))));
+    }
+    if !launch.work_at.is_empty() {
+        lines.push(format!("    WorkingDirectory = {}", ps_quote(&launch.work_at)));
+    }
+    lines.push("    WindowStyle = 'Hidden'".to_string());
+    lines.push("}".to_string());
+    lines.push("Start-Process @launch".to_string());
+    lines.push(String::new());
+    lines.join("\r\n")
+}
+
+pub fn export_launcher(config_path: &str, name: &str, style: LauncherStyle, out: Option<&Path>) -> Result<(), SvcError> {
+    let config = load_document(config_path)?;
+    let service = config.service(name)?;
+    let launch = launch(service)?;
+    let script = match style {
+        LauncherStyle::Cmd => cmd_script(name, &launch)?,
+        LauncherStyle::Ps1 => ps1_script(name, &launch),
+    };
+    match out {
+        Some(path) => {
+            // Windows PowerShell reads a script without a BOM in the ANSI code page
+            let content = match style {
+                LauncherStyle::Cmd => script,
+                LauncherStyle::Ps1 => format!("\u{feff}{}", script),
+            };
+            effects::write(path, &content)?;
+            println!("Launcher for {} written to {}.", name.cyan(), path.display());
+        }
+        None => print!("{}", script),
+    }
+    for note in left_out(service) {
+        eprintln!("  {} {}", "!".yellow(), note);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Launch {
+        Launch {
+            program: r"C:\Program Files\100% Tools\tool.exe".to_string(),
+            args: vec!["--name".to_string(), r#"say "hi" & go"#.to_string(), "50%".to_string()],
+            work_at: r"C:\data\100%".to_string(),
+            env: vec![("GREETING".to_string(), r#"it's "R&D" at 5%"#.to_string())],
+        }
+    }
+
+    #[test]
+    fn cmd_doubles_percent_and_escapes_what_falls_outside_quotes() {
+        let script = cmd_script("tool", &sample()).unwrap();
+        let lines: Vec<&str> = script.lines().collect();
+        assert_eq!(lines[3], r#"set "GREETING=it's "R^&D" at 5%%""#);
+        assert_eq!(lines[4], r#"cd /d "C:\data\100%%""#);
+        assert_eq!(
+            lines[5],
+            r#"start "" /min "C:\Program Files\100%% Tools\tool.exe" --name "say \"hi\" & go" 50%%"#
+        );
+        assert!(script.ends_with("\r\n"));
+    }
+
+    #[test]
+    fn ps1_quotes_every_value_as_a_literal() {
+        let script = ps1_script("tool", &sample());
+        assert!(script.contains(r#"[Environment]::SetEnvironmentVariable('GREETING', 'it''s "R&D" at 5%')"#));
+        assert!(script.contains(r"    FilePath = 'C:\Program Files\100% Tools\tool.exe'"));
+        assert!(script.contains(r#"    ArgumentList = '--name "say \"hi\" & go" 50%'"#));
+        assert!(script.contains("    WindowStyle = 'Hidden'"));
+    }
+
+    #[test]
+    fn a_line_break_is_refused_for_cmd_only() {
+        let mut launch = sample();
+        launch.args.push("two\nlines".to_string());
+        assert!(cmd_script("tool", &launch).is_err());
+        assert!(ps1_script("tool", &launch).contains("two\nlines"));
+    }
+}
@@ -0,0 +1,70 @@
+use crate::SvcError;
+use colored::Colorize;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+// --lenient or `lenient: true`: a service entry that doesn't parse or validate is left out with
+// what was wrong with it, and the rest of the config loads without it
+
+static LENIENT: AtomicBool = AtomicBool::new(false);
+// Every entry left out so far in this invocation, from whichever file
+static SKIPPED: Mutex<Vec<Skipped>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Skipped {
+    // Its name, or #N by position when it has none
+    pub service: String,
+    pub reason: String,
+    // The config it is in; empty for a config parsed from text
+    pub file: String,
+}
+
+impl Skipped {
+    pub fn error(&self) -> SvcError {
+        SvcError::InvalidEntry {
+            service: self.service.clone(),
+            reason: self.reason.clone(),
+        }
+    }
+}
+
+pub fn enable() {
+    LENIENT.store(true, Ordering::Relaxed);
+}
+
+pub fn on() -> bool {
+    LENIENT.load(Ordering::Relaxed)
+}
+
+// Loading the same file again in one invocation leaves out the same entries; they count once
+pub fn record(skipped: &[Skipped]) {
+    let mut recorded = SKIPPED.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    for entry in skipped {
+        if !recorded.contains(entry) {
+            recorded.push(entry.clone());
+        }
+    }
+}
+
+// Why a name that was looked up and not found was left out, when it was
+pub fn skipped(name: &str) -> Option<SvcError> {
+    let recorded = SKIPPED.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    recorded.iter().find(|entry| entry.service == name).map(Skipped::error)
+}
+
+// After the command, once, so the banner doesn't get between lines of its output
+pub fn notice() {
+    let recorded = SKIPPED.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if recorded.is_empty() {
+        return;
+    }
+    let names: Vec<&str> = recorded.iter().map(|entry| entry.service.as_str()).collect();
+    eprintln!(
+        "{} lenient mode skipped {} config entr{} ({}); 'svc validate --lenient' says why",
+        "note:".cyan().bold(),
+        recorded.len(),
+        if recorded.len() == 1 { "y" } else { "ies" },
+        names.join(", ")
+    );
+}
@@ -0,0 +1,1660 @@
+use colored::Colorize;
+use std::borrow::Cow;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::process::exit;
+use std::time::Duration;
+use cli::{Cmd, ConfigCmd, FirewallCmd, SecretCmd, Selection, SessionFilter, ShutdownHookCmd};
+use backends::Backend;
+use notify::SvcEvent;
+use thiserror::Error;
+pub use config::{find_document, find_service, load_config, load_document, load_settings, parse_config, read_config};
+pub use config::{Config, Service, ServiceType};
+pub use config::{ConfigAccessError, PathsRelativeTo, RestartPolicy, RunAs, Settings, StartupState};
+use config::{not_found, scoped_services, startup_delay};
+pub use process::{get_status, ProcessBackend, RunOutcome, RunOverride, ServiceStatus};
+use process::{adjust_service, apply_env, checked_work_dir, expand_env, get_pids, is_pid_alive, kill_and_wait};
+use process::{kill_pids_within, kill_service, owner_of, running_pids, session_pids, stop_service};
+use process::{terminate, work_dir};
+
+mod adhoc;
+mod apply;
+mod artifacts;
+mod audit;
+pub mod backends;
+mod backup;
+mod bundle;
+mod bootreport;
+mod bulk;
+#[doc(hidden)]
+pub mod cancel;
+mod capabilities;
+mod capture;
+mod clock;
+mod check;
+mod checkpoint;
+#[doc(hidden)]
+pub mod cli;
+pub mod config;
+#[doc(hidden)]
+pub mod debugbundle;
+mod deps;
+#[doc(hidden)]
+pub mod diskbudget;
+mod desired;
+mod edit;
+#[doc(hidden)]
+pub mod effects;
+mod encoding;
+#[doc(hidden)]
+pub mod enrich;
+mod errors;
+#[doc(hidden)]
+pub mod elevation;
+mod filelock;
+mod firewall;
+mod flavor;
+mod format;
+#[doc(hidden)]
+pub mod history;
+#[doc(hidden)]
+pub mod hosts;
+mod idlestop;
+mod index;
+mod killguard;
+mod launcher;
+#[doc(hidden)]
+pub mod lenient;
+mod liveenv;
+mod list;
+mod logfile;
+#[doc(hidden)]
+pub mod locate;
+mod logs;
+mod outdated;
+mod metrics;
+mod migrate;
+mod mutex;
+mod order;
+#[doc(hidden)]
+pub mod notify;
+#[doc(hidden)]
+pub mod output;
+mod pacing;
+mod pathcmp;
+mod startup;
+mod startupenv;
+mod templates;
+mod stopall;
+mod stopsignal;
+mod terminal;
+mod token;
+mod pe;
+mod pipe;
+#[doc(hidden)]
+pub mod ports;
+pub mod prelude;
+mod probe;
+#[doc(hidden)]
+pub mod progress;
+mod priority;
+mod procinfo;
+mod ready;
+mod recover;
+pub mod process;
+mod relocate;
+mod restart;
+mod rollback;
+#[doc(hidden)]
+pub mod retry;
+mod runas;
+#[doc(hidden)]
+pub mod safe;
+mod schema;
+mod scm;
+mod scratch;
+mod shells;
+mod secrets;
+mod serve;
+mod shutdownhook;
+mod spawned;
+mod state;
+mod steps;
+mod summary;
+mod units;
+mod uptime;
+mod validate;
+mod warnings;
+mod watch;
+#[doc(hidden)]
+pub mod workspace;
+mod yamledit;
+
+const VERSION: &str = "1.1.0";
+
+// For `svc down` and `svc kill --all` as a whole
+const DEFAULT_STOP_DEADLINE: Duration = Duration::from_secs(30);
+// How long a restart waits for killed processes to exit, unless told otherwise
+const KILL_WAIT: Duration = Duration::from_secs(10);
+
+const NO_SERVICES_HINT: &str =
+    "services.yaml defines no services yet; 'svc add' appends one, 'svc init' writes a commented starter config";
+
+// New kinds of failure come in minor versions, so matching on one needs a catch-all arm
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum SvcError {
+    #[error("Service is already running.")]
+    ServiceIsRunning,
+    #[error("Service is not running.")]
+    ServiceIsNotRunning,
+    #[error("Service has been disabled")]
+    ServiceIsDisabled,
+    #[error("Service has been enabled")]
+    ServiceIsEnabled,
+    #[error("Service {name} not found in the configuration.{}", did_you_mean(.suggestion))]
+    ServiceNotFound { name: String, suggestion: Option<String> },
+    #[error("Service {0} not found in the configuration.")]
+    NoServicesConfigured(String),
+    #[error("Service {0} is defined more than once")]
+    DuplicateService(String),
+    #[error("A service named {0} already exists")]
+    ServiceNameTaken(String),
+    #[error("Service {0} is protected")]
+    ServiceIsProtected(String),
+    #[error("Invalid command {0}")]
+    InvalidCommand(String),
+    #[error("Unknown command {name}.{}", did_you_mean(.suggestion))]
+    UnknownCommand { name: String, suggestion: Option<String> },
+    #[error("No config file at {0}")]
+    ConfigMissing(String),
+    #[error("Config {path} can't be read: {problem}")]
+    ConfigAccess { path: String, problem: config::ConfigAccessError },
+    #[error("Unknown help topic {topic}, available topics: {available}")]
+    UnknownHelpTopic { topic: String, available: String },
+    #[error("No services to watch")]
+    NothingToWatch,
+    #[error("A background watcher is already running with PID {0}")]
+    WatcherIsRunning(u64),
+    #[error("No background watcher is running")]
+    WatcherIsNotRunning,
+    #[error("Utility {path} failed to run with error: {status}{}", format_output_tail(.output))]
+    UtilFailed {
+        path: String,
+        status: String,
+        // Passed on as svc's own exit code, so `svc run tool && next` works in batch files
+        code: Option<i32>,
+        output: Vec<String>,
+    },
+    #[error("Interpreter {interpreter} of {service} was not found")]
+    InterpreterNotFound { service: String, interpreter: String },
+    #[error("Secret {0} is not in the secret store")]
+    SecretNotFound(String),
+    #[error("Secret key {0:?} isn't a plain name")]
+    InvalidSecretKey(String),
+    #[error("Logon as {user} failed: {reason}")]
+    LogonFailed { user: String, reason: String },
+    #[error("Step {step} of {service} ({path}) failed: {reason}")]
+    StepFailed {
+        service: String,
+        step: usize,
+        path: String,
+        reason: String,
+    },
+    #[error("{failed} of {total} services failed")]
+    BulkFailed { failed: usize, total: usize },
+    #[error("{} did not stop", .stuck.join(", "))]
+    StopIncomplete { stuck: Vec<String> },
+    #[error("Desired-state document is invalid: {0}")]
+    InvalidDesiredState(String),
+    #[error("Apply did not finish for {}", .failed.join(", "))]
+    ApplyIncomplete { failed: Vec<String> },
+    #[error("svc can't {} under this account: {reason}", .capability.action())]
+    CapabilityMissing { capability: probe::Capability, reason: String },
+    #[error("{} isn't available on {platform}: {constraint}", .feature.describe())]
+    FeatureUnavailable {
+        feature: flavor::Feature,
+        platform: &'static str,
+        constraint: &'static str,
+    },
+    #[error("Switchover of {service} failed: {reason}")]
+    SwitchoverFailed { service: String, reason: String },
+    #[error("Service {service} was restarted but is not ready: {reason}")]
+    NotReady { service: String, reason: String },
+    #[error("{service} has no process {condition}")]
+    ConditionNotMet { service: String, condition: String },
+    #[error("Cannot bundle {service}: {reason}")]
+    BundleRefused { service: String, reason: String },
+    #[error("{left} start-up entries need attention")]
+    StartupAuditFindings { left: usize },
+    #[error("{} start-up change(s) failed: {}", .failed.len(), .failed.join(", "))]
+    StartupApplyIncomplete { failed: Vec<String> },
+    #[error("{} failed, so the start-up changes made before were rolled back{}", .failed.join(", "), not_put_back(.unreverted))]
+    RolledBack { failed: Vec<String>, unreverted: Vec<String> },
+    #[error("No service is tagged {0}")]
+    NoTaggedServices(String),
+    #[error("No process with PID {0} is running")]
+    ProcessNotFound(u64),
+    #[error("PID {pid}{} belongs to no configured service", in_parens(.image))]
+    ProcessNotManaged { pid: u64, image: Option<String> },
+    #[error("{command} failed: {reason}")]
+    HelperFailed {
+        command: String,
+        reason: String,
+        output: Vec<String>,
+    },
+    #[error("{0} needs administrator rights")]
+    ElevationRequired(String),
+    #[error("Working directory {path} of {service} {reason}")]
+    WorkDirInvalid {
+        service: String,
+        path: String,
+        reason: String,
+    },
+    #[error("Processes matched {service} only loosely and were not killed")]
+    LooseMatch { service: String, pids: Vec<u64> },
+    #[error("Refused to kill processes of {service} that belong to Windows")]
+    KillRefused { service: String, pids: Vec<u64> },
+    #[error("Cannot open log file {path}: {reason}")]
+    LogFileFailed { path: String, reason: String },
+    #[error("{service} was still running {waited} after being killed")]
+    StillRunning {
+        service: String,
+        pids: Vec<u64>,
+        waited: String,
+    },
+    #[error("{service} did not go idle within {waited}")]
+    IdleTimeout { service: String, waited: String },
+    #[error("svc is in read-only mode, '{0}' is not allowed")]
+    ReadOnlyMode(&'static str),
+    #[error("Validation found {0} errors")]
+    ValidationFailed(usize),
+    #[error("{failed} of {total} processes could not be adjusted")]
+    AdjustFailed { failed: usize, total: usize },
+    #[error("CPU {0} is out of range, an affinity mask names CPUs 0 to {}", usize::BITS - 1)]
+    AffinityOutOfRange(u32),
+    #[error("Cannot terminate PID {pid}{}: access denied, {reason}", owned_by(.owner))]
+    KillDenied {
+        pid: u64,
+        owner: Option<String>,
+        reason: String,
+        fix: &'static str,
+    },
+    #[error("The environment of PID {pid}{} can't be read with svc's rights", in_parens(.owner))]
+    EnvironmentDenied { pid: u64, owner: Option<String> },
+    #[error("Config file is being modified by PID {0}")]
+    ConfigLocked(u64),
+    #[error("Config file is not in canonical form")]
+    ConfigNotFormatted,
+    #[error("Formatting would drop {0} comment(s) from the config")]
+    FmtDropsComments(usize),
+    #[error("Interrupted")]
+    Interrupted,
+    #[error("Another svc invocation is working on service {0}")]
+    ServiceBusy(String),
+    #[error("{0} items could not be restored")]
+    RestoreIncomplete(usize),
+    #[error("Alias {0} shadows a built-in command")]
+    AliasShadowsCommand(String),
+    #[error("Invalid hosts section for {host}: {reason}")]
+    InvalidHostOverride { host: String, reason: String },
+    #[error("Invalid depends_on of service {service}: {reason}")]
+    InvalidDependency { service: String, reason: String },
+    #[error("Dependency {dependency} of {service} did not start within {waited}")]
+    DependencyTimeout {
+        service: String,
+        dependency: String,
+        waited: String,
+    },
+    #[error("Invalid extends entry {name}: {reason}")]
+    InvalidExtends { name: String, reason: String },
+    #[error("Start-up entry {name} was not created by svc, it runs {data}")]
+    ForeignStartupEntry { name: String, data: String },
+    #[error("Start-up entry {name} reads back as {found} instead of {expected}")]
+    StartupWriteMismatch { name: String, expected: String, found: String },
+    #[error("Instance {instance} of template {template} is invalid: {reason}")]
+    InvalidTemplate {
+        template: String,
+        instance: String,
+        reason: String,
+    },
+    #[error("Service {service} in the config is invalid: {reason}")]
+    InvalidEntry { service: String, reason: String },
+    #[error("Shell {shell} of {service} is not defined{}", defined_shells(.defined))]
+    UnknownShell {
+        service: String,
+        shell: String,
+        defined: Vec<String>,
+    },
+    #[error("State file {path} is corrupt: {reason}")]
+    CorruptState { path: String, reason: String },
+    #[error("There is no bulk operation to resume")]
+    NoCheckpoint,
+    #[error("{service} is also Windows service '{scm_name}' ({state}); control it with sc start/stop {scm_name}")]
+    ScmManaged {
+        service: String,
+        scm_name: String,
+        state: String,
+    },
+    #[error("Lock file {path} of {service} is held{}", held_by(.pid))]
+    LockHeld {
+        service: String,
+        path: String,
+        pid: Option<u64>,
+    },
+
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Yaml error: {0}")]
+    YamlError(#[from] serde_yaml::Error),
+    #[error("Archive error: {0}")]
+    ArchiveError(#[from] zip::result::ZipError),
+    #[error("Cannot read PID")]
+    CannotReadPID,
+    #[error("Failed to parse PID")]
+    FailedToParsePID,
+    #[error("Failed to convert string from Utf8")]
+    FailedToConvertUtf8(#[from] std::string::FromUtf8Error),
+}
+
+fn format_output_tail(output: &[String]) -> String {
+    output.iter().map(|line| format!("\n  | {}", line)).collect()
+}
+
+fn owned_by(owner: &Option<String>) -> String {
+    owner.as_ref().map(|owner| format!(" owned by {}", owner)).unwrap_or_default()
+}
+
+fn in_parens(text: &Option<String>) -> String {
+    text.as_ref().map(|text| format!(" ({})", text)).unwrap_or_default()
+}
+
+fn not_put_back(unreverted: &[String]) -> String {
+    match unreverted.is_empty() {
+        true => String::new(),
+        false => format!(", except for {}", unreverted.join(", ")),
+    }
+}
+
+fn held_by(pid: &Option<u64>) -> String {
+    pid.map(|pid| format!(" by PID {}", pid)).unwrap_or_default()
+}
+
+fn defined_shells(defined: &[String]) -> String {
+    match defined {
+        [] => ", the shells setting is empty".to_string(),
+        defined => format!(", the shells setting has {}", defined.join(", ")),
+    }
+}
+
+fn did_you_mean(suggestion: &Option<String>) -> String {
+    suggestion
+        .as_ref()
+        .map(|near| format!(" Did you mean {}?", near))
+        .unwrap_or_default()
+}
+
+impl SvcError {
+    // Stable identifier for machine-readable error output
+    pub fn code(&self) -> &'static str {
+        match self {
+            SvcError::ServiceIsRunning => "already_running",
+            SvcError::ServiceIsNotRunning => "not_running",
+            SvcError::ServiceIsDisabled => "already_disabled",
+            SvcError::ServiceIsEnabled => "already_enabled",
+            SvcError::ServiceNotFound { .. } | SvcError::NoServicesConfigured(_) => "service_not_found",
+            SvcError::DuplicateService(_) => "duplicate_service",
+            SvcError::ServiceNameTaken(_) => "service_name_taken",
+            SvcError::ServiceIsProtected(_) => "service_protected",
+            SvcError::InvalidCommand(_) => "invalid_command",
+            SvcError::UnknownCommand { .. } => "unknown_command",
+            SvcError::ConfigMissing(_) => "config_missing",
+            SvcError::ConfigAccess { .. } => "config_access",
+            SvcError::UnknownHelpTopic { .. } => "unknown_help_topic",
+            SvcError::NothingToWatch => "nothing_to_watch",
+            SvcError::WatcherIsRunning(_) => "watcher_running",
+            SvcError::WatcherIsNotRunning => "watcher_not_running",
+            SvcError::UtilFailed { .. } => "util_failed",
+            SvcError::InterpreterNotFound { .. } => "interpreter_not_found",
+            SvcError::SecretNotFound(_) => "secret_not_found",
+            SvcError::InvalidSecretKey(_) => "invalid_secret_key",
+            SvcError::LogonFailed { .. } => "logon_failed",
+            SvcError::KillDenied { .. } => "kill_denied",
+            SvcError::EnvironmentDenied { .. } => "environment_denied",
+            SvcError::AdjustFailed { .. } => "adjust_failed",
+            SvcError::AffinityOutOfRange(_) => "affinity_out_of_range",
+            SvcError::ValidationFailed(_) => "validation_failed",
+            SvcError::ElevationRequired(_) => "elevation_required",
+            SvcError::ReadOnlyMode(_) => "read_only_mode",
+            SvcError::IdleTimeout { .. } => "idle_timeout",
+            SvcError::StillRunning { .. } => "still_running",
+            SvcError::LogFileFailed { .. } => "log_file_failed",
+            SvcError::LooseMatch { .. } => "loose_match",
+            SvcError::KillRefused { .. } => "kill_refused",
+            SvcError::WorkDirInvalid { .. } => "work_dir_invalid",
+            SvcError::HelperFailed { .. } => "helper_failed",
+            SvcError::BulkFailed { .. } => "bulk_failed",
+            SvcError::StopIncomplete { .. } => "stop_incomplete",
+            SvcError::InvalidDesiredState(_) => "invalid_desired_state",
+            SvcError::ApplyIncomplete { .. } => "apply_incomplete",
+            SvcError::CapabilityMissing { .. } => "capability_missing",
+            SvcError::FeatureUnavailable { .. } => "feature_unavailable",
+            SvcError::StartupAuditFindings { .. } => "startup_audit_findings",
+            SvcError::StartupApplyIncomplete { .. } => "startup_apply_incomplete",
+            SvcError::RolledBack { .. } => "rolled_back",
+            SvcError::SwitchoverFailed { .. } => "switchover_failed",
+            SvcError::NotReady { .. } => "not_ready",
+            SvcError::ConditionNotMet { .. } => "condition_not_met",
+            SvcError::BundleRefused { .. } => "bundle_refused",
+            SvcError::NoTaggedServices(_) => "no_tagged_services",
+            SvcError::ProcessNotFound(_) => "process_not_found",
+            SvcError::ProcessNotManaged { .. } => "process_not_managed",
+            SvcError::StepFailed { .. } => "step_failed",
+            SvcError::ConfigLocked(_) => "config_locked",
+            SvcError::ConfigNotFormatted => "config_not_formatted",
+            SvcError::FmtDropsComments(_) => "fmt_drops_comments",
+            SvcError::Interrupted => "interrupted",
+            SvcError::ServiceBusy(_) => "service_busy",
+            SvcError::RestoreIncomplete(_) => "restore_incomplete",
+            SvcError::ArchiveError(_) => "archive",
+            SvcError::AliasShadowsCommand(_) => "alias_shadows_command",
+            SvcError::ForeignStartupEntry { .. } => "foreign_startup_entry",
+            SvcError::StartupWriteMismatch { .. } => "startup_write_mismatch",
+            SvcError::CorruptState { .. } => "corrupt_state",
+            SvcError::NoCheckpoint => "no_checkpoint",
+            SvcError::ScmManaged { .. } => "scm_managed",
+            SvcError::LockHeld { .. } => "lock_held",
+            SvcError::UnknownShell { .. } => "unknown_shell",
+            SvcError::InvalidEntry { .. } => "invalid_entry",
+            SvcError::InvalidTemplate { .. } => "invalid_template",
+            SvcError::InvalidDependency { .. } => "invalid_dependency",
+            SvcError::InvalidExtends { .. } => "invalid_extends",
+            SvcError::DependencyTimeout { .. } => "dependency_timeout",
+            SvcError::InvalidHostOverride { .. } => "invalid_host_override",
+            SvcError::IoError(_) => "io",
+            SvcError::YamlError(_) => "config_parse",
+            SvcError::CannotReadPID => "cannot_read_pid",
+            SvcError::FailedToParsePID => "invalid_pid",
+            SvcError::FailedToConvertUtf8(_) => "invalid_utf8",
+        }
+    }
+
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            SvcError::ServiceIsRunning => Some("use 'svc restart' to restart it"),
+            SvcError::ServiceIsEnabled => Some("use 'svc disable' to remove its start-up entry first"),
+            SvcError::ServiceIsNotRunning => Some("use 'svc run' to start it"),
+            SvcError::ServiceNotFound { .. } => Some("check the names defined in services.yaml"),
+            SvcError::NoServicesConfigured(_) => Some(NO_SERVICES_HINT),
+            SvcError::NoTaggedServices(_) => Some("tag services with `tags: [name]` in services.yaml"),
+            SvcError::ProcessNotFound(_) => Some("it may have exited already; check the PID with 'tasklist'"),
+            SvcError::ProcessNotManaged { .. } => Some("svc only acts on processes of the services in services.yaml"),
+            SvcError::ServiceIsProtected(_) => Some("confirm by typing its name, or pass --force"),
+            SvcError::InvalidCommand(_) | SvcError::UnknownCommand { .. } => Some("run 'svc help' to see usage"),
+            SvcError::NothingToWatch => Some("name services to watch, or set `restart: always` on some"),
+            SvcError::InterpreterNotFound { .. } => {
+                Some("install it or add its directory to PATH, or give `interpreter` as a full path")
+            }
+            SvcError::WatcherIsRunning(_) => Some("use 'svc watch --stop' to stop it"),
+            SvcError::CorruptState { .. } | SvcError::CannotReadPID | SvcError::FailedToParsePID => {
+                Some("pass --safe to run the command without svc's saved state, or delete the file")
+            }
+            SvcError::ConfigNotFormatted => Some("run 'svc fmt' to rewrite it"),
+            SvcError::FmtDropsComments(_) => Some("pass --drop-comments to rewrite it anyway"),
+            SvcError::ScmManaged { .. } => Some("pass --force to have svc do it anyway"),
+            SvcError::NoCheckpoint => {
+                Some("'svc resume-last' picks up after a bulk command such as 'svc kill --all' or 'svc run --tag'")
+            }
+            SvcError::ConfigAccess { problem, .. } => Some(match problem {
+                ConfigAccessError::Directory { .. } => "point --config or SVC_CONFIG at the file inside it",
+                ConfigAccessError::Locked => "close the file in the editor holding it, or retry once it has saved",
+                ConfigAccessError::PermissionDenied { .. } => "give this account read access to the file, or run svc as its owner",
+                ConfigAccessError::OddPath { .. } => "retype the path; quotes or spaces pasted around it end up in the name",
+            }),
+            SvcError::AliasShadowsCommand(_) => Some("rename or remove it under command_aliases in services.yaml"),
+            SvcError::InvalidDependency { .. } => Some("fix depends_on in services.yaml"),
+            SvcError::DependencyTimeout { .. } => {
+                Some("check the dependency with 'svc status'; 'svc run --no-deps' starts the service alone")
+            }
+            SvcError::InvalidExtends { .. } => {
+                Some("extends lists services of the global services.yaml, and only in a .svc.yaml")
+            }
+            SvcError::InvalidHostOverride { .. } => Some("fix the hosts section in services.yaml"),
+            SvcError::InvalidTemplate { .. } => Some("fix the template under templates or the instance's vars in services.yaml"),
+            SvcError::InvalidEntry { .. } => Some("fix that field of the service in services.yaml, then run 'svc validate'"),
+            SvcError::UnknownShell { .. } => Some("add it under shells in services.yaml, or fix the service's shell field"),
+            SvcError::ForeignStartupEntry { .. } => Some("svc leaves it alone; remove it from the Run key yourself if it is stale"),
+            SvcError::StartupWriteMismatch { .. } => {
+                Some("a group policy or another program may be rewriting the Run key; check it in regedit")
+            }
+            SvcError::ConfigLocked(_) => Some("another svc invocation is writing the config, retry once it finishes"),
+            SvcError::ServiceBusy(_) => Some("retry once the other invocation finishes"),
+            SvcError::LockHeld { .. } => Some("retry once the other run finishes, or wait for it with --lock-timeout"),
+            SvcError::SecretNotFound(_) => Some("store it with 'svc secret set <key>'"),
+            SvcError::InvalidSecretKey(_) => Some("a key names a file in the secret store, so it has no \\, /, : or .."),
+            SvcError::KillDenied { fix, .. } => Some(*fix),
+            SvcError::EnvironmentDenied { .. } => {
+                Some("elevated processes and those of other users need an elevated console, or pass --elevate")
+            }
+            SvcError::StepFailed { .. } => Some("fix the step, or set continue_on_error: true on it if it may fail"),
+            SvcError::ElevationRequired(_) => Some("run it from an elevated console, or pass --elevate"),
+            SvcError::WorkDirInvalid { .. } => Some("a relative directory after `at` is taken from the current directory"),
+            SvcError::StillRunning { .. } => Some("something may keep it alive; retry with a longer --timeout or kill it from an elevated console"),
+            SvcError::StopIncomplete { .. } => Some("retry with a longer --deadline, or pass --force-at-deadline to terminate what is left"),
+            SvcError::InvalidDesiredState(_) => Some("see 'svc help desired-state' for the document format"),
+            SvcError::ApplyIncomplete { .. } => Some("what was applied stays applied; fix the failures and apply the same document again"),
+            SvcError::CapabilityMissing { .. } => Some("nothing was changed; 'svc doctor' shows what this account is allowed to do"),
+            SvcError::FeatureUnavailable { .. } => Some("nothing was changed; 'svc doctor' shows what this edition of Windows supports"),
+            SvcError::SwitchoverFailed { .. } => Some("the new instance was stopped and the old one left running; check its log, or raise ready_timeout"),
+            SvcError::NotReady { .. } => {
+                Some("the services after it were left alone; check its log, then run 'svc restart --outdated' again")
+            }
+            SvcError::StartupAuditFindings { .. } => Some("--fix rewrites unquoted and stale entries; the notes explain the ones it leaves alone"),
+            SvcError::StartupApplyIncomplete { .. } => {
+                Some("the other changes stay made; --rollback-on-failure puts them back when any change fails")
+            }
+            SvcError::RolledBack { unreverted, .. } if !unreverted.is_empty() => {
+                Some("some could not be put back; the end state above shows what each service has now")
+            }
+            SvcError::RolledBack { .. } => Some("every service has the start-up entries it had before the run"),
+            SvcError::LooseMatch { .. } => Some("check the processes with 'svc status', then confirm at a console or pass --force"),
+            SvcError::KillRefused { .. } => Some("svc never kills processes under System32 unless the service's own path is there"),
+            SvcError::LogFileFailed { .. } => Some("check that the directory can be created and written to, or point log at another file"),
+            SvcError::IdleTimeout { .. } => Some("retry later, raise --max-wait, or pass --now to restart regardless"),
+            SvcError::ReadOnlyMode(_) => Some("drop --read-only and unset SVC_READ_ONLY to make changes"),
+            SvcError::AdjustFailed { .. } => Some("elevated processes can only be adjusted from an elevated console"),
+            SvcError::AffinityOutOfRange(_) => Some("CPUs past the first 64 are in another processor group"),
+            SvcError::YamlError(_) => Some("check services.yaml near the reported line and column, then run 'svc validate'"),
+            _ => None,
+        }
+    }
+}
+
+// Start a service and say how it went; a Util's output is printed with --verbose
+fn run_service(service: &Service, with: &RunOverride) -> Result<(), SvcError> {
+    scm::refuse(service, with.force)?;
+    let effect = effects::Effect::Start {
+        service: service.name.to_string(),
+    };
+    match effects::perform(effect, || process::start(&process::System, service, with))? {
+        Some(RunOutcome::Started { pid, user: Some(user) }) => println!(
+            "Executable {} started as {} with PID {}.",
+            service.path.cyan(),
+            user.cyan(),
+            pid.to_string().green()
+        ),
+        Some(RunOutcome::Started { .. }) => println!("Executable {} started in the background.", service.path.cyan()),
+        Some(RunOutcome::Finished { output }) if output::options().verbose => output::lines(&output),
+        Some(RunOutcome::Finished { .. }) | None => {}
+    }
+    diskbudget::enforce(service);
+    Ok(())
+}
+
+// `svc run <name> -- ...` appends to the configured args for this one run
+fn with_extra_args(mut service: Service<'static>, extra: Vec<String>) -> Service<'static> {
+    service.args.extend(extra.into_iter().map(Cow::Owned));
+    service
+}
+
+// `svc which`: the command line `svc run` starts, with the shell and hosts overrides applied
+// The program svc starts for the service and its arguments: a Util's script goes to its interpreter
+fn invocation<'a>(service: &'a Service) -> (&'a str, Vec<&'a str>) {
+    let (program, mut args): (&str, Vec<&str>) = match service.service_type {
+        ServiceType::Executable => (&service.path, Vec::new()),
+        ServiceType::Util => {
+            let mut args: Vec<&str> = service.interpreter_args.iter().map(String::as_str).collect();
+            args.extend(encoding::chcp_prefix(service));
+            args.push(&service.path);
+            (&service.interpreter, args)
+        }
+    };
+    args.extend(service.args.iter().map(AsRef::as_ref));
+    (program, args)
+}
+
+fn which(config_path: &str, name: &str) -> Result<(), SvcError> {
+    let config = load_document(config_path)?;
+    let service = config.service(name)?;
+    let (program, args) = invocation(service);
+    let line: Vec<String> = std::iter::once(program).chain(args.iter().copied()).map(elevation::quote).collect();
+    let line = line.join(" ");
+    let host = service.shell.as_deref().and_then(|shell| shells::host_variant(&config, shell));
+    let binary = match service.service_type {
+        ServiceType::Executable => pe::inspect(Path::new(service.path.as_ref())),
+        ServiceType::Util => None,
+    };
+    let stop = process::stop_method(service, work_dir(service));
+    let overridden = hosts::applied(&config, &service.name);
+
+    if output::options().json {
+        let report = serde_json::json!({
+            "service": service.name,
+            "program": program,
+            "args": args,
+            "command_line": line,
+            "shell": service.shell,
+            "shell_host": host,
+            "encoding": service.encoding.map(|encoding| encoding.to_string()),
+            "binary": binary.map(|info| serde_json::json!({
+                "arch": info.arch.to_string(),
+                "subsystem": info.subsystem.to_string(),
+            })),
+            "stop": stop,
+            "host_override": overridden.as_ref().map(|(host, fields)| serde_json::json!({
+                "host": host,
+                "fields": fields,
+            })),
+        });
+        println!("{}", serde_json::to_string_pretty(&report).map_err(std::io::Error::other)?);
+        return Ok(());
+    }
+    println!("{}", line);
+    if let Some(shell) = &service.shell {
+        let variant = host.map(|host| format!(", as set for {}", host)).unwrap_or_default();
+        eprintln!("{}", format!("shell {}{}", shell, variant).dimmed());
+    }
+    if let Some(encoding) = service.encoding {
+        eprintln!("{}", format!("output decoded as {}", encoding).dimmed());
+    }
+    if let Some(info) = binary {
+        eprintln!("{}", format!("{} {} binary", info.arch, info.subsystem).dimmed());
+    }
+    eprintln!("{}", format!("stop: {}", stop).dimmed());
+    if let Some((host, fields)) = overridden {
+        eprintln!("{}", format!("host override: {} (hosts.{})", fields.join(", "), host).dimmed());
+    }
+    Ok(())
+}
+
+fn enable_service(service: &Service, settings: &Settings, backend: Option<Backend>) -> Result<(), SvcError> {
+    let enabled = startup::enable(service, settings, backend)?;
+    if enabled.backend == Backend::RunKey {
+        println!("Service {} enabled.", service.name.cyan());
+    } else {
+        println!("Service {} enabled ({}).", service.name.cyan(), enabled.backend);
+    }
+    for stale in enabled.removed {
+        println!("Removed its previous start-up entry ({}).", stale);
+    }
+    Ok(())
+}
+
+fn disable_service(service: &Service, backend: Option<Backend>) -> Result<(), SvcError> {
+    for backend in startup::disable(service, backend)? {
+        if backend == Backend::RunKey {
+            println!("Service {} disabled.", service.name.cyan());
+        } else {
+            println!("Service {} disabled ({}).", service.name.cyan(), backend);
+        }
+    }
+    Ok(())
+}
+
+// What `svc status` prints for one service, from a status and port conflicts gathered beforehand
+fn show_status(
+    config: &Config,
+    service: &Service,
+    status: ServiceStatus,
+    port_conflicts: &[String],
+    trend: bool,
+    no_deps: bool,
+    session: SessionFilter,
+) -> Result<(), SvcError> {
+    let dependencies = if no_deps {
+        Vec::new()
+    } else {
+        deps::states(&config.services, service)?
+    };
+    let extra = enrich::extra(service, &status.pids);
+    print_status(service, status, startup_delay(config, service), &dependencies, session)?;
+    for conflict in port_conflicts {
+        println!("Port conflict: {}", conflict.yellow());
+    }
+    if !extra.is_empty() {
+        println!("Extra:");
+        for (key, value) in &extra {
+            println!("  {}: {}", key, enrich::display(value).cyan());
+        }
+    }
+    if let Some((host, fields)) = hosts::applied(config, &service.name) {
+        println!("Host override: {} (hosts.{})", fields.join(", ").cyan(), host);
+    }
+    if trend {
+        metrics::print_trend(&service.name)?;
+    }
+    Ok(())
+}
+
+fn print_status(
+    service: &Service,
+    mut status: ServiceStatus,
+    startup_delay: Option<Duration>,
+    dependencies: &[deps::Dependency],
+    session: SessionFilter,
+) -> Result<(), SvcError> {
+    procinfo::retain_session(&mut status.pids, session);
+    if service.protected {
+        println!("Name: {} {}", service.name.cyan(), output::symbol("\u{1F6E1} protected", "[protected]").yellow());
+    } else if service.adhoc {
+        println!("Name: {} {}", service.name.cyan(), "(adhoc)".yellow());
+    } else if service.global {
+        println!("Name: {} {}", service.name.cyan(), "(global)".yellow());
+    } else {
+        println!("Name: {}", service.name.cyan());
+    }
+    if let Some(log_file) = &service.log_file {
+        println!("Log: {}", log_file.display().to_string().cyan());
+    }
+    if let Some(label) = &service.label {
+        println!("Label: {}", label.cyan());
+    }
+    if !service.tags.is_empty() {
+        println!("Tags: {}", service.tags.join(", ").cyan());
+    }
+    println!("Type: {}", service.service_type.to_string().cyan());
+    println!("Path: {}", service.path.cyan());
+    // Only the names; values may hold tokens
+    if !service.env.is_empty() {
+        let keys: Vec<&str> = service.env.keys().map(String::as_str).collect();
+        println!("Env: {}", keys.join(", ").cyan());
+    }
+
+    match service.service_type {
+        ServiceType::Executable => {
+            let binary = pe::inspect(Path::new(service.path.as_ref()));
+            if let Some(binary) = binary {
+                println!("Architecture: {}", format!("{} ({})", binary.arch, binary.subsystem).cyan());
+            }
+
+            let running: Vec<(u64, Option<pe::Arch>)> =
+                status.pids.iter().map(|&pid| (pid, procinfo::process_arch(pid))).collect();
+            let show_sessions = procinfo::sessions_differ(&status.pids);
+            let switchover = restart::switchover(&service.name);
+            let pid_str = if running.is_empty() {
+                "not running".yellow().to_string()
+            } else {
+                running
+                    .iter()
+                    .map(|(pid, arch)| {
+                        let mut details: Vec<String> = arch.iter().map(ToString::to_string).collect();
+                        match &switchover {
+                            Some(overlap) if overlap.new == *pid => details.push("new".to_string()),
+                            Some(overlap) if overlap.old.contains(pid) => details.push("old".to_string()),
+                            _ => {}
+                        }
+                        if show_sessions {
+                            if let Some(session) = procinfo::session_id(*pid) {
+                                details.push(format!("session {}", session));
+                            }
+                        }
+                        if details.is_empty() {
+                            pid.to_string()
+                        } else {
+                            format!("{} ({})", pid, details.join(", "))
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+                    .green()
+                    .to_string()
+            };
+            println!("PID: {}", pid_str);
+            if let Some(reason) = outdated::reason(service, &status.pids) {
+                println!("Binary: {}", reason.yellow());
+            }
+            for registration in scm::registrations(service) {
+                let line = format!(
+                    "also registered as Windows service '{}' {} state: {}",
+                    registration.name,
+                    output::symbol("\u{2014}", "-"),
+                    registration.state
+                );
+                println!("SCM: {}", line.yellow());
+            }
+            // Live values, so a `svc adjust` shows up right away
+            let scheduling: Vec<(u64, priority::Scheduling)> =
+                status.pids.iter().map(|&pid| (pid, priority::query(pid))).collect();
+            if scheduling.iter().any(|(_, s)| s.priority.is_some()) {
+                let shown: Vec<String> = scheduling.iter().map(|(_, s)| s.to_string()).collect();
+                if shown.iter().all(|s| *s == shown[0]) {
+                    println!("Priority: {}", shown[0].cyan());
+                } else {
+                    let per_pid: Vec<String> =
+                        scheduling.iter().zip(&shown).map(|((pid, _), s)| format!("{} {}", pid, s)).collect();
+                    println!("Priority: {}", per_pid.join(", ").cyan());
+                }
+            }
+            if !running.is_empty() {
+                if let Some(reason) = deps::reason(dependencies) {
+                    println!("State: {}", format!("degraded ({})", reason).yellow());
+                }
+            }
+            if restart::pending(&service.name)? {
+                println!("State: {}", "restart pending (waiting for idle)".yellow());
+            }
+            if !dependencies.is_empty() {
+                let states: Vec<String> = dependencies
+                    .iter()
+                    .map(|d| match d.state {
+                        deps::State::Running => format!("{} (running)", d.name).green().to_string(),
+                        deps::State::Down => format!("{} (down)", d.name).yellow().to_string(),
+                        deps::State::Degraded => format!("{} (degraded)", d.name).yellow().to_string(),
+                    })
+                    .collect();
+                println!("Depends on: {}", states.join(", "));
+            }
+            for warning in pe::warnings(&service.path, binary.map(|b| b.arch), &running) {
+                warnings::warn("binary", &service.name, warning);
+            }
+            match probe::missing(probe::Capability::StartupRead) {
+                Some(reason) if !status.is_start_up() => println!("Start-up: {} ({})", "unknown".yellow(), reason),
+                _ if status.is_start_up() => println!("Start-up: {}", "enabled".green()),
+                _ => println!("Start-up: {}", "disabled".yellow()),
+            }
+            for entry in &status.startup {
+                let differs = if entry.matches_config { "" } else { " (differs from the config)" };
+                println!("  {:<16}{}{}", entry.backend.to_string(), entry.command_line(), differs.yellow());
+            }
+            warnings::startup(&service.name, &status.startup);
+            if let Some(delay) = startup_delay {
+                println!("Start-up delay: {}", format!("{}s", delay.as_secs_f64()).cyan());
+            }
+            if let Some(hook) = shutdownhook::describe()? {
+                println!("Shutdown hook: {}", hook);
+            }
+        }
+        ServiceType::Util => {
+            match &service.shell {
+                Some(shell) => println!("Interpreter: {} (shell {})", service.interpreter.cyan(), shell),
+                None => println!("Interpreter: {}", service.interpreter.cyan()),
+            }
+            let pid_str = if status.pids.is_empty() {
+                "not running".yellow().to_string()
+            } else {
+                status.pids.iter().map(u64::to_string).collect::<Vec<_>>().join(", ").green().to_string()
+            };
+            println!("PID: {}", pid_str);
+        }
+    }
+
+    Ok(())
+}
+
+// Destructive actions on protected services need the name typed back, or --force
+fn confirm_protected(service: &Service, force: bool) -> Result<(), SvcError> {
+    if !service.protected || force {
+        return Ok(());
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err(SvcError::ServiceIsProtected(service.name.to_string()));
+    }
+
+    eprint!(
+        "Service {} is protected. Type its name to confirm: ",
+        service.name.cyan()
+    );
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    cancel::check()?;
+    if answer.trim() == service.name {
+        Ok(())
+    } else {
+        Err(SvcError::ServiceIsProtected(service.name.to_string()))
+    }
+}
+
+fn watch_command(config_path: &str, args: cli::WatchArgs) -> Result<(), SvcError> {
+    if args.stop {
+        return watch::stop_background();
+    }
+    if args.status {
+        return watch::print_background_status();
+    }
+
+    let (min_interval, max_interval) = match args.interval {
+        Some(interval) => (interval.clone(), interval),
+        None => (args.min_interval, args.max_interval),
+    };
+    if min_interval.get() > max_interval.get() {
+        return Err(SvcError::InvalidCommand(format!(
+            "watch with --min-interval {} longer than --max-interval {}",
+            min_interval, max_interval
+        )));
+    }
+    let document = load_document(config_path)?;
+    let options = watch::WatchOptions {
+        names: args.names,
+        // The flags win over the config's setting, which is read once as watching starts
+        kill_removed: !args.no_kill_removed && (args.kill_removed || document.settings.kill_removed),
+        kill_on_exit: args.kill_on_exit,
+        min_interval,
+        max_interval,
+        log_max_size: args.log_max_size,
+        sample_interval: args.sample_interval,
+    };
+    let config = document.services;
+    if let Some(name) = options.names.iter().find(|name| !config.iter().any(|s| s.name == name.as_str())) {
+        return Err(not_found(&config, name));
+    }
+    if watch::select(config, &options.names).is_empty() {
+        return Err(SvcError::NothingToWatch);
+    }
+
+    if args.background {
+        watch::start_background(&options)
+    } else if args.background_child {
+        watch::run_background_child(config_path, &options)
+    } else {
+        watch::watch(config_path, &options, None)
+    }
+}
+
+fn fmt_command(config_path: &Path, check: bool, drop_comments: bool) -> Result<(), SvcError> {
+    let _lock = format::lock_config(config_path)?;
+    let content = read_config(config_path)?;
+    let canonical = format::canonicalize(&content)?;
+
+    if content == canonical {
+        println!("{} is already formatted.", config_path.display().to_string().cyan());
+        return Ok(());
+    }
+    if check {
+        return Err(SvcError::ConfigNotFormatted);
+    }
+
+    // The one rewrite of the whole file; comments don't survive it, so that takes asking for
+    let comments = format::comments(&content);
+    if comments > 0 && !drop_comments {
+        return Err(SvcError::FmtDropsComments(comments));
+    }
+    format::write_atomic(config_path, &canonical)?;
+    println!("{} formatted.", config_path.display().to_string().cyan());
+    if comments > 0 {
+        eprintln!("{} {} comment(s) dropped with it", "note:".cyan().bold(), comments);
+    }
+    Ok(())
+}
+
+// The workspace's .svc.yaml when there is one, otherwise the global services.yaml
+pub fn config_path() -> Result<String, SvcError> {
+    Ok(locate::config()?.0.display().to_string())
+}
+
+// Why the command would run into access denied with the current token, checked before it starts
+// Watch and serve act on what they see as they run, which no plan made up front can list
+fn refuse_dry_run(command: &Cmd) -> Result<(), SvcError> {
+    let name = match command {
+        Cmd::Watch(args) if !args.status => "watch",
+        Cmd::Serve { .. } => "serve",
+        _ => return Ok(()),
+    };
+    Err(SvcError::InvalidCommand(format!("--dry-run with svc {}, which keeps acting as it runs", name)))
+}
+
+fn needs_elevation(config_path: &str, command: &Cmd) -> Result<Option<String>, SvcError> {
+    if elevation::is_elevated() {
+        return Ok(None);
+    }
+    let (action, access, pids) = match command {
+        Cmd::Kill { pid: Some(pid), .. } => (format!("Killing PID {}", pid), elevation::Access::Terminate, vec![*pid]),
+        Cmd::Kill {
+            name: Some(name),
+            session,
+            ..
+        } => {
+            let mut pids = get_pids(&find_service(config_path, name)?)?;
+            procinfo::retain_session(&mut pids, *session);
+            (format!("Killing service {}", name), elevation::Access::Terminate, pids)
+        }
+        Cmd::Stop {
+            name: Some(name),
+            session,
+            ..
+        } => {
+            let mut pids = get_pids(&find_service(config_path, name)?)?;
+            procinfo::retain_session(&mut pids, *session);
+            (format!("Stopping service {}", name), elevation::Access::Terminate, pids)
+        }
+        Cmd::Restart { name: Some(name), .. } => {
+            let pids = get_pids(&find_service(config_path, name)?)?;
+            (format!("Restarting service {}", name), elevation::Access::Terminate, pids)
+        }
+        Cmd::Adjust { name, .. } => {
+            let pids = get_pids(&find_service(config_path, name)?)?;
+            (format!("Adjusting service {}", name), elevation::Access::SetInformation, pids)
+        }
+        Cmd::Env {
+            name, live: true, pid, ..
+        } => {
+            let pids = pid.map_or_else(|| get_pids(&find_service(config_path, name)?), |pid| Ok(vec![pid]))?;
+            // Only the first is read unless --pid picks one
+            let pids = pids.into_iter().take(1).collect();
+            (format!("Reading the environment of {}", name), elevation::Access::ReadMemory, pids)
+        }
+        Cmd::Enable {
+            name: Some(name), backend, ..
+        }
+        | Cmd::Disable {
+            name: Some(name), backend, ..
+        } => {
+            let enabling = matches!(command, Cmd::Enable { .. });
+            let config = find_document(config_path, name)?;
+            let service = config.service(name)?;
+            let backends = startup::touched(service, &config.settings, *backend, enabling)?;
+            return Ok(startup::needs_admin(service, &backends).map(|backend| {
+                let verb = if enabling { "Enabling" } else { "Disabling" };
+                format!("{} {} on {}", verb, name, backend)
+            }));
+        }
+        // Firewall rules can only ever be changed elevated
+        Cmd::Firewall(FirewallCmd::Apply { name, .. }) => {
+            return Ok(Some(format!("Changing the firewall rule of {}", name)));
+        }
+        _ => return Ok(None),
+    };
+    Ok(pids.iter().any(|&pid| elevation::denied(pid, access)).then_some(action))
+}
+
+// `svc down` and `svc kill --all`
+fn stop_services(
+    config_path: &str,
+    force: bool,
+    session: SessionFilter,
+    args: &cli::StopAllArgs,
+    uptime: &cli::UptimeArgs,
+    only: Option<&[String]>,
+) -> Result<(), SvcError> {
+    let options = stopall::Options {
+        deadline: args.deadline.as_ref().map_or(DEFAULT_STOP_DEADLINE, |deadline| deadline.get()),
+        halt_on_failure: args.halt_on_failure,
+        force_at_deadline: args.force_at_deadline,
+        skip_protected: !force,
+        checkpoint: None,
+    };
+    let live = stopall::Live {
+        session,
+        force,
+        uptime: uptime.clone(),
+    };
+    stopall::run(config_path, options, live, only)
+}
+
+// `svc resume-last`: the last bulk operation again, on its own config, for the services it
+// didn't reach or that failed
+fn resume_last() -> Result<(), SvcError> {
+    let checkpoint = checkpoint::last()?.ok_or(SvcError::NoCheckpoint)?;
+    let remaining = checkpoint.remaining();
+    if remaining.is_empty() {
+        println!("Nothing to resume: {} succeeded for every service.", checkpoint.command_line().cyan());
+        return Ok(());
+    }
+    let command = cli::parse_args(&checkpoint.args)
+        .map_err(|err| SvcError::CorruptState {
+            path: checkpoint::path().map(|path| path.display().to_string()).unwrap_or_default(),
+            reason: err.to_string(),
+        })?
+        .command;
+    for capability in command.capabilities() {
+        probe::require(capability)?;
+    }
+    println!("Resuming {} for {}.", checkpoint.command_line().cyan(), remaining.join(", "));
+    checkpoint::resuming(checkpoint.args.clone());
+    match command {
+        Cmd::Kill {
+            force,
+            session,
+            bulk,
+            stop_all,
+            uptime,
+            ..
+        } if bulk.all => stop_services(&checkpoint.config, force, session, &stop_all, &uptime, Some(&remaining)),
+        Cmd::Down {
+            force,
+            session,
+            stop_all,
+        } => stop_services(&checkpoint.config, force, session, &stop_all, &Default::default(), Some(&remaining)),
+        command => bulk::run(&checkpoint.config, command, Selection::Names(remaining)),
+    }
+}
+
+pub fn run(command: cli::Cmd, elevate: elevation::Elevate) -> Result<(), SvcError> {
+    let config_path = config_path()?;
+
+    if effects::dry_run() {
+        refuse_dry_run(&command)?;
+    } else if let Some(action) = needs_elevation(&config_path, &command)? {
+        exit(elevation::ensure(action, elevate)?);
+    }
+    for capability in command.capabilities() {
+        probe::require(capability)?;
+    }
+
+    // An earlier mutation was killed between writing the temp file and renaming it
+    if !matches!(command, Cmd::Config(ConfigCmd::Recover { .. }))
+        && format::temp_path(Path::new(&config_path)).exists()
+    {
+        eprintln!(
+            "{} an interrupted config write left {}; run 'svc config recover'",
+            "warning:".yellow().bold(),
+            format::temp_path(Path::new(&config_path)).display()
+        );
+    }
+    if !matches!(command, Cmd::Config(ConfigCmd::Migrate | ConfigCmd::Diff)) {
+        migrate::check();
+    }
+
+    // One at a time in dependency order rather than as a bulk command
+    if let Cmd::Kill {
+        force,
+        session,
+        bulk,
+        stop_all,
+        uptime,
+        ..
+    } = &command
+    {
+        if bulk.all {
+            return stop_services(&config_path, *force, *session, stop_all, uptime, None);
+        }
+        if stop_all.given() {
+            return Err(SvcError::InvalidCommand(
+                "--deadline, --halt-on-failure and --force-at-deadline go with --all".to_string(),
+            ));
+        }
+    }
+    match command.selection() {
+        Some(selection) => bulk::run(&config_path, command, selection),
+        // Left alone as asked, so not a failure
+        None => match dispatch(&config_path, command) {
+            Err(err @ SvcError::ConditionNotMet { .. }) => {
+                output::report_skipped(&err);
+                Ok(())
+            }
+            result => result,
+        },
+    }
+}
+
+fn dispatch(config_path: &str, command: Cmd) -> Result<(), SvcError> {
+    match command {
+        Cmd::Run { name: None, .. }
+        | Cmd::Enable { name: None, .. }
+        | Cmd::Disable { name: None, .. }
+        | Cmd::Status { name: None, pid: None, .. }
+        | Cmd::Kill { name: None, pid: None, .. }
+        | Cmd::Stop { name: None, .. } => {
+            Err(SvcError::InvalidCommand("a service name, --stdin, --tag or --all".to_string()))
+        }
+        Cmd::Status {
+            pid: Some(pid),
+            trend,
+            no_deps,
+            session,
+            ..
+        } => {
+            let services = load_config(config_path)?;
+            let service = owner_of(&services, pid)?;
+            if !output::options().json {
+                println!("PID {} belongs to service {}.", pid, service.name.cyan());
+            }
+            let command = Cmd::Status {
+                name: Some(service.name.to_string()),
+                pid: None,
+                trend,
+                no_deps,
+                session,
+                sort: None,
+                bulk: Default::default(),
+            };
+            dispatch(config_path, command)
+        }
+        Cmd::Kill {
+            pid: Some(pid),
+            force,
+            uptime,
+            ..
+        } => {
+            let services = load_config(config_path)?;
+            let service = owner_of(&services, pid)?;
+            uptime::select(&uptime, service, vec![pid])?;
+            scm::refuse(service, force)?;
+            confirm_protected(service, force)?;
+            let result = killguard::screen(service, vec![pid], force).and_then(|pids| terminate(service, &pids));
+            history::record_result(&service.name, "kill", &result);
+            notify::outcome(SvcEvent::Stop { service: service.name.to_string() }, "kill", &result);
+            result
+        }
+        Cmd::Run {
+            name: Some(name),
+            at: Some(at),
+            terminal,
+            extra,
+            force,
+            no_deps,
+            lock_timeout,
+            capture,
+            ..
+        } => {
+            if at[0] != "at" {
+                return Err(SvcError::InvalidCommand(at.join(" ")));
+            }
+            let service = with_extra_args(find_service(config_path, &name)?, extra);
+            let work_at = checked_work_dir(&service, &at[1])?;
+            if !no_deps {
+                deps::start_dependencies(config_path, &service)?;
+            }
+            let result = if terminal {
+                terminal::spawn(&service, &work_at)
+            } else {
+                run_service(
+                    &service,
+                    &RunOverride {
+                        work_at: Some(work_at),
+                        force,
+                        lock_timeout: lock_timeout.map(|timeout| timeout.get()),
+                        capture,
+                    },
+                )
+            };
+            notify::outcome(SvcEvent::Start { service: name }, "run", &result);
+            result
+        }
+        Cmd::Run {
+            name: Some(name),
+            at: None,
+            startup: true,
+            lock_timeout,
+            ..
+        } => {
+            let started = clock::Stamp::now();
+            let config = load_document(config_path)?;
+            let service = config.service(&name)?;
+            let delay = startup_delay(&config, service);
+            if let Some(delay) = delay {
+                cancel::sleep(delay)?;
+            }
+            let woke = clock::Stamp::now();
+            startupenv::apply(service)?;
+            let with = RunOverride {
+                lock_timeout: lock_timeout.map(|timeout| timeout.get()),
+                ..RunOverride::default()
+            };
+            let result = run_service(service, &with);
+            bootreport::record(&name, started, delay, woke, result.is_ok());
+            notify::outcome(SvcEvent::Start { service: name }, "run", &result);
+            result
+        }
+        Cmd::Run {
+            name: Some(name),
+            at: None,
+            terminal: true,
+            extra,
+            force,
+            no_deps,
+            ..
+        } => {
+            let service = with_extra_args(find_service(config_path, &name)?, extra);
+            scm::refuse(&service, force)?;
+            if !no_deps {
+                deps::start_dependencies(config_path, &service)?;
+            }
+            let _lock = mutex::lock("run", &service.name)?;
+            if !get_pids(&service)?.is_empty() {
+                return Err(SvcError::ServiceIsRunning);
+            }
+            let result = terminal::spawn(&service, work_dir(&service));
+            notify::outcome(SvcEvent::Start { service: name }, "run", &result);
+            result
+        }
+        Cmd::Run {
+            name: Some(name),
+            at: None,
+            extra,
+            force,
+            no_deps,
+            lock_timeout,
+            capture,
+            ..
+        } => {
+            let service = with_extra_args(find_service(config_path, &name)?, extra);
+            if !no_deps {
+                deps::start_dependencies(config_path, &service)?;
+            }
+            let with = RunOverride {
+                work_at: None,
+                force,
+                lock_timeout: lock_timeout.map(|timeout| timeout.get()),
+                capture,
+            };
+            let result = run_service(&service, &with);
+            notify::outcome(SvcEvent::Start { service: name }, "run", &result);
+            result
+        }
+        Cmd::Enable {
+            name: Some(name), backend, ..
+        } => {
+            let config = find_document(config_path, &name)?;
+            let service = config.service(&name)?;
+            let result = enable_service(service, &config.settings, backend);
+            history::record_result(&name, "enable", &result);
+            notify::outcome(SvcEvent::Enable { service: name }, "enable", &result);
+            result
+        }
+        Cmd::Disable {
+            name: Some(name), backend, ..
+        } => {
+            let config = find_document(config_path, &name)?;
+            let result = disable_service(config.service(&name)?, backend);
+            history::record_result(&name, "disable", &result);
+            notify::outcome(SvcEvent::Disable { service: name }, "disable", &result);
+            result
+        }
+        Cmd::Status {
+            name: Some(name),
+            trend,
+            no_deps,
+            session,
+            ..
+        } => {
+            let config = find_document(config_path, &name)?;
+            let service = config.service(&name)?;
+            if output::options().json {
+                let mut report = list::reports(&config.services, &[service], !no_deps)?;
+                report[0].retain_session(session);
+                println!("{}", serde_json::to_string_pretty(&report[0]).map_err(std::io::Error::other)?);
+                return Ok(());
+            }
+            let status = get_status(service)?;
+            let conflicts = ports::conflicts(&config.services, &[(service.name.as_ref(), &status.pids)]);
+            let conflicts = conflicts.get(service.name.as_ref()).map(Vec::as_slice).unwrap_or_default();
+            show_status(&config, service, status, conflicts, trend, no_deps, session)
+        }
+        Cmd::List { sort, scope, outdated } => {
+            let services = scoped_services(config_path, scope)?;
+            ports::warn_duplicates(&services);
+            list::list(&services, sort, outdated)
+        }
+        Cmd::Adjust {
+            name,
+            priority,
+            affinity,
+            persist,
+        } => {
+            let service = find_service(config_path, &name)?;
+            // Refused before --persist could write it
+            affinity.as_deref().map(priority::mask).transpose()?;
+            let adjusted = adjust_service(&service, priority, affinity.as_deref());
+            if persist {
+                edit::set_scheduling(Path::new(config_path), &name, priority, affinity.as_deref())?;
+            }
+            adjusted
+        }
+        Cmd::Kill {
+            name: Some(name),
+            force,
+            session,
+            uptime,
+            ..
+        } => {
+            let service = find_service(config_path, &name)?;
+            // Skipped by the condition it was left alone, with nothing to record
+            let pids = match session_pids(&service, session) {
+                Ok(pids) => Ok(uptime::select(&uptime, &service, pids)?),
+                Err(err) => Err(err),
+            };
+            scm::refuse(&service, force)?;
+            confirm_protected(&service, force)?;
+            let result = pids.and_then(|pids| kill_pids_within(&service, pids, force, Duration::MAX).map(drop));
+            history::record_result(&name, "kill", &result);
+            notify::outcome(SvcEvent::Stop { service: name }, "kill", &result);
+            result
+        }
+        Cmd::Down {
+            force,
+            session,
+            stop_all,
+        } => stop_services(config_path, force, session, &stop_all, &Default::default(), None),
+        Cmd::ResumeLast => resume_last(),
+        Cmd::Stop {
+            name: Some(name),
+            force,
+            session,
+            uptime,
+            ..
+        } => {
+            let service = find_service(config_path, &name)?;
+            let pids = match session_pids(&service, session) {
+                Ok(pids) => Ok(uptime::select(&uptime, &service, pids)?),
+                Err(err) => Err(err),
+            };
+            confirm_protected(&service, force)?;
+            let result = pids
+                .and_then(|pids| killguard::screen(&service, pids, force))
+                .and_then(|pids| process::stop_pids(&service, pids));
+            history::record_result(&name, "stop", &result);
+            notify::outcome(SvcEvent::Stop { service: name }, "stop", &result);
+            result
+        }
+        Cmd::Restart {
+            name,
+            outdated,
+            force,
+            when_idle,
+            now,
+            idle_cpu,
+            idle_for,
+            max_wait,
+            timeout,
+            overlap,
+            uptime,
+        } => {
+            let options = restart::RestartOptions {
+                force,
+                when_idle,
+                now,
+                idle_cpu,
+                idle_for: idle_for.get(),
+                max_wait: max_wait.get(),
+                timeout: timeout.get(),
+                overlap,
+                uptime,
+            };
+            match name {
+                Some(name) if !outdated => restart::restart(&find_service(config_path, &name)?, &options),
+                _ => restart::restart_outdated(config_path, &options),
+            }
+        }
+        Cmd::Serve {
+            interval,
+            max_subscribers,
+            max_connections,
+            sample_interval,
+        } => serve::serve(
+            config_path,
+            &serve::ServeOptions {
+                interval,
+                max_subscribers,
+                max_connections,
+                sample_interval,
+            },
+        ),
+        Cmd::Watch(args) => watch_command(config_path, args),
+        Cmd::Check {
+            nagios,
+            names,
+            no_deps,
+            outdated,
+        } => {
+            let options = check::CheckOptions {
+                nagios,
+                with_deps: !no_deps,
+                outdated,
+            };
+            let code = check::check(&load_config(config_path)?, &names, &options)?;
+            exit(i32::from(code));
+        }
+        Cmd::Logs { name, lines, follow } => logs::show(&find_service(config_path, &name)?, lines, follow),
+        Cmd::Du { names, enforce } => {
+            let config = load_document(config_path)?;
+            let services: Vec<&Service> = if names.is_empty() {
+                config.services.iter().collect()
+            } else {
+                names.iter().map(|name| config.service(name)).collect::<Result<_, _>>()?
+            };
+            diskbudget::report(&services, enforce)
+        }
+        Cmd::Summary { with_startup, format } => {
+            let code = summary::summary(&load_config(config_path)?, &summary::SummaryOptions { with_startup, format })?;
+            exit(i32::from(code));
+        }
+        Cmd::Fmt { check, drop_comments } => fmt_command(Path::new(config_path), check, drop_comments),
+        Cmd::ApplyStartup { rollback_on_failure } => {
+            apply::apply_startup(&load_document(config_path)?, effects::dry_run(), rollback_on_failure)
+        }
+        Cmd::AuditStartup { fix } => audit::run(config_path, fix),
+        Cmd::Doctor => probe::doctor(),
+        Cmd::Errors => errors::list(),
+        Cmd::Apply {
+            file,
+            stdin: _,
+            prune,
+            force,
+        } => {
+            let source = file.map_or(desired::Source::Stdin, desired::Source::File);
+            desired::apply(config_path, &source, &desired::ApplyOptions {
+                dry_run: effects::dry_run(),
+                prune,
+                force,
+            })
+        }
+        Cmd::Validate {
+            offline,
+            timeout,
+            signatures,
+        } => validate::validate(
+            config_path,
+            &load_document(config_path)?,
+            &validate::ValidateOptions {
+                offline,
+                timeout: timeout.get(),
+                signatures,
+            },
+        ),
+        Cmd::Backup { file } => backup::backup(config_path, &file),
+        Cmd::Bundle { name, out, whole_dir } => bundle::bundle(config_path, &name, &out, whole_dir),
+        Cmd::ExportLauncher { name, style, out } => launcher::export_launcher(config_path, &name, style, out.as_deref()),
+        Cmd::Restore { file } => backup::restore(config_path, &file, effects::dry_run()),
+        Cmd::Relocate {
+            name,
+            search,
+            pick,
+            restart,
+        } => relocate::relocate(config_path, &name, &relocate::RelocateOptions { search, pick, restart }),
+        Cmd::RunAdhoc {
+            path,
+            name,
+            work_at,
+            util,
+            interpreter,
+            log,
+        } => {
+            let name = match name {
+                Some(name) => name,
+                None => Path::new(&path)
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .ok_or_else(|| SvcError::InvalidCommand(path.clone()))?
+                    .to_string(),
+            };
+            adhoc::run_adhoc(
+                config_path,
+                adhoc::AdhocEntry {
+                    name,
+                    service_type: if util { ServiceType::Util } else { ServiceType::Executable },
+                    path,
+                    work_at,
+                    interpreter,
+                    log,
+                },
+            )
+        }
+        Cmd::Promote { name } => adhoc::promote(config_path, &name),
+        Cmd::Add {
+            name,
+            path,
+            service_type,
+            interpreter,
+            at,
+        } => {
+            let service_type = match service_type {
+                cli::ServiceKind::Executable => ServiceType::Executable,
+                cli::ServiceKind::Util => ServiceType::Util,
+            };
+            let entry = edit::NewService {
+                name,
+                service_type,
+                path,
+                interpreter,
+                work_at: at,
+            };
+            edit::add(Path::new(config_path), &load_config(config_path)?, entry)
+        }
+        Cmd::Set { name, field, value } => {
+            edit::set_field(Path::new(config_path), &load_config(config_path)?, &name, &field, &value)
+        }
+        Cmd::Remove { name, purge, force } => {
+            let options = artifacts::RemoveOptions {
+                purge,
+                dry_run: effects::dry_run(),
+                force,
+            };
+            artifacts::remove(config_path, &name, &options)
+        }
+        Cmd::Gc { yes } => {
+            let options = artifacts::GcOptions {
+                dry_run: effects::dry_run(),
+                yes,
+            };
+            artifacts::gc(config_path, &options)
+        }
+        Cmd::Recover => recover::recover(config_path),
+        Cmd::Forget { name } => adhoc::forget(&name),
+        Cmd::Rename { old, new } => edit::rename(Path::new(config_path), &load_config(config_path)?, &old, &new),
+        Cmd::Config(ConfigCmd::Recover { keep }) => edit::recover(Path::new(config_path), keep),
+        Cmd::Config(ConfigCmd::Migrate) => migrate::migrate(),
+        Cmd::Config(ConfigCmd::Diff) => migrate::diff(),
+        Cmd::ShutdownHook(ShutdownHookCmd::Install) => shutdownhook::install(),
+        Cmd::ShutdownHook(ShutdownHookCmd::Uninstall) => shutdownhook::uninstall(),
+        Cmd::ShutdownHook(ShutdownHookCmd::Status) => shutdownhook::print_status(),
+        Cmd::Secret(SecretCmd::Set { key }) => secrets::set_secret(&key),
+        Cmd::Secret(SecretCmd::Remove { key }) => secrets::remove_secret(&key),
+        Cmd::Export { resolve } => edit::export(Path::new(config_path), resolve),
+        Cmd::Schema => schema::print(),
+        Cmd::Init => locate::init(),
+        Cmd::Which { name } => which(config_path, &name),
+        Cmd::Env {
+            name,
+            live,
+            pid,
+            diff_config,
+            startup,
+        } => {
+            let options = liveenv::EnvOptions {
+                live,
+                pid,
+                diff_config,
+                startup,
+            };
+            liveenv::show(&find_service(config_path, &name)?, &options)
+        }
+        Cmd::StartupReport => bootreport::report(),
+        Cmd::Firewall(FirewallCmd::Apply { name, remove }) => firewall::apply(&find_service(config_path, &name)?, remove),
+        Cmd::Version if output::options().json => capabilities::print(),
+        Cmd::Version => {
+            println!("svc {}", VERSION);
+            Ok(())
+        }
+        Cmd::Help { topic } => cli::print_help(topic.as_deref()),
+    }
+}
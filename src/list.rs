@@ -0,0 +1,299 @@
+use crate::cli::{OrderKey, SessionFilter, SortKey};
+use crate::deps::{self, Dependency, State};
+use crate::procinfo::{self, ProcessUsage};
+use crate::probe::{self, Capability};
+use crate::order::{self, Order};
+use crate::{enrich, outdated, ports, restart, scm, warnings};
+use crate::units::format_bytes;
+use crate::backends::{self, StartupEntry};
+use crate::{output, running_pids, workspace, Service, ServiceStatus, ServiceType, SvcError, NO_SERVICES_HINT};
+use colored::Colorize;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+// What status and list report about a service in JSON mode
+#[derive(Debug, Serialize)]
+pub struct StatusReport<'a> {
+    pub name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<&'a str>,
+    #[serde(rename = "type")]
+    pub service_type: &'a ServiceType,
+    pub path: &'a str,
+    pub pids: Vec<u64>,
+    pub startup: bool,
+    // Each backend that starts it, with what it starts
+    pub startup_entries: Vec<StartupEntry>,
+    pub state: State,
+    pub dependencies: Vec<Dependency<'a>>,
+    pub processes: Vec<ProcessUsage>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub adhoc: bool,
+    // From the global services.yaml while a workspace config is active
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub global: bool,
+    // Kill, stop and remove ask first, and bulk stops leave it running
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub protected: bool,
+    // A `svc restart --when-idle` is waiting on it
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub restart_pending: bool,
+    // A `svc restart --overlap` runs old and new instances side by side
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub switchover: Option<restart::Switchover>,
+    // Windows services that run the same binary
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub scm_services: Vec<scm::Registration>,
+    // Why a running instance no longer runs the binary on disk
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outdated: Option<String>,
+    // Its declared port bound by another configured service, or its process bound to another's
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub port_conflicts: Vec<String>,
+    // What the enrichers setting's programs said about it
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl<'a> StatusReport<'a> {
+    pub fn new(
+        service: &'a Service,
+        status: ServiceStatus,
+        dependencies: Vec<Dependency<'a>>,
+        processes: Vec<ProcessUsage>,
+    ) -> Self {
+        StatusReport {
+            name: &service.name,
+            label: service.label.as_deref(),
+            service_type: &service.service_type,
+            path: &service.path,
+            state: deps::own_state(!status.pids.is_empty(), &dependencies),
+            outdated: outdated::reason(service, &status.pids),
+            pids: status.pids,
+            startup: !status.startup.is_empty(),
+            startup_entries: status.startup,
+            dependencies,
+            processes,
+            adhoc: service.adhoc,
+            global: service.global,
+            protected: service.protected,
+            restart_pending: restart::pending(&service.name).unwrap_or(false),
+            switchover: restart::switchover(&service.name),
+            scm_services: scm::registrations(service),
+            port_conflicts: Vec::new(),
+            extra: serde_json::Map::new(),
+        }
+    }
+
+    pub fn retain_session(&mut self, filter: SessionFilter) {
+        procinfo::retain_session(&mut self.pids, filter);
+        self.processes.retain(|p| self.pids.contains(&p.pid));
+        self.state = deps::own_state(!self.pids.is_empty(), &self.dependencies);
+    }
+
+    // Totals over the processes we could open; None when none of them reported the value
+    fn memory(&self) -> Option<u64> {
+        self.processes.iter().filter_map(|p| p.memory).reduce(|a, b| a + b)
+    }
+
+    fn cpu(&self) -> Option<f64> {
+        self.processes.iter().filter_map(|p| p.cpu).reduce(|a, b| a + b)
+    }
+
+    // Age of the oldest process
+    fn uptime(&self) -> Option<u64> {
+        self.processes.iter().filter_map(|p| p.uptime).max()
+    }
+}
+
+// Largest first, unknown values last
+fn descending<T: PartialOrd>(a: Option<T>, b: Option<T>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => b.partial_cmp(&a).unwrap_or(Ordering::Equal),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+// In config order first, and stable after, so services that tie keep it
+fn sort(config: &[Service], reports: &mut [StatusReport], key: Option<SortKey>) {
+    let by = match key {
+        Some(SortKey::Name) => Some(OrderKey::Name),
+        Some(SortKey::State) => Some(OrderKey::State),
+        _ => None,
+    };
+    Order::of(config).arrange(reports, by, |report| report.name, |report| report.state);
+    match key {
+        Some(SortKey::Memory) => reports.sort_by(|a, b| descending(a.memory(), b.memory())),
+        Some(SortKey::Cpu) => reports.sort_by(|a, b| descending(a.cpu(), b.cpu())),
+        Some(SortKey::Uptime) => reports.sort_by(|a, b| descending(a.uptime(), b.uptime())),
+        Some(SortKey::Pids) => reports.sort_by_key(|report| std::cmp::Reverse(report.pids.len())),
+        _ => {}
+    }
+}
+
+// Status of the selected services, with resource usage from one shared sampling pass;
+// with `with_deps` their dependencies are looked up in the same pass
+pub fn reports<'a>(
+    config: &'a [Service<'a>],
+    selected: &[&'a Service<'a>],
+    with_deps: bool,
+) -> Result<Vec<StatusReport<'a>>, SvcError> {
+    let mut needed = selected.to_vec();
+    if with_deps {
+        for service in selected {
+            for dependency in deps::closure(config, service) {
+                if !needed.iter().any(|s| s.name == dependency.name) {
+                    needed.push(dependency);
+                }
+            }
+        }
+    }
+    // One process snapshot shared by every service
+    let images = procinfo::Images::take();
+    let mut statuses = order::par_map(&needed, |service| {
+        Ok(ServiceStatus {
+            pids: running_pids(service, &images)?,
+            startup: backends::query(service)?,
+        })
+    })
+    .into_iter()
+    .collect::<Result<Vec<_>, SvcError>>()?;
+    let running: HashMap<&str, bool> = needed
+        .iter()
+        .zip(&statuses)
+        .map(|(service, status)| (service.name.as_ref(), !status.pids.is_empty()))
+        .collect();
+    let known: Vec<(&str, &[u64])> =
+        needed.iter().zip(&statuses).map(|(service, status)| (service.name.as_ref(), status.pids.as_slice())).collect();
+    let mut port_conflicts = ports::conflicts(config, &known);
+    // Dependencies only feed the evaluation, they aren't reported themselves
+    statuses.truncate(selected.len());
+
+    let pids: Vec<u64> = statuses.iter().flat_map(|status| status.pids.iter().copied()).collect();
+    let mut usage = procinfo::sample(&pids).into_iter();
+    let with_pids: Vec<(&Service, &ServiceStatus)> = selected.iter().copied().zip(&statuses).collect();
+    let mut extras = order::par_map(&with_pids, |(service, status)| enrich::extra(service, &status.pids)).into_iter();
+    Ok(selected
+        .iter()
+        .zip(statuses)
+        .map(|(service, status)| {
+            let dependencies = if with_deps {
+                deps::evaluate(config, &running, service)
+            } else {
+                Vec::new()
+            };
+            let processes = usage.by_ref().take(status.pids.len()).collect();
+            let mut report = StatusReport::new(service, status, dependencies, processes);
+            report.extra = extras.next().unwrap_or_default();
+            report.port_conflicts = port_conflicts.remove(service.name.as_ref()).unwrap_or_default();
+            report
+        })
+        .collect())
+}
+
+fn format_uptime(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3_599 => format!("{}m", seconds / 60),
+        3_600..=86_399 => format!("{}h {}m", seconds / 3_600, seconds % 3_600 / 60),
+        _ => format!("{}d {}h", seconds / 86_400, seconds % 86_400 / 3_600),
+    }
+}
+
+// `outdated_only` keeps the services whose binary changed on disk since they started
+pub fn list(config: &[Service], key: Option<SortKey>, outdated_only: bool) -> Result<(), SvcError> {
+    let all: Vec<&Service> = config.iter().collect();
+    let mut reports = reports(config, &all, true)?;
+    if outdated_only {
+        reports.retain(|report| report.outdated.is_some());
+    }
+    sort(config, &mut reports, key);
+    let _warnings = warnings::collect();
+    for report in &reports {
+        warnings::startup(report.name, &report.startup_entries);
+    }
+
+    if output::options().json {
+        println!("{}", serde_json::to_string_pretty(&reports).map_err(std::io::Error::other)?);
+        return Ok(());
+    }
+    if reports.is_empty() && outdated_only {
+        println!("No running service's binary changed on disk since it started.");
+        return Ok(());
+    }
+    if reports.is_empty() {
+        println!("No services configured.");
+        println!("{} {}", "hint:".cyan().bold(), NO_SERVICES_HINT);
+        return Ok(());
+    }
+
+    let rows: Vec<[String; 8]> = reports
+        .iter()
+        .map(|report| {
+            let dash = || "-".to_string();
+            // A Util runs to completion and has no start-up entry, so neither column applies
+            let util = matches!(report.service_type, ServiceType::Util);
+            [
+                match (report.adhoc, report.global) {
+                    (true, _) => format!("{} (adhoc)", report.name),
+                    (_, true) => format!("{} (global)", report.name),
+                    _ => report.name.to_string(),
+                } + if report.outdated.is_some() { " (outdated)" } else { "" }
+                    + if report.protected { output::symbol(" \u{1F6E1}", " [protected]") } else { "" },
+                report.label.map_or_else(dash, String::from),
+                report.service_type.to_string(),
+                if util { dash() } else { report.pids.len().to_string() },
+                match (util, report.startup) {
+                    (true, _) => dash(),
+                    (false, true) => "enabled".to_string(),
+                    // The Run keys couldn't be read, so no entry says nothing
+                    (false, false) if probe::missing(Capability::StartupRead).is_some() => "unknown".to_string(),
+                    (false, false) => "disabled".to_string(),
+                },
+                report.memory().map_or_else(dash, format_bytes),
+                report.cpu().map_or_else(dash, |cpu| format!("{:.1}%", cpu)),
+                report.uptime().map_or_else(dash, format_uptime),
+            ]
+        })
+        .collect();
+
+    let header = ["NAME", "LABEL", "TYPE", "PIDS", "START-UP", "MEMORY", "CPU", "UPTIME"];
+    // Labels are often CJK or emoji, so columns are measured as the console shows them
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(output::width(cell));
+        }
+    }
+    // Without any label the column would be dashes only
+    let labeled = reports.iter().any(|report| report.label.is_some());
+
+    let line = |cells: [&str; 8]| {
+        cells
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .filter(|&(at, _)| at != 1 || labeled)
+            .map(|(_, (cell, width))| output::pad(cell, width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    if let Some(path) = workspace::active() {
+        println!("Workspace: {}", path.display().to_string().cyan());
+    }
+    println!("{}", line(header).bold());
+    for (row, report) in rows.iter().zip(&reports) {
+        let extra: Vec<String> =
+            report.extra.iter().map(|(key, value)| format!("{}={}", key, enrich::display(value))).collect();
+        match extra.is_empty() {
+            true => println!("{}", line(row.each_ref().map(String::as_str))),
+            false => println!("{}  {}", line(row.each_ref().map(String::as_str)), extra.join(", ").cyan()),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,232 @@
+use crate::{expand_env, get_pids, output, procinfo, secrets, startupenv, Service, SvcError};
+use colored::Colorize;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+
+const REDACTED: &str = "<redacted>";
+
+pub struct EnvOptions {
+    // Read the running process instead of the config
+    pub live: bool,
+    // Which of the service's processes, instead of its first
+    pub pid: Option<u64>,
+    // With live: compare the configured variables with the process's
+    pub diff_config: bool,
+    // What `svc run --startup` passes it at logon: the captured allowlist, then the configured env
+    pub startup: bool,
+}
+
+// Variables named like a stored secret have their values hidden
+struct Redactor {
+    keys: HashSet<String>,
+}
+
+impl Redactor {
+    fn new() -> Result<Self, SvcError> {
+        let keys = secrets::keys()?.iter().map(|key| key.to_lowercase()).collect();
+        Ok(Redactor { keys })
+    }
+
+    fn show<'a>(&self, name: &str, value: &'a str) -> &'a str {
+        if self.keys.contains(&name.to_lowercase()) {
+            REDACTED
+        } else {
+            value
+        }
+    }
+}
+
+// The configured env as the service gets it, on top of svc's own environment
+fn configured(service: &Service) -> Vec<(String, String)> {
+    service.env.iter().map(|(name, value)| (name.clone(), expand_env(value))).collect()
+}
+
+fn pick_pid(service: &Service, wanted: Option<u64>) -> Result<(u64, usize), SvcError> {
+    let pids = get_pids(service)?;
+    match wanted {
+        Some(pid) if pids.contains(&pid) => Ok((pid, pids.len())),
+        Some(pid) => Err(SvcError::InvalidCommand(format!(
+            "--pid {}, which is not a process of {}",
+            pid, service.name
+        ))),
+        None => pids.first().map(|&pid| (pid, pids.len())).ok_or(SvcError::ServiceIsNotRunning),
+    }
+}
+
+// Access denied names whose process it is rather than reporting the raw OS error
+fn live(service: &Service, pid: u64) -> Result<Vec<(String, String)>, SvcError> {
+    procinfo::environment(pid).map_err(|err| match err.kind() {
+        std::io::ErrorKind::PermissionDenied => SvcError::EnvironmentDenied {
+            pid,
+            owner: procinfo::owner(pid).or_else(|| service.run_as.as_ref().map(|run_as| run_as.user.to_string())),
+        },
+        _ => err.into(),
+    })
+}
+
+fn print_variables(variables: &[(String, String)], redactor: &Redactor, pid: Option<u64>) -> Result<(), SvcError> {
+    if output::options().json {
+        let variables: BTreeMap<&str, &str> =
+            variables.iter().map(|(name, value)| (name.as_str(), redactor.show(name, value))).collect();
+        let document = match pid {
+            Some(pid) => serde_json::json!({ "pid": pid, "variables": variables }),
+            None => serde_json::json!({ "variables": variables }),
+        };
+        println!("{}", serde_json::to_string_pretty(&document).map_err(std::io::Error::other)?);
+        return Ok(());
+    }
+    for (name, value) in variables {
+        println!("{}={}", name, redactor.show(name, value));
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum State {
+    Same,
+    Differs,
+    // Configured, but not in the process at all
+    Missing,
+}
+
+#[derive(Serialize)]
+struct Difference<'a> {
+    name: &'a str,
+    state: State,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    live: Option<&'a str>,
+    configured: &'a str,
+}
+
+// Each configured variable against the process's; names compare case-insensitively, as
+// Windows looks them up
+fn print_diff(
+    configured: &[(String, String)],
+    live: &[(String, String)],
+    redactor: &Redactor,
+    pid: u64,
+) -> Result<(), SvcError> {
+    let differences: Vec<Difference> = configured
+        .iter()
+        .map(|(name, value)| {
+            let found = live.iter().find(|(other, _)| other.eq_ignore_ascii_case(name));
+            let state = match found {
+                None => State::Missing,
+                Some((_, found)) if found == value => State::Same,
+                Some(_) => State::Differs,
+            };
+            Difference {
+                name,
+                state,
+                live: found.map(|(_, found)| redactor.show(name, found)),
+                configured: redactor.show(name, value),
+            }
+        })
+        .collect();
+
+    if output::options().json {
+        let document = serde_json::json!({ "pid": pid, "variables": differences });
+        println!("{}", serde_json::to_string_pretty(&document).map_err(std::io::Error::other)?);
+        return Ok(());
+    }
+    if differences.is_empty() {
+        println!("The service configures no env.");
+        return Ok(());
+    }
+    let width = differences.iter().map(|difference| output::width(difference.name)).max().unwrap_or(0);
+    for difference in &differences {
+        let name = output::pad(difference.name, width);
+        match difference.state {
+            State::Same => println!("{} {}", "=".green(), name),
+            State::Differs => println!(
+                "{} {}  live: {}  configured: {}",
+                "~".yellow().bold(),
+                name.yellow(),
+                difference.live.unwrap_or_default(),
+                difference.configured
+            ),
+            State::Missing => println!(
+                "{} {}  not in the process, configured: {}",
+                "-".red().bold(),
+                name.red(),
+                difference.configured
+            ),
+        }
+    }
+    let differing = differences.iter().filter(|difference| !matches!(difference.state, State::Same)).count();
+    println!(
+        "{} of {} configured variables differ in PID {}.",
+        differing,
+        differences.len(),
+        pid
+    );
+    Ok(())
+}
+
+// Through the same startupenv::apply a logon launch goes through, so %VAR% references in the
+// configured env see the captured values; a configured name wins over a captured one
+fn print_startup(service: &Service, redactor: &Redactor) -> Result<(), SvcError> {
+    let captured = startupenv::load(&service.name)?;
+    startupenv::apply(service)?;
+    let configured = configured(service);
+    let mut variables: Vec<(String, String)> = captured
+        .iter()
+        .flat_map(|captured| captured.variables.iter())
+        .filter(|(name, _)| !configured.iter().any(|(other, _)| other.eq_ignore_ascii_case(name)))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    variables.extend(configured);
+    let unset = captured.as_ref().map(|captured| captured.unset.clone()).unwrap_or_default();
+
+    if output::options().json {
+        let shown: BTreeMap<&str, &str> =
+            variables.iter().map(|(name, value)| (name.as_str(), redactor.show(name, value))).collect();
+        let document = serde_json::json!({
+            "captured": captured.as_ref().map(|captured| &captured.captured),
+            "variables": shown,
+            "unset": unset,
+        });
+        println!("{}", serde_json::to_string_pretty(&document).map_err(std::io::Error::other)?);
+        return Ok(());
+    }
+    for (name, value) in &variables {
+        println!("{}={}", name, redactor.show(name, value));
+    }
+    let note = match &captured {
+        _ if service.startup_env_allowlist.is_empty() && captured.is_none() => {
+            "no startup_env_allowlist; everything else comes from the logon environment".to_string()
+        }
+        None => format!("nothing captured yet; 'svc enable {}' captures the allowlist", service.name),
+        Some(captured) if unset.is_empty() => format!("allowlist captured {}", captured.captured),
+        Some(captured) => format!("allowlist captured {}, when {} unset", captured.captured, unset.join(", ")),
+    };
+    eprintln!("{} {}", "note:".cyan().bold(), note);
+    Ok(())
+}
+
+// `svc env`: what the config passes a service, what a running process has, or the difference
+pub fn show(service: &Service, options: &EnvOptions) -> Result<(), SvcError> {
+    let redactor = Redactor::new()?;
+    if options.startup {
+        return print_startup(service, &redactor);
+    }
+    if !options.live {
+        return print_variables(&configured(service), &redactor, None);
+    }
+    let (pid, count) = pick_pid(service, options.pid)?;
+    if count > 1 && options.pid.is_none() && !output::options().json {
+        eprintln!(
+            "{} has {} processes, showing PID {}; pick another with --pid",
+            service.name.cyan(),
+            count,
+            pid
+        );
+    }
+    let variables = live(service, pid)?;
+    if options.diff_config {
+        print_diff(&configured(service), &variables, &redactor, pid)
+    } else {
+        print_variables(&variables, &redactor, Some(pid))
+    }
+}
@@ -0,0 +1,182 @@
+use crate::format::write_atomic;
+use crate::{workspace, SvcError};
+use colored::Colorize;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+pub const CONFIG_FILE: &str = "services.yaml";
+
+static FLAG: OnceLock<PathBuf> = OnceLock::new();
+static FOUND: OnceLock<(PathBuf, Source)> = OnceLock::new();
+
+// Where the config in use came from, in the order svc looks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Flag,
+    Env,
+    Workspace,
+    CurrentDir,
+    AppData,
+    ExeDir,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Source::Flag => "--config",
+            Source::Env => "SVC_CONFIG",
+            Source::Workspace => "workspace",
+            Source::CurrentDir => "current directory",
+            Source::AppData => r"%APPDATA%\svc",
+            Source::ExeDir => "next to svc.exe",
+        })
+    }
+}
+
+// --config, taken before anything reads the config; relative to the current directory
+pub fn set_flag(path: &str) {
+    let path = std::path::absolute(path).unwrap_or_else(|_| PathBuf::from(path));
+    let _ = FLAG.set(path);
+}
+
+fn env_path() -> Option<PathBuf> {
+    let path = std::env::var_os("SVC_CONFIG").filter(|path| !path.is_empty())?;
+    Some(std::path::absolute(&path).unwrap_or_else(|_| PathBuf::from(path)))
+}
+
+pub fn exe_path() -> Result<PathBuf, SvcError> {
+    Ok(std::env::current_exe()?.parent().unwrap().join(CONFIG_FILE))
+}
+
+pub fn appdata_path() -> Option<PathBuf> {
+    let appdata = std::env::var_os("APPDATA").filter(|path| !path.is_empty())?;
+    Some(PathBuf::from(appdata).join("svc").join(CONFIG_FILE))
+}
+
+// The locations searched when neither --config nor SVC_CONFIG is set, after the workspace
+pub fn searched() -> Result<Vec<(PathBuf, Source)>, SvcError> {
+    let mut paths = Vec::new();
+    if let Ok(cwd) = std::env::current_dir() {
+        paths.push((cwd.join(CONFIG_FILE), Source::CurrentDir));
+    }
+    paths.extend(appdata_path().map(|path| (path, Source::AppData)));
+    paths.push((exe_path()?, Source::ExeDir));
+    Ok(paths)
+}
+
+// The config outside any workspace and directory: %APPDATA%\svc\services.yaml, or the one
+// next to svc.exe that older installs keep; the APPDATA one when there is neither yet
+pub fn global_path() -> Result<String, SvcError> {
+    Ok(global()?.0.display().to_string())
+}
+
+fn global() -> Result<(PathBuf, Source), SvcError> {
+    let candidates: Vec<(PathBuf, Source)> = appdata_path()
+        .map(|path| (path, Source::AppData))
+        .into_iter()
+        .chain(Some((exe_path()?, Source::ExeDir)))
+        .collect();
+    let found = candidates.iter().find(|(path, _)| path.is_file()).unwrap_or(&candidates[0]);
+    Ok(found.clone())
+}
+
+fn find() -> Result<(PathBuf, Source), SvcError> {
+    if let Some(path) = FLAG.get() {
+        return Ok((path.clone(), Source::Flag));
+    }
+    if let Some(path) = env_path() {
+        return Ok((path, Source::Env));
+    }
+    if let Some(path) = workspace::active() {
+        return Ok((path.to_path_buf(), Source::Workspace));
+    }
+    let searched = searched()?;
+    if let Some(found) = searched.iter().find(|(path, _)| path.is_file()) {
+        return Ok(found.clone());
+    }
+    // Nothing yet: loading it reports ConfigMissing with the locations searched
+    global()
+}
+
+// The config commands use and where it came from; looked up once per run
+pub fn config() -> Result<&'static (PathBuf, Source), SvcError> {
+    if let Some(found) = FOUND.get() {
+        return Ok(found);
+    }
+    let found = find()?;
+    Ok(FOUND.get_or_init(|| found))
+}
+
+// A config that logon or an elevated relaunch wouldn't find by searching again, so what
+// svc starts then has to be told with --config
+pub fn pinned() -> Option<PathBuf> {
+    match config().ok()? {
+        (path, Source::Flag | Source::Env | Source::Workspace | Source::CurrentDir) => Some(path.clone()),
+        _ => None,
+    }
+}
+
+// `--config <path>` for another svc process when the config is pinned, else nothing
+pub fn carried_args() -> Vec<String> {
+    match pinned() {
+        Some(path) => vec!["--config".to_string(), path.display().to_string()],
+        None => Vec::new(),
+    }
+}
+
+// Whether a missing config was named explicitly, rather than not found by searching
+pub fn explicit() -> bool {
+    matches!(config(), Ok((_, Source::Flag | Source::Env)))
+}
+
+// Only comments, which is a config without services until some are uncommented
+const STARTER: &str = "\
+# svc's services; 'svc help config-file' describes every field, and
+# 'svc schema' prints a JSON Schema for editors
+#
+# - name: MyServer
+#   type: Executable
+#   path: D:\\path\\to\\server.exe
+#   # relaunched by `svc watch` whenever it exits
+#   restart: always
+#
+# - name: backup
+#   type: Util
+#   path: D:\\path\\to\\backup.py
+#   interpreter: python
+";
+
+// Where `svc init` writes: the config named by --config or SVC_CONFIG, else the APPDATA one
+pub fn init_path() -> Option<PathBuf> {
+    FLAG.get().cloned().or_else(env_path).or_else(appdata_path)
+}
+
+// `svc init`: a commented starter config where svc looks for one, never over an existing file
+pub fn init() -> Result<(), SvcError> {
+    let path = init_path()
+        .ok_or_else(|| SvcError::InvalidCommand(r"init without %APPDATA%; pass --config <path>".to_string()))?;
+    if path.exists() {
+        return Err(SvcError::InvalidCommand(format!("init, {} already exists", path.display())));
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    write_atomic(&path, STARTER)?;
+    println!("Created {}.", path.display().to_string().cyan());
+    println!("Add services to it, then try 'svc list'.");
+    Ok(())
+}
+
+// For the getting-started text of a missing config
+pub fn describe_searched(missing: &Path) -> Vec<String> {
+    if explicit() {
+        return vec![missing.display().to_string()];
+    }
+    searched()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(path, source)| format!("{} ({})", path.display(), source))
+        .collect()
+}
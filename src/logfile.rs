@@ -0,0 +1,95 @@
+use crate::SvcError;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+// Delays between rename attempts while another process holds the log open
+const ROTATE_BACKOFF: [Duration; 3] = [
+    Duration::from_millis(50),
+    Duration::from_millis(200),
+    Duration::from_millis(500),
+];
+
+// Open a log for appending while letting readers, writers and renamers share it,
+// so a tail or another svc invocation never makes rotation fail outright
+pub fn open_shared(path: &Path) -> Result<File, SvcError> {
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        const FILE_SHARE_READ: u32 = 0x1;
+        const FILE_SHARE_WRITE: u32 = 0x2;
+        const FILE_SHARE_DELETE: u32 = 0x4;
+        options.share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE);
+    }
+
+    Ok(options.open(path)?)
+}
+
+pub fn rotated_path(path: &Path) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+// Move the live log to its `.1` generation and return a handle to a fresh one.
+// When the rename stays blocked, copy the content over and truncate in place instead.
+pub fn rotate(path: &Path, file: File) -> Result<File, SvcError> {
+    let rotated = rotated_path(path);
+    for delay in ROTATE_BACKOFF {
+        if fs::rename(path, &rotated).is_ok() {
+            return open_shared(path);
+        }
+        thread::sleep(delay);
+    }
+
+    fs::copy(path, &rotated)?;
+    file.set_len(0)?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    // svc rotating its log while a tail follows it: the rename goes through the reader's
+    // handle, which keeps the generation it opened, and nothing is copied or truncated
+    #[test]
+    fn a_log_rotates_under_a_reader_that_follows_it() {
+        let dir = std::env::temp_dir().join(format!("svc-logfile-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("api.log");
+        let mut file = open_shared(&path).unwrap();
+        let mut follower = File::open(&path).unwrap();
+
+        let reader = thread::spawn(move || {
+            let mut followed = String::new();
+            while !followed.ends_with("line 199\n") {
+                follower.read_to_string(&mut followed).unwrap();
+                thread::yield_now();
+            }
+            followed
+        });
+        for line in 0..1000 {
+            if line > 0 && line % 200 == 0 {
+                file = rotate(&path, file).unwrap();
+            }
+            file.write_all(format!("line {}\n", line).as_bytes()).unwrap();
+        }
+
+        let lines = |range: std::ops::Range<u32>| -> String { range.map(|line| format!("line {}\n", line)).collect() };
+        assert_eq!(reader.join().unwrap(), lines(0..200));
+        assert_eq!(fs::read_to_string(rotated_path(&path)).unwrap(), lines(600..800));
+        assert_eq!(fs::read_to_string(&path).unwrap(), lines(800..1000));
+        // A second writer appends to the generation it opened, as another svc invocation would
+        let mut other = open_shared(&path).unwrap();
+        rotate(&path, open_shared(&path).unwrap()).unwrap();
+        other.write_all(b"late\n").unwrap();
+        assert_eq!(fs::read_to_string(rotated_path(&path)).unwrap(), lines(800..1000) + "late\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,239 @@
+use crate::encoding::{self, Encoding};
+use crate::output::timestamp;
+use crate::{cancel, logfile, Service, SvcError};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// How often `svc logs -f` looks for new output
+const FOLLOW_POLL: Duration = Duration::from_millis(500);
+// Read backwards in blocks this size until enough lines are found
+const TAIL_BLOCK: u64 = 64 * 1024;
+
+// `log: true` for <exe dir>\logs\<name>.log, or the path of the file
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum LogSetting<'a> {
+    Default(bool),
+    Path(Cow<'a, str>),
+}
+
+// Where the output of an Executable goes, if anywhere
+pub fn path(service: &Service) -> Option<PathBuf> {
+    if let Some(log_file) = &service.log_file {
+        return Some(log_file.clone());
+    }
+    match service.log.as_ref()? {
+        LogSetting::Default(false) => None,
+        LogSetting::Default(true) => {
+            let dir = Path::new(service.path.as_ref()).parent().unwrap_or_else(|| Path::new("."));
+            Some(dir.join("logs").join(format!("{}.log", service.name)))
+        }
+        LogSetting::Path(path) => Some(PathBuf::from(path.as_ref())),
+    }
+}
+
+fn failed(path: &Path, err: impl ToString) -> SvcError {
+    SvcError::LogFileFailed {
+        path: path.display().to_string(),
+        reason: err.to_string(),
+    }
+}
+
+// The log opened for appending, with a header so launches can be told apart
+pub fn open(service: &Service) -> Result<Option<File>, SvcError> {
+    let Some(path) = path(service) else {
+        return Ok(None);
+    };
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|err| failed(&path, err))?;
+    }
+    let mut file = logfile::open_shared(&path).map_err(|err| failed(&path, err))?;
+    writeln!(file, "==== {} svc run {} ====", timestamp(), service.name).map_err(|err| failed(&path, err))?;
+    Ok(Some(file))
+}
+
+// A line between launches in an existing log, e.g. why the next launch happened; best effort
+pub fn note(service: &Service, message: &str) {
+    let Some(path) = path(service).filter(|path| path.exists()) else {
+        return;
+    };
+    if let Ok(mut file) = logfile::open_shared(&path) {
+        let _ = writeln!(file, "==== {} {} ====", timestamp(), message);
+    }
+}
+
+// Start of the last `lines` lines, reading only as much of the end of the file as needed
+fn tail_start(file: &mut File, len: u64, lines: usize) -> Result<u64, SvcError> {
+    let mut end = len;
+    let mut found = 0;
+    // A final newline ends the last line rather than starting an empty one
+    let mut skip_last = true;
+    while end > 0 {
+        let start = end.saturating_sub(TAIL_BLOCK);
+        let mut block = vec![0; (end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut block)?;
+        for (at, byte) in block.iter().enumerate().rev() {
+            if *byte != b'\n' {
+                skip_last = false;
+                continue;
+            }
+            if skip_last {
+                skip_last = false;
+                continue;
+            }
+            found += 1;
+            if found == lines {
+                return Ok(start + at as u64 + 1);
+            }
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+// The child writes the log itself, so it is in the service's encoding
+fn print_from(file: &mut File, from: u64, encoding: Option<Encoding>) -> Result<u64, SvcError> {
+    file.seek(SeekFrom::Start(from))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    print!("{}", encoding::decode(encoding, &bytes));
+    std::io::stdout().flush()?;
+    Ok(from + bytes.len() as u64)
+}
+
+// The last lines of the service's log, then with `follow` whatever it writes next until Ctrl+C
+pub fn show(service: &Service, lines: usize, follow: bool) -> Result<(), SvcError> {
+    let Some(path) = path(service) else {
+        return Err(SvcError::InvalidCommand(format!(
+            "logs for {}, which has no log configured",
+            service.name
+        )));
+    };
+    let mut file = File::open(&path).map_err(|err| failed(&path, err))?;
+    let len = file.metadata()?.len();
+    let start = match lines {
+        0 => len,
+        lines => tail_start(&mut file, len, lines)?,
+    };
+    let position = print_from(&mut file, start, service.encoding)?;
+    if !follow {
+        return Ok(());
+    }
+
+    let mut followed = Followed::new(file, position);
+    loop {
+        match cancel::sleep(FOLLOW_POLL) {
+            Ok(()) => {}
+            // Ctrl+C is how following ends
+            Err(SvcError::Interrupted) => return Ok(()),
+            Err(err) => return Err(err),
+        }
+        let bytes = followed.poll(&path)?;
+        if !bytes.is_empty() {
+            print!("{}", encoding::decode(service.encoding, &bytes));
+            std::io::stdout().flush()?;
+        }
+    }
+}
+
+// The log as `svc logs -f` follows it: the file it has open, and how far into it it has read
+struct Followed {
+    file: File,
+    id: Option<(u64, u64)>,
+    position: u64,
+}
+
+impl Followed {
+    fn new(file: File, position: u64) -> Self {
+        Followed {
+            id: file_id(&file),
+            file,
+            position,
+        }
+    }
+
+    // What was written since the last poll. Once the path is another file, as after a rotation
+    // renamed this one away, the rest of this one comes first and then the new one from the top,
+    // however long it has grown; a file cut shorter is read from the top again
+    fn poll(&mut self, path: &Path) -> Result<Vec<u8>, SvcError> {
+        let mut bytes = Vec::new();
+        if let Ok(current) = File::open(path) {
+            let id = file_id(&current);
+            if id.is_some() && id != self.id {
+                self.read_into(&mut bytes)?;
+                *self = Followed::new(current, 0);
+            } else if current.metadata()?.len() < self.position {
+                self.position = 0;
+            }
+        }
+        self.read_into(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn read_into(&mut self, bytes: &mut Vec<u8>) -> Result<(), SvcError> {
+        self.file.seek(SeekFrom::Start(self.position))?;
+        self.position += self.file.read_to_end(bytes)? as u64;
+        Ok(())
+    }
+}
+
+// Which file a handle has open: the volume and file index, which a rename keeps
+#[cfg(windows)]
+fn file_id(file: &File) -> Option<(u64, u64)> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Storage::FileSystem::{GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION};
+
+    let mut info: BY_HANDLE_FILE_INFORMATION = unsafe { std::mem::zeroed() };
+    (unsafe { GetFileInformationByHandle(file.as_raw_handle() as _, &mut info) } != 0).then(|| {
+        let index = (u64::from(info.nFileIndexHigh) << 32) | u64::from(info.nFileIndexLow);
+        (u64::from(info.dwVolumeSerialNumber), index)
+    })
+}
+
+#[cfg(unix)]
+fn file_id(file: &File) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    file.metadata().ok().map(|metadata| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(any(windows, unix)))]
+fn file_id(_file: &File) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn following_moves_to_the_new_file_after_a_rotation_however_long_it_grew() {
+        let dir = std::env::temp_dir().join(format!("svc-logs-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("api.log");
+        fs::write(&path, "first\n").unwrap();
+        let file = File::open(&path).unwrap();
+        let mut followed = Followed::new(file, 0);
+        assert_eq!(followed.poll(&path).unwrap(), b"first\n");
+
+        // The old file gets a last line, is renamed away, and the new one outgrows it before the poll
+        let mut old = logfile::open_shared(&path).unwrap();
+        old.write_all(b"last of the old\n").unwrap();
+        drop(old);
+        fs::rename(&path, logfile::rotated_path(&path)).unwrap();
+        fs::write(&path, "new 1\nnew 2, longer than all the old file held\n").unwrap();
+        let polled = String::from_utf8(followed.poll(&path).unwrap()).unwrap();
+        assert_eq!(polled, "last of the old\nnew 1\nnew 2, longer than all the old file held\n");
+        assert!(followed.poll(&path).unwrap().is_empty());
+
+        // Cut shorter in place: read from the top again
+        fs::write(&path, "short\n").unwrap();
+        assert_eq!(followed.poll(&path).unwrap(), b"short\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
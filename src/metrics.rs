@@ -0,0 +1,215 @@
+use crate::procinfo::{self, ProcessUsage};
+use crate::state::{self, state_dir};
+use crate::units::format_bytes;
+use crate::{diskbudget, output, warnings, Service, SvcError};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Enough for a day of samples even at one per minute; older lines are dropped on write
+const MAX_SAMPLES: usize = 1440;
+const DAY: u64 = 86_400;
+const SPARKLINE_WIDTH: usize = 48;
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+// The same levels for consoles without the block characters
+const ASCII_SPARKS: [char; 8] = ['_', '.', ':', '-', '=', '+', '*', '#'];
+
+// One line of services/<service>/metrics.jsonl, summed over the service's processes
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Sample {
+    pub time: u64,
+    pub rss: u64,
+    // Cumulative CPU time of the processes alive at sampling time
+    pub cpu_ms: u64,
+    // Relaunches by the watcher since the previous sample
+    pub restarts: u32,
+}
+
+// Memory and CPU of the sampled processes, taken once per sampling round and shared by all services
+pub struct Snapshot(HashMap<u64, (u64, u64)>);
+
+// One native pass over the given processes; those svc may not open are left out
+pub fn snapshot(pids: &[u64]) -> Snapshot {
+    Snapshot::from_usages(procinfo::sample(pids))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl Snapshot {
+    fn from_usages(usages: Vec<ProcessUsage>) -> Snapshot {
+        let processes = usages
+            .into_iter()
+            .filter_map(|usage| Some((usage.pid, (usage.memory.unwrap_or(0), usage.cpu_time_ms?))))
+            .collect();
+        Snapshot(processes)
+    }
+
+    // Cumulative CPU time of exactly these processes, None when one is missing from the snapshot
+    pub fn cpu_ms(&self, pids: &[u64]) -> Option<u64> {
+        pids.iter().map(|pid| self.0.get(pid).map(|(_, cpu)| *cpu)).sum()
+    }
+
+    pub fn sample(&self, pids: &[u64], restarts: u32) -> Sample {
+        let (rss, cpu_ms) = pids
+            .iter()
+            .filter_map(|pid| self.0.get(pid))
+            .fold((0, 0), |(rss, cpu), (r, c)| (rss + r, cpu + c));
+        Sample {
+            time: now(),
+            rss,
+            cpu_ms,
+            restarts,
+        }
+    }
+}
+
+// Where samples were kept, one file per service, before each service had its own directory
+pub fn metrics_dir() -> Result<PathBuf, SvcError> {
+    Ok(state_dir()?.join("metrics"))
+}
+
+// Samples left in the old place move over the first time they're needed
+fn metrics_path(service: &str) -> Result<PathBuf, SvcError> {
+    let path = state::service_dir(service)?.join("metrics.jsonl");
+    let legacy = metrics_dir()?.join(format!("{}.jsonl", service));
+    if !path.exists() && legacy.exists() {
+        fs::rename(&legacy, &path)?;
+    }
+    Ok(path)
+}
+
+fn read_lines(path: &std::path::Path) -> Result<String, SvcError> {
+    Ok(state::read(path, |content| Ok(content.to_string()))?.unwrap_or_default())
+}
+
+fn read_samples(service: &str) -> Result<Vec<Sample>, SvcError> {
+    let content = read_lines(&metrics_path(service)?)?;
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+// Append a sample, keeping the file at MAX_SAMPLES lines
+pub fn record(service: &str, sample: &Sample) -> Result<(), SvcError> {
+    let path = metrics_path(service)?;
+    let _lock = state::lock(&path)?;
+    let content = read_lines(&path)?;
+    let mut lines: Vec<&str> = content.lines().collect();
+    if lines.len() >= MAX_SAMPLES {
+        lines.drain(..=lines.len() - MAX_SAMPLES);
+    }
+
+    let line = serde_json::to_string(sample).map_err(std::io::Error::other)?;
+    lines.push(&line);
+    state::replace(&path, &(lines.join("\n") + "\n"))
+}
+
+// A service's sample of a round, as watch and serve take them: a failed write is only a
+// warning, and the service's files are kept to its disk budget afterwards
+pub fn keep(service: &Service, sample: &Sample) {
+    if let Err(err) = record(&service.name, sample) {
+        warnings::warn("metrics_failed", &service.title(), format!("failed to record metrics: {}", err));
+    }
+    diskbudget::enforce(service);
+}
+
+// Only for a person at a console; output::unicode picks the characters it can show
+fn supports_sparkline() -> bool {
+    std::io::stdout().is_terminal()
+}
+
+fn sparkline(values: &[u64]) -> String {
+    let chunk = values.len().div_ceil(SPARKLINE_WIDTH).max(1);
+    let points: Vec<u64> = values
+        .chunks(chunk)
+        .map(|chunk| chunk.iter().copied().max().unwrap_or(0))
+        .collect();
+    let sparks = if output::unicode() { SPARKS } else { ASCII_SPARKS };
+    let (min, max) = (
+        points.iter().copied().min().unwrap_or(0),
+        points.iter().copied().max().unwrap_or(0),
+    );
+    points
+        .iter()
+        .map(|&value| {
+            let level = if max == min {
+                0
+            } else {
+                ((value - min) * (sparks.len() as u64 - 1) / (max - min)) as usize
+            };
+            sparks[level]
+        })
+        .collect()
+}
+
+// Summary of the last day of samples for `svc status --trend`
+pub fn print_trend(service: &str) -> Result<(), SvcError> {
+    let since = now().saturating_sub(DAY);
+    let samples: Vec<Sample> = read_samples(service)?
+        .into_iter()
+        .filter(|sample| sample.time >= since)
+        .collect();
+    let Some(current) = samples.last() else {
+        println!("Trend: {}", "no samples in the last 24h, they are taken by 'svc watch'".yellow());
+        return Ok(());
+    };
+
+    let memory: Vec<u64> = samples.iter().map(|sample| sample.rss).collect();
+    println!(
+        "Memory (24h): min {}, max {}, current {}",
+        format_bytes(memory.iter().copied().min().unwrap_or(0)).cyan(),
+        format_bytes(memory.iter().copied().max().unwrap_or(0)).cyan(),
+        format_bytes(current.rss).cyan()
+    );
+
+    // CPU usage between the last two samples, unless the service restarted in between
+    if let [.., previous, current] = samples.as_slice() {
+        if current.cpu_ms >= previous.cpu_ms && current.time > previous.time {
+            let percent = (current.cpu_ms - previous.cpu_ms) as f64 / ((current.time - previous.time) * 10) as f64;
+            println!("CPU: {}", format!("{:.1}%", percent).cyan());
+        }
+    }
+
+    let restarts: u32 = samples.iter().map(|sample| sample.restarts).sum();
+    println!("Restarts (24h): {}", restarts.to_string().cyan());
+    if supports_sparkline() {
+        println!("Memory trend: {}", sparkline(&memory).green());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(pid: u64, memory: Option<u64>, cpu_time_ms: Option<u64>) -> ProcessUsage {
+        ProcessUsage {
+            memory,
+            cpu_time_ms,
+            ..ProcessUsage::unknown(pid)
+        }
+    }
+
+    #[test]
+    fn a_snapshot_leaves_out_processes_without_cpu_time() {
+        let snapshot = Snapshot::from_usages(vec![
+            usage(1, Some(4096), Some(30)),
+            usage(2, None, Some(20)),
+            usage(3, Some(1024), None),
+        ]);
+        assert_eq!(snapshot.cpu_ms(&[1, 2]), Some(50));
+        assert_eq!(snapshot.cpu_ms(&[1, 3]), None);
+        let sample = snapshot.sample(&[1, 2, 3], 2);
+        assert_eq!((sample.rss, sample.cpu_ms, sample.restarts), (4096, 50, 2));
+    }
+}
@@ -0,0 +1,282 @@
+use crate::effects::{self, Effect};
+use crate::format::{sibling, to_canonical, write_atomic};
+use crate::locate::{self, Source};
+use crate::state::state_dir;
+use crate::{output, SvcError};
+use colored::Colorize;
+use serde::Serialize;
+use serde_yaml::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Older installs keep services.yaml next to svc.exe; the per-user one in %APPDATA%\svc is
+// searched first. `svc config migrate` moves the old file over, and until then svc says which
+// of the two it uses
+
+const MIGRATED: &str = ".migrated";
+// Holds the legacy path the notice was last shown for, so it is shown once per file
+const NOTICE_FILE: &str = "migrate-notice";
+
+// The legacy config and the per-user one
+fn paths(command: &str) -> Result<(PathBuf, PathBuf), SvcError> {
+    let user = locate::appdata_path()
+        .ok_or_else(|| SvcError::InvalidCommand(format!(r"{} without %APPDATA%", command)))?;
+    Ok((locate::exe_path()?, user))
+}
+
+// A config's services by name, plus its settings, each in canonical form: what two configs are
+// compared by, leaving comments, key order and quoting out of it
+#[derive(Debug, PartialEq, Eq)]
+struct Normalized {
+    settings: String,
+    services: Vec<(String, String)>,
+}
+
+fn normalize(content: &str) -> Result<Normalized, SvcError> {
+    let document: Value = serde_yaml::from_str(content)?;
+    let (mut settings, services) = match document {
+        Value::Mapping(mut mapping) => {
+            let services = mapping.remove("services").unwrap_or(Value::Null);
+            (Value::Mapping(mapping), services)
+        }
+        other => (Value::Null, other),
+    };
+    if settings.as_mapping().is_some_and(|mapping| mapping.is_empty()) {
+        settings = Value::Null;
+    }
+    let services = match services {
+        Value::Sequence(services) => services,
+        _ => Vec::new(),
+    };
+    Ok(Normalized {
+        settings: to_canonical(&settings)?,
+        services: services
+            .iter()
+            .enumerate()
+            .map(|(at, service)| {
+                let name = service.get("name").and_then(Value::as_str);
+                let name = name.map_or_else(|| format!("#{}", at + 1), String::from);
+                Ok((name, to_canonical(service)?))
+            })
+            .collect::<Result<_, SvcError>>()?,
+    })
+}
+
+fn read_normalized(path: &Path) -> Result<Normalized, SvcError> {
+    normalize(&fs::read_to_string(path)?)
+}
+
+// Before a command: with only the legacy config, a notice naming both paths, once, unless
+// SVC_NO_MIGRATE_NOTICE is set; with both and different content, which one is in use
+pub fn check() {
+    let (Ok((path, source)), Ok((legacy, user))) = (locate::config(), paths("config")) else {
+        return;
+    };
+    if output::options().quiet {
+        return;
+    }
+    match source {
+        Source::ExeDir if !user.exists() => notice(&legacy, &user),
+        Source::AppData if *path == user && legacy.is_file() => {
+            let same = matches!((read_normalized(&user), read_normalized(&legacy)), (Ok(a), Ok(b)) if a == b);
+            if !same {
+                eprintln!(
+                    "{} using {}; {} next to svc.exe differs from it ('svc config diff' compares them)",
+                    "warning:".yellow().bold(),
+                    user.display(),
+                    legacy.display()
+                );
+            }
+        }
+        _ => {}
+    }
+}
+
+fn notice(legacy: &Path, user: &Path) {
+    if std::env::var("SVC_NO_MIGRATE_NOTICE").is_ok_and(|value| !matches!(value.trim(), "" | "0")) {
+        return;
+    }
+    let Ok(marker) = state_dir().map(|dir| dir.join(NOTICE_FILE)) else {
+        return;
+    };
+    let shown = legacy.display().to_string();
+    if fs::read_to_string(&marker).is_ok_and(|content| content.trim() == shown) {
+        return;
+    }
+    eprintln!(
+        "{} the config is {}, next to svc.exe; 'svc config migrate' moves it to {} \
+         (SVC_NO_MIGRATE_NOTICE=1 hides this)",
+        "note:".cyan().bold(),
+        legacy.display(),
+        user.display()
+    );
+    if !effects::dry_run() {
+        let _ = fs::write(marker, shown);
+    }
+}
+
+// `svc config migrate`: copy the legacy config to the per-user location, check the copy reads
+// back as the same entries, then rename the legacy file so only the new one is found
+pub fn migrate() -> Result<(), SvcError> {
+    let (legacy, user) = paths("config migrate")?;
+    if !legacy.is_file() {
+        return Err(SvcError::InvalidCommand(format!(
+            "config migrate, there is no {} to migrate",
+            legacy.display()
+        )));
+    }
+    let content = fs::read_to_string(&legacy)?;
+    let expected = normalize(&content)?;
+    if user.exists() {
+        if read_normalized(&user)? != expected {
+            return Err(SvcError::InvalidCommand(format!(
+                "config migrate, {} already exists with other entries; 'svc config diff' compares the two",
+                user.display()
+            )));
+        }
+        println!("{} already has the same entries.", user.display().to_string().cyan());
+    } else {
+        if let Some(dir) = user.parent().filter(|_| !effects::dry_run()) {
+            fs::create_dir_all(dir)?;
+        }
+        write_atomic(&user, &content)?;
+        if !effects::dry_run() && read_normalized(&user).ok().as_ref() != Some(&expected) {
+            effects::delete(&user)?;
+            return Err(SvcError::InvalidCommand(format!(
+                "config migrate, the copy at {} didn't read back the same; {} is left as it was",
+                user.display(),
+                legacy.display()
+            )));
+        }
+        println!("Copied {} to {}.", legacy.display(), user.display().to_string().cyan());
+    }
+    let migrated = sibling(&legacy, MIGRATED);
+    let effect = Effect::WriteFile {
+        path: migrated.display().to_string(),
+    };
+    effects::perform(effect, || Ok(fs::rename(&legacy, &migrated)?))?;
+    println!("Renamed the old one to {}; svc now finds {}.", migrated.display(), user.display());
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum State {
+    Same,
+    Differs,
+    // In the per-user config only
+    Added,
+    // Next to svc.exe only
+    Removed,
+}
+
+#[derive(Serialize)]
+struct Compared {
+    name: String,
+    state: State,
+}
+
+fn compare(user: &Normalized, legacy: &Normalized) -> Vec<Compared> {
+    let mut compared: Vec<Compared> = user
+        .services
+        .iter()
+        .map(|(name, entry)| Compared {
+            name: name.clone(),
+            state: match legacy.services.iter().find(|(other, _)| other == name) {
+                None => State::Added,
+                Some((_, other)) if other == entry => State::Same,
+                Some(_) => State::Differs,
+            },
+        })
+        .collect();
+    compared.extend(
+        legacy
+            .services
+            .iter()
+            .filter(|(name, _)| !user.services.iter().any(|(other, _)| other == name))
+            .map(|(name, _)| Compared {
+                name: name.clone(),
+                state: State::Removed,
+            }),
+    );
+    compared
+}
+
+// `svc config diff`: the per-user config's entries against those of the one next to svc.exe
+pub fn diff() -> Result<(), SvcError> {
+    let (legacy, user) = paths("config diff")?;
+    for path in [&user, &legacy] {
+        if !path.is_file() {
+            return Err(SvcError::InvalidCommand(format!("config diff, there is no {}", path.display())));
+        }
+    }
+    let (user_entries, legacy_entries) = (read_normalized(&user)?, read_normalized(&legacy)?);
+    let compared = compare(&user_entries, &legacy_entries);
+    let settings_differ = user_entries.settings != legacy_entries.settings;
+
+    if output::options().json {
+        let document = serde_json::json!({
+            "user": user.display().to_string(),
+            "legacy": legacy.display().to_string(),
+            "settings_differ": settings_differ,
+            "services": compared,
+        });
+        println!("{}", serde_json::to_string_pretty(&document).map_err(std::io::Error::other)?);
+        return Ok(());
+    }
+    println!("{} against {}:", user.display().to_string().cyan(), legacy.display());
+    if settings_differ {
+        println!("{} settings", "~".yellow().bold());
+    }
+    let width = compared.iter().map(|entry| output::width(&entry.name)).max().unwrap_or(0);
+    for entry in &compared {
+        let name = output::pad(&entry.name, width);
+        match entry.state {
+            State::Same => println!("{} {}", "=".green(), name),
+            State::Differs => println!("{} {}  differs", "~".yellow().bold(), name.yellow()),
+            State::Added => println!("{} {}  only in the per-user config", "+".green().bold(), name.green()),
+            State::Removed => println!("{} {}  only next to svc.exe", "-".red().bold(), name.red()),
+        }
+    }
+    if !settings_differ && compared.iter().all(|entry| entry.state == State::Same) {
+        println!("Both have the same entries.");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configs_compare_by_entries_rather_than_by_text() {
+        let legacy = "services:
+  - name: api   # the web API
+    type: Executable
+    path: D:\\api.exe
+  - { name: backup, type: Util, path: backup.py }
+";
+        let user = "services:
+- path: 'D:\\api.exe'
+  name: api
+  type: Executable
+- name: backup
+  type: Util
+  path: backup.py
+";
+        assert_eq!(normalize(legacy).unwrap(), normalize(user).unwrap());
+        // The bare list form is the same config without settings
+        assert_eq!(normalize(user.trim_start_matches("services:\n")).unwrap(), normalize(user).unwrap());
+
+        let changed = "startup_stagger: 5s
+services:
+  - { name: api, type: Executable, path: D:\\api2.exe }
+  - { name: db, type: Executable, path: D:\\db.exe }
+";
+        let (changed, legacy) = (normalize(changed).unwrap(), normalize(legacy).unwrap());
+        assert_ne!(changed.settings, legacy.settings);
+        let compared = compare(&changed, &legacy);
+        let states: Vec<(&str, State)> = compared.iter().map(|entry| (entry.name.as_str(), entry.state)).collect();
+        assert_eq!(states, [("api", State::Differs), ("db", State::Added), ("backup", State::Removed)]);
+    }
+}
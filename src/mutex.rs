@@ -0,0 +1,145 @@
+use crate::SvcError;
+
+#[cfg(windows)]
+const LOCK_WAIT: std::time::Duration = std::time::Duration::from_secs(10);
+#[cfg(windows)]
+const POLL_MS: u32 = 100;
+
+// Held across a check-then-act window on one service so concurrent svc invocations
+// take turns; released when dropped, and by Windows if the holder crashes
+pub struct ServiceLock {
+    #[cfg(windows)]
+    handle: windows_sys::Win32::Foundation::HANDLE,
+}
+
+#[cfg(windows)]
+impl Drop for ServiceLock {
+    fn drop(&mut self) {
+        use windows_sys::Win32::Foundation::CloseHandle;
+        use windows_sys::Win32::System::Threading::ReleaseMutex;
+        unsafe {
+            ReleaseMutex(self.handle);
+            CloseHandle(self.handle);
+        }
+    }
+}
+
+// The locks svc takes, as (scope, name) pairs: per service, then the shared ones
+pub const SERVICE_SCOPES: [&str; 2] = ["run", "startup"];
+pub const SHARED: [(&str, &str); 2] = [("adhoc", "registry"), ("spawned", "file")];
+
+// `scope` names the kind of operation, e.g. "run" takes Local\svc-run-<name>
+pub fn object_name(scope: &str, service: &str) -> String {
+    // Backslashes are reserved in object names
+    format!(r"Local\svc-{}-{}", scope, service.replace('\\', "_"))
+}
+
+#[cfg(windows)]
+fn wide(name: &str) -> Vec<u16> {
+    use std::os::windows::ffi::OsStrExt;
+    std::ffi::OsStr::new(name).encode_wide().chain(Some(0)).collect()
+}
+
+#[cfg(windows)]
+pub fn lock(scope: &str, service: &str) -> Result<ServiceLock, SvcError> {
+    use crate::{cancel, warnings};
+    use std::time::Instant;
+    use windows_sys::Win32::Foundation::{CloseHandle, WAIT_ABANDONED, WAIT_OBJECT_0, WAIT_TIMEOUT};
+    use windows_sys::Win32::System::Threading::{CreateMutexW, WaitForSingleObject};
+
+    let name = object_name(scope, service);
+    let handle = unsafe { CreateMutexW(std::ptr::null(), 0, wide(&name).as_ptr()) };
+    if handle.is_null() {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    let started = Instant::now();
+    loop {
+        match unsafe { WaitForSingleObject(handle, POLL_MS) } {
+            WAIT_OBJECT_0 => return Ok(ServiceLock { handle }),
+            // Its holder died mid-operation; we own it now, but what it was doing may be half done
+            WAIT_ABANDONED => {
+                warnings::warn(
+                    "abandoned_lock",
+                    service,
+                    format!("took over {}, which an svc that exited left held", name),
+                );
+                return Ok(ServiceLock { handle });
+            }
+            WAIT_TIMEOUT if started.elapsed() < LOCK_WAIT && !cancel::is_cancelled() => {}
+            WAIT_TIMEOUT => {
+                unsafe { CloseHandle(handle) };
+                cancel::check()?;
+                return Err(SvcError::ServiceBusy(service.to_string()));
+            }
+            _ => {
+                let err = std::io::Error::last_os_error();
+                unsafe { CloseHandle(handle) };
+                return Err(err.into());
+            }
+        }
+    }
+}
+
+// Named mutexes are a Windows facility; elsewhere invocations simply don't serialize
+#[cfg(not(windows))]
+pub fn lock(_scope: &str, _service: &str) -> Result<ServiceLock, SvcError> {
+    Ok(ServiceLock {})
+}
+
+// What another svc left of a lock; only Windows has named mutexes to leave
+#[cfg_attr(not(windows), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Leftover {
+    // Its holder exited holding it; taken and let go here, so it is free again
+    Abandoned,
+    // A live svc has it
+    Held,
+}
+
+// None when nobody has the lock open or it is free
+#[cfg(windows)]
+pub fn probe(scope: &str, service: &str) -> Result<Option<Leftover>, SvcError> {
+    use windows_sys::Win32::Foundation::{CloseHandle, WAIT_ABANDONED, WAIT_OBJECT_0, WAIT_TIMEOUT};
+    use windows_sys::Win32::System::Threading::{OpenMutexW, ReleaseMutex, WaitForSingleObject};
+    use windows_sys::Win32::System::Threading::{MUTEX_MODIFY_STATE, SYNCHRONIZATION_SYNCHRONIZE};
+
+    let access = SYNCHRONIZATION_SYNCHRONIZE | MUTEX_MODIFY_STATE;
+    let handle = unsafe { OpenMutexW(access, 0, wide(&object_name(scope, service)).as_ptr()) };
+    if handle.is_null() {
+        return Ok(None);
+    }
+    let leftover = match unsafe { WaitForSingleObject(handle, 0) } {
+        WAIT_OBJECT_0 => {
+            unsafe { ReleaseMutex(handle) };
+            Ok(None)
+        }
+        WAIT_ABANDONED => {
+            unsafe { ReleaseMutex(handle) };
+            Ok(Some(Leftover::Abandoned))
+        }
+        WAIT_TIMEOUT => Ok(Some(Leftover::Held)),
+        _ => Err(std::io::Error::last_os_error().into()),
+    };
+    unsafe { CloseHandle(handle) };
+    leftover
+}
+
+#[cfg(not(windows))]
+pub fn probe(_scope: &str, _service: &str) -> Result<Option<Leftover>, SvcError> {
+    Ok(None)
+}
+
+#[cfg(all(test, windows))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_lock_whose_holder_exited_is_taken_over() {
+        // A thread that exits without releasing leaves the mutex abandoned, as a crashed svc would
+        std::thread::spawn(|| std::mem::forget(lock("test", "abandoned").unwrap())).join().unwrap();
+        assert_eq!(probe("test", "abandoned").unwrap(), Some(Leftover::Abandoned));
+        assert_eq!(probe("test", "abandoned").unwrap(), None);
+        drop(lock("test", "abandoned").unwrap());
+    }
+}
@@ -0,0 +1,408 @@
+use crate::flavor::{self, Feature};
+use crate::runas::ps_quote;
+use crate::{capture, output, secrets, SvcError};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+static DISPATCHER: OnceLock<Dispatcher> = OnceLock::new();
+
+// AppUserModelID of Windows PowerShell, which every desktop already has registered for toasts
+const TOAST_APP_ID: &str = r"{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\WindowsPowerShell\v1.0\powershell.exe";
+
+// Something that happened to a service, as sinks receive it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SvcEvent {
+    Start { service: String },
+    Stop { service: String },
+    Restart { service: String },
+    // `svc watch` relaunched it this many times within the window
+    CrashLoop { service: String, restarts: usize, within: String },
+    Enable { service: String },
+    Disable { service: String },
+    Failure { service: String, action: String, error: String },
+}
+
+// The event names a sink's `events` filter lists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Start,
+    Stop,
+    Restart,
+    CrashLoop,
+    Enable,
+    Disable,
+    Failure,
+}
+
+impl SvcEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            SvcEvent::Start { .. } => EventKind::Start,
+            SvcEvent::Stop { .. } => EventKind::Stop,
+            SvcEvent::Restart { .. } => EventKind::Restart,
+            SvcEvent::CrashLoop { .. } => EventKind::CrashLoop,
+            SvcEvent::Enable { .. } => EventKind::Enable,
+            SvcEvent::Disable { .. } => EventKind::Disable,
+            SvcEvent::Failure { .. } => EventKind::Failure,
+        }
+    }
+
+    // One line for humans, used as toast text, mail subject and event log message
+    pub fn message(&self) -> String {
+        match self {
+            SvcEvent::Start { service } => format!("{} started", service),
+            SvcEvent::Stop { service } => format!("{} stopped", service),
+            SvcEvent::Restart { service } => format!("{} restarted", service),
+            SvcEvent::CrashLoop { service, restarts, within } => {
+                format!("{} is crash-looping: restarted {} times within {}", service, restarts, within)
+            }
+            SvcEvent::Enable { service } => format!("{} enabled at start-up", service),
+            SvcEvent::Disable { service } => format!("{} disabled at start-up", service),
+            SvcEvent::Failure { service, action, error } => format!("{} of {} failed: {}", action, service, error),
+        }
+    }
+
+    fn is_problem(&self) -> bool {
+        matches!(self, SvcEvent::CrashLoop { .. } | SvcEvent::Failure { .. })
+    }
+}
+
+pub trait Notifier {
+    // Shown when delivery fails, e.g. "webhook https://..."
+    fn name(&self) -> String;
+    fn notify(&self, event: &SvcEvent) -> Result<(), SvcError>;
+}
+
+// Run a PowerShell script fed over stdin, so nothing sensitive lands on a command line
+fn powershell(script: &str) -> Result<(), SvcError> {
+    let mut child = Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(format!("$ErrorActionPreference = 'Stop'\n{}\n", script).as_bytes())?;
+    }
+    let output = capture::wait_output(child)?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<String> = stderr.lines().filter(|line| !line.trim().is_empty()).map(String::from).collect();
+    Err(SvcError::HelperFailed {
+        command: "powershell".to_string(),
+        reason: lines.first().map_or_else(|| output.status.to_string(), |line| line.trim().to_string()),
+        output: lines,
+    })
+}
+
+// POSTs the event as JSON
+#[derive(Debug, Deserialize)]
+pub struct Webhook {
+    pub url: String,
+}
+
+impl Notifier for Webhook {
+    fn name(&self) -> String {
+        format!("webhook {}", self.url)
+    }
+
+    fn notify(&self, event: &SvcEvent) -> Result<(), SvcError> {
+        let body = serde_json::to_string(event).map_err(std::io::Error::other)?;
+        powershell(&format!(
+            "Invoke-RestMethod -Method Post -Uri {} -ContentType 'application/json' -Body {} | Out-Null",
+            ps_quote(&self.url),
+            ps_quote(&body)
+        ))
+    }
+}
+
+// A desktop notification for the logged-on user
+#[derive(Debug, Deserialize)]
+pub struct Toast {}
+
+impl Notifier for Toast {
+    fn name(&self) -> String {
+        "toast".to_string()
+    }
+
+    fn notify(&self, event: &SvcEvent) -> Result<(), SvcError> {
+        powershell(&format!(
+            "[void][Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime]\n\
+             $xml = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02)\n\
+             $text = $xml.GetElementsByTagName('text')\n\
+             [void]$text.Item(0).AppendChild($xml.CreateTextNode('svc'))\n\
+             [void]$text.Item(1).AppendChild($xml.CreateTextNode({}))\n\
+             $toast = [Windows.UI.Notifications.ToastNotification]::new($xml)\n\
+             [Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier({}).Show($toast)",
+            ps_quote(&event.message()),
+            ps_quote(TOAST_APP_ID)
+        ))
+    }
+}
+
+// An Application log entry through eventcreate; creating the source the first time
+// needs administrator rights
+#[derive(Debug, Deserialize)]
+pub struct EventLog {
+    #[serde(default = "default_source")]
+    pub source: String,
+}
+
+fn default_source() -> String {
+    "svc".to_string()
+}
+
+// eventcreate takes IDs from 1 to 1000; one per kind of event so they can be filtered on
+fn event_id(kind: EventKind) -> u32 {
+    match kind {
+        EventKind::Start => 100,
+        EventKind::Stop => 101,
+        EventKind::Restart => 102,
+        EventKind::CrashLoop => 103,
+        EventKind::Enable => 104,
+        EventKind::Disable => 105,
+        EventKind::Failure => 106,
+    }
+}
+
+impl Notifier for EventLog {
+    fn name(&self) -> String {
+        format!("event log source {}", self.source)
+    }
+
+    fn notify(&self, event: &SvcEvent) -> Result<(), SvcError> {
+        let level = if event.is_problem() { "ERROR" } else { "INFORMATION" };
+        capture::run_helper(Command::new("eventcreate").args([
+            "/L",
+            "APPLICATION",
+            "/T",
+            level,
+            "/SO",
+            &self.source,
+            "/ID",
+            &event_id(event.kind()).to_string(),
+            "/D",
+            &event.message(),
+        ]))
+    }
+}
+
+// Mail through an SMTP relay; the password, if the relay wants one, comes from the secret store
+#[derive(Debug, Deserialize)]
+pub struct Email {
+    pub smtp_server: String,
+    #[serde(default = "default_smtp_port")]
+    pub port: u16,
+    pub from: String,
+    pub to: Vec<String>,
+    pub user: Option<String>,
+    pub password_secret: Option<String>,
+    #[serde(default)]
+    pub tls: bool,
+}
+
+fn default_smtp_port() -> u16 {
+    25
+}
+
+impl Notifier for Email {
+    fn name(&self) -> String {
+        format!("email via {}", self.smtp_server)
+    }
+
+    fn notify(&self, event: &SvcEvent) -> Result<(), SvcError> {
+        let to: Vec<String> = self.to.iter().map(|to| ps_quote(to)).collect();
+        let body = serde_json::to_string_pretty(event).map_err(std::io::Error::other)?;
+        let mut script = String::new();
+        let mut send = format!(
+            "Send-MailMessage -SmtpServer {} -Port {} -From {} -To @({}) -Subject {} -Body {}",
+            ps_quote(&self.smtp_server),
+            self.port,
+            ps_quote(&self.from),
+            to.join(", "),
+            ps_quote(&format!("svc: {}", event.message())),
+            ps_quote(&body)
+        );
+        if self.tls {
+            send.push_str(" -UseSsl");
+        }
+        if let (Some(user), Some(secret)) = (&self.user, &self.password_secret) {
+            script.push_str(&format!(
+                "$password = ConvertTo-SecureString {}\n\
+                 $credential = New-Object System.Management.Automation.PSCredential({}, $password)\n",
+                ps_quote(&secrets::read_blob(secret)?),
+                ps_quote(user)
+            ));
+            send.push_str(" -Credential $credential");
+        }
+        script.push_str(&send);
+        powershell(&script)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Sink {
+    Webhook(Webhook),
+    Toast(Toast),
+    EventLog(EventLog),
+    Email(Email),
+}
+
+// One `notify` entry of the settings
+#[derive(Debug, Deserialize)]
+pub struct SinkConfig {
+    // Every event when empty
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+    #[serde(flatten)]
+    pub sink: Sink,
+}
+
+struct Route {
+    events: Vec<EventKind>,
+    notifier: Box<dyn Notifier + Send + Sync>,
+}
+
+#[derive(Default)]
+pub struct Dispatcher {
+    routes: Vec<Route>,
+}
+
+impl Dispatcher {
+    pub fn new(sinks: Vec<SinkConfig>) -> Self {
+        let mut dispatcher = Dispatcher::default();
+        for config in sinks {
+            let notifier: Box<dyn Notifier + Send + Sync> = match config.sink {
+                Sink::Webhook(sink) => Box::new(sink),
+                // Skipped where there is nothing to show them; `svc doctor` says so
+                Sink::Toast(_) if flavor::missing(Feature::Toasts).is_some() => continue,
+                Sink::Toast(sink) => Box::new(sink),
+                Sink::EventLog(sink) => Box::new(sink),
+                Sink::Email(sink) => Box::new(sink),
+            };
+            dispatcher.add(config.events, notifier);
+        }
+        dispatcher
+    }
+
+    pub fn add(&mut self, events: Vec<EventKind>, notifier: Box<dyn Notifier + Send + Sync>) {
+        self.routes.push(Route { events, notifier });
+    }
+
+    // Every sink that wants the event gets it; one failing doesn't keep it from the others,
+    // nor fails the command that raised it
+    pub fn dispatch(&self, event: &SvcEvent) {
+        let kind = event.kind();
+        for route in &self.routes {
+            if !route.events.is_empty() && !route.events.contains(&kind) {
+                continue;
+            }
+            if let Err(err) = route.notifier.notify(event) {
+                if !output::options().quiet {
+                    eprintln!(
+                        "{} notification via {} failed: {}",
+                        "warning:".yellow().bold(),
+                        route.notifier.name(),
+                        err
+                    );
+                }
+            }
+        }
+    }
+}
+
+pub fn init(sinks: Vec<SinkConfig>) {
+    let _ = DISPATCHER.set(Dispatcher::new(sinks));
+}
+
+pub fn emit(event: SvcEvent) {
+    if let Some(dispatcher) = DISPATCHER.get() {
+        dispatcher.dispatch(&event);
+    }
+}
+
+// The event for a command's outcome: the given one on success, a failure otherwise
+pub fn outcome(event: SvcEvent, action: &str, result: &Result<(), SvcError>) {
+    let service = match &event {
+        SvcEvent::Start { service }
+        | SvcEvent::Stop { service }
+        | SvcEvent::Restart { service }
+        | SvcEvent::CrashLoop { service, .. }
+        | SvcEvent::Enable { service }
+        | SvcEvent::Disable { service }
+        | SvcEvent::Failure { service, .. } => service.clone(),
+    };
+    match result {
+        Ok(()) => emit(event),
+        Err(err) => emit(SvcEvent::Failure {
+            service,
+            action: action.to_string(),
+            error: err.to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    // Keeps what it was sent, or fails every delivery
+    struct Recorder {
+        received: Arc<Mutex<Vec<SvcEvent>>>,
+        fails: bool,
+    }
+
+    impl Notifier for Recorder {
+        fn name(&self) -> String {
+            "recorder".to_string()
+        }
+
+        fn notify(&self, event: &SvcEvent) -> Result<(), SvcError> {
+            self.received.lock().unwrap().push(event.clone());
+            if self.fails {
+                return Err(SvcError::InvalidCommand("unreachable".to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    fn recorder(dispatcher: &mut Dispatcher, events: Vec<EventKind>, fails: bool) -> Arc<Mutex<Vec<SvcEvent>>> {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let notifier = Recorder {
+            received: received.clone(),
+            fails,
+        };
+        dispatcher.add(events, Box::new(notifier));
+        received
+    }
+
+    #[test]
+    fn each_sink_gets_the_events_it_asked_for_even_when_another_fails() {
+        let mut dispatcher = Dispatcher::default();
+        let failing = recorder(&mut dispatcher, Vec::new(), true);
+        let everything = recorder(&mut dispatcher, Vec::new(), false);
+        let problems = recorder(&mut dispatcher, vec![EventKind::CrashLoop, EventKind::Failure], false);
+
+        let start = SvcEvent::Start { service: "api".to_string() };
+        let failure = SvcEvent::Failure {
+            service: "api".to_string(),
+            action: "stop".to_string(),
+            error: "access denied".to_string(),
+        };
+        dispatcher.dispatch(&start);
+        dispatcher.dispatch(&failure);
+
+        assert_eq!(*failing.lock().unwrap(), [start.clone(), failure.clone()]);
+        assert_eq!(*everything.lock().unwrap(), [start, failure.clone()]);
+        assert_eq!(*problems.lock().unwrap(), [failure]);
+    }
+}
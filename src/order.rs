@@ -0,0 +1,142 @@
+use crate::cli::OrderKey;
+use crate::deps::State;
+use crate::Service;
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+// Output about several services comes in one order: the config's, so captured output diffs
+// cleanly from run to run. --sort name or --sort state reorders it, with ties left in config
+// order. Work done in parallel and lookups through maps have no order of their own, so what
+// they produce is put back into this one before it is shown
+
+// Where each service is declared
+pub struct Order<'c> {
+    positions: HashMap<&'c str, usize>,
+}
+
+impl<'c> Order<'c> {
+    pub fn of(services: &'c [Service]) -> Self {
+        Order {
+            positions: services.iter().enumerate().map(|(at, service)| (service.name.as_ref(), at)).collect(),
+        }
+    }
+
+    // Names the config doesn't declare, like adhoc services, come after those it does
+    fn position(&self, name: &str) -> usize {
+        self.positions.get(name).copied().unwrap_or(usize::MAX)
+    }
+
+    // Into config order, then by `key` when one is given
+    pub fn arrange<T>(
+        &self,
+        items: &mut [T],
+        key: Option<OrderKey>,
+        name: impl Fn(&T) -> &str,
+        state: impl Fn(&T) -> State,
+    ) {
+        items.sort_by(|a, b| {
+            let by_key = match key {
+                None => Ordering::Equal,
+                Some(OrderKey::Name) => name(a).cmp(name(b)),
+                Some(OrderKey::State) => rank(state(a)).cmp(&rank(state(b))),
+            };
+            by_key
+                .then_with(|| self.position(name(a)).cmp(&self.position(name(b))))
+                .then_with(|| name(a).cmp(name(b)))
+        });
+    }
+}
+
+// Running first, then degraded, then down
+fn rank(state: State) -> u8 {
+    match state {
+        State::Running => 0,
+        State::Degraded => 1,
+        State::Down => 2,
+    }
+}
+
+// `f` of each item in parallel, the results in the items' order whichever finished first
+pub fn par_map<'i, T: Sync, R: Send>(items: &'i [T], f: impl Fn(&'i T) -> R + Sync) -> Vec<R> {
+    let finished = items
+        .par_iter()
+        .enumerate()
+        .map(|(at, item)| (at, f(item)))
+        .fold(Vec::new, |mut done, result| {
+            done.push(result);
+            done
+        })
+        .reduce(Vec::new, |mut done, more| {
+            done.extend(more);
+            done
+        });
+    reassemble(finished)
+}
+
+fn reassemble<R>(mut finished: Vec<(usize, R)>) -> Vec<R> {
+    finished.sort_by_key(|(at, _)| *at);
+    finished.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_config;
+
+    const CONFIG: &str = "services:
+  - { name: web, path: web.exe, type: Executable }
+  - { name: api, path: api.exe, type: Executable }
+  - { name: db, path: db.exe, type: Executable }
+  - { name: cache, path: cache.exe, type: Executable }
+";
+
+    // Every rotation of the items, each also reversed, in place of the orders threads finish in
+    fn shuffles<T: Clone>(items: &[T]) -> Vec<Vec<T>> {
+        (0..items.len())
+            .flat_map(|by| {
+                let mut rotated = items.to_vec();
+                rotated.rotate_left(by);
+                let reversed = rotated.iter().rev().cloned().collect();
+                [rotated, reversed]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn results_come_back_in_the_order_of_the_items() {
+        let tagged: Vec<(usize, char)> = "abcde".chars().enumerate().collect();
+        for finished in shuffles(&tagged) {
+            assert_eq!(reassemble(finished), ['a', 'b', 'c', 'd', 'e']);
+        }
+        let items: Vec<u64> = (0..200).collect();
+        assert_eq!(par_map(&items, |item| item * 2), items.iter().map(|item| item * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn however_they_were_gathered_services_come_out_in_one_order() {
+        let config = parse_config(CONFIG).unwrap();
+        let order = Order::of(&config.services);
+        let state = |name: &str| match name {
+            "api" | "cache" => State::Running,
+            "db" => State::Degraded,
+            _ => State::Down,
+        };
+        let gathered = ["cache", "web", "db", "api", "adhoc-tool"];
+        for key in [None, Some(OrderKey::Name), Some(OrderKey::State)] {
+            let arranged: Vec<Vec<&str>> = shuffles(&gathered)
+                .into_iter()
+                .map(|mut names| {
+                    order.arrange(&mut names, key, |name| *name, |name| state(name));
+                    names
+                })
+                .collect();
+            let expected = match key {
+                None => ["web", "api", "db", "cache", "adhoc-tool"],
+                Some(OrderKey::Name) => ["adhoc-tool", "api", "cache", "db", "web"],
+                Some(OrderKey::State) => ["api", "cache", "db", "web", "adhoc-tool"],
+            };
+            assert!(arranged.iter().all(|names| names == &expected), "{:?}: {:?}", key, arranged);
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use crate::{pathcmp, procinfo, Service, ServiceType};
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Why a running instance no longer runs the binary now at the service's path, if it doesn't:
+// its image is another file, as when a deployment renamed the old one aside to put the new
+// one in place, or the file at the path is newer than the process
+pub fn reason(service: &Service, pids: &[u64]) -> Option<String> {
+    if !matches!(service.service_type, ServiceType::Executable) {
+        return None;
+    }
+    let changed = changed_at(&service.path);
+    pids.iter().find_map(|&pid| {
+        stale(
+            &service.path,
+            procinfo::image_path(pid).as_deref(),
+            procinfo::started_at(pid),
+            changed,
+        )
+    })
+}
+
+// The later of the file's creation and last write, in seconds since the Unix epoch: a copied
+// build keeps its write time from the build machine, but is created when it lands
+fn changed_at(path: &str) -> Option<u64> {
+    let metadata = fs::metadata(path).ok()?;
+    let seconds = |time: std::io::Result<SystemTime>| {
+        time.ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|since| since.as_secs())
+    };
+    seconds(metadata.modified()).max(seconds(metadata.created()))
+}
+
+fn stale(path: &str, image: Option<&str>, started: Option<u64>, changed: Option<u64>) -> Option<String> {
+    if let Some(image) = image.filter(|image| !pathcmp::same_file(image, path)) {
+        return Some(format!("binary updated on disk since start; it still runs {}", image));
+    }
+    match (started, changed) {
+        (Some(started), Some(changed)) if changed > started => Some("binary updated on disk since start".to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_binary_newer_than_its_process_is_stale() {
+        let path = "/opt/api/api.exe";
+        assert!(stale(path, Some(path), Some(1_000), Some(1_001)).is_some());
+        assert_eq!(stale(path, Some(path), Some(1_000), Some(900)), None);
+        // Without a start time nothing can be told
+        assert_eq!(stale(path, None, None, Some(1_001)), None);
+    }
+
+    #[test]
+    fn a_process_still_running_the_renamed_old_file_is_stale() {
+        let reason = stale("/opt/api/api.exe", Some("/opt/api/api.exe.old"), Some(1_000), Some(900)).unwrap();
+        assert!(reason.contains("api.exe.old"), "{}", reason);
+    }
+}
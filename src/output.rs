@@ -0,0 +1,287 @@
+use crate::{clock, errors, locate, SvcError};
+use colored::Colorize;
+use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+use std::sync::OnceLock;
+
+// Global output switches, parsed once from the command line
+#[derive(Debug, Default, Clone, Copy)]
+pub struct OutputOptions {
+    pub quiet: bool,
+    pub json: bool,
+    pub verbose: bool,
+    pub no_color: bool,
+    // Colors and unicode markers even where the console looks like it can't show them
+    pub force_color: bool,
+}
+
+static OPTIONS: OnceLock<OutputOptions> = OnceLock::new();
+static UNICODE: OnceLock<bool> = OnceLock::new();
+
+pub fn init(options: OutputOptions) {
+    if options.json || options.no_color {
+        colored::control::set_override(false);
+    } else if options.force_color {
+        colored::control::set_override(true);
+    } else if !enable_vt() {
+        // Escape codes would show up as text
+        colored::control::set_override(false);
+    }
+    let _ = UNICODE.set(options.force_color || console_unicode());
+    let _ = OPTIONS.set(options);
+}
+
+// Turns on escape code processing for each console svc writes to; false when one can't,
+// as in the conhost of Windows before 10
+#[cfg(windows)]
+fn enable_vt() -> bool {
+    use windows_sys::Win32::System::Console::{
+        GetConsoleMode, GetStdHandle, SetConsoleMode, ENABLE_VIRTUAL_TERMINAL_PROCESSING, STD_ERROR_HANDLE,
+        STD_OUTPUT_HANDLE,
+    };
+    [STD_OUTPUT_HANDLE, STD_ERROR_HANDLE].into_iter().all(|which| {
+        let handle = unsafe { GetStdHandle(which) };
+        let mut mode = 0;
+        // Redirected, which colored already leaves uncolored
+        if unsafe { GetConsoleMode(handle, &mut mode) } == 0 {
+            return true;
+        }
+        mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+            || unsafe { SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) } != 0
+    })
+}
+
+#[cfg(not(windows))]
+fn enable_vt() -> bool {
+    true
+}
+
+// Whether the console shows characters outside its code page: with UTF-8 as the output
+// code page, or in Windows Terminal and other terminals that render them regardless;
+// redirected output is UTF-8 for the reader to decode
+#[cfg(windows)]
+fn console_unicode() -> bool {
+    use windows_sys::Win32::System::Console::{GetConsoleMode, GetConsoleOutputCP, GetStdHandle, STD_OUTPUT_HANDLE};
+    const CP_UTF8: u32 = 65001;
+
+    let mut mode = 0;
+    let console = unsafe { GetConsoleMode(GetStdHandle(STD_OUTPUT_HANDLE), &mut mode) } != 0;
+    !console
+        || unsafe { GetConsoleOutputCP() } == CP_UTF8
+        || std::env::var_os("WT_SESSION").is_some()
+        || std::env::var_os("TERM_PROGRAM").is_some()
+}
+
+#[cfg(not(windows))]
+fn console_unicode() -> bool {
+    true
+}
+
+// Shields, sparklines and other markers, or their ASCII stand-ins on a console that
+// would show them as question marks
+pub fn symbol<'a>(unicode: &'a str, ascii: &'a str) -> &'a str {
+    if self::unicode() {
+        unicode
+    } else {
+        ascii
+    }
+}
+
+pub fn unicode() -> bool {
+    UNICODE.get().copied().unwrap_or(true)
+}
+
+pub fn options() -> OutputOptions {
+    OPTIONS.get().copied().unwrap_or_default()
+}
+
+// Columns a character takes up in a console: two for CJK and emoji, none for combining
+// marks, joiners and variation selectors
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0300..=0x036F | 0x1AB0..=0x1AFF | 0x1DC0..=0x1DFF | 0x200B..=0x200F | 0x20D0..=0x20FF => 0,
+        0xFE00..=0xFE0F | 0xFE20..=0xFE2F | 0xE0100..=0xE01EF => 0,
+        0x1100..=0x115F | 0x2E80..=0x303E | 0x3041..=0x33FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF => 2,
+        0xA000..=0xA4CF | 0xAC00..=0xD7A3 | 0xF900..=0xFAFF | 0xFE30..=0xFE4F | 0xFF00..=0xFF60 => 2,
+        0xFFE0..=0xFFE6 | 0x1F300..=0x1F64F | 0x1F680..=0x1F6FF | 0x1F900..=0x1F9FF | 0x1FA70..=0x1FAFF => 2,
+        0x20000..=0x2FFFD | 0x30000..=0x3FFFD => 2,
+        _ if c.is_control() => 0,
+        _ => 1,
+    }
+}
+
+pub fn width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+// `text` padded with spaces to `columns`, as `{:width$}` would if it counted columns, not chars
+pub fn pad(text: &str, columns: usize) -> String {
+    format!("{}{}", text, " ".repeat(columns.saturating_sub(width(text))))
+}
+
+// Whole lines on stdout under one lock, flushed at once, so a pipe reader such as findstr
+// or a log collector never sees them split or mixed with another thread's; a reader that
+// went away doesn't end svc
+pub fn lines(lines: &[String]) {
+    let mut stdout = std::io::stdout().lock();
+    let _ = lines
+        .iter()
+        .try_for_each(|line| writeln!(stdout, "{}", line))
+        .and_then(|()| stdout.flush());
+}
+
+pub fn line(line: &str) {
+    lines(&[line.to_string()]);
+}
+
+// Walk the source chain, skipping causes whose text is already part of the message
+fn causes(err: &SvcError) -> Vec<String> {
+    let message = err.to_string();
+    let mut causes = Vec::new();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        let text = cause.to_string();
+        if !message.contains(&text) {
+            causes.push(text);
+        }
+        source = cause.source();
+    }
+    causes
+}
+
+pub fn error_object(err: &SvcError) -> serde_json::Value {
+    serde_json::json!({
+        "code": err.code(),
+        "group": errors::group(err.code()),
+        "message": err.to_string(),
+        "details": errors::details(err),
+        "causes": causes(err),
+    })
+}
+
+pub fn report_error(err: &SvcError) {
+    let options = options();
+
+    if options.json {
+        eprintln!("{}", serde_json::json!({ "error": error_object(err) }));
+        return;
+    }
+
+    eprintln!("{} {}", "error:".red().bold(), err);
+    for cause in causes(err) {
+        eprintln!("  {} {}", "caused by:".red(), cause);
+    }
+    if !options.quiet {
+        if let Some(hint) = err.hint() {
+            eprintln!("{} {}", "hint:".cyan().bold(), hint);
+        }
+        if let SvcError::ConfigMissing(path) = err {
+            getting_started(Path::new(path));
+        }
+    }
+}
+
+// One failure among several services, as `svc kill --stdin` carries on past
+pub fn report_service_error(service: &str, err: &SvcError) {
+    if options().json {
+        eprintln!("{}", serde_json::json!({ "service": service, "error": error_object(err) }));
+    } else {
+        eprintln!("{} {}: {}", "error:".red().bold(), service.cyan(), err);
+    }
+}
+
+// A service a condition left alone, which is neither a failure nor quite a success
+pub fn report_skipped(err: &SvcError) {
+    if options().json {
+        eprintln!("{}", serde_json::json!({ "skipped": error_object(err) }));
+    } else {
+        println!("{} {}", "skipped:".yellow().bold(), err);
+    }
+}
+
+// Shown instead of a bare "file not found" the first time svc runs
+fn getting_started(path: &Path) {
+    let looked: String = locate::describe_searched(path).iter().map(|line| format!("  {}\n", line.cyan())).collect();
+    let init = match locate::init_path() {
+        Some(target) => format!("'svc init' creates a commented one at {},\nor create", target.display()),
+        None => "Create".to_string(),
+    };
+    eprintln!(
+        "\n{}\n\
+         svc looked for its services in:\n{}\
+         {} it with a list of services, for example:\n\n\
+         \x20 - name: MyServer\n\
+         \x20   type: Executable\n\
+         \x20   path: D:\\path\\to\\server.exe\n\n\
+         then try 'svc list'. 'svc help config-file' describes every field.",
+        "Getting started".bold(),
+        looked,
+        init
+    );
+}
+
+pub fn timestamp() -> String {
+    format_time(std::time::SystemTime::now())
+}
+
+// Wall-clock time as shown and recorded: local, with the UTC offset
+pub fn format_time(time: std::time::SystemTime) -> String {
+    clock::local(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widths_are_the_columns_a_console_shows() {
+        assert_eq!(width("api"), 3);
+        assert_eq!(width("Сервер"), 6);
+        assert_eq!(width("服务器"), 6);
+        assert_eq!(width("📦 My Server"), 12);
+        // e and a combining acute accent take one column, as é does
+        assert_eq!(width("cafe\u{301}"), 4);
+        assert_eq!(pad("服务", 6), "服务  ");
+        assert_eq!(pad("api", 2), "api");
+    }
+
+    // libtest only captures print!, so the writers run in a child of this test binary with
+    // stdout on a pipe
+    #[test]
+    fn concurrent_groups_of_lines_come_out_whole() {
+        const WRITERS: usize = 8;
+        const GROUPS: usize = 200;
+        if std::env::var_os("SVC_OUTPUT_STRESS").is_some() {
+            let writers: Vec<_> = (0..WRITERS)
+                .map(|writer| {
+                    std::thread::spawn(move || {
+                        for group in 0..GROUPS {
+                            lines(&(0..3).map(|at| format!("stress {} {} {}", writer, group, at)).collect::<Vec<_>>());
+                        }
+                    })
+                })
+                .collect();
+            for writer in writers {
+                writer.join().unwrap();
+            }
+            return;
+        }
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["output::tests::concurrent_groups_of_lines_come_out_whole", "--exact", "--nocapture"])
+            .env("SVC_OUTPUT_STRESS", "1")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        // The harness may print its own text ahead of the first line
+        let written: Vec<&str> = stdout.lines().filter_map(|line| line.find("stress ").map(|at| &line[at..])).collect();
+        assert_eq!(written.len(), WRITERS * GROUPS * 3);
+        for group in written.chunks(3) {
+            let (prefix, _) = group[0].rsplit_once(' ').unwrap();
+            for (at, line) in group.iter().enumerate() {
+                assert_eq!(*line, format!("{} {}", prefix, at), "{:?}", group);
+            }
+        }
+    }
+}
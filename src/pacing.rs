@@ -0,0 +1,110 @@
+use std::time::{Duration, Instant};
+
+// How long polling stays at its shortest after something happened
+const ACTIVE_WINDOW: Duration = Duration::from_secs(30);
+
+// Polls at the shortest interval for a while after each event, then doubles the interval
+// with every quiet poll up to the longest; equal bounds poll at a fixed interval
+pub struct Pacer {
+    min: Duration,
+    max: Duration,
+    current: Duration,
+    last_event: Instant,
+}
+
+impl Pacer {
+    // Starting counts as an event, so the first polls come quickly
+    pub fn new(min: Duration, max: Duration, now: Instant) -> Self {
+        Pacer {
+            min,
+            max: max.max(min),
+            current: min,
+            last_event: now,
+        }
+    }
+
+    pub fn event(&mut self, now: Instant) {
+        self.current = self.min;
+        self.last_event = now;
+    }
+
+    // The wait before the next poll
+    pub fn next(&mut self, now: Instant) -> Duration {
+        if now.saturating_duration_since(self.last_event) >= ACTIVE_WINDOW {
+            self.current = (self.current * 2).min(self.max);
+        }
+        self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Time that only moves when the test says so
+    struct FakeClock {
+        now: Instant,
+    }
+
+    impl FakeClock {
+        fn advance(&mut self, by: Duration) -> Instant {
+            self.now += by;
+            self.now
+        }
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    // Sleeps out each interval the pacer asks for, returning them
+    fn poll(pacer: &mut Pacer, clock: &mut FakeClock, times: usize) -> Vec<u64> {
+        (0..times)
+            .map(|_| {
+                let wait = pacer.next(clock.now);
+                clock.advance(wait);
+                wait.as_secs()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stays_short_within_the_active_window() {
+        let mut clock = FakeClock { now: Instant::now() };
+        let mut pacer = Pacer::new(secs(1), secs(30), clock.now);
+        assert_eq!(poll(&mut pacer, &mut clock, 29), vec![1; 29]);
+    }
+
+    #[test]
+    fn decays_to_the_longest_interval_when_quiet() {
+        let mut clock = FakeClock { now: Instant::now() };
+        let mut pacer = Pacer::new(secs(1), secs(30), clock.now);
+        clock.advance(ACTIVE_WINDOW);
+        assert_eq!(poll(&mut pacer, &mut clock, 7), [2, 4, 8, 16, 30, 30, 30]);
+        assert_eq!(pacer.next(clock.now), secs(30));
+    }
+
+    #[test]
+    fn an_event_resets_to_the_shortest_interval() {
+        let mut clock = FakeClock { now: Instant::now() };
+        let mut pacer = Pacer::new(secs(2), secs(60), clock.now);
+        clock.advance(secs(120));
+        poll(&mut pacer, &mut clock, 6);
+        assert_eq!(pacer.next(clock.now), secs(60));
+
+        pacer.event(clock.now);
+        assert_eq!(pacer.next(clock.now), secs(2));
+        clock.advance(secs(29));
+        assert_eq!(pacer.next(clock.now), secs(2));
+        clock.advance(secs(1));
+        assert_eq!(pacer.next(clock.now), secs(4));
+    }
+
+    #[test]
+    fn equal_bounds_poll_at_a_fixed_interval() {
+        let mut clock = FakeClock { now: Instant::now() };
+        let mut pacer = Pacer::new(secs(5), secs(5), clock.now);
+        clock.advance(secs(300));
+        assert_eq!(poll(&mut pacer, &mut clock, 3), [5, 5, 5]);
+    }
+}
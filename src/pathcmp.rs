@@ -0,0 +1,227 @@
+use std::ffi::OsStr;
+use std::path::Path;
+
+// A path in the one form every "is this the same file?" check in svc compares, so the
+// running check, start-up entries, firewall rules and the kill guard can't disagree
+#[derive(Debug, Clone)]
+pub struct ComparablePath(Vec<u16>);
+
+impl PartialEq for ComparablePath {
+    fn eq(&self, other: &Self) -> bool {
+        same_units(&self.0, &other.0)
+    }
+}
+
+impl ComparablePath {
+    // Whether this path lies somewhere below the directory
+    pub fn is_within(&self, dir: &ComparablePath) -> bool {
+        let mut dir = dir.0.clone();
+        if dir.last() != Some(&u16::from(b'\\')) {
+            dir.push(u16::from(b'\\'));
+        }
+        self.0.len() > dir.len() && same_units(&self.0[..dir.len()], &dir)
+    }
+
+    // Whether the last components of this path are the given relative one, so
+    // "app\foo.exe" matches C:\x\app\foo.exe but not C:\x\myapp\foo.exe
+    pub fn ends_with(&self, tail: &ComparablePath) -> bool {
+        let Some(start) = self.0.len().checked_sub(tail.0.len()) else {
+            return false;
+        };
+        (start == 0 || self.0[start - 1] == u16::from(b'\\')) && same_units(&self.0[start..], &tail.0)
+    }
+}
+
+fn units(path: &OsStr) -> Vec<u16> {
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        path.encode_wide().collect()
+    }
+    #[cfg(not(windows))]
+    {
+        path.to_string_lossy().encode_utf16().collect()
+    }
+}
+
+// Backslashes only, no \\?\ prefix, no trailing separator except on a root like C:\
+fn tidy(mut units: Vec<u16>) -> Vec<u16> {
+    let slash = u16::from(b'\\');
+    for unit in &mut units {
+        if *unit == u16::from(b'/') {
+            *unit = slash;
+        }
+    }
+    let unc: Vec<u16> = r"\\?\UNC\".encode_utf16().collect();
+    let verbatim: Vec<u16> = r"\\?\".encode_utf16().collect();
+    if units.starts_with(&unc) {
+        // \\?\UNC\server\share is \\server\share
+        units.drain(2..unc.len());
+    } else if units.starts_with(&verbatim) {
+        units.drain(..verbatim.len());
+    }
+    let is_drive_root = |units: &[u16]| units.len() == 3 && units[1] == u16::from(b':');
+    while units.len() > 1 && units.last() == Some(&slash) && !is_drive_root(&units) {
+        units.pop();
+    }
+    composed(units)
+}
+
+// Composed form, so "é" typed as e + U+0301 matches the precomposed one
+#[cfg(windows)]
+fn composed(units: Vec<u16>) -> Vec<u16> {
+    use windows_sys::Win32::Globalization::{NormalizeString, NormalizationC};
+
+    let length = units.len() as i32;
+    let needed = unsafe { NormalizeString(NormalizationC, units.as_ptr(), length, std::ptr::null_mut(), 0) };
+    if needed <= 0 {
+        return units;
+    }
+    let mut composed = vec![0; needed as usize];
+    let written = unsafe { NormalizeString(NormalizationC, units.as_ptr(), length, composed.as_mut_ptr(), needed) };
+    if written <= 0 {
+        return units;
+    }
+    composed.truncate(written as usize);
+    composed
+}
+
+#[cfg(not(windows))]
+fn composed(units: Vec<u16>) -> Vec<u16> {
+    units
+}
+
+// Case-insensitive the way NTFS is, per UTF-16 unit rather than per locale
+#[cfg(windows)]
+fn same_units(a: &[u16], b: &[u16]) -> bool {
+    use windows_sys::Win32::Globalization::{CompareStringOrdinal, CSTR_EQUAL};
+
+    unsafe { CompareStringOrdinal(a.as_ptr(), a.len() as i32, b.as_ptr(), b.len() as i32, 1) == CSTR_EQUAL }
+}
+
+#[cfg(not(windows))]
+fn same_units(a: &[u16], b: &[u16]) -> bool {
+    String::from_utf16_lossy(a).to_lowercase() == String::from_utf16_lossy(b).to_lowercase()
+}
+
+// Long form of an existing path whose components may be 8.3 names, e.g. PROGRA~1
+#[cfg(windows)]
+fn long_path(path: &Path) -> Option<Vec<u16>> {
+    use windows_sys::Win32::Storage::FileSystem::GetLongPathNameW;
+
+    let wide: Vec<u16> = units(path.as_os_str()).into_iter().chain(Some(0)).collect();
+    let needed = unsafe { GetLongPathNameW(wide.as_ptr(), std::ptr::null_mut(), 0) };
+    if needed == 0 {
+        return None;
+    }
+    let mut buffer = vec![0u16; needed as usize];
+    let written = unsafe { GetLongPathNameW(wide.as_ptr(), buffer.as_mut_ptr(), needed) };
+    (written != 0 && written < needed).then(|| buffer[..written as usize].to_vec())
+}
+
+#[cfg(not(windows))]
+fn long_path(_path: &Path) -> Option<Vec<u16>> {
+    None
+}
+
+// Only the spelling is tidied, without touching the filesystem; for paths that are
+// already final, such as the image paths of running processes
+pub fn lexical(path: &OsStr) -> ComparablePath {
+    ComparablePath(tidy(units(path)))
+}
+
+// The spelling tidied, then for a path that exists symlinks and 8.3 names resolved;
+// a missing or relative path is compared as written
+pub fn canonicalize_for_compare(path: &str) -> ComparablePath {
+    let as_path = Path::new(path);
+    if as_path.is_absolute() {
+        if let Ok(resolved) = std::fs::canonicalize(as_path) {
+            return lexical(resolved.as_os_str());
+        }
+        if let Some(long) = long_path(as_path) {
+            return ComparablePath(tidy(long));
+        }
+    }
+    lexical(OsStr::new(path))
+}
+
+pub fn same_file(a: &str, b: &str) -> bool {
+    canonicalize_for_compare(a) == canonicalize_for_compare(b)
+}
+
+pub fn is_within(path: &str, dir: &str) -> bool {
+    canonicalize_for_compare(path).is_within(&canonicalize_for_compare(dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn same(a: &str, b: &str) -> bool {
+        lexical(OsStr::new(a)) == lexical(OsStr::new(b))
+    }
+
+    #[test]
+    fn spellings_of_one_path_are_equal_and_of_others_are_not() {
+        let equal = [
+            (r"C:\Tools\api.exe", r"c:\tools\API.EXE"),
+            (r"C:\Tools\api.exe", "C:/Tools/api.exe"),
+            (r"C:\Tools\api.exe", r"\\?\C:\Tools\api.exe"),
+            (r"\\server\share\api.exe", r"\\?\UNC\server\share\api.exe"),
+            (r"C:\Tools", r"C:\Tools\"),
+            (r"C:\Tools", "C:/Tools//"),
+            (r"C:\", "C:/"),
+        ];
+        for (a, b) in equal {
+            assert!(same(a, b), "{} and {} should be equal", a, b);
+        }
+        let unequal = [
+            (r"C:\Tools\api.exe", r"D:\Tools\api.exe"),
+            (r"C:\Tools\api.exe", r"C:\Tools\api.ex"),
+            (r"C:\Tools\api.exe", r"C:\Tools\api.exe.bak"),
+            (r"C:\Tools", r"C:\Tool"),
+            (r"\\server\share", r"\\server\other"),
+            // No PATH search nor current directory is applied
+            ("api.exe", r"C:\Tools\api.exe"),
+        ];
+        for (a, b) in unequal {
+            assert!(!same(a, b), "{} and {} should differ", a, b);
+        }
+    }
+
+    #[test]
+    fn within_and_ends_with_stop_at_separators() {
+        let path = lexical(OsStr::new(r"C:\x\app\foo.exe"));
+        assert!(path.is_within(&lexical(OsStr::new(r"c:\X"))));
+        assert!(path.is_within(&lexical(OsStr::new(r"C:\x\"))));
+        assert!(path.is_within(&lexical(OsStr::new(r"C:\"))));
+        assert!(!path.is_within(&lexical(OsStr::new(r"C:\x\ap"))));
+        // A path isn't within itself
+        assert!(!path.is_within(&path));
+
+        assert!(path.ends_with(&lexical(OsStr::new(r"app\foo.exe"))));
+        assert!(path.ends_with(&lexical(OsStr::new("APP/FOO.EXE"))));
+        assert!(path.ends_with(&path));
+        assert!(!path.ends_with(&lexical(OsStr::new(r"pp\foo.exe"))));
+        assert!(!lexical(OsStr::new(r"C:\x\myapp\foo.exe")).ends_with(&lexical(OsStr::new(r"app\foo.exe"))));
+    }
+
+    #[test]
+    fn paths_outside_the_console_code_page_compare_by_their_text() {
+        assert!(same(r"D:\服务\api.exe", r"d:/服务/API.EXE"));
+        assert!(same(r"C:\Программы\Сервис.exe", r"c:\программы\сервис.EXE"));
+        assert!(!same(r"D:\服务\api.exe", r"D:\服務\api.exe"));
+        assert!(!same(r"C:\Программы\a.exe", r"C:\Програмы\a.exe"));
+        assert!(lexical(OsStr::new(r"D:\服务\bin\api.exe")).is_within(&lexical(OsStr::new(r"d:\服务"))));
+        assert!(lexical(OsStr::new(r"C:\x\Сервис\a.exe")).ends_with(&lexical(OsStr::new(r"сервис\A.exe"))));
+    }
+
+    // Only Windows composes; elsewhere the two spellings stay apart, as the filesystem keeps them
+    #[cfg(windows)]
+    #[test]
+    fn a_combining_accent_matches_the_precomposed_letter() {
+        assert!(same("C:\\Caf\u{e9}\\api.exe", "C:\\Cafe\u{301}\\api.exe"));
+        assert!(same("C:\\CAF\u{c9}\\api.exe", "c:\\cafe\u{301}\\API.exe"));
+        assert!(!same("C:\\Caf\u{e9}\\api.exe", "C:\\Cafe\\api.exe"));
+    }
+}
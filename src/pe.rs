@@ -0,0 +1,122 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+// IMAGE_FILE_MACHINE_* values shared by PE headers and IsWow64Process2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    X86,
+    X64,
+    Arm,
+    Arm64,
+    Other(u16),
+}
+
+impl Arch {
+    pub fn from_machine(machine: u16) -> Arch {
+        match machine {
+            0x014c => Arch::X86,
+            0x8664 => Arch::X64,
+            0x01c4 => Arch::Arm,
+            0xaa64 => Arch::Arm64,
+            other => Arch::Other(other),
+        }
+    }
+
+    pub fn is_32_bit(self) -> bool {
+        matches!(self, Arch::X86 | Arch::Arm)
+    }
+}
+
+impl Display for Arch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Arch::X86 => write!(f, "x86"),
+            Arch::X64 => write!(f, "x64"),
+            Arch::Arm => write!(f, "ARM"),
+            Arch::Arm64 => write!(f, "ARM64"),
+            Arch::Other(machine) => write!(f, "machine {:#06x}", machine),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    Console,
+    Gui,
+    Other(u16),
+}
+
+impl Display for Subsystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Subsystem::Console => write!(f, "console"),
+            Subsystem::Gui => write!(f, "GUI"),
+            Subsystem::Other(value) => write!(f, "subsystem {}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PeInfo {
+    pub arch: Arch,
+    pub subsystem: Subsystem,
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+// Machine and subsystem out of the headers of an executable; None for anything that isn't PE
+pub fn inspect(path: &Path) -> Option<PeInfo> {
+    let mut file = File::open(path).ok()?;
+    let mut dos = [0u8; 64];
+    file.read_exact(&mut dos).ok()?;
+    if &dos[..2] != b"MZ" {
+        return None;
+    }
+
+    // e_lfanew points at "PE\0\0", followed by the COFF header and the optional header
+    let offset = u32::from_le_bytes(dos[0x3c..0x40].try_into().ok()?);
+    file.seek(SeekFrom::Start(u64::from(offset))).ok()?;
+    let mut headers = [0u8; 4 + 20 + 70];
+    file.read_exact(&mut headers).ok()?;
+    if &headers[..4] != b"PE\0\0" {
+        return None;
+    }
+
+    let machine = read_u16(&headers, 4)?;
+    // Subsystem sits at the same offset in PE32 and PE32+ optional headers
+    let subsystem = match read_u16(&headers, 24 + 68)? {
+        2 => Subsystem::Gui,
+        3 => Subsystem::Console,
+        other => Subsystem::Other(other),
+    };
+    Some(PeInfo {
+        arch: Arch::from_machine(machine),
+        subsystem,
+    })
+}
+
+// Reasons a service's binary or its running processes look like the wrong build
+pub fn warnings(path: &str, binary: Option<Arch>, running: &[(u64, Option<Arch>)]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let Some(binary) = binary else {
+        return warnings;
+    };
+
+    let lower = path.to_lowercase();
+    if binary.is_32_bit() && lower.contains("\\program files\\") {
+        warnings.push(format!("{} is a 32-bit ({}) binary under Program Files rather than Program Files (x86)", path, binary));
+    }
+    for (pid, arch) in running {
+        if let Some(arch) = arch.filter(|&arch| arch != binary) {
+            warnings.push(format!(
+                "PID {} runs {} code but {} is {}; it may be a stale process from before an update",
+                pid, arch, path, binary
+            ));
+        }
+    }
+    warnings
+}
@@ -0,0 +1,151 @@
+use crate::SvcError;
+use std::fs::File;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+pub const PIPE_NAME: &str = r"\\.\pipe\svc";
+
+// Connections being served; a slot is given back when its thread ends, however it ends
+#[cfg_attr(not(windows), allow(dead_code))]
+#[derive(Default)]
+struct Slots {
+    taken: Mutex<usize>,
+    freed: Condvar,
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+struct Slot(Arc<Slots>);
+
+#[cfg_attr(not(windows), allow(dead_code))]
+impl Slots {
+    // Waits while every slot is taken
+    fn take(slots: &Arc<Slots>, max: usize) -> Slot {
+        let mut taken = slots.taken.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        while *taken >= max {
+            taken = slots.freed.wait(taken).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        *taken += 1;
+        Slot(Arc::clone(slots))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        *self.0.taken.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) -= 1;
+        self.0.freed.notify_one();
+    }
+}
+
+// Accept clients on the named pipe forever, handing each connection to `handle` on its own
+// thread; with `max` connections open, the next client waits for one of them to close
+#[cfg(windows)]
+pub fn listen<F>(max: usize, handle: F) -> Result<(), SvcError>
+where
+    F: Fn(File) + Send + Sync + 'static,
+{
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::FromRawHandle;
+    use windows_sys::Win32::Foundation::{CloseHandle, GetLastError, ERROR_PIPE_CONNECTED, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::PIPE_ACCESS_DUPLEX;
+    use windows_sys::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES,
+        PIPE_WAIT,
+    };
+
+    let name: Vec<u16> = std::ffi::OsStr::new(PIPE_NAME).encode_wide().chain(Some(0)).collect();
+    let handle = Arc::new(handle);
+    let slots = Arc::new(Slots::default());
+    loop {
+        let slot = Slots::take(&slots, max.max(1));
+        // One pipe instance per client; a fresh one is created as soon as the last is taken
+        let pipe = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                PIPE_ACCESS_DUPLEX,
+                PIPE_TYPE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                4096,
+                4096,
+                0,
+                std::ptr::null(),
+            )
+        };
+        if pipe == INVALID_HANDLE_VALUE {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        let connected = unsafe { ConnectNamedPipe(pipe, std::ptr::null_mut()) } != 0
+            || unsafe { GetLastError() } == ERROR_PIPE_CONNECTED;
+        if !connected {
+            unsafe { CloseHandle(pipe) };
+            continue;
+        }
+
+        // The File owns the handle from here on and closes it when the client is done
+        let file = unsafe { File::from_raw_handle(pipe) };
+        let handle = Arc::clone(&handle);
+        std::thread::spawn(move || {
+            let _slot = slot;
+            handle(file)
+        });
+    }
+}
+
+#[cfg(not(windows))]
+pub fn listen<F>(_max: usize, _handle: F) -> Result<(), SvcError>
+where
+    F: Fn(File) + Send + Sync + 'static,
+{
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "named pipes are only available on Windows").into())
+}
+
+// Runs `read` on a connection, disconnecting the client if it takes longer than `timeout`, so
+// a client that connects and never writes can't keep its thread blocked in a read
+#[cfg(windows)]
+pub fn within<T>(file: &File, timeout: Duration, read: impl FnOnce() -> T) -> T {
+    use std::os::windows::io::AsRawHandle;
+    use std::sync::mpsc::{self, RecvTimeoutError};
+    use windows_sys::Win32::System::Pipes::DisconnectNamedPipe;
+
+    // As a number, since a raw handle may not cross threads
+    let pipe = file.as_raw_handle() as usize;
+    let (done, finished) = mpsc::channel::<()>();
+    let deadline = std::thread::spawn(move || {
+        if finished.recv_timeout(timeout) == Err(RecvTimeoutError::Timeout) {
+            // Fails the pending read; the handle itself stays the File's to close
+            unsafe { DisconnectNamedPipe(pipe as windows_sys::Win32::Foundation::HANDLE) };
+        }
+    });
+    let result = read();
+    drop(done);
+    let _ = deadline.join();
+    result
+}
+
+#[cfg(not(windows))]
+pub fn within<T>(_file: &File, _timeout: Duration, read: impl FnOnce() -> T) -> T {
+    read()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn a_connection_past_the_limit_waits_for_a_slot() {
+        let slots = Arc::new(Slots::default());
+        let first = Slots::take(&slots, 2);
+        let _second = Slots::take(&slots, 2);
+
+        let (taken, third) = mpsc::channel();
+        let waiting = Arc::clone(&slots);
+        std::thread::spawn(move || {
+            let _slot = Slots::take(&waiting, 2);
+            taken.send(()).unwrap();
+        });
+        assert!(third.recv_timeout(Duration::from_millis(100)).is_err());
+        drop(first);
+        assert!(third.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+}
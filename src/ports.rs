@@ -0,0 +1,102 @@
+use crate::{order, procinfo, running_pids, warnings, Service};
+use std::collections::{BTreeMap, HashMap};
+
+// Ports more than one service declares, each with those services in config order
+pub fn duplicates<'a>(services: &'a [Service]) -> Vec<(u16, Vec<&'a str>)> {
+    let mut declared: BTreeMap<u16, Vec<&str>> = BTreeMap::new();
+    for service in services {
+        if let Some(port) = service.port {
+            declared.entry(port).or_default().push(&service.name);
+        }
+    }
+    declared.into_iter().filter(|(_, names)| names.len() > 1).collect()
+}
+
+// "port 8080 is also declared by web", for each service of a duplicate
+pub fn duplicate_messages<'a>(port: u16, names: &[&'a str]) -> Vec<(&'a str, String)> {
+    names
+        .iter()
+        .map(|&name| {
+            let others: Vec<&str> = names.iter().copied().filter(|&other| other != name).collect();
+            (name, format!("port {} is also declared by {}", port, others.join(", ")))
+        })
+        .collect()
+}
+
+// Raised once the config is loaded, on the first service declaring the port; `svc validate`
+// reports every one of them as an error instead
+pub fn warn_duplicates(services: &[Service]) {
+    for (port, names) in duplicates(services) {
+        if let Some((name, message)) = duplicate_messages(port, &names).into_iter().next() {
+            warnings::warn("port_duplicate", name, message);
+        }
+    }
+}
+
+// Services whose declared port a process of another configured service is bound to, and
+// those other services, each with what to say about it
+fn find(services: &[(&str, Option<u16>, Vec<u64>)], listening: &[(u16, u64)]) -> HashMap<String, Vec<String>> {
+    let mut conflicts: HashMap<String, Vec<String>> = HashMap::new();
+    for &(name, port, _) in services {
+        let Some(port) = port else {
+            continue;
+        };
+        for &(_, pid) in listening.iter().filter(|(bound, _)| *bound == port) {
+            let holder = services.iter().find(|(other, _, pids)| *other != name && pids.contains(&pid));
+            if let Some(&(holder, _, _)) = holder {
+                let message = format!("port {} is bound by {} (PID {})", port, holder, pid);
+                conflicts.entry(name.to_string()).or_default().push(message);
+                let message = format!("bound to port {}, which {} declares (PID {})", port, name, pid);
+                conflicts.entry(holder.to_string()).or_default().push(message);
+            }
+        }
+    }
+    conflicts
+}
+
+// Port conflicts by service name, from one TCP table query; `known` are PIDs already looked up,
+// the other services' come from one process snapshot. Nothing is asked when no service
+// declares a port
+pub fn conflicts(config: &[Service], known: &[(&str, &[u64])]) -> HashMap<String, Vec<String>> {
+    if config.iter().all(|service| service.port.is_none()) {
+        return HashMap::new();
+    }
+    let Some(mut listening) = procinfo::listening_ports() else {
+        return HashMap::new();
+    };
+    let images = procinfo::Images::take();
+    let services = order::par_map(config, |service| {
+        let pids = match known.iter().find(|(name, _)| *name == service.name) {
+            Some((_, pids)) => pids.to_vec(),
+            None => running_pids(service, &images).unwrap_or_default(),
+        };
+        (service.name.as_ref(), service.port, pids)
+    });
+    // The TCP table comes in whatever order the system keeps it
+    listening.sort_unstable();
+    find(&services, &listening)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_port_bound_by_another_service_is_reported_on_both() {
+        let services = [
+            ("api", Some(8080), vec![10]),
+            ("web", Some(8081), vec![20]),
+            ("job", None, vec![30]),
+        ];
+        let conflicts = find(&services, &[(8080, 20), (8081, 20), (9000, 30)]);
+        assert_eq!(conflicts["api"], ["port 8080 is bound by web (PID 20)"]);
+        assert_eq!(conflicts["web"], ["bound to port 8080, which api declares (PID 20)"]);
+        assert!(!conflicts.contains_key("job"));
+    }
+
+    #[test]
+    fn its_own_binding_and_strangers_are_no_conflict() {
+        let services = [("api", Some(8080), vec![10])];
+        assert!(find(&services, &[(8080, 10), (8080, 99)]).is_empty());
+    }
+}
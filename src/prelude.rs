@@ -0,0 +1,79 @@
+//! What tools using svc as a library need, in one `use svc::prelude::*`. Everything here is
+//! covered by the crate's version: tests/public-api.txt records it, and a change to it comes with
+//! a version bump.
+//!
+//! Load a config and list its services:
+//!
+//! ```
+//! use svc::prelude::*;
+//!
+//! let config = parse_config(
+//!     "services:
+//!   - name: api
+//!     path: /opt/api/api.exe
+//!     type: Executable
+//!   - name: report
+//!     path: report.ps1
+//!     type: Util
+//! ",
+//! )?;
+//! let names: Vec<&str> = config.services.iter().map(|service| service.name.as_ref()).collect();
+//! assert_eq!(names, ["api", "report"]);
+//! assert!(matches!(config.service("report")?.service_type, ServiceType::Util));
+//! # Ok::<(), SvcError>(())
+//! ```
+//!
+//! Start a service and check that it runs, here through a backend that only pretends to:
+//!
+//! ```
+//! use std::cell::Cell;
+//! use std::path::Path;
+//! use svc::prelude::*;
+//!
+//! struct Pretend(Cell<Option<u64>>);
+//!
+//! impl ProcessBackend for Pretend {
+//!     fn pids(&self, _service: &Service) -> Result<Vec<u64>, SvcError> {
+//!         Ok(self.0.get().into_iter().collect())
+//!     }
+//!     fn spawn(&self, _service: &Service, _work_at: &str) -> Result<u64, SvcError> {
+//!         self.0.set(Some(4242));
+//!         Ok(4242)
+//!     }
+//!     fn run_util(&self, _: &Service, _: &str, _: Option<&Path>) -> Result<Vec<String>, SvcError> {
+//!         Ok(Vec::new())
+//!     }
+//!     fn terminate(&self, _service: &Service, _pids: &[u64]) -> Result<(), SvcError> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let config = parse_config("services:\n  - name: api\n    path: /opt/api/api.exe\n    type: Executable\n")?;
+//! let api = config.service("api")?;
+//! let backend = Pretend(Cell::new(None));
+//! let manager = ServiceManager::with_backend(&backend);
+//!
+//! let outcome = manager.start(api, &RunOverride::default())?;
+//! assert_eq!(outcome, RunOutcome::Started { pid: 4242, user: None });
+//! assert!(manager.is_running(api)?);
+//! # Ok::<(), SvcError>(())
+//! ```
+//!
+//! Query the status of a configured service as JSON; this one asks the machine, so it is only
+//! compiled here:
+//!
+//! ```no_run
+//! use svc::prelude::*;
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let services = load_config("services.yaml")?;
+//! let api = services.iter().find(|service| service.name == "api").ok_or("api isn't configured")?;
+//! let status: ServiceStatus = ServiceManager::system().status(api)?;
+//! println!("{}", serde_json::to_string_pretty(&status)?);
+//! # Ok(())
+//! # }
+//! ```
+
+pub use crate::config::{load_config, parse_config, Config, Service, ServiceType};
+pub use crate::process::{ProcessBackend, RunOutcome, RunOverride, ServiceManager, ServiceStatus, System};
+pub use crate::SvcError;
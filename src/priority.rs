@@ -0,0 +1,168 @@
+use crate::SvcError;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+// Windows priority classes, named as in Task Manager
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum Priority {
+    Idle,
+    BelowNormal,
+    Normal,
+    AboveNormal,
+    High,
+    Realtime,
+}
+
+impl Priority {
+    #[cfg(windows)]
+    fn class(self) -> u32 {
+        use windows_sys::Win32::System::Threading::*;
+        match self {
+            Priority::Idle => IDLE_PRIORITY_CLASS,
+            Priority::BelowNormal => BELOW_NORMAL_PRIORITY_CLASS,
+            Priority::Normal => NORMAL_PRIORITY_CLASS,
+            Priority::AboveNormal => ABOVE_NORMAL_PRIORITY_CLASS,
+            Priority::High => HIGH_PRIORITY_CLASS,
+            Priority::Realtime => REALTIME_PRIORITY_CLASS,
+        }
+    }
+
+    #[cfg(windows)]
+    fn from_class(class: u32) -> Option<Priority> {
+        [
+            Priority::Idle,
+            Priority::BelowNormal,
+            Priority::Normal,
+            Priority::AboveNormal,
+            Priority::High,
+            Priority::Realtime,
+        ]
+        .into_iter()
+        .find(|priority| priority.class() == class)
+    }
+}
+
+impl Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = self.to_possible_value().expect("no priority is hidden");
+        f.write_str(value.get_name())
+    }
+}
+
+// CPU numbers, as written in the config, to an affinity mask; one the mask has no bit for is
+// refused rather than dropped, which would pin the process to fewer CPUs than asked
+pub fn mask(cpus: &[u32]) -> Result<usize, SvcError> {
+    cpus.iter().try_fold(0, |mask, &cpu| match cpu < usize::BITS {
+        true => Ok(mask | 1 << cpu),
+        false => Err(SvcError::AffinityOutOfRange(cpu)),
+    })
+}
+
+pub fn cpus(mask: usize) -> Vec<u32> {
+    (0..usize::BITS).filter(|cpu| mask & 1 << cpu != 0).collect()
+}
+
+// Live priority and affinity of a process, None where it can't be opened
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Scheduling {
+    pub priority: Option<Priority>,
+    pub affinity: Option<usize>,
+    // CPUs the system has, to tell a restricted affinity from the default
+    pub system: Option<usize>,
+}
+
+#[cfg(windows)]
+pub fn query(pid: u64) -> Scheduling {
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{
+        GetPriorityClass, GetProcessAffinityMask, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid as u32) };
+    if process.is_null() {
+        return Scheduling::default();
+    }
+    let priority = Priority::from_class(unsafe { GetPriorityClass(process) });
+    let (mut affinity, mut system) = (0, 0);
+    let ok = unsafe { GetProcessAffinityMask(process, &mut affinity, &mut system) } != 0;
+    unsafe { CloseHandle(process) };
+    Scheduling {
+        priority,
+        affinity: ok.then_some(affinity),
+        system: ok.then_some(system),
+    }
+}
+
+#[cfg(not(windows))]
+pub fn query(_pid: u64) -> Scheduling {
+    Scheduling::default()
+}
+
+impl Display for Scheduling {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.priority {
+            Some(priority) => write!(f, "{}", priority)?,
+            None => write!(f, "unknown")?,
+        }
+        match (self.affinity, self.system) {
+            (Some(affinity), Some(system)) if affinity != system => {
+                let cpus: Vec<String> = cpus(affinity).iter().map(u32::to_string).collect();
+                write!(f, " on CPUs {}", cpus.join(","))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Apply whichever of the two is given; access is denied on processes of elevated
+// or other users unless svc itself runs elevated
+#[cfg(windows)]
+pub fn apply(pid: u64, priority: Option<Priority>, affinity: Option<usize>) -> std::io::Result<()> {
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, SetPriorityClass, SetProcessAffinityMask, PROCESS_QUERY_LIMITED_INFORMATION,
+        PROCESS_SET_INFORMATION,
+    };
+
+    let process = unsafe { OpenProcess(PROCESS_SET_INFORMATION | PROCESS_QUERY_LIMITED_INFORMATION, 0, pid as u32) };
+    if process.is_null() {
+        return Err(std::io::Error::last_os_error());
+    }
+    let mut result = Ok(());
+    if let Some(priority) = priority {
+        if unsafe { SetPriorityClass(process, priority.class()) } == 0 {
+            result = Err(std::io::Error::last_os_error());
+        }
+    }
+    if let (Ok(()), Some(affinity)) = (&result, affinity) {
+        if unsafe { SetProcessAffinityMask(process, affinity) } == 0 {
+            result = Err(std::io::Error::last_os_error());
+        }
+    }
+    unsafe { CloseHandle(process) };
+    result
+}
+
+#[cfg(not(windows))]
+pub fn apply(_pid: u64, _priority: Option<Priority>, _affinity: Option<usize>) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpus_beyond_the_mask_are_refused() {
+        assert_eq!(mask(&[0, 1]).unwrap(), 0b11);
+        assert_eq!(mask(&[]).unwrap(), 0);
+        let last = usize::BITS - 1;
+        assert_eq!(mask(&[last]).unwrap(), 1 << last);
+        assert_eq!(cpus(mask(&[0, 3, last]).unwrap()), [0, 3, last]);
+        assert!(matches!(mask(&[0, usize::BITS]), Err(SvcError::AffinityOutOfRange(cpu)) if cpu == usize::BITS));
+        assert!(matches!(mask(&[u32::MAX]), Err(SvcError::AffinityOutOfRange(u32::MAX))));
+    }
+}
@@ -0,0 +1,142 @@
+use crate::startup::RUN_KEY;
+use crate::{elevation, flavor, output, procinfo, startup, SvcError};
+use colored::Colorize;
+use serde::Serialize;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+// Something a restricted account may not be allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Capability {
+    StartupRead,
+    StartupWrite,
+    ProcessList,
+    ProcessOpen,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 4] = [
+        Capability::StartupRead,
+        Capability::StartupWrite,
+        Capability::ProcessList,
+        Capability::ProcessOpen,
+    ];
+
+    pub fn action(self) -> &'static str {
+        match self {
+            Capability::StartupRead => "read the Run key",
+            Capability::StartupWrite => "write the Run key",
+            Capability::ProcessList => "list processes",
+            Capability::ProcessOpen => "open process handles",
+        }
+    }
+
+    fn needed_by(self) -> &'static str {
+        match self {
+            Capability::StartupRead => "start-up state in status and list, audit-startup",
+            Capability::StartupWrite => "enable, disable, apply-startup, audit-startup --fix",
+            Capability::ProcessList => "status, list, run, stop, kill, restart, down, watch",
+            Capability::ProcessOpen => "stop, kill, restart, down",
+        }
+    }
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Capability::StartupRead => "startup-read",
+            Capability::StartupWrite => "startup-write",
+            Capability::ProcessList => "process-list",
+            Capability::ProcessOpen => "process-open",
+        })
+    }
+}
+
+// Each probe runs at most once per invocation, and only when something asks
+static PROBES: [OnceLock<Option<String>>; 4] = [const { OnceLock::new() }; 4];
+
+// The cheapest call that fails the way the real operation would
+fn check(capability: Capability) -> Result<(), SvcError> {
+    match capability {
+        Capability::StartupRead => startup::probe_access(RUN_KEY, false),
+        Capability::StartupWrite => startup::probe_access(RUN_KEY, true),
+        Capability::ProcessList => procinfo::probe_snapshot(),
+        Capability::ProcessOpen => procinfo::probe_open(),
+    }
+}
+
+// Why svc can't do it here, or None when it can
+pub fn missing(capability: Capability) -> Option<&'static str> {
+    PROBES[capability as usize]
+        .get_or_init(|| check(capability).err().map(|err| err.to_string()))
+        .as_deref()
+}
+
+// Refuse before any side effect rather than fail halfway through
+pub fn require(capability: Capability) -> Result<(), SvcError> {
+    match missing(capability) {
+        Some(reason) => Err(SvcError::CapabilityMissing {
+            capability,
+            reason: reason.to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+#[derive(Serialize)]
+struct Probed {
+    capability: Capability,
+    available: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'static str>,
+    needed_by: &'static str,
+}
+
+// `svc doctor`: the full capability matrix of this account
+pub fn doctor() -> Result<(), SvcError> {
+    let probed: Vec<Probed> = Capability::ALL
+        .iter()
+        .map(|&capability| Probed {
+            capability,
+            available: missing(capability).is_none(),
+            reason: missing(capability),
+            needed_by: capability.needed_by(),
+        })
+        .collect();
+    let (flavor, features) = flavor::report();
+    if output::options().json {
+        let report = serde_json::json!({
+            "elevated": elevation::is_elevated(),
+            "capabilities": probed,
+            "platform": { "flavor": flavor, "features": features },
+        });
+        println!("{}", serde_json::to_string_pretty(&report).map_err(std::io::Error::other)?);
+        return Ok(());
+    }
+    println!("Platform: {}", flavor.describe());
+    println!("Elevated: {}", if elevation::is_elevated() { "yes" } else { "no" });
+    for probe in &probed {
+        let state = match probe.reason {
+            None => format!("{:<7}", "ok").green().to_string(),
+            Some(_) => format!("{:<7}", "missing").red().bold().to_string(),
+        };
+        println!("  {:<13}  {}  {}", probe.capability.to_string(), state, probe.needed_by.dimmed());
+        if let Some(reason) = probe.reason {
+            println!("  {:<13}  {}", "", reason);
+        }
+    }
+    println!("Features:");
+    for feature in &features {
+        let state = match feature.state() {
+            "ok" => format!("{:<8}", "ok").green().to_string(),
+            "degraded" => format!("{:<8}", "degraded").yellow().to_string(),
+            state => format!("{:<8}", state).red().bold().to_string(),
+        };
+        println!("  {:<16}  {}  {}", feature.feature.to_string(), state, feature.needed_by.dimmed());
+        for note in feature.constraint.iter().chain(&feature.fallback) {
+            println!("  {:<16}  {}", "", note);
+        }
+    }
+    Ok(())
+}
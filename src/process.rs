@@ -0,0 +1,951 @@
+use crate::backends::{self, StartupEntry};
+use crate::cli::SessionFilter;
+use crate::effects::{self, Effect};
+use crate::elevation::{self, Access};
+use crate::encoding::{self, Encoding};
+use crate::flavor::{self, Feature};
+use crate::pe::{self, Subsystem};
+use crate::{cancel, capture, filelock, history, killguard, logfile, logs, mutex, output, priority};
+use crate::{procinfo, progress, retry, runas, scratch, shells, spawned, state, steps, stopsignal, terminal, token};
+use crate::{Service, ServiceType, SvcError};
+use colored::Colorize;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const DEFAULT_STOP_GRACE: Duration = Duration::from_secs(10);
+const DEFAULT_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+const STOP_POLL: Duration = Duration::from_millis(250);
+
+// Launch an Executable in the background and return its PID
+fn run_executable(service: &Service, work_at: &str) -> Result<u64, SvcError> {
+    if let Some(run_as) = &service.run_as {
+        return runas::spawn(service, run_as, work_at);
+    }
+
+    let path = &service.path;
+    let mut command = Command::new(path.as_ref());
+    command.args(service.args.iter().map(AsRef::as_ref));
+    apply_env(&mut command, service);
+    if !work_at.is_empty() {
+        command.current_dir(work_at);
+    }
+    if let Some(log) = logs::open(service)? {
+        command.stdout(log.try_clone()?).stderr(log);
+    }
+
+    let child = command.spawn()?; // Run in background
+    let pid = u64::from(child.id());
+    if let Err(err) = spawned::record(&service.name, pid) {
+        eprintln!("{} could not record PID {}: {}", "warning:".yellow().bold(), pid, err);
+    }
+    if service.priority.is_some() || service.affinity.is_some() {
+        let applied = service.affinity.as_deref().map(priority::mask).transpose();
+        if let Err(err) = applied.and_then(|affinity| Ok(priority::apply(pid, service.priority, affinity)?)) {
+            eprintln!("{} could not set priority or affinity: {}", "warning:".yellow().bold(), err);
+        }
+    }
+    spawned::hold(&service.name, child);
+    Ok(pid)
+}
+
+// Run a Util to its end; the tail of what it printed, stderr and with capture_stdout stdout
+fn run_util(service: &Service, work_at: &str, capture: Option<&Path>) -> Result<Vec<String>, SvcError> {
+    let scratch = if service.temp_work_dir {
+        Some(scratch::create(&service.name)?)
+    } else {
+        None
+    };
+    let dir = scratch.as_deref().and_then(Path::to_str).unwrap_or(work_at);
+    let result = run_util_in(service, dir, scratch.as_deref(), capture);
+    if let Some(dir) = &scratch {
+        scratch::finish(&service.name, dir, result.is_ok());
+    }
+    result
+}
+
+fn run_util_in(
+    service: &Service,
+    work_at: &str,
+    scratch: Option<&Path>,
+    capture: Option<&Path>,
+) -> Result<Vec<String>, SvcError> {
+    // Up front, since spawning would only say "program not found" without naming it
+    if shells::find_program(&service.interpreter).is_none() {
+        return Err(SvcError::InterpreterNotFound {
+            service: service.name.to_string(),
+            interpreter: service.interpreter.to_string(),
+        });
+    }
+    let mut command = Command::new(service.interpreter.as_ref());
+    command.args(&service.interpreter_args);
+    command.args(encoding::chcp_prefix(service));
+    // A relative script path must not be looked up inside the scratch directory
+    match scratch {
+        Some(_) => command.arg(std::path::absolute(service.path.as_ref())?),
+        None => command.arg(service.path.as_ref()),
+    };
+    command.args(service.args.iter().map(AsRef::as_ref));
+    apply_env(&mut command, service);
+    if !work_at.is_empty() {
+        command.current_dir(work_at);
+    }
+
+    // Keep the output tail so a failure can say what the script printed
+    command.stderr(Stdio::piped());
+    let quiet = output::options().quiet;
+    let capture = match capture {
+        Some(path) => Some(logfile::open_shared(path).map_err(|err| SvcError::LogFileFailed {
+            path: path.display().to_string(),
+            reason: err.to_string(),
+        })?),
+        None => None,
+    };
+    if service.capture_stdout || capture.is_some() {
+        command.stdout(Stdio::piped());
+    } else if quiet {
+        command.stdout(Stdio::null());
+    }
+    // What --capture tees stdout to; capture_stdout keeps it off the console as before
+    let mut copies: Vec<Box<dyn Write + Send>> = Vec::new();
+    if let Some(file) = capture {
+        copies.push(Box::new(file));
+        if !quiet && !service.capture_stdout {
+            copies.push(Box::new(std::io::stdout()));
+        }
+    }
+
+    let mut child = command.spawn()?;
+    // So `svc status` and `svc kill` find the interpreter from another console
+    let pid = u64::from(child.id());
+    if let Err(err) = spawned::record(&service.name, pid) {
+        eprintln!("{} could not record PID {}: {}", "warning:".yellow().bold(), pid, err);
+    }
+    let stdout = child.stdout.take().map(|stdout| capture::tee_lines(stdout, service.encoding, copies));
+    let stderr = child.stderr.take().map(|stderr| capture::tail_lines(stderr, service.encoding));
+    let scratch = scratch.map(|dir| dir.display().to_string());
+    let waited = cancel::wait(&mut child);
+    let _ = spawned::forget(&service.name, pid);
+    let status = match waited {
+        Ok(status) => status,
+        Err(err) => {
+            let mut record = history::HistoryRecord::new(&service.name, "run", false);
+            record.scratch = scratch;
+            let _ = history::record(&record);
+            return Err(err);
+        }
+    };
+
+    // Joined either way, so everything --capture tees is written before svc returns
+    let stdout = capture::join_tail(stdout);
+    let mut output = if service.capture_stdout { stdout } else { Vec::new() };
+    output.extend(capture::join_tail(stderr));
+    if output.len() > capture::TAIL_LINES {
+        output.drain(..output.len() - capture::TAIL_LINES);
+    }
+
+    let mut record = history::HistoryRecord::new(&service.name, "run", status.success());
+    record.exit_code = status.code();
+    record.scratch = scratch;
+
+    if status.success() {
+        let _ = history::record(&record);
+        Ok(output)
+    } else {
+        record.output = output.clone();
+        let _ = history::record(&record);
+        Err(SvcError::UtilFailed {
+            path: service.path.to_string(),
+            status: status.to_string(),
+            code: status.code(),
+            output,
+        })
+    }
+}
+
+pub fn work_dir<'a>(service: &'a Service) -> &'a str {
+    if service.work_at.is_empty() {
+        Path::new(service.path.as_ref())
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .to_str()
+            .unwrap_or(".")
+    } else {
+        &service.work_at
+    }
+}
+
+// Per-invocation changes to how run_service starts a service
+#[derive(Default)]
+pub struct RunOverride {
+    // In place of the configured directory, already checked by checked_work_dir
+    pub work_at: Option<String>,
+    // Start another instance even though one is running
+    pub force: bool,
+    // How long to wait for the service's lock file when another run holds it
+    pub lock_timeout: Option<Duration>,
+    // Util only: stdout is also appended to this file
+    pub capture: Option<PathBuf>,
+}
+
+// What starting a service came to, for the command to report
+#[derive(Debug, PartialEq, Eq)]
+pub enum RunOutcome {
+    // An Executable left running in the background, as run_as.user when it has one
+    Started { pid: u64, user: Option<String> },
+    // A Util that exited successfully, with the tail of its output
+    Finished { output: Vec<String> },
+}
+
+// What svc does to processes, so the logic around it can run against fake ones
+pub trait ProcessBackend {
+    // PIDs running the service
+    fn pids(&self, service: &Service) -> Result<Vec<u64>, SvcError>;
+    // Launch an Executable in the background and return its PID
+    fn spawn(&self, service: &Service, work_at: &str) -> Result<u64, SvcError>;
+    // Run a Util to its end, failing with UtilFailed when it exits unsuccessfully
+    fn run_util(&self, service: &Service, work_at: &str, capture: Option<&Path>) -> Result<Vec<String>, SvcError>;
+    // Terminate the processes outright
+    fn terminate(&self, service: &Service, pids: &[u64]) -> Result<(), SvcError>;
+}
+
+// The processes of this machine
+pub struct System;
+
+impl ProcessBackend for System {
+    fn pids(&self, service: &Service) -> Result<Vec<u64>, SvcError> {
+        get_pids(service)
+    }
+
+    fn spawn(&self, service: &Service, work_at: &str) -> Result<u64, SvcError> {
+        run_executable(service, work_at)
+    }
+
+    fn run_util(&self, service: &Service, work_at: &str, capture: Option<&Path>) -> Result<Vec<String>, SvcError> {
+        run_util(service, work_at, capture)
+    }
+
+    fn terminate(&self, service: &Service, pids: &[u64]) -> Result<(), SvcError> {
+        terminate(service, pids)
+    }
+}
+
+// The legacy `at <dir>` directory, relative to the caller's CWD, checked up front
+// instead of surfacing as a spawn error
+pub fn checked_work_dir(service: &Service, dir: &str) -> Result<String, SvcError> {
+    let path = std::path::absolute(dir)?;
+    let invalid = |reason: &str| SvcError::WorkDirInvalid {
+        service: service.name.to_string(),
+        path: path.display().to_string(),
+        reason: reason.to_string(),
+    };
+    if path.is_file() {
+        return Err(invalid("is a file, not a directory"));
+    }
+    if !path.is_dir() {
+        return Err(invalid("does not exist"));
+    }
+    path.to_str().map(String::from).ok_or_else(|| invalid("is not valid Unicode"))
+}
+
+// Start a service unless it already runs: its lock file, steps and then the process itself
+pub fn start(backend: &dyn ProcessBackend, service: &Service, with: &RunOverride) -> Result<RunOutcome, SvcError> {
+    let work_at = with.work_at.as_deref().unwrap_or_else(|| work_dir(service));
+    // Held until a Util finishes; an Executable only keeps it through its steps and launch
+    let _held = match &service.lock {
+        Some(lock) => Some(filelock::acquire(
+            &service.name,
+            &Path::new(work_at).join(expand_env(lock)),
+            with.lock_timeout,
+        )?),
+        None => None,
+    };
+    let running = || -> Result<bool, SvcError> {
+        let mut pids = backend.pids(service)?;
+        if service.per_session {
+            procinfo::retain_session(&mut pids, SessionFilter::Current);
+        }
+        Ok(!pids.is_empty() && !with.force)
+    };
+    // Held until the process exists, so a concurrent run sees it as running. A Util only exists
+    // while run_util waits on it, so that keeps it until the Util finishes; a run meanwhile
+    // finds the Util's recorded process and is told it runs instead of waiting out the lock
+    let util = matches!(service.service_type, ServiceType::Util);
+    if util && running()? {
+        return Err(SvcError::ServiceIsRunning);
+    }
+    let _lock = match mutex::lock("run", &service.name) {
+        Err(SvcError::ServiceBusy(_)) if util && running()? => return Err(SvcError::ServiceIsRunning),
+        lock => lock?,
+    };
+    if running()? {
+        return Err(SvcError::ServiceIsRunning);
+    }
+    if let Some(signal) = &service.stop_signal {
+        stopsignal::clear(signal, work_at)?;
+    }
+    if !service.steps.is_empty() {
+        steps::run(service, work_at)?;
+    }
+    if let ServiceType::Executable = service.service_type {
+        let pid = backend.spawn(service, work_at)?;
+        let user = service.run_as.as_ref().map(|run_as| run_as.user.to_string());
+        return Ok(RunOutcome::Started { pid, user });
+    }
+    // Only the Util itself is run again on failure; its steps and lock are done once
+    let run = || backend.run_util(service, work_at, with.capture.as_deref());
+    let output = match &service.retry {
+        Some(policy) => policy.run(&service.title(), |err| matches!(err, SvcError::UtilFailed { .. }), run)?,
+        None => run()?,
+    };
+    Ok(RunOutcome::Finished { output })
+}
+
+// Replace %NAME% and ${NAME} with the variable's value; unknown names stay as written,
+// as cmd leaves them
+pub fn expand_env(value: &str) -> String {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(at) = rest.find(['%', '$']) {
+        expanded.push_str(&rest[..at]);
+        let tail = &rest[at..];
+        let reference = if let Some(inner) = tail.strip_prefix('%') {
+            inner.find('%').map(|end| (&inner[..end], end + 2))
+        } else if let Some(inner) = tail.strip_prefix("${") {
+            inner.find('}').map(|end| (&inner[..end], end + 3))
+        } else {
+            None
+        };
+        match reference {
+            Some((name, length)) if !name.is_empty() => {
+                match std::env::var(name) {
+                    Ok(found) => expanded.push_str(&found),
+                    Err(_) => expanded.push_str(&tail[..length]),
+                }
+                rest = &tail[length..];
+            }
+            _ => {
+                expanded.push_str(&tail[..1]);
+                rest = &tail[1..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+// UTF-8 output is asked of Python first, so the service's own env can still say otherwise
+// What svc sets for the service's process on top of its own environment, values expanded
+pub fn env_vars(service: &Service) -> Vec<(String, String)> {
+    let utf8 = (service.encoding == Some(Encoding::Utf8)).then(|| ("PYTHONIOENCODING".to_string(), "utf-8".to_string()));
+    utf8.into_iter()
+        .chain(service.env.iter().map(|(key, value)| (key.clone(), expand_env(value))))
+        .collect()
+}
+
+pub fn apply_env(command: &mut Command, service: &Service) {
+    command.envs(env_vars(service));
+}
+
+#[derive(Debug, Serialize)]
+pub struct ServiceStatus {
+    pub pids: Vec<u64>,
+    // Every backend that starts the service, not just the one svc uses
+    pub startup: Vec<StartupEntry>,
+}
+
+impl ServiceStatus {
+    pub fn is_start_up(&self) -> bool {
+        !self.startup.is_empty()
+    }
+}
+
+pub fn get_status(service: &Service) -> Result<ServiceStatus, SvcError> {
+    status(&System, service)
+}
+
+pub fn status(backend: &dyn ProcessBackend, service: &Service) -> Result<ServiceStatus, SvcError> {
+    let pids = backend.pids(service)?;
+    let startup = backends::query(service)?;
+    Ok(ServiceStatus { pids, startup })
+}
+
+// start and status against one backend, for tools that drive services through the library
+pub struct ServiceManager<'b> {
+    backend: &'b dyn ProcessBackend,
+}
+
+impl ServiceManager<'static> {
+    // The processes of this machine
+    pub fn system() -> Self {
+        ServiceManager { backend: &System }
+    }
+}
+
+impl<'b> ServiceManager<'b> {
+    pub fn with_backend(backend: &'b dyn ProcessBackend) -> Self {
+        ServiceManager { backend }
+    }
+
+    pub fn start(&self, service: &Service, with: &RunOverride) -> Result<RunOutcome, SvcError> {
+        start(self.backend, service, with)
+    }
+
+    pub fn status(&self, service: &Service) -> Result<ServiceStatus, SvcError> {
+        status(self.backend, service)
+    }
+
+    // Whether any process runs the service, without asking the start-up backends
+    pub fn is_running(&self, service: &Service) -> Result<bool, SvcError> {
+        Ok(!self.backend.pids(service)?.is_empty())
+    }
+}
+
+// Only the PIDs of get_status, without asking every start-up backend; for polling
+pub fn get_pids(service: &Service) -> Result<Vec<u64>, SvcError> {
+    running_pids(service, &procinfo::Images::take())
+}
+
+// The PIDs part of get_status, matched against a snapshot that may be shared by many services
+pub fn running_pids(service: &Service, images: &procinfo::Images) -> Result<Vec<u64>, SvcError> {
+    // A terminal profile's processes are only told apart by command line; WMI is only
+    // asked where there is no native snapshot
+    let native = match service.terminal_profile {
+        Some(_) => images.pids_by_command_line(&service.path),
+        None => images.pids(&service.path),
+    };
+    let pids: Vec<u64> = if let Some(pids) = native {
+        pids
+    } else if flavor::missing(Feature::Wmi).is_some() {
+        // What svc launched itself is still found below
+        Vec::new()
+    } else {
+        let script = format!(
+            r#"Get-WmiObject Win32_Process | Where-Object {{ {} }} | Select-Object -ExpandProperty ProcessId"#,
+            terminal::process_filter(service)
+        );
+        let stdout = retry::transient("the WMI process query", || {
+            capture::helper_stdout(Command::new("powershell").args(["-Command", &script]))
+        })?;
+        stdout
+            .lines()
+            .filter_map(|line| line.trim().parse::<u64>().ok())
+            .collect()
+    };
+
+    // What svc launched itself first, so a script or a renamed binary is found too, and one
+    // launched by this invocation before it shows up anywhere else
+    let mut pids = pids;
+    for pid in spawned::held_pids(&service.name).into_iter().chain(spawned::live_pids(&service.name)?) {
+        if !pids.contains(&pid) {
+            pids.push(pid);
+        }
+    }
+    // Processes of other users can't be matched by path, so trust the PID recorded at launch
+    if service.run_as.is_some() {
+        if let Some(pid) = state::read_pid_file(&runas::pid_file(&service.name))? {
+            if !pids.contains(&pid) && is_pid_alive(pid)? {
+                pids.push(pid);
+            }
+        }
+    }
+    Ok(pids)
+}
+
+// The configured service a process belongs to, matched the way status finds a service's
+// processes, so a path differing only in case or 8.3 form still counts
+pub fn owner_of<'a>(services: &'a [Service<'a>], pid: u64) -> Result<&'a Service<'a>, SvcError> {
+    if !is_pid_alive(pid)? {
+        return Err(SvcError::ProcessNotFound(pid));
+    }
+    let images = procinfo::Images::take();
+    let owns = services
+        .par_iter()
+        .map(|service| Ok(running_pids(service, &images)?.contains(&pid)))
+        .collect::<Result<Vec<bool>, SvcError>>()?;
+    match owns.iter().position(|&owns| owns) {
+        Some(at) => Ok(&services[at]),
+        None => Err(SvcError::ProcessNotManaged {
+            pid,
+            image: procinfo::image_path(pid),
+        }),
+    }
+}
+
+pub fn is_pid_alive(pid: u64) -> Result<bool, SvcError> {
+    if let Some(alive) = spawned::held_alive(pid) {
+        return Ok(alive);
+    }
+    let output = capture::output(Command::new("tasklist").args(["/FI", &format!("PID eq {}", pid), "/FO", "CSV", "/NH"]))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.contains(&format!("\"{}\"", pid)))
+}
+
+// Whether the PID is a running svc rather than a process given the number of one that exited;
+// one whose image can't be read is judged by its PID alone
+pub fn is_svc_alive(pid: u64) -> Result<bool, SvcError> {
+    if !is_pid_alive(pid)? {
+        return Ok(false);
+    }
+    let (Some(image), Ok(own)) = (procinfo::image_path(pid), std::env::current_exe()) else {
+        return Ok(true);
+    };
+    let name = |path: &Path| path.file_name().map(|name| name.to_string_lossy().to_lowercase());
+    Ok(name(Path::new(&image)) == name(&own))
+}
+
+// Those of the PIDs still running once they have all exited or the deadline has passed; `phase`
+// is what progress events call the wait
+pub fn wait_gone(service: &Service, pids: &[u64], deadline: Instant, phase: &'static str) -> Result<Vec<u64>, SvcError> {
+    let mut phase = progress::Phase::begin("stopping", &service.name, phase);
+    loop {
+        let running = get_pids(service)?;
+        let remaining: Vec<u64> = pids.iter().copied().filter(|pid| running.contains(pid)).collect();
+        if remaining.is_empty() || Instant::now() >= deadline {
+            return Ok(remaining);
+        }
+        cancel::sleep(STOP_POLL)?;
+        phase.tick();
+    }
+}
+
+// Raise the stop signal and wait out the grace period; returns the PIDs still running
+pub fn request_stop(
+    service: &Service,
+    signal: &stopsignal::StopSignal,
+    pids: Vec<u64>,
+    grace: Duration,
+) -> Result<Vec<u64>, SvcError> {
+    let work_at = work_dir(service);
+    let mechanism = signal.describe(work_at);
+    println!(
+        "Asking service {} to stop via {}, waiting up to {}s.",
+        service.name.cyan(),
+        mechanism,
+        grace.as_secs_f64()
+    );
+
+    let raised = stopsignal::raise(signal, work_at)?;
+    let remaining = wait_gone(service, &pids, Instant::now() + grace, "signal")?;
+    drop(raised);
+
+    if remaining.is_empty() {
+        println!("Service {} stopped via {}.", service.name.cyan(), mechanism);
+    } else {
+        println!(
+            "Service {} did not stop within {}s, terminating it.",
+            service.name.cyan(),
+            grace.as_secs_f64()
+        );
+    }
+    Ok(remaining)
+}
+
+pub fn adjust_service(service: &Service, priority: Option<priority::Priority>, affinity: Option<&[u32]>) -> Result<(), SvcError> {
+    let mask = affinity.map(priority::mask).transpose()?;
+    if mask == Some(0) {
+        return Err(SvcError::InvalidCommand("--affinity needs at least one CPU number".to_string()));
+    }
+    let pids = get_pids(service)?;
+    if pids.is_empty() {
+        return Err(SvcError::ServiceIsNotRunning);
+    }
+
+    let effect = Effect::Adjust {
+        service: service.name.to_string(),
+        pids: pids.clone(),
+    };
+    effects::perform(effect, || {
+        let mut failed = 0;
+        for &pid in &pids {
+            match priority::apply(pid, priority, mask) {
+                // Read back, so what's printed is what the process has now
+                Ok(()) => println!("PID {}: {}", pid.to_string().green(), priority::query(pid)),
+                Err(err) => {
+                    failed += 1;
+                    println!("PID {}: {}", pid.to_string().red(), err);
+                }
+            }
+        }
+        if failed > 0 {
+            return Err(SvcError::AdjustFailed {
+                failed,
+                total: pids.len(),
+            });
+        }
+        Ok(())
+    })
+    .map(drop)
+}
+
+// The service's PIDs in the session, or ServiceIsNotRunning when there are none
+pub fn session_pids(service: &Service, session: SessionFilter) -> Result<Vec<u64>, SvcError> {
+    let mut pids = get_pids(service)?;
+    procinfo::retain_session(&mut pids, session);
+    if pids.is_empty() {
+        return Err(SvcError::ServiceIsNotRunning);
+    }
+    Ok(pids)
+}
+
+// force also takes PIDs that matched only loosely without asking
+pub fn kill_service(service: &Service, session: SessionFilter, force: bool) -> Result<(), SvcError> {
+    kill_within(service, session, force, Duration::MAX).map(drop)
+}
+
+// As kill_service, with the stop_signal's grace cut to `cap`; true when the service exited on
+// its signal rather than being terminated
+pub fn kill_within(service: &Service, session: SessionFilter, force: bool, cap: Duration) -> Result<bool, SvcError> {
+    kill_pids_within(service, session_pids(service, session)?, force, cap)
+}
+
+// The kill of kill_within for just these processes of the service
+pub fn kill_pids_within(service: &Service, pids: Vec<u64>, force: bool, cap: Duration) -> Result<bool, SvcError> {
+    kill_screened(service, killguard::screen(service, pids, force)?, cap)
+}
+
+fn kill_screened(service: &Service, pids: Vec<u64>, cap: Duration) -> Result<bool, SvcError> {
+
+    let Some(signal) = &service.stop_signal else {
+        return terminate(service, &pids).map(|()| false);
+    };
+    let effect = Effect::Stop {
+        service: service.name.to_string(),
+        pids: pids.clone(),
+    };
+    let exited = effects::perform(effect, || {
+        let grace = service.stop_grace.as_ref().map_or(DEFAULT_STOP_GRACE, |grace| grace.get());
+        let pids = request_stop(service, signal, pids, grace.min(cap))?;
+        if pids.is_empty() {
+            return Ok(true);
+        }
+        terminate(service, &pids).map(|()| false)
+    })?;
+    Ok(exited.unwrap_or(false))
+}
+
+// taskkill without /F posts WM_CLOSE to the process's windows, as closing them by hand
+// would; returns the PIDs still running after the timeout, or at once for processes
+// without a window, which taskkill can only terminate
+fn close_windows(service: &Service, pids: Vec<u64>, timeout: Duration) -> Result<Vec<u64>, SvcError> {
+    let (asked, windowless): (Vec<u64>, Vec<u64>) = pids
+        .into_iter()
+        .partition(|pid| capture::run_helper(Command::new("taskkill").arg("/PID").arg(pid.to_string())).is_ok());
+    if asked.is_empty() {
+        println!("Service {} has no window to close, force killing it.", service.name.cyan());
+        return Ok(windowless);
+    }
+    println!(
+        "Asking service {} to close, waiting up to {}s.",
+        service.name.cyan(),
+        timeout.as_secs_f64()
+    );
+
+    let mut remaining = wait_gone(service, &asked, Instant::now() + timeout, "graceful")?;
+    if remaining.is_empty() && windowless.is_empty() {
+        println!("Service {} stopped gracefully.", service.name.cyan());
+    } else if !remaining.is_empty() {
+        println!(
+            "Service {} did not stop within {}s, force killing it.",
+            service.name.cyan(),
+            timeout.as_secs_f64()
+        );
+    }
+    remaining.extend(windowless);
+    Ok(remaining)
+}
+
+// A console program has no window for taskkill to close, so without a stop_signal its stop
+// can only terminate it
+pub fn console_program(service: &Service) -> bool {
+    let executable = matches!(service.service_type, ServiceType::Executable);
+    executable && pe::inspect(Path::new(service.path.as_ref())).is_some_and(|info| info.subsystem == Subsystem::Console)
+}
+
+// How `svc stop` goes about it, for which
+pub fn stop_method(service: &Service, work_at: &str) -> String {
+    let timeout = service.stop_timeout.as_ref().map_or(DEFAULT_STOP_TIMEOUT, |timeout| timeout.get());
+    match &service.stop_signal {
+        Some(signal) => format!("{}, terminated after {}s", signal.describe(work_at), timeout.as_secs_f64()),
+        None if console_program(service) => "terminated, a console program has no window to close".to_string(),
+        None => format!("its windows closed, terminated after {}s", timeout.as_secs_f64()),
+    }
+}
+
+// Ask the service to exit through its stop_signal or its windows, and terminate whatever
+// is left after stop_timeout
+pub fn stop_service(service: &Service, session: SessionFilter, force: bool) -> Result<(), SvcError> {
+    stop_pids(service, killguard::screen(service, session_pids(service, session)?, force)?)
+}
+
+// The stop of stop_service for just these processes, already screened
+pub fn stop_pids(service: &Service, pids: Vec<u64>) -> Result<(), SvcError> {
+    let effect = Effect::Stop {
+        service: service.name.to_string(),
+        pids: pids.clone(),
+    };
+    effects::perform(effect, || {
+        let timeout = service.stop_timeout.as_ref().map_or(DEFAULT_STOP_TIMEOUT, |timeout| timeout.get());
+        let remaining = match &service.stop_signal {
+            Some(signal) => request_stop(service, signal, pids, timeout)?,
+            None if console_program(service) => {
+                println!("Service {} is a console program without a stop_signal, terminating it.", service.name.cyan());
+                pids
+            }
+            None => close_windows(service, pids, timeout)?,
+        };
+        if !remaining.is_empty() {
+            progress::Phase::begin("stopping", &service.name, "force");
+            terminate(service, &remaining)?;
+        }
+        progress::emit("stopped", Some(&service.name), serde_json::json!({}));
+        Ok(())
+    })
+    .map(drop)
+}
+
+// kill_service, then wait for the processes to be gone: taskkill returns before a
+// process has fully exited, so a run right after it would still see it running
+pub fn kill_and_wait(service: &Service, session: SessionFilter, force: bool, timeout: Duration) -> Result<(), SvcError> {
+    kill_pids_and_wait(service, session_pids(service, session)?, force, timeout)
+}
+
+// The kill and wait of kill_and_wait for just these processes of the service
+pub fn kill_pids_and_wait(service: &Service, pids: Vec<u64>, force: bool, timeout: Duration) -> Result<(), SvcError> {
+    let killed = killguard::screen(service, pids, force)?;
+    kill_screened(service, killed.clone(), Duration::MAX)?;
+    // Nothing was asked to exit
+    if effects::dry_run() {
+        return Ok(());
+    }
+    let deadline = Instant::now() + timeout;
+    let mut phase = progress::Phase::begin("stopping", &service.name, "exit");
+    loop {
+        let pids: Vec<u64> = get_pids(service)?.into_iter().filter(|pid| killed.contains(pid)).collect();
+        if pids.is_empty() {
+            progress::emit("stopped", Some(&service.name), serde_json::json!({}));
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(SvcError::StillRunning {
+                service: service.name.to_string(),
+                pids,
+                waited: format!("{}s", timeout.as_secs_f64()),
+            });
+        }
+        cancel::sleep(STOP_POLL)?;
+        phase.tick();
+    }
+}
+
+// Terminate the processes outright, skipping any stop_signal
+pub fn terminate(service: &Service, pids: &[u64]) -> Result<(), SvcError> {
+    let effect = Effect::Terminate {
+        service: service.name.to_string(),
+        pids: pids.to_vec(),
+    };
+    effects::perform(effect, || terminate_now(service, pids)).map(drop)
+}
+
+fn terminate_now(service: &Service, pids: &[u64]) -> Result<(), SvcError> {
+    // Parallelize killing of PIDs; whether each was killed by us rides along with the result
+    let results: Vec<(u64, Result<bool, SvcError>)> = pids
+        .par_iter()
+        .map(|&pid| {
+            let kill = || capture::run_helper(Command::new("taskkill").arg("/F").arg("/PID").arg(pid.to_string()));
+            let result = match kill() {
+                // Denied opening it to terminate: the tokens say why, or let a retry through
+                Err(_) if elevation::denied(pid, Access::Terminate) => token::retry_denied(pid, kill),
+                result => result,
+            };
+            match result {
+                Ok(()) => (pid, Ok(true)),
+                // Exited on its own in the meantime
+                Err(_) if !is_pid_alive(pid).unwrap_or(true) => (pid, Ok(false)),
+                Err(err) => (pid, Err(err)),
+            }
+        })
+        .collect();
+    // Reported once all are done, in PID order, rather than as the threads finish
+    let killed: Vec<String> = results
+        .iter()
+        .filter(|(_, result)| matches!(result, Ok(true)))
+        .map(|(pid, _)| format!("Service {} with PID {} killed.", service.name.cyan(), pid.to_string().green()))
+        .collect();
+    output::lines(&killed);
+
+    let Some((pid, Err(err))) = results.into_iter().find(|(_, result)| result.is_err()) else {
+        return Ok(());
+    };
+    // Terminating another account's process needs rights the current token may lack
+    match &service.run_as {
+        Some(run_as) if !matches!(err, SvcError::KillDenied { .. }) => {
+            let mut facts = token::gather(pid);
+            facts.owner.get_or_insert_with(|| run_as.user.to_string());
+            Err(token::explain(pid, &facts))
+        }
+        _ => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_config;
+    use std::cell::{Cell, RefCell};
+
+    // Processes that exist only in the test, recording where each start happened
+    #[derive(Default)]
+    struct Fake {
+        running: Vec<u64>,
+        started: RefCell<Vec<String>>,
+        // Util runs that exit with an error before one succeeds
+        failures: Cell<u32>,
+    }
+
+    impl ProcessBackend for Fake {
+        fn pids(&self, _service: &Service) -> Result<Vec<u64>, SvcError> {
+            Ok(self.running.clone())
+        }
+
+        fn spawn(&self, _service: &Service, work_at: &str) -> Result<u64, SvcError> {
+            self.started.borrow_mut().push(work_at.to_string());
+            Ok(4242)
+        }
+
+        fn run_util(&self, service: &Service, work_at: &str, _capture: Option<&Path>) -> Result<Vec<String>, SvcError> {
+            self.started.borrow_mut().push(work_at.to_string());
+            if self.failures.get() > 0 {
+                self.failures.set(self.failures.get() - 1);
+                return Err(SvcError::UtilFailed {
+                    path: service.path.to_string(),
+                    status: "exit code: 1".to_string(),
+                    code: Some(1),
+                    output: Vec::new(),
+                });
+            }
+            Ok(vec!["done".to_string()])
+        }
+
+        fn terminate(&self, _service: &Service, _pids: &[u64]) -> Result<(), SvcError> {
+            Ok(())
+        }
+    }
+
+    fn service(entry: &str) -> Service<'static> {
+        parse_config(entry).unwrap().services.remove(0)
+    }
+
+    #[test]
+    fn work_dir_falls_back_to_the_executables_directory() {
+        let api = service("- name: api\n  path: /opt/api/api.exe\n  type: Executable\n");
+        assert_eq!(work_dir(&api), "/opt/api");
+        let bare = service("- name: api\n  path: api.exe\n  type: Executable\n");
+        assert_eq!(work_dir(&bare), "");
+        let set = service("- name: api\n  path: /opt/api/api.exe\n  type: Executable\n  work_at: /srv\n");
+        assert_eq!(work_dir(&set), "/srv");
+    }
+
+    #[test]
+    fn start_spawns_in_the_working_directory() {
+        let backend = Fake::default();
+        let api = service("- name: api\n  path: /opt/api/api.exe\n  type: Executable\n");
+        let outcome = start(&backend, &api, &RunOverride::default()).unwrap();
+        assert_eq!(outcome, RunOutcome::Started { pid: 4242, user: None });
+        assert_eq!(*backend.started.borrow(), ["/opt/api"]);
+    }
+
+    #[test]
+    fn override_replaces_the_configured_directory() {
+        let backend = Fake::default();
+        let job = service("- name: job\n  path: /opt/job/job.py\n  type: Util\n  work_at: /srv\n");
+        let with = RunOverride {
+            work_at: Some("/tmp".to_string()),
+            ..RunOverride::default()
+        };
+        let outcome = start(&backend, &job, &with).unwrap();
+        assert_eq!(outcome, RunOutcome::Finished { output: vec!["done".to_string()] });
+        assert_eq!(*backend.started.borrow(), ["/tmp"]);
+    }
+
+    #[test]
+    fn running_service_is_not_started_twice() {
+        let backend = Fake {
+            running: vec![7],
+            ..Fake::default()
+        };
+        let api = service("- name: api\n  path: /opt/api/api.exe\n  type: Executable\n");
+        let err = start(&backend, &api, &RunOverride::default()).unwrap_err();
+        assert!(matches!(err, SvcError::ServiceIsRunning));
+        assert!(backend.started.borrow().is_empty());
+
+        let forced = RunOverride {
+            force: true,
+            ..RunOverride::default()
+        };
+        assert!(start(&backend, &api, &forced).is_ok());
+        assert_eq!(backend.started.borrow().len(), 1);
+    }
+
+    #[test]
+    fn a_util_that_is_still_running_is_reported_as_running() {
+        let backend = Fake {
+            running: vec![7],
+            ..Fake::default()
+        };
+        let job = service("- name: job\n  path: job.py\n  type: Util\n");
+        let err = start(&backend, &job, &RunOverride::default()).unwrap_err();
+        assert!(matches!(err, SvcError::ServiceIsRunning));
+        assert!(backend.started.borrow().is_empty());
+    }
+
+    #[test]
+    fn a_failing_util_is_run_again_as_its_retry_says() {
+        let job = service("- name: job\n  path: job.py\n  type: Util\n  retry: { attempts: 2, delay: 1ms }\n");
+        let backend = Fake::default();
+        backend.failures.set(2);
+        let outcome = start(&backend, &job, &RunOverride::default()).unwrap();
+        assert_eq!(outcome, RunOutcome::Finished { output: vec!["done".to_string()] });
+        assert_eq!(backend.started.borrow().len(), 3);
+
+        backend.failures.set(3);
+        let err = start(&backend, &job, &RunOverride::default()).unwrap_err();
+        assert!(matches!(err, SvcError::UtilFailed { .. }), "{}", err);
+        assert_eq!(backend.started.borrow().len(), 6);
+    }
+
+    #[test]
+    fn a_work_dir_must_be_an_existing_directory() {
+        let api = service("- name: api\n  path: /opt/api/api.exe\n  type: Executable\n");
+        let dir = std::env::temp_dir().join(format!("svc-workdir-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("api.exe");
+        std::fs::write(&file, "").unwrap();
+        let reason = |path: &Path| match checked_work_dir(&api, path.to_str().unwrap()) {
+            Err(SvcError::WorkDirInvalid { service, reason, .. }) => {
+                assert_eq!(service, "api");
+                reason
+            }
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(reason(&dir.join("missing")), "does not exist");
+        assert_eq!(reason(&file), "is a file, not a directory");
+        assert_eq!(checked_work_dir(&api, dir.to_str().unwrap()).unwrap(), dir.to_str().unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_relative_work_dir_is_taken_against_the_current_directory() {
+        let api = service("- name: api\n  path: /opt/api/api.exe\n  type: Executable\n");
+        let cwd = std::env::current_dir().unwrap();
+        // cargo runs tests from the crate root, which has src
+        assert_eq!(checked_work_dir(&api, "src").unwrap(), cwd.join("src").to_str().unwrap());
+        assert_eq!(Path::new(&checked_work_dir(&api, ".").unwrap()), std::path::absolute(".").unwrap());
+        let err = checked_work_dir(&api, "no-such-dir").unwrap_err();
+        assert!(err.to_string().contains(cwd.join("no-such-dir").to_str().unwrap()), "{}", err);
+    }
+}
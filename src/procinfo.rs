@@ -0,0 +1,791 @@
+use crate::cli::SessionFilter;
+use crate::pathcmp::{self, ComparablePath};
+use crate::pe::Arch;
+use crate::priority::{self, Priority};
+use crate::terminal::TERMINAL_HOSTS;
+use serde::Serialize;
+
+// How long CPU time is accumulated between the two reads of a sampling pass
+#[cfg(windows)]
+const CPU_SAMPLE: std::time::Duration = std::time::Duration::from_millis(200);
+
+// Resource usage of one process; fields stay empty for processes svc may not open
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessUsage {
+    pub pid: u64,
+    // Working set in bytes
+    pub memory: Option<u64>,
+    // Share of the whole machine over the sampling pass, 100 meaning every core busy
+    pub cpu: Option<f64>,
+    pub threads: Option<u32>,
+    pub handles: Option<u32>,
+    // Seconds since the process started
+    pub uptime: Option<u64>,
+    // Terminal Services session; 0 for services, 1 and up for interactive logons
+    pub session: Option<u32>,
+    pub priority: Option<Priority>,
+    // CPU numbers the process may run on
+    pub affinity: Option<Vec<u32>>,
+    // Kernel and user time so far in milliseconds, for metrics
+    #[serde(skip)]
+    pub cpu_time_ms: Option<u64>,
+}
+
+impl ProcessUsage {
+    pub(crate) fn unknown(pid: u64) -> Self {
+        let scheduling = priority::query(pid);
+        ProcessUsage {
+            pid,
+            memory: None,
+            cpu: None,
+            threads: None,
+            handles: None,
+            uptime: None,
+            session: session_id(pid),
+            priority: scheduling.priority,
+            affinity: scheduling.affinity.map(priority::cpus),
+            cpu_time_ms: None,
+        }
+    }
+}
+
+// Usage of every given PID out of one process snapshot and a single CPU sampling pass
+#[cfg(windows)]
+pub fn sample(pids: &[u64]) -> Vec<ProcessUsage> {
+    use crate::clock;
+    use std::collections::HashMap;
+    use std::time::SystemTime;
+    use windows_sys::Win32::Foundation::{CloseHandle, FILETIME, HANDLE, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+    };
+    use windows_sys::Win32::System::ProcessStatus::{K32GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS};
+    use windows_sys::Win32::System::Threading::{
+        GetProcessHandleCount, GetProcessTimes, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    fn ticks(time: &FILETIME) -> u64 {
+        (u64::from(time.dwHighDateTime) << 32) | u64::from(time.dwLowDateTime)
+    }
+
+    // (creation, kernel + user) in 100ns ticks
+    fn times(process: HANDLE) -> Option<(u64, u64)> {
+        let mut creation = FILETIME::default();
+        let mut exit = FILETIME::default();
+        let mut kernel = FILETIME::default();
+        let mut user = FILETIME::default();
+        let ok = unsafe { GetProcessTimes(process, &mut creation, &mut exit, &mut kernel, &mut user) };
+        (ok != 0).then(|| (ticks(&creation), ticks(&kernel) + ticks(&user)))
+    }
+
+    let mut threads: HashMap<u64, u32> = HashMap::new();
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
+        if snapshot != INVALID_HANDLE_VALUE {
+            let mut entry = PROCESSENTRY32W {
+                dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+                ..Default::default()
+            };
+            let mut more = Process32FirstW(snapshot, &mut entry) != 0;
+            while more {
+                threads.insert(u64::from(entry.th32ProcessID), entry.cntThreads);
+                more = Process32NextW(snapshot, &mut entry) != 0;
+            }
+            CloseHandle(snapshot);
+        }
+    }
+
+    let handles: Vec<(u64, Option<HANDLE>)> = pids
+        .iter()
+        .map(|&pid| {
+            let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid as u32) };
+            (pid, (!process.is_null()).then_some(process))
+        })
+        .collect();
+
+    let first: Vec<Option<(u64, u64)>> = handles.iter().map(|(_, process)| process.and_then(times)).collect();
+    std::thread::sleep(CPU_SAMPLE);
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get()) as f64;
+    let now = clock::unix_secs(SystemTime::now());
+
+    handles
+        .into_iter()
+        .zip(first)
+        .map(|((pid, process), first)| {
+            let mut usage = ProcessUsage::unknown(pid);
+            usage.threads = threads.get(&pid).copied();
+            let Some(process) = process else {
+                return usage;
+            };
+
+            let mut counters = PROCESS_MEMORY_COUNTERS {
+                cb: std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32,
+                ..Default::default()
+            };
+            if unsafe { K32GetProcessMemoryInfo(process, &mut counters, counters.cb) } != 0 {
+                usage.memory = Some(counters.WorkingSetSize as u64);
+            }
+            let mut count = 0;
+            if unsafe { GetProcessHandleCount(process, &mut count) } != 0 {
+                usage.handles = Some(count);
+            }
+            if let (Some((created, before)), Some((_, after))) = (first, times(process)) {
+                let elapsed = CPU_SAMPLE.as_nanos() as f64 / 100.0;
+                usage.cpu = Some(after.saturating_sub(before) as f64 / elapsed / cores * 100.0);
+                usage.cpu_time_ms = Some(after / 10_000);
+                // FILETIME counts from 1601, 11644473600 seconds before the Unix epoch
+                usage.uptime = Some(clock::uptime(pid, (created / 10_000_000).saturating_sub(11_644_473_600), now));
+            }
+
+            unsafe { CloseHandle(process) };
+            usage
+        })
+        .collect()
+}
+
+#[cfg(not(windows))]
+pub fn sample(pids: &[u64]) -> Vec<ProcessUsage> {
+    pids.iter().map(|&pid| ProcessUsage::unknown(pid)).collect()
+}
+
+struct Image {
+    pid: u64,
+    path: ComparablePath,
+    // File name as the snapshot reports it, e.g. WindowsTerminal.exe
+    name: String,
+}
+
+// Image paths of the processes svc may open, read once from a single snapshot and
+// without going through a console code page; None where there is no native way
+pub struct Images(Option<Vec<Image>>);
+
+impl Images {
+    #[cfg(windows)]
+    pub fn take() -> Images {
+        use crate::debugbundle;
+        use std::ffi::OsString;
+        use std::os::windows::ffi::OsStringExt;
+        use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+        use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+            CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+        };
+        use windows_sys::Win32::System::Threading::{
+            OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+        };
+
+        let mut images = Vec::new();
+        let mut recorded = Vec::new();
+        unsafe {
+            let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
+            if snapshot == INVALID_HANDLE_VALUE {
+                return Images(None);
+            }
+            let mut entry = PROCESSENTRY32W {
+                dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+                ..Default::default()
+            };
+            let mut buffer = vec![0u16; 32768];
+            let mut more = Process32FirstW(snapshot, &mut entry) != 0;
+            while more {
+                let pid = entry.th32ProcessID;
+                let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+                if !process.is_null() {
+                    let mut size = buffer.len() as u32;
+                    if QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, buffer.as_mut_ptr(), &mut size) != 0 {
+                        let name_length = entry.szExeFile.iter().position(|&unit| unit == 0).unwrap_or(entry.szExeFile.len());
+                        let path = OsString::from_wide(&buffer[..size as usize]);
+                        let name = String::from_utf16_lossy(&entry.szExeFile[..name_length]);
+                        if debugbundle::enabled() {
+                            recorded.push((u64::from(pid), name.clone(), path.to_string_lossy().to_string()));
+                        }
+                        images.push(Image {
+                            pid: u64::from(pid),
+                            path: pathcmp::lexical(&path),
+                            name,
+                        });
+                    }
+                    CloseHandle(process);
+                }
+                more = Process32NextW(snapshot, &mut entry) != 0;
+            }
+            CloseHandle(snapshot);
+        }
+        debugbundle::snapshot(recorded);
+        Images(Some(images))
+    }
+
+    #[cfg(not(windows))]
+    pub fn take() -> Images {
+        Images(None)
+    }
+
+    // PIDs started from the given executable; None when the caller has to ask WMI instead
+    pub fn pids(&self, path: &str) -> Option<Vec<u64>> {
+        let images = self.0.as_ref()?;
+        let written = pathcmp::lexical(std::ffi::OsStr::new(path));
+        // A relative path has nothing to be equal to, so its components have to end the image path
+        if !std::path::Path::new(path).is_absolute() {
+            return Some(images.iter().filter(|image| image.path.ends_with(&written)).map(|image| image.pid).collect());
+        }
+        // Both spellings, as a process started through a symlink may report either
+        let resolved = pathcmp::canonicalize_for_compare(path);
+        let pids = images
+            .iter()
+            .filter(|image| image.path == written || image.path == resolved)
+            .map(|image| image.pid)
+            .collect();
+        Some(pids)
+    }
+
+    // PIDs whose command line runs the executable, for a service opened in a terminal
+    // profile, whose own image is only the profile's shell; the terminal hosts carry the
+    // path on their command line too without being the service
+    pub fn pids_by_command_line(&self, path: &str) -> Option<Vec<u64>> {
+        let images = self.0.as_ref()?;
+        let pids = images
+            .iter()
+            .filter(|image| !TERMINAL_HOSTS.iter().any(|host| host.eq_ignore_ascii_case(&image.name)))
+            .filter(|image| command_line(image.pid).is_some_and(|line| mentions(&line, path)))
+            .map(|image| image.pid)
+            .collect();
+        Some(pids)
+    }
+}
+
+// Whether the path appears in the command line as a whole argument, quoted or not,
+// so C:\apps\foo.exe doesn't match a command line running C:\apps\foo.exe.bak
+fn mentions(command_line: &str, path: &str) -> bool {
+    let (line, path) = (command_line.to_lowercase(), path.to_lowercase());
+    let bounded = |c: Option<char>| c.is_none_or(|c| c == '"' || c.is_whitespace());
+    line.match_indices(&path).any(|(at, _)| {
+        bounded(line[..at].chars().next_back()) && bounded(line[at + path.len()..].chars().next())
+    })
+}
+
+// Full image path of one process, or None when it can't be opened
+#[cfg(windows)]
+pub fn image_path(pid: u64) -> Option<String> {
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid as u32) };
+    if process.is_null() {
+        return None;
+    }
+    let mut buffer = vec![0u16; 32768];
+    let mut size = buffer.len() as u32;
+    let ok = unsafe { QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, buffer.as_mut_ptr(), &mut size) } != 0;
+    unsafe { CloseHandle(process) };
+    ok.then(|| String::from_utf16_lossy(&buffer[..size as usize]))
+}
+
+#[cfg(not(windows))]
+pub fn image_path(_pid: u64) -> Option<String> {
+    None
+}
+
+// One family's table of listening TCP sockets, as u32s so the rows are aligned; the table can
+// grow between asking for its size and reading it, hence the retries
+#[cfg(windows)]
+fn tcp_table(family: u32) -> Option<Vec<u32>> {
+    use windows_sys::Win32::Foundation::{ERROR_INSUFFICIENT_BUFFER, NO_ERROR};
+    use windows_sys::Win32::NetworkManagement::IpHelper::{GetExtendedTcpTable, TCP_TABLE_OWNER_PID_LISTENER};
+
+    let mut size = 0u32;
+    for _ in 0..3 {
+        let mut table = vec![0u32; (size as usize).div_ceil(4).max(1)];
+        size = (table.len() * 4) as u32;
+        let result = unsafe {
+            GetExtendedTcpTable(table.as_mut_ptr().cast(), &mut size, 0, family, TCP_TABLE_OWNER_PID_LISTENER, 0)
+        };
+        match result {
+            NO_ERROR => return Some(table),
+            ERROR_INSUFFICIENT_BUFFER => continue,
+            _ => return None,
+        }
+    }
+    None
+}
+
+// The rows after a table's entry count
+#[cfg(windows)]
+fn tcp_rows<T>(table: &[u32]) -> &[T] {
+    let count = (table[0] as usize).min((table.len() - 1) * 4 / std::mem::size_of::<T>());
+    unsafe { std::slice::from_raw_parts(table[1..].as_ptr().cast::<T>(), count) }
+}
+
+// Every listening TCP port with the PID bound to it, IPv4 and IPv6, from one query of each table;
+// None when a table can't be read
+#[cfg(windows)]
+pub fn listening_ports() -> Option<Vec<(u16, u64)>> {
+    use windows_sys::Win32::NetworkManagement::IpHelper::{MIB_TCP6ROW_OWNER_PID, MIB_TCPROW_OWNER_PID};
+    const AF_INET: u32 = 2;
+    const AF_INET6: u32 = 23;
+
+    let port = |port: u32| u16::from_be(port as u16);
+    let v4 = tcp_table(AF_INET)?;
+    let v6 = tcp_table(AF_INET6)?;
+    let mut ports: Vec<(u16, u64)> = tcp_rows::<MIB_TCPROW_OWNER_PID>(&v4)
+        .iter()
+        .map(|row| (port(row.dwLocalPort), row.dwOwningPid as u64))
+        .chain(tcp_rows::<MIB_TCP6ROW_OWNER_PID>(&v6).iter().map(|row| (port(row.dwLocalPort), row.dwOwningPid as u64)))
+        .collect();
+    // A socket on both families, or on several addresses, is one binding
+    ports.sort_unstable();
+    ports.dedup();
+    Some(ports)
+}
+
+#[cfg(not(windows))]
+pub fn listening_ports() -> Option<Vec<(u16, u64)>> {
+    None
+}
+
+// When the process was created, in seconds since the Unix epoch; None when it can't be opened
+#[cfg(windows)]
+pub fn started_at(pid: u64) -> Option<u64> {
+    use windows_sys::Win32::Foundation::{CloseHandle, FILETIME};
+    use windows_sys::Win32::System::Threading::{GetProcessTimes, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION};
+
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid as u32) };
+    if process.is_null() {
+        return None;
+    }
+    let mut creation = FILETIME::default();
+    let mut exit = FILETIME::default();
+    let mut kernel = FILETIME::default();
+    let mut user = FILETIME::default();
+    let ok = unsafe { GetProcessTimes(process, &mut creation, &mut exit, &mut kernel, &mut user) } != 0;
+    unsafe { CloseHandle(process) };
+    let ticks = (u64::from(creation.dwHighDateTime) << 32) | u64::from(creation.dwLowDateTime);
+    // FILETIME counts 100ns ticks from 1601
+    ok.then(|| (ticks / 10_000_000).saturating_sub(11_644_473_600))
+}
+
+#[cfg(not(windows))]
+pub fn started_at(_pid: u64) -> Option<u64> {
+    None
+}
+
+// Command line of one process, read from the process itself; None when it can't be opened
+#[cfg(windows)]
+pub fn command_line(pid: u64) -> Option<String> {
+    use windows_sys::Wdk::System::Threading::{NtQueryInformationProcess, ProcessCommandLineInformation};
+    use windows_sys::Win32::Foundation::{CloseHandle, UNICODE_STRING};
+    use windows_sys::Win32::System::Threading::{OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION};
+
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid as u32) };
+    if process.is_null() {
+        return None;
+    }
+    // The first call only reports the size, a UNICODE_STRING followed by the text it points to
+    let mut needed = 0u32;
+    unsafe { NtQueryInformationProcess(process, ProcessCommandLineInformation, std::ptr::null_mut(), 0, &mut needed) };
+    let mut buffer = vec![0u64; (needed as usize).div_ceil(8)];
+    let status = unsafe {
+        NtQueryInformationProcess(
+            process,
+            ProcessCommandLineInformation,
+            buffer.as_mut_ptr().cast(),
+            (buffer.len() * 8) as u32,
+            &mut needed,
+        )
+    };
+    unsafe { CloseHandle(process) };
+    if needed == 0 || status < 0 {
+        return None;
+    }
+    let string = unsafe { &*buffer.as_ptr().cast::<UNICODE_STRING>() };
+    if string.Buffer.is_null() {
+        return None;
+    }
+    let units = unsafe { std::slice::from_raw_parts(string.Buffer, usize::from(string.Length) / 2) };
+    let line = String::from_utf16_lossy(units).trim().to_string();
+    (!line.is_empty()).then_some(line)
+}
+
+// Off Windows the command line comes from WMI, through whatever powershell is on PATH
+#[cfg(not(windows))]
+pub fn command_line(pid: u64) -> Option<String> {
+    let output = crate::capture::output(std::process::Command::new("powershell").args([
+        "-NoProfile",
+        "-Command",
+        &format!("(Get-WmiObject Win32_Process -Filter 'ProcessId={}').CommandLine", pid),
+    ]))
+    .ok()?;
+    let line = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!line.is_empty()).then_some(line)
+}
+
+// Where RTL_USER_PROCESS_PARAMETERS keeps the environment block and its size in bytes; the
+// struct windows-sys declares stops before them
+#[cfg(all(windows, target_pointer_width = "64"))]
+const ENVIRONMENT_OFFSETS: (usize, usize) = (0x80, 0x3f0);
+#[cfg(all(windows, target_pointer_width = "32"))]
+const ENVIRONMENT_OFFSETS: (usize, usize) = (0x48, 0x290);
+// Far beyond any real block; a larger size means the parameters weren't read right
+#[cfg(windows)]
+const MAX_ENVIRONMENT: usize = 16 << 20;
+
+#[cfg(windows)]
+fn read_memory(
+    process: windows_sys::Win32::Foundation::HANDLE,
+    address: usize,
+    buffer: &mut [u8],
+) -> std::io::Result<()> {
+    use windows_sys::Win32::System::Diagnostics::Debug::ReadProcessMemory;
+
+    let mut read = 0;
+    let ok = unsafe { ReadProcessMemory(process, address as _, buffer.as_mut_ptr().cast(), buffer.len(), &mut read) };
+    if ok == 0 || read != buffer.len() {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn read_usize(process: windows_sys::Win32::Foundation::HANDLE, address: usize) -> std::io::Result<usize> {
+    let mut bytes = [0u8; size_of::<usize>()];
+    read_memory(process, address, &mut bytes)?;
+    Ok(usize::from_ne_bytes(bytes))
+}
+
+// The environment block a process started with (or has since changed to), read from its PEB;
+// PermissionDenied for elevated processes and those of other users
+#[cfg(windows)]
+pub fn environment(pid: u64) -> std::io::Result<Vec<(String, String)>> {
+    use std::mem::offset_of;
+    use windows_sys::Wdk::System::Threading::{NtQueryInformationProcess, ProcessBasicInformation};
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{OpenProcess, PEB, PROCESS_BASIC_INFORMATION};
+    use windows_sys::Win32::System::Threading::{PROCESS_QUERY_INFORMATION, PROCESS_VM_READ};
+
+    let process = unsafe { OpenProcess(PROCESS_QUERY_INFORMATION | PROCESS_VM_READ, 0, pid as u32) };
+    if process.is_null() {
+        return Err(std::io::Error::last_os_error());
+    }
+    let read = || -> std::io::Result<Vec<u16>> {
+        let mut info: PROCESS_BASIC_INFORMATION = unsafe { std::mem::zeroed() };
+        let mut needed = 0u32;
+        let status = unsafe {
+            NtQueryInformationProcess(
+                process,
+                ProcessBasicInformation,
+                (&mut info as *mut PROCESS_BASIC_INFORMATION).cast(),
+                size_of::<PROCESS_BASIC_INFORMATION>() as u32,
+                &mut needed,
+            )
+        };
+        if status < 0 || info.PebBaseAddress.is_null() {
+            return Err(std::io::Error::other(format!("NtQueryInformationProcess failed with {:#x}", status)));
+        }
+        let parameters = read_usize(process, info.PebBaseAddress as usize + offset_of!(PEB, ProcessParameters))?;
+        let (block_at, size_at) = ENVIRONMENT_OFFSETS;
+        let block = read_usize(process, parameters + block_at)?;
+        let size = read_usize(process, parameters + size_at)?;
+        if block == 0 || size > MAX_ENVIRONMENT {
+            return Err(std::io::Error::other("the process parameters hold no environment block"));
+        }
+        let mut bytes = vec![0u8; size];
+        read_memory(process, block, &mut bytes)?;
+        Ok(bytes.chunks_exact(2).map(|pair| u16::from_ne_bytes([pair[0], pair[1]])).collect())
+    };
+    let units = read();
+    unsafe { CloseHandle(process) };
+    Ok(parse_environment(&units?))
+}
+
+#[cfg(not(windows))]
+pub fn environment(_pid: u64) -> std::io::Result<Vec<(String, String)>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "reading another process's environment needs Windows",
+    ))
+}
+
+// NAME=value strings up to an empty one; the per-drive "=C:=C:\dir" entries keep their
+// leading '=' in the name
+#[cfg(windows)]
+fn parse_environment(units: &[u16]) -> Vec<(String, String)> {
+    units
+        .split(|&unit| unit == 0)
+        .take_while(|entry| !entry.is_empty())
+        .filter_map(|entry| {
+            let entry = String::from_utf16_lossy(entry);
+            let split = entry.char_indices().skip(1).find(|(_, c)| *c == '=')?.0;
+            Some((entry[..split].to_string(), entry[split + 1..].to_string()))
+        })
+        .collect()
+}
+
+// The account a process runs as, DOMAIN\user; None when even that is out of reach
+#[cfg(windows)]
+pub fn owner(pid: u64) -> Option<String> {
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::Security::{GetTokenInformation, TokenUser, TOKEN_QUERY, TOKEN_USER};
+    use windows_sys::Win32::System::Threading::{OpenProcess, OpenProcessToken, PROCESS_QUERY_LIMITED_INFORMATION};
+
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid as u32) };
+    if process.is_null() {
+        return None;
+    }
+    let mut token: HANDLE = std::ptr::null_mut();
+    let opened = unsafe { OpenProcessToken(process, TOKEN_QUERY, &mut token) } != 0;
+    unsafe { CloseHandle(process) };
+    if !opened {
+        return None;
+    }
+    let mut needed = 0u32;
+    unsafe { GetTokenInformation(token, TokenUser, std::ptr::null_mut(), 0, &mut needed) };
+    let mut buffer = vec![0u64; (needed as usize).div_ceil(8)];
+    let ok = unsafe { GetTokenInformation(token, TokenUser, buffer.as_mut_ptr().cast(), needed, &mut needed) } != 0;
+    unsafe { CloseHandle(token) };
+    if !ok {
+        return None;
+    }
+    let sid = unsafe { (*buffer.as_ptr().cast::<TOKEN_USER>()).User.Sid };
+    account_name(sid)
+}
+
+// DOMAIN\user of a SID
+#[cfg(windows)]
+fn account_name(sid: windows_sys::Win32::Security::PSID) -> Option<String> {
+    use windows_sys::Win32::Security::LookupAccountSidW;
+
+    let (mut name, mut domain) = ([0u16; 256], [0u16; 256]);
+    let (mut name_len, mut domain_len, mut kind) = (256u32, 256u32, 0);
+    let ok = unsafe {
+        LookupAccountSidW(
+            std::ptr::null(),
+            sid,
+            name.as_mut_ptr(),
+            &mut name_len,
+            domain.as_mut_ptr(),
+            &mut domain_len,
+            &mut kind,
+        )
+    } != 0;
+    ok.then(|| {
+        let name = String::from_utf16_lossy(&name[..name_len as usize]);
+        let domain = String::from_utf16_lossy(&domain[..domain_len as usize]);
+        format!("{}\\{}", domain, name)
+    })
+}
+
+#[cfg(not(windows))]
+pub fn owner(_pid: u64) -> Option<String> {
+    None
+}
+
+// The account owning a file, when its security descriptor can be read at all
+#[cfg(windows)]
+pub fn file_owner(path: &std::path::Path) -> Option<String> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Security::{GetFileSecurityW, GetSecurityDescriptorOwner, OWNER_SECURITY_INFORMATION};
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut needed = 0u32;
+    unsafe { GetFileSecurityW(wide.as_ptr(), OWNER_SECURITY_INFORMATION, std::ptr::null_mut(), 0, &mut needed) };
+    if needed == 0 {
+        return None;
+    }
+    let mut buffer = vec![0u64; (needed as usize).div_ceil(8)];
+    let descriptor = buffer.as_mut_ptr().cast();
+    if unsafe { GetFileSecurityW(wide.as_ptr(), OWNER_SECURITY_INFORMATION, descriptor, needed, &mut needed) } == 0 {
+        return None;
+    }
+    let (mut sid, mut defaulted) = (std::ptr::null_mut(), 0);
+    if unsafe { GetSecurityDescriptorOwner(descriptor, &mut sid, &mut defaulted) } == 0 || sid.is_null() {
+        return None;
+    }
+    account_name(sid)
+}
+
+#[cfg(not(windows))]
+pub fn file_owner(_path: &std::path::Path) -> Option<String> {
+    None
+}
+
+// Architecture of the code a process runs, as opposed to that of the machine
+#[cfg(windows)]
+pub fn process_arch(pid: u64) -> Option<Arch> {
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::SystemInformation::IMAGE_FILE_MACHINE_UNKNOWN;
+    use windows_sys::Win32::System::Threading::{IsWow64Process2, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION};
+
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid as u32) };
+    if process.is_null() {
+        return None;
+    }
+    let (mut process_machine, mut native_machine) = (0, 0);
+    let ok = unsafe { IsWow64Process2(process, &mut process_machine, &mut native_machine) } != 0;
+    unsafe { CloseHandle(process) };
+
+    // An unknown process machine means the process isn't under WOW64 and runs native code
+    ok.then(|| match process_machine {
+        IMAGE_FILE_MACHINE_UNKNOWN => Arch::from_machine(native_machine),
+        machine => Arch::from_machine(machine),
+    })
+}
+
+#[cfg(not(windows))]
+pub fn process_arch(_pid: u64) -> Option<Arch> {
+    None
+}
+
+#[cfg(windows)]
+pub fn session_id(pid: u64) -> Option<u32> {
+    use windows_sys::Win32::System::RemoteDesktop::ProcessIdToSessionId;
+
+    let mut session = 0;
+    (unsafe { ProcessIdToSessionId(pid as u32, &mut session) } != 0).then_some(session)
+}
+
+#[cfg(not(windows))]
+pub fn session_id(_pid: u64) -> Option<u32> {
+    None
+}
+
+pub fn current_session() -> Option<u32> {
+    session_id(u64::from(std::process::id()))
+}
+
+// Drop PIDs outside the current session when asked to; a PID whose session can't be
+// read is kept, since it can't be told apart
+pub fn retain_session(pids: &mut Vec<u64>, filter: SessionFilter) {
+    if filter == SessionFilter::All {
+        return;
+    }
+    if let Some(current) = current_session() {
+        pids.retain(|&pid| session_id(pid).is_none_or(|session| session == current));
+    }
+}
+
+// Sessions are only worth printing when the PIDs span more than one, or all share one other
+// than the caller's
+pub fn sessions_differ(pids: &[u64]) -> bool {
+    let sessions: Vec<Option<u32>> = pids.iter().map(|&pid| session_id(pid)).collect();
+    spans_sessions(&sessions, current_session())
+}
+
+// A session that can't be read tells nothing either way, so it is left out
+fn spans_sessions(sessions: &[Option<u32>], current: Option<u32>) -> bool {
+    let mut known = sessions.iter().flatten();
+    let Some(first) = known.next() else {
+        return false;
+    };
+    known.any(|session| session != first) || current.is_some_and(|current| current != *first)
+}
+
+// Wait up to `timeout` for any of the processes to exit, true as soon as one does; ends
+// early with Interrupted on Ctrl+C. At most 64 processes can be waited on at once, the rest
+// are only noticed by the next poll
+#[cfg(windows)]
+pub fn wait_for_exit(pids: &[u64], timeout: std::time::Duration) -> Result<bool, crate::SvcError> {
+    use windows_sys::Win32::Foundation::{CloseHandle, WAIT_OBJECT_0, WAIT_TIMEOUT};
+    use windows_sys::Win32::System::Threading::{OpenProcess, WaitForMultipleObjects, PROCESS_SYNCHRONIZE};
+
+    let handles: Vec<_> = pids
+        .iter()
+        .map(|&pid| unsafe { OpenProcess(PROCESS_SYNCHRONIZE, 0, pid as u32) })
+        .filter(|handle| !handle.is_null())
+        // MAXIMUM_WAIT_OBJECTS
+        .take(64)
+        .collect();
+    let started = std::time::Instant::now();
+    let result = loop {
+        if let Err(err) = crate::cancel::check() {
+            break Err(err);
+        }
+        let left = timeout.saturating_sub(started.elapsed());
+        if left.is_zero() {
+            break Ok(false);
+        }
+        // In slices, so Ctrl+C still lands promptly
+        let slice = left.min(std::time::Duration::from_millis(50)).as_millis() as u32;
+        if handles.is_empty() {
+            std::thread::sleep(std::time::Duration::from_millis(u64::from(slice)));
+            continue;
+        }
+        match unsafe { WaitForMultipleObjects(handles.len() as u32, handles.as_ptr(), 0, slice) } {
+            WAIT_TIMEOUT => {}
+            signaled if signaled < WAIT_OBJECT_0 + handles.len() as u32 => break Ok(true),
+            // The handles can't be waited on; sleeping out the rest is still a poll
+            _ => break crate::cancel::sleep(left).map(|()| false),
+        }
+    };
+    for handle in handles {
+        unsafe { CloseHandle(handle) };
+    }
+    result
+}
+
+// Whether a process snapshot can be taken, as every PID lookup starts with one
+#[cfg(windows)]
+pub fn probe_snapshot() -> Result<(), crate::SvcError> {
+    use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::Diagnostics::ToolHelp::{CreateToolhelp32Snapshot, TH32CS_SNAPPROCESS};
+
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) };
+    if snapshot == INVALID_HANDLE_VALUE {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    unsafe { CloseHandle(snapshot) };
+    Ok(())
+}
+
+// Processes are listed through tasklist and WMI stand-ins here
+#[cfg(not(windows))]
+pub fn probe_snapshot() -> Result<(), crate::SvcError> {
+    Ok(())
+}
+
+// Whether process handles open with the rights stop and kill use; svc's own process is the
+// one that always should, so a refusal there means policy blocks them everywhere
+#[cfg(windows)]
+pub fn probe_open() -> Result<(), crate::SvcError> {
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_SYNCHRONIZE, PROCESS_TERMINATE,
+    };
+
+    let access = PROCESS_QUERY_LIMITED_INFORMATION | PROCESS_SYNCHRONIZE | PROCESS_TERMINATE;
+    let process = unsafe { OpenProcess(access, 0, std::process::id()) };
+    if process.is_null() {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    unsafe { CloseHandle(process) };
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub fn probe_open() -> Result<(), crate::SvcError> {
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub fn wait_for_exit(_pids: &[u64], timeout: std::time::Duration) -> Result<bool, crate::SvcError> {
+    crate::cancel::sleep(timeout).map(|()| false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_show_when_the_pids_span_more_than_one() {
+        // Two sessions among the PIDs, whichever the caller is in or if it can't be read
+        assert!(spans_sessions(&[Some(1), Some(2)], Some(1)));
+        assert!(spans_sessions(&[Some(1), Some(2)], None));
+        // All in one session: only worth showing when it isn't the caller's
+        assert!(!spans_sessions(&[Some(1), Some(1)], Some(1)));
+        assert!(!spans_sessions(&[Some(2), Some(2)], None));
+        assert!(spans_sessions(&[Some(2), Some(2)], Some(1)));
+        // Unreadable sessions are left out
+        assert!(!spans_sessions(&[None, Some(1)], Some(1)));
+        assert!(!spans_sessions(&[None, None], Some(1)));
+        assert!(!spans_sessions(&[], Some(1)));
+    }
+}
@@ -0,0 +1,101 @@
+use crate::{output, SvcError};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Set by --progress-events; events are only printed in JSON mode
+static ENABLED: AtomicBool = AtomicBool::new(false);
+// The last sequence number, held while its line is printed so lines from services handled in
+// parallel come out in order
+static SEQUENCE: Mutex<u64> = Mutex::new(0);
+
+// How often a phase that is still going is announced again
+const HEARTBEAT: Duration = Duration::from_secs(1);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed) && output::options().json
+}
+
+#[derive(Serialize)]
+struct Event<'a> {
+    seq: u64,
+    event: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    service: Option<&'a str>,
+    #[serde(flatten)]
+    fields: Map<String, Value>,
+}
+
+// One line of newline-delimited JSON on stdout: {"seq", "event", "service", ...fields}
+pub fn emit(event: &str, service: Option<&str>, fields: Value) {
+    if !enabled() {
+        return;
+    }
+    let mut sequence = SEQUENCE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    *sequence += 1;
+    let fields = match fields {
+        Value::Object(fields) => fields,
+        _ => Map::new(),
+    };
+    let line = Event {
+        seq: *sequence,
+        event,
+        service,
+        fields,
+    };
+    if let Ok(line) = serde_json::to_string(&line) {
+        println!("{}", line);
+    }
+}
+
+// A step of one service that can take a while, announced when it begins and then about once a
+// second while it is polled, with how long it has taken so far
+pub struct Phase {
+    event: &'static str,
+    service: String,
+    phase: &'static str,
+    started: Instant,
+    announced: Instant,
+}
+
+impl Phase {
+    pub fn begin(event: &'static str, service: &str, phase: &'static str) -> Phase {
+        let now = Instant::now();
+        let phase = Phase {
+            event,
+            service: service.to_string(),
+            phase,
+            started: now,
+            announced: now,
+        };
+        phase.announce();
+        phase
+    }
+
+    fn announce(&self) {
+        let elapsed = self.started.elapsed().as_millis() as u64;
+        emit(self.event, Some(&self.service), json!({ "phase": self.phase, "elapsed_ms": elapsed }));
+    }
+
+    pub fn tick(&mut self) {
+        if self.announced.elapsed() >= HEARTBEAT {
+            self.announced = Instant::now();
+            self.announce();
+        }
+    }
+}
+
+// The last line of the command, after whatever else it printed: {"event": "result", "ok"},
+// with the error object when it failed
+pub fn finish(result: &Result<(), SvcError>) {
+    match result {
+        Ok(()) => emit("result", None, json!({ "ok": true })),
+        Err(err) => emit("result", None, json!({ "ok": false, "error": output::error_object(err) })),
+    }
+}
@@ -0,0 +1,124 @@
+use crate::encoding;
+use crate::{cancel, is_pid_alive, logs, progress, spawned, Service, SvcError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(30);
+// Between readiness checks of a new instance
+const POLL: Duration = Duration::from_millis(250);
+// For one connection of a health check
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Whether the service says when it is up
+pub fn configured(service: &Service) -> bool {
+    service.ready_pattern.is_some() || service.health_check.is_some()
+}
+
+// Where the log ends now, so a ready line an earlier launch wrote doesn't count
+pub fn log_mark(service: &Service) -> u64 {
+    logs::path(service)
+        .and_then(|path| std::fs::metadata(path).ok())
+        .map_or(0, |metadata| metadata.len())
+}
+
+fn log_has(service: &Service, pattern: &str, mark: u64) -> Result<(), String> {
+    let Some(path) = logs::path(service) else {
+        return Err("it has no log to find ready_pattern in".to_string());
+    };
+    let mut bytes = Vec::new();
+    if let Ok(mut file) = std::fs::File::open(&path) {
+        // Rotated or truncated since: all of it is new
+        let from = if file.metadata().map_or(0, |metadata| metadata.len()) < mark { 0 } else { mark };
+        let _ = file.seek(SeekFrom::Start(from)).and_then(|_| file.read_to_end(&mut bytes));
+    }
+    match encoding::decode(service.encoding, &bytes).contains(pattern) {
+        true => Ok(()),
+        false => Err(format!("no \"{}\" in its log yet", pattern)),
+    }
+}
+
+fn connect(address: &str) -> Result<TcpStream, String> {
+    let stream = address
+        .to_socket_addrs()
+        .map_err(|err| format!("{}: {}", address, err))?
+        .next()
+        .ok_or_else(|| format!("{} resolves to no address", address))
+        .and_then(|addr| TcpStream::connect_timeout(&addr, CHECK_TIMEOUT).map_err(|err| format!("{}: {}", address, err)))?;
+    let _ = stream.set_read_timeout(Some(CHECK_TIMEOUT));
+    let _ = stream.set_write_timeout(Some(CHECK_TIMEOUT));
+    Ok(stream)
+}
+
+// One plain HTTP/1.0 GET, so no client library is needed for a status line
+fn http_ok(rest: &str) -> Result<(), String> {
+    let (host, path) = match rest.find('/') {
+        Some(at) => (&rest[..at], &rest[at..]),
+        None => (rest, "/"),
+    };
+    let address = if host.contains(':') { host.to_string() } else { format!("{}:80", host) };
+    let mut stream = connect(&address)?;
+    write!(stream, "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n", path, host)
+        .map_err(|err| format!("{}: {}", address, err))?;
+    let mut head = [0u8; 64];
+    let read = stream.read(&mut head).map_err(|err| format!("{}: {}", address, err))?;
+    let line = String::from_utf8_lossy(&head[..read]);
+    let status = line.split_whitespace().nth(1).and_then(|code| code.parse::<u16>().ok());
+    match status {
+        Some(200..=399) => Ok(()),
+        Some(code) => Err(format!("http://{}{} answered {}", host, path, code)),
+        None => Err(format!("http://{}{} didn't answer HTTP", host, path)),
+    }
+}
+
+pub fn health(url: &str) -> Result<(), String> {
+    if let Some(address) = url.strip_prefix("tcp://") {
+        return connect(address.trim_end_matches('/')).map(drop);
+    }
+    match url.strip_prefix("http://") {
+        Some(rest) => http_ok(rest),
+        None => Err(format!("health_check {} is neither http:// nor tcp://", url)),
+    }
+}
+
+// Every configured check passes; Err says which doesn't yet
+fn ready(service: &Service, mark: u64) -> Result<(), String> {
+    if let Some(pattern) = &service.ready_pattern {
+        log_has(service, pattern, mark)?;
+    }
+    if let Some(url) = &service.health_check {
+        health(url)?;
+    }
+    Ok(())
+}
+
+// Whether an instance that is already up still passes its checks; its whole log counts
+// for ready_pattern
+pub fn healthy(service: &Service) -> Result<(), String> {
+    ready(service, 0)
+}
+
+// Waits for the instance with this PID to be ready; Err(reason) when it exits first or the
+// timeout passes, Interrupted on Ctrl+C
+pub fn wait(service: &Service, pid: u64, mark: u64, timeout: Duration) -> Result<Result<Duration, String>, SvcError> {
+    let started = Instant::now();
+    let mut phase = progress::Phase::begin("waiting", &service.name, "ready");
+    loop {
+        let not_yet = match ready(service, mark) {
+            Ok(()) => return Ok(Ok(started.elapsed())),
+            Err(reason) => reason,
+        };
+        if !is_pid_alive(pid)? {
+            return Ok(Err(format!("PID {} exited before it was ready", pid)));
+        }
+        if started.elapsed() >= timeout {
+            return Ok(Err(format!("not ready within {}s ({})", timeout.as_secs_f64(), not_yet)));
+        }
+        // An instance this invocation started ends the wait the moment it exits
+        match spawned::held_exit_within(pid, POLL) {
+            Some(_) => cancel::check()?,
+            None => cancel::sleep(POLL)?,
+        }
+        phase.tick();
+    }
+}
@@ -0,0 +1,210 @@
+use crate::format::lock_path;
+use crate::mutex::{self, Leftover};
+use crate::process::{is_pid_alive, is_svc_alive};
+use crate::state::{self, state_dir};
+use crate::{effects, load_config, output, spawned, SvcError};
+use colored::Colorize;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+const WATCH_PID: &str = "watch.pid";
+const WATCH_INTERVAL: &str = "watch.interval";
+
+// Something svc left behind, and why it is stale or still in use
+#[derive(Serialize)]
+struct Item {
+    item: String,
+    reason: String,
+}
+
+#[derive(Serialize, Default)]
+struct Report {
+    cleaned: Vec<Item>,
+    // Locks and files a live process still has, left alone
+    in_use: Vec<Item>,
+}
+
+impl Report {
+    fn note(&mut self, stale: bool, item: impl Into<String>, reason: String) {
+        let item = Item {
+            item: item.into(),
+            reason,
+        };
+        match stale {
+            true => self.cleaned.push(item),
+            false => self.in_use.push(item),
+        }
+    }
+}
+
+fn read_pid(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+// The service and file a state file is, in its directory or where svc kept it before each
+// service had one
+fn service_file(name: &str) -> Option<(&str, &str)> {
+    let legacy = |suffix: &str, file: &'static str| name.strip_suffix(suffix).map(|service| (service, file));
+    name.strip_prefix("services/")
+        .and_then(|rest| rest.split_once('/'))
+        .or_else(|| legacy(".restart.pid", "restart.pid"))
+        .or_else(|| legacy(".runas.pid", "runas.pid"))
+        .or_else(|| legacy(".switchover", "switchover.json"))
+}
+
+// Who a state file says owns it, and whether that is an svc or the service's own process
+fn owner(name: &str, path: &Path) -> Option<(String, u64, bool)> {
+    let (who, pid, is_svc) = if name == WATCH_PID {
+        ("the background watcher".to_string(), read_pid(path), true)
+    } else if name == "shutdown-hook.pid" {
+        ("the shutdown hook".to_string(), read_pid(path), true)
+    } else {
+        match service_file(name)? {
+            (service, "restart.pid") => (format!("the svc waiting to restart {}", service), read_pid(path), true),
+            (service, "runas.pid") => {
+                (format!("the process of {} started as another user", service), read_pid(path), false)
+            }
+            (service, "switchover.json") => {
+                let by = fs::read_to_string(path)
+                    .ok()
+                    .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+                    .and_then(|overlap| overlap.get("by")?.as_u64());
+                (format!("the svc restarting {} with --overlap", service), by, true)
+            }
+            _ => return None,
+        }
+    };
+    // A file that doesn't hold a PID is judged stale below, under PID 0
+    Some((who, pid.unwrap_or(0), is_svc))
+}
+
+fn alive(pid: u64, is_svc: bool) -> Result<bool, SvcError> {
+    match (pid, is_svc) {
+        (0, _) => Ok(false),
+        (pid, true) => is_svc_alive(pid),
+        (pid, false) => is_pid_alive(pid),
+    }
+}
+
+// PID files and the like in the state directory; whether the watcher is still running
+fn state_files(report: &mut Report) -> Result<bool, SvcError> {
+    let dir = state_dir()?;
+    let file_names = |dir: &Path| -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .collect();
+        names.sort();
+        names
+    };
+    let mut names = file_names(&dir);
+    for service in state::service_dirs()? {
+        let files = file_names(&state::service_path(&service)?);
+        names.extend(files.iter().map(|file| state::service_file(&service, file)));
+    }
+    let mut watcher = false;
+    for name in names {
+        let path = dir.join(&name);
+        let Some((who, pid, is_svc)) = owner(&name, &path) else {
+            continue;
+        };
+        let live = alive(pid, is_svc)?;
+        watcher |= live && name == WATCH_PID;
+        if live {
+            report.note(false, name, format!("{} is running as PID {}", who, pid));
+            continue;
+        }
+        effects::delete(&path)?;
+        let reason = match pid {
+            0 => format!("it was left by {} and holds no PID", who),
+            pid => format!("{} (PID {}) is gone", who, pid),
+        };
+        report.note(true, name, reason);
+    }
+    let interval = dir.join(WATCH_INTERVAL);
+    if !watcher && interval.exists() {
+        effects::delete(&interval)?;
+        report.note(true, WATCH_INTERVAL, "no background watcher is running".to_string());
+    }
+    Ok(watcher)
+}
+
+fn config_lock(config_path: &str, report: &mut Report) -> Result<(), SvcError> {
+    let path = lock_path(Path::new(config_path));
+    if !path.exists() {
+        return Ok(());
+    }
+    let pid = read_pid(&path).unwrap_or(0);
+    let item = path.display().to_string();
+    if alive(pid, true)? {
+        report.note(false, item, format!("the svc editing the config is running as PID {}", pid));
+        return Ok(());
+    }
+    effects::delete(&path)?;
+    report.note(true, item, format!("the svc that was editing the config (PID {}) is gone", pid));
+    Ok(())
+}
+
+// The named mutexes svc takes for configured services and its shared files; checking one takes
+// it, which is what releases it when abandoned
+fn locks(config_path: &str, report: &mut Report) -> Result<(), SvcError> {
+    // A config that doesn't load still leaves the shared locks to check
+    let services = load_config(config_path).unwrap_or_default();
+    let mut names: Vec<(&str, &str)> = mutex::SHARED.to_vec();
+    for service in &services {
+        names.extend(mutex::SERVICE_SCOPES.iter().map(|&scope| (scope, service.name.as_ref())));
+    }
+    for (scope, name) in names {
+        match mutex::probe(scope, name)? {
+            Some(Leftover::Abandoned) => {
+                report.note(true, mutex::object_name(scope, name), "an svc exited holding it; released".to_string())
+            }
+            Some(Leftover::Held) => {
+                report.note(false, mutex::object_name(scope, name), "a running svc holds it".to_string())
+            }
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+pub fn recover(config_path: &str) -> Result<(), SvcError> {
+    let mut report = Report::default();
+    state_files(&mut report)?;
+    config_lock(config_path, &mut report)?;
+    let dropped = spawned::prune_all()?;
+    if dropped > 0 {
+        report.note(true, "spawned.json", format!("{} launch(es) of processes that exited", dropped));
+    }
+    let dry_run = effects::dry_run();
+    // Checking a lock changes it, so a dry run leaves them be
+    if !dry_run {
+        locks(config_path, &mut report)?;
+    }
+
+    if output::options().json {
+        println!("{}", serde_json::to_string_pretty(&report).map_err(std::io::Error::other)?);
+        return Ok(());
+    }
+    let width = report.cleaned.iter().chain(&report.in_use).map(|item| output::width(&item.item)).max().unwrap_or(0);
+    if report.cleaned.is_empty() {
+        println!("Nothing stale found.");
+    } else {
+        println!("{} {} stale item(s):", if dry_run { "Would clean up" } else { "Cleaned up" }, report.cleaned.len());
+        for item in &report.cleaned {
+            println!("  {} {}  {}", "-".green(), output::pad(&item.item, width), item.reason);
+        }
+    }
+    if !report.in_use.is_empty() {
+        println!("Still in use, left alone:");
+        for item in &report.in_use {
+            println!("  {} {}  {}", "!".yellow(), output::pad(&item.item, width), item.reason);
+        }
+    }
+    if dry_run {
+        println!("Locks are only checked without --dry-run, since checking one takes it.");
+    }
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use crate::output::format_time;
+use crate::{
+    cancel, capture, confirm_protected, edit, find_service, kill_and_wait, load_document, run_service, startup, RunOverride,
+    SessionFilter, SvcError, KILL_WAIT,
+};
+use colored::Colorize;
+use std::collections::VecDeque;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+
+// Directory levels searched below each root; install trees rarely nest the binary deeper
+const SEARCH_DEPTH: usize = 4;
+const MAX_CANDIDATES: usize = 20;
+
+// Where the old binary most likely moved: next to its versioned folder, or an install root
+fn default_roots(old: &Path) -> Vec<PathBuf> {
+    let mut roots: Vec<PathBuf> = old.parent().and_then(Path::parent).map(Path::to_path_buf).into_iter().collect();
+    for var in ["ProgramFiles", "ProgramFiles(x86)"] {
+        roots.extend(std::env::var_os(var).map(PathBuf::from));
+    }
+    roots.extend(std::env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("Programs")));
+    roots
+}
+
+// Files named like `file_name` below `root`, breadth first so close matches come first
+pub fn search(root: &Path, file_name: &str, depth: usize) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut queue = VecDeque::from([(root.to_path_buf(), 0)]);
+    while let Some((dir, level)) = queue.pop_front() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(kind) = entry.file_type() else {
+                continue;
+            };
+            if kind.is_dir() && level + 1 < depth {
+                queue.push_back((path, level + 1));
+            } else if kind.is_file() && entry.file_name().to_string_lossy().eq_ignore_ascii_case(file_name) {
+                found.push(path);
+                if found.len() >= MAX_CANDIDATES {
+                    return found;
+                }
+            }
+        }
+    }
+    found
+}
+
+// The same-named file closest to a missing binary, for validate to point at relocate
+pub fn nearby(old: &Path) -> Option<PathBuf> {
+    let file_name = old.file_name()?.to_str()?;
+    let root = old.parent()?.parent()?;
+    search(root, file_name, 2).into_iter().next()
+}
+
+fn file_version(path: &Path) -> Option<String> {
+    let output = capture::output(Command::new("powershell").args([
+        "-NoProfile",
+        "-Command",
+        &format!(
+            "(Get-Item -LiteralPath '{}').VersionInfo.FileVersion",
+            path.display().to_string().replace('\'', "''")
+        ),
+    ]))
+    .ok()?;
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!version.is_empty()).then_some(version)
+}
+
+struct Candidate {
+    path: PathBuf,
+    version: Option<String>,
+    modified: Option<SystemTime>,
+}
+
+fn choose(candidates: &[Candidate], pick: Option<usize>) -> Result<Option<usize>, SvcError> {
+    if let Some(pick) = pick {
+        return match pick {
+            1.. if pick <= candidates.len() => Ok(Some(pick - 1)),
+            _ => Err(SvcError::InvalidCommand(format!("--pick {}", pick))),
+        };
+    }
+    if !std::io::stdin().is_terminal() {
+        println!("Pick one with 'svc relocate ... --pick <n>'.");
+        return Ok(None);
+    }
+    eprint!("Use which one? [1-{}, empty to cancel]: ", candidates.len());
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    cancel::check()?;
+    match answer.trim() {
+        "" => Ok(None),
+        text => match text.parse::<usize>() {
+            Ok(n) if (1..=candidates.len()).contains(&n) => Ok(Some(n - 1)),
+            _ => Err(SvcError::InvalidCommand(text.to_string())),
+        },
+    }
+}
+
+pub struct RelocateOptions {
+    pub search: Vec<PathBuf>,
+    pub pick: Option<usize>,
+    pub restart: bool,
+}
+
+pub fn relocate(config_path: &str, name: &str, options: &RelocateOptions) -> Result<(), SvcError> {
+    // Kept as it was, so a restart stops the processes running the old binary
+    let before = find_service(config_path, name)?;
+    let old = PathBuf::from(before.path.as_ref());
+    let Some(file_name) = old.file_name().and_then(|name| name.to_str()) else {
+        return Err(SvcError::InvalidCommand(before.path.to_string()));
+    };
+
+    let roots = if options.search.is_empty() {
+        default_roots(&old)
+    } else {
+        options.search.clone()
+    };
+    let mut paths: Vec<PathBuf> = roots
+        .iter()
+        .flat_map(|root| search(root, file_name, SEARCH_DEPTH))
+        .filter(|path| *path != old)
+        .collect();
+    paths.sort();
+    paths.dedup();
+    let mut candidates: Vec<Candidate> = paths
+        .into_iter()
+        .map(|path| Candidate {
+            version: file_version(&path),
+            modified: std::fs::metadata(&path).and_then(|meta| meta.modified()).ok(),
+            path,
+        })
+        .collect();
+    // Newest first, as an upgrade is the usual reason for a move
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.modified));
+
+    if candidates.is_empty() {
+        let searched: Vec<String> = roots.iter().map(|root| root.display().to_string()).collect();
+        println!("No other {} found under {}.", file_name.cyan(), searched.join(", "));
+        return Ok(());
+    }
+    println!("Service {} points at {}{}", name.cyan(), old.display(), if old.exists() { "" } else { " (missing)" });
+    for (index, candidate) in candidates.iter().enumerate() {
+        println!(
+            "  {}. {}  {}  {}",
+            index + 1,
+            candidate.path.display().to_string().cyan(),
+            candidate.version.as_deref().unwrap_or("-"),
+            candidate.modified.map(format_time).unwrap_or_else(|| "-".to_string())
+        );
+    }
+    let Some(index) = choose(&candidates, options.pick)? else {
+        return Ok(());
+    };
+    let new = candidates[index].path.display().to_string();
+
+    edit::set_path(Path::new(config_path), name, &new)?;
+
+    // The Run value holds the path itself unless it goes through `svc run --startup`
+    let config = load_document(config_path)?;
+    let service = config.service(name)?;
+    if let Some(entry) = startup::find(service)? {
+        let value = startup::value_name(name);
+        startup::set_value(&value, &startup::run_data(service, &config.settings)?)?;
+        if entry.value != value {
+            startup::delete_value(&entry.value)?;
+        }
+        println!("Updated start-up entry of {}.", name.cyan());
+    }
+
+    // Only what was running is started again; relocating a stopped service leaves it stopped
+    if options.restart {
+        confirm_protected(&before, false)?;
+        match kill_and_wait(&before, SessionFilter::All, false, KILL_WAIT) {
+            Ok(()) => run_service(service, &RunOverride::default())?,
+            Err(SvcError::ServiceIsNotRunning) => println!("{} wasn't running, so it wasn't started.", name.cyan()),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,310 @@
+use crate::cli::{SessionFilter, UptimeArgs};
+use crate::notify::{self, SvcEvent};
+use crate::process::{self, RunOutcome};
+use crate::effects::{self, Effect};
+use crate::{cancel, confirm_protected, get_pids, history, kill_and_wait, killguard, procinfo, progress, ready};
+use crate::{load_config, outdated, run_service, state, stopall, terminate, uptime, RunOverride, Service, ServiceType};
+use crate::SvcError;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+// Time between CPU samples while waiting for idle; each sample itself takes a fraction of a second
+const POLL: Duration = Duration::from_secs(5);
+
+pub struct RestartOptions {
+    pub force: bool,
+    pub when_idle: bool,
+    pub now: bool,
+    // Percent of the whole machine, as in `svc list`
+    pub idle_cpu: f64,
+    pub idle_for: Duration,
+    pub max_wait: Duration,
+    // For the old processes to exit once killed
+    pub timeout: Duration,
+    // Start the new instance and wait for it to be ready before stopping the old one
+    pub overlap: bool,
+    // Restart only by the processes up for longer or shorter than this
+    pub uptime: UptimeArgs,
+}
+
+pub fn pid_file(name: &str) -> String {
+    state::service_file(name, "restart.pid")
+}
+
+// Whether another svc is waiting for the service to go idle before restarting it
+pub fn pending(name: &str) -> Result<bool, SvcError> {
+    match state::read_pid_file(&pid_file(name))? {
+        Some(pid) => process::is_svc_alive(pid),
+        None => Ok(false),
+    }
+}
+
+// Marks the restart as pending for status; removed however the wait ends
+struct Pending(String);
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        let _ = state::remove_pid_file(&pid_file(&self.0));
+    }
+}
+
+// Returns once the service has stayed below options.idle_cpu for options.idle_for,
+// or has stopped on its own
+fn wait_for_idle(service: &Service, options: &RestartOptions) -> Result<(), SvcError> {
+    state::write_pid_file(&pid_file(&service.name), u64::from(std::process::id()))?;
+    let _pending = Pending(service.name.to_string());
+    eprintln!(
+        "Waiting for {} to stay below {}% CPU for {}s (giving up after {}s)...",
+        service.name.cyan(),
+        options.idle_cpu,
+        options.idle_for.as_secs_f64(),
+        options.max_wait.as_secs_f64()
+    );
+
+    let started = Instant::now();
+    let mut idle_since: Option<Instant> = None;
+    let mut phase = progress::Phase::begin("waiting", &service.name, "idle");
+    loop {
+        let pids = get_pids(service)?;
+        if pids.is_empty() {
+            return Ok(());
+        }
+        let cpu: Option<f64> = procinfo::sample(&pids).iter().map(|usage| usage.cpu).sum();
+        let Some(cpu) = cpu else {
+            return Err(SvcError::ElevationRequired(format!("Reading the CPU usage of {}", service.name)));
+        };
+
+        if cpu < options.idle_cpu {
+            let since = *idle_since.get_or_insert_with(Instant::now);
+            if since.elapsed() >= options.idle_for {
+                return Ok(());
+            }
+        } else {
+            idle_since = None;
+        }
+        if started.elapsed() >= options.max_wait {
+            return Err(SvcError::IdleTimeout {
+                service: service.name.to_string(),
+                waited: format!("{}s", options.max_wait.as_secs_f64()),
+            });
+        }
+        cancel::sleep(POLL)?;
+        phase.tick();
+    }
+}
+
+// `only` is the PIDs an uptime condition picked; the others keep running, so the new instance
+// starts past the already-running guard
+fn stop_and_start(service: &Service, options: &RestartOptions, only: Option<Vec<u64>>) -> Result<(), SvcError> {
+    let with = RunOverride {
+        force: only.is_some(),
+        ..RunOverride::default()
+    };
+    progress::Phase::begin("restarting", &service.name, "stopping");
+    let stopped = match only {
+        Some(pids) => process::kill_pids_and_wait(service, pids, options.force, options.timeout),
+        None => kill_and_wait(service, SessionFilter::All, options.force, options.timeout),
+    };
+    // Not running is fine, it is simply started
+    match stopped {
+        Ok(()) | Err(SvcError::ServiceIsNotRunning) => {}
+        Err(err) => return Err(err),
+    }
+    progress::Phase::begin("restarting", &service.name, "starting");
+    run_service(service, &with)
+}
+
+// Old and new PIDs while `restart --overlap` runs both, for status to tell apart; `by` is the
+// svc doing it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Switchover {
+    pub by: u64,
+    pub old: Vec<u64>,
+    pub new: u64,
+}
+
+fn switchover_file(name: &str) -> String {
+    state::service_file(name, "switchover.json")
+}
+
+// The switchover in progress, if the svc running it is still alive
+pub fn switchover(name: &str) -> Option<Switchover> {
+    let path = state::state_dir().ok()?.join(switchover_file(name));
+    let overlap: Switchover = serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()?;
+    process::is_svc_alive(overlap.by).unwrap_or(false).then_some(overlap)
+}
+
+// Removed however the switchover ends
+struct Overlapping(String);
+
+impl Overlapping {
+    fn begin(service: &Service, old: &[u64], new: u64) -> Result<Self, SvcError> {
+        let overlap = Switchover {
+            by: u64::from(std::process::id()),
+            old: old.to_vec(),
+            new,
+        };
+        let text = serde_json::to_string(&overlap).map_err(std::io::Error::other)?;
+        state::write_file(&switchover_file(&service.name), &text)?;
+        Ok(Overlapping(service.name.to_string()))
+    }
+}
+
+impl Drop for Overlapping {
+    fn drop(&mut self) {
+        let _ = state::remove_pid_file(&switchover_file(&self.0));
+    }
+}
+
+fn check_overlap(service: &Service) -> Result<(), SvcError> {
+    let refuse = |why: &str| Err(SvcError::InvalidCommand(format!("restart --overlap of {}, {}", service.name, why)));
+    if !matches!(service.service_type, ServiceType::Executable) {
+        return refuse("which is a Util and doesn't keep running");
+    }
+    if !ready::configured(service) {
+        return refuse("which has no ready_pattern or health_check to tell when the new instance is up");
+    }
+    if service.ready_pattern.is_some() && crate::logs::path(service).is_none() {
+        return refuse("whose ready_pattern needs a log to be found in");
+    }
+    // A stop file or event is seen by every instance, the new one too
+    if service.stop_signal.is_some() {
+        return refuse("whose stop_signal would stop the new instance along with the old");
+    }
+    Ok(())
+}
+
+// Start a second instance past the already-running guard, wait for it to be ready, then stop
+// the old PIDs; a new instance that isn't ready in time is killed and the old one left alone
+fn overlap(service: &Service, options: &RestartOptions) -> Result<String, SvcError> {
+    check_overlap(service)?;
+    let old = get_pids(service)?;
+    if old.is_empty() {
+        run_service(service, &RunOverride::default())?;
+        return Ok("was not running, started".to_string());
+    }
+    // Screened before anything starts, so a refusal leaves nothing behind
+    let old = killguard::screen(service, old, options.force)?;
+    let mark = ready::log_mark(service);
+    let with = RunOverride {
+        force: true,
+        ..RunOverride::default()
+    };
+    let effect = Effect::Start {
+        service: service.name.to_string(),
+    };
+    let Some(outcome) = effects::perform(effect, || process::start(&process::System, service, &with))? else {
+        process::stop_pids(service, old)?;
+        return Ok("would start a new instance and stop the old PIDs once it is ready".to_string());
+    };
+    let RunOutcome::Started { pid, .. } = outcome else {
+        return Err(SvcError::InvalidCommand(format!("restart --overlap of {}, which exited at once", service.name)));
+    };
+    let _overlapping = Overlapping::begin(service, &old, pid)?;
+    let timeout = service.ready_timeout.as_ref().map_or(ready::DEFAULT_READY_TIMEOUT, |timeout| timeout.get());
+    println!(
+        "Started a new instance of {} with PID {}, waiting up to {}s for it to be ready.",
+        service.name.cyan(),
+        pid.to_string().green(),
+        timeout.as_secs_f64()
+    );
+
+    // Whatever the new instance started goes with it; the old PIDs are not touched
+    let stop_new = || {
+        let new: Vec<u64> = get_pids(service)?.into_iter().filter(|pid| !old.contains(pid)).collect();
+        match new.is_empty() {
+            true => Ok(()),
+            false => terminate(service, &new),
+        }
+    };
+    let reason = match ready::wait(service, pid, mark, timeout) {
+        Ok(Ok(took)) => {
+            println!("New instance ready after {:.1}s, stopping the old PIDs.", took.as_secs_f64());
+            process::stop_pids(service, old.clone())?;
+            let old: Vec<String> = old.iter().map(u64::to_string).collect();
+            return Ok(format!("new PID {} ready after {:.1}s, old PID(s) {} stopped", pid, took.as_secs_f64(), old.join(", ")));
+        }
+        Ok(Err(reason)) => reason,
+        Err(err) => {
+            stop_new()?;
+            return Err(err);
+        }
+    };
+    stop_new()?;
+    Err(SvcError::SwitchoverFailed {
+        service: service.name.to_string(),
+        reason,
+    })
+}
+
+pub fn restart(service: &Service, options: &RestartOptions) -> Result<(), SvcError> {
+    // With a condition only what runs can be restarted; one the condition skips is left alone
+    let only = match options.uptime.given() {
+        true => Some(uptime::select(&options.uptime, service, process::session_pids(service, SessionFilter::All)?)?),
+        false => None,
+    };
+    // Asked before the wait, so a queued restart doesn't stop on the prompt later
+    confirm_protected(service, options.force)?;
+    if options.when_idle && !options.now {
+        wait_for_idle(service, options)?;
+    }
+    if options.overlap {
+        let result = overlap(service, options);
+        let mut record = history::HistoryRecord::new(&service.name, "switchover", result.is_ok());
+        record.output = vec![match &result {
+            Ok(detail) => detail.clone(),
+            Err(err) => err.to_string(),
+        }];
+        let _ = history::record(&record);
+        let result = result.map(drop);
+        notify::outcome(SvcEvent::Restart { service: service.name.to_string() }, "restart", &result);
+        return result;
+    }
+    let result = stop_and_start(service, options, only);
+    history::record_result(&service.name, "restart", &result);
+    notify::outcome(SvcEvent::Restart { service: service.name.to_string() }, "restart", &result);
+    result
+}
+
+// `svc restart --outdated`: every running service whose binary changed on disk since it started,
+// dependencies first; one with a ready_pattern or health_check must be ready before the next
+pub fn restart_outdated(config_path: &str, options: &RestartOptions) -> Result<(), SvcError> {
+    let services = load_config(config_path)?;
+    let mut flagged = Vec::new();
+    for service in stopall::order(&services).into_iter().rev() {
+        if let Some(reason) = outdated::reason(service, &get_pids(service)?) {
+            flagged.push((service, reason));
+        }
+    }
+    if flagged.is_empty() {
+        println!("No running service's binary changed on disk since it started.");
+        return Ok(());
+    }
+    for (service, reason) in flagged {
+        println!("Restarting {}: {}.", service.name.cyan(), reason);
+        let mark = ready::log_mark(service);
+        restart(service, options)?;
+        // An overlapping restart has already waited for it
+        if options.overlap || !ready::configured(service) || effects::dry_run() {
+            continue;
+        }
+        let Some(&pid) = get_pids(service)?.first() else {
+            return Err(SvcError::NotReady {
+                service: service.name.to_string(),
+                reason: "it exited right after it started".to_string(),
+            });
+        };
+        let timeout = service.ready_timeout.as_ref().map_or(ready::DEFAULT_READY_TIMEOUT, |timeout| timeout.get());
+        match ready::wait(service, pid, mark, timeout)? {
+            Ok(took) => println!("Service {} ready after {:.1}s.", service.name.cyan(), took.as_secs_f64()),
+            Err(reason) => {
+                return Err(SvcError::NotReady {
+                    service: service.name.to_string(),
+                    reason,
+                })
+            }
+        }
+    }
+    Ok(())
+}
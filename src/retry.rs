@@ -0,0 +1,411 @@
+use crate::units::{self, HumanDuration};
+use crate::{cancel, output, SvcError};
+use colored::Colorize;
+use serde::{Deserialize, Deserializer};
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// ERROR_SHARING_VIOLATION, ERROR_LOCK_VIOLATION and ERROR_BUSY while a registry hive
+// is being loaded; RPC_S_SERVER_UNAVAILABLE and RPC_S_CALL_FAILED while WMI starts
+const TRANSIENT_CODES: [i32; 5] = [32, 33, 170, 1722, 1726];
+// The same RPC failures as PowerShell prints them for Get-WmiObject
+const TRANSIENT_TEXT: [&str; 3] = ["0x800706BA", "0x800706BE", "RPC server is unavailable"];
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+static RETRIED: AtomicUsize = AtomicUsize::new(0);
+// The transient_retry setting; without one, two retries 250ms and then 500ms apart
+static TRANSIENT: OnceLock<RetryPolicy> = OnceLock::new();
+
+// How often and how far apart something is tried again: the Util `retry`, the watcher's
+// `restart_backoff` and the transient_retry setting all take this shape
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RetryPolicy {
+    // Tries after the first before giving up
+    #[serde(default = "default_attempts")]
+    pub attempts: u32,
+    // Wait before the first retry
+    #[serde(default = "default_delay", deserialize_with = "units::delay")]
+    pub delay: HumanDuration,
+    // Each wait is this many times the one before
+    #[serde(default = "default_multiplier", deserialize_with = "multiplier")]
+    pub multiplier: f64,
+    // No wait is longer than this
+    #[serde(default, deserialize_with = "units::max_delay")]
+    pub max_delay: Option<HumanDuration>,
+    // Each wait is shortened at random by up to half, so retries of many services spread out
+    #[serde(default)]
+    pub jitter: bool,
+}
+
+fn default_attempts() -> u32 {
+    3
+}
+
+fn default_delay() -> HumanDuration {
+    Duration::from_secs(1).into()
+}
+
+fn default_multiplier() -> f64 {
+    2.0
+}
+
+// Below 1 the waits would shrink towards retrying in a tight loop
+fn multiplier<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let multiplier = f64::deserialize(deserializer)?;
+    if !multiplier.is_finite() || multiplier < 1.0 {
+        return Err(serde::de::Error::custom(format!("multiplier: {} is below 1", multiplier)));
+    }
+    Ok(multiplier)
+}
+
+impl RetryPolicy {
+    pub fn new(attempts: u32, delay: Duration, multiplier: f64, max_delay: Option<Duration>) -> RetryPolicy {
+        RetryPolicy {
+            attempts,
+            delay: delay.into(),
+            multiplier,
+            max_delay: max_delay.map(HumanDuration::from),
+            jitter: false,
+        }
+    }
+
+    // The wait before retry number `retry`, counted from 1, before any jitter
+    pub fn delay(&self, retry: u32) -> Duration {
+        let base = self.delay.get();
+        let cap = self.max_delay.as_ref().map_or(Duration::MAX, HumanDuration::get);
+        if retry <= 1 {
+            return base.min(cap);
+        }
+        let exponent = i32::try_from(retry - 1).unwrap_or(i32::MAX);
+        let grown = Duration::try_from_secs_f64(base.as_secs_f64() * self.multiplier.powi(exponent));
+        grown.unwrap_or(Duration::MAX).min(cap)
+    }
+
+    // The wait before each retry, seeded from the clock for the jitter
+    pub fn delays(&self) -> Delays<'_> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_nanos() as u64);
+        self.delays_seeded(nanos ^ u64::from(std::process::id()))
+    }
+
+    // The same from a fixed seed, so the jittered waits come out the same every time
+    pub fn delays_seeded(&self, seed: u64) -> Delays<'_> {
+        Delays {
+            policy: self,
+            retry: 0,
+            random: Random::new(seed),
+        }
+    }
+
+    // `attempt` until it succeeds, fails in a way `retryable` doesn't pass, or the retries run out,
+    // saying what is retried and when unless --quiet
+    pub fn run<T>(
+        &self,
+        what: &str,
+        retryable: impl Fn(&SvcError) -> bool,
+        attempt: impl FnMut() -> Result<T, SvcError>,
+    ) -> Result<T, SvcError> {
+        self.run_with(
+            retryable,
+            |err, retrying| {
+                if !output::options().quiet {
+                    announce(what, err, retrying);
+                }
+                cancel::sleep(retrying.delay)
+            },
+            attempt,
+        )
+    }
+
+    // The same with `wait` doing the waiting, so tests needn't and callers can report it their way
+    pub fn run_with<T>(
+        &self,
+        retryable: impl Fn(&SvcError) -> bool,
+        mut wait: impl FnMut(&SvcError, &Retrying) -> Result<(), SvcError>,
+        mut attempt: impl FnMut() -> Result<T, SvcError>,
+    ) -> Result<T, SvcError> {
+        let mut delays = self.delays();
+        loop {
+            match attempt() {
+                Err(err) if retryable(&err) => {
+                    let Some(delay) = delays.next() else {
+                        return Err(err);
+                    };
+                    let retrying = Retrying {
+                        retry: delays.retry,
+                        of: Some(self.attempts),
+                        delay,
+                    };
+                    wait(&err, &retrying)?;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+// Iterator over the waits before each retry, `attempts` of them
+pub struct Delays<'p> {
+    policy: &'p RetryPolicy,
+    retry: u32,
+    random: Random,
+}
+
+impl Iterator for Delays<'_> {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        if self.retry >= self.policy.attempts {
+            return None;
+        }
+        self.retry += 1;
+        let delay = self.policy.delay(self.retry);
+        if !self.policy.jitter {
+            return Some(delay);
+        }
+        Some(delay.mul_f64(1.0 - self.random.fraction() / 2.0))
+    }
+}
+
+// xorshift64*, enough to spread waits out without pulling in a crate for it
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Random {
+        // A zero state would stay zero
+        Random(seed | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // In [0, 1)
+    fn fraction(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// The retry about to happen, "retry 2 of 3 in 500ms", or without "of" when there is no limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retrying {
+    pub retry: u32,
+    pub of: Option<u32>,
+    pub delay: Duration,
+}
+
+impl Display for Retrying {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "retry {}", self.retry)?;
+        if let Some(of) = self.of {
+            write!(f, " of {}", of)?;
+        }
+        if self.delay < Duration::from_secs(1) {
+            write!(f, " in {}ms", self.delay.as_millis())
+        } else if self.delay.subsec_millis() == 0 {
+            write!(f, " in {}s", self.delay.as_secs())
+        } else {
+            write!(f, " in {:.1}s", self.delay.as_secs_f64())
+        }
+    }
+}
+
+// One line per retry, the same for whatever is retried
+fn announce(what: &str, err: &SvcError, retrying: &Retrying) {
+    eprintln!("{} {} failed ({}), {}", "retry:".yellow().bold(), what, err, retrying);
+}
+
+// --no-retry, for seeing the first failure as it is
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+// From the transient_retry setting
+pub fn set_transient(policy: RetryPolicy) {
+    let _ = TRANSIENT.set(policy);
+}
+
+// How many retries this run took, for --verbose
+pub fn retried() -> usize {
+    RETRIED.load(Ordering::Relaxed)
+}
+
+// Only failures that pass on their own; access denied or not found never do
+fn is_transient(err: &SvcError) -> bool {
+    match err {
+        SvcError::IoError(err) => err.raw_os_error().is_some_and(|code| TRANSIENT_CODES.contains(&code)),
+        SvcError::HelperFailed { output, .. } => {
+            output.iter().any(|line| TRANSIENT_TEXT.iter().any(|text| line.contains(text)))
+        }
+        _ => false,
+    }
+}
+
+// Runs a read-only query, trying again shortly when it fails transiently
+pub fn transient<T>(what: &str, query: impl FnMut() -> Result<T, SvcError>) -> Result<T, SvcError> {
+    let policy = TRANSIENT.get_or_init(|| RetryPolicy::new(2, Duration::from_millis(250), 2.0, None));
+    let retryable = |err: &SvcError| !DISABLED.load(Ordering::Relaxed) && is_transient(err);
+    policy.run_with(
+        retryable,
+        |err, retrying| {
+            RETRIED.fetch_add(1, Ordering::Relaxed);
+            // These pass unnoticed unless asked about
+            if output::options().verbose {
+                announce(what, err, retrying);
+            }
+            cancel::sleep(retrying.delay)
+        },
+        query,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(yaml: &str) -> Result<RetryPolicy, serde_yaml::Error> {
+        serde_yaml::from_str(yaml)
+    }
+
+    // Policies of every shape the config allows, from a fixed seed so a failure reproduces
+    fn arbitrary(count: usize) -> Vec<RetryPolicy> {
+        let mut random = Random::new(0x5eed);
+        (0..count)
+            .map(|_| {
+                let millis = random.next() % 5_000;
+                let capped = !random.next().is_multiple_of(3);
+                RetryPolicy {
+                    attempts: (random.next() % 40) as u32,
+                    delay: Duration::from_millis(millis).into(),
+                    multiplier: 1.0 + random.fraction() * 4.0,
+                    max_delay: capped.then(|| Duration::from_millis(random.next() % 60_000).into()),
+                    jitter: random.next().is_multiple_of(2),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn config_fields_default_and_name_themselves() {
+        let parsed = policy("attempts: 5\ndelay: 500ms\nmax_delay: 10s\njitter: true\n").unwrap();
+        assert_eq!(parsed.attempts, 5);
+        assert_eq!(parsed.delay.get(), Duration::from_millis(500));
+        assert_eq!(parsed.multiplier, 2.0);
+        assert_eq!(parsed.max_delay.map(|cap| cap.get()), Some(Duration::from_secs(10)));
+        assert!(parsed.jitter);
+
+        let defaults = policy("{}").unwrap();
+        assert_eq!(defaults, RetryPolicy::new(3, Duration::from_secs(1), 2.0, None));
+        assert!(policy("multiplier: 0.5").unwrap_err().to_string().contains("multiplier"));
+        assert!(policy("delay: 5").unwrap_err().to_string().contains("delay"));
+    }
+
+    #[test]
+    fn waits_grow_by_the_multiplier_up_to_the_cap() {
+        let policy = RetryPolicy::new(6, Duration::from_millis(250), 2.0, Some(Duration::from_secs(3)));
+        let delays: Vec<u128> = policy.delays().map(|delay| delay.as_millis()).collect();
+        assert_eq!(delays, [250, 500, 1_000, 2_000, 3_000, 3_000]);
+        // Far past where the multiplier overflows
+        assert_eq!(policy.delay(100_000), Duration::from_secs(3));
+        assert_eq!(RetryPolicy::new(1, Duration::from_secs(1), 3.0, None).delay(u32::MAX), Duration::MAX);
+    }
+
+    #[test]
+    fn delay_sequences_respect_the_cap_and_the_multiplier() {
+        for policy in arbitrary(500) {
+            let cap = policy.max_delay.as_ref().map_or(Duration::MAX, HumanDuration::get);
+            let nominal: Vec<Duration> = (1..=policy.attempts).map(|retry| policy.delay(retry)).collect();
+            assert_eq!(policy.delays_seeded(7).count(), policy.attempts as usize, "{:?}", policy);
+            for (retry, pair) in nominal.windows(2).enumerate() {
+                assert!(pair[1] <= cap, "{:?}: retry {} waits {:?}", policy, retry + 2, pair[1]);
+                assert!(pair[1] >= pair[0], "{:?}: retry {} waits less than the one before", policy, retry + 2);
+                let expected = pair[0].as_secs_f64() * policy.multiplier;
+                let slack = expected * 1e-9 + 1e-6;
+                assert!(
+                    (pair[1].as_secs_f64() - expected.min(cap.as_secs_f64())).abs() <= slack || pair[1] == cap,
+                    "{:?}: retry {} waits {:?} after {:?}",
+                    policy,
+                    retry + 2,
+                    pair[1],
+                    pair[0]
+                );
+            }
+            for (waited, nominal) in policy.delays_seeded(42).zip(&nominal) {
+                assert!(waited <= *nominal && waited <= cap, "{:?}: {:?} over {:?}", policy, waited, nominal);
+                if policy.jitter {
+                    assert!(waited >= *nominal / 2, "{:?}: {:?} under half of {:?}", policy, waited, nominal);
+                } else {
+                    assert_eq!(waited, *nominal);
+                }
+            }
+            // The same seed gives the same waits
+            assert!(policy.delays_seeded(42).eq(policy.delays_seeded(42)));
+        }
+    }
+
+    #[test]
+    fn run_retries_only_what_passes_and_only_so_often() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(100), 3.0, None);
+        let failure = || SvcError::HelperFailed {
+            command: "Get-WmiObject".to_string(),
+            reason: "exit code 1".to_string(),
+            output: vec!["RPC server is unavailable".to_string()],
+        };
+
+        let mut waited = Vec::new();
+        let mut tries = 0;
+        let result = policy.run_with(
+            is_transient,
+            |_, retrying| {
+                waited.push(retrying.to_string());
+                Ok(())
+            },
+            || {
+                tries += 1;
+                if tries < 3 {
+                    Err(failure())
+                } else {
+                    Ok(tries)
+                }
+            },
+        );
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(waited, ["retry 1 of 3 in 100ms", "retry 2 of 3 in 300ms"]);
+
+        let mut tries = 0;
+        let result: Result<(), _> = policy.run_with(is_transient, |_, _| Ok(()), || {
+            tries += 1;
+            Err(failure())
+        });
+        assert!(result.is_err());
+        assert_eq!(tries, 4);
+
+        let mut tries = 0;
+        let result: Result<(), _> = policy.run_with(is_transient, |_, _| Ok(()), || {
+            tries += 1;
+            Err(SvcError::ServiceIsNotRunning)
+        });
+        assert!(matches!(result, Err(SvcError::ServiceIsNotRunning)));
+        assert_eq!(tries, 1);
+
+        // An interrupted wait ends the retries with it
+        let result: Result<(), _> = policy.run_with(is_transient, |_, _| Err(SvcError::Interrupted), || Err(failure()));
+        assert!(matches!(result, Err(SvcError::Interrupted)));
+    }
+
+    #[test]
+    fn retries_read_the_same_wherever_they_happen() {
+        let retrying = |of, millis| Retrying {
+            retry: 2,
+            of,
+            delay: Duration::from_millis(millis),
+        };
+        assert_eq!(retrying(Some(5), 250).to_string(), "retry 2 of 5 in 250ms");
+        assert_eq!(retrying(None, 4_000).to_string(), "retry 2 in 4s");
+        assert_eq!(retrying(Some(5), 1_500).to_string(), "retry 2 of 5 in 1.5s");
+    }
+}
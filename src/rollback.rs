@@ -0,0 +1,129 @@
+use crate::backends::{self, Backend};
+use crate::startup::{self, MACHINE_RUN_KEY, RUN_KEY};
+use crate::{output, Service, Settings, SvcError};
+use colored::Colorize;
+
+// A Run value svc may write for the service, as it was before the batch
+struct Value {
+    key: &'static str,
+    name: String,
+    data: Option<String>,
+}
+
+// A service's start-up entries before the batch changed any of them
+struct Before {
+    values: Vec<Value>,
+    // Entries on the backends that aren't a Run value, which can only be re-created or removed
+    others: Vec<Backend>,
+}
+
+fn others(service: &Service) -> Result<Vec<Backend>, SvcError> {
+    Ok(backends::query(service)?
+        .into_iter()
+        .map(|entry| entry.backend)
+        .filter(|&backend| backend.writable() && !matches!(backend, Backend::RunKey | Backend::MachineRunKey))
+        .collect())
+}
+
+fn capture(service: &Service) -> Result<Before, SvcError> {
+    let mut values = Vec::new();
+    // The namespaced name and the bare one older versions wrote
+    for name in [startup::value_name(&service.name), service.name.to_string()] {
+        for key in [RUN_KEY, MACHINE_RUN_KEY] {
+            let data = startup::query_value_in(key, &name)?;
+            values.push(Value {
+                key,
+                name: name.clone(),
+                data,
+            });
+        }
+    }
+    Ok(Before {
+        values,
+        others: others(service)?,
+    })
+}
+
+// Run values get their exact data back; a task or shortcut is re-created or removed
+fn restore(service: &Service, settings: &Settings, before: &Before) -> Result<(), SvcError> {
+    let _lock = crate::mutex::lock("startup", &service.name)?;
+    for value in &before.values {
+        if startup::query_value_in(value.key, &value.name)? == value.data {
+            continue;
+        }
+        match &value.data {
+            Some(data) => startup::set_value_in(value.key, &value.name, data)?,
+            None => startup::delete_value_in(value.key, &value.name)?,
+        }
+    }
+    let now = others(service)?;
+    for &backend in before.others.iter().filter(|backend| !now.contains(backend)) {
+        backends::enable(service, backend, &startup::run_data(service, settings)?)?;
+    }
+    for &backend in now.iter().filter(|backend| !before.others.contains(backend)) {
+        backends::disable(service, backend)?;
+    }
+    Ok(())
+}
+
+// A batch of start-up changes run with --rollback-on-failure: every service's entries are read
+// before the first change, so those already changed can be put back when a later one fails
+pub struct Batch<'a> {
+    settings: &'a Settings,
+    before: Vec<(&'a Service<'a>, Before)>,
+}
+
+impl<'a> Batch<'a> {
+    // A service whose entries can't be read stops the batch before anything changes
+    pub fn capture(services: &[&'a Service<'a>], settings: &'a Settings) -> Result<Batch<'a>, SvcError> {
+        let before = services
+            .iter()
+            .map(|&service| Ok((service, capture(service)?)))
+            .collect::<Result<Vec<_>, SvcError>>()?;
+        Ok(Batch { settings, before })
+    }
+
+    // Put back the services this run changed, last first; one that can't be put back is
+    // reported and the others are still tried. Returns those left as they are
+    pub fn roll_back(&self, changed: &[&str]) -> Vec<String> {
+        let text = !output::options().json;
+        if text && !changed.is_empty() {
+            println!("Rolling back {} change(s):", changed.len());
+        }
+        let mut unreverted = Vec::new();
+        let changed = self.before.iter().rev().filter(|(service, _)| changed.contains(&service.name.as_ref()));
+        for (service, before) in changed {
+            match restore(service, self.settings, before) {
+                Ok(()) if text => println!("  {} {} rolled back", "<".green(), service.name.cyan()),
+                Ok(()) => {}
+                Err(err) => {
+                    if text {
+                        println!("  {} {} could not be rolled back: {}", "x".red(), service.name.cyan(), err);
+                    }
+                    unreverted.push(service.name.to_string());
+                }
+            }
+        }
+        unreverted
+    }
+
+    // What each service of the batch has now, read back rather than assumed
+    pub fn print_end_state(&self) {
+        if output::options().json {
+            return;
+        }
+        println!("End state:");
+        let width = self.before.iter().map(|(service, _)| output::width(&service.name)).max().unwrap_or(0);
+        for (service, _) in &self.before {
+            let state = match backends::query(service) {
+                Ok(entries) if entries.is_empty() => "disabled".yellow().to_string(),
+                Ok(entries) => {
+                    let backends: Vec<String> = entries.iter().map(|entry| entry.backend.to_string()).collect();
+                    format!("{} ({})", "enabled".green(), backends.join(", "))
+                }
+                Err(err) => format!("{} ({})", "unknown".red(), err),
+            };
+            println!("  {}  {}", output::pad(&service.name, width), state);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{apply_env, capture, elevation, secrets, state, RunAs, Service, SvcError};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+pub fn pid_file(name: &str) -> String {
+    state::service_file(name, "runas.pid")
+}
+
+// Quote a value as a PowerShell single-quoted string literal; PowerShell also takes the curly
+// single quotes for one, so those are doubled too
+pub fn ps_quote(value: &str) -> String {
+    let mut quoted = String::from('\'');
+    for c in value.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
+
+// Start-Process -Credential goes through CreateProcessWithLogonW; the DPAPI blob is
+// only decrypted inside the PowerShell process, so the password never hits a command line
+pub fn spawn(service: &Service, run_as: &RunAs, work_at: &str) -> Result<u64, SvcError> {
+    let blob = secrets::read_blob(&run_as.password_secret)?;
+
+    let mut script = format!(
+        "$ErrorActionPreference = 'Stop'\n\
+         $password = ConvertTo-SecureString {}\n\
+         $credential = New-Object System.Management.Automation.PSCredential({}, $password)\n\
+         $process = Start-Process -FilePath {} -Credential $credential -PassThru",
+        ps_quote(&blob),
+        ps_quote(&run_as.user),
+        ps_quote(&service.path),
+    );
+    if !work_at.is_empty() {
+        script.push_str(&format!(" -WorkingDirectory {}", ps_quote(work_at)));
+    }
+    // Start-Process joins an argument list with bare spaces, so it gets one pre-quoted line
+    if !service.args.is_empty() {
+        let line: Vec<String> = service.args.iter().map(|arg| elevation::quote(arg)).collect();
+        script.push_str(&format!(" -ArgumentList {}", ps_quote(&line.join(" "))));
+    }
+    // A failure to start leaves as its Win32 error code, what tells a refused logon apart
+    let script = format!(
+        "try {{\n{}\n}} catch {{\n\
+         [Console]::Error.WriteLine($_.Exception.Message)\n\
+         $e = $_.Exception\n\
+         while ($e -and $e -isnot [ComponentModel.Win32Exception]) {{ $e = $e.InnerException }}\n\
+         exit $(if ($e) {{ $e.NativeErrorCode }} else {{ 1 }})\n\
+         }}\n\
+         $process.Id\n",
+        script
+    );
+
+    // Start-Process hands its own environment on, so env reaches the other account too,
+    // expanded with this account's variables
+    let mut command = Command::new("powershell");
+    apply_env(&mut command, service);
+    let mut child = command
+        .args(["-NoProfile", "-Command", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(script.as_bytes())?;
+    }
+    let output = capture::wait_output(child)?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or("unknown error");
+        return Err(failure(service, run_as, output.status.code(), reason));
+    }
+
+    let pid = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| SvcError::FailedToParsePID)?;
+    state::write_pid_file(&pid_file(&service.name), pid)?;
+    Ok(pid)
+}
+
+// Win32 errors of a logon that was refused: bad credentials, or an account that may not log on
+// this way or now
+const LOGON_ERRORS: [i32; 10] = [1326, 1327, 1328, 1329, 1330, 1331, 1385, 1793, 1907, 1909];
+
+// A refused logon is LogonFailed; a missing program, a bad working directory or a secret that
+// doesn't decrypt fails the start like any other
+fn failure(service: &Service, run_as: &RunAs, code: Option<i32>, reason: &str) -> SvcError {
+    match code {
+        Some(code) if LOGON_ERRORS.contains(&code) => SvcError::LogonFailed {
+            user: run_as.user.to_string(),
+            reason: reason.to_string(),
+        },
+        _ => SvcError::IoError(std::io::Error::other(format!(
+            "failed to start {} as {}: {}",
+            service.path, run_as.user, reason
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_a_refused_logon_is_a_logon_failure() {
+        let config = crate::parse_config(
+            "- { name: api, path: D:\\api.exe, type: Executable, run_as: { user: svc-api, password_secret: api } }\n",
+        )
+        .unwrap();
+        let service = &config.services[0];
+        let run_as = service.run_as.as_ref().unwrap();
+        let reason = "The user name or password is incorrect.";
+        assert!(matches!(failure(service, run_as, Some(1326), reason), SvcError::LogonFailed { .. }));
+        // Not granted the logon type
+        assert!(matches!(failure(service, run_as, Some(1385), reason), SvcError::LogonFailed { .. }));
+        // The file or directory not found, or a secret DPAPI can't decrypt
+        for code in [Some(2), Some(267), Some(1), None] {
+            let err = failure(service, run_as, code, "The system cannot find the file specified.");
+            assert!(matches!(&err, SvcError::IoError(_)), "{:?}", code);
+            assert!(err.to_string().contains("failed to start D:\\api.exe as svc-api"), "{}", err);
+        }
+    }
+}
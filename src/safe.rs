@@ -0,0 +1,34 @@
+use colored::Colorize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// What --safe leaves out, as the notice lists it
+const SKIPPED: &str = "saved state, history, notifications, enrichers, metrics and the config cache";
+
+static SAFE: AtomicBool = AtomicBool::new(false);
+
+// --safe or SVC_SAFE, for when something svc keeps for itself is broken
+pub fn enable() {
+    SAFE.store(true, Ordering::Relaxed);
+}
+
+pub fn on() -> bool {
+    SAFE.load(Ordering::Relaxed)
+}
+
+// Where state goes in safe mode: empty at the start and private to this invocation, so
+// nothing an earlier one wrote is read and nothing it writes outlives it
+pub fn scratch_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("svc-safe-{}", std::process::id()))
+}
+
+pub fn notice() {
+    eprintln!("{} safe mode, skipping {}", "note:".cyan().bold(), SKIPPED);
+}
+
+// Best effort: an exit in the middle of a command leaves the directory to the temp cleanup
+pub fn finish() {
+    if on() {
+        let _ = std::fs::remove_dir_all(scratch_dir());
+    }
+}
@@ -0,0 +1,461 @@
+use crate::backends::Backend;
+use crate::capabilities::{fields, variants, CONFIG_SCHEMA};
+use crate::enrich::Enricher;
+use crate::firewall::{Firewall, Protocol};
+use crate::idlestop::IdleMetric;
+use crate::notify::{Email, EventKind, EventLog, Webhook};
+use crate::priority::Priority;
+use crate::retry::RetryPolicy;
+use crate::steps::Step;
+use crate::stopsignal::StopSignal;
+use crate::{PathsRelativeTo, RestartPolicy, RunAs, Service, ServiceType, Settings, StartupState, SvcError};
+use serde_json::{json, Map, Value};
+
+// What units::HumanDuration accepts, e.g. 500ms, 30s, 1.5h
+const DURATION_PATTERN: &str = r"^\s*[0-9.]+\s*(ms|s|m|h|d)\s*$";
+// What units::ByteSize accepts, e.g. 512M, 1.5G, 200MiB
+const SIZE_PATTERN: &str = r"^\s*[0-9.]+\s*([bB]|[kKmMgGtT](i?[bB])?)\s*$";
+
+fn duration(description: &str) -> Value {
+    json!({ "type": "string", "pattern": DURATION_PATTERN, "description": description })
+}
+
+fn size(description: &str) -> Value {
+    json!({ "type": "string", "pattern": SIZE_PATTERN, "description": description })
+}
+
+fn one_of(values: &[&str], description: &str) -> Value {
+    json!({ "enum": values, "description": description })
+}
+
+fn flag(description: &str) -> Value {
+    json!({ "type": "boolean", "default": false, "description": description })
+}
+
+fn text(description: &str) -> Value {
+    json!({ "type": "string", "description": description })
+}
+
+fn names(description: &str) -> Value {
+    json!({ "type": "array", "items": { "type": "string" }, "description": description })
+}
+
+// Type, default and description of each Service field
+fn service_field(name: &str) -> Option<Value> {
+    Some(match name {
+        "name" => text("unique name used on the command line"),
+        "path" => text("the executable, or the script a Util runs"),
+        "type" => one_of(variants::<ServiceType>(), "Executable runs in the background, Util is waited on"),
+        "interpreter" => json!({ "type": "string", "default": "python", "description": "Util only, runs the script" }),
+        "shell" => text("Util only, an entry of the shells setting, run instead of interpreter"),
+        "work_at" => text("working directory, defaults to the directory of path"),
+        "args" => names("arguments passed after the path, or after the script of a Util"),
+        "log" => json!({
+            "type": ["boolean", "string"],
+            "description": "Executable only, file stdout and stderr are appended to; true for logs\\<name>.log next to path"
+        }),
+        "env" => json!({
+            "type": "object",
+            "additionalProperties": { "type": "string" },
+            "description": "environment variables set for the process, values may use %VAR% or ${VAR}"
+        }),
+        "restart" => json!({
+            "enum": variants::<RestartPolicy>(),
+            "default": "never",
+            "description": "whether `svc watch` relaunches it when it exits"
+        }),
+        "restart_delay" => duration("wait before `svc watch` relaunches it, doubled while it keeps exiting"),
+        "restart_backoff" => json!({
+            "$ref": "#/$defs/retry",
+            "description": "how `svc watch` backs off relaunches of a service that keeps exiting, over doubling restart_delay"
+        }),
+        "max_restarts_per_hour" => json!({
+            "type": "integer",
+            "minimum": 1,
+            "description": "relaunches by `svc watch` within an hour before it leaves the service down"
+        }),
+        "autostart" => flag("expected to run by `svc check`"),
+        "capture_stdout" => flag("Util only, keep stdout along with stderr"),
+        "encoding" => json!({
+            "anyOf": [
+                { "enum": ["utf8", "oem"] },
+                { "type": "integer", "minimum": 1 },
+                { "type": "string", "pattern": "^[0-9]+$" }
+            ],
+            "description": "how captured output and the log are decoded: utf8, oem or a code page number"
+        }),
+        "chcp" => flag("Util under cmd with encoding utf8, switch the console to code page 65001 first"),
+        "temp_work_dir" => flag("Util only, run in a fresh directory under %TEMP% each time"),
+        "protected" => flag("kill asks for confirmation"),
+        "label" => text("display name in status and watch logs"),
+        "tags" => names("groups run, kill, status, enable and disable select the service by with --tag"),
+        "startup_order" => json!({ "type": "integer", "minimum": 0, "description": "position in the start-up stagger" }),
+        "terminal_profile" => text("Windows Terminal profile used by `run --terminal`"),
+        "depends_on" => names("services `svc run` starts first, and that have to run for this one to count as healthy"),
+        "steps" => json!({
+            "type": "array",
+            "items": { "$ref": "#/$defs/step" },
+            "description": "commands run in order before the service itself"
+        }),
+        "startup" => one_of(variants::<StartupState>(), "start-up state `svc apply-startup` keeps"),
+        "startup_backend" => json!({
+            "enum": variants::<Backend>(),
+            "description": "where enable registers it without --backend, over the startup_backend setting"
+        }),
+        "elevated" => flag("scheduled-task only, the logon task runs with highest privileges"),
+        "logon_delay" => duration("the start-up entry waits this long through `svc run --startup`"),
+        "disk_budget" => size("most svc keeps for it in its own directories before pruning the oldest"),
+        "startup_env_allowlist" => names("variables enable captures and `svc run --startup` sets again at logon"),
+        "priority" => one_of(variants::<Priority>(), "priority class set when it starts"),
+        "affinity" => json!({
+            "type": "array",
+            "items": { "type": "integer", "minimum": 0, "maximum": usize::BITS - 1 },
+            "description": "CPU numbers it may run on"
+        }),
+        "per_session" => flag("each logon session may run its own instance"),
+        "stop_signal" => json!({
+            "oneOf": variants::<StopSignal>()
+                .iter()
+                .map(|variant| json!({
+                    "type": "object",
+                    "properties": { *variant: { "type": "string" } },
+                    "required": [variant],
+                    "additionalProperties": false
+                }))
+                .collect::<Vec<_>>(),
+            "description": "ask it to exit before kill terminates it"
+        }),
+        "stop_grace" => json!({
+            "type": "string",
+            "pattern": DURATION_PATTERN,
+            "default": "10s",
+            "description": "how long kill waits on stop_signal"
+        }),
+        "stop_timeout" => json!({
+            "type": "string",
+            "pattern": DURATION_PATTERN,
+            "default": "10s",
+            "description": "how long stop waits for the service to exit before terminating it"
+        }),
+        "ready_pattern" => text("Executable only, text in its log meaning a new instance is up, for `restart --overlap`"),
+        "health_check" => json!({
+            "type": "string",
+            "pattern": "^(http|tcp)://",
+            "description": "http://host:port/path answering 2xx or 3xx, or tcp://host:port accepting connections"
+        }),
+        "port" => json!({
+            "type": "integer",
+            "minimum": 1,
+            "maximum": 65535,
+            "description": "TCP port it listens on; no two services may declare the same one"
+        }),
+        "ready_timeout" => json!({
+            "type": "string",
+            "pattern": DURATION_PATTERN,
+            "default": "30s",
+            "description": "how long `restart --overlap` waits for the new instance to be ready"
+        }),
+        "idle_stop" => json!({
+            "type": "object",
+            "properties": {
+                "after": { "type": "string", "pattern": DURATION_PATTERN },
+                "metric": { "enum": variants::<IdleMetric>(), "default": "cpu" },
+                "cpu_below": { "type": "number", "minimum": 0, "default": 5 }
+            },
+            "required": ["after"],
+            "description": "`svc watch` stops it once it has been idle for `after`"
+        }),
+        "retry" => json!({
+            "$ref": "#/$defs/retry",
+            "description": "Util only, run it again when it exits with an error"
+        }),
+        "lock" => text("file held exclusively while the steps and the service run, relative to the working directory"),
+        "run_as" => json!({ "$ref": "#/$defs/run_as" }),
+        "firewall" => json!({ "$ref": "#/$defs/firewall" }),
+        _ => return None,
+    })
+}
+
+fn step_field(name: &str) -> Option<Value> {
+    Some(match name {
+        "path" | "type" | "interpreter" => service_field(name)?,
+        "args" => names("arguments passed to the step"),
+        "continue_on_error" => flag("carry on with the next step when this one fails"),
+        _ => return None,
+    })
+}
+
+fn run_as_field(name: &str) -> Option<Value> {
+    Some(match name {
+        "user" => text("account to launch under, e.g. .\\account"),
+        "password_secret" => text("key of the password in the secret store"),
+        _ => return None,
+    })
+}
+
+fn firewall_field(name: &str) -> Option<Value> {
+    Some(match name {
+        "inbound_ports" => json!({
+            "type": "array",
+            "items": { "type": "integer", "minimum": 1, "maximum": 65535 },
+            "description": "ports the rule opens, every port when empty"
+        }),
+        "protocol" => json!({ "enum": variants::<Protocol>(), "default": "tcp", "description": "protocol the rule allows" }),
+        _ => return None,
+    })
+}
+
+fn settings_field(name: &str) -> Option<Value> {
+    Some(match name {
+        "startup_stagger" => duration("start-up entries are delayed by startup_order × this"),
+        "history_output_lines" => json!({
+            "type": "integer",
+            "minimum": 0,
+            "default": 100,
+            "description": "output lines kept per failed history record"
+        }),
+        "transient_retry" => json!({
+            "$ref": "#/$defs/retry",
+            "description": "retries of registry and WMI queries that fail in a way that passes, default 2 from 250ms"
+        }),
+        "disk_budget" => size("disk_budget of services without their own"),
+        "startup_backend" => json!({
+            "enum": variants::<Backend>(),
+            "default": "run-key",
+            "description": "where enable and disable register start-up entries without --backend"
+        }),
+        "notify" => json!({
+            "type": "array",
+            "items": { "$ref": "#/$defs/notify_sink" },
+            "description": "notification sinks, each receiving the events it lists"
+        }),
+        "enrichers" => json!({
+            "type": "array",
+            "items": { "$ref": "#/$defs/enricher" },
+            "description": "programs given each service as JSON on stdin, whose JSON object shows under Extra"
+        }),
+        "command_aliases" => json!({
+            "type": "object",
+            "additionalProperties": { "type": "string" },
+            "description": "extra command names and what they expand to"
+        }),
+        "shells" => json!({
+            "type": "object",
+            "additionalProperties": {
+                "oneOf": [
+                    { "type": "string" },
+                    {
+                        "type": "object",
+                        "properties": {
+                            "command": { "type": "string" },
+                            "hosts": { "type": "object", "additionalProperties": { "type": "string" } }
+                        },
+                        "required": ["command"]
+                    }
+                ]
+            },
+            "description": "command lines, executable and flags, a Util's script is appended to; per computer name under hosts"
+        }),
+        "extends" => names("workspace .svc.yaml only, services of the global services.yaml to use"),
+        "lenient" => json!({
+            "type": "boolean",
+            "default": false,
+            "description": "leave out service entries that don't parse or validate instead of failing, as --lenient does"
+        }),
+        "kill_removed" => json!({
+            "type": "boolean",
+            "default": false,
+            "description": "watch kills services removed from the config, unless run with --no-kill-removed"
+        }),
+        "paths_relative_to" => json!({
+            "enum": variants::<PathsRelativeTo>(),
+            "default": "current-dir",
+            "description": "what a relative path or work_at is taken from: the current directory or the config's"
+        }),
+        "hosts" => json!({
+            "type": "object",
+            "additionalProperties": {
+                "type": "object",
+                "additionalProperties": { "$ref": "#/$defs/service_override" }
+            },
+            "description": "per-machine overrides: computer name, then service name, then fields"
+        }),
+        _ => return None,
+    })
+}
+
+fn retry_field(name: &str) -> Option<Value> {
+    Some(match name {
+        "attempts" => json!({ "type": "integer", "minimum": 0, "default": 3, "description": "tries after the first" }),
+        "delay" => json!({
+            "type": "string",
+            "pattern": DURATION_PATTERN,
+            "default": "1s",
+            "description": "wait before the first retry"
+        }),
+        "multiplier" => json!({
+            "type": "number",
+            "minimum": 1,
+            "default": 2,
+            "description": "each wait is this many times the one before"
+        }),
+        "max_delay" => duration("no wait is longer than this"),
+        "jitter" => flag("shorten each wait at random by up to half"),
+        _ => return None,
+    })
+}
+
+fn enricher_field(name: &str) -> Option<Value> {
+    Some(match name {
+        "command" => text("the program to run per service"),
+        "args" => names("arguments to the program"),
+        "timeout" => duration("how long it has to answer, default 2s"),
+        _ => return None,
+    })
+}
+
+// Fields of every kind of sink share one table; kind says which of them apply
+fn notify_field(name: &str) -> Option<Value> {
+    Some(match name {
+        "kind" => one_of(&["webhook", "toast", "event_log", "email"], "where notifications go"),
+        "events" => json!({
+            "type": "array",
+            "items": { "enum": variants::<EventKind>() },
+            "description": "events sent to this sink, every one when empty"
+        }),
+        "url" => text("webhook only, receives each event as a JSON POST"),
+        "source" => json!({ "type": "string", "default": "svc", "description": "event_log only, the event source" }),
+        "smtp_server" => text("email only, the SMTP relay"),
+        "port" => json!({ "type": "integer", "default": 25, "description": "email only, SMTP port" }),
+        "from" => text("email only, sender address"),
+        "to" => names("email only, recipients"),
+        "user" => text("email only, account to authenticate with"),
+        "password_secret" => text("email only, key of the SMTP password in the secret store"),
+        "tls" => flag("email only, use TLS"),
+        _ => return None,
+    })
+}
+
+// Properties for every field serde knows about, so a field missing from the tables
+// still appears, untyped, rather than being reported as unknown by editors
+fn properties(fields: &[&str], describe: fn(&str) -> Option<Value>) -> Map<String, Value> {
+    fields
+        .iter()
+        .map(|&field| (field.to_string(), describe(field).unwrap_or_else(|| json!({}))))
+        .collect()
+}
+
+fn notify_fields() -> Vec<&'static str> {
+    let mut all = vec!["kind", "events"];
+    for fields in [fields::<Webhook>(), fields::<EventLog>(), fields::<Email>()] {
+        all.extend(fields.iter().filter(|field| !all.contains(field)).collect::<Vec<_>>());
+    }
+    all
+}
+
+pub fn print() -> Result<(), SvcError> {
+    let service_properties = properties(fields::<Service>(), service_field);
+    let mut override_properties = service_properties.clone();
+    override_properties.remove("name");
+
+    let mut instance_properties = override_properties.clone();
+    instance_properties.insert("name".to_string(), service_properties["name"].clone());
+    instance_properties.insert("instances_of".to_string(), text("the template under templates this service expands"));
+    instance_properties.insert(
+        "vars".to_string(),
+        json!({ "type": "object", "description": "values of the template's {{var}} placeholders" }),
+    );
+
+    let mut top = properties(fields::<Settings>(), settings_field);
+    top.insert(
+        "services".to_string(),
+        json!({
+            "type": "array",
+            "items": { "anyOf": [{ "$ref": "#/$defs/service" }, { "$ref": "#/$defs/instance" }] }
+        }),
+    );
+    top.insert(
+        "templates".to_string(),
+        json!({
+            "type": "object",
+            "additionalProperties": { "$ref": "#/$defs/service_override" },
+            "description": "service bodies with {{var}} placeholders, expanded by entries with instances_of"
+        }),
+    );
+
+    let schema = json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "svc services.yaml",
+        "x-svc-config-schema": CONFIG_SCHEMA,
+        "oneOf": [
+            { "type": "array", "items": { "$ref": "#/$defs/service" } },
+            { "type": "object", "properties": top }
+        ],
+        "$defs": {
+            "service": {
+                "type": "object",
+                "properties": service_properties,
+                "required": ["name", "path", "type"]
+            },
+            "service_override": { "type": "object", "properties": override_properties },
+            "instance": {
+                "type": "object",
+                "properties": instance_properties,
+                "required": ["name", "instances_of"]
+            },
+            "step": {
+                "type": "object",
+                "properties": properties(fields::<Step>(), step_field),
+                "required": ["path", "type"]
+            },
+            "run_as": {
+                "type": "object",
+                "properties": properties(fields::<RunAs>(), run_as_field),
+                "required": ["user", "password_secret"]
+            },
+            "firewall": { "type": "object", "properties": properties(fields::<Firewall>(), firewall_field) },
+            "retry": { "type": "object", "properties": properties(fields::<RetryPolicy>(), retry_field) },
+            "enricher": {
+                "type": "object",
+                "properties": properties(fields::<Enricher>(), enricher_field),
+                "required": ["command"]
+            },
+            "notify_sink": {
+                "type": "object",
+                "properties": properties(&notify_fields(), notify_field),
+                "required": ["kind"]
+            }
+        }
+    });
+    println!("{}", serde_json::to_string_pretty(&schema).map_err(std::io::Error::other)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Describe = fn(&str) -> Option<Value>;
+
+    #[test]
+    fn every_config_field_is_described() {
+        let tables: [(&str, Vec<&str>, Describe); 8] = [
+            ("service", fields::<Service>().to_vec(), service_field),
+            ("settings", fields::<Settings>().to_vec(), settings_field),
+            ("step", fields::<Step>().to_vec(), step_field),
+            ("run_as", fields::<RunAs>().to_vec(), run_as_field),
+            ("firewall", fields::<Firewall>().to_vec(), firewall_field),
+            ("retry", fields::<RetryPolicy>().to_vec(), retry_field),
+            ("enricher", fields::<Enricher>().to_vec(), enricher_field),
+            ("notify", notify_fields(), notify_field),
+        ];
+        let mut missing = Vec::new();
+        for (table, fields, describe) in tables {
+            // An empty list would mean the field capture broke, not that all is described
+            assert!(!fields.is_empty(), "{} has no fields", table);
+            let undescribed = fields.iter().filter(|field| describe(field).is_none());
+            missing.extend(undescribed.map(|field| format!("{}.{}", table, field)));
+        }
+        assert!(missing.is_empty(), "without a schema: {}", missing.join(", "));
+    }
+}
@@ -0,0 +1,189 @@
+use crate::{pathcmp, Service, ServiceType, SvcError};
+use serde::Serialize;
+use std::sync::OnceLock;
+
+// A Windows service whose binary is a configured service's path, so the SCM controls it too
+#[derive(Debug, Clone, Serialize)]
+pub struct Registration {
+    pub name: String,
+    pub state: String,
+    #[serde(skip)]
+    bin_path: String,
+}
+
+// Asked once per invocation, since status and list look at every service
+static INSTALLED: OnceLock<Vec<Registration>> = OnceLock::new();
+
+fn installed() -> &'static [Registration] {
+    INSTALLED.get_or_init(query)
+}
+
+// Every Win32 service with its binPath; one the account may not query is left out, and
+// without the SCM to ask nothing is registered as far as svc can tell
+#[cfg(windows)]
+fn query() -> Vec<Registration> {
+    use windows_sys::Win32::System::Services::{
+        CloseServiceHandle, EnumServicesStatusExW, OpenSCManagerW, OpenServiceW, QueryServiceConfigW,
+        ENUM_SERVICE_STATUS_PROCESSW, QUERY_SERVICE_CONFIGW, SC_ENUM_PROCESS_INFO, SC_MANAGER_ENUMERATE_SERVICE,
+        SERVICE_QUERY_CONFIG, SERVICE_STATE_ALL, SERVICE_WIN32,
+    };
+
+    // A NUL-terminated wide string the SCM filled in
+    unsafe fn text(pointer: *const u16) -> String {
+        if pointer.is_null() {
+            return String::new();
+        }
+        let length = (0..).take_while(|&at| *pointer.add(at) != 0).count();
+        String::from_utf16_lossy(std::slice::from_raw_parts(pointer, length))
+    }
+
+    unsafe {
+        let manager = OpenSCManagerW(std::ptr::null(), std::ptr::null(), SC_MANAGER_ENUMERATE_SERVICE);
+        if manager.is_null() {
+            return Vec::new();
+        }
+        let (mut needed, mut count, mut resume) = (0u32, 0u32, 0u32);
+        let enumerate = |buffer: *mut u8, size: u32, needed: &mut u32, count: &mut u32, resume: &mut u32| {
+            EnumServicesStatusExW(
+                manager,
+                SC_ENUM_PROCESS_INFO,
+                SERVICE_WIN32,
+                SERVICE_STATE_ALL,
+                buffer,
+                size,
+                needed,
+                count,
+                resume,
+                std::ptr::null(),
+            )
+        };
+        enumerate(std::ptr::null_mut(), 0, &mut needed, &mut count, &mut resume);
+        // u64s keep the entries' pointers aligned
+        let mut buffer = vec![0u64; (needed as usize).div_ceil(8)];
+        resume = 0;
+        let size = (buffer.len() * 8) as u32;
+        if enumerate(buffer.as_mut_ptr().cast(), size, &mut needed, &mut count, &mut resume) == 0 {
+            CloseServiceHandle(manager);
+            return Vec::new();
+        }
+        let entries = std::slice::from_raw_parts(buffer.as_ptr().cast::<ENUM_SERVICE_STATUS_PROCESSW>(), count as usize);
+        let mut registrations = Vec::new();
+        for entry in entries {
+            let service = OpenServiceW(manager, entry.lpServiceName, SERVICE_QUERY_CONFIG);
+            if service.is_null() {
+                continue;
+            }
+            let mut needed = 0u32;
+            QueryServiceConfigW(service, std::ptr::null_mut(), 0, &mut needed);
+            let mut config = vec![0u64; (needed as usize).div_ceil(8)];
+            let queried = QueryServiceConfigW(service, config.as_mut_ptr().cast(), (config.len() * 8) as u32, &mut needed);
+            CloseServiceHandle(service);
+            if queried == 0 {
+                continue;
+            }
+            let config = &*config.as_ptr().cast::<QUERY_SERVICE_CONFIGW>();
+            registrations.push(Registration {
+                name: text(entry.lpServiceName),
+                state: state_name(entry.ServiceStatusProcess.dwCurrentState).to_string(),
+                bin_path: text(config.lpBinaryPathName),
+            });
+        }
+        CloseServiceHandle(manager);
+        registrations
+    }
+}
+
+#[cfg(not(windows))]
+fn query() -> Vec<Registration> {
+    Vec::new()
+}
+
+#[cfg(windows)]
+fn state_name(state: u32) -> &'static str {
+    use windows_sys::Win32::System::Services::{
+        SERVICE_CONTINUE_PENDING, SERVICE_PAUSED, SERVICE_PAUSE_PENDING, SERVICE_RUNNING, SERVICE_START_PENDING,
+        SERVICE_STOPPED, SERVICE_STOP_PENDING,
+    };
+    match state {
+        SERVICE_STOPPED => "stopped",
+        SERVICE_START_PENDING => "starting",
+        SERVICE_STOP_PENDING => "stopping",
+        SERVICE_RUNNING => "running",
+        SERVICE_CONTINUE_PENDING => "resuming",
+        SERVICE_PAUSE_PENDING => "pausing",
+        SERVICE_PAUSED => "paused",
+        _ => "unknown",
+    }
+}
+
+// The program of a binPath: quoted, or up to the first .exe since unquoted paths may hold
+// spaces, or up to the first space
+fn program(bin_path: &str) -> &str {
+    let line = bin_path.trim();
+    if let Some(rest) = line.strip_prefix('"') {
+        return rest.split('"').next().unwrap_or(rest);
+    }
+    match line.to_ascii_lowercase().find(".exe") {
+        Some(at) => &line[..at + 4],
+        None => line.split_whitespace().next().unwrap_or(line),
+    }
+}
+
+// The Windows services that run this service's binary; a Util's script can't be one
+pub fn registrations(service: &Service) -> Vec<Registration> {
+    if !matches!(service.service_type, ServiceType::Executable) {
+        return Vec::new();
+    }
+    matching(installed(), service)
+}
+
+fn matching(installed: &[Registration], service: &Service) -> Vec<Registration> {
+    installed
+        .iter()
+        .filter(|registration| pathcmp::same_file(program(&registration.bin_path), &service.path))
+        .cloned()
+        .collect()
+}
+
+// Run and kill leave a service the SCM also controls to `sc`, unless forced
+pub fn refuse(service: &Service, force: bool) -> Result<(), SvcError> {
+    if force {
+        return Ok(());
+    }
+    match registrations(service).into_iter().next() {
+        Some(registration) => Err(SvcError::ScmManaged {
+            service: service.name.to_string(),
+            scm_name: registration.name,
+            state: registration.state,
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_program_of_a_bin_path() {
+        assert_eq!(program(r#""C:\Program Files\Foo\foo.exe" -service"#), r"C:\Program Files\Foo\foo.exe");
+        assert_eq!(program(r"C:\Program Files\Foo\foo.EXE -service"), r"C:\Program Files\Foo\foo.EXE");
+        assert_eq!(program(r"C:\tools\agent --run"), r"C:\tools\agent");
+    }
+
+    #[test]
+    fn a_registration_matches_by_binary_not_by_name() {
+        let registration = |name: &str, bin_path: &str| Registration {
+            name: name.to_string(),
+            state: "running".to_string(),
+            bin_path: bin_path.to_string(),
+        };
+        let installed = [
+            registration("FooSvc", "\"/opt/foo/foo.exe\" --service"),
+            registration("foo", "/opt/other/foo.exe"),
+        ];
+        let service: Service = serde_yaml::from_str("{ name: foo, path: /opt/foo/foo.exe, type: Executable }").unwrap();
+        let found: Vec<String> = matching(&installed, &service).into_iter().map(|found| found.name).collect();
+        assert_eq!(found, ["FooSvc"]);
+    }
+}
@@ -0,0 +1,69 @@
+use crate::clock;
+use colored::Colorize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Failed runs whose scratch directories are kept for debugging, per service
+const KEEP_FAILED: usize = 5;
+
+// Where every service's scratch directories go
+pub fn root() -> PathBuf {
+    std::env::temp_dir().join("svc")
+}
+
+pub fn service_dir(name: &str) -> PathBuf {
+    root().join(name)
+}
+
+// A fresh %TEMP%\svc\<name>\<timestamp> directory; the PID keeps runs in the same second apart
+pub fn create(name: &str) -> std::io::Result<PathBuf> {
+    let stamp: String = clock::utc(std::time::SystemTime::now())
+        .chars()
+        .filter_map(|c| match c {
+            '-' | ':' => None,
+            ' ' => Some('-'),
+            c => Some(c),
+        })
+        .collect();
+    let dir = service_dir(name).join(format!("{}-{}", stamp, std::process::id()));
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn warn(action: &str, dir: &Path, err: std::io::Error) {
+    eprintln!(
+        "{} could not {} scratch directory {}: {}",
+        "warning:".yellow().bold(),
+        action,
+        dir.display(),
+        err
+    );
+}
+
+// Remove the directory after a successful run; keep it after a failure and prune older failures.
+// Cleanup never fails the run, it only warns
+pub fn finish(name: &str, dir: &Path, success: bool) {
+    if success {
+        if let Err(err) = fs::remove_dir_all(dir) {
+            warn("remove", dir, err);
+        }
+        return;
+    }
+
+    eprintln!("Kept scratch directory {} for inspection.", dir.display());
+    let Ok(entries) = fs::read_dir(service_dir(name)) else {
+        return;
+    };
+    // Successful runs remove theirs, so what is left are failures; names sort by time
+    let mut kept: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .collect();
+    kept.sort();
+    let excess = kept.len().saturating_sub(KEEP_FAILED);
+    for old in kept.into_iter().take(excess) {
+        if let Err(err) = fs::remove_dir_all(&old) {
+            warn("prune", &old, err);
+        }
+    }
+}
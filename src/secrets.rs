@@ -0,0 +1,148 @@
+use crate::state::user_dir;
+use crate::{capture, debugbundle, effects, SvcError};
+use colored::Colorize;
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+// Secrets are stored as DPAPI blobs (ConvertFrom-SecureString), readable only by the current user;
+// they outlast --safe, which run_as services can't start without
+fn secret_path(key: &str) -> Result<PathBuf, SvcError> {
+    // The key is a file name, which must not reach out of the directory or into another drive's
+    if key.is_empty() || key.contains(['\\', '/', ':']) || key.contains("..") {
+        return Err(SvcError::InvalidSecretKey(key.to_string()));
+    }
+    let dir = user_dir()?.join("secrets");
+    fs::create_dir_all(&dir)?;
+    Ok(dir.join(format!("{}.dpapi", key)))
+}
+
+// Keys of every stored secret
+pub fn keys() -> Result<Vec<String>, SvcError> {
+    let entries = match fs::read_dir(user_dir()?.join("secrets")) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    Ok(entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter_map(|file| file.strip_suffix(".dpapi").map(String::from))
+        .collect())
+}
+
+pub fn read_blob(key: &str) -> Result<String, SvcError> {
+    let path = secret_path(key)?;
+    if !path.exists() {
+        return Err(SvcError::SecretNotFound(key.to_string()));
+    }
+    let blob = fs::read_to_string(path)?.trim().to_string();
+    debugbundle::sensitive(&blob);
+    Ok(blob)
+}
+
+// The value is typed with echo off, so it shows neither on the console nor in its scrollback
+fn read_hidden() -> Result<String, SvcError> {
+    let mut value = String::new();
+    {
+        let _echo = EchoOff::new();
+        std::io::stdin().lock().read_line(&mut value)?;
+    }
+    Ok(value.trim_end_matches(['\r', '\n']).to_string())
+}
+
+// Echo turned off on the console's input for as long as it lives; nothing when input is redirected
+#[cfg(windows)]
+struct EchoOff(Option<(windows_sys::Win32::Foundation::HANDLE, u32)>);
+
+#[cfg(windows)]
+impl EchoOff {
+    fn new() -> Self {
+        use windows_sys::Win32::System::Console::{
+            GetConsoleMode, GetStdHandle, SetConsoleMode, ENABLE_ECHO_INPUT, STD_INPUT_HANDLE,
+        };
+        let handle = unsafe { GetStdHandle(STD_INPUT_HANDLE) };
+        let mut mode = 0;
+        if unsafe { GetConsoleMode(handle, &mut mode) } == 0 {
+            return EchoOff(None);
+        }
+        unsafe { SetConsoleMode(handle, mode & !ENABLE_ECHO_INPUT) };
+        EchoOff(Some((handle, mode)))
+    }
+}
+
+#[cfg(windows)]
+impl Drop for EchoOff {
+    fn drop(&mut self) {
+        if let Some((handle, mode)) = self.0 {
+            unsafe { windows_sys::Win32::System::Console::SetConsoleMode(handle, mode) };
+            // Nor was the Enter that ended the line
+            eprintln!();
+        }
+    }
+}
+
+// Off Windows the terminal's own line discipline isn't svc's to change
+#[cfg(not(windows))]
+struct EchoOff;
+
+#[cfg(not(windows))]
+impl EchoOff {
+    fn new() -> Self {
+        EchoOff
+    }
+}
+
+pub fn set_secret(key: &str) -> Result<(), SvcError> {
+    let path = secret_path(key)?;
+    eprint!("Value for secret {}: ", key.cyan());
+    let value = read_hidden()?;
+    let value = value.as_str();
+
+    // The plaintext travels over stdin only, never on a command line
+    let mut child = Command::new("powershell")
+        .args([
+            "-NoProfile",
+            "-Command",
+            "ConvertTo-SecureString ([Console]::In.ReadLine()) -AsPlainText -Force | ConvertFrom-SecureString",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        writeln!(stdin, "{}", value)?;
+    }
+    let output = capture::wait_output(child)?;
+
+    let blob = String::from_utf8(output.stdout)?;
+    if !output.status.success() || blob.trim().is_empty() {
+        return Err(SvcError::IoError(std::io::Error::other("failed to protect secret with DPAPI")));
+    }
+    effects::write(&path, blob.trim())?;
+
+    println!("Secret {} stored.", key.cyan());
+    Ok(())
+}
+
+pub fn remove_secret(key: &str) -> Result<(), SvcError> {
+    let path = secret_path(key)?;
+    if !path.exists() {
+        return Err(SvcError::SecretNotFound(key.to_string()));
+    }
+    effects::delete(&path)?;
+
+    println!("Secret {} removed.", key.cyan());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_key_cannot_reach_outside_the_secret_store() {
+        for key in ["", "..\\..\\x", "../x", "a/b", "a\\b", "C:x", "..", "a..b"] {
+            assert!(matches!(secret_path(key), Err(SvcError::InvalidSecretKey(_))), "{:?}", key);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use crate::output::timestamp;
+use crate::pipe::{self, PIPE_NAME};
+use crate::units::HumanDuration;
+use crate::{cancel, get_status, load_config, metrics, shutdownhook, Service, SvcError};
+use colored::Colorize;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Bumped whenever a message changes shape; clients may send the version they speak
+pub const PROTOCOL_VERSION: u32 = 1;
+// Subscribers hear from the server at least this often, even when nothing changes
+const HEARTBEAT: Duration = Duration::from_secs(10);
+// A client that hasn't sent its request by then is disconnected
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub struct ServeOptions {
+    pub interval: HumanDuration,
+    pub max_subscribers: usize,
+    pub max_connections: usize,
+    pub sample_interval: HumanDuration,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct ServiceState {
+    pids: Vec<u64>,
+    startup: bool,
+}
+
+// One JSON line sent by a client right after connecting
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Request {
+    // Stream status changes of the matching services until the client disconnects
+    Subscribe(Vec<String>),
+    // Reply once with the current status of the matching services
+    Status(Vec<String>),
+}
+
+#[derive(Debug, Deserialize)]
+struct Envelope {
+    #[serde(default)]
+    version: Option<u32>,
+    #[serde(flatten)]
+    request: Request,
+}
+
+struct Subscriber {
+    patterns: Vec<String>,
+    events: mpsc::Sender<Value>,
+    // Gone once the client's thread has exited
+    alive: Weak<()>,
+}
+
+// State shared between the polling loop and the client threads
+#[derive(Default)]
+struct Shared {
+    services: Mutex<BTreeMap<String, ServiceState>>,
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+// `*` matches any run of characters, everything else matches literally
+pub fn matches_pattern(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            let Some(tail) = name.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=tail.len())
+                .filter(|&at| tail.is_char_boundary(at))
+                .any(|at| matches_pattern(rest, &tail[at..]))
+        }
+    }
+}
+
+fn matches_any(patterns: &[String], name: &str) -> bool {
+    patterns.iter().any(|pattern| matches_pattern(pattern, name))
+}
+
+fn message(body: Value) -> Value {
+    let mut message = json!({ "version": PROTOCOL_VERSION });
+    if let (Some(message), Value::Object(body)) = (message.as_object_mut(), body) {
+        message.extend(body);
+    }
+    message
+}
+
+fn change(old: &ServiceState, new: &ServiceState) -> Vec<&'static str> {
+    let mut changes = Vec::new();
+    match (old.pids.is_empty(), new.pids.is_empty()) {
+        (true, false) => changes.push("started"),
+        (false, true) => changes.push("stopped"),
+        (false, false) if old.pids != new.pids => changes.push("pids"),
+        _ => {}
+    }
+    if old.startup != new.startup {
+        changes.push("startup");
+    }
+    changes
+}
+
+impl Shared {
+    fn selected(&self, patterns: &[String]) -> BTreeMap<String, ServiceState> {
+        let services = self.services.lock().unwrap();
+        services
+            .iter()
+            .filter(|(name, _)| matches_any(patterns, name))
+            .map(|(name, state)| (name.clone(), state.clone()))
+            .collect()
+    }
+
+    // Swap in a fresh snapshot and tell subscribers what changed since the last one
+    fn publish(&self, states: BTreeMap<String, ServiceState>) {
+        let events: Vec<(String, Value)> = {
+            let mut services = self.services.lock().unwrap();
+            let events = states
+                .iter()
+                .filter_map(|(name, new)| Some((name, services.get(name)?, new)))
+                .flat_map(|(name, old, new)| {
+                    change(old, new).into_iter().map(move |event| {
+                        let body = json!({
+                            "event": event,
+                            "service": name,
+                            "pids": new.pids,
+                            "startup": new.startup,
+                            "time": timestamp(),
+                        });
+                        (name.clone(), message(body))
+                    })
+                })
+                .collect();
+            *services = states;
+            events
+        };
+
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| {
+            events
+                .iter()
+                .filter(|(name, _)| matches_any(&subscriber.patterns, name))
+                .all(|(_, event)| subscriber.events.send(event.clone()).is_ok())
+        });
+    }
+}
+
+fn send(writer: &mut File, message: &Value) -> Result<(), SvcError> {
+    writeln!(writer, "{}", message)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn serve_client(file: File, shared: &Shared, max_subscribers: usize) -> Result<(), SvcError> {
+    let mut writer = file.try_clone()?;
+    let mut line = String::new();
+    pipe::within(&writer, REQUEST_TIMEOUT, || BufReader::new(&file).read_line(&mut line))?;
+
+    let envelope: Envelope = match serde_json::from_str(&line) {
+        Ok(envelope) => envelope,
+        Err(err) => return send(&mut writer, &message(json!({ "error": format!("invalid request: {}", err) }))),
+    };
+    if let Some(version) = envelope.version.filter(|&version| version > PROTOCOL_VERSION) {
+        let error = format!(
+            "unsupported protocol version {}, this server speaks version {}",
+            version, PROTOCOL_VERSION
+        );
+        return send(&mut writer, &message(json!({ "error": error })));
+    }
+
+    match envelope.request {
+        Request::Status(patterns) => send(&mut writer, &message(json!({ "services": shared.selected(&patterns) }))),
+        Request::Subscribe(patterns) => {
+            let alive = Arc::new(());
+            let (sender, events) = mpsc::channel();
+            {
+                let mut subscribers = shared.subscribers.lock().unwrap();
+                subscribers.retain(|subscriber| subscriber.alive.strong_count() > 0);
+                if subscribers.len() >= max_subscribers {
+                    let error = format!("too many subscribers (limit {}), try again later", max_subscribers);
+                    return send(&mut writer, &message(json!({ "error": error })));
+                }
+                subscribers.push(Subscriber {
+                    patterns: patterns.clone(),
+                    events: sender,
+                    alive: Arc::downgrade(&alive),
+                });
+            }
+
+            // Start with the current state so the client knows what the changes apply to
+            let services = shared.selected(&patterns);
+            send(&mut writer, &message(json!({ "subscribed": patterns, "services": services })))?;
+            loop {
+                let event = match events.recv_timeout(HEARTBEAT) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => message(json!({ "heartbeat": timestamp() })),
+                    Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                };
+                // Fails once the client has gone, which ends the subscription
+                send(&mut writer, &event)?;
+            }
+        }
+    }
+}
+
+// Poll every service on one shared loop and answer pipe clients from its snapshot
+// Metrics of the running services out of one process snapshot, as watch takes them; serve
+// relaunches nothing, so no restarts are counted
+fn sample(services: &[Service], states: &BTreeMap<String, ServiceState>) {
+    let all: Vec<u64> = states.values().flat_map(|state| state.pids.iter().copied()).collect();
+    let snapshot = metrics::snapshot(&all);
+    for service in services {
+        if let Some(state) = states.get(service.name.as_ref()).filter(|state| !state.pids.is_empty()) {
+            metrics::keep(service, &snapshot.sample(&state.pids, 0));
+        }
+    }
+}
+
+pub fn serve(config_path: &str, options: &ServeOptions) -> Result<(), SvcError> {
+    let shared = Arc::new(Shared::default());
+    let mut services = load_config(config_path)?;
+    shutdownhook::start(config_path)?;
+
+    let listener = {
+        let shared = Arc::clone(&shared);
+        let max_subscribers = options.max_subscribers;
+        let max_connections = options.max_connections;
+        thread::spawn(move || {
+            pipe::listen(max_connections, move |file| {
+                let _ = serve_client(file, &shared, max_subscribers);
+            })
+        })
+    };
+    println!("Serving status on {}, Ctrl+C to stop.", PIPE_NAME.cyan());
+
+    let mut last_sample: Option<Instant> = None;
+    loop {
+        if listener.is_finished() {
+            return listener.join().unwrap_or(Ok(()));
+        }
+
+        match load_config(config_path) {
+            Ok(config) => services = config,
+            Err(err) => eprintln!("[{}] Config reload failed, keeping the previous config: {}", timestamp(), err),
+        }
+        let states = services
+            .par_iter()
+            .filter_map(|service| {
+                let status = get_status(service).ok()?;
+                let state = ServiceState {
+                    pids: status.pids,
+                    startup: !status.startup.is_empty(),
+                };
+                Some((service.name.to_string(), state))
+            })
+            .collect();
+        if last_sample.is_none_or(|at| at.elapsed() >= options.sample_interval.get()) {
+            last_sample = Some(Instant::now());
+            sample(&services, &states);
+        }
+        shared.publish(states);
+
+        cancel::sleep(options.interval.get())?;
+    }
+}
@@ -0,0 +1,99 @@
+use crate::backends::split_args;
+use crate::{expand_env, hosts, Config, Service, SvcError};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+// An entry of the `shells` table: the command line a Util's script is appended to, either
+// the same everywhere or with variants per computer name
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ShellSetting {
+    Command(String),
+    PerHost {
+        command: String,
+        #[serde(default)]
+        hosts: BTreeMap<String, String>,
+    },
+}
+
+impl ShellSetting {
+    // The command line for this machine, and the hosts key it came from if any
+    fn command_line(&self) -> (&str, Option<&str>) {
+        match self {
+            ShellSetting::Command(command) => (command, None),
+            ShellSetting::PerHost { command, hosts } => {
+                let host = hosts::current();
+                let own = hosts
+                    .iter()
+                    .find(|(key, _)| host.as_deref().is_some_and(|host| hosts::matches(key, host)));
+                match own {
+                    Some((key, command)) => (command, Some(key)),
+                    None => (command, None),
+                }
+            }
+        }
+    }
+}
+
+// Program and flags of a shell's command line. Paths like C:\Program Files\...\pwsh.exe are
+// usually written unquoted, so an unquoted line is cut after its first word ending in .exe
+fn split(line: &str) -> (String, Vec<String>) {
+    let line = expand_env(line.trim());
+    if !line.starts_with('"') {
+        let lower = line.to_ascii_lowercase();
+        let end = lower
+            .match_indices(".exe")
+            .map(|(at, _)| at + ".exe".len())
+            .find(|&end| line[end..].chars().next().is_none_or(char::is_whitespace));
+        if let Some(end) = end {
+            return (line[..end].to_string(), split_args(&line[end..]));
+        }
+    }
+    let mut words = split_args(&line).into_iter();
+    (words.next().unwrap_or_default(), words.collect())
+}
+
+// Replace the interpreter of every Util that names a shell with the shell's program and flags
+pub fn resolve(config: &mut Config) -> Result<(), SvcError> {
+    for service in &mut config.services {
+        resolve_service(&config.settings.shells, service)?;
+    }
+    Ok(())
+}
+
+pub fn resolve_service(shells: &BTreeMap<String, ShellSetting>, service: &mut Service) -> Result<(), SvcError> {
+    let Some(name) = &service.shell else {
+        return Ok(());
+    };
+    let shell = shells.get(name.as_ref()).ok_or_else(|| SvcError::UnknownShell {
+        service: service.name.to_string(),
+        shell: name.to_string(),
+        defined: shells.keys().cloned().collect(),
+    })?;
+    let (program, flags) = split(shell.command_line().0);
+    service.interpreter = Cow::Owned(program);
+    service.interpreter_args = flags;
+    Ok(())
+}
+
+// The hosts key whose variant of the shell applies on this machine, for `svc which`
+pub fn host_variant<'a>(config: &'a Config, shell: &str) -> Option<&'a str> {
+    config.settings.shells.get(shell)?.command_line().1
+}
+
+// Where Windows would find a program: a path as given, or a bare name on PATH with PATHEXT
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.components().count() > 1 || path.is_absolute() {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    let extensions = std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+    let names: Vec<String> = std::iter::once(program.to_string())
+        .chain(extensions.split(';').filter(|ext| !ext.is_empty()).map(|ext| format!("{}{}", program, ext)))
+        .collect();
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|candidate| candidate.is_file())
+}
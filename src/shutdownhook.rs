@@ -0,0 +1,213 @@
+use crate::state::{self, state_dir};
+use crate::process::is_svc_alive;
+use crate::{effects, SvcError};
+#[cfg(windows)]
+use crate::{load_config, stopall, SessionFilter};
+use colored::Colorize;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(windows)]
+use std::time::Duration;
+
+// Presence means watch and serve hook the session end; removing it unhooks them at once
+const MARKER_FILE: &str = "shutdown-hook";
+// The process currently holding the hidden window
+const PID_FILE: &str = "shutdown-hook.pid";
+// What the last session end stopped and how, as `svc --json down` prints it
+#[cfg(windows)]
+const REPORT_FILE: &str = "shutdown-report.json";
+// Windows shows "apps are preventing shutdown" after a few seconds and lets the user force it;
+// whatever still runs after this is terminated without waiting on its stop_signal
+#[cfg(windows)]
+const BUDGET: Duration = Duration::from_secs(15);
+
+// Set from WM_QUERYENDSESSION until the session ends or the end is cancelled; the watch loop
+// leaves services down meanwhile, rather than relaunching what the hook is stopping
+static ENDING: AtomicBool = AtomicBool::new(false);
+
+pub fn session_ending() -> bool {
+    ENDING.load(Ordering::Relaxed)
+}
+
+fn installed() -> Result<bool, SvcError> {
+    Ok(state_dir()?.join(MARKER_FILE).exists())
+}
+
+// PID of the watch or serve process the hook lives in, if one is running
+pub fn active() -> Result<Option<u64>, SvcError> {
+    match state::read_pid_file(PID_FILE)? {
+        Some(pid) if is_svc_alive(pid)? => Ok(Some(pid)),
+        _ => Ok(None),
+    }
+}
+
+pub fn install() -> Result<(), SvcError> {
+    effects::write(&state_dir()?.join(MARKER_FILE), "")?;
+    println!("Shutdown hook installed.");
+    if active()?.is_none() {
+        println!("It takes effect in the next `svc watch` or `svc serve`.");
+    }
+    Ok(())
+}
+
+pub fn uninstall() -> Result<(), SvcError> {
+    let marker = state_dir()?.join(MARKER_FILE);
+    if marker.exists() {
+        effects::delete(&marker)?;
+    }
+    println!("Shutdown hook uninstalled.");
+    Ok(())
+}
+
+// None when not installed, for the status line of `svc status`
+pub fn describe() -> Result<Option<String>, SvcError> {
+    if !installed()? {
+        return Ok(None);
+    }
+    Ok(Some(match active()? {
+        Some(pid) => format!("{} (PID {})", "active".green(), pid),
+        None => format!("{}, no watch or serve process is running", "installed".yellow()),
+    }))
+}
+
+pub fn print_status() -> Result<(), SvcError> {
+    let line = describe()?.unwrap_or_else(|| "not installed".yellow().to_string());
+    println!("Shutdown hook: {}", line);
+    Ok(())
+}
+
+// The stop sequence for this session's instances within the budget, its report left in the
+// state directory for after the next logon
+#[cfg(windows)]
+fn stop_all(config_path: &str) {
+    let Ok(services) = load_config(config_path) else {
+        return;
+    };
+    let options = stopall::Options {
+        deadline: BUDGET,
+        halt_on_failure: false,
+        force_at_deadline: true,
+        // The session ends, so protected services go too
+        skip_protected: false,
+        checkpoint: None,
+    };
+    // Nobody is there to confirm a loose match while the session ends
+    let mut live = stopall::Live {
+        session: SessionFilter::Current,
+        force: false,
+        uptime: Default::default(),
+    };
+    let outcomes = stopall::stop_all(&services, &options, &mut live);
+    stopall::record(&outcomes);
+    let report = serde_json::json!({ "services": outcomes });
+    if let (Ok(dir), Ok(report)) = (state_dir(), serde_json::to_string_pretty(&report)) {
+        let _ = std::fs::write(dir.join(REPORT_FILE), report);
+    }
+}
+
+// Hook the session end from a resident process; a no-op unless installed, or when another
+// process already holds the hook
+pub fn start(config_path: &str) -> Result<(), SvcError> {
+    if !installed()? || active()?.is_some() {
+        return Ok(());
+    }
+    spawn_window(config_path)
+}
+
+#[cfg(windows)]
+fn spawn_window(config_path: &str) -> Result<(), SvcError> {
+    state::write_pid_file(PID_FILE, u64::from(std::process::id()))?;
+    let _ = window::CONFIG_PATH.set(config_path.to_string());
+    std::thread::spawn(window::run);
+    Ok(())
+}
+
+// There is no session end to hook elsewhere
+#[cfg(not(windows))]
+fn spawn_window(_config_path: &str) -> Result<(), SvcError> {
+    Ok(())
+}
+
+#[cfg(windows)]
+mod window {
+    use std::sync::OnceLock;
+    use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows_sys::Win32::System::Shutdown::{ShutdownBlockReasonCreate, ShutdownBlockReasonDestroy};
+    use windows_sys::Win32::System::Threading::SetProcessShutdownParameters;
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, RegisterClassW, TranslateMessage, MSG,
+        WM_ENDSESSION, WM_QUERYENDSESSION, WNDCLASSW,
+    };
+
+    pub static CONFIG_PATH: OnceLock<String> = OnceLock::new();
+
+    fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(Some(0)).collect()
+    }
+
+    unsafe extern "system" fn window_proc(hwnd: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        match message {
+            // Agree to end the session, but keep it waiting until WM_ENDSESSION is handled
+            WM_QUERYENDSESSION => {
+                super::ENDING.store(true, super::Ordering::Relaxed);
+                if super::installed().unwrap_or(false) {
+                    let reason = wide("Stopping services started by svc");
+                    unsafe { ShutdownBlockReasonCreate(hwnd, reason.as_ptr()) };
+                }
+                1
+            }
+            WM_ENDSESSION => {
+                // wparam is 0 when the shutdown was cancelled after all
+                if wparam == 0 {
+                    super::ENDING.store(false, super::Ordering::Relaxed);
+                }
+                if wparam != 0 && super::installed().unwrap_or(false) {
+                    if let Some(config_path) = CONFIG_PATH.get() {
+                        super::stop_all(config_path);
+                    }
+                }
+                unsafe { ShutdownBlockReasonDestroy(hwnd) };
+                0
+            }
+            _ => unsafe { DefWindowProcW(hwnd, message, wparam, lparam) },
+        }
+    }
+
+    // A hidden top-level window; message-only windows never see the session messages
+    pub fn run() {
+        let class = wide("svc-shutdown-hook");
+        unsafe {
+            // Ask to be told before ordinary applications, so services stop while the desktop is intact
+            SetProcessShutdownParameters(0x3ff, 0);
+            let instance = GetModuleHandleW(std::ptr::null());
+            let mut window_class: WNDCLASSW = std::mem::zeroed();
+            window_class.lpfnWndProc = Some(window_proc);
+            window_class.hInstance = instance;
+            window_class.lpszClassName = class.as_ptr();
+            RegisterClassW(&window_class);
+
+            let hwnd = CreateWindowExW(
+                0,
+                class.as_ptr(),
+                class.as_ptr(),
+                0,
+                0,
+                0,
+                0,
+                0,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                instance,
+                std::ptr::null(),
+            );
+            if hwnd.is_null() {
+                return;
+            }
+            let mut message: MSG = std::mem::zeroed();
+            while GetMessageW(&mut message, std::ptr::null_mut(), 0, 0) > 0 {
+                TranslateMessage(&message);
+                DispatchMessageW(&message);
+            }
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use crate::state::{self, state_dir};
+use crate::{is_pid_alive, mutex, pathcmp, procinfo, SvcError};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::Child;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SPAWNED_FILE: &str = "spawned.json";
+// Between svc noting the launch and Windows' own creation time of the process
+const START_SLACK_SECS: u64 = 5;
+
+// A process svc started for a service. Its image may not be the configured path (a script's
+// interpreter, a binary that renames or re-executes itself), so the PID is what finds it again
+#[derive(Debug, Serialize, Deserialize)]
+struct Launch {
+    pid: u64,
+    // Seconds since the Unix epoch
+    started: u64,
+    // As Windows reported it right after the launch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    image: Option<String>,
+}
+
+type Launches = BTreeMap<String, Vec<Launch>>;
+
+// A process this invocation launched, with the handle it still has
+struct Held {
+    service: String,
+    child: Child,
+}
+
+// Asking the handle beats a snapshot, which a process slow to start may not show up in yet
+static HELD: Mutex<Vec<Held>> = Mutex::new(Vec::new());
+
+fn path() -> Result<PathBuf, SvcError> {
+    Ok(state_dir()?.join(SPAWNED_FILE))
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+// A damaged file only loses the hints; services are still found by path
+fn load() -> Result<Launches, SvcError> {
+    match state::read_json(&path()?) {
+        Ok(launches) => Ok(launches.unwrap_or_default()),
+        Err(SvcError::CorruptState { .. }) => Ok(Launches::new()),
+        Err(err) => Err(err),
+    }
+}
+
+fn save(launches: &Launches) -> Result<(), SvcError> {
+    let json = serde_json::to_string_pretty(launches).map_err(std::io::Error::other)?;
+    state::replace(&path()?, &json)
+}
+
+// Whether the PID still belongs to the process svc launched rather than a later one given
+// the same number; a process that can't be opened is judged by its PID alone
+fn is_same(launch: &Launch) -> Result<bool, SvcError> {
+    let Some(created) = procinfo::started_at(launch.pid) else {
+        return is_pid_alive(launch.pid);
+    };
+    if created.abs_diff(launch.started) > START_SLACK_SECS {
+        return Ok(false);
+    }
+    Ok(match (&launch.image, procinfo::image_path(launch.pid)) {
+        (Some(recorded), Some(image)) => pathcmp::same_file(recorded, &image),
+        _ => true,
+    })
+}
+
+// Drops the launches whose processes are gone; returns whether any were
+fn prune(launches: &mut Vec<Launch>) -> Result<bool, SvcError> {
+    let before = launches.len();
+    let mut kept = Vec::new();
+    for launch in launches.drain(..) {
+        if is_same(&launch)? {
+            kept.push(launch);
+        }
+    }
+    *launches = kept;
+    Ok(launches.len() != before)
+}
+
+pub fn record(service: &str, pid: u64) -> Result<(), SvcError> {
+    let launch = Launch {
+        pid,
+        started: now(),
+        image: procinfo::image_path(pid),
+    };
+    let _lock = mutex::lock("spawned", "file")?;
+    let mut launches = load()?;
+    // Every service's, so a removed service's launches don't linger
+    for entry in launches.values_mut() {
+        prune(entry)?;
+    }
+    launches.retain(|_, entry| !entry.is_empty());
+    launches.entry(service.to_string()).or_default().push(launch);
+    save(&launches)
+}
+
+// Drops every launch whose process is gone; how many there were
+pub fn prune_all() -> Result<usize, SvcError> {
+    if !path()?.exists() {
+        return Ok(0);
+    }
+    let _lock = mutex::lock("spawned", "file")?;
+    let mut launches = load()?;
+    let count = |launches: &Launches| launches.values().map(Vec::len).sum::<usize>();
+    let before = count(&launches);
+    for entry in launches.values_mut() {
+        prune(entry)?;
+    }
+    launches.retain(|_, entry| !entry.is_empty());
+    let dropped = before - count(&launches);
+    if dropped > 0 {
+        save(&launches)?;
+    }
+    Ok(dropped)
+}
+
+// Once svc has seen the process exit itself
+pub fn forget(service: &str, pid: u64) -> Result<(), SvcError> {
+    let _lock = mutex::lock("spawned", "file")?;
+    let mut launches = load()?;
+    let Some(entry) = launches.get_mut(service) else {
+        return Ok(());
+    };
+    entry.retain(|launch| launch.pid != pid);
+    if entry.is_empty() {
+        launches.remove(service);
+    }
+    save(&launches)
+}
+
+// PIDs svc launched for the service that are still running, forgetting the rest
+pub fn live_pids(service: &str) -> Result<Vec<u64>, SvcError> {
+    if !path()?.exists() {
+        return Ok(Vec::new());
+    }
+    let _lock = mutex::lock("spawned", "file")?;
+    let mut launches = load()?;
+    let Some(entry) = launches.get_mut(service) else {
+        return Ok(Vec::new());
+    };
+    let changed = prune(entry)?;
+    let pids = entry.iter().map(|launch| launch.pid).collect();
+    if changed {
+        if entry.is_empty() {
+            launches.remove(service);
+        }
+        save(&launches)?;
+    }
+    Ok(pids)
+}
+
+// Keeps the handle for the rest of the invocation, so verifying the start doesn't depend on
+// finding the process again
+pub fn hold(service: &str, child: Child) {
+    if let Ok(mut held) = HELD.lock() {
+        held.push(Held {
+            service: service.to_string(),
+            child,
+        });
+    }
+}
+
+fn running(child: &mut Child) -> bool {
+    matches!(child.try_wait(), Ok(None))
+}
+
+// Whether a process this invocation launched still runs; None for any other PID
+pub fn held_alive(pid: u64) -> Option<bool> {
+    let mut held = HELD.lock().ok()?;
+    let entry = held.iter_mut().find(|entry| u64::from(entry.child.id()) == pid)?;
+    Some(running(&mut entry.child))
+}
+
+// The service's processes this invocation launched that still run
+pub fn held_pids(service: &str) -> Vec<u64> {
+    let Ok(mut held) = HELD.lock() else {
+        return Vec::new();
+    };
+    held.iter_mut()
+        .filter(|entry| entry.service == service)
+        .filter_map(|entry| running(&mut entry.child).then(|| u64::from(entry.child.id())))
+        .collect()
+}
+
+// Waits up to the timeout for a process this invocation launched to exit and says whether
+// it did; None for any other PID, which the caller has to poll
+#[cfg(windows)]
+pub fn held_exit_within(pid: u64, timeout: Duration) -> Option<bool> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Foundation::WAIT_OBJECT_0;
+    use windows_sys::Win32::System::Threading::WaitForSingleObject;
+
+    // Children are never dropped, so the handle outlives the lock
+    let handle = HELD
+        .lock()
+        .ok()?
+        .iter()
+        .find(|entry| u64::from(entry.child.id()) == pid)?
+        .child
+        .as_raw_handle();
+    let millis = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
+    Some(unsafe { WaitForSingleObject(handle, millis) } == WAIT_OBJECT_0)
+}
+
+#[cfg(not(windows))]
+pub fn held_exit_within(pid: u64, timeout: Duration) -> Option<bool> {
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        if !held_alive(pid)? {
+            return Some(true);
+        }
+        let left = deadline.saturating_duration_since(std::time::Instant::now());
+        if left.is_zero() {
+            return Some(false);
+        }
+        std::thread::sleep(left.min(Duration::from_millis(10)));
+    }
+}
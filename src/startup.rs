@@ -0,0 +1,607 @@
+use crate::backends::{self, Backend};
+use crate::effects::{self, Effect};
+use crate::probe::{self, Capability};
+use crate::{elevation, locate, mutex, pathcmp, retry, startupenv, Service, Settings, SvcError};
+
+pub const RUN_KEY: &str = r#"HKCU\SOFTWARE\Microsoft\Windows\CurrentVersion\Run"#;
+// The same for every user of the machine; writing it takes administrator rights
+pub const MACHINE_RUN_KEY: &str = r#"HKLM\SOFTWARE\Microsoft\Windows\CurrentVersion\Run"#;
+
+// svc's Run values carry this prefix so a service named like another program's
+// entry (say OneDrive) can't overwrite or delete it
+const PREFIX: &str = "svc:";
+
+pub fn value_name(service: &str) -> String {
+    format!("{}{}", PREFIX, service)
+}
+
+// The service a namespaced value belongs to; None for a bare name
+pub fn service_of(value: &str) -> Option<&str> {
+    value.strip_prefix(PREFIX)
+}
+
+// A Run value that svc wrote for a service
+pub struct Entry {
+    pub value: String,
+    pub data: String,
+}
+
+impl Entry {
+    // Written under the bare service name, before values were namespaced
+    pub fn is_legacy(&self) -> bool {
+        !self.value.starts_with(PREFIX)
+    }
+}
+
+pub enum Lookup {
+    Found(Entry),
+    // A bare-name value with data svc wouldn't have written
+    Foreign(String),
+    Missing,
+}
+
+impl Lookup {
+    // The entry disable may delete; another program's value under the name is refused
+    pub fn removable(self, service: &Service) -> Result<Entry, SvcError> {
+        match self {
+            Lookup::Found(entry) => Ok(entry),
+            Lookup::Foreign(data) => Err(SvcError::ForeignStartupEntry {
+                name: service.name.to_string(),
+                data,
+            }),
+            Lookup::Missing => Err(SvcError::ServiceIsDisabled),
+        }
+    }
+}
+
+// What the Run value starts without a stagger or work_at: the quoted path and args, since
+// Windows splits an unquoted C:\Program Files\... at the first space
+pub fn command_line(service: &Service) -> String {
+    let mut line = format!("\"{}\"", service.path);
+    for arg in &service.args {
+        line.push(' ');
+        line.push_str(&elevation::quote(arg));
+    }
+    line
+}
+
+// Data svc writes is the service's command line, the bare path older versions wrote, or a
+// `svc run --startup` line for it
+pub fn written_by_svc(service: &Service, data: &str) -> bool {
+    pathcmp::same_file(data, &service.path)
+        || data.eq_ignore_ascii_case(&command_line(service))
+        || data.ends_with(&format!(" run --startup \"{}\"", service.name))
+}
+
+pub fn lookup(service: &Service) -> Result<Lookup, SvcError> {
+    lookup_in(service, query_value)
+}
+
+// The namespaced value first, then a bare-name one if svc wrote it; `query` reads a value of
+// the per-user Run key
+fn lookup_in(service: &Service, query: impl Fn(&str) -> Result<Option<String>, SvcError>) -> Result<Lookup, SvcError> {
+    let value = value_name(&service.name);
+    if let Some(data) = query(&value)? {
+        return Ok(Lookup::Found(Entry { value, data }));
+    }
+    Ok(match query(&service.name)? {
+        Some(data) if written_by_svc(service, &data) => Lookup::Found(Entry {
+            value: service.name.to_string(),
+            data,
+        }),
+        Some(data) => Lookup::Foreign(data),
+        None => Lookup::Missing,
+    })
+}
+
+pub fn find(service: &Service) -> Result<Option<Entry>, SvcError> {
+    Ok(match lookup(service)? {
+        Lookup::Found(entry) => Some(entry),
+        _ => None,
+    })
+}
+
+// Services that have a namespaced Run value, whether or not they are still configured
+pub fn svc_values() -> Result<Vec<String>, SvcError> {
+    Ok(value_names()?
+        .into_iter()
+        .filter_map(|name| service_of(&name).map(String::from))
+        .collect())
+}
+
+// Whether logon starts the service through `svc run --startup` rather than directly
+pub fn mediated(service: &Service, settings: &Settings) -> bool {
+    settings.startup_stagger.is_some()
+        || service.logon_delay.is_some()
+        || !service.work_at.is_empty()
+        || !service.startup_env_allowlist.is_empty()
+}
+
+// Run value data for a service; with a stagger configured, logon goes through
+// `svc run --startup` so svc can pace it, with work_at too since a Run value
+// can't set a working directory, and with startup_env_allowlist so it can set the variables
+pub fn run_data(service: &Service, settings: &Settings) -> Result<String, SvcError> {
+    Ok(if mediated(service, settings) {
+        format!(
+            "\"{}\" {}run --startup \"{}\"",
+            std::env::current_exe()?.display(),
+            locate::pinned().map(|path| format!("--config \"{}\" ", path.display())).unwrap_or_default(),
+            service.name
+        )
+    } else {
+        command_line(service)
+    })
+}
+
+pub struct Enabled {
+    pub backend: Backend,
+    // Backends an earlier entry for the service was removed from
+    pub removed: Vec<Backend>,
+}
+
+// Where enable registers the service: the given backend, or the one the service or the settings name
+pub fn target(service: &Service, settings: &Settings, backend: Option<Backend>) -> Backend {
+    backend.or(service.startup_backend).unwrap_or(settings.startup_backend)
+}
+
+// The backends svc has an entry for the service on
+fn registered(service: &Service) -> Result<Vec<Backend>, SvcError> {
+    Ok(backends::query(service)?.iter().map(|entry| entry.backend).filter(|backend| backend.writable()).collect())
+}
+
+// The backends enable (with `enabling`) or disable would write to
+pub fn touched(
+    service: &Service,
+    settings: &Settings,
+    backend: Option<Backend>,
+    enabling: bool,
+) -> Result<Vec<Backend>, SvcError> {
+    let mut backends = match backend {
+        Some(backend) if !enabling => return Ok(vec![backend]),
+        _ => registered(service)?,
+    };
+    if enabling {
+        let target = target(service, settings, backend);
+        backends.retain(|&stale| stale != target);
+        backends.insert(0, target);
+    }
+    Ok(backends)
+}
+
+// The first of `backends` that only an administrator can write: the machine Run key when it
+// doesn't open for writing, and the logon task of an elevated service, which /RL HIGHEST makes
+pub fn needs_admin(service: &Service, backends: &[Backend]) -> Option<Backend> {
+    backends.iter().copied().find(|&backend| match backend {
+        Backend::MachineRunKey => matches!(
+            probe_access(MACHINE_RUN_KEY, true),
+            Err(SvcError::IoError(err)) if err.kind() == std::io::ErrorKind::PermissionDenied
+        ),
+        Backend::ScheduledTask => service.elevated,
+        _ => false,
+    })
+}
+
+// On the given backend, or the one the service or the settings name. An entry svc wrote on
+// another backend is removed afterwards, so switching backends leaves nothing stale
+pub fn enable(service: &Service, settings: &Settings, backend: Option<Backend>) -> Result<Enabled, SvcError> {
+    let _lock = mutex::lock("startup", &service.name)?;
+    let backend = target(service, settings, backend);
+    if service.elevated && backend != Backend::ScheduledTask {
+        return Err(SvcError::InvalidCommand(format!(
+            "enable {} on {}; only scheduled-task can start it elevated",
+            service.name, backend
+        )));
+    }
+    let removed: Vec<Backend> = registered(service)?.into_iter().filter(|&stale| stale != backend).collect();
+    if backend == Backend::RunKey || removed.contains(&Backend::RunKey) {
+        probe::require(Capability::StartupWrite)?;
+    }
+    backends::enable(service, backend, &run_data(service, settings)?)?;
+    for &stale in &removed {
+        backends::disable(service, stale)?;
+    }
+    startupenv::capture(service)?;
+    Ok(Enabled { backend, removed })
+}
+
+// From the given backend, or from every backend svc registered the service on; returns
+// the backends it was removed from
+pub fn disable(service: &Service, backend: Option<Backend>) -> Result<Vec<Backend>, SvcError> {
+    let _lock = mutex::lock("startup", &service.name)?;
+    let backends: Vec<Backend> = match backend {
+        Some(backend) => vec![backend],
+        None => registered(service)?,
+    };
+    if backends.is_empty() {
+        return Err(SvcError::ServiceIsDisabled);
+    }
+    if backends.contains(&Backend::RunKey) {
+        probe::require(Capability::StartupWrite)?;
+    }
+    for &backend in &backends {
+        backends::disable(service, backend)?;
+    }
+    if backend.is_none() || backends::query(service)?.iter().all(|entry| !entry.backend.writable()) {
+        startupenv::forget(&service.name)?;
+    }
+    Ok(backends)
+}
+
+// Windows goes through the wide registry API, so names and data outside the
+// console code page (CJK, Cyrillic, ...) survive the round trip
+#[cfg(windows)]
+use native as platform;
+#[cfg(not(windows))]
+use reg as platform;
+pub use platform::probe_access;
+
+// Read back after writing, so a value that didn't stick fails now rather than at the next logon
+pub fn set_value_in(path: &str, name: &str, data: &str) -> Result<(), SvcError> {
+    let effect = Effect::SetRegistryValue {
+        key: path.to_string(),
+        name: name.to_string(),
+        data: data.to_string(),
+    };
+    effects::perform(effect, || {
+        platform::set_value_in(path, name, data)?;
+        match query_value_in(path, name)? {
+            Some(found) if found == data => Ok(()),
+            found => Err(SvcError::StartupWriteMismatch {
+                name: name.to_string(),
+                expected: data.to_string(),
+                found: found.unwrap_or_else(|| "nothing".to_string()),
+            }),
+        }
+    })
+    .map(drop)
+}
+
+pub fn delete_value_in(path: &str, name: &str) -> Result<(), SvcError> {
+    let effect = Effect::DeleteRegistryValue {
+        key: path.to_string(),
+        name: name.to_string(),
+    };
+    effects::perform(effect, || platform::delete_value_in(path, name)).map(drop)
+}
+
+pub fn query_value_in(path: &str, name: &str) -> Result<Option<String>, SvcError> {
+    retry::transient("reading the registry", || platform::query_value_in(path, name))
+}
+
+pub fn value_names_in(path: &str) -> Result<Vec<String>, SvcError> {
+    retry::transient("reading the registry", || platform::value_names_in(path))
+}
+
+// The per-user Run key, where svc's start-up entries live unless another backend is asked for
+pub fn query_value(name: &str) -> Result<Option<String>, SvcError> {
+    query_value_in(RUN_KEY, name)
+}
+
+pub fn set_value(name: &str, data: &str) -> Result<(), SvcError> {
+    set_value_in(RUN_KEY, name, data)
+}
+
+pub fn delete_value(name: &str) -> Result<(), SvcError> {
+    delete_value_in(RUN_KEY, name)
+}
+
+pub fn value_names() -> Result<Vec<String>, SvcError> {
+    value_names_in(RUN_KEY)
+}
+
+#[cfg(windows)]
+mod native {
+    use crate::SvcError;
+    use windows_sys::Win32::Foundation::{
+        ERROR_FILE_NOT_FOUND, ERROR_MORE_DATA, ERROR_NO_MORE_ITEMS, ERROR_SUCCESS, WIN32_ERROR,
+    };
+    use windows_sys::Win32::System::Registry::{
+        RegCloseKey, RegDeleteValueW, RegEnumValueW, RegOpenKeyExW, RegQueryValueExW, RegSetValueExW, HKEY,
+        HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE,
+        KEY_QUERY_VALUE, KEY_SET_VALUE, REG_EXPAND_SZ, REG_SAM_FLAGS, REG_SZ,
+    };
+
+    fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(Some(0)).collect()
+    }
+
+    fn error(status: WIN32_ERROR) -> SvcError {
+        std::io::Error::from_raw_os_error(status as i32).into()
+    }
+
+    struct Key(HKEY);
+
+    impl Drop for Key {
+        fn drop(&mut self) {
+            unsafe { RegCloseKey(self.0) };
+        }
+    }
+
+    // A key written as reg writes it, e.g. HKCU\SOFTWARE\...
+    fn open(path: &str, access: REG_SAM_FLAGS) -> Result<Key, WIN32_ERROR> {
+        let (hive, subkey) = match path.split_once('\\') {
+            Some(("HKLM", subkey)) => (HKEY_LOCAL_MACHINE, subkey),
+            Some((_, subkey)) => (HKEY_CURRENT_USER, subkey),
+            None => (HKEY_CURRENT_USER, path),
+        };
+        let subkey = wide(subkey);
+        let mut key = std::ptr::null_mut();
+        match unsafe { RegOpenKeyExW(hive, subkey.as_ptr(), 0, access, &mut key) } {
+            ERROR_SUCCESS => Ok(Key(key)),
+            status => Err(status),
+        }
+    }
+
+    // Whether the key opens for reading or writing, without touching a value; a missing key
+    // reads as empty
+    pub fn probe_access(path: &str, write: bool) -> Result<(), SvcError> {
+        match open(path, if write { KEY_SET_VALUE } else { KEY_QUERY_VALUE }) {
+            Ok(_) => Ok(()),
+            Err(ERROR_FILE_NOT_FOUND) if !write => Ok(()),
+            Err(status) => Err(error(status)),
+        }
+    }
+
+    pub fn query_value_in(path: &str, name: &str) -> Result<Option<String>, SvcError> {
+        let key = match open(path, KEY_QUERY_VALUE) {
+            Ok(key) => key,
+            Err(ERROR_FILE_NOT_FOUND) => return Ok(None),
+            Err(status) => return Err(error(status)),
+        };
+        let name = wide(name);
+        let mut buffer: Vec<u16> = vec![0; 260];
+        loop {
+            let mut kind = 0;
+            let mut size = (buffer.len() * 2) as u32;
+            let status = unsafe {
+                RegQueryValueExW(key.0, name.as_ptr(), std::ptr::null(), &mut kind, buffer.as_mut_ptr().cast(), &mut size)
+            };
+            match status {
+                ERROR_SUCCESS if kind == REG_SZ || kind == REG_EXPAND_SZ => {
+                    // Stored data may or may not carry its terminating nul
+                    let data = &buffer[..size as usize / 2];
+                    let end = data.iter().position(|&unit| unit == 0).unwrap_or(data.len());
+                    return Ok(Some(String::from_utf16_lossy(&data[..end])));
+                }
+                // Not a string, so nothing svc could have written
+                ERROR_SUCCESS => return Ok(None),
+                ERROR_MORE_DATA => buffer.resize((size as usize).div_ceil(2) + 1, 0),
+                ERROR_FILE_NOT_FOUND => return Ok(None),
+                status => return Err(error(status)),
+            }
+        }
+    }
+
+    pub fn set_value_in(path: &str, name: &str, data: &str) -> Result<(), SvcError> {
+        let key = open(path, KEY_SET_VALUE).map_err(error)?;
+        let data = wide(data);
+        let status = unsafe {
+            RegSetValueExW(key.0, wide(name).as_ptr(), 0, REG_SZ, data.as_ptr().cast(), (data.len() * 2) as u32)
+        };
+        match status {
+            ERROR_SUCCESS => Ok(()),
+            status => Err(error(status)),
+        }
+    }
+
+    pub fn delete_value_in(path: &str, name: &str) -> Result<(), SvcError> {
+        let key = open(path, KEY_SET_VALUE).map_err(error)?;
+        match unsafe { RegDeleteValueW(key.0, wide(name).as_ptr()) } {
+            // Already gone is what was asked for
+            ERROR_SUCCESS | ERROR_FILE_NOT_FOUND => Ok(()),
+            status => Err(error(status)),
+        }
+    }
+
+    pub fn value_names_in(path: &str) -> Result<Vec<String>, SvcError> {
+        let key = match open(path, KEY_QUERY_VALUE) {
+            Ok(key) => key,
+            Err(ERROR_FILE_NOT_FOUND) => return Ok(Vec::new()),
+            Err(status) => return Err(error(status)),
+        };
+        let mut names = Vec::new();
+        // Value names are at most 16383 characters
+        let mut buffer: Vec<u16> = vec![0; 16384];
+        for index in 0.. {
+            let mut length = buffer.len() as u32;
+            let status = unsafe {
+                RegEnumValueW(
+                    key.0,
+                    index,
+                    buffer.as_mut_ptr(),
+                    &mut length,
+                    std::ptr::null(),
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                )
+            };
+            match status {
+                ERROR_SUCCESS => names.push(String::from_utf16_lossy(&buffer[..length as usize])),
+                ERROR_NO_MORE_ITEMS => break,
+                status => return Err(error(status)),
+            }
+        }
+        Ok(names)
+    }
+}
+
+// Off Windows there is no registry API, so reg on PATH stands in for it
+#[cfg(not(windows))]
+mod reg {
+    use crate::{capture, SvcError};
+    use std::process::{Command, Stdio};
+
+    // Data of a Run value, or None when the value doesn't exist
+    pub fn query_value_in(path: &str, name: &str) -> Result<Option<String>, SvcError> {
+        let output = capture::output(
+            Command::new("reg")
+                .arg("query")
+                .arg(path)
+                .arg("/v")
+                .arg(name)
+                .stderr(Stdio::null()),
+        )?;
+        if !output.status.success() {
+            return Ok(None);
+        }
+
+        // Output line looks like: "    <name>    REG_SZ    <data>"
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(stdout.lines().find_map(|line| {
+            line.split_once("REG_SZ")
+                .map(|(_, data)| data.trim().to_string())
+        }))
+    }
+
+    pub fn set_value_in(path: &str, name: &str, data: &str) -> Result<(), SvcError> {
+        capture::run_helper(
+            Command::new("reg")
+                .arg("add")
+                .arg(path)
+                .arg("/v")
+                .arg(name)
+                .arg("/t")
+                .arg("REG_SZ")
+                .arg("/d")
+                .arg(data)
+                .arg("/f"),
+        )
+    }
+
+    // Only whether reg runs; what it may write shows when it does
+    pub fn probe_access(path: &str, _write: bool) -> Result<(), SvcError> {
+        Command::new("reg").arg("query").arg(path).stdout(Stdio::null()).stderr(Stdio::null()).status()?;
+        Ok(())
+    }
+
+    pub fn delete_value_in(path: &str, name: &str) -> Result<(), SvcError> {
+        capture::run_helper(Command::new("reg").arg("delete").arg(path).arg("/v").arg(name).arg("/f"))
+    }
+
+    pub fn value_names_in(path: &str) -> Result<Vec<String>, SvcError> {
+        let output = capture::output(Command::new("reg").arg("query").arg(path).stderr(Stdio::null()))?;
+        if !output.status.success() {
+            return Ok(Vec::new());
+        }
+
+        // Value lines are indented: "    <name>    REG_SZ    <data>"
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(stdout
+            .lines()
+            .filter(|line| line.starts_with(' '))
+            .filter_map(|line| line.split_once("    REG_").map(|(name, _)| name.trim().to_string()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_config;
+    use std::collections::HashMap;
+
+    const CONFIG: &str = r#"
+services:
+  - name: api
+    type: Executable
+    path: 'C:\Program Files\Api\api.exe'
+    args: ['--port', '8080']
+  - name: OneDrive
+    type: Executable
+    path: 'C:\tools\sync.exe'
+"#;
+
+    // The per-user Run key in memory, by value name
+    fn lookup_with(service: &str, values: &[(&str, &str)]) -> Lookup {
+        let values: HashMap<String, String> =
+            values.iter().map(|(name, data)| (name.to_string(), data.to_string())).collect();
+        let config = parse_config(CONFIG).unwrap();
+        lookup_in(config.service(service).unwrap(), |name| Ok(values.get(name).cloned())).unwrap()
+    }
+
+    fn found(lookup: Lookup) -> (String, bool) {
+        match lookup {
+            Lookup::Found(entry) => (entry.value.clone(), entry.is_legacy()),
+            Lookup::Foreign(data) => panic!("foreign: {}", data),
+            Lookup::Missing => panic!("missing"),
+        }
+    }
+
+    #[test]
+    fn data_svc_writes_is_recognized_in_each_of_its_forms() {
+        let config = parse_config(CONFIG).unwrap();
+        let api = config.service("api").unwrap();
+        assert!(written_by_svc(api, r#""C:\Program Files\Api\api.exe" --port 8080"#));
+        // Older versions wrote the bare path, and Windows doesn't care about case
+        assert!(written_by_svc(api, r"C:\Program Files\Api\api.exe"));
+        assert!(written_by_svc(api, r#""c:\program files\api\API.EXE" --port 8080"#));
+        assert!(written_by_svc(api, r#""C:\svc\svc.exe" run --startup "api""#));
+        assert!(!written_by_svc(api, r#""C:\Program Files\Api\api.exe" --port 9090"#));
+        assert!(!written_by_svc(api, r#""C:\svc\svc.exe" run --startup "api2""#));
+    }
+
+    #[test]
+    fn another_programs_bare_value_is_a_collision_svc_leaves_alone() {
+        let onedrive = r#""C:\Program Files\Microsoft OneDrive\OneDrive.exe" /background"#;
+        let config = parse_config(CONFIG).unwrap();
+        let refused = lookup_with("OneDrive", &[("OneDrive", onedrive)]).removable(config.service("OneDrive").unwrap());
+        assert!(matches!(refused, Err(SvcError::ForeignStartupEntry { data, .. }) if data == onedrive));
+        // svc's own entry lives under its prefix, next to the other program's
+        let values = [("OneDrive", onedrive), ("svc:OneDrive", r#""C:\tools\sync.exe""#)];
+        assert_eq!(found(lookup_with("OneDrive", &values)), ("svc:OneDrive".to_string(), false));
+    }
+
+    #[test]
+    fn a_bare_value_an_older_svc_wrote_is_found_for_migration() {
+        let legacy = [("api", r#""C:\Program Files\Api\api.exe" --port 8080"#)];
+        assert_eq!(found(lookup_with("api", &legacy)), ("api".to_string(), true));
+        // Once moved, the namespaced value is the one found, even with the old one still there
+        let both = [legacy[0], ("svc:api", r#""C:\Program Files\Api\api.exe" --port 8080"#)];
+        assert_eq!(found(lookup_with("api", &both)), ("svc:api".to_string(), false));
+        let config = parse_config(CONFIG).unwrap();
+        let missing = lookup_with("api", &[]).removable(config.service("api").unwrap());
+        assert!(matches!(missing, Err(SvcError::ServiceIsDisabled)));
+    }
+
+    // A scratch key under HKCU, so the real Run key is never touched
+    #[cfg(windows)]
+    #[test]
+    fn names_and_data_outside_the_code_page_round_trip_through_the_registry() {
+        use windows_sys::Win32::System::Registry::{RegCloseKey, RegCreateKeyW, RegDeleteTreeW, HKEY_CURRENT_USER};
+
+        let subkey = format!(r"SOFTWARE\svc-test-{}", std::process::id());
+        let wide: Vec<u16> = subkey.encode_utf16().chain(Some(0)).collect();
+        let mut key = std::ptr::null_mut();
+        assert_eq!(unsafe { RegCreateKeyW(HKEY_CURRENT_USER, wide.as_ptr(), &mut key) }, 0);
+        unsafe { RegCloseKey(key) };
+        let path = format!(r"HKCU\{}", subkey);
+
+        let values = [
+            ("服务", r#""D:\服务\api.exe" --名前 値"#),
+            ("Сервис", r"C:\Программы\Сервис.exe"),
+            ("Cafe\u{301}", "C:\\Cafe\u{301}\\api.exe"),
+        ];
+        let result = std::panic::catch_unwind(|| {
+            for (name, data) in values {
+                platform::set_value_in(&path, name, data).unwrap();
+                assert_eq!(platform::query_value_in(&path, name).unwrap().as_deref(), Some(data));
+            }
+            let mut names = platform::value_names_in(&path).unwrap();
+            names.sort();
+            let mut expected: Vec<&str> = values.iter().map(|(name, _)| *name).collect();
+            expected.sort();
+            assert_eq!(names, expected);
+            // The combining spelling is its own value, not the precomposed one
+            assert_eq!(platform::query_value_in(&path, "Caf\u{e9}").unwrap(), None);
+            for (name, _) in values {
+                platform::delete_value_in(&path, name).unwrap();
+                assert_eq!(platform::query_value_in(&path, name).unwrap(), None);
+            }
+        });
+        unsafe { RegDeleteTreeW(HKEY_CURRENT_USER, wide.as_ptr()) };
+        if let Err(panic) = result {
+            std::panic::resume_unwind(panic);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use crate::output::timestamp;
+use crate::state::{self, prev_path};
+use crate::{effects, warnings, Service, SvcError};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+// `startup_env_allowlist: [..]`: variables a logon launch gets as they were when the service was
+// enabled. A Run value or logon task starts in the logon environment, so a variable set only in
+// the shell that ran `svc enable` would be missing; svc captures the listed ones then and sets
+// them again in `svc run --startup` before it starts the service
+
+const FILE: &str = "startup-env.json";
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Captured {
+    pub captured: String,
+    pub variables: BTreeMap<String, String>,
+    // Listed but unset at enable, and so unset at logon too
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unset: Vec<String>,
+}
+
+// Names as `env` would take them: not empty and without `=`
+pub fn allowlist<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let names = Vec::<String>::deserialize(deserializer)?;
+    match names.iter().find(|name| name.is_empty() || name.contains('=')) {
+        Some(name) => Err(serde::de::Error::custom(format!(
+            "startup_env_allowlist: {:?} isn't a variable name",
+            name
+        ))),
+        None => Ok(names),
+    }
+}
+
+fn path(service: &str) -> Result<PathBuf, SvcError> {
+    Ok(state::service_path(service)?.join(FILE))
+}
+
+// The allowlisted variables out of `lookup`, svc's own environment outside tests
+fn collect(names: &[String], lookup: impl Fn(&str) -> Option<String>) -> Captured {
+    let mut captured = Captured {
+        captured: timestamp(),
+        ..Captured::default()
+    };
+    for name in names {
+        match lookup(name) {
+            Some(value) => {
+                captured.variables.insert(name.clone(), value);
+            }
+            None => captured.unset.push(name.clone()),
+        }
+    }
+    captured
+}
+
+// At enable: record what the allowlist names now, warning about those that are unset. Without an
+// allowlist, whatever an earlier enable captured goes
+pub fn capture(service: &Service) -> Result<(), SvcError> {
+    if service.startup_env_allowlist.is_empty() {
+        return forget(&service.name);
+    }
+    let captured = collect(&service.startup_env_allowlist, |name| std::env::var(name).ok());
+    if !captured.unset.is_empty() {
+        warnings::warn(
+            "startup_env_unset",
+            &service.name,
+            format!(
+                "startup_env_allowlist names {} unset here, so a logon launch won't have {}",
+                captured.unset.join(", "),
+                if captured.unset.len() == 1 { "it" } else { "them" }
+            ),
+        );
+    }
+    state::service_dir(&service.name)?;
+    let content = serde_json::to_string_pretty(&captured).map_err(std::io::Error::other)?;
+    state::replace(&path(&service.name)?, &content)
+}
+
+// At disable, with the last start-up entry gone; the previous version `replace` keeps goes too,
+// or reading would fall back to it
+pub fn forget(service: &str) -> Result<(), SvcError> {
+    let path = path(service)?;
+    for file in [prev_path(&path), path] {
+        if file.exists() {
+            effects::delete(&file)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn load(service: &str) -> Result<Option<Captured>, SvcError> {
+    state::read_json(&path(service)?)
+}
+
+// In `svc run --startup`, before anything is started: the service, its steps and the configured
+// env's %VAR% references all see the captured values. Nothing else runs in svc yet at that point
+pub fn apply(service: &Service) -> Result<(), SvcError> {
+    let Some(captured) = load(&service.name)? else {
+        return Ok(());
+    };
+    for (name, value) in &captured.variables {
+        std::env::set_var(name, value);
+    }
+    for name in &captured.unset {
+        std::env::remove_var(name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listed_variables_are_captured_or_noted_unset() {
+        let names = ["SDK_ROOT".to_string(), "TOKEN_FILE".to_string(), "MISSING".to_string()];
+        let captured = collect(&names, |name| match name {
+            "SDK_ROOT" => Some(r"D:\sdk".to_string()),
+            "TOKEN_FILE" => Some(String::new()),
+            _ => None,
+        });
+        assert_eq!(captured.variables.get("SDK_ROOT").map(String::as_str), Some(r"D:\sdk"));
+        // Set but empty is still set
+        assert_eq!(captured.variables.get("TOKEN_FILE").map(String::as_str), Some(""));
+        assert_eq!(captured.unset, ["MISSING"]);
+
+        let json = serde_json::to_string(&captured).unwrap();
+        assert_eq!(serde_json::from_str::<Captured>(&json).unwrap(), captured);
+    }
+
+    #[test]
+    fn allowlist_names_must_be_variable_names() {
+        let parse = |yaml: &str| -> Result<Vec<String>, serde_yaml::Error> {
+            allowlist(serde_yaml::Deserializer::from_str(yaml))
+        };
+        assert_eq!(parse("[SDK_ROOT, Path]").unwrap(), ["SDK_ROOT", "Path"]);
+        assert!(parse("[\"A=B\"]").unwrap_err().to_string().contains("isn't a variable name"));
+        assert!(parse("[\"\"]").is_err());
+    }
+}
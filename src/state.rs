@@ -0,0 +1,260 @@
+use crate::effects::{self, Effect};
+use crate::format::sibling;
+use crate::{safe, SvcError};
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const SERVICES_DIR: &str = "services";
+
+// Tells apart the temp files of writes this process makes at once
+static WRITES: AtomicU64 = AtomicU64::new(0);
+
+// Per-user directory svc keeps across invocations, even in safe mode
+pub fn user_dir() -> Result<PathBuf, SvcError> {
+    let base = std::env::var_os("LOCALAPPDATA")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    let dir = base.join("svc");
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+// Per-user directory for files svc maintains itself (PID files, logs); a scratch one in safe mode
+pub fn state_dir() -> Result<PathBuf, SvcError> {
+    if !safe::on() {
+        return user_dir();
+    }
+    let dir = safe::scratch_dir();
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+// A service's own subdirectory, so svc processes busy with different services never write the
+// same file
+pub fn service_dir(name: &str) -> Result<PathBuf, SvcError> {
+    let dir = service_path(name)?;
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+// The subdirectory, without creating it
+pub fn service_path(name: &str) -> Result<PathBuf, SvcError> {
+    Ok(state_dir()?.join(SERVICES_DIR).join(name))
+}
+
+// A file of the service's subdirectory, relative to the state directory as the PID file
+// functions take it
+pub fn service_file(name: &str, file: &str) -> String {
+    format!("{}/{}/{}", SERVICES_DIR, name, file)
+}
+
+// Names that have a subdirectory, whatever is left in it
+pub fn service_dirs() -> Result<Vec<String>, SvcError> {
+    let mut names: Vec<String> = fs::read_dir(state_dir()?.join(SERVICES_DIR))
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+pub fn prev_path(path: &Path) -> PathBuf {
+    sibling(path, ".prev")
+}
+
+// An advisory lock on a state file, taken around a read-modify-write so svc processes and threads
+// doing one at once take turns; released on drop
+pub struct FileLock(#[allow(dead_code)] File);
+
+pub fn lock(path: &Path) -> Result<FileLock, SvcError> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(sibling(path, ".lock"))?;
+    file.lock()?;
+    Ok(FileLock(file))
+}
+
+// A state file written whole: to a temp file, then renamed into place, so a reader sees the old
+// content or the new and never part of it. The version it replaces is kept as <file>.prev
+pub fn replace(path: &Path, content: &str) -> Result<(), SvcError> {
+    let effect = Effect::WriteFile {
+        path: path.display().to_string(),
+    };
+    effects::perform(effect, || {
+        let write = WRITES.fetch_add(1, Ordering::Relaxed);
+        let temp = sibling(path, &format!(".{}-{}.tmp", std::process::id(), write));
+        let mut file = File::create(&temp)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+        match fs::rename(path, prev_path(path)) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        fs::rename(&temp, path)?;
+        Ok(())
+    })
+    .map(drop)
+}
+
+// A state file as `parse` reads it, or its previous version when the file is missing (caught
+// between the two renames of `replace`) or doesn't parse (cut short by a crash); None when there
+// is neither. CorruptState only when no version parses
+pub fn read<T>(path: &Path, parse: impl Fn(&str) -> Result<T, String>) -> Result<Option<T>, SvcError> {
+    let mut damaged = None;
+    for candidate in [path.to_path_buf(), prev_path(path)] {
+        match fs::read_to_string(&candidate) {
+            Ok(content) => match parse(&content) {
+                Ok(value) => return Ok(Some(value)),
+                Err(reason) => {
+                    damaged.get_or_insert(reason);
+                }
+            },
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    match damaged {
+        Some(reason) => Err(SvcError::CorruptState {
+            path: path.display().to_string(),
+            reason,
+        }),
+        None => Ok(None),
+    }
+}
+
+// A JSON state file, read as `read` does
+pub fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<Option<T>, SvcError> {
+    read(path, |content| serde_json::from_str(content).map_err(|err| err.to_string()))
+}
+
+// A line added to a JSON-lines state file under its lock, so lines written at once don't run into
+// each other
+pub fn append(path: &Path, line: &str) -> Result<(), SvcError> {
+    let _lock = lock(path)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(format!("{}\n", line).as_bytes())?;
+    Ok(())
+}
+
+pub fn read_pid_file(name: &str) -> Result<Option<u64>, SvcError> {
+    let path = state_dir()?.join(name);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(path).map_err(|_| SvcError::CannotReadPID)?;
+    let pid = content
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| SvcError::FailedToParsePID)?;
+    Ok(Some(pid))
+}
+
+// A small file of the state directory, renamed into place so a reader never finds it empty
+pub fn write_file(name: &str, content: &str) -> Result<(), SvcError> {
+    let path = state_dir()?.join(name);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp = sibling(&path, &format!(".{}.tmp", std::process::id()));
+    fs::write(&temp, content)?;
+    fs::rename(&temp, &path)?;
+    Ok(())
+}
+
+pub fn write_pid_file(name: &str, pid: u64) -> Result<(), SvcError> {
+    write_file(name, &pid.to_string())
+}
+
+// Write the PID file only where there is none, false when another process has it; linked into
+// place from a full copy, so it is never found empty either
+pub fn claim_pid_file(name: &str, pid: u64) -> Result<bool, SvcError> {
+    let path = state_dir()?.join(name);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    claim(&path, &pid.to_string())
+}
+
+fn claim(path: &Path, content: &str) -> Result<bool, SvcError> {
+    let temp = sibling(path, &format!(".{}.tmp", std::process::id()));
+    fs::write(&temp, content)?;
+    let claimed = match fs::hard_link(&temp, path) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => Ok(false),
+        Err(err) => Err(err.into()),
+    };
+    let _ = fs::remove_file(&temp);
+    claimed
+}
+
+pub fn remove_pid_file(name: &str) -> Result<(), SvcError> {
+    let path = state_dir()?.join(name);
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn readers_never_see_a_write_in_progress() {
+        let dir = std::env::temp_dir().join(format!("svc-state-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("counter.json");
+        const WRITERS: u64 = 4;
+        const WRITES: u64 = 50;
+
+        thread::scope(|scope| {
+            for _ in 0..WRITERS {
+                scope.spawn(|| {
+                    for _ in 0..WRITES {
+                        let _lock = lock(&path).unwrap();
+                        let count: u64 = read_json(&path).unwrap().unwrap_or(0);
+                        // Padded so a torn write would cut the JSON short
+                        replace(&path, &format!("{}{}", count + 1, " ".repeat(4096))).unwrap();
+                    }
+                });
+            }
+            for _ in 0..2 {
+                scope.spawn(|| {
+                    let mut seen = 0;
+                    while seen < WRITERS * WRITES {
+                        let count: Option<u64> = read_json(&path).expect("a reader found no version that parses");
+                        let count = count.unwrap_or(0);
+                        assert!(count >= seen, "went back from {} to {}", seen, count);
+                        seen = count;
+                    }
+                });
+            }
+        });
+
+        assert_eq!(read_json::<u64>(&path).unwrap(), Some(WRITERS * WRITES));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_pid_file_is_claimed_once() {
+        let dir = std::env::temp_dir().join(format!("svc-claim-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("watch.pid");
+        assert!(claim(&path, "4100").unwrap());
+        assert!(!claim(&path, "4200").unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "4100");
+        // Nothing is left of the copy it was linked from
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
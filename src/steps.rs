@@ -0,0 +1,81 @@
+use crate::config::default_interpreter;
+use crate::{cancel, history, Service, ServiceType, SvcError};
+use colored::Colorize;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::process::Command;
+
+// A command run to completion before the service itself starts, e.g. a database migration
+#[derive(Debug, Deserialize)]
+pub struct Step<'a> {
+    path: Cow<'a, str>,
+    #[serde(rename = "type")]
+    step_type: ServiceType,
+    #[serde(default = "default_interpreter")]
+    interpreter: Cow<'a, str>,
+    #[serde(default)]
+    args: Vec<Cow<'a, str>>,
+    // Carry on with the next step when this one fails
+    #[serde(default)]
+    continue_on_error: bool,
+}
+
+// Run the steps in order, recording every exit code; stops at the first failing mandatory step
+pub fn run(service: &Service, work_at: &str) -> Result<(), SvcError> {
+    let total = service.steps.len();
+    let mut results = Vec::with_capacity(total);
+    let mut failure = None;
+
+    for (index, step) in service.steps.iter().enumerate() {
+        let mut command = match step.step_type {
+            ServiceType::Executable => Command::new(step.path.as_ref()),
+            ServiceType::Util => {
+                let mut command = Command::new(step.interpreter.as_ref());
+                command.arg(step.path.as_ref());
+                command
+            }
+        };
+        command.args(step.args.iter().map(AsRef::as_ref));
+        if !work_at.is_empty() {
+            command.current_dir(work_at);
+        }
+
+        let status = match command.spawn() {
+            Ok(mut child) => cancel::wait(&mut child),
+            Err(err) => Err(err.into()),
+        };
+        let label = format!("Step {}/{} {}:", index + 1, total, step.path);
+        let (success, exit_code, reason) = match &status {
+            Ok(status) => (status.success(), status.code(), status.to_string()),
+            Err(err) => (false, None, err.to_string()),
+        };
+        results.push(history::StepRecord {
+            path: step.path.to_string(),
+            success,
+            exit_code,
+        });
+
+        if success {
+            println!("{} {}", label, "ok".green());
+        } else if step.continue_on_error && !matches!(status, Err(SvcError::Interrupted)) {
+            println!("{} {} ({}), continuing", label, "failed".yellow(), reason);
+        } else {
+            println!("{} {} ({})", label, "failed".red(), reason);
+            failure = Some(match status {
+                Err(err @ SvcError::Interrupted) => err,
+                _ => SvcError::StepFailed {
+                    service: service.name.to_string(),
+                    step: index + 1,
+                    path: step.path.to_string(),
+                    reason,
+                },
+            });
+            break;
+        }
+    }
+
+    let mut record = history::HistoryRecord::new(&service.name, "steps", failure.is_none());
+    record.steps = results;
+    let _ = history::record(&record);
+    failure.map_or(Ok(()), Err)
+}
@@ -0,0 +1,551 @@
+use crate::checkpoint::{self, Progress, Tracker};
+use crate::cli::{SessionFilter, UptimeArgs};
+use crate::history::{self, HistoryRecord};
+use crate::{get_pids, kill_pids_within, killguard, load_config, output, procinfo, terminate};
+use crate::{cancel, effects, ports, scm, uptime, Service, SvcError};
+use colored::Colorize;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+// How often services that failed to stop are looked at again while waiting for the deadline
+const STUCK_POLL: Duration = Duration::from_millis(100);
+
+// How a stop of every service is scheduled, shared by `svc down`, `svc kill --all` and the
+// shutdown hook
+pub struct Options {
+    // For the whole run; a signal's grace is cut short by it
+    pub deadline: Duration,
+    // Skip the rest after the first service that doesn't stop
+    pub halt_on_failure: bool,
+    // Terminate what still runs at the deadline, and what the run didn't reach by then
+    pub force_at_deadline: bool,
+    // Leave protected services running, as down and kill --all do without --force
+    pub skip_protected: bool,
+    // Where each outcome is recorded as it comes, for `svc resume-last`
+    pub checkpoint: Option<Tracker>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Method {
+    NotRunning,
+    // Exited on its stop_signal
+    Signal,
+    // Terminated, without a stop_signal or after its grace
+    Terminated,
+    // Still running after its stop, gone on its own before the deadline
+    Late,
+    // Terminated at the deadline with --force-at-deadline
+    ForcedAtDeadline,
+    // Still running at the end
+    Failed,
+    // Not tried: past the deadline, or after a failure with --halt-on-failure
+    Skipped,
+    // Left running: none of its processes met --if-uptime-gt or --if-uptime-lt
+    ConditionNotMet,
+    // Left running: protected, and the run wasn't forced
+    Protected,
+    // Not reached before Ctrl+C
+    NotAttempted,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Outcome {
+    pub service: String,
+    pub method: Method,
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// What the scheduler does to one service; the processes of the real one, a script in tests
+pub trait Stopper {
+    // Stop it as gracefully as `within` allows; None when it wasn't running
+    fn stop(&mut self, service: &Service, within: Duration) -> Result<Option<Method>, SvcError>;
+    fn running(&mut self, service: &Service) -> bool;
+    fn terminate(&mut self, service: &Service) -> Result<(), SvcError>;
+}
+
+// Dependents before what they depend on, so nothing loses a dependency while it still runs;
+// a service several others depend on goes after all of them
+pub fn order<'a>(services: &'a [Service<'a>]) -> Vec<&'a Service<'a>> {
+    fn visit<'a>(
+        service: &'a Service<'a>,
+        services: &'a [Service<'a>],
+        seen: &mut HashSet<&'a str>,
+        order: &mut Vec<&'a Service<'a>>,
+    ) {
+        if !seen.insert(&service.name) {
+            return;
+        }
+        for name in &service.depends_on {
+            if let Some(dependency) = services.iter().find(|s| s.name == *name) {
+                visit(dependency, services, seen, order);
+            }
+        }
+        order.push(service);
+    }
+
+    let (mut seen, mut order) = (HashSet::new(), Vec::new());
+    for service in services {
+        visit(service, services, &mut seen, &mut order);
+    }
+    order.reverse();
+    order
+}
+
+fn outcome(service: &Service, method: Method, began: Instant, error: Option<String>) -> Outcome {
+    Outcome {
+        service: service.name.to_string(),
+        method,
+        duration_ms: began.elapsed().as_millis() as u64,
+        error,
+    }
+}
+
+fn progress(method: Method) -> Progress {
+    match method {
+        Method::Failed => Progress::Failed,
+        Method::Skipped | Method::NotAttempted => Progress::NotAttempted,
+        _ => Progress::Succeeded,
+    }
+}
+
+fn checkpoint(options: &Options, outcome: &Outcome) {
+    if let Some(tracker) = &options.checkpoint {
+        tracker.done(&outcome.service, progress(outcome.method), outcome.error.clone());
+    }
+}
+
+fn forced(stopper: &mut dyn Stopper, service: &Service) -> (Method, Option<String>) {
+    match stopper.terminate(service) {
+        Ok(()) => (Method::ForcedAtDeadline, None),
+        Err(err) => (Method::Failed, Some(err.to_string())),
+    }
+}
+
+// Stop every service in `order`, one at a time, within the deadline; after Ctrl+C the one
+// being stopped gets a short grace and the rest are not attempted
+pub fn stop_all(services: &[Service], options: &Options, stopper: &mut dyn Stopper) -> Vec<Outcome> {
+    let deadline = Instant::now() + options.deadline;
+    let mut outcomes = Vec::new();
+    // Outcomes of services still running after their stop, with the service
+    let mut stuck: Vec<(usize, &Service)> = Vec::new();
+    let mut halted = false;
+
+    for service in order(services) {
+        let began = Instant::now();
+        let remaining = deadline.saturating_duration_since(began);
+        let (method, error) = if cancel::requested() {
+            (Method::NotAttempted, None)
+        } else if options.skip_protected && service.protected {
+            (Method::Protected, None)
+        } else if halted {
+            (Method::Skipped, Some("an earlier service did not stop".to_string()))
+        } else if remaining.is_zero() {
+            match (stopper.running(service), options.force_at_deadline) {
+                (false, _) => (Method::NotRunning, None),
+                (true, true) => forced(stopper, service),
+                (true, false) => (Method::Skipped, Some("the deadline passed".to_string())),
+            }
+        } else {
+            match cancel::in_flight(|| stopper.stop(service, remaining)) {
+                Ok(None) => (Method::NotRunning, None),
+                Ok(Some(method)) if !stopper.running(service) => (method, None),
+                Ok(Some(_)) => (Method::Failed, Some("still running after its stop".to_string())),
+                Err(SvcError::Interrupted) => (Method::Failed, Some("interrupted before it stopped".to_string())),
+                Err(err) => (Method::Failed, Some(err.to_string())),
+            }
+        };
+        if method == Method::Failed {
+            stuck.push((outcomes.len(), service));
+            halted = options.halt_on_failure;
+        }
+        let outcome = outcome(service, method, began, error);
+        checkpoint(options, &outcome);
+        outcomes.push(outcome);
+    }
+
+    // A stuck service gets until the deadline to go on its own; after Ctrl+C none is forced
+    if options.force_at_deadline && !stuck.is_empty() {
+        while Instant::now() < deadline
+            && !cancel::requested()
+            && stuck.iter().any(|(_, service)| stopper.running(service))
+        {
+            std::thread::sleep(STUCK_POLL.min(deadline.saturating_duration_since(Instant::now())));
+        }
+        for (at, service) in stuck {
+            let (method, error) = if !stopper.running(service) {
+                (Method::Late, None)
+            } else if cancel::requested() {
+                continue;
+            } else {
+                forced(stopper, service)
+            };
+            outcomes[at].method = method;
+            outcomes[at].error = error.or(outcomes[at].error.take());
+            checkpoint(options, &outcomes[at]);
+        }
+    }
+    outcomes
+}
+
+// The processes of the configured services
+pub struct Live {
+    pub session: SessionFilter,
+    // Skip the confirmation for loose matches
+    pub force: bool,
+    // Only the processes up for longer or shorter than this are stopped
+    pub uptime: UptimeArgs,
+}
+
+impl Live {
+    fn running_pids(&self, service: &Service) -> Vec<u64> {
+        let mut pids = get_pids(service).unwrap_or_default();
+        procinfo::retain_session(&mut pids, self.session);
+        pids
+    }
+
+    fn pids(&self, service: &Service) -> Vec<u64> {
+        uptime::matching(&self.uptime, self.running_pids(service))
+    }
+}
+
+impl Stopper for Live {
+    fn stop(&mut self, service: &Service, within: Duration) -> Result<Option<Method>, SvcError> {
+        let pids = self.pids(service);
+        if pids.is_empty() {
+            return match self.running_pids(service).is_empty() {
+                true => Ok(None),
+                false => Ok(Some(Method::ConditionNotMet)),
+            };
+        }
+        scm::refuse(service, self.force)?;
+        match kill_pids_within(service, pids, self.force, within) {
+            Ok(true) => Ok(Some(Method::Signal)),
+            Ok(false) => Ok(Some(Method::Terminated)),
+            Err(SvcError::ServiceIsNotRunning) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    // On a dry run the planned stop is taken as done
+    fn running(&mut self, service: &Service) -> bool {
+        !effects::dry_run() && !self.pids(service).is_empty()
+    }
+
+    fn terminate(&mut self, service: &Service) -> Result<(), SvcError> {
+        let pids = killguard::screen(service, self.pids(service), self.force)?;
+        terminate(service, &pids)
+    }
+}
+
+// A kill record per service the run acted on, with what went wrong for those that didn't stop
+pub fn record(outcomes: &[Outcome]) {
+    for outcome in outcomes {
+        if matches!(
+            outcome.method,
+            Method::NotRunning | Method::Skipped | Method::ConditionNotMet | Method::NotAttempted
+        ) {
+            continue;
+        }
+        let mut entry = HistoryRecord::new(&outcome.service, "kill", outcome.method != Method::Failed);
+        entry.output.extend(outcome.error.clone());
+        let _ = history::record(&entry);
+    }
+}
+
+// The services that were still running at the end
+pub fn stuck(outcomes: &[Outcome]) -> Vec<String> {
+    outcomes
+        .iter()
+        .filter(|outcome| outcome.method == Method::Failed)
+        .map(|outcome| outcome.service.clone())
+        .collect()
+}
+
+fn method_name(method: Method) -> String {
+    serde_json::to_value(method)
+        .ok()
+        .and_then(|value| value.as_str().map(String::from))
+        .unwrap_or_default()
+}
+
+// One line per service in stop order, or one {"services": [...]} document with --json
+pub fn print_report(outcomes: &[Outcome]) -> Result<(), SvcError> {
+    if output::options().json {
+        let report = serde_json::json!({ "services": outcomes });
+        println!("{}", serde_json::to_string_pretty(&report).map_err(std::io::Error::other)?);
+        return Ok(());
+    }
+    let width = outcomes.iter().map(|outcome| output::width(&outcome.service)).max().unwrap_or(0);
+    for outcome in outcomes {
+        let method = format!("{:<18}", method_name(outcome.method));
+        let method = match outcome.method {
+            Method::Failed => method.red().bold().to_string(),
+            Method::Skipped
+            | Method::ConditionNotMet
+            | Method::Protected
+            | Method::NotAttempted
+            | Method::ForcedAtDeadline
+            | Method::Late => {
+                method.yellow().to_string()
+            }
+            _ => method,
+        };
+        let error = outcome.error.as_deref().map(|error| format!("  {}", error)).unwrap_or_default();
+        println!(
+            "  {}  {}  {:>6.1}s{}",
+            output::pad(&outcome.service, width),
+            method,
+            outcome.duration_ms as f64 / 1000.0,
+            error
+        );
+    }
+    let protected = outcomes.iter().filter(|outcome| outcome.method == Method::Protected).count();
+    if protected > 0 {
+        println!("{} protected, skipped; --force stops them too.", protected);
+    }
+    Ok(())
+}
+
+// `svc down` and `svc kill --all`: every configured service, or those `only` names, then the
+// report; what Ctrl+C kept it from is left for `svc resume-last`
+pub fn run(config_path: &str, mut options: Options, mut live: Live, only: Option<&[String]>) -> Result<(), SvcError> {
+    let mut services = load_config(config_path)?;
+    ports::warn_duplicates(&services);
+    if let Some(only) = only {
+        services.retain(|service| only.iter().any(|name| service.name == name.as_str()));
+    }
+    let names: Vec<String> = order(&services).iter().map(|service| service.name.to_string()).collect();
+    options.checkpoint = Some(Tracker::begin(config_path, &names));
+    let outcomes = stop_all(&services, &options, &mut live);
+    record(&outcomes);
+    print_report(&outcomes)?;
+    let not_attempted = outcomes.iter().filter(|outcome| outcome.method == Method::NotAttempted).count();
+    if not_attempted > 0 {
+        return Err(checkpoint::interrupted(not_attempted, outcomes.len()));
+    }
+    let stuck = stuck(&outcomes);
+    if !stuck.is_empty() {
+        return Err(SvcError::StopIncomplete { stuck });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    enum Behavior {
+        // Exits on its signal, after taking this long or all it's given
+        Stops(Duration),
+        // Ignores being stopped and terminated both
+        Hangs,
+        // Ignores its stop but exits on its own after this long
+        ExitsAfter(Duration),
+    }
+
+    #[derive(Default)]
+    struct Script {
+        behaviors: HashMap<String, Behavior>,
+        gone: HashSet<String>,
+        exits_at: HashMap<String, Instant>,
+        stopped: Vec<String>,
+    }
+
+    impl Script {
+        fn with(mut self, name: &str, behavior: Behavior) -> Self {
+            self.behaviors.insert(name.to_string(), behavior);
+            self
+        }
+    }
+
+    impl Stopper for Script {
+        fn stop(&mut self, service: &Service, within: Duration) -> Result<Option<Method>, SvcError> {
+            let name = service.name.to_string();
+            if !self.running(service) {
+                return Ok(None);
+            }
+            self.stopped.push(name.clone());
+            match self.behaviors.get(&name) {
+                None => {
+                    self.gone.insert(name);
+                    Ok(Some(Method::Signal))
+                }
+                Some(Behavior::Stops(takes)) => {
+                    std::thread::sleep((*takes).min(within));
+                    self.gone.insert(name);
+                    Ok(Some(Method::Signal))
+                }
+                Some(Behavior::Hangs) => Ok(Some(Method::Terminated)),
+                Some(Behavior::ExitsAfter(after)) => {
+                    self.exits_at.insert(name, Instant::now() + *after);
+                    Ok(Some(Method::Terminated))
+                }
+            }
+        }
+
+        fn running(&mut self, service: &Service) -> bool {
+            let exited = self.exits_at.get(service.name.as_ref()).is_some_and(|at| Instant::now() >= *at);
+            !exited && !self.gone.contains(service.name.as_ref())
+        }
+
+        fn terminate(&mut self, service: &Service) -> Result<(), SvcError> {
+            match self.behaviors.get(service.name.as_ref()) {
+                Some(Behavior::Hangs) => Err(SvcError::KillDenied {
+                    pid: 4312,
+                    owner: Some("SYSTEM".to_string()),
+                    reason: "svc isn't elevated".to_string(),
+                    fix: "pass --elevate",
+                }),
+                _ => {
+                    self.gone.insert(service.name.to_string());
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    fn services(yaml: &str) -> Vec<Service<'static>> {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn options(deadline: Duration, halt_on_failure: bool, force_at_deadline: bool) -> Options {
+        Options {
+            deadline,
+            halt_on_failure,
+            force_at_deadline,
+            skip_protected: false,
+            checkpoint: None,
+        }
+    }
+
+    fn methods(outcomes: &[Outcome]) -> Vec<(&str, Method)> {
+        outcomes.iter().map(|outcome| (outcome.service.as_str(), outcome.method)).collect()
+    }
+
+    // Without dependencies the last configured stops first: a, b, c
+    const UNRELATED: &str = "
+- { name: c, path: c.exe, type: Executable }
+- { name: b, path: b.exe, type: Executable }
+- { name: a, path: a.exe, type: Executable }
+";
+
+    #[test]
+    fn order_puts_dependents_first_and_survives_cycles() {
+        let names = |yaml: &str| -> Vec<String> {
+            let services = services(yaml);
+            order(&services).iter().map(|service| service.name.to_string()).collect()
+        };
+        let chain = "
+- { name: db, path: db.exe, type: Executable }
+- { name: api, path: api.exe, type: Executable, depends_on: [cache] }
+- { name: cache, path: cache.exe, type: Executable, depends_on: [db, elsewhere] }
+- { name: tool, path: tool.exe, type: Executable }
+";
+        // A dependency the config doesn't have is left out rather than waited on
+        assert_eq!(names(chain), ["tool", "api", "cache", "db"]);
+        let cycle = "
+- { name: a, path: a.exe, type: Executable, depends_on: [b] }
+- { name: b, path: b.exe, type: Executable, depends_on: [a] }
+";
+        assert_eq!(names(cycle), ["a", "b"]);
+    }
+
+    #[test]
+    fn dependents_stop_before_a_shared_dependency() {
+        let services = services(
+            "
+- { name: db, path: db.exe, type: Executable }
+- { name: cache, path: cache.exe, type: Executable, depends_on: [db] }
+- { name: api, path: api.exe, type: Executable, depends_on: [db, cache] }
+- { name: worker, path: worker.exe, type: Executable, depends_on: [cache] }
+",
+        );
+        let mut script = Script::default();
+        let outcomes = stop_all(&services, &options(Duration::from_secs(5), false, false), &mut script);
+        let at = |name: &str| script.stopped.iter().position(|stopped| stopped == name).unwrap();
+        assert!(at("api") < at("cache") && at("worker") < at("cache"));
+        assert!(at("cache") < at("db"));
+        assert!(outcomes.iter().all(|outcome| outcome.method == Method::Signal));
+        assert!(stuck(&outcomes).is_empty());
+    }
+
+    #[test]
+    fn a_service_out_of_time_is_skipped() {
+        let services = services(UNRELATED);
+        let mut script = Script::default().with("a", Behavior::Stops(Duration::from_secs(5)));
+        let outcomes = stop_all(&services, &options(Duration::from_millis(50), false, false), &mut script);
+        // a's grace was cut to the deadline, and nothing was left for the others
+        assert!(outcomes[0].duration_ms < 1000);
+        assert_eq!(methods(&outcomes), [("a", Method::Signal), ("b", Method::Skipped), ("c", Method::Skipped)]);
+        assert_eq!(script.stopped, ["a"]);
+    }
+
+    #[test]
+    fn a_service_out_of_time_is_forced_with_force_at_deadline() {
+        let services = services(UNRELATED);
+        let mut script = Script::default().with("a", Behavior::Stops(Duration::from_secs(5)));
+        let outcomes = stop_all(&services, &options(Duration::from_millis(50), false, true), &mut script);
+        assert_eq!(
+            methods(&outcomes),
+            [("a", Method::Signal), ("b", Method::ForcedAtDeadline), ("c", Method::ForcedAtDeadline)]
+        );
+    }
+
+    #[test]
+    fn a_hung_service_does_not_hold_up_the_others() {
+        let services = services(UNRELATED);
+        let mut script = Script::default().with("b", Behavior::Hangs);
+        let outcomes = stop_all(&services, &options(Duration::from_secs(5), false, false), &mut script);
+        assert_eq!(methods(&outcomes), [("a", Method::Signal), ("b", Method::Failed), ("c", Method::Signal)]);
+        assert_eq!(stuck(&outcomes), ["b"]);
+        assert!(outcomes[1].error.is_some());
+    }
+
+    #[test]
+    fn halt_on_failure_skips_the_rest() {
+        let services = services(UNRELATED);
+        let mut script = Script::default().with("b", Behavior::Hangs);
+        let outcomes = stop_all(&services, &options(Duration::from_secs(5), true, false), &mut script);
+        assert_eq!(methods(&outcomes), [("a", Method::Signal), ("b", Method::Failed), ("c", Method::Skipped)]);
+        assert_eq!(script.stopped, ["a", "b"]);
+    }
+
+    #[test]
+    fn stuck_services_are_forced_or_late_at_the_deadline() {
+        let services = services(UNRELATED);
+        let mut script = Script::default()
+            .with("a", Behavior::ExitsAfter(Duration::from_millis(20)))
+            .with("b", Behavior::Hangs)
+            .with("c", Behavior::ExitsAfter(Duration::from_secs(60)));
+        let outcomes = stop_all(&services, &options(Duration::from_millis(200), false, true), &mut script);
+        // b can't even be terminated, so it is the one left
+        assert_eq!(methods(&outcomes), [("a", Method::Late), ("b", Method::Failed), ("c", Method::ForcedAtDeadline)]);
+        assert_eq!(stuck(&outcomes), ["b"]);
+    }
+
+    #[test]
+    fn protected_services_stay_running_unless_the_run_is_forced() {
+        let services = services(
+            "
+- { name: db, path: db.exe, type: Executable, protected: true }
+- { name: api, path: api.exe, type: Executable, depends_on: [db] }
+",
+        );
+        let mut script = Script::default();
+        let mut skipping = options(Duration::from_secs(5), false, false);
+        skipping.skip_protected = true;
+        let outcomes = stop_all(&services, &skipping, &mut script);
+        assert_eq!(methods(&outcomes), [("api", Method::Signal), ("db", Method::Protected)]);
+        assert_eq!(script.stopped, ["api"]);
+        // Left running as asked, which isn't stuck
+        assert!(stuck(&outcomes).is_empty());
+
+        let outcomes = stop_all(&services, &options(Duration::from_secs(5), false, false), &mut Script::default());
+        assert_eq!(methods(&outcomes), [("api", Method::Signal), ("db", Method::Signal)]);
+    }
+}
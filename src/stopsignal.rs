@@ -0,0 +1,122 @@
+use crate::SvcError;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// How to ask a service that can't receive console signals to exit on its own
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopSignal<'a> {
+    // Created on stop; relative paths are taken from the service's working directory
+    File(Cow<'a, str>),
+    // Name of a Windows event that is set on stop, e.g. Local\myapp-stop
+    Event(Cow<'a, str>),
+}
+
+impl StopSignal<'_> {
+    fn file_path(&self, work_at: &str) -> Option<PathBuf> {
+        match self {
+            StopSignal::File(file) => Some(Path::new(work_at).join(file.as_ref())),
+            StopSignal::Event(_) => None,
+        }
+    }
+
+    // For the stop output, e.g. "sentinel file D:\app\stop"
+    pub fn describe(&self, work_at: &str) -> String {
+        match self {
+            StopSignal::File(_) => format!("sentinel file {}", self.file_path(work_at).unwrap_or_default().display()),
+            StopSignal::Event(name) => format!("event {}", name),
+        }
+    }
+}
+
+// A raised signal, withdrawn again when dropped
+pub struct Raised {
+    file: Option<PathBuf>,
+    #[cfg(windows)]
+    event: Option<windows_sys::Win32::Foundation::HANDLE>,
+}
+
+impl Drop for Raised {
+    fn drop(&mut self) {
+        if let Some(file) = &self.file {
+            let _ = fs::remove_file(file);
+        }
+        #[cfg(windows)]
+        if let Some(event) = self.event {
+            use windows_sys::Win32::Foundation::CloseHandle;
+            use windows_sys::Win32::System::Threading::ResetEvent;
+            unsafe {
+                ResetEvent(event);
+                CloseHandle(event);
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+fn wide(name: &str) -> Vec<u16> {
+    use std::os::windows::ffi::OsStrExt;
+    std::ffi::OsStr::new(name).encode_wide().chain(Some(0)).collect()
+}
+
+pub fn raise(signal: &StopSignal, work_at: &str) -> Result<Raised, SvcError> {
+    match signal {
+        StopSignal::File(_) => {
+            let file = signal.file_path(work_at).unwrap_or_default();
+            fs::write(&file, "")?;
+            Ok(Raised {
+                file: Some(file),
+                #[cfg(windows)]
+                event: None,
+            })
+        }
+        #[cfg(windows)]
+        StopSignal::Event(name) => {
+            use windows_sys::Win32::System::Threading::{CreateEventW, SetEvent};
+
+            // Manual reset, so every process waiting on it sees the signal
+            let event = unsafe { CreateEventW(std::ptr::null(), 1, 0, wide(name).as_ptr()) };
+            if event.is_null() || unsafe { SetEvent(event) } == 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            Ok(Raised {
+                file: None,
+                event: Some(event),
+            })
+        }
+        #[cfg(not(windows))]
+        StopSignal::Event(_) => Err(std::io::Error::from(std::io::ErrorKind::Unsupported).into()),
+    }
+}
+
+// Withdraw a signal left over from an earlier stop, so a fresh start doesn't exit at once
+pub fn clear(signal: &StopSignal, work_at: &str) -> Result<(), SvcError> {
+    match signal {
+        StopSignal::File(_) => {
+            let file = signal.file_path(work_at).unwrap_or_default();
+            match fs::remove_file(&file) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+                _ => Ok(()),
+            }
+        }
+        #[cfg(windows)]
+        StopSignal::Event(name) => {
+            use windows_sys::Win32::Foundation::CloseHandle;
+            use windows_sys::Win32::System::Threading::{OpenEventW, ResetEvent, EVENT_MODIFY_STATE};
+
+            // Only still around if something holds it open
+            let event = unsafe { OpenEventW(EVENT_MODIFY_STATE, 0, wide(name).as_ptr()) };
+            if !event.is_null() {
+                unsafe {
+                    ResetEvent(event);
+                    CloseHandle(event);
+                }
+            }
+            Ok(())
+        }
+        #[cfg(not(windows))]
+        StopSignal::Event(_) => Ok(()),
+    }
+}
@@ -0,0 +1,100 @@
+use crate::check::expected;
+use crate::procinfo::Images;
+use crate::probe::{self, Capability};
+use crate::{output, running_pids, startup, Service, ServiceType, SvcError};
+use colored::Colorize;
+use rayon::prelude::*;
+use serde::Serialize;
+
+pub struct SummaryOptions {
+    pub with_startup: bool,
+    pub format: Option<String>,
+}
+
+// Executables only; a Util is neither up nor down between runs
+#[derive(Debug, Default, Serialize)]
+struct Counts {
+    up: usize,
+    // Expected to run, as `svc check` sees it, but not running
+    down: usize,
+    // Not running and not expected to
+    stopped: usize,
+    // Without a start-up entry; only counted with --with-startup
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disabled: Option<usize>,
+    total: usize,
+}
+
+impl Counts {
+    fn fill(&self, template: &str) -> String {
+        let disabled = self.disabled.map_or_else(|| "-".to_string(), |n| n.to_string());
+        template
+            .replace("{up}", &self.up.to_string())
+            .replace("{down}", &self.down.to_string())
+            .replace("{stopped}", &self.stopped.to_string())
+            .replace("{disabled}", &disabled)
+            .replace("{total}", &self.total.to_string())
+    }
+}
+
+// The exit code is 0 only when nothing expected to run is down
+pub fn summary(config: &[Service], options: &SummaryOptions) -> Result<u8, SvcError> {
+    let expected = expected(config, &[])?;
+    let executables: Vec<&Service> = config
+        .iter()
+        .filter(|service| matches!(service.service_type, ServiceType::Executable))
+        .collect();
+
+    // One process snapshot for every service, and the registry only when asked
+    let images = Images::take();
+    let states = executables
+        .par_iter()
+        .map(|service| {
+            let running = !running_pids(service, &images)?.is_empty();
+            // Left out, as if not asked, where the Run key can't be read
+            let enabled = match options.with_startup && probe::missing(Capability::StartupRead).is_none() {
+                true => Some(startup::find(service)?.is_some()),
+                false => None,
+            };
+            Ok((service, running, enabled))
+        })
+        .collect::<Result<Vec<_>, SvcError>>()?;
+
+    let mut counts = Counts {
+        total: states.len(),
+        disabled: options.with_startup.then_some(0),
+        ..Counts::default()
+    };
+    for (service, running, enabled) in &states {
+        if *running {
+            counts.up += 1;
+        } else if expected.iter().any(|s| s.name == service.name) {
+            counts.down += 1;
+        } else {
+            counts.stopped += 1;
+        }
+        if let (Some(disabled), Some(false)) = (counts.disabled.as_mut(), enabled) {
+            *disabled += 1;
+        }
+    }
+
+    if output::options().json {
+        println!("{}", serde_json::to_string(&counts).map_err(std::io::Error::other)?);
+    } else if let Some(template) = &options.format {
+        println!("{}", counts.fill(template));
+    } else {
+        let mut parts = vec![
+            format!("{} up", counts.up).green().to_string(),
+            match counts.down {
+                0 => "0 down".to_string(),
+                down => format!("{} down", down).red().bold().to_string(),
+            },
+            format!("{} stopped", counts.stopped).dimmed().to_string(),
+        ];
+        if let Some(disabled) = counts.disabled {
+            parts.push(format!("{} disabled", disabled).yellow().to_string());
+        }
+        println!("{}", parts.join(", "));
+    }
+    Ok(u8::from(counts.down > 0))
+}
@@ -0,0 +1,148 @@
+use crate::SvcError;
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeSet;
+
+// Fields of an instance entry that only say how to expand it
+const INSTANCE_FIELDS: [&str; 2] = ["instances_of", "vars"];
+
+fn invalid(template: &str, instance: &str, reason: String) -> SvcError {
+    SvcError::InvalidTemplate {
+        template: template.to_string(),
+        instance: instance.to_string(),
+        reason,
+    }
+}
+
+fn entries(document: &mut Value) -> Option<&mut Vec<Value>> {
+    match document {
+        Value::Mapping(mapping) => mapping.get_mut("services")?.as_sequence_mut(),
+        other => other.as_sequence_mut(),
+    }
+}
+
+// A var's value as it goes into the middle of a string
+fn as_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(flag) => Some(flag.to_string()),
+        _ => None,
+    }
+}
+
+// Fill the {{var}} placeholders of one string. A string that is nothing but one placeholder
+// takes the var's value as is, so `tags: '{{tags}}'` can be a whole list, except in a list,
+// whose items (args, tags, depends_on) are all text
+fn fill(text: &str, vars: &Mapping, used: &mut BTreeSet<String>, in_list: bool) -> Result<Value, String> {
+    let whole = text.trim().strip_prefix("{{").and_then(|rest| rest.strip_suffix("}}"));
+    if let Some(var) = whole.map(str::trim).filter(|var| !var.contains("{{") && !var.contains("}}")) {
+        let value = vars.get(var).ok_or_else(|| format!("{{{{{}}}}} is not in its vars", var))?;
+        used.insert(var.to_string());
+        if !in_list || !value.is_number() && !value.is_bool() {
+            return Ok(value.clone());
+        }
+    }
+    let mut filled = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        let var = rest[start + 2..start + end].trim();
+        let value = vars.get(var).ok_or_else(|| format!("{{{{{}}}}} is not in its vars", var))?;
+        let value = as_text(value).ok_or_else(|| format!("{} is used inside a string, so it has to be a scalar", var))?;
+        used.insert(var.to_string());
+        filled.push_str(&rest[..start]);
+        filled.push_str(&value);
+        rest = &rest[start + end + 2..];
+    }
+    filled.push_str(rest);
+    Ok(Value::String(filled))
+}
+
+fn fill_value(value: &Value, vars: &Mapping, used: &mut BTreeSet<String>, in_list: bool) -> Result<Value, String> {
+    Ok(match value {
+        Value::String(text) => fill(text, vars, used, in_list)?,
+        Value::Sequence(items) => Value::Sequence(
+            items
+                .iter()
+                .map(|item| fill_value(item, vars, used, true))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Mapping(mapping) => Value::Mapping(fill_mapping(mapping, vars, used)?),
+        other => other.clone(),
+    })
+}
+
+fn fill_mapping(mapping: &Mapping, vars: &Mapping, used: &mut BTreeSet<String>) -> Result<Mapping, String> {
+    mapping
+        .iter()
+        .map(|(key, value)| Ok((key.clone(), fill_value(value, vars, used, false)?)))
+        .collect()
+}
+
+// One instance entry as the service it stands for: the template with its vars filled in,
+// and the entry's own fields over it
+fn instantiate(templates: &Mapping, entry: &Mapping) -> Result<Value, SvcError> {
+    let template = entry.get("instances_of").and_then(Value::as_str).unwrap_or_default();
+    let Some(name) = entry.get("name").and_then(Value::as_str) else {
+        return Err(invalid(template, "?", "an instance needs its own name".to_string()));
+    };
+    let body = templates
+        .get(template)
+        .and_then(Value::as_mapping)
+        .ok_or_else(|| invalid(template, name, "no such template under templates".to_string()))?;
+    let mut vars = match entry.get("vars") {
+        None => Mapping::new(),
+        Some(Value::Mapping(vars)) => vars.clone(),
+        Some(_) => return Err(invalid(template, name, "vars has to be a mapping".to_string())),
+    };
+    // {{name}} is always the instance's own name
+    let given: BTreeSet<String> = vars.keys().filter_map(Value::as_str).map(String::from).collect();
+    vars.entry("name".into()).or_insert_with(|| name.into());
+
+    let mut used = BTreeSet::new();
+    let mut service = fill_mapping(body, &vars, &mut used).map_err(|reason| invalid(template, name, reason))?;
+    let unused: Vec<&String> = given.iter().filter(|var| !used.contains(*var)).collect();
+    if !unused.is_empty() {
+        let unused: Vec<&str> = unused.iter().map(|var| var.as_str()).collect();
+        return Err(invalid(template, name, format!("vars {} are not used by the template", unused.join(", "))));
+    }
+    for (field, value) in entry {
+        if !field.as_str().is_some_and(|field| INSTANCE_FIELDS.contains(&field)) {
+            service.insert(field.clone(), value.clone());
+        }
+    }
+    Ok(Value::Mapping(service))
+}
+
+// Replace each entry with `instances_of` by the service it expands to, before hosts overrides
+// and everything else see the services
+pub fn expand(document: &mut Value) -> Result<(), SvcError> {
+    let templates = document
+        .get("templates")
+        .and_then(Value::as_mapping)
+        .cloned()
+        .unwrap_or_default();
+    let Some(entries) = entries(document) else {
+        return Ok(());
+    };
+    let names: Vec<Option<String>> = entries
+        .iter()
+        .map(|entry| entry.get("name").and_then(Value::as_str).map(String::from))
+        .collect();
+    for (at, entry) in entries.iter_mut().enumerate() {
+        let Some(instance) = entry.as_mapping().filter(|entry| entry.contains_key("instances_of")) else {
+            continue;
+        };
+        let expanded = instantiate(&templates, instance)?;
+        // The template can't rename the instance, but another entry may already have its name
+        let name = expanded.get("name").and_then(Value::as_str).unwrap_or_default();
+        if names.iter().enumerate().any(|(other, known)| other != at && known.as_deref() == Some(name)) {
+            let template = instance.get("instances_of").and_then(Value::as_str).unwrap_or_default();
+            return Err(invalid(template, name, "another service already has this name".to_string()));
+        }
+        *entry = expanded;
+    }
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use crate::runas::ps_quote;
+use crate::effects::{self, Effect};
+use crate::flavor::{self, Feature};
+use crate::{Service, ServiceType, SvcError};
+use colored::Colorize;
+use std::process::{Command, Stdio};
+
+// Hosts that carry the service's path on their own command line without being the service
+pub const TERMINAL_HOSTS: [&str; 3] = ["wt.exe", "WindowsTerminal.exe", "OpenConsole.exe"];
+
+// Windows Terminal ships wt.exe as an app execution alias on PATH
+fn wt_installed() -> bool {
+    Command::new("where")
+        .arg("wt.exe")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+// WMI filter for the service's processes. Services opened in a terminal profile are
+// matched by command line, since a utility's executable is only its interpreter.
+pub fn process_filter(service: &Service) -> String {
+    let pattern = ps_quote(&format!("*{}*", service.path));
+    if service.terminal_profile.is_some() {
+        format!(
+            "$_.CommandLine -like {} -and $_.Name -notin {}",
+            pattern,
+            TERMINAL_HOSTS.map(ps_quote).join(", ")
+        )
+    } else {
+        format!("$_.ExecutablePath -like {}", pattern)
+    }
+}
+
+// Open the service interactively, in Windows Terminal when it's available
+pub fn spawn(service: &Service, work_at: &str) -> Result<(), SvcError> {
+    if service.run_as.is_some() {
+        return Err(SvcError::InvalidCommand("run --terminal for a run_as service".to_string()));
+    }
+    let effect = Effect::Start {
+        service: service.name.to_string(),
+    };
+    effects::perform(effect, || open(service, work_at)).map(drop)
+}
+
+fn open(service: &Service, work_at: &str) -> Result<(), SvcError> {
+    let mut program: Vec<&str> = match service.service_type {
+        ServiceType::Executable => vec![&service.path],
+        ServiceType::Util => vec![&service.interpreter, &service.path],
+    };
+    program.extend(service.args.iter().map(AsRef::as_ref));
+
+    let unavailable = flavor::missing(Feature::WindowsTerminal);
+    if unavailable.is_none() && wt_installed() {
+        let mut command = Command::new("wt.exe");
+        if let Some(profile) = &service.terminal_profile {
+            command.arg("-p").arg(profile.as_ref());
+        }
+        if !work_at.is_empty() {
+            command.arg("-d").arg(work_at);
+        }
+        command.args(&program).spawn()?;
+        println!("Service {} opened in Windows Terminal.", service.title().cyan());
+        return Ok(());
+    }
+
+    let why = match unavailable {
+        Some(constraint) => format!("isn't available on {}: {}", flavor::current().describe(), constraint),
+        None => "is not installed".to_string(),
+    };
+    eprintln!(
+        "{} Windows Terminal {}, opening {} in a new console window.",
+        "warning:".yellow().bold(),
+        why,
+        service.title()
+    );
+    let mut command = Command::new(program[0]);
+    command.args(&program[1..]);
+    if !work_at.is_empty() {
+        command.current_dir(work_at);
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NEW_CONSOLE: u32 = 0x0000_0010;
+        command.creation_flags(CREATE_NEW_CONSOLE);
+    }
+
+    command.spawn()?;
+    println!("Service {} opened in a new console window.", service.title().cyan());
+    Ok(())
+}
@@ -0,0 +1,460 @@
+use crate::{elevation, output, procinfo, SvcError};
+use colored::Colorize;
+use std::sync::OnceLock;
+
+// A kill that was denied access: what the tokens of the process and of svc say about why,
+// and what would get past it. SeDebugPrivilege held but disabled is enabled, once per
+// invocation, and the kill tried again with it
+
+const ELEVATE: &str = "run svc from an elevated console, or pass --elevate";
+
+// The one attempt at enabling SeDebugPrivilege, shared by every PID of the invocation
+static ENABLING: OnceLock<DebugPrivilege> = OnceLock::new();
+
+// Mandatory integrity level of a token, by the RID of its label
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Integrity {
+    Untrusted,
+    Low,
+    Medium,
+    High,
+    // SYSTEM and services; protected processes label themselves above it
+    System,
+}
+
+impl Integrity {
+    #[cfg_attr(not(windows), allow(dead_code))]
+    fn from_rid(rid: u32) -> Integrity {
+        match rid {
+            0..0x1000 => Integrity::Untrusted,
+            0x1000..0x2000 => Integrity::Low,
+            0x2000..0x3000 => Integrity::Medium,
+            0x3000..0x4000 => Integrity::High,
+            _ => Integrity::System,
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Integrity::Untrusted => "untrusted",
+            Integrity::Low => "low",
+            Integrity::Medium => "medium",
+            Integrity::High => "high",
+            Integrity::System => "system",
+        }
+    }
+}
+
+// SeDebugPrivilege in svc's own token, which opens any process whatever its security
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DebugPrivilege {
+    NotHeld,
+    Disabled,
+    Enabled,
+    // Was disabled, and svc enabled it for this kill
+    EnabledNow,
+    EnableFailed(String),
+}
+
+// What the tokens said, gathered by `gather` or put together by tests
+#[derive(Debug, Clone)]
+pub struct Facts {
+    // The account the process runs as
+    pub owner: Option<String>,
+    pub integrity: Option<Integrity>,
+    // A protected process, which not even SeDebugPrivilege opens for terminating
+    pub protected: bool,
+    // svc's own account, level and elevation
+    pub user: Option<String>,
+    pub own_integrity: Option<Integrity>,
+    pub elevated: bool,
+    pub debug: DebugPrivilege,
+}
+
+// Why access was denied and what would fix it, from the facts alone
+pub fn explain(pid: u64, facts: &Facts) -> SvcError {
+    let (reason, fix) = diagnose(facts);
+    SvcError::KillDenied {
+        pid,
+        owner: facts.owner.clone(),
+        reason,
+        fix,
+    }
+}
+
+fn diagnose(facts: &Facts) -> (String, &'static str) {
+    let other_account = match (&facts.owner, &facts.user) {
+        (Some(owner), Some(user)) if !owner.eq_ignore_ascii_case(user) => Some(owner.as_str()),
+        _ => None,
+    };
+    if facts.protected {
+        return (
+            "it is a protected process, which no token may terminate".to_string(),
+            "stop it through the service or program that manages it",
+        );
+    }
+    if !facts.elevated {
+        if let (Some(theirs), Some(ours)) = (facts.integrity, facts.own_integrity) {
+            if theirs > ours {
+                let reason = format!(
+                    "it runs at {} integrity, above svc's {}, as processes started elevated do",
+                    theirs.describe(),
+                    ours.describe()
+                );
+                return (reason, ELEVATE);
+            }
+        }
+        return match other_account {
+            Some(owner) => (
+                format!("it belongs to {} and svc, not elevated, may only end its own account's processes", owner),
+                "pass --elevate, or run svc as the account the process belongs to",
+            ),
+            None => ("svc isn't elevated".to_string(), ELEVATE),
+        };
+    }
+    let whose = other_account.map(|owner| format!(" of {}", owner)).unwrap_or_default();
+    match &facts.debug {
+        DebugPrivilege::NotHeld => (
+            format!("svc is elevated, but its token lacks SeDebugPrivilege to open the process{}", whose),
+            "run svc as an administrator holding SeDebugPrivilege (Debug programs in secpol.msc)",
+        ),
+        DebugPrivilege::EnableFailed(why) => (
+            format!("svc holds SeDebugPrivilege but could not enable it: {}", why),
+            "run svc as an administrator whose SeDebugPrivilege no policy restricts",
+        ),
+        DebugPrivilege::Disabled | DebugPrivilege::Enabled | DebugPrivilege::EnabledNow => {
+            let level = match facts.integrity {
+                Some(Integrity::System) => " at system integrity",
+                _ => "",
+            };
+            (
+                format!("even elevated with SeDebugPrivilege enabled, the process{}{} refuses", whose, level),
+                "it may guard itself, as anti-tamper software does; stop it through what manages it",
+            )
+        }
+    }
+}
+
+// After taskkill was denied: enable SeDebugPrivilege when held but off and try `kill` again
+// with it, which taskkill gets as a copy of svc's token; otherwise the explained error
+pub fn retry_denied(pid: u64, kill: impl Fn() -> Result<(), SvcError>) -> Result<(), SvcError> {
+    let mut facts = gather(pid);
+    // A PID whose taskkill failed before another's enabled the privilege gets the retry too
+    let enabled_here = ENABLING.get() == Some(&DebugPrivilege::EnabledNow);
+    if facts.debug == DebugPrivilege::Disabled || (facts.debug == DebugPrivilege::Enabled && enabled_here) {
+        facts.debug = enable_debug(pid);
+        if facts.debug == DebugPrivilege::EnabledNow && kill().is_ok() {
+            return Ok(());
+        }
+    }
+    Err(explain(pid, &facts))
+}
+
+// Tried once per invocation, whichever PID asked first, and said either way
+fn enable_debug(pid: u64) -> DebugPrivilege {
+    ENABLING
+        .get_or_init(|| {
+            let result = set_debug_privilege();
+            if !output::options().quiet {
+                eprintln!(
+                    "{} SeDebugPrivilege is held but disabled; enabling it to terminate PID {}: {}",
+                    "note:".cyan().bold(),
+                    pid,
+                    match &result {
+                        Ok(()) => "enabled".to_string(),
+                        Err(err) => format!("failed, {}", err),
+                    }
+                );
+            }
+            match result {
+                Ok(()) => DebugPrivilege::EnabledNow,
+                Err(err) => DebugPrivilege::EnableFailed(err.to_string()),
+            }
+        })
+        .clone()
+}
+
+pub fn gather(pid: u64) -> Facts {
+    let (integrity, protected) = process_token(pid);
+    Facts {
+        owner: procinfo::owner(pid),
+        integrity,
+        protected,
+        user: procinfo::owner(u64::from(std::process::id())),
+        own_integrity: own_integrity(),
+        elevated: elevation::is_elevated(),
+        debug: debug_privilege(),
+    }
+}
+
+#[cfg(windows)]
+fn integrity_of(token: windows_sys::Win32::Foundation::HANDLE) -> Option<Integrity> {
+    use windows_sys::Win32::Security::{GetSidSubAuthority, GetSidSubAuthorityCount, GetTokenInformation};
+    use windows_sys::Win32::Security::{TokenIntegrityLevel, TOKEN_MANDATORY_LABEL};
+
+    let mut needed = 0u32;
+    unsafe { GetTokenInformation(token, TokenIntegrityLevel, std::ptr::null_mut(), 0, &mut needed) };
+    let mut buffer = vec![0u64; (needed as usize).div_ceil(8)];
+    let label = buffer.as_mut_ptr().cast();
+    if unsafe { GetTokenInformation(token, TokenIntegrityLevel, label, needed, &mut needed) } == 0 {
+        return None;
+    }
+    let sid = unsafe { (*buffer.as_ptr().cast::<TOKEN_MANDATORY_LABEL>()).Label.Sid };
+    // The level is the label's last sub-authority
+    let count = unsafe { *GetSidSubAuthorityCount(sid) };
+    let rid = unsafe { *GetSidSubAuthority(sid, u32::from(count).checked_sub(1)?) };
+    Some(Integrity::from_rid(rid))
+}
+
+// The integrity level of the process, and whether it is protected
+#[cfg(windows)]
+fn process_token(pid: u64) -> (Option<Integrity>, bool) {
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::Security::TOKEN_QUERY;
+    use windows_sys::Win32::System::Threading::{GetProcessInformation, OpenProcess, OpenProcessToken};
+    use windows_sys::Win32::System::Threading::{ProcessProtectionLevelInfo, PROCESS_PROTECTION_LEVEL_INFORMATION};
+    use windows_sys::Win32::System::Threading::{PROCESS_QUERY_LIMITED_INFORMATION, PROTECTION_LEVEL_NONE};
+
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid as u32) };
+    if process.is_null() {
+        return (None, false);
+    }
+    let mut protection = PROCESS_PROTECTION_LEVEL_INFORMATION { ProtectionLevel: PROTECTION_LEVEL_NONE };
+    let protected = unsafe {
+        GetProcessInformation(
+            process,
+            ProcessProtectionLevelInfo,
+            (&mut protection as *mut PROCESS_PROTECTION_LEVEL_INFORMATION).cast(),
+            std::mem::size_of::<PROCESS_PROTECTION_LEVEL_INFORMATION>() as u32,
+        )
+    } != 0
+        && protection.ProtectionLevel != PROTECTION_LEVEL_NONE;
+    let mut token: HANDLE = std::ptr::null_mut();
+    let opened = unsafe { OpenProcessToken(process, TOKEN_QUERY, &mut token) } != 0;
+    unsafe { CloseHandle(process) };
+    if !opened {
+        return (None, protected);
+    }
+    let integrity = integrity_of(token);
+    unsafe { CloseHandle(token) };
+    (integrity, protected)
+}
+
+#[cfg(not(windows))]
+fn process_token(_pid: u64) -> (Option<Integrity>, bool) {
+    (None, false)
+}
+
+// svc's own token, opened with `access`
+#[cfg(windows)]
+fn own_token(access: u32) -> Option<windows_sys::Win32::Foundation::HANDLE> {
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    let mut token = std::ptr::null_mut();
+    (unsafe { OpenProcessToken(GetCurrentProcess(), access, &mut token) } != 0).then_some(token)
+}
+
+#[cfg(windows)]
+fn own_integrity() -> Option<Integrity> {
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::Security::TOKEN_QUERY;
+
+    let token = own_token(TOKEN_QUERY)?;
+    let integrity = integrity_of(token);
+    unsafe { CloseHandle(token) };
+    integrity
+}
+
+#[cfg(not(windows))]
+fn own_integrity() -> Option<Integrity> {
+    None
+}
+
+#[cfg(windows)]
+fn debug_luid() -> Option<windows_sys::Win32::Foundation::LUID> {
+    use windows_sys::Win32::Foundation::LUID;
+    use windows_sys::Win32::Security::{LookupPrivilegeValueW, SE_DEBUG_NAME};
+
+    let mut luid = LUID { LowPart: 0, HighPart: 0 };
+    (unsafe { LookupPrivilegeValueW(std::ptr::null(), SE_DEBUG_NAME, &mut luid) } != 0).then_some(luid)
+}
+
+#[cfg(windows)]
+fn debug_privilege() -> DebugPrivilege {
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::Security::{GetTokenInformation, TokenPrivileges, LUID_AND_ATTRIBUTES};
+    use windows_sys::Win32::Security::{SE_PRIVILEGE_ENABLED, TOKEN_PRIVILEGES, TOKEN_QUERY};
+
+    let (Some(luid), Some(token)) = (debug_luid(), own_token(TOKEN_QUERY)) else {
+        return DebugPrivilege::NotHeld;
+    };
+    let mut needed = 0u32;
+    unsafe { GetTokenInformation(token, TokenPrivileges, std::ptr::null_mut(), 0, &mut needed) };
+    let mut buffer = vec![0u64; (needed as usize).div_ceil(8)];
+    let list = buffer.as_mut_ptr().cast();
+    let ok = unsafe { GetTokenInformation(token, TokenPrivileges, list, needed, &mut needed) } != 0;
+    unsafe { CloseHandle(token) };
+    if !ok {
+        return DebugPrivilege::NotHeld;
+    }
+    let privileges = buffer.as_ptr().cast::<TOKEN_PRIVILEGES>();
+    let held: &[LUID_AND_ATTRIBUTES] = unsafe {
+        std::slice::from_raw_parts((*privileges).Privileges.as_ptr(), (*privileges).PrivilegeCount as usize)
+    };
+    match held.iter().find(|entry| entry.Luid.LowPart == luid.LowPart && entry.Luid.HighPart == luid.HighPart) {
+        None => DebugPrivilege::NotHeld,
+        Some(entry) if entry.Attributes & SE_PRIVILEGE_ENABLED != 0 => DebugPrivilege::Enabled,
+        Some(_) => DebugPrivilege::Disabled,
+    }
+}
+
+#[cfg(not(windows))]
+fn debug_privilege() -> DebugPrivilege {
+    DebugPrivilege::NotHeld
+}
+
+#[cfg(windows)]
+fn set_debug_privilege() -> std::io::Result<()> {
+    use windows_sys::Win32::Foundation::{CloseHandle, GetLastError, ERROR_NOT_ALL_ASSIGNED};
+    use windows_sys::Win32::Security::{AdjustTokenPrivileges, LUID_AND_ATTRIBUTES, SE_PRIVILEGE_ENABLED};
+    use windows_sys::Win32::Security::{TOKEN_ADJUST_PRIVILEGES, TOKEN_PRIVILEGES, TOKEN_QUERY};
+
+    let luid = debug_luid().ok_or_else(std::io::Error::last_os_error)?;
+    let token = own_token(TOKEN_ADJUST_PRIVILEGES | TOKEN_QUERY).ok_or_else(std::io::Error::last_os_error)?;
+    let wanted = TOKEN_PRIVILEGES {
+        PrivilegeCount: 1,
+        Privileges: [LUID_AND_ATTRIBUTES {
+            Luid: luid,
+            Attributes: SE_PRIVILEGE_ENABLED,
+        }],
+    };
+    let adjusted =
+        unsafe { AdjustTokenPrivileges(token, 0, &wanted, 0, std::ptr::null_mut(), std::ptr::null_mut()) } != 0;
+    // Succeeds without enabling anything when the token doesn't hold it after all
+    let result = match (adjusted, unsafe { GetLastError() }) {
+        (true, ERROR_NOT_ALL_ASSIGNED) => Err(std::io::Error::other("the token doesn't hold it")),
+        (true, _) => Ok(()),
+        (false, _) => Err(std::io::Error::last_os_error()),
+    };
+    unsafe { CloseHandle(token) };
+    result
+}
+
+#[cfg(not(windows))]
+fn set_debug_privilege() -> std::io::Result<()> {
+    Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn facts() -> Facts {
+        Facts {
+            owner: Some(r"DESK\alice".to_string()),
+            integrity: Some(Integrity::Medium),
+            protected: false,
+            user: Some(r"DESK\alice".to_string()),
+            own_integrity: Some(Integrity::Medium),
+            elevated: false,
+            debug: DebugPrivilege::NotHeld,
+        }
+    }
+
+    fn explained(facts: Facts) -> (String, &'static str) {
+        let err = explain(4312, &facts);
+        let hint = err.hint().unwrap();
+        (err.to_string(), hint)
+    }
+
+    #[test]
+    fn integrity_levels_come_from_the_label_rid() {
+        assert_eq!(Integrity::from_rid(0x0000), Integrity::Untrusted);
+        assert_eq!(Integrity::from_rid(0x1000), Integrity::Low);
+        assert_eq!(Integrity::from_rid(0x2100), Integrity::Medium);
+        assert_eq!(Integrity::from_rid(0x3000), Integrity::High);
+        assert_eq!(Integrity::from_rid(0x4000), Integrity::System);
+        assert_eq!(Integrity::from_rid(0x5000), Integrity::System);
+        assert!(Integrity::High > Integrity::Medium);
+    }
+
+    #[test]
+    fn a_process_started_elevated_needs_svc_elevated() {
+        let (message, hint) = explained(Facts {
+            integrity: Some(Integrity::High),
+            ..facts()
+        });
+        assert_eq!(
+            message,
+            concat!(
+                r"Cannot terminate PID 4312 owned by DESK\alice: access denied, ",
+                "it runs at high integrity, above svc's medium, as processes started elevated do"
+            )
+        );
+        assert_eq!(hint, ELEVATE);
+    }
+
+    #[test]
+    fn another_accounts_process_names_the_account() {
+        let (message, hint) = explained(Facts {
+            owner: Some(r"DESK\bob".to_string()),
+            ..facts()
+        });
+        assert!(message.contains(r"it belongs to DESK\bob and svc, not elevated"), "{}", message);
+        assert!(hint.contains("the account the process belongs to"), "{}", hint);
+
+        // Same account and level, and still denied: elevation is what is left
+        let (message, hint) = explained(Facts { owner: None, ..facts() });
+        assert_eq!(message, "Cannot terminate PID 4312: access denied, svc isn't elevated");
+        assert_eq!(hint, ELEVATE);
+    }
+
+    #[test]
+    fn elevated_without_the_debug_privilege_says_so() {
+        let elevated = Facts {
+            owner: Some(r"NT AUTHORITY\SYSTEM".to_string()),
+            integrity: Some(Integrity::System),
+            own_integrity: Some(Integrity::High),
+            elevated: true,
+            ..facts()
+        };
+        let (message, hint) = explained(elevated.clone());
+        assert!(message.contains(r"lacks SeDebugPrivilege to open the process of NT AUTHORITY\SYSTEM"), "{}", message);
+        assert!(hint.contains("secpol.msc"), "{}", hint);
+
+        let (message, hint) = explained(Facts {
+            debug: DebugPrivilege::EnableFailed("the token doesn't hold it".to_string()),
+            ..elevated.clone()
+        });
+        assert!(message.contains("could not enable it: the token doesn't hold it"), "{}", message);
+        assert!(hint.contains("no policy restricts"), "{}", hint);
+
+        for debug in [DebugPrivilege::Enabled, DebugPrivilege::EnabledNow] {
+            let (message, hint) = explained(Facts { debug, ..elevated.clone() });
+            assert!(message.contains("SeDebugPrivilege enabled"), "{}", message);
+            assert!(message.contains("at system integrity refuses"), "{}", message);
+            assert!(hint.contains("anti-tamper"), "{}", hint);
+        }
+    }
+
+    #[test]
+    fn nothing_gets_past_a_protected_process() {
+        for elevated in [false, true] {
+            let (message, hint) = explained(Facts {
+                protected: true,
+                elevated,
+                debug: DebugPrivilege::Enabled,
+                ..facts()
+            });
+            assert!(message.contains("protected process"), "{}", message);
+            assert!(hint.contains("manages it"), "{}", hint);
+        }
+    }
+
+    #[test]
+    fn the_error_carries_the_pid_and_owner() {
+        let err = explain(4312, &facts());
+        assert_eq!(err.code(), "kill_denied");
+        assert!(matches!(err, SvcError::KillDenied { pid: 4312, owner: Some(_), .. }));
+    }
+}
@@ -0,0 +1,306 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
+
+const DURATION_FORMATS: &str = "a number with a unit: ms, s, m, h or d (e.g. 500ms, 30s, 1.5h)";
+const SIZE_FORMATS: &str = "a number with a unit: B, K, M, G or T (e.g. 512M, 1.5G)";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnitError {
+    value: String,
+    expected: &'static str,
+}
+
+impl Display for UnitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid value '{}', expected {}", self.value, self.expected)
+    }
+}
+
+impl std::error::Error for UnitError {}
+
+// Split "1.5G" into (1.5, "G"); bare numbers are rejected since the unit would be a guess
+fn split_unit<'a>(text: &'a str, expected: &'static str) -> Result<(f64, &'a str), UnitError> {
+    let error = || UnitError {
+        value: text.to_string(),
+        expected,
+    };
+    let trimmed = text.trim();
+    let at = trimmed
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .ok_or_else(error)?;
+    let (number, unit) = trimmed.split_at(at);
+    let number = number.parse::<f64>().map_err(|_| error())?;
+    if !number.is_finite() || number < 0.0 {
+        return Err(error());
+    }
+    Ok((number, unit.trim()))
+}
+
+// A duration that remembers how it was written, so it serializes back the same way
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HumanDuration {
+    text: String,
+    value: Duration,
+}
+
+impl HumanDuration {
+    pub fn get(&self) -> Duration {
+        self.value
+    }
+}
+
+// For defaults set in code, written the way a config would write them
+impl From<Duration> for HumanDuration {
+    fn from(value: Duration) -> Self {
+        let text = match value.subsec_millis() {
+            0 => format!("{}s", value.as_secs()),
+            _ => format!("{}ms", value.as_millis()),
+        };
+        HumanDuration { text, value }
+    }
+}
+
+impl FromStr for HumanDuration {
+    type Err = UnitError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (number, unit) = split_unit(text, DURATION_FORMATS)?;
+        let seconds = match unit {
+            "ms" => number / 1000.0,
+            "s" => number,
+            "m" => number * 60.0,
+            "h" => number * 3600.0,
+            "d" => number * 86400.0,
+            _ => {
+                return Err(UnitError {
+                    value: text.to_string(),
+                    expected: DURATION_FORMATS,
+                })
+            }
+        };
+        // Too long for a Duration is as wrong as a bad unit
+        let value = Duration::try_from_secs_f64(seconds).map_err(|_| UnitError {
+            value: text.to_string(),
+            expected: DURATION_FORMATS,
+        })?;
+        Ok(HumanDuration {
+            text: text.trim().to_string(),
+            value,
+        })
+    }
+}
+
+impl Display for HumanDuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+// A byte count in binary units (1K = 1024 bytes), remembering its original spelling
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByteSize {
+    text: String,
+    bytes: u64,
+}
+
+impl ByteSize {
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+}
+
+impl FromStr for ByteSize {
+    type Err = UnitError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (number, unit) = split_unit(text, SIZE_FORMATS)?;
+        let multiplier: u64 = match unit.to_ascii_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+            "" => 1,
+            "K" => 1 << 10,
+            "M" => 1 << 20,
+            "G" => 1 << 30,
+            "T" => 1 << 40,
+            _ => {
+                return Err(UnitError {
+                    value: text.to_string(),
+                    expected: SIZE_FORMATS,
+                })
+            }
+        };
+        Ok(ByteSize {
+            text: text.trim().to_string(),
+            bytes: (number * multiplier as f64) as u64,
+        })
+    }
+}
+
+impl Display for ByteSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+// Render a byte count the way sizes are written in the config, e.g. 40.1M
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    if bytes < 1 << 10 {
+        return format!("{}B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, UNITS[unit])
+}
+
+macro_rules! serde_via_str {
+    ($ty:ty) => {
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(&self.text)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                parse_value(deserializer, None)
+            }
+        }
+    };
+}
+
+serde_via_str!(HumanDuration);
+serde_via_str!(ByteSize);
+
+// YAML numbers arrive as numbers, so accept any scalar and let FromStr reject bare ones
+fn parse_value<'de, D, T>(deserializer: D, field: Option<&str>) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = UnitError>,
+{
+    let text = match serde_yaml::Value::deserialize(deserializer)? {
+        serde_yaml::Value::String(text) => text,
+        serde_yaml::Value::Number(number) => number.to_string(),
+        other => format!("{:?}", other),
+    };
+    text.parse::<T>().map_err(|err| match field {
+        Some(field) => serde::de::Error::custom(format!("{}: {}", field, err)),
+        None => serde::de::Error::custom(err),
+    })
+}
+
+// Config fields name themselves in parse errors through these wrappers,
+// e.g. `#[serde(default, deserialize_with = "units::restart_delay")]`
+macro_rules! named_fields {
+    ($($field:ident: $ty:ty),* $(,)?) => {
+        $(
+            pub fn $field<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<$ty>, D::Error> {
+                parse_value(deserializer, Some(stringify!($field))).map(Some)
+            }
+        )*
+    };
+}
+
+named_fields! {
+    disk_budget: ByteSize,
+    logon_delay: HumanDuration,
+    max_delay: HumanDuration,
+    ready_timeout: HumanDuration,
+    restart_delay: HumanDuration,
+    startup_stagger: HumanDuration,
+    stop_grace: HumanDuration,
+    stop_timeout: HumanDuration,
+}
+
+// The same for a field with a default rather than None
+pub fn delay<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HumanDuration, D::Error> {
+    parse_value(deserializer, Some("delay"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn duration(text: &str) -> Result<Duration, UnitError> {
+        text.parse::<HumanDuration>().map(|duration| duration.get())
+    }
+
+    fn size(text: &str) -> Result<u64, UnitError> {
+        text.parse::<ByteSize>().map(|size| size.bytes())
+    }
+
+    #[test]
+    fn durations_take_each_unit_and_fractions() {
+        assert_eq!(duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(duration("1.5h"), Ok(Duration::from_secs(5400)));
+        assert_eq!(duration("1d"), Ok(Duration::from_secs(86400)));
+        assert_eq!(duration(" 2 h "), Ok(Duration::from_secs(7200)));
+        assert_eq!(duration("0s"), Ok(Duration::ZERO));
+    }
+
+    #[test]
+    fn durations_without_a_known_unit_are_rejected() {
+        for text in ["30", "", "s", "-5s", "5 minutes", "5M", "1e3s", "1.2.3s", "99999999999999999999999d"] {
+            let err = duration(text).unwrap_err();
+            assert_eq!(err.to_string(), format!("invalid value '{}', expected {}", text, DURATION_FORMATS));
+        }
+    }
+
+    #[test]
+    fn sizes_are_binary_and_take_any_case_of_unit() {
+        assert_eq!(size("512B"), Ok(512));
+        assert_eq!(size("64K"), Ok(64 * 1024));
+        assert_eq!(size("512m"), Ok(512 << 20));
+        assert_eq!(size("1.5G"), Ok(3 << 29));
+        assert_eq!(size("2GiB"), Ok(2 << 30));
+        assert_eq!(size("1TB"), Ok(1 << 40));
+        for text in ["512", "", "5X", "-1G", "G"] {
+            assert!(size(text).unwrap_err().to_string().contains(SIZE_FORMATS), "{}", text);
+        }
+    }
+
+    #[test]
+    fn values_serialize_back_as_they_were_written() {
+        let durations: Vec<HumanDuration> = serde_yaml::from_str("[1.5h, 90s, ' 2m']").unwrap();
+        assert_eq!(serde_yaml::to_string(&durations).unwrap(), "- 1.5h\n- 90s\n- 2m\n");
+        let size: ByteSize = serde_yaml::from_str("1.5G").unwrap();
+        assert_eq!(serde_yaml::to_string(&size).unwrap(), "1.5G\n");
+        assert_eq!(HumanDuration::from(Duration::from_millis(1500)).to_string(), "1500ms");
+        assert_eq!(HumanDuration::from(Duration::from_secs(10)).to_string(), "10s");
+    }
+
+    #[test]
+    fn config_fields_name_themselves_in_errors() {
+        #[derive(Debug, Deserialize)]
+        struct Entry {
+            #[serde(default, deserialize_with = "stop_timeout")]
+            stop_timeout: Option<HumanDuration>,
+            #[serde(default, deserialize_with = "disk_budget")]
+            disk_budget: Option<ByteSize>,
+        }
+        let entry: Entry = serde_yaml::from_str("stop_timeout: 10s\ndisk_budget: 200M\n").unwrap();
+        assert_eq!(entry.stop_timeout.unwrap().get(), Duration::from_secs(10));
+        assert_eq!(entry.disk_budget.unwrap().bytes(), 200 << 20);
+        // A YAML number gets the same answer as a bare number in quotes
+        let err = serde_yaml::from_str::<Entry>("stop_timeout: 10\n").unwrap_err().to_string();
+        assert!(err.starts_with("stop_timeout: invalid value '10'"), "{}", err);
+        let err = serde_yaml::from_str::<Entry>("disk_budget: lots\n").unwrap_err().to_string();
+        assert!(err.starts_with("disk_budget: invalid value 'lots'"), "{}", err);
+    }
+
+    #[test]
+    fn byte_counts_are_written_in_the_largest_whole_unit() {
+        assert_eq!(format_bytes(0), "0B");
+        assert_eq!(format_bytes(1023), "1023B");
+        assert_eq!(format_bytes(1024), "1.0K");
+        assert_eq!(format_bytes(42 << 20), "42.0M");
+        assert_eq!(format_bytes(3 << 29), "1.5G");
+        assert_eq!(format_bytes(2048 << 40), "2048.0T");
+    }
+}
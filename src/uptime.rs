@@ -0,0 +1,100 @@
+use crate::cli::UptimeArgs;
+use crate::{clock, procinfo, Service, SvcError};
+use colored::Colorize;
+use std::time::{Duration, SystemTime};
+
+// "up for longer than 1h", "up for longer than 1h and less than 2h"
+fn describe(args: &UptimeArgs) -> String {
+    match (&args.if_uptime_gt, &args.if_uptime_lt) {
+        (Some(longer), Some(shorter)) => format!("up for longer than {} and less than {}", longer, shorter),
+        (Some(longer), None) => format!("up for longer than {}", longer),
+        (None, Some(shorter)) => format!("up for less than {}", shorter),
+        (None, None) => "running".to_string(),
+    }
+}
+
+fn holds(args: &UptimeArgs, uptime: Duration) -> bool {
+    args.if_uptime_gt.as_ref().is_none_or(|longer| uptime > longer.get())
+        && args.if_uptime_lt.as_ref().is_none_or(|shorter| uptime < shorter.get())
+}
+
+// The PIDs the condition holds for, then the others; one whose start can't be read is among
+// the others, since nothing can be told about it
+fn split(args: &UptimeArgs, uptimes: &[(u64, Option<Duration>)]) -> (Vec<u64>, Vec<u64>) {
+    let (matching, others): (Vec<_>, Vec<_>) = uptimes
+        .iter()
+        .partition(|(_, uptime)| uptime.is_some_and(|uptime| holds(args, uptime)));
+    let pids = |uptimes: Vec<&(u64, Option<Duration>)>| uptimes.into_iter().map(|&(pid, _)| pid).collect();
+    (pids(matching), pids(others))
+}
+
+fn uptimes(pids: &[u64]) -> Vec<(u64, Option<Duration>)> {
+    let now = clock::unix_secs(SystemTime::now());
+    pids.iter()
+        .map(|&pid| (pid, procinfo::started_at(pid).map(|started| Duration::from_secs(clock::uptime(pid, started, now)))))
+        .collect()
+}
+
+// The PIDs the condition holds for, without a word about the others
+pub fn matching(args: &UptimeArgs, pids: Vec<u64>) -> Vec<u64> {
+    if !args.given() {
+        return pids;
+    }
+    split(args, &uptimes(&pids)).0
+}
+
+// The service's PIDs the condition holds for, the others reported and left alone;
+// ConditionNotMet when it holds for none
+pub fn select(args: &UptimeArgs, service: &Service, pids: Vec<u64>) -> Result<Vec<u64>, SvcError> {
+    if !args.given() {
+        return Ok(pids);
+    }
+    let (matching, others) = split(args, &uptimes(&pids));
+    if matching.is_empty() {
+        return Err(SvcError::ConditionNotMet {
+            service: service.name.to_string(),
+            condition: describe(args),
+        });
+    }
+    if !others.is_empty() {
+        let others: Vec<String> = others.iter().map(u64::to_string).collect();
+        println!(
+            "Leaving PID {} of {} alone: not {}.",
+            others.join(", "),
+            service.name.cyan(),
+            describe(args)
+        );
+    }
+    Ok(matching)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(longer: Option<&str>, shorter: Option<&str>) -> UptimeArgs {
+        UptimeArgs {
+            if_uptime_gt: longer.map(|text| text.parse().unwrap()),
+            if_uptime_lt: shorter.map(|text| text.parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn only_the_pids_within_the_bounds_are_matched() {
+        let uptimes = [
+            (1, Some(Duration::from_secs(30))),
+            (2, Some(Duration::from_secs(5400))),
+            (3, Some(Duration::from_secs(9000))),
+            (4, None),
+        ];
+        assert_eq!(split(&args(Some("1h"), None), &uptimes), (vec![2, 3], vec![1, 4]));
+        assert_eq!(split(&args(None, Some("5m")), &uptimes), (vec![1], vec![2, 3, 4]));
+        assert_eq!(split(&args(Some("1h"), Some("2h")), &uptimes), (vec![2], vec![1, 3, 4]));
+    }
+
+    #[test]
+    fn the_condition_reads_as_written() {
+        assert_eq!(describe(&args(Some("1h"), Some("2h"))), "up for longer than 1h and less than 2h");
+        assert_eq!(describe(&args(None, Some("5m"))), "up for less than 5m");
+    }
+}
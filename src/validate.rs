@@ -0,0 +1,336 @@
+use crate::backends::{self, Backend};
+use crate::capabilities::fields;
+use crate::pe::Subsystem;
+use crate::steps::Step;
+use crate::warnings::{self, Warning};
+use crate::{capture, cli, firewall, order, pe, ports, read_config, relocate, scm, shells, templates, work_dir};
+use crate::{output, Config, Service, ServiceType, Settings, SvcError, NO_SERVICES_HINT};
+use colored::Colorize;
+use serde_yaml::Value;
+use std::path::Path;
+use std::process::Command;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// Probes in flight at once; paths on one slow share shouldn't queue up everything else
+const WORKERS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Level {
+    Error,
+    // Of a kind, so that the same warning about many services is printed once
+    Warning(&'static str),
+    // The probe didn't finish in time, so nothing is known either way
+    Unknown,
+}
+
+struct Finding {
+    service: String,
+    level: Level,
+    message: String,
+}
+
+pub struct ValidateOptions {
+    pub offline: bool,
+    pub timeout: Duration,
+    pub signatures: bool,
+}
+
+// Authenticode status as PowerShell reports it: Valid, NotSigned, HashMismatch, ...
+fn signature(path: &str) -> Option<String> {
+    let output = capture::output(Command::new("powershell").args([
+        "-NoProfile",
+        "-Command",
+        &format!("(Get-AuthenticodeSignature -LiteralPath '{}').Status", path.replace('\'', "''")),
+    ]))
+    .ok()?;
+    let status = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!status.is_empty()).then_some(status)
+}
+
+// Filesystem facts about one service; run on its own thread so a hung share can be abandoned
+fn probe(
+    name: &str,
+    path: String,
+    work_at: String,
+    executable: bool,
+    // Whether it has a stop_signal
+    signalled: bool,
+    signatures: bool,
+    interpreter: Option<(String, String)>,
+) -> Vec<(Level, String)> {
+    let mut findings = Vec::new();
+    if let Some((_, missing)) = interpreter.filter(|(program, _)| shells::find_program(program).is_none()) {
+        findings.push((Level::Error, missing));
+    }
+    let binary = Path::new(&path);
+    if !binary.exists() {
+        let moved = relocate::nearby(binary)
+            .map(|found| format!("; {} looks like its new place, see 'svc relocate {}'", found.display(), name))
+            .unwrap_or_default();
+        findings.push((Level::Error, format!("{} does not exist{}", path, moved)));
+    } else if executable {
+        match pe::inspect(binary) {
+            None => {
+                let message = format!("{} is not a Windows executable", path);
+                findings.push((Level::Warning("not_windows_executable"), message));
+            }
+            Some(info) => {
+                for warning in pe::warnings(&path, Some(info.arch), &[]) {
+                    findings.push((Level::Warning("binary"), warning));
+                }
+                // taskkill can only close windows, which a console program doesn't have
+                if info.subsystem == Subsystem::Console && !signalled {
+                    let message = format!(
+                        "{} is a console program and there is no stop_signal, so stop can only terminate it",
+                        path
+                    );
+                    findings.push((Level::Warning("stop_method"), message));
+                }
+            }
+        }
+    }
+    if signatures && binary.is_file() {
+        match signature(&path).as_deref() {
+            Some("Valid") => {}
+            Some("NotSigned") => findings.push((Level::Warning("not_signed"), format!("{} is not signed", path))),
+            Some(status) => findings.push((Level::Error, format!("{} has a bad signature ({})", path, status))),
+            None => findings.push((Level::Unknown, format!("the signature of {} could not be checked", path))),
+        }
+    }
+    if !work_at.is_empty() && !Path::new(&work_at).is_dir() {
+        findings.push((Level::Error, format!("working directory {} does not exist", work_at)));
+    }
+    findings
+}
+
+// Several mechanisms starting the same program launch it twice at logon
+fn startup_findings(entries: &[backends::StartupEntry]) -> Vec<(Level, String)> {
+    let mut findings = Vec::new();
+    if entries.len() > 1 {
+        let backends: Vec<String> = entries.iter().map(|entry| entry.backend.to_string()).collect();
+        findings.push((
+            Level::Warning("startup_duplicate"),
+            format!("starts at logon through {}, so it may be launched more than once", backends.join(", ")),
+        ));
+    }
+    for entry in entries.iter().filter(|entry| !entry.matches_config) {
+        // An SCM service of the same name is as likely another program's
+        if entry.backend != Backend::Scm {
+            findings.push((
+                Level::Warning("startup_stale"),
+                format!("the {} entry runs {}, not what services.yaml says", entry.backend, entry.command_line()),
+            ));
+        }
+    }
+    findings
+}
+
+// Where the Util's interpreter will look for a relative script: in the working directory, unless
+// a scratch directory takes its place, when run_util makes it absolute against svc's own
+fn script_path(service: &Service, work_at: &str) -> String {
+    let path = Path::new(service.path.as_ref());
+    let util = matches!(service.service_type, ServiceType::Util);
+    if util && path.is_relative() && !work_at.is_empty() && !service.temp_work_dir {
+        return Path::new(work_at).join(path).display().to_string();
+    }
+    service.path.to_string()
+}
+
+fn check_service(service: &Service, options: &ValidateOptions) -> Vec<Finding> {
+    let work_at = work_dir(service).to_string();
+    let path = script_path(service, &work_at);
+    let executable = matches!(service.service_type, ServiceType::Executable);
+    let signalled = service.stop_signal.is_some();
+    let (timeout, signatures) = (options.timeout, options.signatures);
+    let interpreter = matches!(service.service_type, ServiceType::Util).then(|| {
+        let missing = match &service.shell {
+            Some(shell) => format!("shell {} runs {}, which is not on this machine", shell, service.interpreter),
+            None => format!("interpreter {} is not on PATH", service.interpreter),
+        };
+        (service.interpreter.to_string(), missing)
+    });
+
+    let (sender, receiver) = mpsc::channel();
+    let (name, probe_path) = (service.name.to_string(), path.clone());
+    thread::spawn(move || {
+        let _ = sender.send(probe(&name, probe_path, work_at, executable, signalled, signatures, interpreter));
+    });
+    let mut results = receiver.recv_timeout(timeout).unwrap_or_else(|_| {
+        vec![(
+            Level::Unknown,
+            format!("{} did not answer within {}s", path, timeout.as_secs_f64()),
+        )]
+    });
+    match firewall::problem(service) {
+        Ok(None) => {}
+        Ok(Some(problem)) => results.push((Level::Warning("firewall"), problem)),
+        Err(_) => results.push((Level::Unknown, "the firewall rule could not be checked".to_string())),
+    }
+    for registration in scm::registrations(service) {
+        results.push((
+            Level::Warning("scm"),
+            format!(
+                "{} is also Windows service '{}' ({}), so svc and the SCM would both control it",
+                path, registration.name, registration.state
+            ),
+        ));
+    }
+    match backends::query(service) {
+        Ok(entries) => results.extend(startup_findings(&entries)),
+        Err(_) => results.push((Level::Unknown, "the start-up entries could not be checked".to_string())),
+    }
+
+    results
+        .into_iter()
+        .map(|(level, message)| Finding {
+            service: service.name.to_string(),
+            level,
+            message,
+        })
+        .collect()
+}
+
+fn unknown(key: &str, known: &[&str]) -> String {
+    let suggestion = cli::suggest(key, known.iter().copied())
+        .map(|known| format!(", did you mean {}?", known))
+        .unwrap_or_default();
+    format!("unknown field {}{}", key, suggestion)
+}
+
+fn unknown_in(mapping: &Value, known: &[&str], skip: &[&str]) -> Vec<String> {
+    let keys = mapping.as_mapping().into_iter().flat_map(|mapping| mapping.keys());
+    keys.filter_map(Value::as_str)
+        .filter(|key| !known.contains(key) && !skip.contains(key))
+        .map(|key| unknown(key, known))
+        .collect()
+}
+
+// Keys serde skips, usually typos like `autostrat`. Loading ignores them so that fields a
+// newer svc wrote survive an older one, which is why only validate reports them
+fn unknown_keys(config_path: &str) -> Result<Vec<Finding>, SvcError> {
+    let mut document: Value = serde_yaml::from_str(&read_config(Path::new(config_path))?)?;
+    // Instances are checked as the services they expand to, template fields included
+    templates::expand(&mut document)?;
+    let file = Path::new(config_path)
+        .file_name()
+        .map_or_else(|| config_path.to_string(), |name| name.to_string_lossy().into_owned());
+    let error = |service: &str, message: String| Finding {
+        service: service.to_string(),
+        level: Level::Error,
+        message,
+    };
+    let mut findings: Vec<Finding> = match &document {
+        Value::Mapping(_) => unknown_in(&document, fields::<Settings>(), &["services", "templates"])
+            .into_iter()
+            .map(|message| error(&file, message))
+            .collect(),
+        _ => Vec::new(),
+    };
+    let entries = match &document {
+        Value::Mapping(mapping) => mapping.get("services").and_then(Value::as_sequence),
+        other => other.as_sequence(),
+    };
+    for entry in entries.into_iter().flatten() {
+        let name = entry.get("name").and_then(Value::as_str).unwrap_or("?");
+        findings.extend(unknown_in(entry, fields::<Service>(), &[]).into_iter().map(|message| error(name, message)));
+        for step in entry.get("steps").and_then(Value::as_sequence).into_iter().flatten() {
+            let messages = unknown_in(step, fields::<Step>(), &[]);
+            findings.extend(messages.into_iter().map(|message| error(name, format!("in steps, {}", message))));
+        }
+    }
+    // Overrides are service fields too, checked on every host
+    for (host, overrides) in document.get("hosts").and_then(Value::as_mapping).into_iter().flatten() {
+        for (name, overridden) in overrides.as_mapping().into_iter().flatten() {
+            let (host, name) = (host.as_str().unwrap_or("?"), name.as_str().unwrap_or("?"));
+            let messages = unknown_in(overridden, fields::<Service>(), &[]);
+            findings.extend(messages.into_iter().map(|message| error(name, format!("in hosts.{}, {}", host, message))));
+        }
+    }
+    Ok(findings)
+}
+
+// The config already passed loading; this looks at what it points to on disk
+pub fn validate(config_path: &str, config: &Config, options: &ValidateOptions) -> Result<(), SvcError> {
+    let mut findings: Vec<Finding> = if options.offline {
+        Vec::new()
+    } else {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(WORKERS)
+            .build()
+            .map_err(std::io::Error::other)?;
+        pool.install(|| {
+            order::par_map(&config.services, |service| check_service(service, options))
+                .into_iter()
+                .flatten()
+                .collect()
+        })
+    };
+    findings.extend(unknown_keys(config_path)?);
+    // What lenient loading left out, which is why it was left out
+    for entry in &config.skipped {
+        let file = match entry.file == config_path {
+            true => String::new(),
+            false => format!(" (in {})", entry.file),
+        };
+        findings.push(Finding {
+            service: entry.service.clone(),
+            level: Level::Error,
+            message: format!("skipped{}: {}", file, entry.reason),
+        });
+    }
+    for (port, names) in ports::duplicates(&config.services) {
+        findings.extend(ports::duplicate_messages(port, &names).into_iter().map(|(name, message)| Finding {
+            service: name.to_string(),
+            level: Level::Error,
+            message,
+        }));
+    }
+    findings.sort_by_key(|finding| finding.level);
+
+    // Warnings are printed grouped by kind, between the errors and the unknowns
+    let (mut warned, mut rest) = (Vec::new(), Vec::new());
+    for finding in findings {
+        match finding.level {
+            Level::Warning(kind) => warned.push(Warning {
+                kind,
+                service: finding.service,
+                message: finding.message,
+            }),
+            _ => rest.push(finding),
+        }
+    }
+    let findings = rest;
+    let print = |finding: &Finding| {
+        let label = match finding.level {
+            Level::Unknown => "unknown:".dimmed().bold(),
+            _ => "error:".red().bold(),
+        };
+        println!("{} {}: {}", label, finding.service.cyan(), finding.message);
+    };
+    findings.iter().filter(|finding| finding.level == Level::Error).for_each(print);
+    for line in warnings::summarize(&warned) {
+        println!("{}", line.text(true));
+    }
+    findings.iter().filter(|finding| finding.level == Level::Unknown).for_each(print);
+
+    let count = |level: Level| findings.iter().filter(|f| f.level == level).count();
+    let errors = count(Level::Error);
+    println!(
+        "{} services, {} errors, {} warnings, {} unknown{}",
+        config.services.len(),
+        errors,
+        warned.len(),
+        count(Level::Unknown),
+        if options.offline { " (offline, paths not checked)" } else { "" }
+    );
+    // A config without services is valid, but rarely what was meant
+    if config.services.is_empty() && !output::options().quiet {
+        println!("{} {}", "note:".cyan().bold(), NO_SERVICES_HINT);
+    }
+    if errors > 0 {
+        return Err(SvcError::ValidationFailed(errors));
+    }
+    Ok(())
+}
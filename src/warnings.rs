@@ -0,0 +1,157 @@
+use crate::backends::StartupEntry;
+use crate::output;
+use colored::Colorize;
+use serde::Serialize;
+use std::sync::Mutex;
+
+// One warning about one service; `kind` groups warnings that say the same thing
+pub struct Warning {
+    pub kind: &'static str,
+    pub service: String,
+    pub message: String,
+}
+
+static COLLECTED: Mutex<Option<Vec<Warning>>> = Mutex::new(None);
+
+// Warnings raised while it lives are held back and printed grouped when it drops; the
+// outermost collector wins when commands nest
+pub struct Collector {
+    owner: bool,
+}
+
+pub fn collect() -> Collector {
+    let mut collected = COLLECTED.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let owner = collected.is_none();
+    if owner {
+        *collected = Some(Vec::new());
+    }
+    Collector { owner }
+}
+
+impl Collector {
+    // Stop collecting and hand the warnings over instead of printing them
+    pub fn take(mut self) -> Vec<Warning> {
+        self.drain()
+    }
+
+    fn drain(&mut self) -> Vec<Warning> {
+        if !self.owner {
+            return Vec::new();
+        }
+        self.owner = false;
+        let mut collected = COLLECTED.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        collected.take().unwrap_or_default()
+    }
+}
+
+impl Drop for Collector {
+    fn drop(&mut self) {
+        let warnings = self.drain();
+        report(&warnings);
+    }
+}
+
+// Raise a warning: collected when a collector is active, printed right away otherwise
+pub fn warn(kind: &'static str, service: &str, message: String) {
+    let warning = Warning {
+        kind,
+        service: service.to_string(),
+        message,
+    };
+    let mut collected = COLLECTED.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    match collected.as_mut() {
+        Some(warnings) => warnings.push(warning),
+        None => {
+            drop(collected);
+            report(&[warning]);
+        }
+    }
+}
+
+// Warnings of one kind, in the order they were first raised
+fn grouped(warnings: &[Warning]) -> Vec<(&'static str, Vec<&Warning>)> {
+    let mut groups: Vec<(&'static str, Vec<&Warning>)> = Vec::new();
+    for warning in warnings {
+        match groups.iter_mut().find(|(kind, _)| *kind == warning.kind) {
+            Some((_, group)) => group.push(warning),
+            None => groups.push((warning.kind, vec![warning])),
+        }
+    }
+    groups
+}
+
+pub enum Line<'a> {
+    Warning(&'a Warning),
+    // The rest of a kind's services, left out without --verbose
+    More(usize),
+}
+
+impl Line<'_> {
+    // With the "warning:" label and colors for the console, plain for the watch log
+    pub fn text(&self, labeled: bool) -> String {
+        match self {
+            Line::Warning(warning) if labeled => {
+                format!("{} {}: {}", "warning:".yellow().bold(), warning.service.cyan(), warning.message)
+            }
+            Line::Warning(warning) => format!("{}: {}", warning.service, warning.message),
+            Line::More(count) => format!("  ... and {} more services, --verbose lists them", count),
+        }
+    }
+}
+
+// Each kind once with how many more services it concerns, or every warning with --verbose
+pub fn summarize(warnings: &[Warning]) -> Vec<Line<'_>> {
+    let verbose = output::options().verbose;
+    let mut lines = Vec::new();
+    for (_, group) in grouped(warnings) {
+        let shown = if verbose { group.len() } else { 1 };
+        lines.extend(group.iter().take(shown).map(|warning| Line::Warning(warning)));
+        if group.len() > shown {
+            lines.push(Line::More(group.len() - shown));
+        }
+    }
+    lines
+}
+
+#[derive(Serialize)]
+struct Group<'a> {
+    kind: &'static str,
+    count: usize,
+    services: Vec<&'a str>,
+    messages: Vec<&'a str>,
+}
+
+// On stderr: grouped lines, or one {"warnings": [...]} document in JSON mode
+pub fn report(warnings: &[Warning]) {
+    if warnings.is_empty() {
+        return;
+    }
+    if output::options().json {
+        let groups: Vec<Group> = grouped(warnings)
+            .into_iter()
+            .map(|(kind, group)| Group {
+                kind,
+                count: group.len(),
+                services: group.iter().map(|warning| warning.service.as_str()).collect(),
+                messages: group.iter().map(|warning| warning.message.as_str()).collect(),
+            })
+            .collect();
+        eprintln!("{}", serde_json::json!({ "warnings": groups }));
+        return;
+    }
+    for line in summarize(warnings) {
+        eprintln!("{}", line.text(true));
+    }
+}
+
+// A service started by more than one backend is launched more than once at logon
+pub fn startup(service: &str, entries: &[StartupEntry]) {
+    if entries.len() > 1 {
+        let backends: Vec<String> = entries.iter().map(|entry| entry.backend.to_string()).collect();
+        warn(
+            "startup_duplicate",
+            service,
+            format!("starts at logon through {}, see 'svc validate'", backends.join(", ")),
+        );
+    }
+}
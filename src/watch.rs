@@ -0,0 +1,548 @@
+use crate::cli::SessionFilter;
+use crate::output::{self, timestamp};
+use crate::notify::{self, SvcEvent};
+use crate::pacing::Pacer;
+use crate::{history, idlestop, locate, logfile, logs, metrics, procinfo, shutdownhook, state};
+use crate::{stop_service, warnings};
+use crate::process::is_svc_alive;
+use crate::{get_pids, kill_service, load_config, run_service, RestartPolicy, RunOverride, Service, SvcError};
+use colored::Colorize;
+use crate::retry::{Retrying, RetryPolicy};
+use crate::units::{ByteSize, HumanDuration};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant, SystemTime};
+
+const PID_FILE: &str = "watch.pid";
+// The background watcher's current poll interval, for `svc watch --status`
+const INTERVAL_FILE: &str = "watch.interval";
+const LOG_FILE: &str = "watch.log";
+// This many relaunches within the window is reported once as a crash loop
+const CRASH_LOOP_RESTARTS: usize = 5;
+const CRASH_LOOP_WINDOW: Duration = Duration::from_secs(600);
+// Window of max_restarts_per_hour, and how long relaunches are remembered
+const RESTART_LIMIT_WINDOW: Duration = Duration::from_secs(3600);
+// A relaunch soon after the last one waits twice as long as that one did, up to this
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+#[derive(Debug)]
+pub struct WatchOptions {
+    // Explicit services to watch; empty means every service with `restart: always`
+    pub names: Vec<String>,
+    // Kill services that disappear from the config on reload instead of just unwatching them
+    pub kill_removed: bool,
+    // Bounds of the adaptive poll interval; equal for a fixed one
+    pub min_interval: HumanDuration,
+    pub max_interval: HumanDuration,
+    // Size at which the background watcher's log file is rotated
+    pub log_max_size: ByteSize,
+    // Time between metrics samples of the watched services
+    pub sample_interval: HumanDuration,
+    // Kill the relaunched services when watching is interrupted
+    pub kill_on_exit: bool,
+}
+
+// Services to supervise out of a freshly loaded config, including those that are only
+// stopped when idle
+pub fn select(config: Vec<Service<'static>>, names: &[String]) -> Vec<Service<'static>> {
+    config
+        .into_iter()
+        .filter(|s| relaunched(s, names) || (names.is_empty() && s.idle_stop.is_some()))
+        .collect()
+}
+
+// Whether the watcher starts the service again when it finds it down
+fn relaunched(service: &Service, names: &[String]) -> bool {
+    if names.is_empty() {
+        service.restart == RestartPolicy::Always
+    } else {
+        names.iter().any(|name| service.name == name.as_str())
+    }
+}
+
+fn config_stamp(config_path: &str) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(config_path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+// Watch events go to the console, or to a log file when running without one
+struct WatchLog {
+    file: Option<(PathBuf, File)>,
+    max_size: u64,
+}
+
+impl WatchLog {
+    fn line(&mut self, message: &str) {
+        let line = format!("[{}] {}", timestamp(), message);
+        let Some((path, file)) = &mut self.file else {
+            output::line(&line);
+            return;
+        };
+
+        // Keep a single rotated generation next to the live log
+        if file.metadata().is_ok_and(|m| m.len() >= self.max_size) {
+            if let Ok(current) = file.try_clone() {
+                if let Ok(rotated) = logfile::rotate(path, current) {
+                    *file = rotated;
+                }
+            }
+        }
+        // One write per event, so a collector tailing the file never reads half a line
+        let _ = file.write_all(format!("{}\n", line).as_bytes());
+    }
+}
+
+// Relaunch every watched service whenever its process disappears,
+// picking up config changes as they happen
+pub fn watch(config_path: &str, options: &WatchOptions, log_file: Option<&Path>) -> Result<(), SvcError> {
+    let file = match log_file {
+        Some(path) => Some((path.to_path_buf(), logfile::open_shared(path)?)),
+        None => None,
+    };
+    let mut log = WatchLog {
+        file,
+        max_size: options.log_max_size.bytes(),
+    };
+    let mut down_since: HashMap<String, Instant> = HashMap::new();
+    let mut pids: HashMap<String, Vec<u64>> = HashMap::new();
+    let mut restarts: HashMap<String, u32> = HashMap::new();
+    let mut relaunches: HashMap<String, Vec<Instant>> = HashMap::new();
+    // Past their max_restarts_per_hour, so left down until something starts them again
+    let mut given_up: HashSet<String> = HashSet::new();
+    let mut last_sample: Option<Instant> = None;
+    let mut idle = idlestop::Tracker::default();
+    // Stopped for being idle, so left down until something starts them again
+    let mut idle_stopped: HashSet<String> = HashSet::new();
+
+    shutdownhook::start(config_path)?;
+    let mut stamp = config_stamp(config_path);
+    let mut services = select(load_config(config_path)?, &options.names);
+    let names: Vec<&str> = services.iter().map(|s| s.name.as_ref()).collect();
+    log.line(&format!("Watching {}.", names.join(", ")));
+    let mut pacer = Pacer::new(options.min_interval.get(), options.max_interval.get(), Instant::now());
+    let mut shown = None;
+
+    loop {
+        // Anything that happened this round keeps the polls coming quickly for a while
+        let before = pids.clone();
+        let mut acted = false;
+        let current = config_stamp(config_path);
+        if current != stamp {
+            stamp = current;
+            services = reload(config_path, options, services, &mut log);
+            acted = true;
+        }
+
+        // Failures that recur every round for many services are logged grouped
+        let round_warnings = warnings::collect();
+        for service in &services {
+            match get_pids(service) {
+                Ok(running) if running.is_empty() => {
+                    idle.forget(&service.name);
+                    let left_down = idle_stopped.contains(service.name.as_ref())
+                        || given_up.contains(service.name.as_ref())
+                        || shutdownhook::session_ending();
+                    if !relaunched(service, &options.names) || left_down {
+                        pids.remove(service.name.as_ref());
+                        continue;
+                    }
+                    let recent = relaunches.entry(service.name.to_string()).or_default();
+                    recent.retain(|at| at.elapsed() < RESTART_LIMIT_WINDOW);
+                    if service.max_restarts_per_hour.is_some_and(|limit| recent.len() >= limit as usize) {
+                        log.line(&format!(
+                            "Service {} was relaunched {} times within the hour, no longer relaunching it.",
+                            service.title(),
+                            recent.len()
+                        ));
+                        let _ = history::record(&history::HistoryRecord::new(&service.name, "watch-give-up", false));
+                        given_up.insert(service.name.to_string());
+                        down_since.remove(service.name.as_ref());
+                        continue;
+                    }
+                    // Give the service its restart_delay, backed off while it keeps exiting, before
+                    // relaunching it
+                    let Some(wait) = backoff(service, recent) else {
+                        log.line(&format!(
+                            "Service {} kept exiting through its restart_backoff, no longer relaunching it.",
+                            service.title()
+                        ));
+                        let _ = history::record(&history::HistoryRecord::new(&service.name, "watch-give-up", false));
+                        given_up.insert(service.name.to_string());
+                        down_since.remove(service.name.as_ref());
+                        continue;
+                    };
+                    let since = *down_since.entry(service.name.to_string()).or_insert_with(|| {
+                        if wait.retry > 0 {
+                            log.line(&format!("Service {} keeps exiting, {}.", service.title(), wait));
+                        }
+                        Instant::now()
+                    });
+                    if since.elapsed() < wait.delay {
+                        continue;
+                    }
+
+                    log.line(&format!("Service {} is not running, starting it.", service.title()));
+                    down_since.remove(service.name.as_ref());
+                    pids.remove(service.name.as_ref());
+                    logs::note(service, "relaunched by svc watch");
+                    acted = true;
+                    let result = run_service(service, &RunOverride::default());
+                    match &result {
+                        Ok(()) => {
+                            *restarts.entry(service.name.to_string()).or_default() += 1;
+                            let recent = relaunches.entry(service.name.to_string()).or_default();
+                            if crash_looping(recent, &mut log, service) {
+                                notify::emit(SvcEvent::CrashLoop {
+                                    service: service.name.to_string(),
+                                    restarts: recent.len(),
+                                    within: format!("{}s", CRASH_LOOP_WINDOW.as_secs()),
+                                });
+                            }
+                        }
+                        Err(err) => log.line(&format!("Failed to start {}: {}", service.title(), err)),
+                    }
+                    notify::outcome(SvcEvent::Restart { service: service.name.to_string() }, "watch", &result);
+                }
+                Ok(running) => {
+                    down_since.remove(service.name.as_ref());
+                    idle_stopped.remove(service.name.as_ref());
+                    given_up.remove(service.name.as_ref());
+                    pids.insert(service.name.to_string(), running);
+                }
+                Err(err) => warnings::warn("query_failed", &service.title(), format!("failed to query: {}", err)),
+            }
+        }
+        if last_sample.is_none_or(|at| at.elapsed() >= options.sample_interval.get()) {
+            last_sample = Some(Instant::now());
+            for service in sample(&services, &pids, &mut restarts, &mut idle) {
+                let Some(idle_stop) = &service.idle_stop else {
+                    continue;
+                };
+                log.line(&format!("Service {} has been idle ({}), stopping it.", service.title(), idle_stop));
+                acted = true;
+                let result = stop_service(service, SessionFilter::All, false);
+                match &result {
+                    Ok(()) => {
+                        idle_stopped.insert(service.name.to_string());
+                        pids.remove(service.name.as_ref());
+                    }
+                    Err(err) => log.line(&format!("Failed to stop {}: {}", service.title(), err)),
+                }
+                idle.forget(&service.name);
+                history::record_result(&service.name, "idle-stop", &result);
+                notify::outcome(SvcEvent::Stop { service: service.name.to_string() }, "watch", &result);
+            }
+        }
+
+        for line in warnings::summarize(&round_warnings.take()) {
+            log.line(&line.text(false));
+        }
+
+        let now = Instant::now();
+        if acted || pids != before {
+            pacer.event(now);
+        }
+        let interval = pacer.next(now);
+        if shown != Some(interval) {
+            shown = Some(interval);
+            show_interval(interval, &mut log);
+        }
+        // An exit of a watched process ends the wait early where it can be waited on
+        let watched: Vec<u64> = pids.values().flatten().copied().collect();
+        if let Err(err) = procinfo::wait_for_exit(&watched, interval) {
+            log.line("Interrupted, no longer watching.");
+            if options.kill_on_exit {
+                kill_watched(&services, options, &mut log);
+            }
+            return Err(err);
+        }
+    }
+}
+
+// With --verbose on the console; the background watcher keeps it for `svc watch --status`
+fn show_interval(interval: Duration, log: &mut WatchLog) {
+    let text = format!("{}s", interval.as_secs_f64());
+    if log.file.is_some() {
+        if let Ok(dir) = state::state_dir() {
+            let _ = std::fs::write(dir.join(INTERVAL_FILE), &text);
+        }
+    }
+    if output::options().verbose {
+        log.line(&format!("Polling every {}.", text));
+    }
+}
+
+// How long a relaunch waits after the service went down: its restart_delay, then for each
+// relaunch within the crash-loop window what its restart_backoff gives that retry, restart_delay
+// doubled by default, so a service that keeps exiting can't spin the CPU. None once the
+// restart_backoff's attempts are used up
+fn backoff(service: &Service, recent: &[Instant]) -> Option<Retrying> {
+    let delay = service.restart_delay.as_ref().map(HumanDuration::get).unwrap_or_default();
+    let soon = recent.iter().filter(|at| at.elapsed() < CRASH_LOOP_WINDOW).count() as u32;
+    if soon == 0 {
+        return Some(Retrying { retry: 0, of: None, delay });
+    }
+    let Some(policy) = &service.restart_backoff else {
+        let doubling = RetryPolicy::new(u32::MAX, delay.max(Duration::from_secs(1)), 2.0, Some(MAX_BACKOFF.max(delay)));
+        return Some(Retrying { retry: soon, of: None, delay: doubling.delay(soon) });
+    };
+    // Seeded by the service, so the jittered wait doesn't change from one poll to the next
+    let mut seed = DefaultHasher::new();
+    service.name.hash(&mut seed);
+    let delay = policy.delays_seeded(seed.finish()).nth(soon as usize - 1)?;
+    Some(Retrying { retry: soon, of: Some(policy.attempts), delay })
+}
+
+// Records a relaunch; true only for the one that reaches CRASH_LOOP_RESTARTS within the
+// window, so a service stuck in a loop is reported once rather than on every relaunch
+fn crash_looping(recent: &mut Vec<Instant>, log: &mut WatchLog, service: &Service) -> bool {
+    recent.push(Instant::now());
+    let soon = recent.iter().filter(|at| at.elapsed() < CRASH_LOOP_WINDOW).count();
+    if soon != CRASH_LOOP_RESTARTS {
+        return false;
+    }
+    log.line(&format!(
+        "Service {} was relaunched {} times within {}s, it looks crash-looping.",
+        service.title(),
+        soon,
+        CRASH_LOOP_WINDOW.as_secs()
+    ));
+    true
+}
+
+// --kill-on-exit: the services the watcher relaunches go down with it, protected ones aside
+fn kill_watched(services: &[Service<'static>], options: &WatchOptions, log: &mut WatchLog) {
+    for service in services.iter().filter(|s| relaunched(s, &options.names)) {
+        if service.protected {
+            log.line(&format!("Service {} is protected, leaving it running.", service.title()));
+            continue;
+        }
+        match kill_service(service, SessionFilter::All, false) {
+            Ok(()) => log.line(&format!("Service {} killed.", service.title())),
+            Err(SvcError::ServiceIsNotRunning) => {}
+            Err(err) => log.line(&format!("Failed to kill {}: {}", service.title(), err)),
+        }
+    }
+}
+
+// One process snapshot per round, split into a metrics sample for each watched service;
+// the same snapshot judges idleness, and the services idle long enough are returned
+fn sample<'s>(
+    services: &'s [Service<'static>],
+    pids: &HashMap<String, Vec<u64>>,
+    restarts: &mut HashMap<String, u32>,
+    idle: &mut idlestop::Tracker,
+) -> Vec<&'s Service<'static>> {
+    let all: Vec<u64> = pids.values().flatten().copied().collect();
+    let snapshot = metrics::snapshot(&all);
+    let mut idle_services = Vec::new();
+    for service in services {
+        let pids = pids.get(service.name.as_ref()).map(Vec::as_slice).unwrap_or_default();
+        let sample = snapshot.sample(pids, restarts.remove(service.name.as_ref()).unwrap_or(0));
+        metrics::keep(service, &sample);
+        if !pids.is_empty() && idle.observe(service, pids, snapshot.cpu_ms(pids)) {
+            idle_services.push(service);
+        }
+    }
+    idle_services
+}
+
+// Swap in the new service set; an invalid config keeps the previous one active
+fn reload(
+    config_path: &str,
+    options: &WatchOptions,
+    previous: Vec<Service<'static>>,
+    log: &mut WatchLog,
+) -> Vec<Service<'static>> {
+    let config = match load_config(config_path) {
+        Ok(config) => config,
+        Err(err) => {
+            log.line(&format!("Config reload failed, keeping the previous config: {}", err));
+            return previous;
+        }
+    };
+    let services = select(config, &options.names);
+
+    for service in &services {
+        if !previous.iter().any(|s| s.name == service.name) {
+            log.line(&format!("Service {} added to the watch list.", service.title()));
+        }
+    }
+    for service in &previous {
+        if services.iter().any(|s| s.name == service.name) {
+            continue;
+        }
+        log.line(&format!("Service {} removed from the watch list.", service.title()));
+        if options.kill_removed && service.protected {
+            log.line(&format!("Service {} is protected, leaving it running.", service.title()));
+        } else if options.kill_removed {
+            match kill_service(service, SessionFilter::All, false) {
+                Ok(()) => log.line(&format!("Service {} killed.", service.title())),
+                Err(SvcError::ServiceIsNotRunning) => {}
+                Err(err) => log.line(&format!("Failed to kill {}: {}", service.title(), err)),
+            }
+        }
+    }
+
+    log.line("Config reloaded.");
+    services
+}
+
+// Entry point of the detached watcher: records its own PID and logs to a file. Two
+// `watch --background` at once both find no watcher, so the PID file decides: whichever child
+// takes it first watches, and the other ends with WatcherIsRunning
+pub fn run_background_child(config_path: &str, options: &WatchOptions) -> Result<(), SvcError> {
+    let pid = u64::from(std::process::id());
+    // A file left by a dead watcher is cleared by running_watcher, then taken
+    let claimed = state::claim_pid_file(PID_FILE, pid)?
+        || (running_watcher()?.is_none() && state::claim_pid_file(PID_FILE, pid)?);
+    if !claimed {
+        return Err(SvcError::WatcherIsRunning(state::read_pid_file(PID_FILE)?.unwrap_or_default()));
+    }
+    let result = watch(config_path, options, Some(&state::state_dir()?.join(LOG_FILE)));
+    // Only its own: `watch --stop` may have removed it, and another watcher taken it since
+    if state::read_pid_file(PID_FILE).ok().flatten() == Some(pid) {
+        let _ = state::remove_pid_file(PID_FILE);
+    }
+    let _ = std::fs::remove_file(state::state_dir()?.join(INTERVAL_FILE));
+    result
+}
+
+fn running_watcher() -> Result<Option<u64>, SvcError> {
+    match state::read_pid_file(PID_FILE)? {
+        Some(pid) if is_svc_alive(pid)? => Ok(Some(pid)),
+        Some(_) => {
+            // The recorded watcher died without cleaning up after itself
+            state::remove_pid_file(PID_FILE)?;
+            Ok(None)
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn start_background(options: &WatchOptions) -> Result<(), SvcError> {
+    if let Some(pid) = running_watcher()? {
+        return Err(SvcError::WatcherIsRunning(pid));
+    }
+
+    let mut command = Command::new(std::env::current_exe()?);
+    command
+        .args(locate::carried_args())
+        .arg("watch")
+        .arg("--background-child")
+        // Either way, so the child doesn't fall back to the config's kill_removed
+        .arg(if options.kill_removed { "--kill-removed" } else { "--no-kill-removed" })
+        .arg("--min-interval")
+        .arg(options.min_interval.to_string())
+        .arg("--max-interval")
+        .arg(options.max_interval.to_string())
+        .arg("--log-max-size")
+        .arg(options.log_max_size.to_string())
+        .arg("--sample-interval")
+        .arg(options.sample_interval.to_string())
+        .args(&options.names)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    let child = command.spawn()?;
+    println!(
+        "Background watcher started with PID {}, logging to {}.",
+        child.id().to_string().green(),
+        state::state_dir()?.join(LOG_FILE).display().to_string().cyan()
+    );
+    Ok(())
+}
+
+pub fn stop_background() -> Result<(), SvcError> {
+    let pid = running_watcher()?.ok_or(SvcError::WatcherIsNotRunning)?;
+
+    Command::new("taskkill")
+        .arg("/F")
+        .arg("/PID")
+        .arg(pid.to_string())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    state::remove_pid_file(PID_FILE)?;
+
+    println!("Background watcher with PID {} stopped.", pid.to_string().green());
+    Ok(())
+}
+
+pub fn print_background_status() -> Result<(), SvcError> {
+    match running_watcher()? {
+        Some(pid) => {
+            let interval = std::fs::read_to_string(state::state_dir()?.join(INTERVAL_FILE)).ok();
+            let polling = interval.map(|interval| format!(", polling every {}", interval.trim())).unwrap_or_default();
+            println!("Background watcher: {} (PID {}{})", "running".green(), pid, polling)
+        }
+        None => println!("Background watcher: {}", "not running".yellow()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(content: &str) -> Vec<u64> {
+        content
+            .lines()
+            .map(|line| {
+                let (stamp, event) = line.split_once("] ").unwrap_or_else(|| panic!("{:?}", line));
+                assert!(stamp.starts_with('['), "{:?}", line);
+                let number = event.strip_prefix("event ").and_then(|number| number.parse().ok());
+                number.unwrap_or_else(|| panic!("{:?}", line))
+            })
+            .collect()
+    }
+
+    // What a collector tailing the file sees: whole events in order, across rotations
+    #[test]
+    fn a_reader_sees_whole_watch_events_while_the_log_rotates() {
+        const EVENTS: u64 = 3000;
+        let dir = std::env::temp_dir().join(format!("svc-watch-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("watch.log");
+        let mut log = WatchLog {
+            file: Some((path.clone(), logfile::open_shared(&path).unwrap())),
+            max_size: 4096,
+        };
+        let done = std::sync::atomic::AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                    let content = std::fs::read_to_string(&path).unwrap_or_default();
+                    // Only what ends in a newline is an event yet
+                    let whole = &content[..content.rfind('\n').map_or(0, |end| end + 1)];
+                    let read = events(whole);
+                    assert!(read.windows(2).all(|pair| pair[1] == pair[0] + 1), "{:?}", read);
+                }
+            });
+            for event in 0..EVENTS {
+                log.line(&format!("event {}", event));
+            }
+            done.store(true, std::sync::atomic::Ordering::Relaxed);
+        });
+
+        let rotated = events(&std::fs::read_to_string(logfile::rotated_path(&path)).unwrap());
+        let live = events(&std::fs::read_to_string(&path).unwrap());
+        let kept: Vec<u64> = rotated.into_iter().chain(live).collect();
+        assert!(kept.windows(2).all(|pair| pair[1] == pair[0] + 1), "{:?}", kept);
+        assert_eq!(kept.last(), Some(&(EVENTS - 1)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
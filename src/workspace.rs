@@ -0,0 +1,63 @@
+use crate::format::write_atomic;
+use crate::state::state_dir;
+use crate::{pathcmp, SvcError};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+// A project's own config, found from the current directory upwards
+pub const WORKSPACE_FILE: &str = ".svc.yaml";
+// Every config other than the global one svc has been used with, one path per line
+const KNOWN_FILE: &str = "workspaces.txt";
+
+static ACTIVE: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+// The nearest .svc.yaml in the current directory or above, looking no higher than the
+// user's profile for directories inside it; any SVC_NO_WORKSPACE other than 0 turns it off
+fn find() -> Option<PathBuf> {
+    if std::env::var("SVC_NO_WORKSPACE").is_ok_and(|value| !matches!(value.trim(), "" | "0")) {
+        return None;
+    }
+    let cwd = std::env::current_dir().ok()?;
+    let home = std::env::var("USERPROFILE").ok();
+    for dir in cwd.ancestors() {
+        let candidate = dir.join(WORKSPACE_FILE);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        if home.as_deref().is_some_and(|home| pathcmp::same_file(&dir.to_string_lossy(), home)) {
+            break;
+        }
+    }
+    None
+}
+
+// The workspace config commands use, if there is one; looked up once per run
+pub fn active() -> Option<&'static Path> {
+    ACTIVE.get_or_init(find).as_deref()
+}
+
+fn known_path() -> Result<PathBuf, SvcError> {
+    Ok(state_dir()?.join(KNOWN_FILE))
+}
+
+// Noted each time such a config is used, so `svc gc` from anywhere knows its services
+pub fn remember(path: &Path) -> Result<(), SvcError> {
+    let mut known = known()?;
+    if known.iter().any(|known| pathcmp::same_file(&known.to_string_lossy(), &path.to_string_lossy())) {
+        return Ok(());
+    }
+    known.push(path.to_path_buf());
+    let lines: Vec<String> = known.iter().map(|path| format!("{}\n", path.display())).collect();
+    write_atomic(&known_path()?, &lines.concat())
+}
+
+// Configs other than the global one svc has been used with that still exist
+pub fn known() -> Result<Vec<PathBuf>, SvcError> {
+    let content = match fs::read_to_string(known_path()?) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    Ok(content.lines().map(PathBuf::from).filter(|path| path.is_file()).collect())
+}
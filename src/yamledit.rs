@@ -0,0 +1,433 @@
+use serde_yaml::{Mapping, Value};
+
+// Lines [start, end) of the file replaced by `lines`; an insertion when the range is empty
+struct Edit {
+    start: usize,
+    end: usize,
+    lines: Vec<String>,
+}
+
+// The config file as lines, and the changes to make to them
+struct Text<'a> {
+    lines: Vec<&'a str>,
+    edits: Vec<Edit>,
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_content(line: &str) -> bool {
+    let text = line.trim();
+    !text.is_empty() && !text.starts_with('#')
+}
+
+fn is_item(line: &str) -> bool {
+    let text = line.trim_start_matches(' ');
+    text == "-" || text.starts_with("- ")
+}
+
+// The key of a `key: value` line whose key starts at column k, and what follows the colon;
+// before the key there may only be spaces, or the dash of the sequence item it starts
+fn key_at(line: &str, k: usize) -> Option<(&str, &str)> {
+    let (prefix, rest) = (line.get(..k)?, line.get(k..)?);
+    if !matches!(prefix.trim(), "" | "-") || rest.starts_with([' ', '-', '#', '?', '"', '\'', '{', '[']) {
+        return None;
+    }
+    let colon = rest.find(": ").or_else(|| rest.ends_with(':').then(|| rest.len() - 1))?;
+    let key = &rest[..colon];
+    (!key.is_empty() && !key.contains([' ', '#', ','])).then(|| (key, rest[colon + 1..].trim()))
+}
+
+// `key: value` as serde_yaml writes it, every line but the first indented to column k
+fn render_field(key: &str, value: &Value, k: usize) -> Option<Vec<String>> {
+    let mut field = Mapping::new();
+    field.insert(key.into(), value.clone());
+    Some(indented(&serde_yaml::to_string(&field).ok()?, k))
+}
+
+// One sequence item, its dash at column s
+fn render_item(value: &Value, s: usize) -> Option<Vec<String>> {
+    Some(indented(&serde_yaml::to_string(&Value::Sequence(vec![value.clone()])).ok()?, s))
+}
+
+fn indented(text: &str, by: usize) -> Vec<String> {
+    text.lines()
+        .map(|line| match line.is_empty() {
+            true => String::new(),
+            false => format!("{}{}", " ".repeat(by), line),
+        })
+        .collect()
+}
+
+fn name(entry: &Value) -> Option<&str> {
+    entry.get("name").and_then(Value::as_str)
+}
+
+// Which old item each new one is, as (old, new) index pairs: by position when the count is
+// unchanged, as after a rename; otherwise by name, items only being dropped or appended
+fn align(old: &[Value], new: &[Value]) -> Vec<(Option<usize>, Option<usize>)> {
+    if old.len() == new.len() {
+        return (0..old.len()).map(|at| (Some(at), Some(at))).collect();
+    }
+    let mut pairs = Vec::new();
+    let mut next = 0;
+    for (at, entry) in old.iter().enumerate() {
+        if next < new.len() && name(entry).is_some() && name(entry) == name(&new[next]) {
+            pairs.push((Some(at), Some(next)));
+            next += 1;
+        } else {
+            pairs.push((Some(at), None));
+        }
+    }
+    pairs.extend((next..new.len()).map(|at| (None, Some(at))));
+    pairs
+}
+
+impl<'a> Text<'a> {
+    fn first_content(&self, start: usize, end: usize) -> Option<usize> {
+        (start..end).find(|&at| is_content(self.lines[at]))
+    }
+
+    // Each field of the block mapping whose keys start at column k within [start, end), with
+    // the lines it spans up to its last one that isn't a comment or blank
+    fn fields(&self, start: usize, end: usize, k: usize) -> Vec<(&'a str, usize, usize)> {
+        let mut fields: Vec<(&str, usize, usize)> = Vec::new();
+        for at in start..end {
+            let line = self.lines[at];
+            if !is_content(line) {
+                continue;
+            }
+            match key_at(line, k) {
+                Some((key, _)) => fields.push((key, at, at + 1)),
+                None => {
+                    if let Some(field) = fields.last_mut() {
+                        field.2 = at + 1;
+                    }
+                }
+            }
+        }
+        fields
+    }
+
+    fn mapping(&mut self, start: usize, end: usize, k: usize, old: &Mapping, new: &Mapping) -> Option<()> {
+        let fields = self.fields(start, end, k);
+        let written = |key: &Value| key.as_str().is_some_and(|key| fields.iter().any(|field| field.0 == key));
+        if fields.len() != old.len() || !old.keys().all(written) {
+            return None;
+        }
+        for &(key, from, to) in &fields {
+            let was = old.get(key)?;
+            match new.get(key) {
+                Some(value) if value == was => {}
+                Some(value) => self.field(from, to, k, key, was, value)?,
+                // The first field of an item shares its line with the dash
+                None if self.lines[from].trim_start().starts_with('-') => return None,
+                None => self.edits.push(Edit {
+                    start: from,
+                    end: to,
+                    lines: Vec::new(),
+                }),
+            }
+        }
+        let after = fields.last()?.2;
+        for (key, value) in new.iter().filter(|(key, _)| !old.contains_key(*key)) {
+            let lines = render_field(key.as_str()?, value, k)?;
+            self.edits.push(Edit {
+                start: after,
+                end: after,
+                lines,
+            });
+        }
+        Some(())
+    }
+
+    // A changed field: only what changed inside a block mapping or a list of mappings, the
+    // whole field otherwise
+    fn field(&mut self, from: usize, to: usize, k: usize, key: &str, old: &Value, new: &Value) -> Option<()> {
+        let block = key_at(self.lines[from], k).is_some_and(|(_, inline)| inline.is_empty() || inline.starts_with('#'));
+        if block {
+            match (old, new) {
+                (Value::Mapping(old), Value::Mapping(new)) if !old.is_empty() => {
+                    let child = indent(self.lines[self.first_content(from + 1, to)?]);
+                    return match child > k {
+                        true => self.mapping(from + 1, to, child, old, new),
+                        false => None,
+                    };
+                }
+                (Value::Sequence(old), Value::Sequence(new)) if !old.is_empty() && old.iter().all(Value::is_mapping) => {
+                    return self.sequence(from + 1, to, old, new);
+                }
+                _ => {}
+            }
+        }
+        let mut lines = render_field(key, new, k)?;
+        lines[0] = format!("{}{}", &self.lines[from][..k], lines[0].trim_start());
+        self.edits.push(Edit { start: from, end: to, lines });
+        Some(())
+    }
+
+    fn sequence(&mut self, start: usize, end: usize, old: &[Value], new: &[Value]) -> Option<()> {
+        let s = indent(self.lines[self.first_content(start, end)?]);
+        let starts: Vec<usize> = (start..end)
+            .filter(|&at| is_content(self.lines[at]) && indent(self.lines[at]) == s && is_item(self.lines[at]))
+            .collect();
+        if starts.len() != old.len() {
+            return None;
+        }
+        // Each item up to its last line that isn't a comment or blank
+        let items: Vec<(usize, usize)> = starts
+            .iter()
+            .enumerate()
+            .map(|(at, &from)| {
+                let next = starts.get(at + 1).copied().unwrap_or(end);
+                let last = (from..next).rev().find(|&line| is_content(self.lines[line])).unwrap_or(from);
+                (from, last + 1)
+            })
+            .collect();
+        let lines = self.lines.clone();
+        let blank = |at: usize| lines[at].trim().is_empty();
+        let spaced = items.len() > 1 && (items[0].1..items[1].0).any(blank);
+
+        for pair in align(old, new) {
+            match pair {
+                (Some(was), Some(now)) if old[was] == new[now] => {}
+                (Some(was), Some(now)) => self.item(items[was], s, &old[was], &new[now])?,
+                (Some(was), None) => {
+                    let (mut from, mut to) = items[was];
+                    // The comments right above it are about it
+                    while from > start && lines[from - 1].trim_start().starts_with('#') && indent(lines[from - 1]) == s {
+                        from -= 1;
+                    }
+                    // So are the blank lines setting it apart from the next item, or from the one
+                    // before for the last
+                    if was + 1 < items.len() {
+                        while to < end && blank(to) {
+                            to += 1;
+                        }
+                    } else {
+                        while from > start && blank(from - 1) {
+                            from -= 1;
+                        }
+                    }
+                    self.edits.push(Edit {
+                        start: from,
+                        end: to,
+                        lines: Vec::new(),
+                    });
+                }
+                (None, Some(now)) => {
+                    let after = items.last()?.1;
+                    let mut lines = render_item(&new[now], s)?;
+                    if spaced {
+                        lines.insert(0, String::new());
+                    }
+                    self.edits.push(Edit {
+                        start: after,
+                        end: after,
+                        lines,
+                    });
+                }
+                (None, None) => {}
+            }
+        }
+        Some(())
+    }
+
+    fn item(&mut self, (from, to): (usize, usize), s: usize, old: &Value, new: &Value) -> Option<()> {
+        if let (Value::Mapping(old), Value::Mapping(new)) = (old, new) {
+            let dash = &self.lines[from][s + 1..];
+            let k = match dash.trim().is_empty() {
+                true => indent(self.lines[self.first_content(from + 1, to)?]),
+                false => s + 1 + indent(dash),
+            };
+            return self.mapping(from, to, k, old, new);
+        }
+        self.edits.push(Edit {
+            start: from,
+            end: to,
+            lines: render_item(new, s)?,
+        });
+        Some(())
+    }
+
+    fn apply(mut self, newline: &str, trailing: bool) -> Option<String> {
+        self.edits.sort_by_key(|edit| edit.start);
+        let mut lines: Vec<String> = Vec::new();
+        let mut at = 0;
+        for edit in self.edits {
+            if edit.start < at {
+                return None;
+            }
+            lines.extend(self.lines[at..edit.start].iter().map(|line| line.to_string()));
+            lines.extend(edit.lines);
+            at = edit.end;
+        }
+        lines.extend(self.lines[at..].iter().map(|line| line.to_string()));
+        let mut text = lines.join(newline);
+        if trailing {
+            text.push_str(newline);
+        }
+        Some(text)
+    }
+}
+
+// The config file's text changed only where the document it parses to differs from `new`:
+// comments, blank lines and every untouched line stay byte for byte. None when the change
+// can't be made that way, or the result wouldn't parse back to exactly `new`
+pub fn edit(current: &str, new: &Value) -> Option<String> {
+    let old: Value = serde_yaml::from_str(current).ok()?;
+    if old == *new {
+        return Some(current.to_string());
+    }
+    let (bom, body) = match current.strip_prefix('\u{feff}') {
+        Some(body) => ("\u{feff}", body),
+        None => ("", current),
+    };
+    let lines: Vec<&str> = body.lines().collect();
+    // Several documents, or markers around one, are beyond these edits
+    if lines.iter().any(|line| line.starts_with("---") || line.starts_with("...") || line.contains('\t')) {
+        return None;
+    }
+    let end = lines.len();
+    let mut text = Text {
+        lines,
+        edits: Vec::new(),
+    };
+    match (&old, new) {
+        (Value::Mapping(old), Value::Mapping(new)) => text.mapping(0, end, 0, old, new)?,
+        (Value::Sequence(old), Value::Sequence(new)) => text.sequence(0, end, old, new)?,
+        _ => return None,
+    }
+    let newline = if body.contains("\r\n") { "\r\n" } else { "\n" };
+    let edited = format!("{}{}", bom, text.apply(newline, body.ends_with('\n'))?);
+    (serde_yaml::from_str::<Value>(&edited).ok()? == *new).then_some(edited)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMENTED: &str = "\
+# Services on this machine
+startup_stagger: 2s   # between logon entries
+
+services:
+  # The web API
+  - name: api
+    type: Executable
+    path: C:\\api\\api.exe   # the current build
+    args: [--port, '8080']
+
+  # Nightly
+  - name: backup
+    type: Util
+    path: D:\\scripts\\backup.py
+    depends_on:
+      - api
+";
+
+    fn edited(change: impl FnOnce(&mut Value)) -> String {
+        let mut document: Value = serde_yaml::from_str(COMMENTED).unwrap();
+        change(&mut document);
+        edit(COMMENTED, &document).unwrap()
+    }
+
+    // The lines a line diff adds and removes, each in order
+    fn diff(before: &str, after: &str) -> (Vec<String>, Vec<String>) {
+        let (old, new): (Vec<&str>, Vec<&str>) = (before.lines().collect(), after.lines().collect());
+        // Longest common subsequence of the lines from each position on
+        let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                common[i][j] = match old[i] == new[j] {
+                    true => common[i + 1][j + 1] + 1,
+                    false => common[i + 1][j].max(common[i][j + 1]),
+                };
+            }
+        }
+        let (mut added, mut removed) = (Vec::new(), Vec::new());
+        let (mut i, mut j) = (0, 0);
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old[i] == new[j] {
+                (i, j) = (i + 1, j + 1);
+            } else if j < new.len() && (i == old.len() || common[i][j + 1] >= common[i + 1][j]) {
+                added.push(new[j].to_string());
+                j += 1;
+            } else {
+                removed.push(old[i].to_string());
+                i += 1;
+            }
+        }
+        (added, removed)
+    }
+
+    fn services(document: &mut Value) -> &mut Vec<Value> {
+        document["services"].as_sequence_mut().unwrap()
+    }
+
+    #[test]
+    fn setting_a_field_changes_only_its_line() {
+        let after = edited(|document| services(document)[1]["type"] = "Executable".into());
+        assert_eq!(diff(COMMENTED, &after), (vec!["    type: Executable".to_string()], vec!["    type: Util".to_string()]));
+        assert!(after.contains("    path: C:\\api\\api.exe   # the current build"));
+    }
+
+    #[test]
+    fn a_new_field_goes_after_the_last_of_its_entry() {
+        let after = edited(|document| services(document)[0]["restart"] = "always".into());
+        assert_eq!(diff(COMMENTED, &after), (vec!["    restart: always".to_string()], vec![]));
+        assert!(after.contains("    args: [--port, '8080']\n    restart: always\n\n  # Nightly"));
+    }
+
+    #[test]
+    fn an_added_service_follows_the_others_spaced_like_them() {
+        let after = edited(|document| {
+            let entry = serde_yaml::from_str("{name: web, type: Executable, path: C:\\web.exe}").unwrap();
+            services(document).push(entry);
+        });
+        assert!(after.starts_with(COMMENTED));
+        assert_eq!(&after[COMMENTED.len()..], "\n  - name: web\n    type: Executable\n    path: C:\\web.exe\n");
+    }
+
+    #[test]
+    fn removing_a_service_or_field_leaves_the_comments() {
+        let after = edited(|document| {
+            services(document).retain(|entry| entry["name"] != "api");
+        });
+        let (added, removed) = diff(COMMENTED, &after);
+        assert!(added.is_empty());
+        let api = [
+            "  # The web API",
+            "  - name: api",
+            "    type: Executable",
+            "    path: C:\\api\\api.exe   # the current build",
+            "    args: [--port, '8080']",
+            "",
+        ];
+        assert_eq!(removed, api);
+        assert!(after.contains("services:\n  # Nightly\n"));
+
+        let after = edited(|document| {
+            services(document)[1].as_mapping_mut().unwrap().remove("depends_on");
+        });
+        assert_eq!(diff(COMMENTED, &after), (vec![], vec!["    depends_on:".to_string(), "      - api".to_string()]));
+    }
+
+    #[test]
+    fn a_rename_touches_the_name_line_only() {
+        let after = edited(|document| services(document)[0]["name"] = "web".into());
+        assert_eq!(diff(COMMENTED, &after), (vec!["  - name: web".to_string()], vec!["  - name: api".to_string()]));
+    }
+
+    #[test]
+    fn what_the_text_edits_cant_reach_is_left_to_the_caller() {
+        let flow = "services: [{name: api, type: Executable, path: a.exe}]\n";
+        let mut document: Value = serde_yaml::from_str(flow).unwrap();
+        document["services"][0]["path"] = "b.exe".into();
+        let after = edit(flow, &document);
+        // Replaced as a whole, or not at all; never a file that reads differently
+        assert!(after.is_none_or(|after| serde_yaml::from_str::<Value>(&after).unwrap() == document));
+        assert!(edit("- a\n---\n- b\n", &Value::Null).is_none());
+    }
+}
@@ -0,0 +1,1003 @@
+// The svc binary against a throwaway config and state directory
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const CONFIG: &str = "- name: api
+  path: /opt/api/api.exe
+  type: Executable
+  args: [--port, '8080']
+  env:
+    MODE: prod
+- name: backup
+  path: backup.py
+  type: Util
+";
+
+fn sandbox(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("svc-cli-{}-{}", test, std::process::id()));
+    fs::create_dir_all(dir.join("state")).unwrap();
+    fs::write(dir.join("services.yaml"), CONFIG).unwrap();
+    dir
+}
+
+fn svc_command(dir: &Path, args: &[&str]) -> Command {
+    let mut path = vec![dir.join("bin")];
+    path.extend(std::env::var_os("PATH").iter().flat_map(std::env::split_paths));
+    let mut command = Command::new(env!("CARGO_BIN_EXE_svc"));
+    command
+        .args(args)
+        .env("SVC_CONFIG", dir.join("services.yaml"))
+        .env("LOCALAPPDATA", dir.join("state"))
+        .env("PATH", std::env::join_paths(path).unwrap())
+        .env_remove("SVC_READ_ONLY")
+        .env_remove("SVC_SAFE")
+        .env("NO_COLOR", "1");
+    command
+}
+
+fn svc(dir: &Path, args: &[&str]) -> Output {
+    svc_command(dir, args).output().unwrap()
+}
+
+// Stand-ins for the Windows tools status asks, where they don't exist: no processes, and
+// no start-up entries
+#[cfg(unix)]
+fn windows_tools(dir: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+    fs::create_dir_all(dir.join("bin")).unwrap();
+    let tools = [
+        ("tasklist", "#!/bin/sh\necho INFO\n"),
+        ("powershell", "#!/bin/sh\nexit 0\n"),
+        ("reg", "#!/bin/sh\nexit 1\n"),
+    ];
+    for (tool, script) in tools {
+        let path = dir.join("bin").join(tool);
+        fs::write(&path, script).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
+
+#[cfg(not(unix))]
+fn windows_tools(_dir: &Path) {}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn which_prints_the_command_line() {
+    let dir = sandbox("which");
+    let output = svc(&dir, &["which", "api"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output).trim(), "/opt/api/api.exe --port 8080");
+
+    let output = svc(&dir, &["--json", "which", "backup"]);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["program"], "python");
+    assert_eq!(report["args"][0], "backup.py");
+}
+
+#[test]
+fn env_shows_the_configured_variables() {
+    let dir = sandbox("env");
+    let output = svc(&dir, &["env", "api"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output).trim(), "MODE=prod");
+}
+
+#[test]
+fn unknown_service_fails_with_a_coded_error() {
+    let dir = sandbox("unknown");
+    let output = svc(&dir, &["which", "apj"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not found"));
+
+    let output = svc(&dir, &["--json", "run", "apj"]);
+    assert_eq!(output.status.code(), Some(1));
+    let report: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(report["error"]["code"], "service_not_found");
+}
+
+#[test]
+fn read_only_mode_refuses_mutations() {
+    let dir = sandbox("readonly");
+    let output = svc(&dir, &["--read-only", "run", "api"]);
+    assert_eq!(output.status.code(), Some(1));
+    let output = svc(&dir, &["--read-only", "which", "api"]);
+    assert!(output.status.success());
+    // Not even svc's own note of the config it used is written
+    assert!(!dir.join("state/svc/workspaces.txt").exists());
+}
+
+#[test]
+fn a_dry_run_lists_its_effects_and_changes_nothing() {
+    let dir = sandbox("dryrun");
+    windows_tools(&dir);
+    let output = svc(&dir, &["--dry-run", "set", "api", "restart", "always"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains(&format!("write {}", dir.join("services.yaml").display())));
+    assert_eq!(fs::read_to_string(dir.join("services.yaml")).unwrap(), CONFIG);
+
+    // The plan follows whatever the command itself printed
+    let output = svc(&dir, &["--json", "--dry-run", "enable", "api"]);
+    let text = stdout(&output);
+    let plan: serde_json::Value = serde_json::from_str(&text[text.find("{\n").unwrap()..]).unwrap();
+    assert_eq!(plan["effects"].as_array().unwrap().len(), 1);
+    assert_eq!(plan["effects"][0]["effect"], "set_registry_value");
+    assert_eq!(plan["effects"][0]["name"], "svc:api");
+
+    let output = svc(&dir, &["--read-only", "--dry-run", "run", "api"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("start api"));
+}
+
+#[test]
+fn safe_mode_ignores_poisoned_state() {
+    let dir = sandbox("safe");
+    windows_tools(&dir);
+    fs::create_dir_all(dir.join("state/svc/services/api")).unwrap();
+    fs::write(dir.join("state/svc/services/api/restart.pid"), "not a pid").unwrap();
+
+    let output = svc(&dir, &["status", "api"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--safe"));
+
+    let output = svc(&dir, &["--safe", "status", "api"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("not running"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("safe mode"));
+}
+
+// The dependency takes a second before it execs its real process, and the tasklist stand-in
+// never lists it, so only the handle kept from the launch tells that it started
+#[cfg(unix)]
+#[test]
+fn a_dependency_started_here_is_verified_by_its_handle() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = sandbox("handle");
+    windows_tools(&dir);
+    let slow = dir.join("slow-start.sh");
+    fs::write(&slow, "#!/bin/sh\nsleep 1\nexec sleep 2\n").unwrap();
+    fs::set_permissions(&slow, fs::Permissions::from_mode(0o755)).unwrap();
+    let config = format!(
+        "- name: slow\n  path: {}\n  type: Executable\n\
+         - name: app\n  path: /bin/true\n  type: Executable\n  depends_on: [slow]\n",
+        slow.display()
+    );
+    fs::write(dir.join("services.yaml"), config).unwrap();
+
+    let started = std::time::Instant::now();
+    let output = svc(&dir, &["run", "app"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout(&output).contains("Starting slow"));
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+}
+
+// Ctrl+C in the middle of the second of three one-second utilities: it finishes, the third
+// isn't started, and resume-last runs only that one
+#[cfg(unix)]
+#[test]
+fn an_interrupted_bulk_run_is_resumed_where_it_stopped() {
+    let dir = sandbox("resume");
+    windows_tools(&dir);
+    let slow = dir.join("slow.sh");
+    fs::write(&slow, "sleep 1\n").unwrap();
+    let config: String = ["one", "two", "three"]
+        .iter()
+        .map(|name| {
+            format!(
+                "- {{ name: {}, path: {}, type: Util, interpreter: sh, tags: [batch] }}\n",
+                name,
+                slow.display()
+            )
+        })
+        .collect();
+    fs::write(dir.join("services.yaml"), config).unwrap();
+
+    let child = svc_command(&dir, &["run", "--tag", "batch"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(1400));
+    Command::new("kill").args(["-INT", &child.id().to_string()]).status().unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(130), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout(&output).contains("not attempted"));
+
+    let checkpoint: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(dir.join("state/svc/last-bulk.json")).unwrap()).unwrap();
+    let left: Vec<&str> = checkpoint["services"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|entry| entry["progress"] != "succeeded")
+        .map(|entry| entry["service"].as_str().unwrap())
+        .collect();
+    assert!(!left.is_empty() && !left.contains(&"one"), "{}", checkpoint);
+
+    let output = svc(&dir, &["resume-last"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout(&output).contains(&format!("for {}.", left.join(", "))));
+    assert!(stdout(&output).contains(&format!("{} of {} succeeded", left.len(), left.len())));
+    assert!(stdout(&svc(&dir, &["resume-last"])).contains("Nothing to resume"));
+}
+
+// A reg stand-in that keeps values as files and refuses to write backup's: enabling both puts
+// api's value in, then takes it out again once backup fails
+#[cfg(unix)]
+#[test]
+fn a_failed_batch_enable_rolls_back_what_it_changed() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = sandbox("rollback");
+    windows_tools(&dir);
+    let reg = format!(
+        "#!/bin/sh
+d=\"{}/$(echo \"$2\" | tr '\\\\' _)\"
+case \"$1\" in
+\
+         query) [ -f \"$d/$4\" ] || exit 1; printf '    %s    REG_SZ    %s\\n' \"$4\" \"$(cat \"$d/$4\")\";;
+\
+         add) case \"$4\" in *backup) echo access denied >&2; exit 1;; esac; mkdir -p \"$d\"; printf %s \"$8\" > \"$d/$4\";;
+\
+         delete) rm -f \"$d/$4\";;
+esac
+",
+        dir.join("reg").display()
+    );
+    fs::write(dir.join("bin/reg"), reg).unwrap();
+    fs::set_permissions(dir.join("bin/reg"), fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = svc(&dir, &["enable", "--all", "--rollback-on-failure"]);
+    assert_eq!(output.status.code(), Some(1));
+    let text = stdout(&output);
+    assert!(text.contains("Service api enabled."), "{}", text);
+    assert!(text.contains("api rolled back"), "{}", text);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("backup failed"));
+    assert!(stdout(&svc(&dir, &["status", "api"])).contains("Start-up: disabled"));
+
+    // Without the flag api's entry stays
+    svc(&dir, &["enable", "--all"]);
+    assert!(!stdout(&svc(&dir, &["status", "api"])).contains("Start-up: disabled"));
+}
+
+// One enricher answers, one never does; status still comes out, with the first one's facts
+#[cfg(unix)]
+#[test]
+fn enrichers_add_facts_and_a_stuck_one_is_only_a_warning() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = sandbox("enrichers");
+    windows_tools(&dir);
+    for (name, script) in [
+        ("deployinfo", "#!/bin/sh\ncat >/dev/null\necho '{\"version\": \"1.4.2\"}'\n"),
+        ("stuck", "#!/bin/sh\nsleep 10\n"),
+    ] {
+        let path = dir.join(name);
+        fs::write(&path, script).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let config = format!(
+        "enrichers:\n  - command: {}\n  - command: {}\n    timeout: 300ms\nservices:\n{}",
+        dir.join("deployinfo").display(),
+        dir.join("stuck").display(),
+        CONFIG.lines().map(|line| format!("  {}\n", line)).collect::<String>()
+    );
+    fs::write(dir.join("services.yaml"), config).unwrap();
+
+    let output = svc(&dir, &["--json", "status", "api"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["extra"]["version"], "1.4.2");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("enricher stuck did not answer"));
+
+    let output = svc(&dir, &["--no-enrichers", "status", "api"]);
+    assert!(!stdout(&output).contains("Extra:"));
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn validate_reports_missing_paths() {
+    let dir = sandbox("validate");
+    let output = svc(&dir, &["validate"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).contains("/opt/api/api.exe does not exist"));
+}
+
+#[test]
+fn a_bundle_runs_from_wherever_it_is_moved() {
+    let dir = sandbox("bundle");
+    fs::create_dir_all(dir.join("tools/lib")).unwrap();
+    fs::write(dir.join("tools/backup.py"), "print('backed up')\n").unwrap();
+    fs::write(dir.join("tools/lib/helpers.py"), "").unwrap();
+    let config = format!(
+        "- name: backup\n  path: {}\n  type: Util\n  work_at: {}\n",
+        dir.join("tools/backup.py").display(),
+        dir.display()
+    );
+    fs::write(dir.join("services.yaml"), config).unwrap();
+
+    let output = svc(&dir, &["bundle", "backup", "--out", dir.join("out").to_str().unwrap(), "--whole-dir"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout(&output).contains("python isn't bundled"), "{}", stdout(&output));
+    assert!(stdout(&output).contains("work_at is left out"));
+    assert!(fs::read_to_string(dir.join("out/run.cmd")).unwrap().contains("\r\n"));
+
+    fs::rename(dir.join("out"), dir.join("moved")).unwrap();
+    let config = dir.join("moved/services.yaml");
+    let output = svc_command(&dir, &["--config", config.to_str().unwrap(), "--json", "which", "backup"])
+        .current_dir(std::env::temp_dir())
+        .output()
+        .unwrap();
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let script = dir.join("moved/tools/backup.py");
+    assert_eq!(report["args"][0], script.to_str().unwrap());
+    assert!(dir.join("moved/tools/lib/helpers.py").is_file());
+
+    let output = svc(&dir, &["bundle", "backup", "--out", dir.join("moved").to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn progress_events_end_with_the_result_even_on_error() {
+    let dir = sandbox("progress-events");
+    let output = svc(&dir, &["--json", "--progress-events", "which", "api"]);
+    assert!(output.status.success());
+    let last: serde_json::Value = serde_json::from_str(stdout(&output).lines().last().unwrap()).unwrap();
+    assert_eq!(last["event"], "result");
+    assert_eq!(last["ok"], true);
+
+    let output = svc(&dir, &["--json", "--progress-events", "kill", "apj"]);
+    assert_eq!(output.status.code(), Some(1));
+    let last: serde_json::Value = serde_json::from_str(stdout(&output).lines().last().unwrap()).unwrap();
+    assert_eq!((last["seq"].as_u64(), last["ok"].as_bool()), (Some(1), Some(false)));
+    assert_eq!(last["error"]["code"], "service_not_found");
+
+    // Consumers expecting a single object are left alone without the flag
+    let output = svc(&dir, &["--json", "which", "api"]);
+    assert!(!stdout(&output).contains("\"event\""));
+}
+
+#[test]
+fn recover_clears_what_an_svc_that_exited_left_behind() {
+    let dir = sandbox("recover");
+    windows_tools(&dir);
+    // A helper that exits without cleaning up, as a crashed watcher would
+    let mut helper = Command::new(env!("CARGO_BIN_EXE_svc")).arg("--version").spawn().unwrap();
+    helper.wait().unwrap();
+    fs::create_dir_all(dir.join("state/svc")).unwrap();
+    fs::write(dir.join("state/svc/watch.pid"), helper.id().to_string()).unwrap();
+    fs::write(dir.join("state/svc/watch.interval"), "5s").unwrap();
+    fs::write(dir.join("state/svc/api.restart.pid"), "not a pid").unwrap();
+    fs::write(dir.join("state/svc/history.jsonl"), "").unwrap();
+
+    let output = svc(&dir, &["--dry-run", "recover"]);
+    assert!(stdout(&output).contains("Would clean up 3 stale item(s)"), "{}", stdout(&output));
+    assert!(dir.join("state/svc/watch.pid").exists());
+
+    let output = svc(&dir, &["recover"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let text = stdout(&output);
+    assert!(text.contains(&format!("the background watcher (PID {}) is gone", helper.id())), "{}", text);
+    assert!(text.contains("api.restart.pid"));
+    assert!(!dir.join("state/svc/watch.pid").exists());
+    assert!(!dir.join("state/svc/watch.interval").exists());
+    assert!(dir.join("state/svc/history.jsonl").exists());
+
+    let output = svc(&dir, &["--json", "recover"]);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["cleaned"], serde_json::json!([]));
+}
+
+#[test]
+fn a_launcher_is_the_same_each_time_it_is_exported() {
+    let dir = sandbox("launcher");
+    let first = svc(&dir, &["export-launcher", "api"]);
+    assert!(first.status.success(), "{}", String::from_utf8_lossy(&first.stderr));
+    assert!(stdout(&first).contains("start \"\" /min /opt/api/api.exe --port 8080\r\n"), "{}", stdout(&first));
+    assert_eq!(stdout(&svc(&dir, &["export-launcher", "api"])), stdout(&first));
+
+    let out = dir.join("api.ps1");
+    let output = svc(&dir, &["export-launcher", "api", "--style", "ps1", "--out", out.to_str().unwrap()]);
+    assert!(output.status.success());
+    let script = fs::read_to_string(&out).unwrap();
+    assert!(script.starts_with('\u{feff}'));
+    assert!(script.contains("    ArgumentList = '--port 8080'"));
+}
+
+#[test]
+fn edits_leave_comments_and_untouched_lines_as_they_were() {
+    let dir = sandbox("comments");
+    windows_tools(&dir);
+    let commented = "\
+# Services of this machine
+
+services:
+  # The API
+  - name: api
+    type: Executable
+    path: /opt/api/api.exe   # the current build
+    args: [--port, '8080']
+
+  # Nightly
+  - name: backup
+    type: Util
+    path: backup.py
+";
+    fs::write(dir.join("services.yaml"), commented).unwrap();
+    let read = || fs::read_to_string(dir.join("services.yaml")).unwrap();
+
+    assert!(svc(&dir, &["set", "api", "restart", "always"]).status.success());
+    let set = commented.replace("'8080']\n", "'8080']\n    restart: always\n");
+    assert_eq!(read(), set);
+
+    let output = svc(&dir, &["add", "web", "--path", "/opt/web/web.exe"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(read(), format!("{}\n  - name: web\n    type: Executable\n    path: /opt/web/web.exe\n", set));
+
+    let output = svc(&dir, &["remove", "web"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(read(), set);
+}
+
+#[cfg(unix)]
+#[test]
+fn server_core_refuses_the_startup_folder_by_name() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = sandbox("server-core");
+    windows_tools(&dir);
+    let reg = "#!/bin/sh\n[ \"$4\" = InstallationType ] || exit 1\necho '    InstallationType    REG_SZ    Server Core'\n";
+    fs::write(dir.join("bin/reg"), reg).unwrap();
+    fs::set_permissions(dir.join("bin/reg"), fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = svc(&dir, &["--json", "enable", "api", "--backend", "startup-folder"]);
+    assert_eq!(output.status.code(), Some(1));
+    let report: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(report["error"]["code"], "feature_unavailable");
+
+    let output = svc(&dir, &["--json", "doctor"]);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["platform"]["flavor"], "server-core");
+    let features = report["platform"]["features"].as_array().unwrap();
+    let toasts = features.iter().find(|feature| feature["feature"] == "toasts").unwrap();
+    assert_eq!(toasts["available"], false);
+    assert!(toasts["fallback"].is_string());
+}
+
+#[cfg(unix)]
+#[test]
+fn statuses_come_out_in_config_order_however_they_were_named() {
+    use std::io::Write;
+    use std::process::Stdio;
+    let dir = sandbox("order");
+    windows_tools(&dir);
+    let config = ["zeta", "alpha", "mid"]
+        .map(|name| format!("- name: {}\n  path: /opt/{}/{}.exe\n  type: Executable\n", name, name, name))
+        .concat();
+    fs::write(dir.join("services.yaml"), config).unwrap();
+
+    let names = |args: &[&str]| -> Vec<String> {
+        let mut child = svc_command(&dir, args).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+        child.stdin.take().unwrap().write_all(b"mid\nzeta\nalpha\n").unwrap();
+        let output = child.wait_with_output().unwrap();
+        let reports: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout).unwrap();
+        reports.iter().map(|report| report["name"].as_str().unwrap().to_string()).collect()
+    };
+    for _ in 0..3 {
+        assert_eq!(names(&["--json", "status", "--stdin"]), ["zeta", "alpha", "mid"]);
+    }
+    assert_eq!(names(&["--json", "status", "--stdin", "--sort", "name"]), ["alpha", "mid", "zeta"]);
+    // Nothing runs, so every service ties on state and keeps its config order
+    assert_eq!(names(&["--json", "status", "--stdin", "--sort", "state"]), ["zeta", "alpha", "mid"]);
+    assert_eq!(names(&["--json", "list", "--sort", "name"]), ["alpha", "mid", "zeta"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn lenient_loading_skips_broken_entries_of_the_config_and_what_it_extends() {
+    let dir = sandbox("lenient");
+    windows_tools(&dir);
+    fs::create_dir_all(dir.join("appdata/svc")).unwrap();
+    let global = "- { name: db, path: /opt/db/db.exe, type: Executable }\n\
+                  - { name: cache, path: /opt/cache/cache.exe }\n";
+    fs::write(dir.join("appdata/svc/services.yaml"), global).unwrap();
+    let workspace = "extends: [db, cache]\n\
+                     services:\n\
+                     \x20 - { name: api, path: /opt/api/api.exe, type: Executable }\n\
+                     \x20 - { name: web, path: /opt/web/web.exe, type: Daemon }\n\
+                     \x20 - { name: worker, path: /opt/w/w.exe, type: Executable, depends_on: [web] }\n";
+    fs::write(dir.join(".svc.yaml"), workspace).unwrap();
+    let run = |args: &[&str]| {
+        svc_command(&dir, args)
+            .env("SVC_CONFIG", dir.join(".svc.yaml"))
+            .env("APPDATA", dir.join("appdata"))
+            .output()
+            .unwrap()
+    };
+    let stderr = |output: &Output| String::from_utf8_lossy(&output.stderr).into_owned();
+
+    let output = run(&["--json", "status", "--all"]);
+    assert!(!output.status.success());
+
+    let output = run(&["--lenient", "--json", "status", "--all"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let reports: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout).unwrap();
+    let names: Vec<&str> = reports.iter().map(|report| report["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["api", "db"]);
+    let banner = stderr(&output);
+    assert!(banner.contains("lenient mode skipped 3 config entries (web, cache, worker)"), "{}", banner);
+
+    // A skipped name is reported with what is wrong with its entry, not as unknown
+    let output = run(&["--lenient", "status", "web"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Service web in the config is invalid"), "{}", stderr(&output));
+    assert!(stderr(&output).contains("Daemon"), "{}", stderr(&output));
+
+    let output = run(&["--lenient", "validate", "--offline"]);
+    let text = stdout(&output);
+    assert!(text.contains("error: worker: skipped: "), "{}", text);
+    assert!(text.contains("error: cache: skipped (in "), "{}", text);
+
+    fs::write(dir.join(".svc.yaml"), format!("lenient: true\n{}", workspace)).unwrap();
+    assert!(run(&["--json", "status", "--all"]).status.success());
+}
+
+#[test]
+fn env_startup_shows_what_a_logon_launch_gets() {
+    let dir = sandbox("env-startup");
+    fs::write(
+        dir.join("services.yaml"),
+        "- name: api
+  path: /opt/api/api.exe
+  type: Executable
+  startup_env_allowlist: [SDK_ROOT, MODE, PROFILE]
+  env:
+    MODE: prod
+    SDK_BIN: '%SDK_ROOT%/bin'
+",
+    )
+    .unwrap();
+    let output = svc(&dir, &["env", "api", "--startup"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("nothing captured yet"));
+
+    // As enable leaves it: MODE is configured too, so the configured value wins
+    let captured = dir.join("state").join("svc").join("services").join("api");
+    fs::create_dir_all(&captured).unwrap();
+    fs::write(
+        captured.join("startup-env.json"),
+        concat!(
+            r#"{"captured": "2026-10-14 09:00:00", "variables": {"SDK_ROOT": "/sdk", "MODE": "dev"},"#,
+            r#" "unset": ["PROFILE"]}"#
+        ),
+    )
+    .unwrap();
+    let output = svc(&dir, &["env", "api", "--startup"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "SDK_ROOT=/sdk\nMODE=prod\nSDK_BIN=/sdk/bin\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("when PROFILE unset"));
+
+    let output = svc(&dir, &["--json", "env", "api", "--startup"]);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["variables"]["SDK_ROOT"], "/sdk");
+    assert_eq!(report["unset"][0], "PROFILE");
+    assert!(!svc(&dir, &["env", "api", "--startup", "--live"]).status.success());
+}
+
+#[test]
+fn du_enforces_the_disk_budget_only_inside_svcs_directories() {
+    let dir = sandbox("du");
+    let outside = dir.join("outside");
+    fs::create_dir_all(&outside).unwrap();
+    fs::write(
+        dir.join("services.yaml"),
+        format!(
+            "- name: api\n  path: /opt/api/api.exe\n  type: Executable\n  disk_budget: 700B\n  log: {}\n",
+            outside.join("api.log").display()
+        ),
+    )
+    .unwrap();
+    let state = dir.join("state").join("svc");
+    let own = state.join("services").join("api");
+    fs::create_dir_all(&own).unwrap();
+    let files = [
+        (own.join("metrics.jsonl"), 600),
+        (own.join("metrics.jsonl.prev"), 100),
+        (own.join("restart.pid"), 10),
+        (state.join("adhoc-api.log"), 300),
+        (state.join("adhoc-api.log.1"), 400),
+        (outside.join("api.log"), 5000),
+        (outside.join("api.log.1"), 5000),
+    ];
+    for (path, bytes) in &files {
+        fs::write(path, "x".repeat(*bytes)).unwrap();
+    }
+    // Kept from failed runs, the older first
+    let scratch = dir.join("tmp").join("svc").join("api");
+    for run in ["run-a", "run-b"] {
+        fs::create_dir_all(scratch.join(run)).unwrap();
+        fs::write(scratch.join(run).join("out.txt"), "x".repeat(300)).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    let du = |args: &[&str]| {
+        let mut command = svc_command(&dir, args);
+        command.env("TMPDIR", dir.join("tmp")).env("TEMP", dir.join("tmp"));
+        command.output().unwrap()
+    };
+
+    let output = du(&["--json", "du"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report[0]["bytes"], 2010);
+    assert_eq!(report[0]["over"], true);
+
+    let output = du(&["--json", "du", "--enforce"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let pruned = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(pruned.matches("Pruned ").count(), 3, "{}", pruned);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report[0]["over"], false);
+
+    // Metrics first, then the rotated log, then the older failed run; the live log, the
+    // newest failed run, the rest of the state and anything outside svc's directories stay
+    let gone = [&files[0].0, &files[1].0, &files[4].0];
+    for (path, _) in &files {
+        assert_eq!(path.exists(), !gone.contains(&path), "{}", path.display());
+    }
+    assert!(!scratch.join("run-a").exists());
+    assert!(scratch.join("run-b").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn adhoc_services_are_found_by_name_until_forgotten() {
+    let dir = sandbox("adhoc");
+    windows_tools(&dir);
+    let script = dir.join("thing.sh");
+    fs::write(&script, "#!/bin/sh\necho hi\n").unwrap();
+    let stderr = |output: &Output| String::from_utf8_lossy(&output.stderr).into_owned();
+
+    let output = svc(&dir, &["run-adhoc", "--path", script.to_str().unwrap(), "--util", "--interpreter", "sh"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    // The bare-list config doesn't have it, so the lookup has to go on to the adhoc registry
+    let output = svc(&dir, &["kill", "thing"]);
+    assert!(stderr(&output).contains("not running"), "{}", stderr(&output));
+    let output = svc(&dir, &["status", "thing"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("thing (adhoc)"));
+
+    assert!(svc(&dir, &["forget", "thing"]).status.success());
+    for command in ["kill", "status"] {
+        let output = svc(&dir, &[command, "thing"]);
+        assert!(stderr(&output).contains("not found"), "{}: {}", command, stderr(&output));
+    }
+}
+
+#[test]
+fn validate_looks_for_a_relative_script_in_the_working_directory() {
+    let dir = sandbox("validate-relative");
+    fs::create_dir_all(dir.join("jobs")).unwrap();
+    fs::write(dir.join("jobs/nightly.py"), "print('done')\n").unwrap();
+    let config = format!(
+        "- name: nightly\n  path: nightly.py\n  type: Util\n  interpreter: sh\n  work_at: {}\n",
+        dir.join("jobs").display()
+    );
+    fs::write(dir.join("services.yaml"), config).unwrap();
+
+    // svc's own directory doesn't have it; the one the script runs in does
+    let output = svc_command(&dir, &["validate"]).current_dir(&dir).output().unwrap();
+    assert!(!stdout(&output).contains("does not exist"), "{}", stdout(&output));
+
+    fs::remove_file(dir.join("jobs/nightly.py")).unwrap();
+    let output = svc_command(&dir, &["validate"]).current_dir(&dir).output().unwrap();
+    let missing = format!("{} does not exist", dir.join("jobs").join("nightly.py").display());
+    assert!(stdout(&output).contains(&missing), "{}", stdout(&output));
+}
+
+#[cfg(unix)]
+#[test]
+fn list_shows_labels_in_columns_aligned_as_the_console_draws_them() {
+    let dir = sandbox("labels");
+    windows_tools(&dir);
+    let config = "- { name: api, label: \"📦 接口服务\", path: /opt/api/api.exe, type: Executable }\n\
+                  - { name: database, label: База, path: /opt/db/db.exe, type: Executable }\n";
+    fs::write(dir.join("services.yaml"), config).unwrap();
+
+    let output = svc(&dir, &["list"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let text = stdout(&output);
+    let lines: Vec<&str> = text.lines().filter(|line| line.contains("Executable") || line.starts_with("NAME")).collect();
+    assert!(lines[0].starts_with("NAME      LABEL"), "{}", text);
+    // The emoji and CJK each take two columns, so TYPE starts in the same column on every row
+    let column = |line: &str, word: &str| {
+        let before = &line[..line.find(word).unwrap()];
+        before.chars().map(|c| if c as u32 >= 0x2E80 { 2 } else { 1 }).sum::<usize>()
+    };
+    let starts = [column(lines[0], "TYPE"), column(lines[1], "Executable"), column(lines[2], "Executable")];
+    assert!(starts.iter().all(|&start| start == starts[0]), "{}", text);
+
+    let output = svc(&dir, &["--json", "list"]);
+    let reports: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(reports[0]["label"], "📦 接口服务");
+    assert_eq!(reports[1]["label"], "База");
+}
+
+#[cfg(unix)]
+#[test]
+fn relocate_restart_leaves_a_stopped_service_stopped() {
+    let dir = sandbox("relocate-restart");
+    windows_tools(&dir);
+    fs::create_dir_all(dir.join("apps/api")).unwrap();
+    fs::write(dir.join("apps/api/api.exe"), "").unwrap();
+
+    let search = dir.join("apps");
+    let output = svc(&dir, &["relocate", "api", "--search", search.to_str().unwrap(), "--pick", "1", "--restart"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout(&output).contains("api wasn't running, so it wasn't started."), "{}", stdout(&output));
+    assert!(fs::read_to_string(dir.join("services.yaml")).unwrap().contains("apps/api/api.exe"));
+}
+
+// The headers pe::inspect reads, of an x64 program for `subsystem` (2 GUI, 3 console)
+fn pe_stub(path: &Path, subsystem: u8) {
+    let mut bytes = vec![0u8; 64 + 4 + 20 + 70];
+    bytes[..2].copy_from_slice(b"MZ");
+    bytes[0x3c] = 64;
+    bytes[64..68].copy_from_slice(b"PE\0\0");
+    bytes[68..70].copy_from_slice(&0x8664u16.to_le_bytes());
+    bytes[64 + 24 + 68] = subsystem;
+    fs::write(path, bytes).unwrap();
+}
+
+#[test]
+fn which_and_validate_tell_how_a_console_program_is_stopped() {
+    let dir = sandbox("which-subsystem");
+    pe_stub(&dir.join("tool.exe"), 3);
+    pe_stub(&dir.join("app.exe"), 2);
+    let config = format!(
+        "- {{ name: tool, path: {}, type: Executable }}\n- {{ name: app, path: {}, type: Executable }}\n",
+        dir.join("tool.exe").display(),
+        dir.join("app.exe").display()
+    );
+    fs::write(dir.join("services.yaml"), config).unwrap();
+
+    let output = svc(&dir, &["which", "tool"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("x64 console binary"), "{}", stderr);
+    assert!(stderr.contains("stop: terminated, a console program has no window to close"), "{}", stderr);
+    let output = svc(&dir, &["--json", "which", "app"]);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["binary"]["subsystem"], "GUI");
+    assert!(report["stop"].as_str().unwrap().starts_with("its windows closed"));
+
+    let output = svc(&dir, &["validate"]);
+    let text = stdout(&output);
+    assert!(text.contains("tool.exe is a console program and there is no stop_signal"), "{}", text);
+    assert!(!text.contains("app.exe is a console program"), "{}", text);
+}
+
+#[test]
+fn which_names_the_host_override_it_applied() {
+    let dir = sandbox("which-host");
+    let config = "services:\n  - { name: api, path: /opt/api/api.exe, type: Executable }\n\
+                  hosts:\n  LAPTOP:\n    api:\n      path: /srv/api/api.exe\n      args: [--dev]\n";
+    fs::write(dir.join("services.yaml"), config).unwrap();
+
+    let output = svc(&dir, &["--host", "laptop", "which", "api"]);
+    assert_eq!(stdout(&output).trim(), "/srv/api/api.exe --dev");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("host override: path, args (hosts.LAPTOP)"), "{}", stderr);
+
+    let output = svc(&dir, &["--host", "laptop", "--json", "which", "api"]);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["host_override"]["host"], "LAPTOP");
+    assert_eq!(report["host_override"]["fields"], serde_json::json!(["path", "args"]));
+
+    let output = svc(&dir, &["--host", "desktop", "which", "api"]);
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("host override"));
+}
+
+#[test]
+fn help_version_and_init_leave_the_config_unread() {
+    let dir = sandbox("no-config-load");
+    let config = "- { name: api, path: /opt/api/api.exe, type: Executable }\n- { name: b, type: Bogus }\n";
+    fs::write(dir.join("services.yaml"), config).unwrap();
+    let notes = |args: &[&str]| String::from_utf8_lossy(&svc(&dir, args).stderr).into_owned();
+
+    // Lenient loading notes what it skipped, so the note shows whether the config was read
+    for args in [&["version"][..], &["--version"], &["help"], &["run", "--help"]] {
+        let args = [&["--lenient"], args].concat();
+        assert!(!notes(&args).contains("lenient mode skipped"), "{:?}", args);
+    }
+    assert!(notes(&["--lenient", "which", "api"]).contains("lenient mode skipped 1 config entry (b)"));
+}
+
+#[test]
+fn no_services_point_at_add_and_init() {
+    let dir = sandbox("no-services");
+    fs::write(dir.join("services.yaml"), "services: []\n").unwrap();
+    let output = svc(&dir, &["validate"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let text = stdout(&output);
+    assert!(text.contains("0 services"), "{}", text);
+    assert!(text.contains("'svc add' appends one, 'svc init' writes a commented starter config"), "{}", text);
+}
+
+#[cfg(unix)]
+#[test]
+fn stop_takes_names_on_stdin() {
+    use std::io::Write;
+    use std::process::Stdio;
+    let dir = sandbox("stop-stdin");
+    windows_tools(&dir);
+    let mut child = svc_command(&dir, &["stop", "--stdin"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"api\n# skipped\nnope\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // Each name is tried and reported on its own, the unknown one at the end
+    assert!(!output.status.success());
+    assert!(stderr.contains("api"), "{}", stderr);
+    assert!(stderr.contains("not running"), "{}", stderr);
+    assert!(stderr.contains("nope"), "{}", stderr);
+
+    let output = svc(&dir, &["stop"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("required"));
+}
+
+#[test]
+fn fmt_keeps_a_commented_config_unless_told_to_drop_the_comments() {
+    let dir = sandbox("fmt-comments");
+    let config = "# lab services\n- name: api   # the web API\n  type: Executable\n  path: /opt/api/api.exe\n";
+    fs::write(dir.join("services.yaml"), config).unwrap();
+
+    let output = svc(&dir, &["fmt"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("drop 2 comment(s)"), "{}", stderr);
+    assert_eq!(fs::read_to_string(dir.join("services.yaml")).unwrap(), config);
+
+    let output = svc(&dir, &["fmt", "--drop-comments"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("2 comment(s) dropped"));
+    assert!(!fs::read_to_string(dir.join("services.yaml")).unwrap().contains('#'));
+}
+
+#[test]
+fn json_version_lists_every_registered_subcommand() {
+    use clap::CommandFactory;
+    fn names(command: &clap::Command) -> Vec<String> {
+        let mut names = Vec::new();
+        for sub in command.get_subcommands() {
+            names.push(sub.get_name().to_string());
+            names.extend(names_under(sub));
+        }
+        names
+    }
+    fn names_under(command: &clap::Command) -> Vec<String> {
+        names(command).into_iter().map(|name| format!("{} {}", command.get_name(), name)).collect()
+    }
+    fn listed(commands: &serde_json::Value, prefix: &str) -> Vec<String> {
+        let mut names = Vec::new();
+        for command in commands.as_array().unwrap() {
+            let name = format!("{}{}", prefix, command["name"].as_str().unwrap());
+            names.push(name.clone());
+            if let Some(subcommands) = command.get("subcommands") {
+                names.extend(listed(subcommands, &format!("{} ", name)));
+            }
+        }
+        names
+    }
+
+    let dir = sandbox("version-json");
+    let output = svc(&dir, &["--json", "version"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let capabilities: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(capabilities["version"], env!("CARGO_PKG_VERSION"));
+
+    // Nested ones too, each under its parent
+    let mut expected = names(&svc::cli::Cli::command());
+    let mut reported = listed(&capabilities["commands"], "");
+    expected.sort();
+    reported.sort();
+    assert_eq!(reported, expected);
+    assert!(reported.iter().any(|name| name == "config migrate"), "{:?}", reported);
+}
+
+// The script leaves where.txt wherever it was run
+#[cfg(unix)]
+#[test]
+fn run_takes_the_legacy_at_dir_form_and_refuses_it_with_at_flag() {
+    let dir = sandbox("run-at");
+    windows_tools(&dir);
+    let script = dir.join("where.sh");
+    fs::write(&script, "pwd > where.txt\n").unwrap();
+    fs::write(
+        dir.join("services.yaml"),
+        format!("- {{ name: job, path: {}, type: Util, interpreter: sh }}\n", script.display()),
+    )
+    .unwrap();
+    let (legacy, flag) = (dir.join("legacy"), dir.join("flag"));
+    fs::create_dir_all(&legacy).unwrap();
+    fs::create_dir_all(&flag).unwrap();
+
+    let output = svc(&dir, &["run", "job", "at", legacy.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(legacy.join("where.txt").is_file());
+    let output = svc(&dir, &["run", "job", "--at", flag.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(flag.join("where.txt").is_file());
+
+    let output = svc(&dir, &["run", "job", "at", legacy.to_str().unwrap(), "--at", flag.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be used with"));
+    // Only `at` introduces a directory
+    let output = svc(&dir, &["run", "job", "in", legacy.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&format!("in {}", legacy.display())));
+    let output = svc(&dir, &["run", "job", "at", dir.join("missing").to_str().unwrap()]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("does not exist"));
+}
+
+#[test]
+fn adjust_refuses_a_cpu_past_the_affinity_mask_before_persisting_it() {
+    let dir = sandbox("affinity-range");
+    windows_tools(&dir);
+    let output = svc(&dir, &["--json", "adjust", "api", "--affinity", "0,64", "--persist"]);
+    assert!(!output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(report["error"]["code"], "affinity_out_of_range");
+    assert_eq!(fs::read_to_string(dir.join("services.yaml")).unwrap(), CONFIG);
+}
+
+// Without --force a bulk stop skips a protected service instead of asking about it or failing
+#[test]
+fn bulk_stops_skip_protected_services_without_force() {
+    let dir = sandbox("protected-bulk");
+    windows_tools(&dir);
+    fs::write(
+        dir.join("services.yaml"),
+        "- { name: db, path: /opt/db/db.exe, type: Executable, protected: true, tags: [data] }\n",
+    )
+    .unwrap();
+
+    let output = svc(&dir, &["stop", "--tag", "data"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let text = stdout(&output);
+    assert!(text.contains("skipped: Service db is protected"), "{}", text);
+    assert!(text.contains("0 of 1 succeeded, 1 protected, skipped, 0 failed"), "{}", text);
+
+    let output = svc(&dir, &["down"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let text = stdout(&output);
+    assert!(text.contains("protected") && text.contains("1 protected, skipped"), "{}", text);
+
+    let output = svc(&dir, &["--json", "down", "--force"]);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["services"][0]["method"], "not_running");
+}
+
+#[test]
+fn list_marks_protected_services() {
+    let dir = sandbox("protected-list");
+    windows_tools(&dir);
+    fs::write(
+        dir.join("services.yaml"),
+        "- { name: db, path: /opt/db/db.exe, type: Executable, protected: true }\n\
+         - { name: api, path: /opt/api/api.exe, type: Executable }\n",
+    )
+    .unwrap();
+
+    let output = svc(&dir, &["list"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let text = stdout(&output);
+    let row = |name: &str| text.lines().find(|line| line.starts_with(name)).unwrap_or_default().to_string();
+    assert!(row("db ").contains("[protected]") || row("db ").contains('\u{1F6E1}'), "{}", text);
+    assert!(!row("api ").contains("protected"), "{}", text);
+
+    let reports: serde_json::Value = serde_json::from_slice(&svc(&dir, &["--json", "list"]).stdout).unwrap();
+    assert_eq!(reports[0]["protected"], true);
+    assert!(reports[1].get("protected").is_none());
+}
@@ -0,0 +1,186 @@
+// The config model and process API as other tools use them
+use std::cell::Cell;
+use std::path::Path;
+use svc::process::{self, ProcessBackend, RunOutcome, RunOverride};
+use svc::{parse_config, Service, ServiceType, SvcError};
+
+const CONFIG: &str = "settings:
+  startup_backend: run-key
+services:
+  - name: api
+    path: /opt/api/api.exe
+    type: Executable
+  - name: report
+    path: report.ps1
+    type: Util
+    interpreter: pwsh
+    work_at: /srv/reports
+";
+
+struct Counting {
+    spawned: Cell<u32>,
+}
+
+impl ProcessBackend for Counting {
+    fn pids(&self, _service: &Service) -> Result<Vec<u64>, SvcError> {
+        Ok(Vec::new())
+    }
+
+    fn spawn(&self, _service: &Service, _work_at: &str) -> Result<u64, SvcError> {
+        self.spawned.set(self.spawned.get() + 1);
+        Ok(100 + u64::from(self.spawned.get()))
+    }
+
+    fn run_util(&self, _service: &Service, work_at: &str, _capture: Option<&Path>) -> Result<Vec<String>, SvcError> {
+        Ok(vec![work_at.to_string()])
+    }
+
+    fn terminate(&self, _service: &Service, _pids: &[u64]) -> Result<(), SvcError> {
+        Ok(())
+    }
+}
+
+#[test]
+fn config_model_is_usable_outside_the_cli() {
+    let config = parse_config(CONFIG).unwrap();
+    assert_eq!(config.services.len(), 2);
+    let report = config.service("report").unwrap();
+    assert!(matches!(report.service_type, ServiceType::Util));
+    assert_eq!(report.interpreter, "pwsh");
+    assert_eq!(config.service("api").unwrap().interpreter, "python");
+}
+
+#[test]
+fn services_start_through_an_injected_backend() {
+    let config = parse_config(CONFIG).unwrap();
+    let backend = Counting { spawned: Cell::new(0) };
+
+    let api = config.service("api").unwrap();
+    let outcome = process::start(&backend, api, &RunOverride::default()).unwrap();
+    assert_eq!(outcome, RunOutcome::Started { pid: 101, user: None });
+
+    let report = config.service("report").unwrap();
+    let outcome = process::start(&backend, report, &RunOverride::default()).unwrap();
+    assert_eq!(outcome, RunOutcome::Finished { output: vec!["/srv/reports".to_string()] });
+    assert_eq!(backend.spawned.get(), 1);
+}
+
+// One line of the surface for each item the prelude exports: the compiler checks each against
+// the crate, and the text is what tests/public-api.txt records
+macro_rules! signatures {
+    ($($name:literal: $ty:ty = $item:expr;)*) => {{
+        $(let _: $ty = $item;)*
+        // As written here, on one line and without the module path
+        let written = |ty: &str| ty.split_whitespace().collect::<Vec<_>>().join(" ").replace("api::", "");
+        vec![$(format!("{}: {}", $name, written(stringify!($ty)))),*]
+    }};
+}
+
+// The fields and variants, matched without `..` so one added or removed stops this compiling
+macro_rules! shape {
+    (struct $ty:ident { $($field:ident),* }) => {{
+        let _ = |value: api::$ty| {
+            let api::$ty { $($field: _),* } = value;
+        };
+        format!("struct {} {{ {} }}", stringify!($ty), [$(stringify!($field)),*].join(", "))
+    }};
+    (enum $ty:ident { $($variant:ident $({ $($field:ident),* })?),* }) => {{
+        let _ = |value: api::$ty| match value {
+            $(api::$ty::$variant $({ $($field: _),* })? => ()),*
+        };
+        let variants: Vec<String> = vec![$(
+            format!(
+                "{}{}",
+                stringify!($variant),
+                String::new() $(+ &format!(" {{ {} }}", [$(stringify!($field)),*].join(", ")))?
+            )
+        ),*];
+        format!("enum {} {{ {} }}", stringify!($ty), variants.join(", "))
+    }};
+}
+
+use svc::prelude as api;
+
+// The signatures are spelled out in full since they are what is recorded
+#[allow(clippy::type_complexity)]
+fn surface() -> Vec<String> {
+    let mut surface = vec![
+        // New fields and variants are minor changes for these, so only the names are recorded
+        "struct Config (non_exhaustive)".to_string(),
+        "struct Service (non_exhaustive)".to_string(),
+        "enum SvcError (non_exhaustive)".to_string(),
+        "trait ProcessBackend".to_string(),
+        "struct System".to_string(),
+        "struct ServiceManager".to_string(),
+        shape!(struct ServiceStatus { pids, startup }),
+        shape!(struct RunOverride { work_at, force, lock_timeout, capture }),
+        shape!(enum RunOutcome { Started { pid, user }, Finished { output } }),
+        shape!(enum ServiceType { Executable, Util }),
+    ];
+    surface.extend(signatures! {
+        "fn parse_config": fn(&str) -> Result<api::Config<'static>, api::SvcError> = api::parse_config;
+        "fn load_config": fn(&str) -> Result<Vec<api::Service<'static>>, api::SvcError> = api::load_config;
+        "fn Config::service":
+            for<'c> fn(&'c api::Config<'static>, &str) -> Result<&'c api::Service<'static>, api::SvcError> =
+            api::Config::service;
+        "fn Service::title": fn(&api::Service<'static>) -> String = api::Service::title;
+        "fn ServiceManager::system": fn() -> api::ServiceManager<'static> = api::ServiceManager::system;
+        "fn ServiceManager::with_backend":
+            fn(&'static dyn api::ProcessBackend) -> api::ServiceManager<'static> = api::ServiceManager::with_backend;
+        "fn ServiceManager::start":
+            fn(&api::ServiceManager<'static>, &api::Service, &api::RunOverride)
+                -> Result<api::RunOutcome, api::SvcError> =
+            api::ServiceManager::start;
+        "fn ServiceManager::status":
+            fn(&api::ServiceManager<'static>, &api::Service) -> Result<api::ServiceStatus, api::SvcError> =
+            api::ServiceManager::status;
+        "fn ServiceManager::is_running":
+            fn(&api::ServiceManager<'static>, &api::Service) -> Result<bool, api::SvcError> =
+            api::ServiceManager::is_running;
+        "fn ServiceStatus::is_start_up": fn(&api::ServiceStatus) -> bool = api::ServiceStatus::is_start_up;
+        "fn SvcError::code": fn(&api::SvcError) -> &'static str = api::SvcError::code;
+        "fn SvcError::hint": fn(&api::SvcError) -> Option<&'static str> = api::SvcError::hint;
+        "fn ProcessBackend::pids":
+            fn(&api::System, &api::Service) -> Result<Vec<u64>, api::SvcError> = api::ProcessBackend::pids;
+        "fn ProcessBackend::spawn":
+            fn(&api::System, &api::Service, &str) -> Result<u64, api::SvcError> = api::ProcessBackend::spawn;
+        "fn ProcessBackend::run_util":
+            fn(&api::System, &api::Service, &str, Option<&Path>) -> Result<Vec<String>, api::SvcError> =
+            api::ProcessBackend::run_util;
+        "fn ProcessBackend::terminate":
+            fn(&api::System, &api::Service, &[u64]) -> Result<(), api::SvcError> = api::ProcessBackend::terminate;
+    });
+    surface
+}
+
+#[test]
+fn public_surface_changes_only_with_a_version_bump() {
+    let snapshot = include_str!("public-api.txt").replace("\r\n", "\n");
+    let mut sections: Vec<(&str, Vec<&str>)> = Vec::new();
+    for line in snapshot.lines().filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        match line.strip_prefix("version ") {
+            Some(version) => sections.push((version, Vec::new())),
+            None => sections.last_mut().expect("a surface line before any version").1.push(line),
+        }
+    }
+    let mut versions: Vec<&str> = sections.iter().map(|(version, _)| *version).collect();
+    versions.dedup();
+    assert_eq!(versions.len(), sections.len(), "a version appears twice in tests/public-api.txt");
+
+    let current = surface();
+    let (version, recorded) = sections.last().expect("tests/public-api.txt records no version");
+    let proposal = format!("version {}\n{}", env!("CARGO_PKG_VERSION"), current.join("\n"));
+    assert_eq!(
+        recorded, &current,
+        "the prelude no longer matches the surface of {}; bump the version in Cargo.toml and add this \
+         to the end of tests/public-api.txt:\n\n{}\n",
+        version, proposal
+    );
+    assert_eq!(
+        *version,
+        env!("CARGO_PKG_VERSION"),
+        "tests/public-api.txt ends with the surface of {}; add the same surface under the new version:\n\n{}\n",
+        version,
+        proposal
+    );
+}